@@ -0,0 +1,54 @@
+//! A domain server with a studio extension, as a studio would build it in a crate of its own
+//!
+//! The extension warns when a fixed instance disconnects while a task using it is active, which the stock domain
+//! server only shows in the instance state.
+
+use std::collections::HashSet;
+
+use clap::Parser;
+use tracing::*;
+
+use audiocloud_api::{AppTaskId, FixedInstanceId};
+use audiocloud_domain_server::extensions::{DomainExtension, InstanceEvent, TaskEvent};
+use audiocloud_domain_server::server::{self, ServerOpts};
+
+#[derive(Default)]
+struct DisconnectWatch {
+    active_tasks: HashSet<AppTaskId>,
+    disconnected: HashSet<FixedInstanceId>,
+}
+
+impl DomainExtension for DisconnectWatch {
+    fn name(&self) -> &str {
+        "disconnect-watch"
+    }
+
+    fn on_task_event(&mut self, event: &TaskEvent) {
+        match event {
+            TaskEvent::Activated { task_id } => {
+                self.active_tasks.insert(task_id.clone());
+            }
+            TaskEvent::Deactivated { task_id } | TaskEvent::Deleted { task_id } => {
+                self.active_tasks.remove(task_id);
+            }
+            _ => {}
+        }
+    }
+
+    fn on_instance_event(&mut self, event: &InstanceEvent) {
+        if let InstanceEvent::State { instance_id, connected, .. } = event {
+            if *connected {
+                self.disconnected.remove(instance_id);
+            } else if self.disconnected.insert(instance_id.clone()) && !self.active_tasks.is_empty() {
+                warn!(%instance_id, active_tasks = self.active_tasks.len(), "Instance disconnected during a session");
+            }
+        }
+    }
+}
+
+#[actix_web::main]
+async fn main() -> anyhow::Result<()> {
+    let _ = dotenv::dotenv();
+
+    server::run(ServerOpts::parse(), vec![Box::new(DisconnectWatch::default())]).await
+}
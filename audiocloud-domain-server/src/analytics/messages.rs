@@ -0,0 +1,13 @@
+use actix::Message;
+
+use crate::analytics::UtilizationReport;
+use crate::DomainResult;
+
+/// Most recently computed utilization reports, most recent first
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<Vec<UtilizationReport>>")]
+pub struct ListUtilizationReports {
+    /// Only reports over this window, all windows if not set
+    pub window_hours: Option<u64>,
+    pub limit:        usize,
+}
@@ -0,0 +1,49 @@
+use actix::{Actor, Addr};
+use anyhow::anyhow;
+use clap::Args;
+use once_cell::sync::OnceCell;
+use tracing::*;
+
+pub use messages::*;
+pub use report::*;
+use supervisor::AnalyticsSupervisor;
+
+use crate::db::Db;
+
+pub mod messages;
+mod report;
+mod supervisor;
+#[cfg(test)]
+mod tests;
+
+static ANALYTICS_SUPERVISOR: OnceCell<Addr<AnalyticsSupervisor>> = OnceCell::new();
+
+#[derive(Args, Clone, Debug)]
+pub struct AnalyticsOpts {
+    /// How often the utilization reports are computed, in seconds
+    #[clap(long, env, default_value = "3600")]
+    pub analytics_interval_seconds: u64,
+
+    /// Windows the utilization reports are computed over, in hours before the time they are computed at
+    #[clap(long, env, value_delimiter = ',', default_value = "24,168,720")]
+    pub analytics_window_hours: Vec<u64>,
+
+    /// Utilization reports are removed this many days after they were computed
+    #[clap(long, env, default_value = "365")]
+    pub analytics_retention_days: u64,
+}
+
+#[instrument(skip_all, err)]
+pub fn init(db: Db, opts: AnalyticsOpts) -> anyhow::Result<()> {
+    let supervisor = AnalyticsSupervisor::new(db, opts);
+
+    ANALYTICS_SUPERVISOR.set(supervisor.start())
+                        .map_err(|_| anyhow!("Analytics supervisor already initialized"))?;
+
+    Ok(())
+}
+
+pub fn get_analytics_supervisor() -> &'static Addr<AnalyticsSupervisor> {
+    ANALYTICS_SUPERVISOR.get()
+                        .expect("Analytics supervisor not initialized")
+}
@@ -0,0 +1,319 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use chrono::{DurationRound, Timelike};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use audiocloud_api::{AppTaskId, FixedInstanceId, ModelId, Timestamp};
+
+const HOURS_PER_DAY: usize = 24;
+const BUSIEST_HOURS: usize = 3;
+
+/// Time a task reserved an instance for, cut short if the task was deleted before the reservation ended
+#[derive(Clone, Debug, PartialEq)]
+pub struct UsageRecord {
+    pub task_id:     AppTaskId,
+    pub instance_id: FixedInstanceId,
+    pub from:        Timestamp,
+    pub to:          Timestamp,
+}
+
+/// Something that happened to a task or an instance that the utilization reports count
+#[derive(Clone, Debug, PartialEq)]
+pub struct UsageEvent {
+    pub at:          Timestamp,
+    pub kind:        UsageEventKind,
+    pub task_id:     Option<AppTaskId>,
+    pub instance_id: Option<FixedInstanceId>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageEventKind {
+    Play,
+    PlayFailed,
+    Render,
+    RenderFinished,
+    RenderFailed,
+    EngineError,
+    InstanceError,
+    InstanceDisconnected,
+}
+
+impl UsageEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UsageEventKind::Play => "play",
+            UsageEventKind::PlayFailed => "play_failed",
+            UsageEventKind::Render => "render",
+            UsageEventKind::RenderFinished => "render_finished",
+            UsageEventKind::RenderFailed => "render_failed",
+            UsageEventKind::EngineError => "engine_error",
+            UsageEventKind::InstanceError => "instance_error",
+            UsageEventKind::InstanceDisconnected => "instance_disconnected",
+        }
+    }
+
+    /// Tasks with events of this kind count as failed
+    pub fn is_task_failure(&self) -> bool {
+        matches!(self,
+                 UsageEventKind::PlayFailed | UsageEventKind::RenderFailed | UsageEventKind::EngineError)
+    }
+
+    /// Events of this kind count against the instance that reported them
+    pub fn is_instance_failure(&self) -> bool {
+        matches!(self,
+                 UsageEventKind::InstanceError | UsageEventKind::InstanceDisconnected)
+    }
+}
+
+impl FromStr for UsageEventKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "play" => Ok(Self::Play),
+            "play_failed" => Ok(Self::PlayFailed),
+            "render" => Ok(Self::Render),
+            "render_finished" => Ok(Self::RenderFinished),
+            "render_failed" => Ok(Self::RenderFailed),
+            "engine_error" => Ok(Self::EngineError),
+            "instance_error" => Ok(Self::InstanceError),
+            "instance_disconnected" => Ok(Self::InstanceDisconnected),
+            other => Err(anyhow!("Unknown usage event kind {other}")),
+        }
+    }
+}
+
+/// Utilization of the instances of the domain over a window ending at the time the report was computed
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct UtilizationReport {
+    pub window_hours: u64,
+    #[schema(value_type = String)]
+    pub from:         Timestamp,
+    #[schema(value_type = String)]
+    pub to:           Timestamp,
+    pub instances:    Vec<InstanceUtilization>,
+    pub models:       Vec<ModelUtilization>,
+    pub totals:       UtilizationTotals,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct InstanceUtilization {
+    #[schema(value_type = String)]
+    pub instance_id: FixedInstanceId,
+    pub usage:       UsageStats,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct ModelUtilization {
+    #[schema(value_type = String)]
+    pub model_id:  ModelId,
+    /// Instances of the model, idle ones included
+    pub instances: usize,
+    pub usage:     UsageStats,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct UsageStats {
+    /// Seconds of the window reserved by tasks
+    pub reserved_seconds: f64,
+    /// Share of the window reserved by tasks, from 0 to 1, models average it over their instances
+    pub utilization:      f64,
+    /// Reserved seconds by hour of the day (UTC), starting at midnight
+    pub hourly_seconds:   Vec<f64>,
+    /// Hours of the day (UTC) with the most reserved time, busiest first
+    pub busiest_hours:    Vec<u32>,
+    /// Tasks that reserved time within the window
+    pub tasks:            u64,
+    /// Tasks that failed to play or render, or that the engine reported an error for
+    pub failed_tasks:     u64,
+    /// Share of the tasks that failed, from 0 to 1
+    pub failure_rate:     f64,
+    /// Errors and disconnects reported by the instance drivers
+    pub instance_errors:  u64,
+}
+
+/// Counters of the whole domain that studios bill and plan by
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct UtilizationTotals {
+    pub tasks:                   u64,
+    pub failed_tasks:            u64,
+    /// Hours of instance time reserved by tasks, summed over instances
+    pub reserved_instance_hours: f64,
+    pub plays:                   u64,
+    pub play_failures:           u64,
+    pub renders:                 u64,
+    pub renders_finished:        u64,
+    pub render_failures:         u64,
+    pub engine_errors:           u64,
+}
+
+#[derive(Default)]
+struct UsageAccumulator {
+    reserved_seconds: f64,
+    hourly_seconds:   [f64; HOURS_PER_DAY],
+    tasks:            HashSet<AppTaskId>,
+    instance_errors:  u64,
+}
+
+impl UsageAccumulator {
+    fn reserve(&mut self, task_id: &AppTaskId, from: Timestamp, to: Timestamp) {
+        let mut at = from;
+        while at < to {
+            let hour_end = at.duration_trunc(chrono::Duration::hours(1))
+                             .map(|hour| hour + chrono::Duration::hours(1))
+                             .unwrap_or(to)
+                             .min(to);
+
+            let seconds = seconds_between(at, hour_end);
+            self.hourly_seconds[at.hour() as usize] += seconds;
+            self.reserved_seconds += seconds;
+
+            at = hour_end;
+        }
+
+        self.tasks.insert(task_id.clone());
+    }
+
+    fn merge(&mut self, other: &UsageAccumulator) {
+        self.reserved_seconds += other.reserved_seconds;
+        for (hour, seconds) in other.hourly_seconds.iter().enumerate() {
+            self.hourly_seconds[hour] += seconds;
+        }
+        self.tasks.extend(other.tasks.iter().cloned());
+        self.instance_errors += other.instance_errors;
+    }
+
+    fn stats(&self, capacity_seconds: f64, failed: &HashSet<AppTaskId>) -> UsageStats {
+        let tasks = self.tasks.len() as u64;
+        let failed_tasks = self.tasks.intersection(failed).count() as u64;
+
+        let mut busiest_hours = (0..HOURS_PER_DAY).filter(|hour| self.hourly_seconds[*hour] > 0.0)
+                                                  .collect::<Vec<_>>();
+        busiest_hours.sort_by(|a, b| {
+                         self.hourly_seconds[*b].total_cmp(&self.hourly_seconds[*a])
+                                                .then(a.cmp(b))
+                     });
+        busiest_hours.truncate(BUSIEST_HOURS);
+
+        UsageStats { reserved_seconds: { self.reserved_seconds },
+                     utilization:      { ratio(self.reserved_seconds, capacity_seconds) },
+                     hourly_seconds:   { self.hourly_seconds.to_vec() },
+                     busiest_hours:    { busiest_hours.into_iter().map(|hour| hour as u32).collect() },
+                     tasks:            { tasks },
+                     failed_tasks:     { failed_tasks },
+                     failure_rate:     { ratio(failed_tasks as f64, tasks as f64) },
+                     instance_errors:  { self.instance_errors }, }
+    }
+}
+
+/// Compute the utilization of `instances` over the `window_hours` before `to`
+///
+/// Reservations and events outside of the window are ignored, reservations crossing its edges count with the part
+/// inside. Instances that only show up in the reservations or events are reported too.
+pub fn compute_utilization(window_hours: u64,
+                           to: Timestamp,
+                           instances: &[FixedInstanceId],
+                           usage: &[UsageRecord],
+                           events: &[UsageEvent])
+                           -> UtilizationReport {
+    let from = to - chrono::Duration::hours(window_hours as i64);
+    let window_seconds = seconds_between(from, to);
+
+    let mut by_instance = instances.iter()
+                                   .map(|id| (id.clone(), UsageAccumulator::default()))
+                                   .collect::<HashMap<_, _>>();
+
+    let mut totals = UtilizationTotals::default();
+    let mut tasks = HashSet::new();
+
+    for record in usage {
+        let (start, end) = (record.from.max(from), record.to.min(to));
+        if start >= end {
+            continue;
+        }
+
+        by_instance.entry(record.instance_id.clone())
+                   .or_default()
+                   .reserve(&record.task_id, start, end);
+
+        tasks.insert(record.task_id.clone());
+    }
+
+    let mut failed = HashSet::new();
+
+    for event in events.iter().filter(|event| event.at >= from && event.at < to) {
+        match event.kind {
+            UsageEventKind::Play => totals.plays += 1,
+            UsageEventKind::PlayFailed => totals.play_failures += 1,
+            UsageEventKind::Render => totals.renders += 1,
+            UsageEventKind::RenderFinished => totals.renders_finished += 1,
+            UsageEventKind::RenderFailed => totals.render_failures += 1,
+            UsageEventKind::EngineError => totals.engine_errors += 1,
+            UsageEventKind::InstanceError | UsageEventKind::InstanceDisconnected => {}
+        }
+
+        if let (true, Some(task_id)) = (event.kind.is_task_failure(), &event.task_id) {
+            failed.insert(task_id.clone());
+        }
+
+        if let (true, Some(instance_id)) = (event.kind.is_instance_failure(), &event.instance_id) {
+            by_instance.entry(instance_id.clone()).or_default().instance_errors += 1;
+        }
+    }
+
+    let mut by_model = HashMap::<ModelId, (usize, UsageAccumulator)>::new();
+    for (instance_id, accumulator) in &by_instance {
+        let (count, model) = by_model.entry(instance_id.model_id()).or_default();
+        *count += 1;
+        model.merge(accumulator);
+    }
+
+    let mut instances =
+        by_instance.iter()
+                   .map(|(instance_id, accumulator)| InstanceUtilization { instance_id: { instance_id.clone() },
+                                                                           usage:       {
+                                                                               accumulator.stats(window_seconds,
+                                                                                                 &failed)
+                                                                           }, })
+                   .collect::<Vec<_>>();
+    instances.sort_by_key(|instance| instance.instance_id.to_string());
+
+    let mut models =
+        by_model.into_iter()
+                .map(|(model_id, (count, accumulator))| ModelUtilization { model_id:  { model_id },
+                                                                           instances: { count },
+                                                                           usage:     {
+                                                                               accumulator.stats(window_seconds
+                                                                                                 * count as f64,
+                                                                                                 &failed)
+                                                                           }, })
+                .collect::<Vec<_>>();
+    models.sort_by_key(|model| model.model_id.to_string());
+
+    totals.reserved_instance_hours = by_instance.values().map(|usage| usage.reserved_seconds).sum::<f64>() / 3600.0;
+    totals.failed_tasks = tasks.intersection(&failed).count() as u64;
+    totals.tasks = tasks.len() as u64;
+
+    UtilizationReport { window_hours: { window_hours },
+                        from:         { from },
+                        to:           { to },
+                        instances:    { instances },
+                        models:       { models },
+                        totals:       { totals }, }
+}
+
+fn seconds_between(from: Timestamp, to: Timestamp) -> f64 {
+    (to - from).num_milliseconds() as f64 / 1000.0
+}
+
+fn ratio(part: f64, whole: f64) -> f64 {
+    if whole > 0.0 {
+        part / whole
+    } else {
+        0.0
+    }
+}
@@ -0,0 +1,223 @@
+#![allow(unused_variables)]
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use actix::{Actor, AsyncContext, Context, ContextFutureSpawner, Handler, ResponseFuture, WrapFuture};
+use actix_broker::BrokerSubscribe;
+use tracing::*;
+
+use audiocloud_api::audio_engine::EngineEvent;
+use audiocloud_api::common::media::RenderId;
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::{now, AppTaskId, FixedInstanceId, PlayId};
+
+use crate::analytics::{
+    compute_utilization, AnalyticsOpts, ListUtilizationReports, UsageEvent, UsageEventKind, UtilizationReport,
+};
+use crate::db::Db;
+use crate::fixed_instances::{get_instance_supervisor, ListFixedInstances, NotifyInstanceError, NotifyInstanceState};
+use crate::tasks::{NotifyEngineEvent, NotifyTaskDeleted, NotifyTaskReservation};
+use crate::DomainResult;
+
+pub struct AnalyticsSupervisor {
+    db:        Db,
+    opts:      AnalyticsOpts,
+    /// Engines report playing and rendering progress repeatedly, only the first report of each play or render counts
+    playing:   HashMap<AppTaskId, PlayId>,
+    rendering: HashMap<AppTaskId, RenderId>,
+    connected: HashMap<FixedInstanceId, bool>,
+}
+
+impl AnalyticsSupervisor {
+    pub fn new(db: Db, opts: AnalyticsOpts) -> Self {
+        Self { db:        { db },
+               opts:      { opts },
+               playing:   { HashMap::new() },
+               rendering: { HashMap::new() },
+               connected: { HashMap::new() }, }
+    }
+
+    fn record(&mut self,
+              kind: UsageEventKind,
+              task_id: Option<AppTaskId>,
+              instance_id: Option<FixedInstanceId>,
+              ctx: &mut Context<Self>) {
+        let db = self.db.clone();
+        let event = UsageEvent { at:          { now() },
+                                 kind:        { kind },
+                                 task_id:     { task_id },
+                                 instance_id: { instance_id }, };
+
+        async move {
+            if let Err(error) = db.append_usage_event(&event).await {
+                warn!(%error, kind = event.kind.as_str(), "Failed to store usage event");
+            }
+        }.into_actor(self)
+         .spawn(ctx);
+    }
+
+    fn compute_reports(&mut self, ctx: &mut Context<Self>) {
+        let db = self.db.clone();
+        let opts = self.opts.clone();
+
+        async move {
+            let instances = match get_instance_supervisor().send(ListFixedInstances).await {
+                Ok(instances) => instances.into_iter().map(|instance| instance.instance_id).collect(),
+                Err(error) => {
+                    warn!(%error, "Failed to list instances, reporting only instances that were used");
+                    vec![]
+                }
+            };
+
+            if let Err(error) = compute_and_save_reports(&db, &opts, &instances).await {
+                warn!(%error, "Failed to compute utilization reports");
+            }
+        }.into_actor(self)
+         .spawn(ctx);
+    }
+}
+
+async fn compute_and_save_reports(db: &Db, opts: &AnalyticsOpts, instances: &[FixedInstanceId]) -> anyhow::Result<()> {
+    let to = now();
+
+    for window_hours in &opts.analytics_window_hours {
+        let from = to - chrono::Duration::hours(*window_hours as i64);
+        let usage = db.fetch_usage_records(from, to).await?;
+        let events = db.fetch_usage_events(from, to).await?;
+
+        let report = compute_utilization(*window_hours, to, instances, &usage, &events);
+        db.save_utilization_report(&report).await?;
+
+        debug!(window_hours,
+               reserved_instance_hours = report.totals.reserved_instance_hours,
+               "Computed utilization report");
+    }
+
+    // usage is kept for as long as the longest window needs it
+    let longest_window = opts.analytics_window_hours.iter().copied().max().unwrap_or_default();
+    let usage_cutoff = to - chrono::Duration::hours(longest_window as i64);
+    let report_cutoff = to - chrono::Duration::days(opts.analytics_retention_days as i64);
+
+    db.trim_analytics(usage_cutoff, report_cutoff).await?;
+
+    Ok(())
+}
+
+impl Actor for AnalyticsSupervisor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.subscribe_system_async::<NotifyTaskReservation>(ctx);
+        self.subscribe_system_async::<NotifyTaskDeleted>(ctx);
+        self.subscribe_system_async::<NotifyEngineEvent>(ctx);
+        self.subscribe_system_async::<NotifyInstanceState>(ctx);
+        self.subscribe_system_async::<NotifyInstanceError>(ctx);
+
+        ctx.run_interval(Duration::from_secs(self.opts.analytics_interval_seconds.max(1)),
+                         Self::compute_reports);
+    }
+}
+
+impl Handler<NotifyTaskReservation> for AnalyticsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskReservation, ctx: &mut Self::Context) -> Self::Result {
+        let db = self.db.clone();
+
+        async move {
+            if let Err(error) = db.save_task_usage(&msg.task_id, &msg.reservation).await {
+                warn!(%error, task_id = %msg.task_id, "Failed to store task usage");
+            }
+        }.into_actor(self)
+         .spawn(ctx);
+    }
+}
+
+impl Handler<NotifyTaskDeleted> for AnalyticsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskDeleted, ctx: &mut Self::Context) -> Self::Result {
+        let db = self.db.clone();
+
+        self.playing.remove(&msg.task_id);
+        self.rendering.remove(&msg.task_id);
+
+        // a task deleted before its reservation ended only used the instances until then
+        async move {
+            if let Err(error) = db.end_task_usage(&msg.task_id, now()).await {
+                warn!(%error, task_id = %msg.task_id, "Failed to end task usage");
+            }
+        }.into_actor(self)
+         .spawn(ctx);
+    }
+}
+
+impl Handler<NotifyEngineEvent> for AnalyticsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyEngineEvent, ctx: &mut Self::Context) -> Self::Result {
+        let (kind, task_id) = match msg.event {
+            EngineEvent::Playing { task_id, play_id, .. } => {
+                if self.playing.get(&task_id) == Some(&play_id) {
+                    return;
+                }
+
+                self.playing.insert(task_id.clone(), play_id);
+                (UsageEventKind::Play, task_id)
+            }
+            EngineEvent::Rendering { task_id, render_id, .. } => {
+                if self.rendering.get(&task_id) == Some(&render_id) {
+                    return;
+                }
+
+                self.rendering.insert(task_id.clone(), render_id);
+                (UsageEventKind::Render, task_id)
+            }
+            EngineEvent::PlayingFailed { task_id, .. } => (UsageEventKind::PlayFailed, task_id),
+            EngineEvent::RenderingFinished { task_id, .. } => (UsageEventKind::RenderFinished, task_id),
+            EngineEvent::RenderingFailed { task_id, .. } => (UsageEventKind::RenderFailed, task_id),
+            EngineEvent::Error { task_id, .. } => (UsageEventKind::EngineError, task_id),
+            _ => return,
+        };
+
+        self.record(kind, Some(task_id), None, ctx);
+    }
+}
+
+impl Handler<NotifyInstanceState> for AnalyticsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyInstanceState, ctx: &mut Self::Context) -> Self::Result {
+        let connected = *msg.connected.value();
+        let was_connected = self.connected
+                                .insert(msg.instance_id.clone(), connected)
+                                .unwrap_or(true);
+
+        // instances report their state repeatedly, only count the moment they drop
+        if was_connected && !connected {
+            self.record(UsageEventKind::InstanceDisconnected, None, Some(msg.instance_id), ctx);
+        }
+    }
+}
+
+impl Handler<NotifyInstanceError> for AnalyticsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyInstanceError, ctx: &mut Self::Context) -> Self::Result {
+        self.record(UsageEventKind::InstanceError, None, Some(msg.instance_id), ctx);
+    }
+}
+
+impl Handler<ListUtilizationReports> for AnalyticsSupervisor {
+    type Result = ResponseFuture<DomainResult<Vec<UtilizationReport>>>;
+
+    fn handle(&mut self, msg: ListUtilizationReports, ctx: &mut Self::Context) -> Self::Result {
+        let db = self.db.clone();
+        Box::pin(async move {
+            db.fetch_utilization_reports(msg.window_hours, msg.limit)
+              .await
+              .map_err(|error| DomainError::BadGateway { error: error.to_string(), })
+        })
+    }
+}
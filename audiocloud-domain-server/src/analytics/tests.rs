@@ -0,0 +1,95 @@
+use chrono::{TimeZone, Utc};
+
+use audiocloud_api::{AppId, AppTaskId, FixedInstanceId, TaskId, Timestamp};
+
+use crate::analytics::{compute_utilization, UsageEvent, UsageEventKind, UsageRecord};
+
+fn instance(model: &str, instance: &str) -> FixedInstanceId {
+    FixedInstanceId::new("distopik".to_owned(), model.to_owned(), instance.to_owned())
+}
+
+fn task(name: &str) -> AppTaskId {
+    AppTaskId::new(AppId::test(), TaskId::new(name.to_owned()))
+}
+
+fn at(day: u32, hour: u32, minute: u32) -> Timestamp {
+    Utc.with_ymd_and_hms(2022, 10, day, hour, minute, 0).unwrap()
+}
+
+fn usage(task_id: &str, instance_id: FixedInstanceId, from: Timestamp, to: Timestamp) -> UsageRecord {
+    UsageRecord { task_id: task(task_id),
+                  instance_id,
+                  from,
+                  to }
+}
+
+fn event(at: Timestamp,
+         kind: UsageEventKind,
+         task_id: Option<&str>,
+         instance_id: Option<FixedInstanceId>)
+         -> UsageEvent {
+    UsageEvent { at,
+                 kind,
+                 task_id: task_id.map(task),
+                 instance_id }
+}
+
+#[test]
+fn test_utilization_is_computed_over_the_window() {
+    let (left, right, pre) = (instance("la2a", "1"), instance("la2a", "2"), instance("pre73", "1"));
+
+    let records = vec![usage("mix", left.clone(), at(21, 10, 0), at(21, 12, 30)),
+                       usage("mix", right.clone(), at(21, 10, 0), at(21, 12, 30)),
+                       usage("late", left.clone(), at(21, 23, 0), at(22, 2, 0)),
+                       usage("old", right.clone(), at(20, 10, 0), at(20, 11, 0))];
+
+    let events = vec![event(at(20, 10, 30), UsageEventKind::Play, Some("old"), None),
+                      event(at(21, 11, 0), UsageEventKind::Play, Some("mix"), None),
+                      event(at(21, 11, 30), UsageEventKind::Render, Some("mix"), None),
+                      event(at(21, 11, 45), UsageEventKind::RenderFinished, Some("mix"), None),
+                      event(at(21, 12, 0),
+                            UsageEventKind::InstanceDisconnected,
+                            None,
+                            Some(right.clone())),
+                      event(at(21, 23, 30), UsageEventKind::PlayFailed, Some("late"), None)];
+
+    let report = compute_utilization(24, at(22, 0, 0), &[left.clone(), right, pre.clone()], &records, &events);
+    assert_eq!(report.from, at(21, 0, 0));
+
+    let instance_ids = report.instances
+                             .iter()
+                             .map(|usage| usage.instance_id.clone())
+                             .collect::<Vec<_>>();
+    assert_eq!(instance_ids.len(), 3, "idle instances are reported too");
+    assert!(instance_ids.contains(&pre));
+
+    let left = &report.instances
+                      .iter()
+                      .find(|usage| usage.instance_id == left)
+                      .expect("left instance reported")
+                      .usage;
+
+    assert_eq!(left.reserved_seconds,
+               3.5 * 3600.0,
+               "reservations count with the part inside the window");
+    assert_eq!(left.hourly_seconds[12], 1800.0);
+    assert_eq!(left.busiest_hours, vec![10, 11, 23]);
+    assert_eq!((left.tasks, left.failed_tasks, left.failure_rate), (2, 1, 0.5));
+
+    let la2a = report.models
+                     .iter()
+                     .find(|model| model.model_id == instance("la2a", "1").model_id())
+                     .expect("model reported");
+
+    assert_eq!(la2a.instances, 2);
+    assert_eq!(la2a.usage.utilization, 0.125);
+    assert_eq!(la2a.usage.instance_errors, 1);
+
+    let totals = &report.totals;
+    assert_eq!(totals.reserved_instance_hours, 6.0);
+    assert_eq!((totals.tasks, totals.failed_tasks), (2, 1));
+    assert_eq!((totals.plays, totals.play_failures),
+               (1, 1),
+               "events outside the window are ignored");
+    assert_eq!((totals.renders, totals.renders_finished), (1, 1));
+}
@@ -0,0 +1,31 @@
+use actix::Message;
+use serde::Deserialize;
+
+use audiocloud_api::{AppTaskId, FixedInstanceId, Timestamp};
+
+use crate::audit::AuditEntry;
+use crate::DomainResult;
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct RecordAuditEntry {
+    pub entry: AuditEntry,
+}
+
+/// Filter for audit entries, all conditions must match
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    pub task_id:     Option<AppTaskId>,
+    pub instance_id: Option<FixedInstanceId>,
+    pub actor:       Option<String>,
+    pub since:       Option<Timestamp>,
+    /// Only return entries older than this sequence number, for paging backwards
+    pub before_id:   Option<i64>,
+    pub limit:       Option<usize>,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<Vec<AuditEntry>>")]
+pub struct QueryAuditEntries {
+    pub query: AuditQuery,
+}
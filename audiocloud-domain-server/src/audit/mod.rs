@@ -0,0 +1,195 @@
+use std::future::Future;
+use std::str::FromStr;
+
+use actix::{Actor, Addr};
+use anyhow::anyhow;
+use clap::Args;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tracing::*;
+use utoipa::ToSchema;
+
+use audiocloud_api::{now, AppTaskId, FixedInstanceId, Timestamp};
+pub use messages::*;
+use supervisor::AuditSupervisor;
+
+use crate::db::Db;
+use crate::{DomainResult, DomainSecurity};
+
+pub mod messages;
+mod supervisor;
+
+static AUDIT_SUPERVISOR: OnceCell<Addr<AuditSupervisor>> = OnceCell::new();
+
+/// Interface through which a mutating command reached the domain
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOrigin {
+    Rest,
+    Socket,
+    Cloud,
+    Nats,
+    /// Actions taken by automation scripts
+    Automation,
+}
+
+impl AuditOrigin {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditOrigin::Rest => "rest",
+            AuditOrigin::Socket => "socket",
+            AuditOrigin::Cloud => "cloud",
+            AuditOrigin::Nats => "nats",
+            AuditOrigin::Automation => "automation",
+        }
+    }
+}
+
+impl FromStr for AuditOrigin {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rest" => Ok(Self::Rest),
+            "socket" => Ok(Self::Socket),
+            "cloud" => Ok(Self::Cloud),
+            "nats" => Ok(Self::Nats),
+            "automation" => Ok(Self::Automation),
+            other => Err(anyhow!("Unknown audit origin {other}")),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum AuditResult {
+    Ok,
+    Error {
+        message: String,
+    },
+    /// Queued for processing, the outcome is not known when the entry is recorded
+    Accepted,
+}
+
+/// A single mutating command: who issued it, against which task or instance, with which parameters and how it ended
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct AuditEntry {
+    /// Sequence number, assigned when the entry is stored
+    pub id:          Option<i64>,
+    #[schema(value_type = String)]
+    pub at:          Timestamp,
+    #[schema(value_type = String)]
+    pub origin:      AuditOrigin,
+    /// `cloud`, `key:<hash prefix>` for secure keys, `token:<subject>` for bearer tokens, `script:<name>` for automation
+    /// scripts or `anonymous`
+    pub actor:       String,
+    pub action:      String,
+    #[schema(value_type = Option<String>)]
+    pub task_id:     Option<AppTaskId>,
+    #[schema(value_type = Option<String>)]
+    pub instance_id: Option<FixedInstanceId>,
+    #[schema(value_type = Object)]
+    pub params:      Value,
+    #[schema(value_type = Object)]
+    pub result:      AuditResult,
+}
+
+impl AuditEntry {
+    pub fn new(origin: AuditOrigin, security: &DomainSecurity, action: &str) -> Self {
+        let mut rv = Self::anonymous(origin, action);
+        rv.actor = describe_actor(security);
+        rv
+    }
+
+    /// For commands that arrived without credentials
+    pub fn anonymous(origin: AuditOrigin, action: &str) -> Self {
+        Self { id:          { None },
+               at:          { now() },
+               origin:      { origin },
+               actor:       { String::from("anonymous") },
+               action:      { action.to_owned() },
+               task_id:     { None },
+               instance_id: { None },
+               params:      { Value::Null },
+               result:      { AuditResult::Accepted }, }
+    }
+
+    pub fn with_task(mut self, task_id: &AppTaskId) -> Self {
+        self.task_id = Some(task_id.clone());
+        self
+    }
+
+    pub fn with_instance(mut self, instance_id: &FixedInstanceId) -> Self {
+        self.instance_id = Some(instance_id.clone());
+        self
+    }
+
+    pub fn with_params(mut self, params: impl Serialize) -> Self {
+        self.params = serde_json::to_value(params).unwrap_or(Value::Null);
+        self
+    }
+
+    pub fn with_result<T>(mut self, result: &DomainResult<T>) -> Self {
+        self.result = match result {
+            Ok(_) => AuditResult::Ok,
+            Err(error) => AuditResult::Error { message: error.to_string(), },
+        };
+        self
+    }
+}
+
+/// Secure keys are never stored, only a prefix of their hash so entries by the same key can be correlated
+fn describe_actor(security: &DomainSecurity) -> String {
+    match security {
+        DomainSecurity::Cloud => String::from("cloud"),
+        DomainSecurity::SecureKey(secure_key) => {
+            let hash = Sha256::digest(secure_key.to_string().as_bytes());
+            let prefix = hash.iter()
+                             .take(6)
+                             .map(|byte| format!("{byte:02x}"))
+                             .collect::<String>();
+            format!("key:{prefix}")
+        }
+        DomainSecurity::Token(token) => format!("token:{}", token.subject),
+    }
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct AuditOpts {
+    /// Publish every audit entry as JSON to this NATS subject, in addition to storing it
+    #[clap(long, env)]
+    pub audit_nats_subject: Option<String>,
+}
+
+/// Record an audit entry, stored and published asynchronously
+pub fn record(entry: AuditEntry) {
+    match AUDIT_SUPERVISOR.get() {
+        Some(supervisor) => supervisor.do_send(RecordAuditEntry { entry }),
+        None => warn!(action = %entry.action, "Audit supervisor not initialized, dropping audit entry"),
+    }
+}
+
+/// Await a command and record its outcome in the audit log
+pub async fn audited<T, F>(entry: AuditEntry, fut: F) -> DomainResult<T>
+    where F: Future<Output = DomainResult<T>>
+{
+    let result = fut.await;
+    record(entry.with_result(&result));
+    result
+}
+
+#[instrument(skip_all, err)]
+pub fn init(db: Db, opts: AuditOpts) -> anyhow::Result<()> {
+    let supervisor = AuditSupervisor::new(db, opts);
+
+    AUDIT_SUPERVISOR.set(supervisor.start())
+                    .map_err(|_| anyhow!("Audit supervisor already initialized"))?;
+
+    Ok(())
+}
+
+pub fn get_audit_supervisor() -> &'static Addr<AuditSupervisor> {
+    AUDIT_SUPERVISOR.get().expect("Audit supervisor not initialized")
+}
@@ -0,0 +1,90 @@
+#![allow(unused_variables)]
+
+use actix::{Actor, Context, ContextFutureSpawner, Handler, ResponseFuture, WrapFuture};
+use actix_broker::BrokerSubscribe;
+use tracing::*;
+
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::Json;
+
+use crate::audit::{AuditEntry, AuditOpts, AuditOrigin, QueryAuditEntries, RecordAuditEntry};
+use crate::db::Db;
+use crate::events::NotifyDomainSessionCommand;
+use crate::{nats, DomainResult, DomainSecurity};
+
+const DEFAULT_QUERY_LIMIT: usize = 100;
+const MAX_QUERY_LIMIT: usize = 1000;
+
+pub struct AuditSupervisor {
+    db:   Db,
+    opts: AuditOpts,
+}
+
+impl AuditSupervisor {
+    pub fn new(db: Db, opts: AuditOpts) -> Self {
+        if let Some(subject) = &opts.audit_nats_subject {
+            nats::label_subject(subject, "audit");
+        }
+
+        Self { db:   { db },
+               opts: { opts }, }
+    }
+}
+
+impl Actor for AuditSupervisor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.subscribe_system_async::<NotifyDomainSessionCommand>(ctx);
+    }
+}
+
+impl Handler<RecordAuditEntry> for AuditSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: RecordAuditEntry, ctx: &mut Self::Context) -> Self::Result {
+        let db = self.db.clone();
+        let subject = self.opts.audit_nats_subject.clone();
+        let mut entry = msg.entry;
+
+        async move {
+            match db.append_audit_entry(&entry).await {
+                Ok(id) => entry.id = Some(id),
+                Err(error) => warn!(%error, action = %entry.action, "Failed to store audit entry"),
+            }
+
+            if let Some(subject) = subject {
+                if let Err(error) = nats::publish(&subject, Json, &entry).await {
+                    warn!(%error, %subject, "Failed to publish audit entry");
+                }
+            }
+        }.into_actor(self)
+         .spawn(ctx);
+    }
+}
+
+impl Handler<NotifyDomainSessionCommand> for AuditSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyDomainSessionCommand, ctx: &mut Self::Context) -> Self::Result {
+        let entry =
+            AuditEntry::new(AuditOrigin::Cloud, &DomainSecurity::Cloud, "domain_command").with_params(&msg.command);
+        self.handle(RecordAuditEntry { entry }, ctx);
+    }
+}
+
+impl Handler<QueryAuditEntries> for AuditSupervisor {
+    type Result = ResponseFuture<DomainResult<Vec<AuditEntry>>>;
+
+    fn handle(&mut self, msg: QueryAuditEntries, ctx: &mut Self::Context) -> Self::Result {
+        let db = self.db.clone();
+        let mut query = msg.query;
+        query.limit = Some(query.limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT));
+
+        Box::pin(async move {
+            db.query_audit_entries(&query)
+              .await
+              .map_err(|error| DomainError::BadGateway { error: error.to_string(), })
+        })
+    }
+}
@@ -0,0 +1,45 @@
+use actix::Message;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::automation::{AutomationRun, AutomationScript, AutomationTrigger};
+use crate::DomainResult;
+
+/// Script as submitted by an operator
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct AutomationScriptUpdate {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[schema(value_type = Object)]
+    pub trigger: AutomationTrigger,
+    pub source:  String,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<Vec<AutomationScript>>")]
+pub struct ListAutomationScripts;
+
+/// Create or replace a script, rejected if it does not compile
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<AutomationScript>")]
+pub struct SaveAutomationScript {
+    pub name:   String,
+    pub update: AutomationScriptUpdate,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult")]
+pub struct DeleteAutomationScript {
+    pub name: String,
+}
+
+/// Run a script right away, whatever its trigger
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<AutomationRun>")]
+pub struct RunAutomationScript {
+    pub name: String,
+}
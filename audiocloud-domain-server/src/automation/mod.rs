@@ -0,0 +1,108 @@
+use actix::{Actor, Addr};
+use anyhow::anyhow;
+use chrono::Timelike;
+use clap::Args;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use tracing::*;
+use utoipa::ToSchema;
+
+use audiocloud_api::{FixedInstanceId, Timestamp};
+pub use messages::*;
+use supervisor::AutomationSupervisor;
+
+use crate::db::Db;
+
+pub mod messages;
+mod sandbox;
+mod supervisor;
+#[cfg(test)]
+mod tests;
+
+static AUTOMATION_SUPERVISOR: OnceCell<Addr<AutomationSupervisor>> = OnceCell::new();
+
+/// An operator defined Rhai script run by the domain when its trigger fires
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct AutomationScript {
+    pub name:       String,
+    pub enabled:    bool,
+    #[schema(value_type = Object)]
+    pub trigger:    AutomationTrigger,
+    pub source:     String,
+    #[schema(value_type = String)]
+    pub updated_at: Timestamp,
+}
+
+/// When an automation script runs, besides being run by an operator
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AutomationTrigger {
+    /// Only when run by an operator
+    Manual,
+    /// Every day at the given UTC time, i.e. a nightly power-down sequence
+    Daily { hour: u32, minute: u32 },
+    /// When a fixed instance reports an error, any instance if none is given. The script gets `instance_id` and
+    /// `error` in scope.
+    InstanceError { instance_id: Option<FixedInstanceId> },
+    /// This many minutes before the reservation of a task starts, i.e. to warm up instances before a session. The
+    /// script gets `task_id` in scope.
+    BeforeReservation { minutes: u32 },
+}
+
+impl AutomationTrigger {
+    /// Daily triggers fire within the minute they are set to
+    pub fn is_due_daily(&self, at: Timestamp) -> bool {
+        matches!(self, Self::Daily { hour, minute } if at.hour() == *hour && at.minute() == *minute)
+    }
+
+    pub fn matches_instance_error(&self, instance: &FixedInstanceId) -> bool {
+        match self {
+            Self::InstanceError { instance_id } => instance_id.as_ref().map(|id| id == instance).unwrap_or(true),
+            _ => false,
+        }
+    }
+}
+
+/// Outcome of running an automation script
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct AutomationRun {
+    /// Lines the script printed or logged
+    pub log:     Vec<String>,
+    /// Domain actions the script requested, i.e. `power instance-1 channel 0 off`
+    pub actions: Vec<String>,
+    pub error:   Option<String>,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct AutomationOpts {
+    /// Run automation scripts at all, when disabled scripts can still be stored but never run
+    #[clap(long, env)]
+    pub enable_automation: bool,
+
+    /// Most Rhai operations a script may run before it is stopped, to keep a script from blocking the domain
+    #[clap(long, env, default_value = "100000")]
+    pub automation_max_operations: u64,
+
+    /// Most domain actions a single run of a script may request
+    #[clap(long, env, default_value = "64")]
+    pub automation_max_actions: usize,
+
+    /// Seconds before a script triggered by instance errors runs again, errors in between are ignored
+    #[clap(long, env, default_value = "60")]
+    pub automation_error_cooldown_seconds: u64,
+}
+
+#[instrument(skip_all, err)]
+pub fn init(db: Db, opts: AutomationOpts) -> anyhow::Result<()> {
+    let supervisor = AutomationSupervisor::new(db, opts);
+
+    AUTOMATION_SUPERVISOR.set(supervisor.start())
+                         .map_err(|_| anyhow!("Automation supervisor already initialized"))?;
+
+    Ok(())
+}
+
+pub fn get_automation_supervisor() -> &'static Addr<AutomationSupervisor> {
+    AUTOMATION_SUPERVISOR.get()
+                         .expect("Automation supervisor not initialized")
+}
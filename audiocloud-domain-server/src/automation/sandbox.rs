@@ -0,0 +1,180 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Scope};
+
+use audiocloud_api::FixedInstanceId;
+
+use crate::automation::{AutomationOpts, AutomationRun};
+
+/// Deepest a script may nest function calls and expressions
+const MAX_CALL_LEVELS: usize = 32;
+const MAX_EXPR_DEPTH: usize = 64;
+/// Largest string, array or map a script may build
+const MAX_VALUE_SIZE: usize = 64 * 1024;
+
+/// Something a script asks the domain to do, carried out by the supervisor once the script returned
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AutomationAction {
+    Power {
+        instance_id: FixedInstanceId,
+        channel:     usize,
+        power:       bool,
+    },
+    /// Power a channel off and on again after a few seconds
+    PowerCycle {
+        instance_id: FixedInstanceId,
+        channel:     usize,
+    },
+}
+
+impl AutomationAction {
+    pub fn describe(&self) -> String {
+        match self {
+            AutomationAction::Power { instance_id,
+                                      channel,
+                                      power, } => {
+                format!("power {instance_id} channel {channel} {}",
+                        if *power { "on" } else { "off" })
+            }
+            AutomationAction::PowerCycle { instance_id, channel } => {
+                format!("power cycle {instance_id} channel {channel}")
+            }
+        }
+    }
+}
+
+/// What a script may see of the domain
+#[derive(Clone, Debug, Default)]
+pub struct SandboxContext {
+    /// Constants in scope of the script, i.e. the `instance_id` that reported an error
+    pub variables: Vec<(&'static str, String)>,
+    /// Fixed instances of the domain, the only ones a script may act on
+    pub instances: Vec<FixedInstanceId>,
+}
+
+/// Check that a script compiles, without running it
+pub fn compile(opts: &AutomationOpts, source: &str) -> anyhow::Result<()> {
+    sandboxed_engine(opts).compile(source)?;
+    Ok(())
+}
+
+/// Run a script, collecting the actions it requests instead of carrying them out
+///
+/// Scripts only get the functions registered here: no modules, files or network. They are stopped after
+/// `AUTOMATION_MAX_OPERATIONS`, and fail once they request more than `AUTOMATION_MAX_ACTIONS`.
+pub fn run(opts: &AutomationOpts, source: &str, context: SandboxContext) -> (AutomationRun, Vec<AutomationAction>) {
+    let log = Rc::new(RefCell::new(vec![]));
+    let actions = Rc::new(RefCell::new(vec![]));
+    let instances = Rc::new(context.instances);
+    let max_actions = opts.automation_max_actions;
+
+    let mut engine = sandboxed_engine(opts);
+
+    engine.on_print({
+              let log = log.clone();
+              move |line| log.borrow_mut().push(line.to_owned())
+          })
+          .on_debug({
+              let log = log.clone();
+              move |line, _, pos| log.borrow_mut().push(format!("{pos:?}: {line}"))
+          });
+
+    engine.register_fn("log", {
+              let log = log.clone();
+              move |line: &str| log.borrow_mut().push(line.to_owned())
+          });
+
+    engine.register_fn("instances", {
+              let instances = instances.clone();
+              move || {
+                  instances.iter()
+                           .map(|id| Dynamic::from(id.to_string()))
+                           .collect::<Array>()
+              }
+          });
+
+    engine.register_fn("power", {
+              let instances = instances.clone();
+              let actions = actions.clone();
+              move |instance: &str, channel: i64, power: bool| -> Result<(), Box<EvalAltResult>> {
+                  let instance_id = known_instance(&instances, instance)?;
+                  let channel = channel_index(channel)?;
+                  push_action(&actions,
+                              max_actions,
+                              AutomationAction::Power { instance_id,
+                                                        channel,
+                                                        power })
+              }
+          });
+
+    engine.register_fn("power_cycle", {
+              let instances = instances.clone();
+              let actions = actions.clone();
+              move |instance: &str, channel: i64| -> Result<(), Box<EvalAltResult>> {
+                  let instance_id = known_instance(&instances, instance)?;
+                  let channel = channel_index(channel)?;
+                  push_action(&actions,
+                              max_actions,
+                              AutomationAction::PowerCycle { instance_id, channel })
+              }
+          });
+
+    let mut scope = Scope::new();
+    for (name, value) in context.variables {
+        scope.push_constant(name, value);
+    }
+
+    let error = engine.run_with_scope(&mut scope, source)
+                      .err()
+                      .map(|error| error.to_string());
+
+    // the engine holds clones of the log and the actions in its callbacks
+    drop(engine);
+
+    let actions = actions.take();
+    let run = AutomationRun { log:     { log.take() },
+                              actions: { actions.iter().map(AutomationAction::describe).collect() },
+                              error:   { error }, };
+
+    (run, actions)
+}
+
+fn sandboxed_engine(opts: &AutomationOpts) -> Engine {
+    let mut engine = Engine::new();
+
+    engine.set_module_resolver(DummyModuleResolver::new())
+          .set_max_operations(opts.automation_max_operations)
+          .set_max_call_levels(MAX_CALL_LEVELS)
+          .set_max_expr_depths(MAX_EXPR_DEPTH, MAX_EXPR_DEPTH)
+          .set_max_string_size(MAX_VALUE_SIZE)
+          .set_max_array_size(MAX_VALUE_SIZE)
+          .set_max_map_size(MAX_VALUE_SIZE);
+
+    engine
+}
+
+fn known_instance(instances: &[FixedInstanceId], instance: &str) -> Result<FixedInstanceId, Box<EvalAltResult>> {
+    instances.iter()
+             .find(|id| id.to_string() == instance)
+             .cloned()
+             .ok_or_else(|| format!("Unknown instance {instance}").into())
+}
+
+fn channel_index(channel: i64) -> Result<usize, Box<EvalAltResult>> {
+    usize::try_from(channel).map_err(|_| format!("Invalid power channel {channel}").into())
+}
+
+fn push_action(actions: &RefCell<Vec<AutomationAction>>,
+               max_actions: usize,
+               action: AutomationAction)
+               -> Result<(), Box<EvalAltResult>> {
+    let mut actions = actions.borrow_mut();
+    if actions.len() >= max_actions {
+        return Err(format!("More than {max_actions} actions requested").into());
+    }
+
+    actions.push(action);
+    Ok(())
+}
@@ -0,0 +1,379 @@
+#![allow(unused_variables)]
+
+use std::collections::{HashMap, HashSet};
+use std::convert::identity;
+use std::time::Duration;
+
+use actix::{
+    Actor, ActorFutureExt, AsyncContext, Context, ContextFutureSpawner, Handler, ResponseActFuture, ResponseFuture,
+    WrapFuture,
+};
+use actix_broker::BrokerSubscribe;
+use chrono::NaiveDate;
+use serde_json::json;
+use tracing::*;
+
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::{now, AppTaskId, FixedInstanceId, Timestamp};
+
+use crate::audit::{audited, AuditEntry, AuditOrigin};
+use crate::automation::sandbox::{self, AutomationAction, SandboxContext};
+use crate::automation::{
+    AutomationOpts, AutomationRun, AutomationScript, AutomationTrigger, DeleteAutomationScript, ListAutomationScripts,
+    RunAutomationScript, SaveAutomationScript,
+};
+use crate::db::Db;
+use crate::fixed_instances::{
+    get_instance_supervisor, ListFixedInstances, NotifyInstanceError, SetDesiredPowerChannel,
+};
+use crate::tasks::{NotifyTaskDeleted, NotifyTaskReservation};
+use crate::DomainResult;
+
+const TRIGGER_INTERVAL: Duration = Duration::from_secs(15);
+const POWER_CYCLE_DELAY: Duration = Duration::from_secs(5);
+
+pub struct AutomationSupervisor {
+    db:               Db,
+    opts:             AutomationOpts,
+    scripts:          HashMap<String, AutomationScript>,
+    /// Start of the reservation of each task
+    reservations:     HashMap<AppTaskId, Timestamp>,
+    /// Day each daily script last ran on, so it runs once within its minute
+    daily_runs:       HashMap<String, NaiveDate>,
+    /// Reservations each script already ran before
+    reservation_runs: HashSet<(String, AppTaskId)>,
+    /// When each script last ran for an instance error
+    error_runs:       HashMap<String, Timestamp>,
+}
+
+impl AutomationSupervisor {
+    pub fn new(db: Db, opts: AutomationOpts) -> Self {
+        Self { db:               { db },
+               opts:             { opts },
+               scripts:          { HashMap::new() },
+               reservations:     { HashMap::new() },
+               daily_runs:       { HashMap::new() },
+               reservation_runs: { HashSet::new() },
+               error_runs:       { HashMap::new() }, }
+    }
+
+    fn load_scripts(&mut self, ctx: &mut Context<Self>) {
+        let db = self.db.clone();
+
+        async move { db.fetch_automation_scripts().await }.into_actor(self)
+                                                          .map(|res, actor, ctx| match res {
+                                                              Ok(scripts) => {
+                                                                  info!(count = scripts.len(),
+                                                                        "Loaded automation scripts");
+                                                                  actor.scripts =
+                                                                      scripts.into_iter()
+                                                                             .map(|script| {
+                                                                                 (script.name.clone(), script)
+                                                                             })
+                                                                             .collect();
+                                                              }
+                                                              Err(error) => {
+                                                                  warn!(%error, "Failed to load automation scripts")
+                                                              }
+                                                          })
+                                                          .wait(ctx);
+    }
+
+    fn enabled_scripts(&self) -> impl Iterator<Item = &AutomationScript> {
+        self.scripts.values().filter(|script| script.enabled)
+    }
+
+    fn check_triggers(&mut self, ctx: &mut Context<Self>) {
+        let at = now();
+        let today = at.date_naive();
+
+        let daily = self.enabled_scripts()
+                        .filter(|script| script.trigger.is_due_daily(at))
+                        .filter(|script| self.daily_runs.get(&script.name) != Some(&today))
+                        .cloned()
+                        .collect::<Vec<_>>();
+
+        for script in daily {
+            self.daily_runs.insert(script.name.clone(), today);
+            self.fire(script, vec![], ctx);
+        }
+
+        let mut before_reservation = vec![];
+        for script in self.enabled_scripts() {
+            if let AutomationTrigger::BeforeReservation { minutes } = &script.trigger {
+                let lead = chrono::Duration::minutes(*minutes as i64);
+                for (task_id, from) in &self.reservations {
+                    let key = (script.name.clone(), task_id.clone());
+                    if *from - lead <= at && at < *from && !self.reservation_runs.contains(&key) {
+                        before_reservation.push((key, script.clone()));
+                    }
+                }
+            }
+        }
+
+        for (key, script) in before_reservation {
+            let variables = vec![("task_id", key.1.to_string())];
+            self.reservation_runs.insert(key);
+            self.fire(script, variables, ctx);
+        }
+    }
+
+    /// Run a script for its trigger, in the background
+    fn fire(&mut self, script: AutomationScript, variables: Vec<(&'static str, String)>, ctx: &mut Context<Self>) {
+        if !self.opts.enable_automation {
+            debug!(name = %script.name, "Automation disabled, not running script");
+            return;
+        }
+
+        let opts = self.opts.clone();
+        async move {
+            run_script(opts, script, variables).await;
+        }.into_actor(self)
+         .spawn(ctx);
+    }
+}
+
+impl Actor for AutomationSupervisor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.load_scripts(ctx);
+
+        self.subscribe_system_async::<NotifyInstanceError>(ctx);
+        self.subscribe_system_async::<NotifyTaskReservation>(ctx);
+        self.subscribe_system_async::<NotifyTaskDeleted>(ctx);
+
+        ctx.run_interval(TRIGGER_INTERVAL, Self::check_triggers);
+    }
+}
+
+impl Handler<NotifyInstanceError> for AutomationSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyInstanceError, ctx: &mut Self::Context) -> Self::Result {
+        let at = now();
+        let cooldown = chrono::Duration::seconds(self.opts.automation_error_cooldown_seconds as i64);
+
+        let scripts = self.enabled_scripts()
+                          .filter(|script| script.trigger.matches_instance_error(&msg.instance_id))
+                          .filter(|script| {
+                              self.error_runs
+                                  .get(&script.name)
+                                  .map(|last| at - *last >= cooldown)
+                                  .unwrap_or(true)
+                          })
+                          .cloned()
+                          .collect::<Vec<_>>();
+
+        for script in scripts {
+            self.error_runs.insert(script.name.clone(), at);
+            let variables = vec![("instance_id", msg.instance_id.to_string()),
+                                 ("error", msg.error.clone())];
+            self.fire(script, variables, ctx);
+        }
+    }
+}
+
+impl Handler<NotifyTaskReservation> for AutomationSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskReservation, ctx: &mut Self::Context) -> Self::Result {
+        let from = msg.reservation.from;
+        if self.reservations.insert(msg.task_id.clone(), from) != Some(from) {
+            // a moved reservation gets its warm-up again
+            self.reservation_runs.retain(|(_, task_id)| task_id != &msg.task_id);
+        }
+    }
+}
+
+impl Handler<NotifyTaskDeleted> for AutomationSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskDeleted, ctx: &mut Self::Context) -> Self::Result {
+        self.reservations.remove(&msg.task_id);
+        self.reservation_runs.retain(|(_, task_id)| task_id != &msg.task_id);
+    }
+}
+
+impl Handler<ListAutomationScripts> for AutomationSupervisor {
+    type Result = DomainResult<Vec<AutomationScript>>;
+
+    fn handle(&mut self, msg: ListAutomationScripts, ctx: &mut Self::Context) -> Self::Result {
+        let mut scripts = self.scripts.values().cloned().collect::<Vec<_>>();
+        scripts.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(scripts)
+    }
+}
+
+impl Handler<SaveAutomationScript> for AutomationSupervisor {
+    type Result = ResponseActFuture<Self, DomainResult<AutomationScript>>;
+
+    fn handle(&mut self, msg: SaveAutomationScript, ctx: &mut Self::Context) -> Self::Result {
+        let SaveAutomationScript { name, update } = msg;
+
+        if let Err(error) = validate_name(&name).and_then(|_| {
+                                                    sandbox::compile(&self.opts, &update.source)
+                                                        .map_err(|error| format!("Invalid automation script: {error}"))
+                                                })
+        {
+            return Box::pin(actix::fut::err(DomainError::Serialization { error }));
+        }
+
+        let script = AutomationScript { name:       { name },
+                                        enabled:    { update.enabled },
+                                        trigger:    { update.trigger },
+                                        source:     { update.source },
+                                        updated_at: { now() }, };
+
+        let db = self.db.clone();
+
+        Box::pin({
+                     let script = script.clone();
+                     async move { db.save_automation_script(&script).await }
+                 }.into_actor(self)
+                  .map(move |res, actor, ctx| match res {
+                      Ok(()) => {
+                          info!(name = %script.name, "Saved automation script");
+                          actor.daily_runs.remove(&script.name);
+                          actor.error_runs.remove(&script.name);
+                          actor.reservation_runs.retain(|(name, _)| name != &script.name);
+                          actor.scripts.insert(script.name.clone(), script.clone());
+                          Ok(script)
+                      }
+                      Err(error) => Err(DomainError::BadGateway { error: error.to_string(), }),
+                  }))
+    }
+}
+
+impl Handler<DeleteAutomationScript> for AutomationSupervisor {
+    type Result = ResponseActFuture<Self, DomainResult>;
+
+    fn handle(&mut self, msg: DeleteAutomationScript, ctx: &mut Self::Context) -> Self::Result {
+        let db = self.db.clone();
+        let name = msg.name;
+
+        Box::pin({
+                     let name = name.clone();
+                     async move { db.delete_automation_script(&name).await }
+                 }.into_actor(self)
+                  .map(move |res, actor, ctx| match res {
+                      Ok(existed) => {
+                          if existed {
+                              info!(%name, "Deleted automation script");
+                          }
+                          actor.scripts.remove(&name);
+                          actor.daily_runs.remove(&name);
+                          actor.error_runs.remove(&name);
+                          actor.reservation_runs.retain(|(script, _)| script != &name);
+                          Ok(())
+                      }
+                      Err(error) => Err(DomainError::BadGateway { error: error.to_string(), }),
+                  }))
+    }
+}
+
+impl Handler<RunAutomationScript> for AutomationSupervisor {
+    type Result = ResponseFuture<DomainResult<AutomationRun>>;
+
+    fn handle(&mut self, msg: RunAutomationScript, ctx: &mut Self::Context) -> Self::Result {
+        if !self.opts.enable_automation {
+            return Box::pin(async {
+                Err(DomainError::NotImplemented { call:   "run_automation_script".to_string(),
+                                                  reason: "Automation is not enabled on this domain".to_string(), })
+            });
+        }
+
+        let script = match self.scripts.get(&msg.name) {
+            Some(script) => script.clone(),
+            None => {
+                let error = format!("Unknown automation script {}", msg.name);
+                return Box::pin(async move { Err(DomainError::Serialization { error }) });
+            }
+        };
+
+        let opts = self.opts.clone();
+
+        Box::pin(async move { Ok(run_script(opts, script, vec![]).await) })
+    }
+}
+
+/// Script names end up in URLs and audit entries
+fn validate_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid automation script name {name}, use letters, digits, - and _"))
+    }
+}
+
+/// Run a script in the sandbox, then carry out the actions it requested if it completed
+async fn run_script(opts: AutomationOpts,
+                    script: AutomationScript,
+                    variables: Vec<(&'static str, String)>)
+                    -> AutomationRun {
+    let instances = match get_instance_supervisor().send(ListFixedInstances).await {
+        Ok(instances) => instances.into_iter().map(|summary| summary.instance_id).collect(),
+        Err(error) => {
+            warn!(%error, "Failed to list instances for automation script");
+            vec![]
+        }
+    };
+
+    let context = SandboxContext { variables: { variables },
+                                   instances: { instances }, };
+
+    let (mut run, actions) = sandbox::run(&opts, &script.source, context);
+
+    if run.error.is_none() {
+        for action in actions {
+            if let Err(error) = perform(&script.name, action).await {
+                run.error = Some(error.to_string());
+                break;
+            }
+        }
+    }
+
+    match &run.error {
+        None => info!(name = %script.name, actions = run.actions.len(), "Automation script ran"),
+        Some(error) => warn!(name = %script.name, %error, "Automation script failed"),
+    }
+
+    run
+}
+
+async fn perform(script: &str, action: AutomationAction) -> DomainResult {
+    match action {
+        AutomationAction::Power { instance_id,
+                                  channel,
+                                  power, } => set_power(script, instance_id, channel, power).await,
+        AutomationAction::PowerCycle { instance_id, channel } => {
+            set_power(script, instance_id.clone(), channel, false).await?;
+            actix::clock::sleep(POWER_CYCLE_DELAY).await;
+            set_power(script, instance_id, channel, true).await
+        }
+    }
+}
+
+async fn set_power(script: &str, instance_id: FixedInstanceId, channel: usize, power: bool) -> DomainResult {
+    let mut audit =
+        AuditEntry::anonymous(AuditOrigin::Automation, "set_power_channel").with_instance(&instance_id)
+                                                                           .with_params(json!({
+                                                                                            "channel": channel,
+                                                                                            "power": power,
+                                                                                        }));
+    audit.actor = format!("script:{script}");
+
+    let set = SetDesiredPowerChannel { instance_id: { instance_id },
+                                       channel:     { channel },
+                                       power:       { power }, };
+
+    audited(audit, async move {
+        get_instance_supervisor().send(set)
+                                 .await
+                                 .map_err(|error| DomainError::BadGateway { error: error.to_string(), })
+                                 .and_then(identity)
+    }).await
+}
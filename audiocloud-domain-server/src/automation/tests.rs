@@ -0,0 +1,86 @@
+use chrono::{TimeZone, Utc};
+
+use audiocloud_api::FixedInstanceId;
+
+use crate::automation::sandbox::{self, AutomationAction, SandboxContext};
+use crate::automation::{AutomationOpts, AutomationTrigger};
+
+fn test_opts() -> AutomationOpts {
+    AutomationOpts { enable_automation:                 true,
+                     automation_max_operations:         10_000,
+                     automation_max_actions:            4,
+                     automation_error_cooldown_seconds: 60, }
+}
+
+fn test_instance() -> FixedInstanceId {
+    FixedInstanceId::new("distopik".to_owned(), "dual1084".to_owned(), "1".to_owned())
+}
+
+fn test_context() -> SandboxContext {
+    SandboxContext { variables: vec![("error", "Overheated".to_string())],
+                     instances: vec![test_instance()], }
+}
+
+#[test]
+fn test_script_actions_are_collected_not_performed() {
+    let source = r#"
+        log("instance reported " + error);
+        for instance in instances() {
+            power(instance, 0, false);
+        }
+        power_cycle(instances()[0], 1);
+    "#;
+
+    let (run, actions) = sandbox::run(&test_opts(), source, test_context());
+
+    assert_eq!(run.error, None);
+    assert_eq!(run.log, vec!["instance reported Overheated".to_string()]);
+    assert_eq!(actions,
+               vec![AutomationAction::Power { instance_id: test_instance(),
+                                              channel:     0,
+                                              power:       false, },
+                    AutomationAction::PowerCycle { instance_id: test_instance(),
+                                                   channel:     1, },]);
+    assert_eq!(run.actions.len(), 2);
+}
+
+#[test]
+fn test_scripts_are_sandboxed() {
+    let opts = test_opts();
+
+    let (run, _) = sandbox::run(&opts, "loop {}", test_context());
+    assert!(run.error.is_some(), "endless scripts are stopped");
+
+    let (run, actions) = sandbox::run(&opts, r#"power("someone/else/1", 0, true);"#, test_context());
+    assert!(run.error.is_some(), "unknown instances are rejected");
+    assert!(actions.is_empty());
+
+    let (run, actions) = sandbox::run(&opts,
+                                      r#"for i in 0..10 { power(instances()[0], i, true); }"#,
+                                      test_context());
+    assert!(run.error.is_some(), "too many actions are rejected");
+    assert_eq!(actions.len(), opts.automation_max_actions);
+
+    let (run, _) = sandbox::run(&opts, r#"import "other" as other;"#, test_context());
+    assert!(run.error.is_some(), "modules can not be imported");
+
+    assert!(sandbox::compile(&opts, "let x = ;").is_err());
+}
+
+#[test]
+fn test_triggers() {
+    let nightly = AutomationTrigger::Daily { hour: 23, minute: 30 };
+
+    assert!(nightly.is_due_daily(Utc.with_ymd_and_hms(2022, 10, 21, 23, 30, 45).unwrap()));
+    assert!(!nightly.is_due_daily(Utc.with_ymd_and_hms(2022, 10, 21, 23, 31, 0).unwrap()));
+    assert!(!AutomationTrigger::Manual.is_due_daily(Utc.with_ymd_and_hms(2022, 10, 21, 23, 30, 0).unwrap()));
+
+    let any_instance = AutomationTrigger::InstanceError { instance_id: None };
+    let one_instance = AutomationTrigger::InstanceError { instance_id: Some(test_instance()), };
+    let other = FixedInstanceId::new("distopik".to_owned(), "la2a".to_owned(), "1".to_owned());
+
+    assert!(any_instance.matches_instance_error(&other));
+    assert!(one_instance.matches_instance_error(&test_instance()));
+    assert!(!one_instance.matches_instance_error(&other));
+    assert!(!nightly.matches_instance_error(&other));
+}
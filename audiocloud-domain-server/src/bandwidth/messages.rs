@@ -0,0 +1,50 @@
+use actix::Message;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use audiocloud_api::AppId;
+
+use crate::bandwidth::BandwidthBytes;
+use crate::DomainResult;
+
+/// An app sent a share of its monthly bandwidth cap it was not warned about yet this month
+#[derive(Message, Clone, Debug, Serialize, Deserialize)]
+#[rtype(result = "()")]
+pub struct NotifyBandwidthCapWarning {
+    pub app_id:     AppId,
+    /// `YYYY-MM`
+    pub month:      String,
+    pub sent_bytes: u64,
+    pub cap_bytes:  u64,
+    /// Share of the cap the warning is about, in percent
+    pub percent:    u64,
+}
+
+/// Bandwidth of an app in a month
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct AppBandwidthUsage {
+    #[schema(value_type = String)]
+    pub app_id:    AppId,
+    /// `YYYY-MM`
+    pub month:     String,
+    pub socket:    BandwidthBytes,
+    pub media:     BandwidthBytes,
+    /// Monthly cap on the bytes sent, if the app has one
+    pub cap_bytes: Option<u64>,
+}
+
+impl AppBandwidthUsage {
+    pub fn sent_bytes(&self) -> u64 {
+        self.socket.sent + self.media.sent
+    }
+}
+
+/// Saved bandwidth of the apps in a month, bytes counted since the last save are not included
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<Vec<AppBandwidthUsage>>")]
+pub struct ListBandwidthUsage {
+    /// `YYYY-MM`, the current month if not set
+    pub month:  Option<String>,
+    /// Only this app, all apps if not set
+    pub app_id: Option<AppId>,
+}
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::mem;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use anyhow::anyhow;
+use once_cell::sync::Lazy;
+use opentelemetry::metrics::Counter;
+use opentelemetry::{global, KeyValue};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use audiocloud_api::{AppId, Timestamp};
+
+use crate::o11y;
+
+/// Label of bytes that could not be attributed to an app
+const NO_APP: &str = "none";
+
+static METER: Lazy<BandwidthMeter> = Lazy::new(Default::default);
+static INSTRUMENTS: Lazy<BandwidthInstruments> = Lazy::new(BandwidthInstruments::new);
+
+struct BandwidthInstruments {
+    bytes_sent:     Counter<u64>,
+    bytes_received: Counter<u64>,
+}
+
+impl BandwidthInstruments {
+    fn new() -> Self {
+        let meter = global::meter("audiocloud.io/bandwidth");
+
+        let bytes_sent = meter.u64_counter("bandwidth_bytes_sent")
+                              .with_description("Bytes sent over sockets and media transfers, by app and channel")
+                              .init();
+        let bytes_received =
+            meter.u64_counter("bandwidth_bytes_received")
+                 .with_description("Bytes received over sockets and media transfers, by app and channel")
+                 .init();
+
+        Self { bytes_sent:     { bytes_sent },
+               bytes_received: { bytes_received }, }
+    }
+}
+
+/// What the bytes of an app went over
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BandwidthChannel {
+    /// Socket messages and stream packets, in both directions
+    Socket,
+    /// Media downloaded from and uploaded to the domain
+    Media,
+}
+
+impl BandwidthChannel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BandwidthChannel::Socket => "socket",
+            BandwidthChannel::Media => "media",
+        }
+    }
+}
+
+impl FromStr for BandwidthChannel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "socket" => Ok(BandwidthChannel::Socket),
+            "media" => Ok(BandwidthChannel::Media),
+            other => Err(anyhow!("Unknown bandwidth channel {other}")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BandwidthDirection {
+    Sent,
+    Received,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct BandwidthBytes {
+    pub sent:     u64,
+    pub received: u64,
+}
+
+impl BandwidthBytes {
+    pub fn add(&mut self, direction: BandwidthDirection, bytes: u64) {
+        match direction {
+            BandwidthDirection::Sent => self.sent += bytes,
+            BandwidthDirection::Received => self.received += bytes,
+        }
+    }
+
+    pub fn merge(&mut self, other: &BandwidthBytes) {
+        self.sent += other.sent;
+        self.received += other.received;
+    }
+}
+
+/// Bytes counted per app and channel since they were last saved
+pub type PendingBandwidth = HashMap<(AppId, BandwidthChannel), BandwidthBytes>;
+
+/// Counts the bytes of each app until the bandwidth supervisor saves them, shared by everything that moves bytes
+#[derive(Debug, Default)]
+pub struct BandwidthMeter {
+    pending: Mutex<PendingBandwidth>,
+}
+
+impl BandwidthMeter {
+    pub fn record(&self, app_id: &AppId, channel: BandwidthChannel, direction: BandwidthDirection, bytes: u64) {
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.entry((app_id.clone(), channel))
+                   .or_default()
+                   .add(direction, bytes);
+        }
+    }
+
+    pub fn take_pending(&self) -> PendingBandwidth {
+        self.pending
+            .lock()
+            .map(|mut pending| mem::take(&mut *pending))
+            .unwrap_or_default()
+    }
+
+    /// Count bytes that could not be saved again, so they are saved with the next ones
+    pub fn restore(&self, restored: PendingBandwidth) {
+        if let Ok(mut pending) = self.pending.lock() {
+            for (key, bytes) in restored {
+                pending.entry(key).or_default().merge(&bytes);
+            }
+        }
+    }
+}
+
+pub fn get_bandwidth_meter() -> &'static BandwidthMeter {
+    &METER
+}
+
+/// Count bytes in the metrics, and against the app for the usage API and its cap if they belong to one
+pub fn record_bandwidth(app_id: Option<&AppId>, channel: BandwidthChannel, direction: BandwidthDirection, bytes: u64) {
+    if bytes == 0 {
+        return;
+    }
+
+    let app = app_id.map(ToString::to_string).unwrap_or_else(|| NO_APP.to_owned());
+    let labels = [KeyValue::new("app", app), KeyValue::new("channel", channel.as_str())];
+
+    o11y::in_context(|ctx| match direction {
+        BandwidthDirection::Sent => INSTRUMENTS.bytes_sent.add(ctx, bytes, &labels),
+        BandwidthDirection::Received => INSTRUMENTS.bytes_received.add(ctx, bytes, &labels),
+    });
+
+    if let Some(app_id) = app_id {
+        METER.record(app_id, channel, direction, bytes);
+    }
+}
+
+/// Month bandwidth is accounted in, as `YYYY-MM` in UTC
+pub fn usage_month(at: Timestamp) -> String {
+    at.format("%Y-%m").to_string()
+}
+
+/// Monthly caps on the bytes sent for each app
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BandwidthCaps {
+    default: Option<u64>,
+    apps:    HashMap<AppId, u64>,
+}
+
+impl BandwidthCaps {
+    /// Caps from a default and `app_id=bytes` overrides
+    pub fn parse(default: Option<u64>, apps: &[String]) -> anyhow::Result<Self> {
+        let apps = apps.iter()
+                       .filter(|app| !app.trim().is_empty())
+                       .map(|app| parse_app_cap(app))
+                       .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self { default, apps })
+    }
+
+    pub fn cap_of(&self, app_id: &AppId) -> Option<u64> {
+        self.apps.get(app_id).copied().or(self.default)
+    }
+}
+
+fn parse_app_cap(app: &str) -> anyhow::Result<(AppId, u64)> {
+    let (app_id, bytes) = app.split_once('=')
+                             .ok_or_else(|| anyhow!("Bandwidth cap {app:?} is not app_id=bytes"))?;
+    let bytes = bytes.trim()
+                     .parse()
+                     .map_err(|error| anyhow!("Bandwidth cap of {app_id}: {error}"))?;
+
+    Ok((AppId::new(app_id.trim().to_owned()), bytes))
+}
+
+/// Highest of the warning `percents` of the cap the bytes sent reached, if the app was not warned about it yet
+pub fn cap_warning(sent: u64, cap: u64, percents: &[u64], warned: Option<u64>) -> Option<u64> {
+    percents.iter()
+            .copied()
+            .filter(|percent| sent as u128 * 100 >= cap as u128 * *percent as u128)
+            .filter(|percent| warned.map(|warned| *percent > warned).unwrap_or(true))
+            .max()
+}
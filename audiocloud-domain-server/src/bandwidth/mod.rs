@@ -0,0 +1,55 @@
+use actix::{Actor, Addr};
+use anyhow::anyhow;
+use clap::Args;
+use once_cell::sync::OnceCell;
+use tracing::*;
+
+pub use messages::*;
+pub use meter::*;
+use supervisor::BandwidthSupervisor;
+
+use crate::db::Db;
+
+pub mod messages;
+mod meter;
+mod supervisor;
+#[cfg(test)]
+mod tests;
+
+static BANDWIDTH_SUPERVISOR: OnceCell<Addr<BandwidthSupervisor>> = OnceCell::new();
+
+#[derive(Args, Clone, Debug)]
+pub struct BandwidthOpts {
+    /// Monthly cap on the bytes sent for each app over sockets and media transfers. Caps are soft, apps over them get
+    /// warning events but are not cut off
+    #[clap(long, env)]
+    pub bandwidth_monthly_cap_bytes: Option<u64>,
+
+    /// Monthly caps of specific apps as `app_id=bytes`, comma separated, instead of BANDWIDTH_MONTHLY_CAP_BYTES
+    #[clap(long, env, value_delimiter = ',')]
+    pub bandwidth_app_caps: Vec<String>,
+
+    /// Shares of the monthly cap in percent an app gets a warning event at, each once a month
+    #[clap(long, env, value_delimiter = ',', default_value = "80,100")]
+    pub bandwidth_cap_warning_percent: Vec<u64>,
+
+    /// How often the counted bytes are saved and checked against the caps, in seconds
+    #[clap(long, env, default_value = "60")]
+    pub bandwidth_flush_seconds: u64,
+}
+
+#[instrument(skip_all, err)]
+pub fn init(db: Db, opts: BandwidthOpts) -> anyhow::Result<()> {
+    let caps = BandwidthCaps::parse(opts.bandwidth_monthly_cap_bytes, &opts.bandwidth_app_caps)?;
+    let supervisor = BandwidthSupervisor::new(db, opts, caps);
+
+    BANDWIDTH_SUPERVISOR.set(supervisor.start())
+                        .map_err(|_| anyhow!("Bandwidth supervisor already initialized"))?;
+
+    Ok(())
+}
+
+pub fn get_bandwidth_supervisor() -> &'static Addr<BandwidthSupervisor> {
+    BANDWIDTH_SUPERVISOR.get()
+                        .expect("Bandwidth supervisor not initialized")
+}
@@ -0,0 +1,149 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use actix::{Actor, ActorFutureExt, AsyncContext, Context, ContextFutureSpawner, Handler, ResponseFuture, WrapFuture};
+use actix_broker::BrokerIssue;
+use tracing::*;
+
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::now;
+
+use crate::bandwidth::{
+    cap_warning, get_bandwidth_meter, usage_month, AppBandwidthUsage, BandwidthCaps, BandwidthOpts, ListBandwidthUsage,
+    NotifyBandwidthCapWarning, PendingBandwidth,
+};
+use crate::db::Db;
+use crate::DomainResult;
+
+/// Saves the bytes the meter counted for each app and warns about apps reaching shares of their monthly caps
+pub struct BandwidthSupervisor {
+    db:     Db,
+    opts:   BandwidthOpts,
+    caps:   BandwidthCaps,
+    /// Bytes are saved one batch at a time, so the totals the caps are checked against include the previous batch
+    saving: bool,
+}
+
+impl BandwidthSupervisor {
+    pub fn new(db: Db, opts: BandwidthOpts, caps: BandwidthCaps) -> Self {
+        Self { db:     { db },
+               opts:   { opts },
+               caps:   { caps },
+               saving: { false }, }
+    }
+
+    fn save_pending(&mut self, ctx: &mut Context<Self>) {
+        if self.saving {
+            return;
+        }
+
+        let pending = get_bandwidth_meter().take_pending();
+        if pending.is_empty() {
+            return;
+        }
+
+        self.saving = true;
+
+        let db = self.db.clone();
+        let caps = self.caps.clone();
+        let percents = self.opts.bandwidth_cap_warning_percent.clone();
+        let month = usage_month(now());
+
+        async move {
+            let res = save_and_check_caps(&db, &caps, &percents, &month, &pending).await;
+            (res, pending)
+        }.into_actor(self)
+         .map(|(res, pending), actor, _ctx| {
+             actor.saving = false;
+
+             match res {
+                 Ok(warnings) => {
+                     for warning in warnings {
+                         warn!(app_id = %warning.app_id,
+                               month = %warning.month,
+                               sent_bytes = warning.sent_bytes,
+                               cap_bytes = warning.cap_bytes,
+                               percent = warning.percent,
+                               "App reached a share of its monthly bandwidth cap");
+
+                         actor.issue_system_async(warning);
+                     }
+                 }
+                 Err(error) => {
+                     warn!(%error, "Failed to save bandwidth usage, keeping it for the next save");
+                     get_bandwidth_meter().restore(pending);
+                 }
+             }
+         })
+         .spawn(ctx);
+    }
+}
+
+async fn save_and_check_caps(db: &Db,
+                             caps: &BandwidthCaps,
+                             percents: &[u64],
+                             month: &str,
+                             pending: &PendingBandwidth)
+                             -> anyhow::Result<Vec<NotifyBandwidthCapWarning>> {
+    db.add_bandwidth_usage(month, pending).await?;
+
+    let mut warnings = vec![];
+    let apps = pending.keys().map(|(app_id, _)| app_id).collect::<HashSet<_>>();
+
+    for app_id in apps {
+        let cap_bytes = match caps.cap_of(app_id) {
+            Some(cap_bytes) => cap_bytes,
+            None => continue,
+        };
+
+        let sent_bytes = db.fetch_bandwidth_usage(month, Some(app_id))
+                           .await?
+                           .iter()
+                           .map(AppBandwidthUsage::sent_bytes)
+                           .sum();
+
+        let warned = db.fetch_bandwidth_warning(app_id, month).await?;
+
+        if let Some(percent) = cap_warning(sent_bytes, cap_bytes, percents, warned) {
+            db.save_bandwidth_warning(app_id, month, percent).await?;
+            warnings.push(NotifyBandwidthCapWarning { app_id:     { app_id.clone() },
+                                                      month:      { month.to_owned() },
+                                                      sent_bytes: { sent_bytes },
+                                                      cap_bytes:  { cap_bytes },
+                                                      percent:    { percent }, });
+        }
+    }
+
+    Ok(warnings)
+}
+
+impl Actor for BandwidthSupervisor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(Duration::from_secs(self.opts.bandwidth_flush_seconds.max(1)),
+                         Self::save_pending);
+    }
+}
+
+impl Handler<ListBandwidthUsage> for BandwidthSupervisor {
+    type Result = ResponseFuture<DomainResult<Vec<AppBandwidthUsage>>>;
+
+    fn handle(&mut self, msg: ListBandwidthUsage, _ctx: &mut Self::Context) -> Self::Result {
+        let db = self.db.clone();
+        let caps = self.caps.clone();
+        let month = msg.month.unwrap_or_else(|| usage_month(now()));
+
+        Box::pin(async move {
+            let mut usage = db.fetch_bandwidth_usage(&month, msg.app_id.as_ref())
+                              .await
+                              .map_err(|error| DomainError::BadGateway { error: error.to_string(), })?;
+
+            for app in &mut usage {
+                app.cap_bytes = caps.cap_of(&app.app_id);
+            }
+
+            Ok(usage)
+        })
+    }
+}
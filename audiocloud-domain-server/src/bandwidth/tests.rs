@@ -0,0 +1,70 @@
+use chrono::{TimeZone, Utc};
+
+use audiocloud_api::AppId;
+
+use crate::bandwidth::{
+    cap_warning, usage_month, BandwidthBytes, BandwidthCaps, BandwidthChannel, BandwidthDirection, BandwidthMeter,
+};
+
+fn app(name: &str) -> AppId {
+    AppId::new(name.to_owned())
+}
+
+#[test]
+fn test_app_caps_override_the_default() -> anyhow::Result<()> {
+    let caps = BandwidthCaps::parse(Some(1_000), &["studio=5000".to_owned(), " label = 200 ".to_owned()])?;
+
+    assert_eq!(caps.cap_of(&app("studio")), Some(5_000));
+    assert_eq!(caps.cap_of(&app("label")), Some(200));
+    assert_eq!(caps.cap_of(&app("other")), Some(1_000));
+
+    assert_eq!(BandwidthCaps::parse(None, &[])?.cap_of(&app("other")), None);
+    assert!(BandwidthCaps::parse(None, &["studio".to_owned()]).is_err());
+    assert!(BandwidthCaps::parse(None, &["studio=lots".to_owned()]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_cap_warnings_are_given_once_per_share() {
+    let percents = [80, 100];
+
+    assert_eq!(cap_warning(799, 1_000, &percents, None), None);
+    assert_eq!(cap_warning(800, 1_000, &percents, None), Some(80));
+    assert_eq!(cap_warning(900, 1_000, &percents, Some(80)), None);
+    assert_eq!(cap_warning(1_200, 1_000, &percents, Some(80)), Some(100));
+    assert_eq!(cap_warning(1_200, 1_000, &percents, Some(100)), None);
+
+    // an app blowing through several shares at once is warned about the highest
+    assert_eq!(cap_warning(1_000, 1_000, &percents, None), Some(100));
+}
+
+#[test]
+fn test_meter_keeps_bytes_until_saved() {
+    let meter = BandwidthMeter::default();
+    let studio = app("studio");
+
+    meter.record(&studio, BandwidthChannel::Socket, BandwidthDirection::Sent, 100);
+    meter.record(&studio, BandwidthChannel::Socket, BandwidthDirection::Received, 10);
+    meter.record(&studio, BandwidthChannel::Media, BandwidthDirection::Received, 5_000);
+
+    let pending = meter.take_pending();
+    assert_eq!(pending.get(&(studio.clone(), BandwidthChannel::Socket)),
+               Some(&BandwidthBytes { sent:     100,
+                                      received: 10, }));
+    assert!(meter.take_pending().is_empty());
+
+    meter.record(&studio, BandwidthChannel::Socket, BandwidthDirection::Sent, 50);
+    meter.restore(pending);
+
+    let pending = meter.take_pending();
+    assert_eq!(pending.get(&(studio.clone(), BandwidthChannel::Socket)),
+               Some(&BandwidthBytes { sent:     150,
+                                      received: 10, }));
+    assert_eq!(pending.get(&(studio, BandwidthChannel::Media)),
+               Some(&BandwidthBytes { sent:     0,
+                                      received: 5_000, }));
+
+    assert_eq!(usage_month(Utc.with_ymd_and_hms(2022, 10, 31, 23, 59, 0).unwrap()),
+               "2022-10");
+}
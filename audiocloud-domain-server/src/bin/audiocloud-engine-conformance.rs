@@ -0,0 +1,47 @@
+use clap::Parser;
+
+use audiocloud_domain_server::conformance;
+
+#[derive(Parser)]
+struct Opts {
+    #[clap(flatten)]
+    conformance: conformance::ConformanceOpts,
+
+    /// Print the report as JSON instead of a summary
+    #[clap(long, env)]
+    json: bool,
+}
+
+#[actix_web::main]
+async fn main() -> anyhow::Result<()> {
+    // plays the part of the domain against a live engine over NATS, checking the ack, ordering and error semantics the
+    // domain relies on. any engine implementation, REAPER based or not, can run this against itself.
+
+    let _ = dotenv::dotenv();
+
+    tracing_subscriber::fmt().with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+                             .init();
+
+    let opts = Opts::parse();
+
+    let report = conformance::run(&opts.conformance).await?;
+
+    if opts.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        for case in &report.cases {
+            let status = if case.passed { "PASS" } else { "FAIL" };
+            println!("{status} {:<28} {:>7} ms  {}",
+                     case.name, case.elapsed_ms, case.description);
+            if let Some(error) = &case.error {
+                println!("     {error}");
+            }
+        }
+    }
+
+    if !report.passed() {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
@@ -4,22 +4,29 @@ use actix::{Actor, ActorFutureExt, Addr, AsyncContext, Context, ContextFutureSpa
 use actix_broker::BrokerIssue;
 use anyhow::anyhow;
 use once_cell::sync::OnceCell;
+use reqwest::header::ETAG;
 use reqwest::{Client, Url};
 use tracing::*;
 
-use audiocloud_api::cloud::domains::DomainConfig;
-
-use crate::config::NotifyDomainConfiguration;
+use crate::config::{cloud_endpoints, NotifyDomainConfiguration};
 
 #[instrument(skip_all, err)]
-pub async fn get_config(url: Url, api_key: String) -> anyhow::Result<DomainConfig> {
-    let client = Client::new();
-    let url = url.join("/v1/domains/config")?;
+pub async fn get_config(api_key: String) -> anyhow::Result<(serde_json::Value, Option<String>)> {
+    let api_key = api_key.as_str();
+    cloud_endpoints().request("/v1/domains/config", move |url| get_config_from(url, api_key))
+                     .await
+}
+
+async fn get_config_from(url: Url, api_key: &str) -> anyhow::Result<(serde_json::Value, Option<String>)> {
+    let response = Client::new().get(url)
+                                .bearer_auth(api_key)
+                                .send()
+                                .await?
+                                .error_for_status()?;
+    let etag = response.headers()
+                       .get(ETAG)
+                       .and_then(|etag| etag.to_str().ok())
+                       .map(ToString::to_string);
 
-    Ok(client.get(url)
-             .bearer_auth(api_key)
-             .send()
-             .await?
-             .json::<DomainConfig>()
-             .await?)
+    Ok((response.json::<serde_json::Value>().await?, etag))
 }
@@ -0,0 +1,147 @@
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use once_cell::sync::OnceCell;
+use reqwest::{Client, Url};
+use tracing::*;
+
+static CLOUD_ENDPOINTS: OnceCell<CloudEndpoints> = OnceCell::new();
+
+/// Base URLs of the cloud, in order of preference, tried in turn until one answers
+///
+/// Requests start at the endpoint that answered last, so a failover sticks until that endpoint fails too. Endpoints
+/// that failed are tried last until their cooldown is over, or a health check finds them answering again.
+#[derive(Debug)]
+pub struct CloudEndpoints {
+    urls:     Vec<Url>,
+    cooldown: Duration,
+    state:    Mutex<EndpointsState>,
+}
+
+#[derive(Debug)]
+struct EndpointsState {
+    current:        usize,
+    unhealthy_till: Vec<Option<Instant>>,
+}
+
+impl CloudEndpoints {
+    pub fn new(urls: Vec<Url>, cooldown: Duration) -> anyhow::Result<Self> {
+        if urls.is_empty() {
+            return Err(anyhow!("At least one cloud URL must be configured"));
+        }
+
+        let state = EndpointsState { current:        { 0 },
+                                     unhealthy_till: { vec![None; urls.len()] }, };
+
+        Ok(Self { urls:     { urls },
+                  cooldown: { cooldown },
+                  state:    { Mutex::new(state) }, })
+    }
+
+    pub fn urls(&self) -> &[Url] {
+        &self.urls
+    }
+
+    /// Indexes of the endpoints in the order to try them, the current one and the healthy ones after it in rotation,
+    /// then the ones still cooling down
+    pub fn order(&self, now: Instant) -> Vec<usize> {
+        let state = self.state.lock().expect("cloud endpoints lock");
+        let rotation = (0..self.urls.len()).map(|offset| (state.current + offset) % self.urls.len());
+
+        let (healthy, cooling): (Vec<_>, Vec<_>) =
+            rotation.partition(|index| !matches!(state.unhealthy_till[*index], Some(till) if till > now));
+
+        healthy.into_iter().chain(cooling).collect()
+    }
+
+    pub fn succeeded(&self, index: usize) {
+        let mut state = self.state.lock().expect("cloud endpoints lock");
+        if state.current != index {
+            info!(url = %self.urls[index], "Cloud endpoint failover");
+        }
+
+        state.current = index;
+        state.unhealthy_till[index] = None;
+    }
+
+    pub fn failed(&self, index: usize, now: Instant) {
+        let mut state = self.state.lock().expect("cloud endpoints lock");
+        state.unhealthy_till[index] = Some(now + self.cooldown);
+    }
+
+    /// Health checks only change whether an endpoint is cooling down, not which one requests start at
+    fn checked(&self, index: usize, healthy: bool, now: Instant) {
+        let mut state = self.state.lock().expect("cloud endpoints lock");
+        state.unhealthy_till[index] = if healthy { None } else { Some(now + self.cooldown) };
+    }
+
+    /// Run a request against `path` of each endpoint in turn, until one succeeds
+    pub async fn request<T, F, R>(&self, path: &str, request: F) -> anyhow::Result<T>
+        where F: Fn(Url) -> R,
+              R: Future<Output = anyhow::Result<T>>
+    {
+        let mut errors = vec![];
+
+        for index in self.order(Instant::now()) {
+            let url = self.urls[index].join(path)?;
+
+            match request(url.clone()).await {
+                Ok(result) => {
+                    self.succeeded(index);
+                    return Ok(result);
+                }
+                Err(error) => {
+                    warn!(%error, %url, "Cloud endpoint request failed");
+                    self.failed(index, Instant::now());
+                    errors.push(format!("{url}: {error}"));
+                }
+            }
+        }
+
+        Err(anyhow!("Every cloud endpoint failed: {}", errors.join("; ")))
+    }
+}
+
+#[instrument(skip_all, err)]
+pub fn init(urls: Vec<Url>, cooldown: Duration, health_path: String, health_interval: Duration) -> anyhow::Result<()> {
+    CLOUD_ENDPOINTS.set(CloudEndpoints::new(urls, cooldown)?)
+                   .map_err(|_| anyhow!("Cloud endpoints already initialized"))?;
+
+    if cloud_endpoints().urls.len() > 1 && !health_interval.is_zero() {
+        actix::spawn(check_health(health_path, health_interval));
+    }
+
+    Ok(())
+}
+
+pub fn cloud_endpoints() -> &'static CloudEndpoints {
+    CLOUD_ENDPOINTS.get().expect("Cloud endpoints not initialized")
+}
+
+async fn check_health(path: String, interval: Duration) {
+    let endpoints = cloud_endpoints();
+    let client = Client::builder().timeout(interval.min(Duration::from_secs(10)))
+                                  .build()
+                                  .unwrap_or_default();
+
+    let mut interval = tokio::time::interval(interval);
+
+    loop {
+        interval.tick().await;
+
+        for (index, url) in endpoints.urls.iter().enumerate() {
+            let healthy = match url.join(&path) {
+                Ok(url) => matches!(client.get(url).send().await, Ok(response) if response.status().is_success()),
+                Err(_) => false,
+            };
+
+            if !healthy {
+                debug!(%url, "Cloud endpoint failed health check");
+            }
+
+            endpoints.checked(index, healthy, Instant::now());
+        }
+    }
+}
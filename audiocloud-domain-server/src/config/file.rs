@@ -1,9 +1,159 @@
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use serde_yaml::{Mapping, Value};
 use tracing::*;
 
-use audiocloud_api::cloud::domains::DomainConfig;
+/// Top level key listing files to merge before the file itself, as globs relative to the file's directory
+const INCLUDE_KEY: &str = "include";
+
+/// Files with this suffix before the extension override what the other files set instead of conflicting with it
+const OVERLAY_SUFFIX: &str = ".overlay";
 
+/// Load the config from a single YAML file, or from all YAML files in a directory merged into one
+///
+/// Files are merged in the order of their paths, mappings are merged key by key and any other value may only be set by
+/// one file, unless the files agree on it. Overlay files (`*.overlay.yaml`) replace values instead, and in a directory
+/// they are merged after all other files. Every file may `include` more files, which are merged before it.
+///
+/// The merged config is returned as JSON, so secret references in it are resolved the same way as in cloud configs.
 #[instrument(skip_all, err)]
-pub async fn get_config(path: PathBuf) -> anyhow::Result<DomainConfig> {
-    Ok(serde_yaml::from_slice(std::fs::read(path)?.as_slice())?)
-}
\ No newline at end of file
+pub async fn get_config(path: PathBuf) -> anyhow::Result<serde_json::Value> {
+    let mut merged = ConfigMerge::default();
+
+    if path.is_dir() {
+        let mut files = find_yaml_files(&path, &["*.yaml", "*.yml"])?;
+        files.sort_by_key(|file| is_overlay(file));
+
+        for file in files {
+            merged.merge_file(&file)?;
+        }
+    } else {
+        merged.merge_file(&path)?;
+    }
+
+    Ok(serde_json::to_value(merged.value)?)
+}
+
+/// A config being merged from several files, remembering which file set each value for conflict reports
+#[derive(Default)]
+pub(crate) struct ConfigMerge {
+    pub(crate) value: Value,
+    origins:          HashMap<String, PathBuf>,
+    loaded:           HashSet<PathBuf>,
+}
+
+impl ConfigMerge {
+    pub(crate) fn merge_file(&mut self, path: &Path) -> anyhow::Result<()> {
+        let canonical = path.canonicalize()
+                            .map_err(|error| anyhow!("Config file {} could not be opened: {error}", path.display()))?;
+
+        // a file included from several places, or included by a directory it is part of, is merged once
+        if !self.loaded.insert(canonical) {
+            return Ok(());
+        }
+
+        debug!(path = %path.display(), "Merging config file");
+
+        let mut value: Value =
+            serde_yaml::from_slice(std::fs::read(path)?.as_slice()).map_err(|error| {
+                                                                       anyhow!("Config file {} does not parse: {error}",
+                                                                               path.display())
+                                                                   })?;
+
+        if let Some(includes) = value.as_mapping_mut().and_then(|mapping| mapping.remove(INCLUDE_KEY)) {
+            let patterns = serde_yaml::from_value::<Vec<String>>(includes).map_err(|error| {
+                               anyhow!("Includes of {} must be a list of globs: {error}", path.display())
+                           })?;
+
+            let dir = path.parent().unwrap_or_else(|| Path::new("."));
+            let patterns = patterns.iter().map(String::as_str).collect::<Vec<_>>();
+
+            for included in find_yaml_files(dir, &patterns)? {
+                self.merge_file(&included)?;
+            }
+        }
+
+        self.merge(value, path, is_overlay(path))
+    }
+
+    pub(crate) fn merge(&mut self, value: Value, origin: &Path, overlay: bool) -> anyhow::Result<()> {
+        let mut target = std::mem::take(&mut self.value);
+        let rv = self.merge_at(&mut target, value, String::new(), origin, overlay);
+        self.value = target;
+
+        rv
+    }
+
+    fn merge_at(&mut self,
+                target: &mut Value,
+                value: Value,
+                key_path: String,
+                origin: &Path,
+                overlay: bool)
+                -> anyhow::Result<()> {
+        match (target, value) {
+            // empty files and explicit nulls do not set anything
+            (_, Value::Null) => {}
+            (target @ Value::Null, Value::Mapping(source)) => {
+                *target = Value::Mapping(Mapping::new());
+                self.merge_at(target, Value::Mapping(source), key_path, origin, overlay)?;
+            }
+            (Value::Mapping(target), Value::Mapping(source)) => {
+                for (key, value) in source {
+                    let child_path = match key.as_str() {
+                        Some(key) if key_path.is_empty() => key.to_owned(),
+                        Some(key) => format!("{key_path}.{key}"),
+                        None => format!("{key_path}.{key:?}"),
+                    };
+
+                    let existing = target.entry(key).or_insert(Value::Null);
+                    self.merge_at(existing, value, child_path, origin, overlay)?;
+                }
+            }
+            (target @ Value::Null, value) => {
+                *target = value;
+                self.origins.insert(key_path, origin.to_owned());
+            }
+            (target, value) if *target == value => {}
+            (target, value) if overlay => {
+                *target = value;
+                self.origins.insert(key_path, origin.to_owned());
+            }
+            (_, _) => {
+                let previous = self.origins
+                                   .get(&key_path)
+                                   .map(|previous| previous.display().to_string())
+                                   .unwrap_or_else(|| "an earlier file".to_owned());
+
+                return Err(anyhow!("Config key `{key_path}` is set differently by {previous} and {}",
+                                   origin.display()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn find_yaml_files(dir: &Path, patterns: &[&str]) -> anyhow::Result<Vec<PathBuf>> {
+    let walker = globwalk::GlobWalkerBuilder::from_patterns(dir, patterns).max_depth(4)
+                                                                          .follow_links(true)
+                                                                          .build()?;
+
+    let mut files = walker.filter_map(Result::ok)
+                          .filter(|entry| entry.file_type().is_file())
+                          .map(|entry| entry.into_path())
+                          .collect::<Vec<_>>();
+
+    // directory listing order differs between file systems, path order does not
+    files.sort();
+
+    Ok(files)
+}
+
+fn is_overlay(path: &Path) -> bool {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().ends_with(OVERLAY_SUFFIX))
+        .unwrap_or(false)
+}
@@ -1,14 +1,18 @@
 use std::collections::HashMap;
 
 use actix::Message;
+use serde::{Deserialize, Serialize};
 
 use audiocloud_api::cloud::domains::{DomainConfig, FixedInstanceRouting};
 use audiocloud_api::{FixedInstanceId, Model, ModelId};
 
+use crate::fixed_instances::FixedInstanceExtras;
+
 #[derive(Message, Clone, Debug)]
 #[rtype(result = "()")]
 pub struct NotifyDomainConfiguration {
     pub config: DomainConfig,
+    pub extras: FixedInstanceExtras,
 }
 
 #[derive(Message, Clone, Debug)]
@@ -22,3 +26,11 @@ pub struct NotifyModels {
 pub struct NotifyFixedInstanceRouting {
     pub routing: HashMap<FixedInstanceId, FixedInstanceRouting>,
 }
+
+/// Pushed by the orchestrator when the domain config changes
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ConfigChanged {
+    /// ETag of the new config, reloading is skipped when it matches the loaded one
+    #[serde(default)]
+    pub etag: Option<String>,
+}
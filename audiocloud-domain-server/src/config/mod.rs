@@ -1,18 +1,40 @@
 use std::path::PathBuf;
+use std::sync::Mutex;
 
 use actix_broker::{Broker, SystemBroker};
 use anyhow::anyhow;
 use clap::{Args, ValueEnum};
+use futures::StreamExt;
+use once_cell::sync::{Lazy, OnceCell};
 use reqwest::Url;
+use tokio::sync::mpsc;
 use tokio::time;
 use tracing::*;
 
 use audiocloud_api::cloud::domains::DomainConfig;
+pub use endpoints::{cloud_endpoints, CloudEndpoints};
 pub use messages::*;
+pub use validate::{validate_config, ConfigDiagnostic, ConfigDiagnosticSeverity, ConfigValidation};
+
+use crate::fixed_instances::FixedInstanceExtras;
+use crate::nats;
+use crate::rate_limit::{self, RateLimitConfig};
+use secrets::{SecretResolver, VaultSource};
 
 mod cloud;
+mod endpoints;
 mod file;
 mod messages;
+mod secrets;
+#[cfg(test)]
+mod tests;
+mod validate;
+
+/// Wakes up the config reload loop, with the ETag of the pushed config if the orchestrator sent one
+static CONFIG_RELOAD: OnceCell<mpsc::UnboundedSender<Option<String>>> = OnceCell::new();
+
+/// The config most recently loaded, before its secret references were resolved
+static LOADED_CONFIG: Lazy<Mutex<Option<serde_json::Value>>> = Lazy::new(Default::default);
 
 #[derive(Args, Debug, Clone)]
 pub struct ConfigOpts {
@@ -20,29 +42,58 @@ pub struct ConfigOpts {
     #[clap(short, long, env, default_value = "file", value_enum)]
     pub config_source: ConfigSource,
 
-    /// Path to the config file
+    /// Path to the config file, or to a directory of YAML files that are merged into the config
     #[clap(long, env, default_value = "config.yaml", required_if_eq("config_source", "file"))]
     pub config_file: PathBuf,
 
-    /// The base cloud URL to use for config retrieval
+    /// The base cloud URLs to use for config retrieval, ICE servers and JWKS, comma separated in order of preference.
+    /// Requests fail over to the next URL when one does not answer
     #[clap(long,
            env,
            default_value = "https://api.audiocloud.io",
+           value_delimiter = ',',
            required_if_eq("config_source", "cloud"))]
-    pub cloud_url: Url,
+    pub cloud_url: Vec<Url>,
+
+    /// Number of seconds a cloud URL that failed is tried last, unless a health check finds it answering again
+    #[clap(long, env, default_value = "60")]
+    pub cloud_endpoint_cooldown_seconds: u64,
+
+    /// Number of seconds between health checks of the cloud URLs, when there is more than one. 0 disables them
+    #[clap(long, env, default_value = "30")]
+    pub cloud_health_check_seconds: u64,
+
+    /// Path of the health check, relative to each cloud URL
+    #[clap(long, env, default_value = "/v1/health")]
+    pub cloud_health_check_path: String,
 
     #[clap(long, env, required_if_eq("config_source", "cloud"))]
     pub api_key: Option<String>,
 
     #[clap(long, env, default_value = "3600")]
     pub config_refresh_seconds: usize,
+
+    /// Vault server that `${vault:path#key}` references in config values are read from
+    #[clap(long, env)]
+    pub vault_addr: Option<Url>,
+
+    /// Token to read secrets from Vault with
+    #[clap(long, env, requires = "vault_addr")]
+    pub vault_token: Option<String>,
+
+    /// NATS subject the orchestrator pushes config change notifications to, the config is reloaded right away
+    #[clap(long, env)]
+    pub config_push_subject: Option<String>,
 }
 
 impl ConfigOpts {
     pub fn describe(&self) -> String {
         match self.config_source {
             ConfigSource::File => format!("file:{}", self.config_file.display()),
-            ConfigSource::Cloud => format!("cloud:{}", self.cloud_url),
+            ConfigSource::Cloud => {
+                let urls = self.cloud_url.iter().map(Url::to_string).collect::<Vec<_>>();
+                format!("cloud:{}", urls.join(","))
+            }
         }
     }
 }
@@ -55,46 +106,128 @@ pub enum ConfigSource {
     File,
 }
 
-async fn load_config(cfg: ConfigOpts) -> anyhow::Result<DomainConfig> {
-    match cfg.config_source {
+/// A loaded config, the fixed instance config it adds to the cloud API's and the ETag it was served with, if any
+type LoadedConfig = (DomainConfig, FixedInstanceExtras, Option<String>);
+
+/// Load the config and resolve the `${env:VAR}`, `${file:/path}` and `${vault:path#key}` references in its values, so
+/// secrets do not have to be written into it
+async fn load_config(cfg: ConfigOpts) -> anyhow::Result<LoadedConfig> {
+    let (mut value, etag) = match cfg.config_source {
         ConfigSource::Cloud => {
-            Ok(cloud::get_config(cfg.cloud_url,
-                                 cfg.api_key.ok_or_else(|| anyhow!("API key must be configured for cloud configuration"))?).await?)
+            let api_key = cfg.api_key
+                             .ok_or_else(|| anyhow!("API key must be configured for cloud configuration"))?;
+            cloud::get_config(api_key).await?
         }
-        ConfigSource::File => Ok(file::get_config(cfg.config_file).await?),
+        ConfigSource::File => (file::get_config(cfg.config_file).await?, None),
+    };
+
+    let vault = match (cfg.vault_addr, cfg.vault_token) {
+        (Some(addr), Some(token)) => Some(VaultSource { addr, token }),
+        _ => None,
+    };
+
+    let unresolved = value.clone();
+
+    SecretResolver::new(vault).resolve(&mut value).await?;
+
+    let extras = serde_json::from_value(value.clone())?;
+    let rate_limits = serde_json::from_value::<RateLimitConfig>(value.clone())?.rate_limits;
+    let config = serde_json::from_value(value)?;
+
+    rate_limit::configure(&rate_limits);
+
+    if let Ok(mut loaded) = LOADED_CONFIG.lock() {
+        *loaded = Some(unresolved);
     }
+
+    Ok((config, extras, etag))
+}
+
+/// The config most recently loaded, with `${..}` secret references as they were written. Secrets written into the
+/// config in place of references are not removed
+pub fn loaded_config() -> Option<serde_json::Value> {
+    LOADED_CONFIG.lock().ok().and_then(|loaded| loaded.clone())
 }
 
 #[instrument(skip_all, err)]
-pub async fn init(cfg: ConfigOpts) -> anyhow::Result<DomainConfig> {
-    let rv = load_config(cfg.clone()).await?;
+pub async fn init(cfg: ConfigOpts) -> anyhow::Result<(DomainConfig, FixedInstanceExtras)> {
+    endpoints::init(cfg.cloud_url.clone(),
+                    time::Duration::from_secs(cfg.cloud_endpoint_cooldown_seconds),
+                    cfg.cloud_health_check_path.clone(),
+                    time::Duration::from_secs(cfg.cloud_health_check_seconds))?;
+
+    let (rv, extras, etag) = load_config(cfg.clone()).await?;
+
+    let (tx_reload, mut rx_reload) = mpsc::unbounded_channel();
+    CONFIG_RELOAD.set(tx_reload)
+                 .map_err(|_| anyhow!("CONFIG_RELOAD already initialized"))?;
 
     actix::spawn({
-        let mut rv = rv.clone();
+        let mut loaded = (rv.clone(), extras.clone(), etag);
         async move {
             loop {
-                time::sleep(time::Duration::from_secs(cfg.config_refresh_seconds as u64)).await;
-                rv = async {
-                         debug!(source = cfg.describe(), "Reloading configuration");
-                         match load_config(cfg.clone()).await {
-                             Err(error) => {
-                                 error!(%error, "Failed to reload config");
-                                 rv
-                             }
-                             Ok(config) => {
-                                 if &rv != &config {
-                                     // TODO: this will not reload models or fixed instance routing
-                                     Broker::<SystemBroker>::issue_async(NotifyDomainConfiguration { config: config.clone() });
-                                     config
-                                 } else {
-                                     rv
-                                 }
-                             }
-                         }
-                     }.instrument(info_span!("config_reload")).await;
+                tokio::select! {
+                    _ = time::sleep(time::Duration::from_secs(cfg.config_refresh_seconds as u64)) => {},
+                    Some(pushed_etag) = rx_reload.recv() => {
+                        if pushed_etag.is_some() && pushed_etag == loaded.2 {
+                            debug!(etag = ?pushed_etag, "Pushed config is the one already loaded");
+                            continue;
+                        }
+                    }
+                }
+
+                loaded = reload_config(&cfg, loaded).instrument(info_span!("config_reload"))
+                                                    .await;
+            }
+        }
+    });
+
+    Ok((rv, extras))
+}
+
+async fn reload_config(cfg: &ConfigOpts, loaded: LoadedConfig) -> LoadedConfig {
+    debug!(source = cfg.describe(), "Reloading configuration");
+    match load_config(cfg.clone()).await {
+        Err(error) => {
+            error!(%error, "Failed to reload config");
+            loaded
+        }
+        Ok((config, extras, etag)) => {
+            if &loaded.0 != &config || &loaded.1 != &extras {
+                // TODO: this will not reload models
+                Broker::<SystemBroker>::issue_async(NotifyDomainConfiguration { config: { config.clone() },
+                                                                                extras: { extras.clone() }, });
+            }
+
+            (config, extras, etag)
+        }
+    }
+}
+
+/// Reload the config as soon as the orchestrator pushes a change notification on `subject`, instead of waiting for
+/// the next refresh. Needs NATS, so it is started after `nats::init`
+pub fn subscribe_config_pushes(subject: Option<String>) -> anyhow::Result<()> {
+    let subject = match subject {
+        Some(subject) => subject,
+        None => return Ok(()),
+    };
+
+    let tx_reload = CONFIG_RELOAD.get()
+                                 .ok_or_else(|| anyhow!("CONFIG_RELOAD not initialized"))?
+                                 .clone();
+
+    info!(%subject, "Listening for config change notifications");
+    nats::label_subject(&subject, "config_changes");
+
+    actix::spawn(async move {
+        let mut pushes = Box::pin(nats::subscribe_json::<ConfigChanged>(subject));
+        while let Some(changed) = pushes.next().await {
+            info!(etag = ?changed.etag, "Config change pushed");
+            if tx_reload.send(changed.etag).is_err() {
+                break;
             }
         }
     });
 
-    Ok(rv)
+    Ok(())
 }
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail};
+use futures::future::LocalBoxFuture;
+use futures::FutureExt;
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use reqwest::{Client, Url};
+use serde_json::Value;
+use tracing::*;
+
+/// A secret reference such as `${env:NATS_PASSWORD}`, `${file:/run/secrets/api_key}` or
+/// `${vault:secret/data/domain#api_key}`, a leading `$$` escapes it
+static SECRET_REFERENCE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\$(\$?)\{(env|file|vault):([^}]*)\}").expect("secret reference regex compiles"));
+
+/// Where `vault:` references are read from, a KV secrets engine of a Vault server
+#[derive(Clone, Debug)]
+pub(crate) struct VaultSource {
+    pub(crate) addr:  Url,
+    pub(crate) token: String,
+}
+
+/// Replaces secret references in the string values of a config with the secrets they point at
+pub(crate) struct SecretResolver {
+    vault:         Option<VaultSource>,
+    vault_secrets: HashMap<String, Value>,
+}
+
+impl SecretResolver {
+    pub(crate) fn new(vault: Option<VaultSource>) -> Self {
+        Self { vault:         { vault },
+               vault_secrets: { HashMap::new() }, }
+    }
+
+    /// Resolve every reference in `value`, failing on the first secret that is missing
+    #[instrument(skip_all, err)]
+    pub(crate) async fn resolve(&mut self, value: &mut Value) -> anyhow::Result<()> {
+        self.resolve_at(value, String::new()).await
+    }
+
+    fn resolve_at<'a>(&'a mut self, value: &'a mut Value, key_path: String) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        async move {
+            match value {
+                Value::String(text) => {
+                    if SECRET_REFERENCE.is_match(text) {
+                        *text = self.resolve_string(text)
+                                    .await
+                                    .map_err(|error| anyhow!("Config key `{key_path}`: {error}"))?;
+                    }
+                }
+                Value::Array(items) => {
+                    for (index, item) in items.iter_mut().enumerate() {
+                        self.resolve_at(item, format!("{key_path}[{index}]")).await?;
+                    }
+                }
+                Value::Object(object) => {
+                    for (key, item) in object.iter_mut() {
+                        let child_path = if key_path.is_empty() {
+                            key.clone()
+                        } else {
+                            format!("{key_path}.{key}")
+                        };
+
+                        self.resolve_at(item, child_path).await?;
+                    }
+                }
+                _ => {}
+            }
+
+            Ok(())
+        }.boxed_local()
+    }
+
+    pub(crate) async fn resolve_string(&mut self, text: &str) -> anyhow::Result<String> {
+        let mut resolved = String::with_capacity(text.len());
+        let mut last = 0;
+
+        for captures in SECRET_REFERENCE.captures_iter(text) {
+            let whole = captures.get(0).expect("capture 0 is always present");
+            resolved.push_str(&text[last..whole.start()]);
+            last = whole.end();
+
+            if captures[1].is_empty() {
+                resolved.push_str(&self.resolve_reference(&captures).await?);
+            } else {
+                // escaped, keep the reference without the first `$`
+                resolved.push_str(&whole.as_str()[1..]);
+            }
+        }
+
+        resolved.push_str(&text[last..]);
+
+        Ok(resolved)
+    }
+
+    async fn resolve_reference(&mut self, captures: &Captures<'_>) -> anyhow::Result<String> {
+        let (kind, reference) = (&captures[2], &captures[3]);
+
+        // the errors name the reference, never the secret
+        match kind {
+            "env" => std::env::var(reference).map_err(|error| anyhow!("Secret `env:{reference}` is not set: {error}")),
+            "file" => {
+                let contents = match tokio::fs::read_to_string(reference).await {
+                    Ok(contents) => contents,
+                    Err(error) => bail!("Secret `file:{reference}` could not be read: {error}"),
+                };
+
+                // secret files usually end with a newline that is not part of the secret
+                Ok(contents.trim_end_matches(&['\r', '\n'][..]).to_owned())
+            }
+            "vault" => self.resolve_vault(reference).await,
+            _ => bail!("Unsupported secret reference `{kind}:{reference}`"),
+        }
+    }
+
+    /// Read `path#key` from Vault, both KV version 1 and 2 layouts of the secret are understood
+    async fn resolve_vault(&mut self, reference: &str) -> anyhow::Result<String> {
+        let (path, key) = reference.split_once('#')
+                                   .ok_or_else(|| anyhow!("Secret `vault:{reference}` must be of the form path#key"))?;
+
+        if !self.vault_secrets.contains_key(path) {
+            let secret = self.fetch_vault_secret(path)
+                             .await
+                             .map_err(|error| anyhow!("Secret `vault:{reference}` could not be read: {error}"))?;
+
+            self.vault_secrets.insert(path.to_owned(), secret);
+        }
+
+        let data = &self.vault_secrets[path]["data"];
+        let value = data["data"].get(key).or_else(|| data.get(key));
+
+        match value {
+            Some(Value::String(value)) => Ok(value.clone()),
+            Some(value) if !value.is_null() => Ok(value.to_string()),
+            _ => bail!("Secret `vault:{reference}` has no key {key}"),
+        }
+    }
+
+    async fn fetch_vault_secret(&self, path: &str) -> anyhow::Result<Value> {
+        let vault = self.vault
+                        .as_ref()
+                        .ok_or_else(|| anyhow!("VAULT_ADDR and VAULT_TOKEN must be configured"))?;
+
+        let url = vault.addr.join(&format!("/v1/{}", path.trim_start_matches('/')))?;
+
+        debug!(%url, "Reading secret from Vault");
+
+        Ok(Client::new().get(url)
+                        .header("X-Vault-Token", &vault.token)
+                        .send()
+                        .await?
+                        .error_for_status()?
+                        .json::<Value>()
+                        .await?)
+    }
+}
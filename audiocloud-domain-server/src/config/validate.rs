@@ -0,0 +1,264 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use audiocloud_api::cloud::domains::{DomainConfig, DomainFixedInstanceConfig, FixedInstanceRouting};
+use audiocloud_api::FixedInstanceId;
+
+use crate::fixed_instances::{instance_routing, CompositeInstanceConfig, FixedInstanceExtras, MaintenanceWindow};
+use crate::models::load_models;
+use crate::rate_limit::RateLimitConfig;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigDiagnosticSeverity {
+    /// The config would not load or would misbehave
+    Error,
+    /// The config would load, but probably not as intended
+    Warning,
+}
+
+/// A problem found in a candidate config, `path` points at the offending element, i.e. `fixed_instances.{id}.power`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ConfigDiagnostic {
+    pub severity: ConfigDiagnosticSeverity,
+    pub path:     String,
+    pub message:  String,
+}
+
+/// Outcome of validating a candidate config, it is valid when there are no error diagnostics
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ConfigValidation {
+    pub valid:       bool,
+    pub diagnostics: Vec<ConfigDiagnostic>,
+}
+
+impl ConfigValidation {
+    fn error(&mut self, path: impl ToString, message: impl ToString) {
+        self.push(ConfigDiagnosticSeverity::Error, path, message);
+    }
+
+    fn warning(&mut self, path: impl ToString, message: impl ToString) {
+        self.push(ConfigDiagnosticSeverity::Warning, path, message);
+    }
+
+    fn push(&mut self, severity: ConfigDiagnosticSeverity, path: impl ToString, message: impl ToString) {
+        self.diagnostics.push(ConfigDiagnostic { severity: { severity },
+                                                 path:     { path.to_string() },
+                                                 message:  { message.to_string() }, });
+    }
+
+    fn finish(mut self) -> Self {
+        self.valid = !self.diagnostics
+                          .iter()
+                          .any(|diagnostic| diagnostic.severity == ConfigDiagnosticSeverity::Error);
+        self
+    }
+}
+
+/// Check a candidate config the way it would be loaded, without applying anything
+///
+/// Models are read from the source the candidate configures, so instances of models that are yet to be added validate.
+pub async fn validate_config(candidate: serde_json::Value) -> ConfigValidation {
+    let mut validation = ConfigValidation::default();
+
+    let config = match serde_json::from_value::<DomainConfig>(candidate.clone()) {
+        Ok(config) => config,
+        Err(error) => {
+            validation.error("", format!("Config does not parse: {error}"));
+            validate_fixed_instances_parse(&candidate, &mut validation);
+            return validation.finish();
+        }
+    };
+
+    match load_models(&config.models).await {
+        Ok(models) => {
+            let mut routing = HashMap::new();
+
+            for (id, instance) in &config.fixed_instances {
+                match models.get(&id.model_id()) {
+                    Some(model) => {
+                        if let Some(routed) = instance_routing(instance, model) {
+                            routing.insert(id.clone(), routed);
+                        }
+                    }
+                    None => validation.error(format!("fixed_instances.{id}"),
+                                             format!("Unknown model {}", id.model_id())),
+                }
+            }
+
+            validate_routing(&routing, &mut validation);
+        }
+        Err(error) => validation.error("models", format!("Models could not be loaded: {error}")),
+    }
+
+    for (id, instance) in &config.fixed_instances {
+        validate_fixed_instance(id, instance, &config, &mut validation);
+    }
+
+    match serde_json::from_value::<RateLimitConfig>(candidate.clone()) {
+        Ok(RateLimitConfig { rate_limits }) => {
+            for (name, limit) in [("rest", rate_limits.rest), ("socket", rate_limits.socket)] {
+                if limit.per_second < 0.0 || limit.burst < 0.0 {
+                    validation.error(format!("rate_limits.{name}"), "Rate and burst can not be negative");
+                }
+            }
+        }
+        Err(error) => validation.error("rate_limits", format!("Rate limits do not parse: {error}")),
+    }
+
+    match serde_json::from_value::<FixedInstanceExtras>(candidate) {
+        Ok(extras) => {
+            for (id, composite) in &extras.composite_instances {
+                validate_composite_instance(id, composite, &config, &mut validation);
+            }
+
+            for (id, windows) in &extras.maintenance {
+                validate_maintenance(id, windows, &config, &extras, &mut validation);
+            }
+
+            for (model_id, policies) in &extras.report_downsampling {
+                for (name, policy) in policies {
+                    if policy.interval_ms == 0 {
+                        validation.error(format!("report_downsampling.{model_id}.{name}"),
+                                         "Downsampling interval must be at least 1 ms");
+                    }
+                }
+            }
+        }
+        Err(error) => validation.error("",
+                                       format!("Composite instances, model sharing, maintenance or report \
+                                                downsampling do not parse: {error}")),
+    }
+
+    validation.finish()
+}
+
+/// Pinpoint the fixed instances whose IDs or configs do not parse, when the config as a whole does not
+fn validate_fixed_instances_parse(candidate: &serde_json::Value, validation: &mut ConfigValidation) {
+    let instances = match candidate.get("fixed_instances")
+                                   .and_then(|instances| instances.as_object())
+    {
+        Some(instances) => instances,
+        None => return,
+    };
+
+    for (id, instance) in instances {
+        if let Err(error) = FixedInstanceId::from_str(id) {
+            validation.error(format!("fixed_instances.{id}"),
+                             format!("Invalid fixed instance ID: {error}"));
+        }
+
+        if let Err(error) = serde_json::from_value::<DomainFixedInstanceConfig>(instance.clone()) {
+            validation.error(format!("fixed_instances.{id}"),
+                             format!("Fixed instance config does not parse: {error}"));
+        }
+    }
+}
+
+fn validate_fixed_instance(id: &FixedInstanceId,
+                           instance: &DomainFixedInstanceConfig,
+                           config: &DomainConfig,
+                           validation: &mut ConfigValidation) {
+    if instance.input_start.is_some() != instance.output_start.is_some() {
+        validation.warning(format!("fixed_instances.{id}"),
+                           "Only one of input_start and output_start is set, the instance will not be routed");
+    }
+
+    if let Some(power) = &instance.power {
+        if &power.instance == id {
+            validation.error(format!("fixed_instances.{id}.power"),
+                             "Instance is its own power controller");
+        } else if !config.fixed_instances.contains_key(&power.instance) {
+            validation.error(format!("fixed_instances.{id}.power"),
+                             format!("Unknown power controller {}", power.instance));
+        }
+    }
+}
+
+fn validate_composite_instance(id: &FixedInstanceId,
+                               composite: &CompositeInstanceConfig,
+                               config: &DomainConfig,
+                               validation: &mut ConfigValidation) {
+    if config.fixed_instances.contains_key(id) {
+        validation.error(format!("composite_instances.{id}"),
+                         "Composite instance has the ID of a fixed instance");
+    }
+
+    if composite.members.is_empty() {
+        validation.error(format!("composite_instances.{id}.members"),
+                         "Composite instance has no members");
+    }
+
+    let mut prefixes = HashSet::new();
+
+    for (i, member) in composite.members.iter().enumerate() {
+        if !config.fixed_instances.contains_key(&member.instance_id) {
+            validation.error(format!("composite_instances.{id}.members.{i}"),
+                             format!("Unknown fixed instance {}", member.instance_id));
+        }
+
+        if member.prefix.is_empty() || member.prefix.contains('.') {
+            validation.error(format!("composite_instances.{id}.members.{i}.prefix"),
+                             "Prefix must not be empty nor contain a dot");
+        }
+
+        if !prefixes.insert(&member.prefix) {
+            validation.error(format!("composite_instances.{id}.members.{i}.prefix"),
+                             format!("Prefix {} is used by another member", member.prefix));
+        }
+    }
+}
+
+fn validate_maintenance(id: &FixedInstanceId,
+                        windows: &[MaintenanceWindow],
+                        config: &DomainConfig,
+                        extras: &FixedInstanceExtras,
+                        validation: &mut ConfigValidation) {
+    if !config.fixed_instances.contains_key(id) && !extras.composite_instances.contains_key(id) {
+        validation.error(format!("maintenance.{id}"), format!("Unknown fixed instance {id}"));
+    }
+
+    for (i, window) in windows.iter().enumerate() {
+        if window.from >= window.to {
+            validation.error(format!("maintenance.{id}.{i}"),
+                             "Maintenance window ends before it starts");
+        }
+    }
+}
+
+/// Instances may not share the channels they are sent on, nor the channels they return on
+pub(super) fn validate_routing(routing: &HashMap<FixedInstanceId, FixedInstanceRouting>,
+                               validation: &mut ConfigValidation) {
+    let mut instances = routing.iter().collect::<Vec<_>>();
+    instances.sort_by(|(a, _), (b, _)| a.to_string().cmp(&b.to_string()));
+
+    for (i, (id, routing)) in instances.iter().enumerate() {
+        for (other_id, other_routing) in &instances[i + 1..] {
+            if overlaps(send_channels(routing), send_channels(other_routing)) {
+                validation.error(format!("fixed_instances.{id}.output_start"),
+                                 format!("Send channels overlap those of {other_id}"));
+            }
+
+            if overlaps(return_channels(routing), return_channels(other_routing)) {
+                validation.error(format!("fixed_instances.{id}.input_start"),
+                                 format!("Return channels overlap those of {other_id}"));
+            }
+        }
+    }
+}
+
+fn send_channels(routing: &FixedInstanceRouting) -> Range<usize> {
+    routing.send_channel..routing.send_channel + routing.send_count
+}
+
+fn return_channels(routing: &FixedInstanceRouting) -> Range<usize> {
+    routing.return_channel..routing.return_channel + routing.return_count
+}
+
+fn overlaps(a: Range<usize>, b: Range<usize>) -> bool {
+    a.start < b.end && b.start < a.end
+}
@@ -0,0 +1,270 @@
+use std::time::Duration;
+
+use anyhow::bail;
+use async_trait::async_trait;
+
+use audiocloud_api::audio_engine::{EngineCommand, EngineEvent};
+use audiocloud_api::AppTaskId;
+
+use crate::conformance::engine::EngineUnderTest;
+use crate::conformance::ConformanceFixture;
+
+/// A single rule of the engine contract, run against a task ID that no other case uses
+#[async_trait(?Send)]
+pub trait ConformanceCase {
+    fn name(&self) -> &'static str;
+
+    fn description(&self) -> &'static str;
+
+    async fn run(&self,
+                 engine: &mut EngineUnderTest,
+                 task_id: &AppTaskId,
+                 fixture: &ConformanceFixture)
+                 -> anyhow::Result<()>;
+}
+
+pub fn all_cases() -> Vec<Box<dyn ConformanceCase>> {
+    vec![Box::new(SetSpecAcknowledged),
+         Box::new(UnknownTaskRejected),
+         Box::new(PlayLifecycle),
+         Box::new(RenderLifecycle),
+         Box::new(CancelRender)]
+}
+
+/// Events may keep arriving for a while after a terminal event, anything arriving within this window is still checked
+const SETTLE: Duration = Duration::from_secs(1);
+
+fn set_spec(task_id: &AppTaskId, fixture: &ConformanceFixture) -> EngineCommand {
+    EngineCommand::SetSpec { task_id:     { task_id.clone() },
+                             spec:        { fixture.spec.clone() },
+                             instances:   { fixture.instances.clone() },
+                             media_ready: { fixture.media_ready.clone() }, }
+}
+
+struct SetSpecAcknowledged;
+
+#[async_trait(?Send)]
+impl ConformanceCase for SetSpecAcknowledged {
+    fn name(&self) -> &'static str {
+        "set_spec_acknowledged"
+    }
+
+    fn description(&self) -> &'static str {
+        "SetSpec for a new task is acknowledged with success, and setting the same spec again is idempotent"
+    }
+
+    async fn run(&self,
+                 engine: &mut EngineUnderTest,
+                 task_id: &AppTaskId,
+                 fixture: &ConformanceFixture)
+                 -> anyhow::Result<()> {
+        engine.expect_ok(set_spec(task_id, fixture)).await?;
+        engine.expect_ok(set_spec(task_id, fixture)).await?;
+
+        engine.observe(task_id, SETTLE, |event| match event {
+                  EngineEvent::Error { error, .. } => bail!("Engine reported an error after SetSpec: {error}"),
+                  EngineEvent::Playing { .. } | EngineEvent::Rendering { .. } => {
+                      bail!("Engine started transport without being asked to: {event:?}")
+                  }
+                  _ => Ok(()),
+              })
+              .await
+    }
+}
+
+struct UnknownTaskRejected;
+
+#[async_trait(?Send)]
+impl ConformanceCase for UnknownTaskRejected {
+    fn name(&self) -> &'static str {
+        "unknown_task_rejected"
+    }
+
+    fn description(&self) -> &'static str {
+        "Transport commands for a task without a spec are rejected with an error reply, not ignored"
+    }
+
+    async fn run(&self,
+                 engine: &mut EngineUnderTest,
+                 task_id: &AppTaskId,
+                 fixture: &ConformanceFixture)
+                 -> anyhow::Result<()> {
+        engine.expect_error(EngineCommand::Play { task_id: { task_id.clone() },
+                                                  play:    { fixture.play.clone() }, })
+              .await?;
+
+        engine.expect_error(EngineCommand::Render { task_id: { task_id.clone() },
+                                                    render:  { fixture.render.clone() }, })
+              .await?;
+
+        engine.observe(task_id, SETTLE, |event| match event {
+                  EngineEvent::Playing { .. } | EngineEvent::Rendering { .. } => {
+                      bail!("Engine started transport for an unknown task: {event:?}")
+                  }
+                  _ => Ok(()),
+              })
+              .await
+    }
+}
+
+struct PlayLifecycle;
+
+#[async_trait(?Send)]
+impl ConformanceCase for PlayLifecycle {
+    fn name(&self) -> &'static str {
+        "play_lifecycle"
+    }
+
+    fn description(&self) -> &'static str {
+        "Play is acknowledged and followed by Playing events for the play ID, StopPlay is followed by Stopped and no \
+         further Playing events"
+    }
+
+    async fn run(&self,
+                 engine: &mut EngineUnderTest,
+                 task_id: &AppTaskId,
+                 fixture: &ConformanceFixture)
+                 -> anyhow::Result<()> {
+        let play_id = fixture.play.play_id.clone();
+        let within = fixture.event_timeout();
+
+        engine.expect_ok(set_spec(task_id, fixture)).await?;
+        engine.expect_ok(EngineCommand::Play { task_id: { task_id.clone() },
+                                               play:    { fixture.play.clone() }, })
+              .await?;
+
+        engine.wait_for(task_id, within, "Playing", |event| match event {
+                  EngineEvent::Playing { play_id: id, .. } if id == &play_id => Ok(Some(())),
+                  EngineEvent::Playing { play_id: id, .. } => bail!("Playing event for unexpected play ID {id}"),
+                  EngineEvent::PlayingFailed { error, .. } => bail!("Playing failed: {error}"),
+                  EngineEvent::Stopped { .. } => bail!("Stopped before any Playing event"),
+                  _ => Ok(None),
+              })
+              .await?;
+
+        engine.expect_ok(EngineCommand::StopPlay { task_id: { task_id.clone() },
+                                                   play_id: { play_id.clone() }, })
+              .await?;
+
+        // Playing events already in flight when StopPlay was acknowledged are tolerated until Stopped
+        engine.wait_for(task_id, within, "Stopped", |event| match event {
+                  EngineEvent::Stopped { .. } => Ok(Some(())),
+                  EngineEvent::Playing { .. } => Ok(None),
+                  other => bail!("Unexpected event while stopping: {other:?}"),
+              })
+              .await?;
+
+        engine.observe(task_id, SETTLE, |event| match event {
+                  EngineEvent::Playing { .. } => bail!("Playing event after Stopped"),
+                  _ => Ok(()),
+              })
+              .await
+    }
+}
+
+struct RenderLifecycle;
+
+#[async_trait(?Send)]
+impl ConformanceCase for RenderLifecycle {
+    fn name(&self) -> &'static str {
+        "render_lifecycle"
+    }
+
+    fn description(&self) -> &'static str {
+        "Render is acknowledged, Rendering completion never decreases and exactly one RenderingFinished or \
+         RenderingFailed ends the render"
+    }
+
+    async fn run(&self,
+                 engine: &mut EngineUnderTest,
+                 task_id: &AppTaskId,
+                 fixture: &ConformanceFixture)
+                 -> anyhow::Result<()> {
+        let render_id = fixture.render.render_id.clone();
+        let mut last_completion = 0f64;
+
+        engine.expect_ok(set_spec(task_id, fixture)).await?;
+        engine.expect_ok(EngineCommand::Render { task_id: { task_id.clone() },
+                                                 render:  { fixture.render.clone() }, })
+              .await?;
+
+        engine.wait_for(task_id,
+                        fixture.render_timeout(),
+                        "RenderingFinished",
+                        |event| match event {
+                            EngineEvent::Rendering { render_id: id, .. }
+                            | EngineEvent::RenderingFinished { render_id: id, .. }
+                                if id != &render_id =>
+                            {
+                                bail!("Render event for unexpected render ID {id}")
+                            }
+                            EngineEvent::Rendering { completion, .. } => {
+                                if *completion < last_completion {
+                                    bail!("Rendering completion went backwards from {last_completion} to {completion}");
+                                }
+                                last_completion = *completion;
+                                Ok(None)
+                            }
+                            EngineEvent::RenderingFinished { .. } => Ok(Some(())),
+                            EngineEvent::RenderingFailed { error, .. } => bail!("Rendering failed: {error}"),
+                            EngineEvent::Playing { .. } => bail!("Playing event during render"),
+                            _ => Ok(None),
+                        })
+              .await?;
+
+        engine.observe(task_id, SETTLE, |event| match event {
+                  EngineEvent::Rendering { .. }
+                  | EngineEvent::RenderingFinished { .. }
+                  | EngineEvent::RenderingFailed { .. } => bail!("Render event after RenderingFinished: {event:?}"),
+                  _ => Ok(()),
+              })
+              .await
+    }
+}
+
+struct CancelRender;
+
+#[async_trait(?Send)]
+impl ConformanceCase for CancelRender {
+    fn name(&self) -> &'static str {
+        "cancel_render"
+    }
+
+    fn description(&self) -> &'static str {
+        "CancelRender is acknowledged and the render ends with RenderingFailed or Stopped, never RenderingFinished"
+    }
+
+    async fn run(&self,
+                 engine: &mut EngineUnderTest,
+                 task_id: &AppTaskId,
+                 fixture: &ConformanceFixture)
+                 -> anyhow::Result<()> {
+        let render_id = fixture.render.render_id.clone();
+
+        engine.expect_ok(set_spec(task_id, fixture)).await?;
+        engine.expect_ok(EngineCommand::Render { task_id: { task_id.clone() },
+                                                 render:  { fixture.render.clone() }, })
+              .await?;
+        engine.expect_ok(EngineCommand::CancelRender { task_id:   { task_id.clone() },
+                                                       render_id: { render_id }, })
+              .await?;
+
+        engine.wait_for(task_id,
+                        fixture.event_timeout(),
+                        "RenderingFailed or Stopped",
+                        |event| match event {
+                            EngineEvent::RenderingFailed { .. } | EngineEvent::Stopped { .. } => Ok(Some(())),
+                            EngineEvent::RenderingFinished { .. } => bail!("Cancelled render finished"),
+                            _ => Ok(None),
+                        })
+              .await?;
+
+        engine.observe(task_id, SETTLE, |event| match event {
+                  EngineEvent::Rendering { .. } | EngineEvent::RenderingFinished { .. } => {
+                      bail!("Render event after cancellation: {event:?}")
+                  }
+                  _ => Ok(()),
+              })
+              .await
+    }
+}
@@ -0,0 +1,126 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use nats_aflowt::{connect, Connection, Subscription};
+use tokio::time::{timeout, Instant};
+use tracing::*;
+
+use audiocloud_api::audio_engine::{EngineCommand, EngineError, EngineEvent};
+use audiocloud_api::{AppTaskId, Codec, MsgPack, SerializableResult};
+
+use crate::conformance::ConformanceOpts;
+
+/// The engine being tested, seen the same way the domain sees it: a NATS request subject for commands and a subject on
+/// which the engine publishes events
+pub struct EngineUnderTest {
+    connection:      Connection,
+    command_subject: String,
+    events:          Subscription,
+    reply_timeout:   Duration,
+}
+
+impl EngineUnderTest {
+    pub async fn connect(opts: &ConformanceOpts) -> anyhow::Result<Self> {
+        let connection = connect(&opts.nats_url).await?;
+        let events = connection.subscribe(&opts.engine_event_subject).await?;
+
+        Ok(Self { connection:      { connection },
+                  command_subject: { opts.engine_command_subject.clone() },
+                  events:          { events },
+                  reply_timeout:   { Duration::from_millis(opts.reply_timeout_ms) }, })
+    }
+
+    /// Send a command and decode the reply, failing if the engine does not reply in time or the reply does not decode
+    pub async fn command(&self, cmd: EngineCommand) -> anyhow::Result<SerializableResult<(), EngineError>> {
+        trace!(?cmd, "Sending");
+
+        let request = MsgPack.serialize(&cmd)?;
+        let reply = timeout(self.reply_timeout,
+                            self.connection.request(&self.command_subject, &request)).await
+                                                                                     .map_err(|_| {
+                                                                                         anyhow!("No reply within {:?}",
+                                                                                                 self.reply_timeout)
+                                                                                     })??;
+
+        MsgPack.deserialize(&reply.data)
+               .map_err(|error| anyhow!("Reply does not decode as SerializableResult<(), EngineError>: {error}"))
+    }
+
+    /// Send a command that must be acknowledged with success
+    pub async fn expect_ok(&self, cmd: EngineCommand) -> anyhow::Result<()> {
+        match self.command(cmd).await? {
+            SerializableResult::Ok(()) => Ok(()),
+            SerializableResult::Error(error) => bail!("Expected success, engine replied with error: {error}"),
+        }
+    }
+
+    /// Send a command that must be rejected with an error reply
+    pub async fn expect_error(&self, cmd: EngineCommand) -> anyhow::Result<()> {
+        match self.command(cmd).await? {
+            SerializableResult::Ok(()) => bail!("Expected an error reply, engine replied with success"),
+            SerializableResult::Error(_) => Ok(()),
+        }
+    }
+
+    /// Next event concerning `task_id`, or `None` if none arrives within `within`
+    pub async fn next_event(&mut self, task_id: &AppTaskId, within: Duration) -> anyhow::Result<Option<EngineEvent>> {
+        let deadline = Instant::now() + within;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let msg = match timeout(remaining, self.events.next()).await {
+                Err(_) => return Ok(None),
+                Ok(None) => bail!("Event subscription closed"),
+                Ok(Some(msg)) => msg,
+            };
+
+            let event: EngineEvent = MsgPack.deserialize(&msg.data)
+                                            .map_err(|error| anyhow!("Event does not decode as EngineEvent: {error}"))?;
+
+            if event.task_id() == task_id {
+                trace!(?event, "Received");
+                return Ok(Some(event));
+            }
+        }
+    }
+
+    /// Wait for the first event concerning `task_id` for which `check` returns a value, events for which it returns
+    /// `None` are skipped and an error fails the wait immediately
+    pub async fn wait_for<T>(&mut self,
+                             task_id: &AppTaskId,
+                             within: Duration,
+                             description: &str,
+                             mut check: impl FnMut(&EngineEvent) -> anyhow::Result<Option<T>>)
+                             -> anyhow::Result<T> {
+        let deadline = Instant::now() + within;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.next_event(task_id, remaining).await? {
+                None => bail!("Timed out after {within:?} waiting for {description}"),
+                Some(event) => {
+                    if let Some(value) = check(&event)? {
+                        return Ok(value);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Check every event concerning `task_id` arriving within `within`
+    pub async fn observe(&mut self,
+                         task_id: &AppTaskId,
+                         within: Duration,
+                         mut check: impl FnMut(&EngineEvent) -> anyhow::Result<()>)
+                         -> anyhow::Result<()> {
+        let deadline = Instant::now() + within;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.next_event(task_id, remaining).await? {
+                None => return Ok(()),
+                Some(event) => check(&event)?,
+            }
+        }
+    }
+}
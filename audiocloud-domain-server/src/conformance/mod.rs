@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use clap::Args;
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+use tracing::*;
+
+use audiocloud_api::cloud::domains::FixedInstanceRouting;
+use audiocloud_api::{
+    AppId, AppMediaObjectId, AppTaskId, FixedInstanceId, RequestPlay, RequestRender, TaskId, TaskSpec,
+};
+
+pub use cases::{all_cases, ConformanceCase};
+pub use engine::EngineUnderTest;
+
+mod cases;
+mod engine;
+
+#[derive(Args, Clone, Debug)]
+pub struct ConformanceOpts {
+    /// NATS URL the engine is connected to
+    #[clap(long, env, default_value = "nats://localhost:4222")]
+    pub nats_url: String,
+
+    /// Subject the engine receives commands on
+    #[clap(long, env)]
+    pub engine_command_subject: String,
+
+    /// Subject the engine publishes events on
+    #[clap(long, env)]
+    pub engine_event_subject: String,
+
+    /// YAML fixture with the spec, play and render requests to use, see `ConformanceFixture`
+    #[clap(long, env)]
+    pub fixture: PathBuf,
+
+    /// Milliseconds the engine has to reply to a command
+    #[clap(long, env, default_value = "500")]
+    pub reply_timeout_ms: u64,
+
+    /// Only run cases whose name contains this string
+    #[clap(long, env)]
+    pub only: Option<String>,
+}
+
+/// Engine specific inputs for the suite
+///
+/// The render should take at least a few seconds so that it can be cancelled before it finishes.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConformanceFixture {
+    pub app_id:            AppId,
+    pub spec:              TaskSpec,
+    #[serde(default)]
+    pub instances:         HashMap<FixedInstanceId, FixedInstanceRouting>,
+    #[serde(default)]
+    pub media_ready:       HashMap<AppMediaObjectId, String>,
+    pub play:              RequestPlay,
+    pub render:            RequestRender,
+    #[serde(default = "default_event_timeout_ms")]
+    pub event_timeout_ms:  u64,
+    #[serde(default = "default_render_timeout_ms")]
+    pub render_timeout_ms: u64,
+}
+
+fn default_event_timeout_ms() -> u64 {
+    5_000
+}
+
+fn default_render_timeout_ms() -> u64 {
+    120_000
+}
+
+impl ConformanceFixture {
+    pub fn load(path: &PathBuf) -> anyhow::Result<Self> {
+        Ok(serde_yaml::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn event_timeout(&self) -> Duration {
+        Duration::from_millis(self.event_timeout_ms)
+    }
+
+    pub fn render_timeout(&self) -> Duration {
+        Duration::from_millis(self.render_timeout_ms)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConformanceCaseResult {
+    pub name:        String,
+    pub description: String,
+    pub task_id:     AppTaskId,
+    pub passed:      bool,
+    pub error:       Option<String>,
+    pub elapsed_ms:  u64,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ConformanceReport {
+    pub cases: Vec<ConformanceCaseResult>,
+}
+
+impl ConformanceReport {
+    pub fn passed(&self) -> bool {
+        self.cases.iter().all(|case| case.passed)
+    }
+}
+
+/// Run all selected cases in order, each against a fresh task ID so that leftovers of one case can't affect the next
+#[instrument(skip_all, err)]
+pub async fn run(opts: &ConformanceOpts) -> anyhow::Result<ConformanceReport> {
+    let fixture = ConformanceFixture::load(&opts.fixture)?;
+    let mut engine = EngineUnderTest::connect(opts).await?;
+    let mut report = ConformanceReport::default();
+    let run_id = nanoid!(8);
+
+    for case in all_cases() {
+        if matches!(&opts.only, Some(only) if !case.name().contains(only.as_str())) {
+            continue;
+        }
+
+        let task_id = AppTaskId::new(fixture.app_id.clone(),
+                                     TaskId::new(format!("conformance-{run_id}-{}", case.name())));
+
+        info!(case = case.name(), %task_id, "Running");
+
+        let started = Instant::now();
+        let result = case.run(&mut engine, &task_id, &fixture).await;
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+
+        match &result {
+            Ok(()) => info!(case = case.name(), elapsed_ms, "Passed"),
+            Err(error) => warn!(case = case.name(), elapsed_ms, %error, "Failed"),
+        }
+
+        report.cases
+              .push(ConformanceCaseResult { name:        { case.name().to_owned() },
+                                            description: { case.description().to_owned() },
+                                            task_id:     { task_id },
+                                            passed:      { result.is_ok() },
+                                            error:       { result.err().map(|error| format!("{error:#}")) },
+                                            elapsed_ms:  { elapsed_ms }, });
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,179 @@
+use std::str::FromStr;
+
+use sqlx::prelude::*;
+
+use audiocloud_api::{AppTaskId, FixedInstanceId, TaskReservation, Timestamp};
+
+use crate::analytics::{UsageEvent, UsageEventKind, UsageRecord, UtilizationReport};
+use crate::db::Db;
+
+#[derive(Debug, FromRow)]
+struct UsageRow {
+    task_id:       String,
+    instance_id:   String,
+    reserved_from: Timestamp,
+    reserved_to:   Timestamp,
+}
+
+impl TryInto<UsageRecord> for UsageRow {
+    type Error = anyhow::Error;
+
+    fn try_into(self) -> Result<UsageRecord, Self::Error> {
+        Ok(UsageRecord { task_id:     { AppTaskId::from_str(&self.task_id)? },
+                         instance_id: { FixedInstanceId::from_str(&self.instance_id)? },
+                         from:        { self.reserved_from },
+                         to:          { self.reserved_to }, })
+    }
+}
+
+#[derive(Debug, FromRow)]
+struct UsageEventRow {
+    at:          Timestamp,
+    kind:        String,
+    task_id:     Option<String>,
+    instance_id: Option<String>,
+}
+
+impl TryInto<UsageEvent> for UsageEventRow {
+    type Error = anyhow::Error;
+
+    fn try_into(self) -> Result<UsageEvent, Self::Error> {
+        Ok(UsageEvent { at:          { self.at },
+                        kind:        { UsageEventKind::from_str(&self.kind)? },
+                        task_id:     { self.task_id.map(|task_id| AppTaskId::from_str(&task_id)).transpose()? },
+                        instance_id: {
+                            self.instance_id
+                                .map(|instance_id| FixedInstanceId::from_str(&instance_id))
+                                .transpose()?
+                        }, })
+    }
+}
+
+impl Db {
+    /// Replace the instances a task reserves and the time it reserves them for
+    pub async fn save_task_usage(&self, task_id: &AppTaskId, reservation: &TaskReservation) -> anyhow::Result<()> {
+        let task_id = task_id.to_string();
+
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(r#"DELETE FROM analytics_usage WHERE task_id = ?"#).bind(&task_id)
+                                                                       .execute(&mut tx)
+                                                                       .await?;
+
+        for instance_id in &reservation.fixed_instances {
+            let query =
+                r#"INSERT INTO analytics_usage (task_id, instance_id, reserved_from, reserved_to) VALUES (?, ?, ?, ?)"#;
+
+            sqlx::query(query).bind(&task_id)
+                              .bind(instance_id.to_string())
+                              .bind(reservation.from)
+                              .bind(reservation.to)
+                              .execute(&mut tx)
+                              .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// End the reservations of a deleted task at `at`, unless they ended before
+    pub async fn end_task_usage(&self, task_id: &AppTaskId, at: Timestamp) -> anyhow::Result<()> {
+        sqlx::query(r#"UPDATE analytics_usage SET reserved_to = ?1 WHERE task_id = ?2 AND reserved_to > ?1"#)
+            .bind(at)
+            .bind(task_id.to_string())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Reservations overlapping `from` to `to`
+    pub async fn fetch_usage_records(&self, from: Timestamp, to: Timestamp) -> anyhow::Result<Vec<UsageRecord>> {
+        let rows: Vec<UsageRow> =
+            sqlx::query_as(r#"SELECT * FROM analytics_usage WHERE reserved_to > ? AND reserved_from < ?"#)
+                .bind(from)
+                .bind(to)
+                .fetch_all(&self.pool)
+                .await?;
+
+        rows.into_iter().map(TryInto::try_into).collect()
+    }
+
+    pub async fn append_usage_event(&self, event: &UsageEvent) -> anyhow::Result<()> {
+        let query = r#"INSERT INTO analytics_events (at, kind, task_id, instance_id) VALUES (?, ?, ?, ?)"#;
+
+        sqlx::query(query).bind(event.at)
+                          .bind(event.kind.as_str())
+                          .bind(event.task_id.as_ref().map(ToString::to_string))
+                          .bind(event.instance_id.as_ref().map(ToString::to_string))
+                          .execute(&self.pool)
+                          .await?;
+
+        Ok(())
+    }
+
+    /// Usage events recorded from `from` until `to`, in the order they were recorded
+    pub async fn fetch_usage_events(&self, from: Timestamp, to: Timestamp) -> anyhow::Result<Vec<UsageEvent>> {
+        let sql = r#"SELECT at, kind, task_id, instance_id FROM analytics_events
+                     WHERE at >= ? AND at < ?
+                     ORDER BY id"#;
+
+        let rows: Vec<UsageEventRow> = sqlx::query_as(sql).bind(from).bind(to).fetch_all(&self.pool).await?;
+
+        rows.into_iter().map(TryInto::try_into).collect()
+    }
+
+    pub async fn save_utilization_report(&self, report: &UtilizationReport) -> anyhow::Result<()> {
+        let query = r#"INSERT INTO utilization_reports (window_hours, computed_at, report) VALUES (?, ?, ?)"#;
+
+        sqlx::query(query).bind(report.window_hours as i64)
+                          .bind(report.to)
+                          .bind(serde_json::to_string(report)?)
+                          .execute(&self.pool)
+                          .await?;
+
+        Ok(())
+    }
+
+    /// Utilization reports over `window_hours`, or over all windows if not set, most recent first
+    pub async fn fetch_utilization_reports(&self,
+                                           window_hours: Option<u64>,
+                                           limit: usize)
+                                           -> anyhow::Result<Vec<UtilizationReport>> {
+        let sql = r#"SELECT report FROM utilization_reports
+                     WHERE (?1 IS NULL OR window_hours = ?1)
+                     ORDER BY id DESC
+                     LIMIT ?2"#;
+
+        let reports: Vec<sqlx::types::Json<UtilizationReport>> =
+            sqlx::query_scalar(sql).bind(window_hours.map(|window_hours| window_hours as i64))
+                                   .bind(limit.min(u32::MAX as usize) as u32)
+                                   .fetch_all(&self.pool)
+                                   .await?;
+
+        Ok(reports.into_iter().map(|report| report.0).collect())
+    }
+
+    /// Delete usage that ended and events recorded before `usage_cutoff`, and reports computed before `report_cutoff`
+    pub async fn trim_analytics(&self, usage_cutoff: Timestamp, report_cutoff: Timestamp) -> anyhow::Result<u64> {
+        let mut trimmed = 0;
+
+        trimmed += sqlx::query(r#"DELETE FROM analytics_usage WHERE reserved_to < ?"#).bind(usage_cutoff)
+                                                                                      .execute(&self.pool)
+                                                                                      .await?
+                                                                                      .rows_affected();
+
+        trimmed += sqlx::query(r#"DELETE FROM analytics_events WHERE at < ?"#).bind(usage_cutoff)
+                                                                              .execute(&self.pool)
+                                                                              .await?
+                                                                              .rows_affected();
+
+        trimmed += sqlx::query(r#"DELETE FROM utilization_reports WHERE computed_at < ?"#).bind(report_cutoff)
+                                                                                          .execute(&self.pool)
+                                                                                          .await?
+                                                                                          .rows_affected();
+
+        Ok(trimmed)
+    }
+}
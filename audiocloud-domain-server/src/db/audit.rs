@@ -0,0 +1,94 @@
+use std::str::FromStr;
+
+use sqlx::prelude::*;
+
+use audiocloud_api::{AppTaskId, FixedInstanceId, Timestamp};
+
+use crate::audit::{AuditEntry, AuditOrigin, AuditQuery, AuditResult};
+use crate::db::Db;
+
+#[derive(Debug, FromRow)]
+struct AuditRow {
+    id:          i64,
+    at:          Timestamp,
+    origin:      String,
+    actor:       String,
+    action:      String,
+    task_id:     Option<String>,
+    instance_id: Option<String>,
+    params:      sqlx::types::Json<serde_json::Value>,
+    result:      sqlx::types::Json<AuditResult>,
+}
+
+impl TryInto<AuditEntry> for AuditRow {
+    type Error = anyhow::Error;
+
+    fn try_into(self) -> Result<AuditEntry, Self::Error> {
+        let Self { id,
+                   at,
+                   origin,
+                   actor,
+                   action,
+                   task_id,
+                   instance_id,
+                   params,
+                   result, } = self;
+
+        Ok(AuditEntry { id:          { Some(id) },
+                        at:          { at },
+                        origin:      { AuditOrigin::from_str(&origin)? },
+                        actor:       { actor },
+                        action:      { action },
+                        task_id:     { task_id.map(|task_id| AppTaskId::from_str(&task_id)).transpose()? },
+                        instance_id: {
+                            instance_id.map(|instance_id| FixedInstanceId::from_str(&instance_id))
+                                       .transpose()?
+                        },
+                        params:      { params.0 },
+                        result:      { result.0 }, })
+    }
+}
+
+impl Db {
+    /// Append an entry to the audit log, returning its sequence number
+    pub async fn append_audit_entry(&self, entry: &AuditEntry) -> anyhow::Result<i64> {
+        let query = r#"INSERT INTO audit (at, origin, actor, action, task_id, instance_id, params, result) VALUES (?, ?, ?, ?, ?, ?, ?, ?)"#;
+
+        let res = sqlx::query(query).bind(entry.at)
+                                    .bind(entry.origin.as_str())
+                                    .bind(&entry.actor)
+                                    .bind(&entry.action)
+                                    .bind(entry.task_id.as_ref().map(ToString::to_string))
+                                    .bind(entry.instance_id.as_ref().map(ToString::to_string))
+                                    .bind(serde_json::to_string(&entry.params)?)
+                                    .bind(serde_json::to_string(&entry.result)?)
+                                    .execute(&self.pool)
+                                    .await?;
+
+        Ok(res.last_insert_rowid())
+    }
+
+    /// Audit entries matching the query, most recent first
+    pub async fn query_audit_entries(&self, query: &AuditQuery) -> anyhow::Result<Vec<AuditEntry>> {
+        let sql = r#"SELECT * FROM audit
+                     WHERE (?1 IS NULL OR task_id = ?1)
+                       AND (?2 IS NULL OR instance_id = ?2)
+                       AND (?3 IS NULL OR actor = ?3)
+                       AND (?4 IS NULL OR at >= ?4)
+                       AND (?5 IS NULL OR id < ?5)
+                     ORDER BY id DESC
+                     LIMIT ?6"#;
+
+        let rows: Vec<AuditRow> =
+            sqlx::query_as(sql).bind(query.task_id.as_ref().map(ToString::to_string))
+                               .bind(query.instance_id.as_ref().map(ToString::to_string))
+                               .bind(query.actor.as_ref())
+                               .bind(query.since)
+                               .bind(query.before_id)
+                               .bind(query.limit.unwrap_or(usize::MAX).min(u32::MAX as usize) as u32)
+                               .fetch_all(&self.pool)
+                               .await?;
+
+        rows.into_iter().map(TryInto::try_into).collect()
+    }
+}
@@ -0,0 +1,64 @@
+use sqlx::prelude::*;
+
+use audiocloud_api::Timestamp;
+
+use crate::automation::{AutomationScript, AutomationTrigger};
+use crate::db::Db;
+
+#[derive(Debug, FromRow)]
+struct AutomationScriptRow {
+    name:       String,
+    enabled:    bool,
+    trigger:    sqlx::types::Json<AutomationTrigger>,
+    source:     String,
+    updated_at: Timestamp,
+}
+
+impl From<AutomationScriptRow> for AutomationScript {
+    fn from(row: AutomationScriptRow) -> Self {
+        let AutomationScriptRow { name,
+                                  enabled,
+                                  trigger,
+                                  source,
+                                  updated_at, } = row;
+
+        Self { name:       { name },
+               enabled:    { enabled },
+               trigger:    { trigger.0 },
+               source:     { source },
+               updated_at: { updated_at }, }
+    }
+}
+
+impl Db {
+    pub async fn save_automation_script(&self, script: &AutomationScript) -> anyhow::Result<()> {
+        let query = r#"INSERT OR REPLACE INTO automation_scripts (name, enabled, trigger, source, updated_at) VALUES (?, ?, ?, ?, ?)"#;
+
+        sqlx::query(query).bind(&script.name)
+                          .bind(script.enabled)
+                          .bind(serde_json::to_string(&script.trigger)?)
+                          .bind(&script.source)
+                          .bind(script.updated_at)
+                          .execute(&self.pool)
+                          .await?;
+
+        Ok(())
+    }
+
+    pub async fn fetch_automation_scripts(&self) -> anyhow::Result<Vec<AutomationScript>> {
+        let rows: Vec<AutomationScriptRow> =
+            sqlx::query_as(r#"SELECT * FROM automation_scripts ORDER BY name"#).fetch_all(&self.pool)
+                                                                               .await?;
+
+        Ok(rows.into_iter().map(AutomationScript::from).collect())
+    }
+
+    /// Delete a script, returning if it existed
+    pub async fn delete_automation_script(&self, name: &str) -> anyhow::Result<bool> {
+        let res = sqlx::query(r#"DELETE FROM automation_scripts WHERE name = ?"#).bind(name)
+                                                                                 .execute(&self.pool)
+                                                                                 .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+}
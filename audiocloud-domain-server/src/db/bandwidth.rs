@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use sqlx::prelude::*;
+
+use audiocloud_api::AppId;
+
+use crate::bandwidth::{AppBandwidthUsage, BandwidthChannel, PendingBandwidth};
+use crate::db::Db;
+
+#[derive(Debug, FromRow)]
+struct BandwidthRow {
+    app_id:         String,
+    channel:        String,
+    bytes_sent:     i64,
+    bytes_received: i64,
+}
+
+impl Db {
+    /// Add bytes counted per app and channel to their totals of `month`
+    pub async fn add_bandwidth_usage(&self, month: &str, pending: &PendingBandwidth) -> anyhow::Result<()> {
+        let query = r#"INSERT INTO bandwidth_usage (app_id, month, channel, bytes_sent, bytes_received)
+                       VALUES (?, ?, ?, ?, ?)
+                       ON CONFLICT (app_id, month, channel) DO UPDATE
+                       SET bytes_sent     = bytes_sent + excluded.bytes_sent,
+                           bytes_received = bytes_received + excluded.bytes_received"#;
+
+        let mut tx = self.pool.begin().await?;
+
+        for ((app_id, channel), bytes) in pending {
+            sqlx::query(query).bind(app_id.to_string())
+                              .bind(month)
+                              .bind(channel.as_str())
+                              .bind(bytes.sent.min(i64::MAX as u64) as i64)
+                              .bind(bytes.received.min(i64::MAX as u64) as i64)
+                              .execute(&mut tx)
+                              .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Bandwidth of the apps in `month`, or of one app, ordered by app
+    pub async fn fetch_bandwidth_usage(&self,
+                                       month: &str,
+                                       app_id: Option<&AppId>)
+                                       -> anyhow::Result<Vec<AppBandwidthUsage>> {
+        let sql = r#"SELECT app_id, channel, bytes_sent, bytes_received FROM bandwidth_usage
+                     WHERE month = ?1 AND (?2 IS NULL OR app_id = ?2)"#;
+
+        let rows: Vec<BandwidthRow> = sqlx::query_as(sql).bind(month)
+                                                         .bind(app_id.map(ToString::to_string))
+                                                         .fetch_all(&self.pool)
+                                                         .await?;
+
+        let mut usage = BTreeMap::<String, AppBandwidthUsage>::new();
+        for row in rows {
+            let app = usage.entry(row.app_id.clone())
+                           .or_insert_with(|| AppBandwidthUsage { app_id:    { AppId::new(row.app_id.clone()) },
+                                                                  month:     { month.to_owned() },
+                                                                  socket:    { Default::default() },
+                                                                  media:     { Default::default() },
+                                                                  cap_bytes: { None }, });
+
+            let bytes = match BandwidthChannel::from_str(&row.channel)? {
+                BandwidthChannel::Socket => &mut app.socket,
+                BandwidthChannel::Media => &mut app.media,
+            };
+
+            bytes.sent += row.bytes_sent.max(0) as u64;
+            bytes.received += row.bytes_received.max(0) as u64;
+        }
+
+        Ok(usage.into_values().collect())
+    }
+
+    /// Highest share of its cap in percent the app was warned about in `month`
+    pub async fn fetch_bandwidth_warning(&self, app_id: &AppId, month: &str) -> anyhow::Result<Option<u64>> {
+        let percent: Option<i64> =
+            sqlx::query_scalar(r#"SELECT percent FROM bandwidth_cap_warnings WHERE app_id = ? AND month = ?"#)
+                .bind(app_id.to_string())
+                .bind(month)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(percent.map(|percent| percent.max(0) as u64))
+    }
+
+    pub async fn save_bandwidth_warning(&self, app_id: &AppId, month: &str, percent: u64) -> anyhow::Result<()> {
+        let query = r#"INSERT INTO bandwidth_cap_warnings (app_id, month, percent) VALUES (?, ?, ?)
+                       ON CONFLICT (app_id, month) DO UPDATE SET percent = MAX(percent, excluded.percent)"#;
+
+        sqlx::query(query).bind(app_id.to_string())
+                          .bind(month)
+                          .bind(percent.min(i64::MAX as u64) as i64)
+                          .execute(&self.pool)
+                          .await?;
+
+        Ok(())
+    }
+}
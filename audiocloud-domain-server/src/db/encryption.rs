@@ -0,0 +1,122 @@
+use std::fs;
+
+use anyhow::{anyhow, bail, ensure};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+use crate::db::DataOpts;
+
+const KEY_INFO: &[u8] = b"audiocloud db record key v1";
+
+const SEALED_PREFIX: &str = "sealed:v1:";
+
+const NONCE_LEN: usize = 12;
+
+/// Envelope encryption of database records
+///
+/// Every record is encrypted with a fresh random data key, and the data key is stored next to it wrapped with the
+/// master key, which is derived with HKDF-SHA256 from the configured key material. Both use ChaCha20-Poly1305 with
+/// the record ID as associated data, so a sealed record can not be moved to another row. A sealed record is
+/// `sealed:v1:` followed by the hex encoded wrapped key and the hex encoded ciphertext, separated by a colon, each
+/// starting with its 12 byte nonce.
+pub(crate) struct RecordCipher(ChaCha20Poly1305);
+
+impl RecordCipher {
+    pub(crate) fn new(key_material: &[u8]) -> Self {
+        let hkdf = Hkdf::<Sha256>::new(None, key_material);
+        let mut key = Key::default();
+        hkdf.expand(KEY_INFO, &mut key)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+
+        Self(ChaCha20Poly1305::new(&key))
+    }
+
+    /// The cipher of the key configured in `opts`, if any
+    pub(crate) fn from_opts(opts: &DataOpts) -> anyhow::Result<Option<Self>> {
+        let key_material = match (&opts.db_encryption_key, &opts.db_encryption_key_file) {
+            (Some(key), _) => key.clone(),
+            (None, Some(path)) => match fs::read_to_string(path) {
+                // key files usually end with a newline that is not part of the key
+                Ok(key) => key.trim_end_matches(&['\r', '\n'][..]).to_owned(),
+                Err(error) => bail!("Could not read database encryption key file: {error}"),
+            },
+            (None, None) => return Ok(None),
+        };
+
+        ensure!(!key_material.is_empty(), "Database encryption key is empty");
+
+        Ok(Some(Self::new(key_material.as_bytes())))
+    }
+
+    pub(crate) fn is_sealed(record: &str) -> bool {
+        record.starts_with(SEALED_PREFIX)
+    }
+
+    pub(crate) fn seal(&self, record_id: &str, plaintext: &str) -> anyhow::Result<String> {
+        let data_key = ChaCha20Poly1305::generate_key(&mut OsRng);
+
+        let wrapped_key = encrypt(&self.0, record_id, &data_key)?;
+        let ciphertext = encrypt(&ChaCha20Poly1305::new(&data_key), record_id, plaintext.as_bytes())?;
+
+        Ok(format!("{SEALED_PREFIX}{}:{}",
+                   to_hex(&wrapped_key),
+                   to_hex(&ciphertext)))
+    }
+
+    pub(crate) fn open(&self, record_id: &str, sealed: &str) -> anyhow::Result<String> {
+        let (wrapped_key, ciphertext) = sealed.strip_prefix(SEALED_PREFIX)
+                                              .and_then(|sealed| sealed.split_once(':'))
+                                              .ok_or_else(|| anyhow!("Record {record_id} is not sealed"))?;
+
+        let data_key = decrypt(&self.0, record_id, &from_hex(wrapped_key)?)?;
+        ensure!(data_key.len() == 32, "Record {record_id} has a malformed data key");
+
+        let plaintext = decrypt(&ChaCha20Poly1305::new(Key::from_slice(&data_key)),
+                                record_id,
+                                &from_hex(ciphertext)?)?;
+
+        Ok(String::from_utf8(plaintext)?)
+    }
+}
+
+fn encrypt(cipher: &ChaCha20Poly1305, record_id: &str, msg: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher.encrypt(&nonce,
+                                    Payload { msg,
+                                              aad: record_id.as_bytes() })
+                           .map_err(|err| anyhow!("Failed to encrypt record {record_id}: {err}"))?;
+
+    let mut rv = nonce.to_vec();
+    rv.extend_from_slice(&ciphertext);
+
+    Ok(rv)
+}
+
+fn decrypt(cipher: &ChaCha20Poly1305, record_id: &str, sealed: &[u8]) -> anyhow::Result<Vec<u8>> {
+    ensure!(sealed.len() > NONCE_LEN, "Record {record_id} is too short to be sealed");
+
+    let (nonce, msg) = sealed.split_at(NONCE_LEN);
+
+    cipher.decrypt(Nonce::from_slice(nonce),
+                   Payload { msg,
+                             aad: record_id.as_bytes() })
+          .map_err(|_| anyhow!("Failed to decrypt record {record_id}, is the database encryption key correct?"))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn from_hex(hex: &str) -> anyhow::Result<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        bail!("Malformed hex in sealed record");
+    }
+
+    (0..hex.len()).step_by(2)
+                  .map(|i| {
+                      u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| anyhow!("Malformed hex in sealed record"))
+                  })
+                  .collect()
+}
@@ -0,0 +1,76 @@
+use std::str::FromStr;
+
+use sqlx::prelude::*;
+
+use audiocloud_api::Timestamp;
+
+use crate::db::Db;
+use crate::incidents::{Incident, IncidentEntry, IncidentId};
+
+#[derive(Debug, FromRow)]
+struct IncidentRow {
+    id:         String,
+    opened_at:  Timestamp,
+    updated_at: Timestamp,
+    closed_at:  Option<Timestamp>,
+    summary:    String,
+    timeline:   sqlx::types::Json<Vec<IncidentEntry>>,
+}
+
+impl TryInto<Incident> for IncidentRow {
+    type Error = anyhow::Error;
+
+    fn try_into(self) -> Result<Incident, Self::Error> {
+        let Self { id,
+                   opened_at,
+                   updated_at,
+                   closed_at,
+                   summary,
+                   timeline, } = self;
+
+        Ok(Incident { id:         { IncidentId::from_str(&id)? },
+                      opened_at:  { opened_at },
+                      updated_at: { updated_at },
+                      closed_at:  { closed_at },
+                      summary:    { summary },
+                      timeline:   { timeline.0 }, })
+    }
+}
+
+impl Db {
+    pub async fn save_incident(&self, incident: &Incident) -> anyhow::Result<()> {
+        let query = r#"INSERT OR REPLACE INTO incident (id, opened_at, updated_at, closed_at, summary, timeline) VALUES (?, ?, ?, ?, ?, ?)"#;
+
+        sqlx::query(query).bind(incident.id.to_string())
+                          .bind(incident.opened_at)
+                          .bind(incident.updated_at)
+                          .bind(incident.closed_at)
+                          .bind(&incident.summary)
+                          .bind(serde_json::to_string(&incident.timeline)?)
+                          .execute(&self.pool)
+                          .await?;
+
+        Ok(())
+    }
+
+    pub async fn fetch_incident(&self, id: &IncidentId) -> anyhow::Result<Option<Incident>> {
+        let opt: Option<IncidentRow> =
+            sqlx::query_as(r#"SELECT * FROM incident WHERE id = ?"#).bind(id.to_string())
+                                                                    .fetch_optional(&self.pool)
+                                                                    .await?;
+
+        Ok(match opt {
+            None => None,
+            Some(row) => Some(row.try_into()?),
+        })
+    }
+
+    pub async fn fetch_recent_incidents(&self, limit: usize) -> anyhow::Result<Vec<Incident>> {
+        let rows: Vec<IncidentRow> =
+            sqlx::query_as(r#"SELECT * FROM incident ORDER BY opened_at DESC LIMIT ?"#).bind(limit as u32)
+                                                                                       .fetch_all(&self.pool)
+                                                                                       .await?;
+
+        rows.into_iter().map(TryInto::try_into).collect()
+    }
+}
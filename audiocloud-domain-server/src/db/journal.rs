@@ -0,0 +1,101 @@
+use std::str::FromStr;
+
+use sqlx::prelude::*;
+
+use audiocloud_api::{AppTaskId, FixedInstanceId, Timestamp};
+
+use crate::db::Db;
+use crate::journal::{JournalEvent, JournalEventKind};
+
+#[derive(Debug, FromRow)]
+struct EventRow {
+    seq:         i64,
+    at:          Timestamp,
+    kind:        String,
+    task_id:     Option<String>,
+    instance_id: Option<String>,
+    payload:     sqlx::types::Json<serde_json::Value>,
+}
+
+impl TryInto<JournalEvent> for EventRow {
+    type Error = anyhow::Error;
+
+    fn try_into(self) -> Result<JournalEvent, Self::Error> {
+        let Self { seq,
+                   at,
+                   kind,
+                   task_id,
+                   instance_id,
+                   payload, } = self;
+
+        Ok(JournalEvent { seq:         { Some(seq) },
+                          at:          { at },
+                          kind:        { JournalEventKind::from_str(&kind)? },
+                          task_id:     { task_id.map(|task_id| AppTaskId::from_str(&task_id)).transpose()? },
+                          instance_id: {
+                              instance_id.map(|instance_id| FixedInstanceId::from_str(&instance_id))
+                                         .transpose()?
+                          },
+                          payload:     { payload.0 }, })
+    }
+}
+
+impl Db {
+    /// Append an event to the journal, returning its sequence number. Sequence numbers only ever grow, also across
+    /// trimming the journal.
+    pub async fn append_journal_event(&self, event: &JournalEvent) -> anyhow::Result<i64> {
+        let query = r#"INSERT INTO events (at, kind, task_id, instance_id, payload) VALUES (?, ?, ?, ?, ?)"#;
+
+        let res = sqlx::query(query).bind(event.at)
+                                    .bind(event.kind.as_str())
+                                    .bind(event.task_id.as_ref().map(ToString::to_string))
+                                    .bind(event.instance_id.as_ref().map(ToString::to_string))
+                                    .bind(serde_json::to_string(&event.payload)?)
+                                    .execute(&self.pool)
+                                    .await?;
+
+        Ok(res.last_insert_rowid())
+    }
+
+    /// Events after sequence number `since`, oldest first, optionally only those of one task
+    pub async fn query_journal_events(&self,
+                                      since: i64,
+                                      task_id: Option<&AppTaskId>,
+                                      limit: usize)
+                                      -> anyhow::Result<Vec<JournalEvent>> {
+        let sql = r#"SELECT * FROM events
+                     WHERE seq > ?1
+                       AND (?2 IS NULL OR task_id = ?2)
+                     ORDER BY seq ASC
+                     LIMIT ?3"#;
+
+        let rows: Vec<EventRow> = sqlx::query_as(sql).bind(since)
+                                                     .bind(task_id.map(ToString::to_string))
+                                                     .bind(limit.min(u32::MAX as usize) as u32)
+                                                     .fetch_all(&self.pool)
+                                                     .await?;
+
+        rows.into_iter().map(TryInto::try_into).collect()
+    }
+
+    /// Sequence number of the oldest event still in the journal, if any, and of the latest event ever appended, 0 if
+    /// none was
+    pub async fn journal_bounds(&self) -> anyhow::Result<(Option<i64>, i64)> {
+        let first: Option<i64> = sqlx::query_scalar(r#"SELECT MIN(seq) FROM events"#).fetch_one(&self.pool)
+                                                                                     .await?;
+
+        let last: Option<i64> =
+            sqlx::query_scalar(r#"SELECT seq FROM sqlite_sequence WHERE name = 'events'"#).fetch_optional(&self.pool)
+                                                                                          .await?;
+
+        Ok((first, last.unwrap_or_default()))
+    }
+
+    /// Delete events recorded before `cutoff`
+    pub async fn trim_journal(&self, cutoff: Timestamp) -> anyhow::Result<u64> {
+        Ok(sqlx::query(r#"DELETE FROM events WHERE at < ?"#).bind(cutoff)
+                                                            .execute(&self.pool)
+                                                            .await?
+                                                            .rows_affected())
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use sqlx::prelude::*;
+
+use audiocloud_api::{AppTaskId, Timestamp};
+
+use crate::db::Db;
+
+/// Rows of each table and the size of the database file
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DbStats {
+    pub table_rows: HashMap<String, u64>,
+    pub size_bytes: u64,
+}
+
+/// What a compaction removed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DbCompaction {
+    pub media_jobs: u64,
+    pub task_rows:  u64,
+}
+
+/// Tables holding rows keyed by task, with the column that tells when a row was last written
+const TASK_TABLES: [(&str, &str); 4] = [("task_permissions", "updated_at"),
+                                        ("task_key_scopes", "updated_at"),
+                                        ("track_takes", "recorded_at"),
+                                        ("task_settings", "updated_at")];
+
+impl Db {
+    /// Delete finished media jobs last modified before `cutoff` and the rows of tasks that are not in `live_tasks` and
+    /// were last written before `cutoff`, then give the freed pages back to the file system
+    pub async fn compact(&self, cutoff: Timestamp, live_tasks: &HashSet<AppTaskId>) -> anyhow::Result<DbCompaction> {
+        let query = r#"DELETE FROM media_job WHERE active = FALSE AND last_modified < ?"#;
+        let media_jobs = sqlx::query(query).bind(cutoff)
+                                           .execute(&self.pool)
+                                           .await?
+                                           .rows_affected();
+
+        let mut task_rows = 0;
+
+        for (table, written_at) in TASK_TABLES {
+            let query = format!("SELECT DISTINCT task_id FROM {table} WHERE {written_at} < ?");
+            let task_ids: Vec<String> = sqlx::query_scalar(&query).bind(cutoff).fetch_all(&self.pool).await?;
+
+            let delete = format!("DELETE FROM {table} WHERE task_id = ?");
+            for task_id in task_ids {
+                // rows of tasks the domain still knows about are removed when the task is
+                if matches!(AppTaskId::from_str(&task_id), Ok(task_id) if live_tasks.contains(&task_id)) {
+                    continue;
+                }
+
+                task_rows += sqlx::query(&delete).bind(task_id)
+                                                 .execute(&self.pool)
+                                                 .await?
+                                                 .rows_affected();
+            }
+        }
+
+        if media_jobs + task_rows > 0 {
+            sqlx::query("VACUUM").execute(&self.pool).await?;
+        }
+
+        Ok(DbCompaction { media_jobs, task_rows })
+    }
+
+    pub async fn stats(&self) -> anyhow::Result<DbStats> {
+        let tables: Vec<String> =
+            sqlx::query_scalar(r#"SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'"#)
+                .fetch_all(&self.pool)
+                .await?;
+
+        let mut table_rows = HashMap::new();
+        for table in tables {
+            let query = format!(r#"SELECT COUNT(*) FROM "{table}""#);
+            let rows: i64 = sqlx::query_scalar(&query).fetch_one(&self.pool).await?;
+
+            table_rows.insert(table, rows as u64);
+        }
+
+        let page_count: i64 = sqlx::query_scalar("PRAGMA page_count").fetch_one(&self.pool).await?;
+        let page_size: i64 = sqlx::query_scalar("PRAGMA page_size").fetch_one(&self.pool).await?;
+
+        Ok(DbStats { table_rows: { table_rows },
+                     size_bytes: { (page_count * page_size) as u64 }, })
+    }
+}
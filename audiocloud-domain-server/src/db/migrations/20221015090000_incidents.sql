@@ -0,0 +1,13 @@
+-- Add migration script here
+
+CREATE TABLE incident
+(
+    id         TEXT NOT NULL PRIMARY KEY,
+    opened_at  TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    closed_at  TEXT,
+    summary    TEXT NOT NULL,
+    timeline   TEXT NOT NULL
+) STRICT;
+
+CREATE INDEX incident_opened_at_idx ON incident (opened_at);
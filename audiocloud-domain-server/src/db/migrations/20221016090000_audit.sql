@@ -0,0 +1,33 @@
+-- Add migration script here
+
+CREATE TABLE audit
+(
+    id          INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    at          TEXT    NOT NULL,
+    origin      TEXT    NOT NULL,
+    actor       TEXT    NOT NULL,
+    action      TEXT    NOT NULL,
+    task_id     TEXT,
+    instance_id TEXT,
+    params      TEXT    NOT NULL,
+    result      TEXT    NOT NULL
+) STRICT;
+
+CREATE INDEX audit_at_idx ON audit (at);
+CREATE INDEX audit_task_id_idx ON audit (task_id);
+CREATE INDEX audit_instance_id_idx ON audit (instance_id);
+
+-- entries are append-only
+CREATE TRIGGER audit_no_update
+    BEFORE UPDATE
+    ON audit
+BEGIN
+    SELECT RAISE(ABORT, 'audit entries are append-only');
+END;
+
+CREATE TRIGGER audit_no_delete
+    BEFORE DELETE
+    ON audit
+BEGIN
+    SELECT RAISE(ABORT, 'audit entries are append-only');
+END;
@@ -0,0 +1,8 @@
+-- Add migration script here
+
+CREATE TABLE task_permissions
+(
+    task_id    TEXT NOT NULL PRIMARY KEY,
+    security   TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+) STRICT;
@@ -0,0 +1,11 @@
+-- Add migration script here
+
+CREATE TABLE track_takes
+(
+    media_id    TEXT NOT NULL PRIMARY KEY,
+    task_id     TEXT NOT NULL,
+    take        TEXT NOT NULL,
+    recorded_at TEXT NOT NULL
+) STRICT;
+
+CREATE INDEX track_takes_task_id_idx ON track_takes (task_id);
@@ -0,0 +1,8 @@
+-- Add migration script here
+
+CREATE TABLE task_tempo_maps
+(
+    task_id    TEXT NOT NULL PRIMARY KEY,
+    tempo_map  TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+) STRICT;
@@ -0,0 +1,14 @@
+-- Add migration script here
+
+CREATE TABLE events
+(
+    seq         INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    at          TEXT    NOT NULL,
+    kind        TEXT    NOT NULL,
+    task_id     TEXT,
+    instance_id TEXT,
+    payload     TEXT    NOT NULL
+) STRICT;
+
+CREATE INDEX events_at_idx ON events (at);
+CREATE INDEX events_task_id_idx ON events (task_id);
@@ -0,0 +1,10 @@
+-- Add migration script here
+
+CREATE TABLE automation_scripts
+(
+    name       TEXT    NOT NULL PRIMARY KEY,
+    enabled    INTEGER NOT NULL,
+    trigger    TEXT    NOT NULL,
+    source     TEXT    NOT NULL,
+    updated_at TEXT    NOT NULL
+) STRICT;
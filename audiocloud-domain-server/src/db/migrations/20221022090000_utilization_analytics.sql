@@ -0,0 +1,33 @@
+-- Add migration script here
+
+CREATE TABLE analytics_usage
+(
+    task_id       TEXT NOT NULL,
+    instance_id   TEXT NOT NULL,
+    reserved_from TEXT NOT NULL,
+    reserved_to   TEXT NOT NULL,
+    PRIMARY KEY (task_id, instance_id)
+) STRICT;
+
+CREATE INDEX analytics_usage_reserved_to_idx ON analytics_usage (reserved_to);
+
+CREATE TABLE analytics_events
+(
+    id          INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    at          TEXT    NOT NULL,
+    kind        TEXT    NOT NULL,
+    task_id     TEXT,
+    instance_id TEXT
+) STRICT;
+
+CREATE INDEX analytics_events_at_idx ON analytics_events (at);
+
+CREATE TABLE utilization_reports
+(
+    id           INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    window_hours INTEGER NOT NULL,
+    computed_at  TEXT    NOT NULL,
+    report       TEXT    NOT NULL
+) STRICT;
+
+CREATE INDEX utilization_reports_computed_at_idx ON utilization_reports (computed_at);
@@ -0,0 +1,19 @@
+-- Add migration script here
+
+CREATE TABLE bandwidth_usage
+(
+    app_id         TEXT    NOT NULL,
+    month          TEXT    NOT NULL,
+    channel        TEXT    NOT NULL,
+    bytes_sent     INTEGER NOT NULL,
+    bytes_received INTEGER NOT NULL,
+    PRIMARY KEY (app_id, month, channel)
+) STRICT;
+
+CREATE TABLE bandwidth_cap_warnings
+(
+    app_id  TEXT    NOT NULL,
+    month   TEXT    NOT NULL,
+    percent INTEGER NOT NULL,
+    PRIMARY KEY (app_id, month)
+) STRICT;
@@ -0,0 +1,8 @@
+-- Add migration script here
+
+CREATE TABLE task_key_scopes
+(
+    task_id    TEXT NOT NULL PRIMARY KEY,
+    scopes     TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+) STRICT;
@@ -0,0 +1,14 @@
+-- Add migration script here
+
+CREATE TABLE task_settings
+(
+    task_id    TEXT NOT NULL PRIMARY KEY,
+    settings   TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+) STRICT;
+
+INSERT INTO task_settings (task_id, settings, updated_at)
+SELECT task_id, '{"tempo_map":' || tempo_map || '}', updated_at
+FROM task_tempo_maps;
+
+DROP TABLE task_tempo_maps;
@@ -1,10 +1,20 @@
 use std::fmt::{Debug, Formatter};
+use std::path::PathBuf;
+use std::sync::Arc;
 
 use clap::Args;
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
 use tracing::*;
 
+mod analytics;
+mod audit;
+mod automation;
+mod bandwidth;
+mod encryption;
+mod incidents;
+mod journal;
+mod maintenance;
 mod media;
 mod models;
 mod sys_props;
@@ -12,9 +22,14 @@ mod tasks;
 #[cfg(test)]
 mod tests;
 
+pub use maintenance::{DbCompaction, DbStats};
+
+use self::encryption::RecordCipher;
+
 #[derive(Clone)]
 pub struct Db {
-    pool: SqlitePool,
+    pool:   SqlitePool,
+    cipher: Option<Arc<RecordCipher>>,
 }
 
 impl Debug for Db {
@@ -31,11 +46,22 @@ pub struct DataOpts {
     /// Sqlite database file where data for media and session cache will be stored. Use :memory: for an in-memory store
     #[clap(long, env, default_value = "sqlite:domain.sqlite")]
     pub database_url: String,
+
+    /// Key material for the envelope encryption of task secure keys in the database, which are stored in plain text
+    /// without one
+    #[clap(long, env)]
+    pub db_encryption_key: Option<String>,
+
+    /// File to read the database encryption key from, when it is not given directly
+    #[clap(long, env, conflicts_with = "db_encryption_key")]
+    pub db_encryption_key_file: Option<PathBuf>,
 }
 
 impl DataOpts {
     pub fn memory() -> Self {
-        Self { database_url: ":memory:".to_string(), }
+        Self { database_url:           ":memory:".to_string(),
+               db_encryption_key:      None,
+               db_encryption_key_file: None, }
     }
 }
 
@@ -52,5 +78,18 @@ pub async fn init(cfg: DataOpts) -> anyhow::Result<Db> {
 
     debug!("Migrations done");
 
-    Ok(Db { pool })
+    let cipher = RecordCipher::from_opts(&cfg)?.map(Arc::new);
+    let db = Db { pool, cipher };
+
+    if db.cipher.is_some() {
+        // records written before encryption was configured are sealed on the first boot with a key
+        let sealed = db.seal_task_permissions().await?;
+        if sealed > 0 {
+            info!(sealed, "Encrypted task secure keys that were stored in plain text");
+        }
+    } else {
+        warn!("No database encryption key configured, task secure keys are stored in plain text");
+    }
+
+    Ok(db)
 }
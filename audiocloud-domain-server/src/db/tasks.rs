@@ -1,3 +1,250 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use sqlx::prelude::*;
+
+use audiocloud_api::{now, AppTaskId, TaskSecurity};
+
+use crate::db::encryption::RecordCipher;
 use crate::db::Db;
+use crate::tasks::{TaskSettings, TrackTake};
+use crate::TaskKeyScopes;
+
+/// Tables and columns holding secure keys, sealed once an encryption key is configured
+const SEALED_TASK_COLUMNS: [(&str, &str); 2] = [("task_permissions", "security"), ("task_key_scopes", "scopes")];
+
+#[derive(Debug, FromRow)]
+struct TaskPermissionsRow {
+    task_id:  String,
+    security: String,
+}
+
+#[derive(Debug, FromRow)]
+struct TaskKeyScopesRow {
+    task_id: String,
+    scopes:  String,
+}
+
+#[derive(Debug, FromRow)]
+struct TaskSettingsRow {
+    task_id:  String,
+    settings: sqlx::types::Json<TaskSettings>,
+}
+
+#[derive(Debug, FromRow)]
+struct TrackTakeRow {
+    task_id: String,
+    take:    sqlx::types::Json<TrackTake>,
+}
+
+impl Db {
+    /// Persist the secure keys of a task, replacing the ones from the domain config once it restarts
+    pub async fn save_task_permissions(&self, task_id: &AppTaskId, security: &TaskSecurity) -> anyhow::Result<()> {
+        let query = r#"INSERT OR REPLACE INTO task_permissions (task_id, security, updated_at) VALUES (?, ?, ?)"#;
+
+        let task_id = task_id.to_string();
+        let mut security = serde_json::to_string(security)?;
+        if let Some(cipher) = &self.cipher {
+            security = cipher.seal(&task_id, &security)?;
+        }
+
+        sqlx::query(query).bind(task_id)
+                          .bind(security)
+                          .bind(now())
+                          .execute(&self.pool)
+                          .await?;
+
+        Ok(())
+    }
+
+    pub async fn fetch_all_task_permissions(&self) -> anyhow::Result<HashMap<AppTaskId, TaskSecurity>> {
+        let rows: Vec<TaskPermissionsRow> =
+            sqlx::query_as(r#"SELECT task_id, security FROM task_permissions"#).fetch_all(&self.pool)
+                                                                               .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let security = self.open_task_permissions(&row.task_id, row.security)?;
+                Ok((AppTaskId::from_str(&row.task_id)?, serde_json::from_str(&security)?))
+            })
+            .collect()
+    }
+
+    pub async fn fetch_task_permissions(&self, task_id: &AppTaskId) -> anyhow::Result<Option<TaskSecurity>> {
+        let row: Option<TaskPermissionsRow> =
+            sqlx::query_as(r#"SELECT task_id, security FROM task_permissions WHERE task_id = ?"#)
+                .bind(task_id.to_string())
+                .fetch_optional(&self.pool)
+                .await?;
+
+        row.map(|row| Ok(serde_json::from_str(&self.open_task_permissions(&row.task_id, row.security)?)?))
+           .transpose()
+    }
+
+    fn open_task_permissions(&self, task_id: &str, security: String) -> anyhow::Result<String> {
+        if !RecordCipher::is_sealed(&security) {
+            return Ok(security);
+        }
+
+        self.cipher
+            .as_ref()
+            .ok_or_else(|| anyhow!("Task permissions of {task_id} are encrypted, but no encryption key is configured"))?
+            .open(task_id, &security)
+    }
+
+    /// Encrypt the task permissions and key scopes that are still stored in plain text, returning how many there were
+    pub(crate) async fn seal_task_permissions(&self) -> anyhow::Result<u64> {
+        let cipher = match &self.cipher {
+            Some(cipher) => cipher,
+            None => return Ok(0),
+        };
+
+        let mut sealed = 0;
+        for (table, column) in SEALED_TASK_COLUMNS {
+            let query = format!("SELECT task_id, {column} AS security FROM {table} WHERE {column} NOT LIKE 'sealed:%'");
+            let rows: Vec<TaskPermissionsRow> = sqlx::query_as(&query).fetch_all(&self.pool).await?;
+
+            let update = format!("UPDATE {table} SET {column} = ? WHERE task_id = ?");
+            let mut tx = self.pool.begin().await?;
+            for row in &rows {
+                sqlx::query(&update).bind(cipher.seal(&row.task_id, &row.security)?)
+                                    .bind(&row.task_id)
+                                    .execute(&mut tx)
+                                    .await?;
+            }
+            tx.commit().await?;
+
+            sealed += rows.len() as u64;
+        }
+
+        Ok(sealed)
+    }
+
+    pub async fn delete_task_permissions(&self, task_id: &AppTaskId) -> anyhow::Result<()> {
+        sqlx::query(r#"DELETE FROM task_permissions WHERE task_id = ?"#).bind(task_id.to_string())
+                                                                        .execute(&self.pool)
+                                                                        .await?;
+
+        Ok(())
+    }
+
+    /// Persist the scopes of the secure keys of a task, sealed like the keys themselves
+    pub async fn save_task_key_scopes(&self, task_id: &AppTaskId, scopes: &TaskKeyScopes) -> anyhow::Result<()> {
+        let query = r#"INSERT OR REPLACE INTO task_key_scopes (task_id, scopes, updated_at) VALUES (?, ?, ?)"#;
+
+        let task_id = task_id.to_string();
+        let mut scopes = serde_json::to_string(scopes)?;
+        if let Some(cipher) = &self.cipher {
+            scopes = cipher.seal(&task_id, &scopes)?;
+        }
+
+        sqlx::query(query).bind(task_id)
+                          .bind(scopes)
+                          .bind(now())
+                          .execute(&self.pool)
+                          .await?;
+
+        Ok(())
+    }
+
+    pub async fn fetch_all_task_key_scopes(&self) -> anyhow::Result<HashMap<AppTaskId, TaskKeyScopes>> {
+        let rows: Vec<TaskKeyScopesRow> =
+            sqlx::query_as(r#"SELECT task_id, scopes FROM task_key_scopes"#).fetch_all(&self.pool)
+                                                                            .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let scopes = self.open_task_permissions(&row.task_id, row.scopes)?;
+                Ok((AppTaskId::from_str(&row.task_id)?, serde_json::from_str(&scopes)?))
+            })
+            .collect()
+    }
+
+    pub async fn fetch_task_key_scopes(&self, task_id: &AppTaskId) -> anyhow::Result<Option<TaskKeyScopes>> {
+        let row: Option<TaskKeyScopesRow> =
+            sqlx::query_as(r#"SELECT task_id, scopes FROM task_key_scopes WHERE task_id = ?"#)
+                .bind(task_id.to_string())
+                .fetch_optional(&self.pool)
+                .await?;
+
+        row.map(|row| Ok(serde_json::from_str(&self.open_task_permissions(&row.task_id, row.scopes)?)?))
+           .transpose()
+    }
+
+    pub async fn delete_task_key_scopes(&self, task_id: &AppTaskId) -> anyhow::Result<()> {
+        sqlx::query(r#"DELETE FROM task_key_scopes WHERE task_id = ?"#).bind(task_id.to_string())
+                                                                       .execute(&self.pool)
+                                                                       .await?;
+
+        Ok(())
+    }
+
+    pub async fn save_track_take(&self, task_id: &AppTaskId, take: &TrackTake) -> anyhow::Result<()> {
+        let query = r#"INSERT OR REPLACE INTO track_takes (media_id, task_id, take, recorded_at) VALUES (?, ?, ?, ?)"#;
+
+        sqlx::query(query).bind(take.media_id.to_string())
+                          .bind(task_id.to_string())
+                          .bind(serde_json::to_string(take)?)
+                          .bind(take.recorded_at)
+                          .execute(&self.pool)
+                          .await?;
+
+        Ok(())
+    }
+
+    /// Takes of all tasks, in recording order
+    pub async fn fetch_all_track_takes(&self) -> anyhow::Result<HashMap<AppTaskId, Vec<TrackTake>>> {
+        let rows: Vec<TrackTakeRow> =
+            sqlx::query_as(r#"SELECT task_id, take FROM track_takes ORDER BY recorded_at"#).fetch_all(&self.pool)
+                                                                                           .await?;
+
+        let mut rv = HashMap::<AppTaskId, Vec<TrackTake>>::new();
+        for row in rows {
+            rv.entry(AppTaskId::from_str(&row.task_id)?)
+              .or_default()
+              .push(row.take.0);
+        }
+
+        Ok(rv)
+    }
+
+    pub async fn delete_track_takes(&self, task_id: &AppTaskId) -> anyhow::Result<()> {
+        sqlx::query(r#"DELETE FROM track_takes WHERE task_id = ?"#).bind(task_id.to_string())
+                                                                   .execute(&self.pool)
+                                                                   .await?;
+
+        Ok(())
+    }
+
+    /// Persist the settings of a task the engine gets next to the spec, replacing the ones persisted before
+    pub async fn save_task_settings(&self, task_id: &AppTaskId, settings: &TaskSettings) -> anyhow::Result<()> {
+        let query = r#"INSERT OR REPLACE INTO task_settings (task_id, settings, updated_at) VALUES (?, ?, ?)"#;
+
+        sqlx::query(query).bind(task_id.to_string())
+                          .bind(serde_json::to_string(settings)?)
+                          .bind(now())
+                          .execute(&self.pool)
+                          .await?;
+
+        Ok(())
+    }
+
+    pub async fn fetch_all_task_settings(&self) -> anyhow::Result<HashMap<AppTaskId, TaskSettings>> {
+        let rows: Vec<TaskSettingsRow> =
+            sqlx::query_as(r#"SELECT task_id, settings FROM task_settings"#).fetch_all(&self.pool)
+                                                                            .await?;
+
+        rows.into_iter()
+            .map(|row| Ok((AppTaskId::from_str(&row.task_id)?, row.settings.0)))
+            .collect()
+    }
+
+    pub async fn delete_task_settings(&self, task_id: &AppTaskId) -> anyhow::Result<()> {
+        sqlx::query(r#"DELETE FROM task_settings WHERE task_id = ?"#).bind(task_id.to_string())
+                                                                     .execute(&self.pool)
+                                                                     .await?;
 
-impl Db {}
+        Ok(())
+    }
+}
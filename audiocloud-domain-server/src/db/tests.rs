@@ -4,12 +4,23 @@ use maplit::{hashmap, hashset};
 use serde_json::json;
 
 use audiocloud_api::{
-    now, AppId, AppMediaObjectId, DownloadFromDomain, MediaChannels, MediaDownload, MediaJobState, MediaMetadata,
-    MediaObject, MediaObjectId, MediaUpload, TrackMediaFormat, UploadToDomain,
+    now, AppId, AppMediaObjectId, AppTaskId, DownloadFromDomain, MediaChannels, MediaDownload, MediaJobState,
+    MediaMetadata, MediaObject, MediaObjectId, MediaUpload, SecureKey, TaskId, TaskSecurity, TimeSegment,
+    TrackMediaFormat, TrackNodeId, UploadToDomain,
 };
 
+use crate::audit::{AuditEntry, AuditOrigin, AuditQuery, AuditResult};
+use crate::automation::{AutomationScript, AutomationTrigger};
+use crate::bandwidth::{BandwidthBytes, BandwidthChannel};
 use crate::db::{DataOpts, Db};
+use crate::incidents::{Incident, IncidentEntry, IncidentSource};
+use crate::journal::{JournalEvent, JournalEventKind};
 use crate::media::{DownloadJobId, UploadJobId};
+use crate::tasks::{
+    TaskLoudnessTarget, TaskSettings, TaskTempoMap, TaskWatermark, TempoChange, TrackHardwareInput, TrackTake,
+    WatermarkMode,
+};
+use crate::{DomainSecurity, SecureKeyScope};
 
 #[actix::test]
 async fn test_migrations() -> anyhow::Result<()> {
@@ -17,13 +28,31 @@ async fn test_migrations() -> anyhow::Result<()> {
     let mut conn = db.pool.acquire().await?;
     let res = sqlx::query!("SELECT name FROM sqlite_master WHERE type='table'").fetch_all(&mut conn)
                                                                                .await?;
-    assert_eq!(res.len(), 5);
+    assert_eq!(res.len(), 19);
     let set = res.into_iter().filter_map(|r| r.name).collect::<HashSet<_>>();
 
     assert_eq!(set,
-               ["_sqlx_migrations", "media_object", "sys_props", "model", "media_job"].into_iter()
-                                                                                      .map(String::from)
-                                                                                      .collect());
+               ["_sqlx_migrations",
+                "media_object",
+                "sys_props",
+                "model",
+                "media_job",
+                "incident",
+                "audit",
+                "task_permissions",
+                "task_key_scopes",
+                "track_takes",
+                "events",
+                "automation_scripts",
+                "analytics_usage",
+                "analytics_events",
+                "utilization_reports",
+                "bandwidth_usage",
+                "bandwidth_cap_warnings",
+                "task_settings",
+                "sqlite_sequence"].into_iter()
+                                  .map(String::from)
+                                  .collect());
 
     Ok(())
 }
@@ -115,6 +144,208 @@ async fn test_create_upload_job() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[actix::test]
+async fn test_incident_timeline() -> anyhow::Result<()> {
+    let db = super::init(DataOpts::memory()).await?;
+
+    let task_id = AppTaskId::new(AppId::test(), TaskId::new("incident-task".to_string()));
+
+    let mut incident = Incident::new(IncidentEntry { at:      now(),
+                                                     source:  IncidentSource::Task(task_id.clone()),
+                                                     message: "Engine error: test".to_string(), });
+
+    incident.push(IncidentEntry { at:      now(),
+                                  source:  IncidentSource::Task(task_id),
+                                  message: "Playing failed".to_string(), });
+
+    db.save_incident(&incident).await?;
+
+    let loaded = db.fetch_incident(&incident.id).await?;
+
+    assert_eq!(loaded.as_ref(), Some(&incident));
+    assert_eq!(incident.summary, "task (2)");
+    assert_eq!(db.fetch_recent_incidents(10).await?, vec![incident]);
+
+    Ok(())
+}
+
+#[actix::test]
+async fn test_audit_log() -> anyhow::Result<()> {
+    let db = super::init(DataOpts::memory()).await?;
+
+    let task_id = AppTaskId::new(AppId::test(), TaskId::new("audit-task".to_string()));
+    let other_task_id = AppTaskId::new(AppId::test(), TaskId::new("other-task".to_string()));
+
+    let mut first =
+        AuditEntry::new(AuditOrigin::Rest, &DomainSecurity::Cloud, "play_task").with_task(&task_id)
+                                                                               .with_params(json!({"segment": 1}))
+                                                                               .with_result(&Ok(()));
+    let mut second =
+        AuditEntry::new(AuditOrigin::Socket, &DomainSecurity::Cloud, "modify_task").with_task(&other_task_id);
+    let mut third = AuditEntry::new(AuditOrigin::Rest, &DomainSecurity::Cloud, "stop_play_task").with_task(&task_id);
+    third.result = AuditResult::Error { message: "Illegal play state".to_string(), };
+
+    first.id = Some(db.append_audit_entry(&first).await?);
+    second.id = Some(db.append_audit_entry(&second).await?);
+    third.id = Some(db.append_audit_entry(&third).await?);
+
+    let query = AuditQuery { task_id: Some(task_id),
+                             ..Default::default() };
+
+    assert_eq!(db.query_audit_entries(&query).await?,
+               vec![third.clone(), first.clone()]);
+
+    let query = AuditQuery { before_id: third.id,
+                             limit: Some(1),
+                             ..Default::default() };
+
+    assert_eq!(db.query_audit_entries(&query).await?, vec![second]);
+
+    let res = sqlx::query("DELETE FROM audit").execute(&db.pool).await;
+    assert!(res.is_err(), "audit entries must be append-only");
+
+    Ok(())
+}
+
+#[actix::test]
+async fn test_journal_replay() -> anyhow::Result<()> {
+    let db = super::init(DataOpts::memory()).await?;
+
+    let task_id = AppTaskId::new(AppId::test(), TaskId::new("journal-task".to_string()));
+    let other_task_id = AppTaskId::new(AppId::test(), TaskId::new("other-task".to_string()));
+
+    assert_eq!(db.journal_bounds().await?, (None, 0));
+
+    let mut first = JournalEvent::new(JournalEventKind::TaskActivated, ()).with_task(&task_id);
+    let mut second = JournalEvent::new(JournalEventKind::TaskActivated, ()).with_task(&other_task_id);
+    let mut third = JournalEvent::new(JournalEventKind::TaskState, json!({"playing": true})).with_task(&task_id);
+    first.at = third.at - chrono::Duration::seconds(2);
+    second.at = third.at - chrono::Duration::seconds(1);
+
+    first.seq = Some(db.append_journal_event(&first).await?);
+    second.seq = Some(db.append_journal_event(&second).await?);
+    third.seq = Some(db.append_journal_event(&third).await?);
+
+    assert_eq!(db.query_journal_events(0, None, 100).await?,
+               vec![first.clone(), second.clone(), third.clone()]);
+    assert_eq!(db.query_journal_events(first.seq.unwrap(), Some(&task_id), 100).await?,
+               vec![third.clone()]);
+    assert_eq!(db.query_journal_events(0, None, 1).await?, vec![first.clone()]);
+
+    assert_eq!(db.trim_journal(third.at).await?, 2);
+
+    // sequence numbers are never reused once trimmed
+    assert_eq!(db.journal_bounds().await?, (third.seq, third.seq.unwrap()));
+
+    Ok(())
+}
+
+#[actix::test]
+async fn test_task_permissions() -> anyhow::Result<()> {
+    let db = super::init(DataOpts::memory()).await?;
+
+    let task_id = AppTaskId::new(AppId::test(), TaskId::new("permissions-task".to_string()));
+    let security = TaskSecurity::default();
+
+    db.save_task_permissions(&task_id, &security).await?;
+    db.save_task_permissions(&task_id, &security).await?;
+
+    assert_eq!(db.fetch_all_task_permissions().await?,
+               hashmap! { task_id.clone() => security.clone() });
+    assert_eq!(db.fetch_task_permissions(&task_id).await?, Some(security));
+
+    db.delete_task_permissions(&task_id).await?;
+
+    assert!(db.fetch_all_task_permissions().await?.is_empty());
+    assert_eq!(db.fetch_task_permissions(&task_id).await?, None);
+
+    Ok(())
+}
+
+#[actix::test]
+async fn test_track_takes() -> anyhow::Result<()> {
+    let db = super::init(DataOpts::memory()).await?;
+
+    let task_id = AppTaskId::new(AppId::test(), TaskId::new("takes-task".to_string()));
+    let track_id = TrackNodeId::new("vocals".to_string());
+    let segment = TimeSegment { start:  0.0,
+                                length: 8.0, };
+
+    let first = TrackTake { media_id:      new_random_test_media_id(),
+                            track_id:      track_id.clone(),
+                            take:          1,
+                            segment:       segment.clone(),
+                            pass:          None,
+                            media_segment: None,
+                            recorded_at:   now(), };
+
+    let second = TrackTake { media_id: new_random_test_media_id(),
+                             take: 2,
+                             pass: Some(2),
+                             media_segment: Some(TimeSegment { start:  8.0,
+                                                               length: 8.0, }),
+                             recorded_at: now(),
+                             ..first.clone() };
+
+    db.save_track_take(&task_id, &first).await?;
+    db.save_track_take(&task_id, &second).await?;
+
+    assert_eq!(db.fetch_all_track_takes().await?,
+               hashmap! { task_id.clone() => vec![first, second] });
+
+    db.delete_track_takes(&task_id).await?;
+
+    assert!(db.fetch_all_track_takes().await?.is_empty());
+
+    Ok(())
+}
+
+#[actix::test]
+async fn test_task_settings() -> anyhow::Result<()> {
+    let db = super::init(DataOpts::memory()).await?;
+
+    let task_id = AppTaskId::new(AppId::test(), TaskId::new("settings-task".to_string()));
+    let tempo_map = TaskTempoMap { changes: vec![TempoChange { time:        0.0,
+                                                               bpm:         96.0,
+                                                               numerator:   6,
+                                                               denominator: 8, }], };
+    let track_inputs = hashmap! { TrackNodeId::new("vocals".to_owned()) => TrackHardwareInput { channel: 3 } };
+
+    let settings = TaskSettings { track_inputs: { track_inputs },
+                                  tempo_map: { tempo_map },
+                                  loudness_target: { TaskLoudnessTarget { target_lufs: Some(-14.0), } },
+                                  watermark: {
+                                      TaskWatermark { mode:     WatermarkMode::Inaudible,
+                                                      level_db: None, }
+                                  },
+                                  ..Default::default() };
+
+    db.save_task_settings(&task_id, &TaskSettings::default()).await?;
+    db.save_task_settings(&task_id, &settings).await?;
+
+    assert_eq!(db.fetch_all_task_settings().await?,
+               hashmap! { task_id.clone() => settings.clone() });
+
+    db.delete_task_settings(&task_id).await?;
+
+    assert!(db.fetch_all_task_settings().await?.is_empty());
+
+    // as the migration from the tempo maps table writes them, without the settings added since
+    sqlx::query("INSERT INTO task_settings (task_id, settings, updated_at) VALUES (?, ?, ?)")
+        .bind(task_id.to_string())
+        .bind(r#"{"tempo_map":{"changes":[{"time":0.0,"bpm":96.0,"numerator":6,"denominator":8}]}}"#)
+        .bind(now())
+        .execute(&db.pool)
+        .await?;
+
+    let migrated = TaskSettings { tempo_map: settings.tempo_map,
+                                  ..Default::default() };
+    assert_eq!(db.fetch_all_task_settings().await?,
+               hashmap! { task_id.clone() => migrated });
+
+    Ok(())
+}
+
 fn test_media_object(media_id: &AppMediaObjectId, media_metadata: &MediaMetadata) -> MediaObject {
     MediaObject { id:       media_id.clone(),
                   metadata: Some(media_metadata.clone()),
@@ -168,3 +399,161 @@ fn new_random_download_job_id() -> DownloadJobId {
 fn new_random_upload_job_id() -> UploadJobId {
     UploadJobId::new()
 }
+
+#[actix::test]
+async fn test_compact_removes_rows_of_forgotten_tasks() -> anyhow::Result<()> {
+    let db = super::init(DataOpts::memory()).await?;
+
+    let live_task_id = AppTaskId::new(AppId::test(), TaskId::new("live-task".to_string()));
+    let forgotten_task_id = AppTaskId::new(AppId::test(), TaskId::new("forgotten-task".to_string()));
+    let security = TaskSecurity::default();
+
+    db.save_task_permissions(&live_task_id, &security).await?;
+    db.save_task_permissions(&forgotten_task_id, &security).await?;
+
+    let before = db.stats().await?;
+    assert_eq!(before.table_rows.get("task_permissions"), Some(&2));
+    assert!(before.size_bytes > 0);
+
+    // nothing is old enough yet
+    let compaction = db.compact(now() - chrono::Duration::hours(1), &hashset! { live_task_id.clone() })
+                       .await?;
+    assert_eq!(compaction.task_rows, 0);
+
+    let compaction = db.compact(now() + chrono::Duration::hours(1), &hashset! { live_task_id.clone() })
+                       .await?;
+    assert_eq!(compaction.task_rows, 1);
+
+    assert_eq!(db.fetch_all_task_permissions().await?,
+               hashmap! { live_task_id => security });
+    assert_eq!(db.stats().await?.table_rows.get("task_permissions"), Some(&1));
+
+    Ok(())
+}
+
+#[actix::test]
+async fn test_task_permissions_are_sealed() -> anyhow::Result<()> {
+    let db = super::init(DataOpts { db_encryption_key: Some("test key".to_string()),
+                                    ..DataOpts::memory() }).await?;
+
+    let task_id = AppTaskId::new(AppId::test(), TaskId::new("sealed-task".to_string()));
+    let plain_task_id = AppTaskId::new(AppId::test(), TaskId::new("plain-task".to_string()));
+    let security = TaskSecurity::default();
+
+    db.save_task_permissions(&task_id, &security).await?;
+
+    // written before an encryption key was configured
+    sqlx::query("INSERT INTO task_permissions (task_id, security, updated_at) VALUES (?, ?, ?)")
+        .bind(plain_task_id.to_string())
+        .bind(serde_json::to_string(&security)?)
+        .bind(now())
+        .execute(&db.pool)
+        .await?;
+
+    assert_eq!(db.seal_task_permissions().await?, 1);
+    assert_eq!(db.seal_task_permissions().await?, 0);
+
+    let stored: Vec<String> = sqlx::query_scalar("SELECT security FROM task_permissions").fetch_all(&db.pool)
+                                                                                         .await?;
+    assert!(stored.iter().all(|security| security.starts_with("sealed:v1:")));
+
+    assert_eq!(db.fetch_all_task_permissions().await?,
+               hashmap! { task_id => security.clone(), plain_task_id => security });
+
+    Ok(())
+}
+
+#[actix::test]
+async fn test_task_key_scopes_are_sealed() -> anyhow::Result<()> {
+    let db = super::init(DataOpts { db_encryption_key: Some("test key".to_string()),
+                                    ..DataOpts::memory() }).await?;
+
+    let task_id = AppTaskId::new(AppId::test(), TaskId::new("scoped-task".to_string()));
+    let scopes = hashmap! { SecureKey::new("listener".to_owned()) => SecureKeyScope::Listen,
+    SecureKey::new("owner".to_owned()) => SecureKeyScope::Full };
+
+    db.save_task_key_scopes(&task_id, &scopes).await?;
+
+    let stored: Vec<String> = sqlx::query_scalar("SELECT scopes FROM task_key_scopes").fetch_all(&db.pool)
+                                                                                      .await?;
+    assert!(stored.iter()
+                  .all(|scopes| scopes.starts_with("sealed:v1:") && !scopes.contains("listener")));
+
+    assert_eq!(db.fetch_all_task_key_scopes().await?,
+               hashmap! { task_id.clone() => scopes.clone() });
+    assert_eq!(db.fetch_task_key_scopes(&task_id).await?, Some(scopes));
+
+    db.delete_task_key_scopes(&task_id).await?;
+
+    assert!(db.fetch_all_task_key_scopes().await?.is_empty());
+    assert_eq!(db.fetch_task_key_scopes(&task_id).await?, None);
+
+    Ok(())
+}
+
+#[actix::test]
+async fn test_automation_scripts() -> anyhow::Result<()> {
+    let db = super::init(DataOpts::memory()).await?;
+
+    let mut nightly = AutomationScript { name:       { "nightly-power-down".to_string() },
+                                         enabled:    { true },
+                                         trigger:    { AutomationTrigger::Daily { hour: 23, minute: 30 } },
+                                         source:     { r#"power("distopik/dual1084/1", 0, false);"#.to_string() },
+                                         updated_at: { now() }, };
+
+    let warm_up = AutomationScript { name:       { "warm-up".to_string() },
+                                     enabled:    { false },
+                                     trigger:    { AutomationTrigger::BeforeReservation { minutes: 15 } },
+                                     source:     { r#"log(task_id);"#.to_string() },
+                                     updated_at: { now() }, };
+
+    db.save_automation_script(&warm_up).await?;
+    db.save_automation_script(&nightly).await?;
+
+    nightly.enabled = false;
+    db.save_automation_script(&nightly).await?;
+
+    assert_eq!(db.fetch_automation_scripts().await?,
+               vec![nightly.clone(), warm_up.clone()]);
+
+    assert!(db.delete_automation_script(&nightly.name).await?);
+    assert!(!db.delete_automation_script(&nightly.name).await?);
+    assert_eq!(db.fetch_automation_scripts().await?, vec![warm_up]);
+
+    Ok(())
+}
+
+#[actix::test]
+async fn test_bandwidth_usage_adds_up() -> anyhow::Result<()> {
+    let db = super::init(DataOpts::memory()).await?;
+    let studio = AppId::new("studio".to_owned());
+    let label = AppId::new("label".to_owned());
+
+    let batch = hashmap! {
+        (studio.clone(), BandwidthChannel::Socket) => BandwidthBytes { sent: 1_000, received: 100 },
+        (label.clone(), BandwidthChannel::Media) => BandwidthBytes { sent: 0, received: 50_000 },
+    };
+
+    db.add_bandwidth_usage("2022-10", &batch).await?;
+    db.add_bandwidth_usage("2022-10", &batch).await?;
+    db.add_bandwidth_usage("2022-11", &batch).await?;
+
+    let usage = db.fetch_bandwidth_usage("2022-10", None).await?;
+    assert_eq!(usage.iter().map(|app| app.app_id.clone()).collect::<Vec<_>>(),
+               vec![label.clone(), studio.clone()]);
+
+    let usage = db.fetch_bandwidth_usage("2022-10", Some(&studio)).await?;
+    assert_eq!(usage.len(), 1);
+    assert_eq!(usage[0].socket,
+               BandwidthBytes { sent:     2_000,
+                                received: 200, });
+    assert_eq!(usage[0].media, BandwidthBytes::default());
+
+    assert_eq!(db.fetch_bandwidth_warning(&studio, "2022-10").await?, None);
+    db.save_bandwidth_warning(&studio, "2022-10", 100).await?;
+    db.save_bandwidth_warning(&studio, "2022-10", 80).await?;
+    assert_eq!(db.fetch_bandwidth_warning(&studio, "2022-10").await?, Some(100));
+    assert_eq!(db.fetch_bandwidth_warning(&studio, "2022-11").await?, None);
+
+    Ok(())
+}
@@ -0,0 +1,246 @@
+#![allow(unused_variables)]
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use actix::{Actor, ActorFutureExt, AsyncContext, Context, ContextFutureSpawner, Handler, WrapFuture};
+use actix_broker::BrokerSubscribe;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::*;
+use uuid::Uuid;
+
+use audiocloud_api::audio_engine::EngineEvent;
+use audiocloud_api::{now, AppTaskId, Json, Timestamp};
+
+use crate::bandwidth::NotifyBandwidthCapWarning;
+use crate::events::{CloudEventOpts, NotifyDomainEvent};
+use crate::nats;
+use crate::tasks::{NotifyEngineEvent, NotifyTaskDeactivated, NotifyTaskDeleted};
+
+pub fn init(opts: CloudEventOpts, subject: String) -> anyhow::Result<()> {
+    nats::label_subject(&subject, "cloud_events");
+    JetStreamEventsPublisher::new(opts, subject).start();
+
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CloudEventKind {
+    TaskDeactivated,
+    TaskDeleted,
+    RenderFinished,
+    RenderFailed,
+    PlayFailed,
+    BandwidthCapWarning,
+    Domain,
+}
+
+/// An event published to the cloud, `id` is unique so the cloud can drop the duplicates at-least-once delivery brings
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CloudEvent {
+    pub id:      String,
+    pub at:      Timestamp,
+    pub kind:    CloudEventKind,
+    pub task_id: Option<AppTaskId>,
+    pub payload: Value,
+}
+
+impl CloudEvent {
+    pub fn new(kind: CloudEventKind, task_id: Option<AppTaskId>, payload: Value) -> Self {
+        Self { id:      { Uuid::new_v4().to_string() },
+               at:      { now() },
+               kind:    { kind },
+               task_id: { task_id },
+               payload: { payload }, }
+    }
+}
+
+/// Publishes cloud-bound events to a JetStream stream one at a time, in order
+///
+/// An event leaves the buffer once the stream acknowledged storing it. While the cloud is unreachable events are kept
+/// and retried, and once the buffer is full the oldest are dropped.
+struct JetStreamEventsPublisher {
+    opts:         CloudEventOpts,
+    subject:      String,
+    buffer:       VecDeque<CloudEvent>,
+    in_flight:    bool,
+    stream_ready: bool,
+    unreachable:  bool,
+    dropped:      u64,
+}
+
+impl JetStreamEventsPublisher {
+    fn new(opts: CloudEventOpts, subject: String) -> Self {
+        Self { opts:         { opts },
+               subject:      { subject },
+               buffer:       { VecDeque::new() },
+               in_flight:    { false },
+               stream_ready: { false },
+               unreachable:  { false },
+               dropped:      { 0 }, }
+    }
+
+    fn push(&mut self, event: CloudEvent, ctx: &mut Context<Self>) {
+        if self.buffer.len() >= self.opts.cloud_events_buffer.max(1) {
+            if let Some(oldest) = self.buffer.pop_front() {
+                self.dropped += 1;
+                warn!(id = %oldest.id,
+                      kind = ?oldest.kind,
+                      dropped = self.dropped,
+                      "Cloud event buffer full, dropping oldest");
+            }
+        }
+
+        self.buffer.push_back(event);
+
+        // while unreachable, the retry timer drives publishing
+        if !self.unreachable {
+            self.publish_next(ctx);
+        }
+    }
+
+    fn publish_next(&mut self, ctx: &mut Context<Self>) {
+        if self.in_flight {
+            return;
+        }
+
+        let event = match self.buffer.front() {
+            Some(event) => event.clone(),
+            None => return,
+        };
+
+        self.in_flight = true;
+
+        let subject = self.subject.clone();
+        let stream = self.opts.cloud_events_stream.clone();
+        let stream_ready = self.stream_ready;
+        let timeout = Duration::from_millis(self.opts.cloud_events_ack_timeout_ms);
+
+        async move {
+            if !stream_ready {
+                nats::ensure_jetstream_stream(&stream, &[subject.clone()], timeout).await?;
+            }
+
+            nats::publish_jetstream(&subject, Json, &event, timeout).await
+        }.into_actor(self)
+         .map(move |res, actor, ctx| {
+             actor.in_flight = false;
+
+             match res {
+                 Ok(ack) => actor.on_acked(&event.id, ack, ctx),
+                 Err(error) => actor.on_failed(error, ctx),
+             }
+         })
+         .spawn(ctx);
+    }
+
+    fn on_acked(&mut self, id: &str, ack: nats::JetStreamAck, ctx: &mut Context<Self>) {
+        self.stream_ready = true;
+
+        if self.unreachable {
+            self.unreachable = false;
+            info!(buffered = self.buffer.len(), "Cloud events stream reachable again");
+        }
+
+        // the event may have been dropped from a full buffer while it was in flight
+        if matches!(self.buffer.front(), Some(event) if event.id == id) {
+            self.buffer.pop_front();
+        }
+
+        trace!(%id, stream = %ack.stream, seq = ack.seq, duplicate = ack.duplicate, "Cloud event acknowledged");
+
+        self.publish_next(ctx);
+    }
+
+    fn on_failed(&mut self, error: anyhow::Error, ctx: &mut Context<Self>) {
+        if !self.unreachable {
+            self.unreachable = true;
+            warn!(%error, buffered = self.buffer.len(), "Cloud events stream unreachable, buffering");
+        }
+
+        ctx.run_later(Duration::from_millis(self.opts.cloud_events_retry_ms), |actor, ctx| {
+               actor.publish_next(ctx)
+           });
+    }
+}
+
+impl Actor for JetStreamEventsPublisher {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.subscribe_system_async::<NotifyDomainEvent>(ctx);
+        self.subscribe_system_async::<NotifyEngineEvent>(ctx);
+        self.subscribe_system_async::<NotifyTaskDeactivated>(ctx);
+        self.subscribe_system_async::<NotifyTaskDeleted>(ctx);
+        self.subscribe_system_async::<NotifyBandwidthCapWarning>(ctx);
+    }
+}
+
+impl Handler<NotifyDomainEvent> for JetStreamEventsPublisher {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyDomainEvent, ctx: &mut Self::Context) -> Self::Result {
+        match serde_json::to_value(&msg.event) {
+            Ok(payload) => self.push(CloudEvent::new(CloudEventKind::Domain, None, payload), ctx),
+            Err(error) => warn!(%error, "Failed to serialize domain event"),
+        }
+    }
+}
+
+impl Handler<NotifyEngineEvent> for JetStreamEventsPublisher {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyEngineEvent, ctx: &mut Self::Context) -> Self::Result {
+        let (kind, task_id, payload) = match msg.event {
+            EngineEvent::RenderingFinished { task_id,
+                                             render_id,
+                                             path, } => {
+                (CloudEventKind::RenderFinished, task_id, json!({ "render_id": render_id, "path": path }))
+            }
+            EngineEvent::RenderingFailed { task_id,
+                                           render_id,
+                                           error, } => {
+                (CloudEventKind::RenderFailed, task_id, json!({ "render_id": render_id, "error": error }))
+            }
+            EngineEvent::PlayingFailed { task_id,
+                                         play_id,
+                                         error, } => {
+                (CloudEventKind::PlayFailed, task_id, json!({ "play_id": play_id, "error": error }))
+            }
+            _ => return,
+        };
+
+        self.push(CloudEvent::new(kind, Some(task_id), payload), ctx);
+    }
+}
+
+impl Handler<NotifyTaskDeactivated> for JetStreamEventsPublisher {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskDeactivated, ctx: &mut Self::Context) -> Self::Result {
+        let event = CloudEvent::new(CloudEventKind::TaskDeactivated, Some(msg.task_id), Value::Null);
+        self.push(event, ctx);
+    }
+}
+
+impl Handler<NotifyTaskDeleted> for JetStreamEventsPublisher {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskDeleted, ctx: &mut Self::Context) -> Self::Result {
+        let event = CloudEvent::new(CloudEventKind::TaskDeleted, Some(msg.task_id), Value::Null);
+        self.push(event, ctx);
+    }
+}
+
+impl Handler<NotifyBandwidthCapWarning> for JetStreamEventsPublisher {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyBandwidthCapWarning, ctx: &mut Self::Context) -> Self::Result {
+        match serde_json::to_value(&msg) {
+            Ok(payload) => self.push(CloudEvent::new(CloudEventKind::BandwidthCapWarning, None, payload), ctx),
+            Err(error) => warn!(%error, "Failed to serialize bandwidth cap warning"),
+        }
+    }
+}
@@ -1,16 +1,43 @@
+use clap::Args;
 use tracing::*;
 
 use audiocloud_api::cloud::domains::{DomainCommandSource, DomainEventSink};
+pub use jetstream_events::{CloudEvent, CloudEventKind};
 pub use messages::*;
 
+mod jetstream_events;
 mod log_events;
 mod noop_events;
 
 mod kafka;
 mod messages;
 
+#[derive(Args, Clone, Debug)]
+pub struct CloudEventOpts {
+    /// Publish task completion, render and domain events as JSON to this NATS subject through JetStream, with
+    /// at-least-once delivery
+    #[clap(long, env)]
+    pub cloud_events_subject: Option<String>,
+
+    /// JetStream stream capturing the cloud events subject, created if it does not exist
+    #[clap(long, env, default_value = "AUDIOCLOUD_DOMAIN_EVENTS")]
+    pub cloud_events_stream: String,
+
+    /// Maximum number of cloud events kept while the stream is unreachable, the oldest are dropped beyond it
+    #[clap(long, env, default_value = "10000")]
+    pub cloud_events_buffer: usize,
+
+    /// Milliseconds to wait for the stream to acknowledge a cloud event
+    #[clap(long, env, default_value = "5000")]
+    pub cloud_events_ack_timeout_ms: u64,
+
+    /// Milliseconds between attempts to publish cloud events while the stream is unreachable
+    #[clap(long, env, default_value = "2000")]
+    pub cloud_events_retry_ms: u64,
+}
+
 #[instrument(skip_all, err)]
-pub async fn init(commands: DomainCommandSource, events: DomainEventSink) -> anyhow::Result<()> {
+pub async fn init(commands: DomainCommandSource, events: DomainEventSink, opts: CloudEventOpts) -> anyhow::Result<()> {
     match commands {
         DomainCommandSource::Disabled => {
             // nothing to do
@@ -39,5 +66,9 @@ pub async fn init(commands: DomainCommandSource, events: DomainEventSink) -> any
         }
     }
 
+    if let Some(subject) = opts.cloud_events_subject.clone() {
+        jetstream_events::init(opts, subject)?;
+    }
+
     Ok(())
 }
@@ -0,0 +1,134 @@
+//! Compile-time extensions of the domain server
+//!
+//! Studios that need custom logic implement [`DomainExtension`] in a crate of their own, depending on this one, and
+//! start the server with [`crate::server::run`] and their extensions instead of forking the domain. Extensions see
+//! the domain through the event types of this module, which only grow new variants, rather than through the
+//! messages the supervisors exchange internally.
+
+use std::collections::HashMap;
+
+use actix::{Actor, Addr};
+use anyhow::anyhow;
+use once_cell::sync::OnceCell;
+use tracing::*;
+
+use audiocloud_api::common::change::TaskState;
+use audiocloud_api::common::instance::{ReportInstancePlayState, ReportInstancePowerState};
+use audiocloud_api::common::media::MediaObject;
+use audiocloud_api::common::task::{InstanceReports, TaskSpec};
+use audiocloud_api::{AppMediaObjectId, AppTaskId, FixedInstanceId, MediaDownload, MediaUpload, TaskReservation};
+use supervisor::ExtensionsSupervisor;
+
+mod supervisor;
+
+#[cfg(test)]
+mod tests;
+
+static EXTENSIONS_SUPERVISOR: OnceCell<Addr<ExtensionsSupervisor>> = OnceCell::new();
+
+/// Custom logic running inside the domain server, called on the actor system thread
+///
+/// Hooks should return quickly and spawn anything slow with `actix::spawn`. An extension that panics is logged and
+/// keeps receiving later events.
+#[allow(unused_variables)]
+pub trait DomainExtension: 'static {
+    /// Name of the extension in logs
+    fn name(&self) -> &str;
+
+    /// Called once, after the domain subsystems started and before the first event
+    fn started(&mut self) {}
+
+    fn on_task_event(&mut self, event: &TaskEvent) {}
+
+    fn on_media_event(&mut self, event: &MediaEvent) {}
+
+    fn on_instance_event(&mut self, event: &InstanceEvent) {}
+}
+
+/// Lifecycle of a task
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum TaskEvent {
+    /// The task was created or its spec modified
+    Spec {
+        task_id: AppTaskId,
+        spec:    TaskSpec,
+    },
+    /// The task was created or its reservation changed
+    Reservation {
+        task_id:     AppTaskId,
+        reservation: TaskReservation,
+    },
+    /// The reservation of the task started
+    Activated {
+        task_id: AppTaskId,
+    },
+    /// The reservation of the task ended
+    Deactivated {
+        task_id: AppTaskId,
+    },
+    /// Play, render or instance state of the task changed
+    State {
+        task_id: AppTaskId,
+        state:   TaskState,
+    },
+    Deleted {
+        task_id: AppTaskId,
+    },
+}
+
+/// Media files moving in and out of the domain
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum MediaEvent {
+    /// Media of a task changed, i.e. a download finished
+    TaskMedia {
+        task_id: AppTaskId,
+        media:   HashMap<AppMediaObjectId, MediaObject>,
+    },
+    Download {
+        download: MediaDownload,
+    },
+    Upload {
+        upload: MediaUpload,
+    },
+}
+
+/// State of the fixed instances of the domain
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum InstanceEvent {
+    State {
+        instance_id: FixedInstanceId,
+        power:       Option<ReportInstancePowerState>,
+        play:        Option<ReportInstancePlayState>,
+        connected:   bool,
+    },
+    Error {
+        instance_id: FixedInstanceId,
+        error:       String,
+    },
+    Reports {
+        instance_id: FixedInstanceId,
+        reports:     InstanceReports,
+    },
+}
+
+/// Start dispatching domain events to the extensions, in the order given
+pub fn init(extensions: Vec<Box<dyn DomainExtension>>) -> anyhow::Result<()> {
+    for extension in &extensions {
+        info!(name = extension.name(), "Loading extension");
+    }
+
+    let supervisor = ExtensionsSupervisor::new(extensions);
+
+    EXTENSIONS_SUPERVISOR.set(supervisor.start())
+                         .map_err(|_| anyhow!("Extensions supervisor already initialized"))?;
+
+    Ok(())
+}
+
+pub fn get_extensions_supervisor() -> &'static Addr<ExtensionsSupervisor> {
+    EXTENSIONS_SUPERVISOR.get()
+                         .expect("Extensions supervisor not initialized")
+}
@@ -0,0 +1,177 @@
+#![allow(unused_variables)]
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use actix::{Actor, Context, Handler};
+use actix_broker::BrokerSubscribe;
+use tracing::*;
+
+use crate::extensions::{DomainExtension, InstanceEvent, MediaEvent, TaskEvent};
+use crate::fixed_instances::{NotifyFixedInstanceReports, NotifyInstanceError, NotifyInstanceState};
+use crate::media::{NotifyDownloadProgress, NotifyUploadProgress};
+use crate::tasks::{
+    NotifyMediaTaskState, NotifyTaskActivated, NotifyTaskDeactivated, NotifyTaskDeleted, NotifyTaskReservation,
+    NotifyTaskSpec, NotifyTaskState,
+};
+
+pub struct ExtensionsSupervisor {
+    extensions: Vec<Box<dyn DomainExtension>>,
+}
+
+impl ExtensionsSupervisor {
+    pub fn new(extensions: Vec<Box<dyn DomainExtension>>) -> Self {
+        Self { extensions: { extensions }, }
+    }
+
+    /// Call a hook on every extension, so that one panicking does not keep the event from the others
+    fn dispatch(&mut self, hook: &str, mut call: impl FnMut(&mut dyn DomainExtension)) {
+        for extension in &mut self.extensions {
+            let extension = extension.as_mut();
+            if catch_unwind(AssertUnwindSafe(|| call(extension))).is_err() {
+                error!(name = extension.name(), hook, "Extension panicked");
+            }
+        }
+    }
+
+    fn task_event(&mut self, event: TaskEvent) {
+        self.dispatch("on_task_event", |extension| extension.on_task_event(&event));
+    }
+
+    fn media_event(&mut self, event: MediaEvent) {
+        self.dispatch("on_media_event", |extension| extension.on_media_event(&event));
+    }
+
+    fn instance_event(&mut self, event: InstanceEvent) {
+        self.dispatch("on_instance_event", |extension| extension.on_instance_event(&event));
+    }
+}
+
+impl Actor for ExtensionsSupervisor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.dispatch("started", |extension| extension.started());
+
+        if self.extensions.is_empty() {
+            return;
+        }
+
+        self.subscribe_system_async::<NotifyTaskSpec>(ctx);
+        self.subscribe_system_async::<NotifyTaskReservation>(ctx);
+        self.subscribe_system_async::<NotifyTaskActivated>(ctx);
+        self.subscribe_system_async::<NotifyTaskDeactivated>(ctx);
+        self.subscribe_system_async::<NotifyTaskState>(ctx);
+        self.subscribe_system_async::<NotifyTaskDeleted>(ctx);
+        self.subscribe_system_async::<NotifyMediaTaskState>(ctx);
+        self.subscribe_system_async::<NotifyDownloadProgress>(ctx);
+        self.subscribe_system_async::<NotifyUploadProgress>(ctx);
+        self.subscribe_system_async::<NotifyInstanceState>(ctx);
+        self.subscribe_system_async::<NotifyInstanceError>(ctx);
+        self.subscribe_system_async::<NotifyFixedInstanceReports>(ctx);
+    }
+}
+
+impl Handler<NotifyTaskSpec> for ExtensionsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskSpec, ctx: &mut Self::Context) -> Self::Result {
+        self.task_event(TaskEvent::Spec { task_id: msg.task_id,
+                                          spec:    msg.spec, });
+    }
+}
+
+impl Handler<NotifyTaskReservation> for ExtensionsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskReservation, ctx: &mut Self::Context) -> Self::Result {
+        self.task_event(TaskEvent::Reservation { task_id:     msg.task_id,
+                                                 reservation: msg.reservation, });
+    }
+}
+
+impl Handler<NotifyTaskActivated> for ExtensionsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskActivated, ctx: &mut Self::Context) -> Self::Result {
+        self.task_event(TaskEvent::Activated { task_id: msg.task_id });
+    }
+}
+
+impl Handler<NotifyTaskDeactivated> for ExtensionsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskDeactivated, ctx: &mut Self::Context) -> Self::Result {
+        self.task_event(TaskEvent::Deactivated { task_id: msg.task_id });
+    }
+}
+
+impl Handler<NotifyTaskState> for ExtensionsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskState, ctx: &mut Self::Context) -> Self::Result {
+        self.task_event(TaskEvent::State { task_id: msg.task_id,
+                                           state:   msg.state, });
+    }
+}
+
+impl Handler<NotifyTaskDeleted> for ExtensionsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskDeleted, ctx: &mut Self::Context) -> Self::Result {
+        self.task_event(TaskEvent::Deleted { task_id: msg.task_id });
+    }
+}
+
+impl Handler<NotifyMediaTaskState> for ExtensionsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyMediaTaskState, ctx: &mut Self::Context) -> Self::Result {
+        self.media_event(MediaEvent::TaskMedia { task_id: msg.task_id,
+                                                 media:   msg.media, });
+    }
+}
+
+impl Handler<NotifyDownloadProgress> for ExtensionsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyDownloadProgress, ctx: &mut Self::Context) -> Self::Result {
+        self.media_event(MediaEvent::Download { download: msg.download });
+    }
+}
+
+impl Handler<NotifyUploadProgress> for ExtensionsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyUploadProgress, ctx: &mut Self::Context) -> Self::Result {
+        self.media_event(MediaEvent::Upload { upload: msg.upload });
+    }
+}
+
+impl Handler<NotifyInstanceState> for ExtensionsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyInstanceState, ctx: &mut Self::Context) -> Self::Result {
+        self.instance_event(InstanceEvent::State { instance_id: msg.instance_id,
+                                                   power:       msg.power,
+                                                   play:        msg.play,
+                                                   connected:   *msg.connected.value(), });
+    }
+}
+
+impl Handler<NotifyInstanceError> for ExtensionsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyInstanceError, ctx: &mut Self::Context) -> Self::Result {
+        self.instance_event(InstanceEvent::Error { instance_id: msg.instance_id,
+                                                   error:       msg.error, });
+    }
+}
+
+impl Handler<NotifyFixedInstanceReports> for ExtensionsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyFixedInstanceReports, ctx: &mut Self::Context) -> Self::Result {
+        self.instance_event(InstanceEvent::Reports { instance_id: msg.instance_id,
+                                                     reports:     msg.reports, });
+    }
+}
@@ -0,0 +1,62 @@
+use std::sync::{Arc, Mutex};
+
+use actix::Actor;
+
+use audiocloud_api::{AppId, AppTaskId, TaskId};
+
+use crate::extensions::supervisor::ExtensionsSupervisor;
+use crate::extensions::{DomainExtension, TaskEvent};
+use crate::tasks::{NotifyTaskActivated, NotifyTaskDeleted};
+
+struct Recorder {
+    seen:  Arc<Mutex<Vec<String>>>,
+    panic: bool,
+}
+
+impl DomainExtension for Recorder {
+    fn name(&self) -> &str {
+        "recorder"
+    }
+
+    fn started(&mut self) {
+        self.seen.lock().unwrap().push("started".to_string());
+    }
+
+    fn on_task_event(&mut self, event: &TaskEvent) {
+        if self.panic {
+            panic!("extension failed");
+        }
+
+        let seen = match event {
+            TaskEvent::Activated { task_id } => format!("activated {task_id}"),
+            TaskEvent::Deleted { task_id } => format!("deleted {task_id}"),
+            _ => "other".to_string(),
+        };
+
+        self.seen.lock().unwrap().push(seen);
+    }
+}
+
+#[actix::test]
+async fn test_extensions_receive_events_despite_panicking_neighbours() {
+    let seen = Arc::new(Mutex::new(vec![]));
+    let task_id = AppTaskId::new(AppId::test(), TaskId::new("extension-task".to_string()));
+
+    let supervisor = ExtensionsSupervisor::new(vec![Box::new(Recorder { seen:  seen.clone(),
+                                                                        panic: true, }),
+                                                    Box::new(Recorder { seen:  seen.clone(),
+                                                                        panic: false, })]).start();
+
+    supervisor.send(NotifyTaskActivated { task_id: task_id.clone(), })
+              .await
+              .expect("supervisor running");
+    supervisor.send(NotifyTaskDeleted { task_id: task_id.clone(), })
+              .await
+              .expect("supervisor running");
+
+    assert_eq!(*seen.lock().unwrap(),
+               vec!["started".to_string(),
+                    "started".to_string(),
+                    format!("activated {task_id}"),
+                    format!("deleted {task_id}")]);
+}
@@ -0,0 +1,186 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use audiocloud_api::{AppTaskId, FixedInstanceId, Timestamp};
+
+use crate::fixed_instances::sharing::{sorted_channels, InstanceClaim};
+
+/// Maintenance windows by instance, as configured under `maintenance` in the domain config
+pub type InstanceMaintenance = HashMap<FixedInstanceId, Vec<MaintenanceWindow>>;
+
+/// Time an instance is out of service, tasks can not reserve it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MaintenanceWindow {
+    pub from:   Timestamp,
+    pub to:     Timestamp,
+    /// Shown to studio managers, i.e. "Tube replacement"
+    pub reason: String,
+}
+
+impl MaintenanceWindow {
+    pub fn overlaps(&self, from: Timestamp, to: Timestamp) -> bool {
+        self.from < to && from < self.to
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum InstanceCalendarEntryKind {
+    Reservation,
+    Maintenance,
+}
+
+/// A reservation of a task or a maintenance window of a fixed instance
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+pub struct InstanceCalendarEntry {
+    pub kind:     InstanceCalendarEntryKind,
+    #[schema(value_type = String)]
+    pub from:     Timestamp,
+    #[schema(value_type = String)]
+    pub to:       Timestamp,
+    /// Task holding the reservation
+    #[schema(value_type = Option<String>)]
+    pub task_id:  Option<AppTaskId>,
+    /// Channels the task holds, empty if it holds all of them
+    pub channels: Vec<usize>,
+    /// Why the instance is out of service
+    pub reason:   Option<String>,
+}
+
+impl InstanceCalendarEntry {
+    pub fn reservation(claim: &InstanceClaim) -> Self {
+        Self { kind:     { InstanceCalendarEntryKind::Reservation },
+               from:     { claim.from },
+               to:       { claim.to },
+               task_id:  { Some(claim.task_id.clone()) },
+               channels: { sorted_channels(&claim.channels) },
+               reason:   { None }, }
+    }
+
+    pub fn maintenance(window: &MaintenanceWindow) -> Self {
+        Self { kind:     { InstanceCalendarEntryKind::Maintenance },
+               from:     { window.from },
+               to:       { window.to },
+               task_id:  { None },
+               channels: { vec![] },
+               reason:   { Some(window.reason.clone()) }, }
+    }
+
+    fn summary(&self, instance_id: &FixedInstanceId) -> String {
+        match (&self.kind, &self.task_id, &self.reason) {
+            (InstanceCalendarEntryKind::Reservation, Some(task_id), _) => {
+                format!("{instance_id} reserved by {task_id}")
+            }
+            (InstanceCalendarEntryKind::Maintenance, _, Some(reason)) => format!("{instance_id} maintenance: {reason}"),
+            (InstanceCalendarEntryKind::Reservation, None, _) => format!("{instance_id} reserved"),
+            (InstanceCalendarEntryKind::Maintenance, _, None) => format!("{instance_id} maintenance"),
+        }
+    }
+
+    fn uid(&self, instance_id: &FixedInstanceId) -> String {
+        let kind = match self.kind {
+            InstanceCalendarEntryKind::Reservation => "reservation",
+            InstanceCalendarEntryKind::Maintenance => "maintenance",
+        };
+
+        let of = match &self.task_id {
+            Some(task_id) => task_id.to_string(),
+            None => ical_time(self.from),
+        };
+
+        format!("{kind}/{instance_id}/{of}@audiocloud").replace(' ', "-")
+    }
+}
+
+/// Calendar entries of an instance from the claims of tasks and the maintenance windows, overlapping `from` to `to`
+///
+/// A task holding several of the instances (i.e. the members of a composite) shows up once.
+pub fn calendar_entries<'a>(claims: impl Iterator<Item = &'a InstanceClaim>,
+                            maintenance: impl Iterator<Item = &'a MaintenanceWindow>,
+                            from: Option<Timestamp>,
+                            to: Option<Timestamp>)
+                            -> Vec<InstanceCalendarEntry> {
+    let mut tasks = HashSet::new();
+
+    let mut rv = claims.filter(|claim| tasks.insert(claim.task_id.clone()))
+                       .map(InstanceCalendarEntry::reservation)
+                       .chain(maintenance.map(InstanceCalendarEntry::maintenance))
+                       .filter(|entry| from.map(|from| entry.to > from).unwrap_or(true))
+                       .filter(|entry| to.map(|to| entry.from < to).unwrap_or(true))
+                       .collect::<Vec<_>>();
+
+    rv.sort_by_key(|entry| entry.from);
+    rv
+}
+
+/// Render calendar entries of an instance as an iCalendar (RFC 5545) feed
+pub fn to_ical(instance_id: &FixedInstanceId, entries: &[InstanceCalendarEntry], now: Timestamp) -> String {
+    let mut lines = vec!["BEGIN:VCALENDAR".to_owned(),
+                         "VERSION:2.0".to_owned(),
+                         "PRODID:-//audiocloud//domain server//EN".to_owned(),
+                         "CALSCALE:GREGORIAN".to_owned(),
+                         format!("X-WR-CALNAME:{}", ical_text(&instance_id.to_string())),];
+
+    for entry in entries {
+        lines.push("BEGIN:VEVENT".to_owned());
+        lines.push(format!("UID:{}", ical_text(&entry.uid(instance_id))));
+        lines.push(format!("DTSTAMP:{}", ical_time(now)));
+        lines.push(format!("DTSTART:{}", ical_time(entry.from)));
+        lines.push(format!("DTEND:{}", ical_time(entry.to)));
+        lines.push(format!("SUMMARY:{}", ical_text(&entry.summary(instance_id))));
+
+        if !entry.channels.is_empty() {
+            let channels = entry.channels.iter().map(ToString::to_string).collect::<Vec<_>>();
+            lines.push(format!("DESCRIPTION:{}",
+                               ical_text(&format!("Channels {}", channels.join(", ")))));
+        }
+
+        if entry.kind == InstanceCalendarEntryKind::Maintenance {
+            lines.push("TRANSP:OPAQUE".to_owned());
+            lines.push("CATEGORIES:MAINTENANCE".to_owned());
+        }
+
+        lines.push("END:VEVENT".to_owned());
+    }
+
+    lines.push("END:VCALENDAR".to_owned());
+
+    let mut rv = String::new();
+    for line in lines {
+        let _ = write!(rv, "{}\r\n", fold_line(&line));
+    }
+
+    rv
+}
+
+fn ical_time(at: Timestamp) -> String {
+    at.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn ical_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Lines longer than 75 octets continue on the next line after a space
+fn fold_line(line: &str) -> String {
+    let mut rv = String::new();
+    let mut octets = 0;
+
+    for c in line.chars() {
+        if octets + c.len_utf8() > 75 {
+            rv.push_str("\r\n ");
+            octets = 1;
+        }
+
+        rv.push(c);
+        octets += c.len_utf8();
+    }
+
+    rv
+}
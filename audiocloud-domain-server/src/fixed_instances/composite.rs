@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use audiocloud_api::cloud::domains::{FixedInstanceRouting, FixedInstanceRoutingMap};
+use audiocloud_api::common::task::{InstanceParameters, InstanceReports};
+use audiocloud_api::common::time::Timestamped;
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::FixedInstanceId;
+
+use crate::fixed_instances::values::empty_object;
+use crate::fixed_instances::NotifyInstanceState;
+use crate::DomainResult;
+
+/// Composite instances by their ID, as configured under `composite_instances` in the domain config
+pub type CompositeInstances = HashMap<FixedInstanceId, CompositeInstanceConfig>;
+
+/// Several fixed instances patched in series (i.e. preamp, EQ and compressor) that tasks use as a single instance
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CompositeInstanceConfig {
+    /// Members in signal order, the engine sends to the first and returns from the last
+    pub members: Vec<CompositeMember>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CompositeMember {
+    pub instance_id: FixedInstanceId,
+    /// Parameters and reports of the member are named `{prefix}.{name}` on the composite
+    pub prefix:      String,
+}
+
+impl CompositeInstanceConfig {
+    pub fn member_ids(&self) -> impl Iterator<Item = &FixedInstanceId> {
+        self.members.iter().map(|member| &member.instance_id)
+    }
+
+    pub fn contains(&self, instance_id: &FixedInstanceId) -> bool {
+        self.member_ids().any(|id| id == instance_id)
+    }
+
+    /// The engine sends to the first member and returns from the last, the members are patched to each other
+    pub fn routing(&self, routing: &FixedInstanceRoutingMap) -> Option<FixedInstanceRouting> {
+        let send = routing.get(&self.members.first()?.instance_id)?;
+        let ret = routing.get(&self.members.last()?.instance_id)?;
+
+        Some(FixedInstanceRouting { send_count:     { send.send_count },
+                                    send_channel:   { send.send_channel },
+                                    return_count:   { ret.return_count },
+                                    return_channel: { ret.return_channel }, })
+    }
+
+    /// Split parameters named `{prefix}.{name}` into the parameters of each member
+    pub fn split_parameters(&self,
+                            composite_id: &FixedInstanceId,
+                            parameters: InstanceParameters)
+                            -> DomainResult<HashMap<FixedInstanceId, InstanceParameters>> {
+        let parameters = match parameters {
+            Value::Object(parameters) => parameters,
+            other => {
+                return Err(DomainError::Serialization { error: format!("Parameters of composite instance \
+                                                                        {composite_id} must be an object, got \
+                                                                        {other}"), })
+            }
+        };
+
+        let mut rv = HashMap::new();
+
+        for (name, value) in parameters {
+            let member = name.split_once('.').and_then(|(prefix, name)| {
+                                                 self.members
+                                                     .iter()
+                                                     .find(|member| member.prefix == prefix)
+                                                     .map(|member| (member, name))
+                                             });
+
+            let (member, name) = match member {
+                Some(member) => member,
+                None => {
+                    return Err(DomainError::Serialization { error: format!("Parameter {name} of composite \
+                                                                            instance {composite_id} does not \
+                                                                            start with the prefix of a member"), })
+                }
+            };
+
+            if let Value::Object(member_parameters) = rv.entry(member.instance_id.clone()).or_insert_with(empty_object)
+            {
+                member_parameters.insert(name.to_owned(), value);
+            }
+        }
+
+        Ok(rv)
+    }
+
+    /// Name the reports of a member the way they are named on the composite
+    pub fn prefix_reports(&self, member_id: &FixedInstanceId, reports: InstanceReports) -> Option<InstanceReports> {
+        let member = self.members.iter().find(|member| &member.instance_id == member_id)?;
+
+        match reports {
+            Value::Object(reports) => {
+                Some(Value::Object(reports.into_iter()
+                                          .map(|(name, value)| (format!("{}.{name}", member.prefix), value))
+                                          .collect()))
+            }
+            _ => None,
+        }
+    }
+
+    /// State of the composite from the states of its members, `None` until all members reported
+    ///
+    /// The composite is connected when all members are, and reports the power and play state of the first member in
+    /// signal order that does not satisfy its desired state yet.
+    pub fn combine_states(&self,
+                          composite_id: &FixedInstanceId,
+                          states: &HashMap<FixedInstanceId, NotifyInstanceState>)
+                          -> Option<NotifyInstanceState> {
+        let members = self.member_ids().map(|id| states.get(id)).collect::<Option<Vec<_>>>()?;
+
+        let connected = members.iter().all(|state| *state.connected.value());
+
+        let powered = members.iter().filter_map(|state| state.power.as_ref());
+        let power = powered.clone()
+                           .find(|power| !power.actual.value().satisfies(*power.desired.value()))
+                           .or_else(|| powered.clone().next())
+                           .cloned();
+
+        let playing = members.iter().filter_map(|state| state.play.as_ref());
+        let play = playing.clone()
+                          .find(|play| !play.actual.value().satisfies(play.desired.value()))
+                          .or_else(|| playing.clone().next())
+                          .cloned();
+
+        Some(NotifyInstanceState { instance_id: { composite_id.clone() },
+                                   power:       { power },
+                                   play:        { play },
+                                   connected:   { Timestamped::new(connected) }, })
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use audiocloud_api::{InstanceReports, ModelId};
+
+/// Downsampling of reports by model and report name, as configured under `report_downsampling` in the domain config
+pub type ReportDownsamplingMap = HashMap<ModelId, ReportDownsamplingPolicies>;
+
+/// Downsampling of the reports of a model, by report name
+pub type ReportDownsamplingPolicies = HashMap<String, ReportDownsampling>;
+
+/// How often a report is sent on, and how the values reported in between are combined
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReportDownsampling {
+    /// Milliseconds between the values sent on
+    pub interval_ms: u64,
+    #[serde(default)]
+    pub aggregate:   ReportAggregate,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportAggregate {
+    /// Highest value, per channel
+    Max,
+    /// Average value, per channel
+    Avg,
+    /// Latest value
+    #[default]
+    Last,
+}
+
+#[derive(Debug)]
+struct PendingReport {
+    since:  Instant,
+    values: Vec<Value>,
+}
+
+/// Collects the values of downsampled reports of an instance and combines them once their interval passed, reports
+/// without a policy are sent on as they come
+#[derive(Debug, Default)]
+pub struct ReportDownsampler {
+    policies: ReportDownsamplingPolicies,
+    pending:  HashMap<String, PendingReport>,
+}
+
+impl ReportDownsampler {
+    pub fn new(policies: ReportDownsamplingPolicies) -> Self {
+        Self { policies: { policies },
+               pending:  { HashMap::new() }, }
+    }
+
+    /// Replace the policies, values collected for reports that are no longer downsampled are dropped
+    pub fn set_policies(&mut self, policies: ReportDownsamplingPolicies) {
+        self.pending.retain(|name, _| policies.contains_key(name));
+        self.policies = policies;
+    }
+
+    /// Collect the reports, returning those that are due to be sent on
+    pub fn push(&mut self, reports: InstanceReports, now: Instant) -> Option<InstanceReports> {
+        if self.policies.is_empty() {
+            return Some(reports);
+        }
+
+        let reports = match reports {
+            Value::Object(reports) => reports,
+            other => return Some(other),
+        };
+
+        let mut due = serde_json::Map::new();
+        for (name, value) in reports {
+            if self.policies.contains_key(&name) {
+                self.pending
+                    .entry(name)
+                    .or_insert_with(|| PendingReport { since:  { now },
+                                                       values: { vec![] }, })
+                    .values
+                    .push(value);
+            } else {
+                due.insert(name, value);
+            }
+        }
+
+        due.extend(self.take_due(now));
+
+        (!due.is_empty()).then(|| Value::Object(due))
+    }
+
+    /// Combined values of the downsampled reports whose interval passed
+    pub fn flush_due(&mut self, now: Instant) -> Option<InstanceReports> {
+        let due = self.take_due(now);
+
+        (!due.is_empty()).then(|| Value::Object(due))
+    }
+
+    fn take_due(&mut self, now: Instant) -> serde_json::Map<String, Value> {
+        let policies = &self.policies;
+        let due = self.pending
+                      .iter()
+                      .filter(|(name, pending)| {
+                          policies.get(*name)
+                                  .map(|policy| {
+                                      now.duration_since(pending.since) >= Duration::from_millis(policy.interval_ms)
+                                  })
+                                  .unwrap_or(true)
+                      })
+                      .map(|(name, _)| name.clone())
+                      .collect::<Vec<_>>();
+
+        let mut rv = serde_json::Map::new();
+        for name in due {
+            if let (Some(pending), Some(policy)) = (self.pending.remove(&name), policies.get(&name)) {
+                rv.insert(name, aggregate_values(policy.aggregate, &pending.values));
+            }
+        }
+
+        rv
+    }
+}
+
+/// Combine report values, per channel for values reported as arrays. Values that are not numbers are combined by
+/// taking the latest one
+pub fn aggregate_values(aggregate: ReportAggregate, values: &[Value]) -> Value {
+    let last = values.last().cloned().unwrap_or(Value::Null);
+
+    if aggregate == ReportAggregate::Last {
+        return last;
+    }
+
+    if let Some(numbers) = values.iter().map(Value::as_f64).collect::<Option<Vec<_>>>() {
+        return aggregate_numbers(aggregate, &numbers).map(Value::from).unwrap_or(last);
+    }
+
+    if let Some(arrays) = values.iter().map(Value::as_array).collect::<Option<Vec<_>>>() {
+        let channels = arrays.iter().map(|values| values.len()).max().unwrap_or_default();
+
+        return Value::Array((0..channels).map(|channel| {
+                                             let values = arrays.iter()
+                                                                .filter_map(|values| values.get(channel).cloned())
+                                                                .collect::<Vec<_>>();
+                                             aggregate_values(aggregate, &values)
+                                         })
+                                         .collect());
+    }
+
+    last
+}
+
+fn aggregate_numbers(aggregate: ReportAggregate, numbers: &[f64]) -> Option<f64> {
+    if numbers.is_empty() {
+        return None;
+    }
+
+    match aggregate {
+        ReportAggregate::Max => numbers.iter().copied().reduce(f64::max),
+        ReportAggregate::Avg => Some(numbers.iter().sum::<f64>() / numbers.len() as f64),
+        ReportAggregate::Last => numbers.last().copied(),
+    }
+}
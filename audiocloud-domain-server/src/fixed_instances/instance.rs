@@ -1,6 +1,6 @@
 #![allow(unused_variables)]
 
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use actix::{Actor, ActorFutureExt, AsyncContext, Context, ContextFutureSpawner, Handler, StreamHandler, WrapFuture};
 use actix_broker::BrokerIssue;
@@ -15,10 +15,12 @@ use audiocloud_api::{
     SerializableResult, Timestamped,
 };
 
+use crate::fixed_instances::sharing::merge_channel_parameters;
 use crate::fixed_instances::values::merge_values;
 use crate::fixed_instances::{
     get_instance_supervisor, NotifyFixedInstanceReports, NotifyInstancePowerChannelsChanged, NotifyInstanceState,
-    SetDesiredPowerChannel, SetInstanceDesiredPlayState, SetInstanceParameters,
+    ReportDownsampler, ReportDownsamplingPolicies, SetDesiredPowerChannel, SetInstanceChannelParameters,
+    SetInstanceDesiredPlayState, SetInstanceReportDownsampling,
 };
 use crate::tasks::{NotifyTaskDeleted, NotifyTaskSpec};
 use crate::{nats, DomainResult};
@@ -36,13 +38,20 @@ pub struct InstanceActor {
     parameters:          serde_json::Value,
     instance_driver_cmd: String,
     model:               Model,
+    reports:             ReportDownsampler,
 }
 
 impl InstanceActor {
-    pub fn new(id: FixedInstanceId, config: DomainFixedInstanceConfig, model: Model) -> anyhow::Result<Self> {
+    pub fn new(id: FixedInstanceId,
+               config: DomainFixedInstanceConfig,
+               model: Model,
+               downsampling: ReportDownsamplingPolicies)
+               -> anyhow::Result<Self> {
         let power = config.power.clone().map(Power::new);
         let media = config.media.clone().map(Media::new);
         let instance_driver_cmd = id.driver_command_subject();
+        nats::label_subject(&instance_driver_cmd, "driver_commands");
+        nats::label_subject(id.driver_event_subject(), "driver_events");
 
         Ok(Self { id:                  { id },
                   connected:           { Timestamped::new(false) },
@@ -50,6 +59,7 @@ impl InstanceActor {
                   power:               { power },
                   media:               { media },
                   model:               { model },
+                  reports:             { ReportDownsampler::new(downsampling) },
                   spec:                { Default::default() },
                   parameters:          { Default::default() },
                   instance_driver_cmd: { instance_driver_cmd }, })
@@ -69,6 +79,13 @@ impl InstanceActor {
             }
         }
 
+        // power channels are followed as reported, only the reports sent on are downsampled
+        if let Some(reports) = self.reports.push(reports, Instant::now()) {
+            self.emit_instance_reports(reports);
+        }
+    }
+
+    fn emit_instance_reports(&self, reports: InstanceReports) {
         self.issue_system_async(NotifyFixedInstanceReports { instance_id: self.id.clone(),
                                                              reports });
     }
@@ -128,16 +145,34 @@ impl Handler<NotifyTaskDeleted> for InstanceActor {
     }
 }
 
-impl Handler<SetInstanceParameters> for InstanceActor {
+impl Handler<SetInstanceChannelParameters> for InstanceActor {
     type Result = DomainResult;
 
-    fn handle(&mut self, msg: SetInstanceParameters, ctx: &mut Self::Context) -> Self::Result {
-        merge_values(&mut self.parameters, msg.parameters);
+    fn handle(&mut self, msg: SetInstanceChannelParameters, ctx: &mut Self::Context) -> Self::Result {
+        match msg.channels {
+            None => merge_values(&mut self.parameters, msg.parameters),
+            Some(channels) => {
+                // the instance is shared per channel, the other channels belong to other tasks
+                if let Err(name) = merge_channel_parameters(&mut self.parameters, msg.parameters, &channels) {
+                    let operation = format!("set parameter {name} beyond channels {channels:?}");
+                    return Err(DomainError::InstanceNotCapable { instance_id: self.id.clone(),
+                                                                 operation });
+                }
+            }
+        }
 
         Ok(())
     }
 }
 
+impl Handler<SetInstanceReportDownsampling> for InstanceActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetInstanceReportDownsampling, ctx: &mut Self::Context) -> Self::Result {
+        self.reports.set_policies(msg.policies);
+    }
+}
+
 impl Handler<SetDesiredPowerChannel> for InstanceActor {
     type Result = DomainResult;
 
@@ -178,9 +213,11 @@ impl StreamHandler<InstanceDriverEvent> for InstanceActor {
             InstanceDriverEvent::IOError { .. } => {}
             InstanceDriverEvent::ConnectionLost => {
                 self.connected = false.into();
+                self.emit_instance_state(ctx);
             }
             InstanceDriverEvent::Connected => {
                 self.connected = true.into();
+                self.emit_instance_state(ctx);
                 self.on_instance_driver_connected(ctx);
             }
             InstanceDriverEvent::Reports { reports } => {
@@ -214,6 +251,9 @@ impl InstanceActor {
                 self.request_instance_driver(cmd, ctx);
             }
         }
+        if let Some(reports) = self.reports.flush_due(Instant::now()) {
+            self.emit_instance_reports(reports);
+        }
     }
 
     fn request_instance_driver(&self, driver: InstanceDriverCommand, ctx: &mut <Self as Actor>::Context) {
@@ -240,9 +280,13 @@ impl InstanceActor {
     }
 
     fn emit_instance_state(&self, ctx: &mut <Self as Actor>::Context) {
-        let x = NotifyInstanceState { instance_id: self.id.clone(),
-                                      power:       self.power.as_ref().map(|power| power.get_power_state()),
-                                      play:        self.media.as_ref().map(|media| media.get_play_state()),
-                                      connected:   self.connected, };
+        self.issue_system_async(NotifyInstanceState { instance_id: self.id.clone(),
+                                                      power:       self.power
+                                                                       .as_ref()
+                                                                       .map(|power| power.get_power_state()),
+                                                      play:        self.media
+                                                                       .as_ref()
+                                                                       .map(|media| media.get_play_state()),
+                                                      connected:   self.connected, });
     }
 }
@@ -0,0 +1,52 @@
+use std::collections::{HashMap, HashSet};
+
+use audiocloud_api::FixedInstanceId;
+
+/// Who wants the state of which instances, so state changes are only sent to those interested in them
+///
+/// Subscribers register again to change what they are interested in, the latest registration replaces the earlier one.
+#[derive(Debug)]
+pub struct InstanceInterest<R> {
+    subscribers: HashMap<String, InterestedSubscriber<R>>,
+}
+
+#[derive(Debug)]
+struct InterestedSubscriber<R> {
+    recipient:    R,
+    instance_ids: HashSet<FixedInstanceId>,
+}
+
+impl<R> Default for InstanceInterest<R> {
+    fn default() -> Self {
+        Self { subscribers: HashMap::new(), }
+    }
+}
+
+impl<R> InstanceInterest<R> {
+    pub fn register(&mut self, subscriber: String, instance_ids: HashSet<FixedInstanceId>, recipient: R) {
+        if instance_ids.is_empty() {
+            self.subscribers.remove(&subscriber);
+        } else {
+            self.subscribers.insert(subscriber,
+                                    InterestedSubscriber { recipient:    { recipient },
+                                                           instance_ids: { instance_ids }, });
+        }
+    }
+
+    /// Recipients interested in the state of the instance
+    pub fn recipients_of<'a>(&'a self, instance_id: &'a FixedInstanceId) -> impl Iterator<Item = &'a R> + 'a {
+        self.subscribers
+            .values()
+            .filter(move |subscriber| subscriber.instance_ids.contains(instance_id))
+            .map(|subscriber| &subscriber.recipient)
+    }
+
+    /// Forget subscribers whose recipients are gone
+    pub fn retain(&mut self, is_alive: impl Fn(&R) -> bool) {
+        self.subscribers.retain(|_, subscriber| is_alive(&subscriber.recipient));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subscribers.is_empty()
+    }
+}
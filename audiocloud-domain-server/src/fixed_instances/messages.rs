@@ -1,12 +1,15 @@
 use std::collections::{HashMap, HashSet};
 
-use actix::Message;
+use actix::{Message, Recipient};
+use serde::{Deserialize, Serialize};
 
 use audiocloud_api::common::instance::{DesiredInstancePlayState, ReportInstancePlayState, ReportInstancePowerState};
 use audiocloud_api::common::newtypes::FixedInstanceId;
 use audiocloud_api::common::task::{InstanceParameters, InstanceReports};
 use audiocloud_api::common::time::Timestamped;
+use audiocloud_api::{AppTaskId, TaskReservation, TaskSpec, Timestamp};
 
+use crate::fixed_instances::{InstanceCalendarEntry, ReportDownsamplingPolicies};
 use crate::DomainResult;
 
 #[derive(Message, Clone, Debug)]
@@ -14,6 +17,25 @@ use crate::DomainResult;
 pub struct SetInstanceParameters {
     pub instance_id: FixedInstanceId,
     pub parameters:  InstanceParameters,
+    /// Task writing the parameters, whose values are for its channels if the model is shared per channel
+    pub task_id:     Option<AppTaskId>,
+}
+
+/// Parameters for the instance actor, with the channels the values are for if only some of them
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<()>")]
+pub struct SetInstanceChannelParameters {
+    pub instance_id: FixedInstanceId,
+    pub parameters:  InstanceParameters,
+    pub channels:    Option<Vec<usize>>,
+}
+
+/// Downsampling of the reports of the instance actor, after the domain config changed
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct SetInstanceReportDownsampling {
+    pub instance_id: FixedInstanceId,
+    pub policies:    ReportDownsamplingPolicies,
 }
 
 #[derive(Message, Clone, Debug)]
@@ -31,12 +53,63 @@ pub struct SetInstanceDesiredPlayState {
     pub desired:     DesiredInstancePlayState,
 }
 
+/// Hold the fixed instances of a task for the time of its reservation, refused when a task with an overlapping
+/// reservation holds instances whose models can not be shared with it
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<()>")]
+pub struct ReserveFixedInstances {
+    pub task_id:     AppTaskId,
+    pub reservation: TaskReservation,
+    pub spec:        TaskSpec,
+}
+
+/// Time range of an instance calendar, entries overlapping it are listed
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct InstanceCalendarQuery {
+    pub from: Option<Timestamp>,
+    pub to:   Option<Timestamp>,
+}
+
+/// Reservations and maintenance windows of a fixed or composite instance, in order of their start
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<Vec<InstanceCalendarEntry>>")]
+pub struct GetInstanceCalendar {
+    pub instance_id: FixedInstanceId,
+    pub query:       InstanceCalendarQuery,
+}
+
+/// Last known states of fixed and composite instances, instances that did not report a state yet are left out
 #[derive(Message, Clone, Debug)]
 #[rtype(result = "HashMap<FixedInstanceId, NotifyInstanceState>")]
 pub struct GetMultipleFixedInstanceState {
     pub instance_ids: HashSet<FixedInstanceId>,
 }
 
+/// Send the states of these instances to the recipient when they change, answered with their states so far
+///
+/// Registering again under the same subscriber replaces the instances it is interested in, none unregisters it.
+/// Recipients that stopped are forgotten on their own.
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "HashMap<FixedInstanceId, NotifyInstanceState>")]
+pub struct RegisterInstanceInterest {
+    pub subscriber:   String,
+    pub instance_ids: HashSet<FixedInstanceId>,
+    pub recipient:    Recipient<NotifyInstanceState>,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "Vec<FixedInstanceSummary>")]
+pub struct ListFixedInstances;
+
+/// Fixed instance with its last reported state, `None` until the instance reports it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FixedInstanceSummary {
+    pub instance_id: FixedInstanceId,
+    pub connected:   bool,
+    pub power:       Option<ReportInstancePowerState>,
+    pub play:        Option<ReportInstancePlayState>,
+}
+
 #[derive(Message, Clone, Debug)]
 #[rtype(result = "()")]
 pub struct NotifyFixedInstanceReports {
@@ -1,19 +1,39 @@
 use actix::{Actor, Addr};
 use anyhow::anyhow;
+use clap::Args;
 use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
 use tracing::*;
 
-use audiocloud_api::cloud::domains::{DomainConfig, FixedInstanceRoutingMap};
+use audiocloud_api::cloud::domains::{
+    DomainConfig, DomainFixedInstanceConfig, FixedInstanceRouting, FixedInstanceRoutingMap,
+};
+use audiocloud_api::Model;
+pub use calendar::{to_ical, InstanceCalendarEntry, InstanceCalendarEntryKind, InstanceMaintenance, MaintenanceWindow};
+pub use composite::{CompositeInstanceConfig, CompositeInstances, CompositeMember};
+pub use downsampling::{
+    aggregate_values, ReportAggregate, ReportDownsampler, ReportDownsampling, ReportDownsamplingMap,
+    ReportDownsamplingPolicies,
+};
+pub use interest::InstanceInterest;
 pub use messages::*;
+pub use sharing::{channel_reports, sorted_channels, task_instance_channels, ModelSharing, ModelSharingMap};
 pub use supervisor::FixedInstancesSupervisor;
 
 use crate::db::Db;
 
+mod calendar;
+mod composite;
+mod downsampling;
 mod instance;
+mod interest;
 mod media;
 mod messages;
 mod power;
+mod sharing;
 mod supervisor;
+#[cfg(test)]
+mod tests;
 mod values;
 
 static INSTANCE_SUPERVISOR: OnceCell<Addr<FixedInstancesSupervisor>> = OnceCell::new();
@@ -22,11 +42,57 @@ pub fn get_instance_supervisor() -> &'static Addr<FixedInstancesSupervisor> {
     INSTANCE_SUPERVISOR.get().expect("Instance supervisor not initialized")
 }
 
+#[derive(Args, Clone, Debug)]
+pub struct FixedInstanceOpts {
+    /// Number of instances to set up at the same time while the domain boots
+    #[clap(long, env, default_value = "16")]
+    pub instance_boot_concurrency: usize,
+
+    /// Milliseconds an instance may take to set up while the domain boots, it is left out of the boot after that
+    #[clap(long, env, default_value = "10000")]
+    pub instance_boot_timeout_ms: u64,
+
+    /// Milliseconds instance state changes are collected for before they are sent to the tasks interested in them,
+    /// an instance that changes more often in that time is sent once with its latest state
+    #[clap(long, env, default_value = "50")]
+    pub instance_state_coalesce_ms: u64,
+}
+
 #[instrument(skip_all, err)]
-pub async fn init(cfg: &DomainConfig, db: Db) -> anyhow::Result<FixedInstanceRoutingMap> {
-    let (routing, supervisor) = FixedInstancesSupervisor::new(cfg, db).await?;
+pub async fn init(cfg: &DomainConfig,
+                  extras: FixedInstanceExtras,
+                  db: Db,
+                  opts: &FixedInstanceOpts)
+                  -> anyhow::Result<FixedInstanceRoutingMap> {
+    let (routing, supervisor) = FixedInstancesSupervisor::new(cfg, extras, db, opts).await?;
     INSTANCE_SUPERVISOR.set(supervisor.start())
                        .map_err(|_| anyhow!("INSTANCE_SUPERVISOR already initialized"))?;
 
     Ok(routing)
 }
+
+/// Fixed instance config this domain server reads from the domain config, next to what the cloud API describes
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FixedInstanceExtras {
+    #[serde(default)]
+    pub composite_instances: CompositeInstances,
+    #[serde(default)]
+    pub model_sharing:       ModelSharingMap,
+    #[serde(default)]
+    pub maintenance:         InstanceMaintenance,
+    #[serde(default)]
+    pub report_downsampling: ReportDownsamplingMap,
+}
+
+/// Where the engine sends to and returns from an instance, if both its input and output channels are configured
+pub fn instance_routing(config: &DomainFixedInstanceConfig, model: &Model) -> Option<FixedInstanceRouting> {
+    match (config.input_start, config.output_start) {
+        (Some(input_start), Some(output_start)) => {
+            Some(FixedInstanceRouting { send_count:     { model.inputs.len() },
+                                        send_channel:   { output_start as usize },
+                                        return_count:   { model.outputs.len() },
+                                        return_channel: { input_start as usize }, })
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use audiocloud_api::common::task::{InstanceParameters, InstanceReports};
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::{AppTaskId, ChannelMask, FixedInstanceId, InputPadId, ModelId, OutputPadId, TaskSpec, Timestamp};
+
+use crate::DomainResult;
+
+/// Sharing modes by model, as configured under `model_sharing` in the domain config
+pub type ModelSharingMap = HashMap<ModelId, ModelSharing>;
+
+/// Whether tasks with overlapping reservations may use instances of a model at the same time
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelSharing {
+    /// Any number of tasks may use an instance at the same time
+    #[default]
+    Shared,
+    /// Tasks may use an instance at the same time as long as they connect to different channels of it
+    PerChannel,
+    /// One task at a time
+    Exclusive,
+}
+
+impl ModelSharing {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ModelSharing::Shared => "shared",
+            ModelSharing::PerChannel => "per_channel",
+            ModelSharing::Exclusive => "exclusive",
+        }
+    }
+}
+
+/// Channels of a fixed instance a task holds for the time of its reservation
+#[derive(Clone, Debug, PartialEq)]
+pub struct InstanceClaim {
+    pub task_id:  AppTaskId,
+    pub from:     Timestamp,
+    pub to:       Timestamp,
+    /// Channels the task connects to, empty if it connects none and holds the whole instance
+    pub channels: HashSet<usize>,
+}
+
+impl InstanceClaim {
+    pub fn conflicts_with(&self, other: &InstanceClaim, sharing: ModelSharing) -> bool {
+        if self.task_id == other.task_id || self.from >= other.to || other.from >= self.to {
+            return false;
+        }
+
+        match sharing {
+            ModelSharing::Shared => false,
+            ModelSharing::PerChannel => {
+                self.channels.is_empty() || other.channels.is_empty() || !self.channels.is_disjoint(&other.channels)
+            }
+            ModelSharing::Exclusive => true,
+        }
+    }
+}
+
+/// Refuse a claim on an instance that conflicts with the claims other tasks hold on it
+pub fn check_claim(instance_id: &FixedInstanceId,
+                   sharing: ModelSharing,
+                   claim: &InstanceClaim,
+                   claims: &[InstanceClaim])
+                   -> DomainResult {
+    match claims.iter().find(|other| claim.conflicts_with(other, sharing)) {
+        Some(other) => Err(DomainError::InstanceNotCapable { instance_id: { instance_id.clone() },
+                                                             operation:   {
+                                                                 format!("{} use overlapping task {}",
+                                                                         sharing.as_str(),
+                                                                         other.task_id)
+                                                             }, }),
+        None => Ok(()),
+    }
+}
+
+/// Channels of each fixed instance the spec of a task connects to, sends and returns alike
+pub fn task_instance_channels(spec: &TaskSpec) -> HashMap<FixedInstanceId, HashSet<usize>> {
+    let mut rv = HashMap::<FixedInstanceId, HashSet<usize>>::new();
+
+    for fixed in spec.fixed.values() {
+        rv.entry(fixed.instance_id.clone()).or_default();
+    }
+
+    for connection in spec.connections.values() {
+        if let InputPadId::FixedInstanceInput(node_id) = &connection.to {
+            if let Some(fixed) = spec.fixed.get(node_id) {
+                rv.entry(fixed.instance_id.clone())
+                  .or_default()
+                  .extend(mask_channels(connection.to_channels));
+            }
+        }
+
+        if let OutputPadId::FixedInstanceOutput(node_id) = &connection.from {
+            if let Some(fixed) = spec.fixed.get(node_id) {
+                rv.entry(fixed.instance_id.clone())
+                  .or_default()
+                  .extend(mask_channels(connection.from_channels));
+            }
+        }
+    }
+
+    rv
+}
+
+/// Channels in ascending order, the order in which tasks holding some channels of an instance write and read values
+pub fn sorted_channels(channels: &HashSet<usize>) -> Vec<usize> {
+    let mut rv = channels.iter().copied().collect::<Vec<_>>();
+    rv.sort();
+    rv
+}
+
+/// Write parameters a task gave for its channels into the per-channel values of all channels of the instance
+///
+/// The n-th value of each parameter is for the n-th of `channels`, values of the other channels are kept. Parameters
+/// that are not per channel would change the channels of other tasks, so they are refused with their name.
+pub fn merge_channel_parameters(current: &mut InstanceParameters,
+                                parameters: InstanceParameters,
+                                channels: &[usize])
+                                -> Result<(), String> {
+    let parameters = match parameters {
+        Value::Object(parameters) => parameters,
+        _ => return Ok(()),
+    };
+
+    if let Some((name, _)) =
+        parameters.iter()
+                  .find(|(_, values)| !matches!(values, Value::Array(values) if values.len() <= channels.len()))
+    {
+        return Err(name.clone());
+    }
+
+    if !current.is_object() {
+        *current = Value::Object(Default::default());
+    }
+
+    if let Value::Object(current) = current {
+        for (name, values) in parameters {
+            let slot = current.entry(name).or_insert_with(|| Value::Array(vec![]));
+            if !slot.is_array() {
+                *slot = Value::Array(vec![]);
+            }
+
+            if let (Value::Array(slot), Value::Array(values)) = (slot, values) {
+                for (channel, value) in channels.iter().zip(values) {
+                    if slot.len() <= *channel {
+                        slot.resize(channel + 1, Value::Null);
+                    }
+
+                    slot[*channel] = value;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reports of some channels of an instance, the per-channel values in the order of `channels` and the others as they
+/// are
+pub fn channel_reports(reports: &InstanceReports, channels: &[usize]) -> InstanceReports {
+    match reports {
+        Value::Object(reports) => {
+            Value::Object(reports.iter()
+                                 .map(|(name, value)| {
+                                     let value = match value {
+                                         Value::Array(values) => {
+                                             Value::Array(channels.iter()
+                                                                  .filter_map(|channel| values.get(*channel).cloned())
+                                                                  .collect())
+                                         }
+                                         other => other.clone(),
+                                     };
+
+                                     (name.clone(), value)
+                                 })
+                                 .collect())
+        }
+        other => other.clone(),
+    }
+}
+
+fn mask_channels(mask: ChannelMask) -> Vec<usize> {
+    match mask {
+        ChannelMask::Mono(start) => vec![start],
+        ChannelMask::Stereo(start) => vec![start, start + 1],
+    }
+}
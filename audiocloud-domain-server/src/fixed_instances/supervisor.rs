@@ -1,69 +1,318 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::time::Duration;
 
 use actix::fut::LocalBoxActorFuture;
-use actix::{fut, Actor, ActorFutureExt, Addr, Context, Handler, MessageResult, WrapFuture};
-use actix_broker::BrokerSubscribe;
+use actix::{
+    fut, Actor, ActorFutureExt, Addr, AsyncContext, Context, Handler, Message, MessageResult, Recipient, WrapFuture,
+};
+use actix_broker::{BrokerIssue, BrokerSubscribe};
 use anyhow::anyhow;
-use futures::executor::block_on;
+use futures::future::join_all;
+use futures::{stream, StreamExt};
 use tracing::*;
 
 use audiocloud_api::cloud::domains::{
     DomainConfig, DomainFixedInstanceConfig, FixedInstanceRouting, FixedInstanceRoutingMap,
 };
+use audiocloud_api::common::task::InstanceParameters;
 use audiocloud_api::domain::DomainError;
-use audiocloud_api::{hashmap_changes, FixedInstanceId, HashMapChanges};
+use audiocloud_api::{hashmap_changes, AppTaskId, FixedInstanceId, HashMapChanges, Model, TaskReservation, TaskSpec};
 
-use crate::config::NotifyDomainConfiguration;
+use crate::config::{NotifyDomainConfiguration, NotifyFixedInstanceRouting};
 use crate::db::Db;
+use crate::fixed_instances::calendar::calendar_entries;
 use crate::fixed_instances::instance::InstanceActor;
+use crate::fixed_instances::sharing::{check_claim, sorted_channels, task_instance_channels, InstanceClaim};
 use crate::fixed_instances::{
-    GetMultipleFixedInstanceState, NotifyInstancePowerChannelsChanged, NotifyInstanceState, SetDesiredPowerChannel,
-    SetInstanceDesiredPlayState, SetInstanceParameters,
+    instance_routing, CompositeInstanceConfig, CompositeInstances, FixedInstanceExtras, FixedInstanceOpts,
+    FixedInstanceSummary, GetInstanceCalendar, GetMultipleFixedInstanceState, InstanceCalendarEntry, InstanceInterest,
+    InstanceMaintenance, ListFixedInstances, ModelSharing, ModelSharingMap, NotifyFixedInstanceReports,
+    NotifyInstancePowerChannelsChanged, NotifyInstanceState, RegisterInstanceInterest, ReportDownsamplingMap,
+    ReportDownsamplingPolicies, ReserveFixedInstances, SetDesiredPowerChannel, SetInstanceChannelParameters,
+    SetInstanceDesiredPlayState, SetInstanceParameters, SetInstanceReportDownsampling,
 };
-use crate::DomainResult;
+use crate::tasks::{NotifyTaskDeleted, NotifyTaskReservation};
+use crate::{models, DomainResult};
 
 pub struct FixedInstancesSupervisor {
-    instances: HashMap<FixedInstanceId, SupervisedInstance>,
-    db:        Db,
+    instances:        HashMap<FixedInstanceId, SupervisedInstance>,
+    composites:       CompositeInstances,
+    sharing:          ModelSharingMap,
+    claims:           HashMap<FixedInstanceId, Vec<InstanceClaim>>,
+    maintenance:      InstanceMaintenance,
+    downsampling:     ReportDownsamplingMap,
+    db:               Db,
+    interest:         InstanceInterest<Recipient<NotifyInstanceState>>,
+    /// Fixed and composite instances whose state changed since it was last sent to those interested in it
+    changed:          HashSet<FixedInstanceId>,
+    /// Combined states of the composite instances, as they were last sent
+    composite_states: HashMap<FixedInstanceId, NotifyInstanceState>,
+    coalesce:         Duration,
 }
 
 struct SupervisedInstance {
     address: Addr<InstanceActor>,
     config:  DomainFixedInstanceConfig,
+    routing: Option<FixedInstanceRouting>,
     state:   Option<NotifyInstanceState>,
     // TODO: current parameters and last known reports should go here
 }
 
 impl FixedInstancesSupervisor {
-    pub async fn new(boot: &DomainConfig, db: Db) -> anyhow::Result<(FixedInstanceRoutingMap, Self)> {
-        let mut routing = HashMap::new();
+    pub async fn new(boot: &DomainConfig,
+                     extras: FixedInstanceExtras,
+                     db: Db,
+                     opts: &FixedInstanceOpts)
+                     -> anyhow::Result<(FixedInstanceRoutingMap, Self)> {
+        let timeout = Duration::from_millis(opts.instance_boot_timeout_ms);
+
+        let db_ref = &db;
+        let downsampling = &extras.report_downsampling;
+        let booted = stream::iter(&boot.fixed_instances).map(|(id, config)| async move {
+                                                            let policies = model_downsampling(downsampling, id);
+                                                            let booted =
+                                                                boot_instance(id, config, policies, db_ref, timeout);
+                                                            (id, config, booted.await)
+                                                        })
+                                                        .buffer_unordered(opts.instance_boot_concurrency.max(1))
+                                                        .collect::<Vec<_>>()
+                                                        .await;
+
         let mut instances = HashMap::new();
+        let mut failed = 0;
+
+        for (id, config, result) in booted {
+            match result {
+                Ok((model, actor)) => {
+                    instances.insert(id.clone(),
+                                     SupervisedInstance { address: { actor.start() },
+                                                          config:  { config.clone() },
+                                                          routing: { instance_routing(config, &model) },
+                                                          state:   None, });
+                }
+                Err(error) => {
+                    // left out instances are added again with the next domain config that lists them
+                    warn!(%id, %error, "Failed to set up instance, booting without it");
+                    failed += 1;
+                }
+            }
+        }
+
+        if failed > 0 {
+            warn!(booted = instances.len(), failed, "Some instances failed to set up");
+        } else {
+            info!(booted = instances.len(), "Instances set up");
+        }
 
-        for (id, config) in &boot.fixed_instances {
-            let model = db.get_model(&id.model_id())
-                          .await?
-                          .ok_or_else(|| anyhow!("Missing model for instance {id}"))?;
+        let coalesce = Duration::from_millis(opts.instance_state_coalesce_ms.max(1));
+
+        let mut supervisor = Self { db:               { db },
+                                    instances:        { instances },
+                                    composites:       { extras.composite_instances },
+                                    sharing:          { extras.model_sharing },
+                                    claims:           { HashMap::new() },
+                                    maintenance:      { extras.maintenance },
+                                    downsampling:     { extras.report_downsampling },
+                                    interest:         { InstanceInterest::default() },
+                                    changed:          { HashSet::new() },
+                                    composite_states: { HashMap::new() },
+                                    coalesce:         { coalesce }, };
+
+        // tasks from the config were reserved by the cloud, they hold their instances without checks
+        for (task_id, task) in &boot.tasks {
+            for (instance_id, claim) in supervisor.task_claims(task_id, &task.reservations, &task.spec) {
+                supervisor.claims.entry(instance_id).or_default().push(claim);
+            }
+        }
 
-            let send_count = model.inputs.len();
-            let return_count = model.outputs.len();
+        Ok((supervisor.routing(), supervisor))
+    }
+
+    /// Routing of the fixed instances, and of the composite instances whose first and last members are routed
+    fn routing(&self) -> FixedInstanceRoutingMap {
+        let mut routing = self.instances
+                              .iter()
+                              .filter_map(|(id, instance)| instance.routing.map(|routing| (id.clone(), routing)))
+                              .collect::<FixedInstanceRoutingMap>();
+
+        let composite_routing =
+            self.composites
+                .iter()
+                .filter_map(|(id, composite)| composite.routing(&routing).map(|routing| (id.clone(), routing)))
+                .collect::<Vec<_>>();
+
+        routing.extend(composite_routing);
+        routing
+    }
 
-            let actor = InstanceActor::new(id.clone(), config.clone(), model)?;
+    fn composite_state(&self,
+                       composite_id: &FixedInstanceId,
+                       composite: &CompositeInstanceConfig)
+                       -> Option<NotifyInstanceState> {
+        let states = composite.member_ids()
+                              .filter_map(|id| Some((id.clone(), self.instances.get(id)?.state.clone()?)))
+                              .collect();
+
+        composite.combine_states(composite_id, &states)
+    }
+
+    /// Last known state of a fixed instance, or the combined state of a composite instance
+    fn instance_state(&mut self, instance_id: &FixedInstanceId) -> Option<NotifyInstanceState> {
+        if let Some(instance) = self.instances.get(instance_id) {
+            return instance.state.clone();
+        }
 
-            if let (Some(input_start), Some(output_start)) = (config.input_start, config.output_start) {
-                routing.insert(id.clone(),
-                               FixedInstanceRouting { send_count:     { send_count },
-                                                      send_channel:   { output_start as usize },
-                                                      return_count:   { return_count },
-                                                      return_channel: { input_start as usize }, });
+        if let Some(state) = self.composite_states.get(instance_id) {
+            return Some(state.clone());
+        }
+
+        let state = self.composite_state(instance_id, self.composites.get(instance_id)?)?;
+        self.composite_states.insert(instance_id.clone(), state.clone());
+
+        Some(state)
+    }
+
+    fn instance_states(&mut self,
+                       instance_ids: &HashSet<FixedInstanceId>)
+                       -> HashMap<FixedInstanceId, NotifyInstanceState> {
+        instance_ids.iter()
+                    .filter_map(|id| Some((id.clone(), self.instance_state(id)?)))
+                    .collect()
+    }
+
+    /// Send the latest state of each instance that changed since the last time, once, to those interested in it
+    fn send_changed_states(&mut self, _ctx: &mut Context<Self>) {
+        if self.changed.is_empty() {
+            return;
+        }
+
+        self.interest.retain(Recipient::connected);
+
+        for instance_id in mem::take(&mut self.changed) {
+            let state = if let Some(composite) = self.composites.get(&instance_id) {
+                match self.composite_state(&instance_id, composite) {
+                    Some(state) => {
+                        self.composite_states.insert(instance_id.clone(), state.clone());
+                        self.issue_system_async(state.clone());
+                        state
+                    }
+                    None => {
+                        self.composite_states.remove(&instance_id);
+                        continue;
+                    }
+                }
+            } else {
+                match self.instances
+                          .get(&instance_id)
+                          .and_then(|instance| instance.state.clone())
+                {
+                    Some(state) => state,
+                    None => continue,
+                }
+            };
+
+            for recipient in self.interest.recipients_of(&instance_id) {
+                recipient.do_send(state.clone());
             }
+        }
+    }
 
-            instances.insert(id.clone(),
-                             SupervisedInstance { address: { actor.start() },
-                                                  config:  { config.clone() },
-                                                  state:   None, });
+    /// Instances a reserved instance stands for, the members of a composite or the instance itself
+    fn reserved_instance_ids(&self, instance_id: &FixedInstanceId) -> Vec<FixedInstanceId> {
+        match self.composites.get(instance_id) {
+            Some(composite) => composite.member_ids().cloned().collect(),
+            None => vec![instance_id.clone()],
         }
+    }
 
-        Ok((routing, Self { db, instances }))
+    /// Claims a task makes on the instances it reserves, on the members of the composite instances it reserves
+    fn task_claims(&self,
+                   task_id: &AppTaskId,
+                   reservation: &TaskReservation,
+                   spec: &TaskSpec)
+                   -> Vec<(FixedInstanceId, InstanceClaim)> {
+        let channels = task_instance_channels(spec);
+        let mut rv = vec![];
+
+        for reserved_id in &reservation.fixed_instances {
+            let claim = InstanceClaim { task_id:  { task_id.clone() },
+                                        from:     { reservation.from },
+                                        to:       { reservation.to },
+                                        channels: { channels.get(reserved_id).cloned().unwrap_or_default() }, };
+
+            for instance_id in self.reserved_instance_ids(reserved_id) {
+                rv.push((instance_id, claim.clone()));
+            }
+        }
+
+        rv
+    }
+
+    fn release_claims(&mut self, task_id: &AppTaskId) {
+        for claims in self.claims.values_mut() {
+            claims.retain(|claim| &claim.task_id != task_id);
+        }
+
+        self.claims.retain(|_, claims| !claims.is_empty());
+    }
+
+    /// Parameters a task writes to an instance shared per channel are for the channels the task claimed
+    fn channel_parameters(&self,
+                          instance_id: FixedInstanceId,
+                          parameters: InstanceParameters,
+                          task_id: Option<&AppTaskId>)
+                          -> SetInstanceChannelParameters {
+        let per_channel = self.sharing.get(&instance_id.model_id()) == Some(&ModelSharing::PerChannel);
+
+        let channels = task_id.filter(|_| per_channel)
+                              .and_then(|task_id| {
+                                  self.claims
+                                      .get(&instance_id)?
+                                      .iter()
+                                      .find(|claim| &claim.task_id == task_id)
+                              })
+                              .filter(|claim| !claim.channels.is_empty())
+                              .map(|claim| sorted_channels(&claim.channels));
+
+        SetInstanceChannelParameters { instance_id: { instance_id },
+                                       parameters:  { parameters },
+                                       channels:    { channels }, }
+    }
+
+    /// Send a message to members of a composite instance, failing with the first member that fails
+    fn send_to_members<M>(&self,
+                          messages: Vec<(FixedInstanceId, M)>,
+                          operation: &'static str)
+                          -> LocalBoxActorFuture<Self, DomainResult>
+        where M: Message<Result = DomainResult> + Send + 'static,
+              InstanceActor: Handler<M>
+    {
+        let mut sends = vec![];
+
+        for (member_id, msg) in messages {
+            match self.instances.get(&member_id) {
+                Some(member) => sends.push(member.address.send(msg)),
+                None => return fut::err(DomainError::InstanceNotFound { instance_id: member_id }).boxed_local(),
+            }
+        }
+
+        join_all(sends).into_actor(self)
+                       .map(move |results, _actor, _ctx| {
+                           for result in results {
+                               match result {
+                                   Ok(Ok(())) => {}
+                                   Ok(Err(error)) => return Err(error),
+                                   Err(err) => {
+                                       return Err(DomainError::BadGateway { error: format!("Failed to {operation}: \
+                                                                                            {err}"), })
+                                   }
+                               }
+                           }
+
+                           Ok(())
+                       })
+                       .boxed_local()
     }
 }
 
@@ -74,14 +323,51 @@ impl Actor for FixedInstancesSupervisor {
     fn started(&mut self, ctx: &mut Self::Context) {
         self.subscribe_system_async::<NotifyDomainConfiguration>(ctx);
         self.subscribe_system_async::<NotifyInstancePowerChannelsChanged>(ctx);
+        self.subscribe_system_async::<NotifyInstanceState>(ctx);
+        self.subscribe_system_async::<NotifyFixedInstanceReports>(ctx);
+        self.subscribe_system_async::<NotifyTaskReservation>(ctx);
+        self.subscribe_system_async::<NotifyTaskDeleted>(ctx);
+
+        ctx.run_interval(self.coalesce, Self::send_changed_states);
     }
 }
 
+/// Create the actor of an instance, with its model from memory or from the database if it was not cached
+async fn boot_instance(id: &FixedInstanceId,
+                       config: &DomainFixedInstanceConfig,
+                       downsampling: ReportDownsamplingPolicies,
+                       db: &Db,
+                       timeout: Duration)
+                       -> anyhow::Result<(Model, InstanceActor)> {
+    let model = async {
+        match models::cached_model(&id.model_id()) {
+            Some(model) => Ok(model),
+            None => db.get_model(&id.model_id())
+                      .await?
+                      .ok_or_else(|| anyhow!("Missing model for instance {id}")),
+        }
+    };
+
+    let model = tokio::time::timeout(timeout, model).await
+                                                    .map_err(|_| anyhow!("Timed out after {timeout:?}"))??;
+
+    let actor = InstanceActor::new(id.clone(), config.clone(), model.clone(), downsampling)?;
+
+    Ok((model, actor))
+}
+
+/// Report downsampling of the model of an instance, none if its model has none configured
+fn model_downsampling(downsampling: &ReportDownsamplingMap, id: &FixedInstanceId) -> ReportDownsamplingPolicies {
+    downsampling.get(&id.model_id()).cloned().unwrap_or_default()
+}
+
 impl Handler<NotifyDomainConfiguration> for FixedInstancesSupervisor {
     type Result = ();
 
     #[instrument(skip_all, name = "handle_notify_domain_configuration")]
     fn handle(&mut self, msg: NotifyDomainConfiguration, _ctx: &mut Self::Context) -> Self::Result {
+        let previous_routing = self.routing();
+
         let existing = self.instances
                            .iter()
                            .map(|(id, instance)| (id.clone(), instance.config.clone()))
@@ -95,15 +381,32 @@ impl Handler<NotifyDomainConfiguration> for FixedInstancesSupervisor {
             self.instances.remove(&id);
         }
 
+        if msg.extras.report_downsampling != self.downsampling {
+            self.downsampling = msg.extras.report_downsampling;
+
+            for (id, instance) in &self.instances {
+                instance.address
+                        .do_send(SetInstanceReportDownsampling { instance_id: { id.clone() },
+                                                                 policies:    {
+                                                                     model_downsampling(&self.downsampling, id)
+                                                                 }, });
+            }
+        }
+
         for (id, config) in added {
-            if let Ok(Some(model)) = block_on(self.db.get_model(&id.model_id())) {
-                match InstanceActor::new(id.clone(), config.clone(), model) {
+            if let Some(model) = models::cached_model(&id.model_id()) {
+                let routing = instance_routing(&config, &model);
+
+                let downsampling = model_downsampling(&self.downsampling, &id);
+
+                match InstanceActor::new(id.clone(), config.clone(), model, downsampling) {
                     Ok(actor) => {
                         let address = actor.start();
 
                         self.instances.insert(id.clone(),
                                               SupervisedInstance { address: { address },
                                                                    config:  { config },
+                                                                   routing: { routing },
                                                                    state:   { None }, });
                     }
                     Err(error) => {
@@ -115,10 +418,26 @@ impl Handler<NotifyDomainConfiguration> for FixedInstancesSupervisor {
 
         for (id, config) in changed {
             if let Some(instance) = self.instances.get_mut(&id) {
+                if let Some(model) = models::cached_model(&id.model_id()) {
+                    instance.routing = instance_routing(&config, &model);
+                }
+
                 instance.config = config;
                 // TODO: set configuration of instance actor
             }
         }
+
+        self.composites = msg.extras.composite_instances;
+        self.composite_states.clear();
+        self.changed.extend(self.composites.keys().cloned());
+        self.sharing = msg.extras.model_sharing;
+        self.maintenance = msg.extras.maintenance;
+
+        let routing = self.routing();
+        if routing != previous_routing {
+            info!("Fixed instance routing changed");
+            self.issue_system_async(NotifyFixedInstanceRouting { routing });
+        }
     }
 }
 
@@ -126,9 +445,27 @@ impl Handler<SetInstanceParameters> for FixedInstancesSupervisor {
     type Result = LocalBoxActorFuture<Self, DomainResult>;
 
     fn handle(&mut self, msg: SetInstanceParameters, _ctx: &mut Context<FixedInstancesSupervisor>) -> Self::Result {
+        let task_id = msg.task_id;
+
+        if let Some(composite) = self.composites.get(&msg.instance_id) {
+            return match composite.split_parameters(&msg.instance_id, msg.parameters) {
+                Ok(parameters) => {
+                    let messages = parameters.into_iter()
+                                             .map(|(instance_id, parameters)| {
+                                                 (instance_id.clone(),
+                                                  self.channel_parameters(instance_id, parameters, task_id.as_ref()))
+                                             })
+                                             .collect();
+
+                    self.send_to_members(messages, "set instance parameters")
+                }
+                Err(error) => fut::err(error).boxed_local(),
+            };
+        }
+
         if let Some(instance) = self.instances.get(&msg.instance_id) {
             instance.address
-                    .send(msg)
+                    .send(self.channel_parameters(msg.instance_id, msg.parameters, task_id.as_ref()))
                     .into_actor(self)
                     .map(|res, _actor, _ctx| match res {
                         Ok(res) => res,
@@ -168,6 +505,31 @@ impl Handler<SetInstanceDesiredPlayState> for FixedInstancesSupervisor {
     type Result = LocalBoxActorFuture<Self, DomainResult>;
 
     fn handle(&mut self, msg: SetInstanceDesiredPlayState, _ctx: &mut Self::Context) -> Self::Result {
+        if let Some(composite) = self.composites.get(&msg.instance_id) {
+            // only members with media play, the others process whatever passes through them
+            let messages = composite.member_ids()
+                                    .filter(|id| {
+                                        self.instances
+                                            .get(id)
+                                            .map(|instance| instance.config.media.is_some())
+                                            .unwrap_or(true)
+                                    })
+                                    .map(|id| {
+                                        (id.clone(),
+                                         SetInstanceDesiredPlayState { instance_id: { id.clone() },
+                                                                       desired:     { msg.desired.clone() }, })
+                                    })
+                                    .collect::<Vec<_>>();
+
+            return if messages.is_empty() {
+                fut::err(DomainError::InstanceNotCapable { instance_id: { msg.instance_id },
+                                                           operation:   { format!("SetInstanceDesiredPlayState") }, })
+                .boxed_local()
+            } else {
+                self.send_to_members(messages, "set instance desired play state")
+            };
+        }
+
         if let Some(instance) = self.instances.get(&msg.instance_id) {
             instance.address
         .send(msg)
@@ -183,24 +545,179 @@ impl Handler<SetInstanceDesiredPlayState> for FixedInstancesSupervisor {
     }
 }
 
+impl Handler<ReserveFixedInstances> for FixedInstancesSupervisor {
+    type Result = DomainResult;
+
+    fn handle(&mut self, msg: ReserveFixedInstances, _ctx: &mut Self::Context) -> Self::Result {
+        let claims = self.task_claims(&msg.task_id, &msg.reservation, &msg.spec);
+
+        for (instance_id, claim) in &claims {
+            let sharing = self.sharing.get(&instance_id.model_id()).copied().unwrap_or_default();
+            let held = self.claims.get(instance_id).map(Vec::as_slice).unwrap_or_default();
+
+            check_claim(instance_id, sharing, claim, held)?;
+
+            let windows = self.maintenance.get(instance_id).map(Vec::as_slice).unwrap_or_default();
+            if let Some(window) = windows.iter().find(|window| window.overlaps(claim.from, claim.to)) {
+                return Err(DomainError::InstanceNotCapable { instance_id: { instance_id.clone() },
+                                                             operation:   {
+                                                                 format!("reservation during maintenance ({})",
+                                                                         window.reason)
+                                                             }, });
+            }
+        }
+
+        self.release_claims(&msg.task_id);
+
+        for (instance_id, claim) in claims {
+            self.claims.entry(instance_id).or_default().push(claim);
+        }
+
+        Ok(())
+    }
+}
+
+impl Handler<NotifyTaskReservation> for FixedInstancesSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskReservation, _ctx: &mut Self::Context) -> Self::Result {
+        for claim in self.claims
+                         .values_mut()
+                         .flat_map(|claims| claims.iter_mut())
+                         .filter(|claim| claim.task_id == msg.task_id)
+        {
+            claim.from = msg.reservation.from;
+            claim.to = msg.reservation.to;
+        }
+    }
+}
+
+impl Handler<NotifyTaskDeleted> for FixedInstancesSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskDeleted, _ctx: &mut Self::Context) -> Self::Result {
+        self.release_claims(&msg.task_id);
+    }
+}
+
+impl Handler<GetInstanceCalendar> for FixedInstancesSupervisor {
+    type Result = DomainResult<Vec<InstanceCalendarEntry>>;
+
+    fn handle(&mut self, msg: GetInstanceCalendar, _ctx: &mut Self::Context) -> Self::Result {
+        if !self.instances.contains_key(&msg.instance_id) && !self.composites.contains_key(&msg.instance_id) {
+            return Err(DomainError::InstanceNotFound { instance_id: msg.instance_id, });
+        }
+
+        // a composite is busy whenever one of its members is
+        let mut instance_ids = self.reserved_instance_ids(&msg.instance_id);
+        if !instance_ids.contains(&msg.instance_id) {
+            instance_ids.push(msg.instance_id.clone());
+        }
+
+        let claims = instance_ids.iter()
+                                 .filter_map(|id| self.claims.get(id))
+                                 .flat_map(|claims| claims.iter());
+
+        let maintenance = instance_ids.iter()
+                                      .filter_map(|id| self.maintenance.get(id))
+                                      .flat_map(|windows| windows.iter());
+
+        Ok(calendar_entries(claims, maintenance, msg.query.from, msg.query.to))
+    }
+}
+
 impl Handler<GetMultipleFixedInstanceState> for FixedInstancesSupervisor {
     type Result = MessageResult<GetMultipleFixedInstanceState>;
 
     fn handle(&mut self, msg: GetMultipleFixedInstanceState, _ctx: &mut Self::Context) -> Self::Result {
-        let mut rv = HashMap::new();
+        MessageResult(self.instance_states(&msg.instance_ids))
+    }
+}
 
-        for id in msg.instance_ids {
-            if let Some(instance) = self.instances.get(&id) {
-                if let Some(state) = instance.state.clone() {
-                    rv.insert(id.clone(), state);
-                }
-            }
-        }
+impl Handler<RegisterInstanceInterest> for FixedInstancesSupervisor {
+    type Result = MessageResult<RegisterInstanceInterest>;
+
+    fn handle(&mut self, msg: RegisterInstanceInterest, _ctx: &mut Self::Context) -> Self::Result {
+        let states = self.instance_states(&msg.instance_ids);
+        self.interest.register(msg.subscriber, msg.instance_ids, msg.recipient);
+
+        MessageResult(states)
+    }
+}
+
+impl Handler<ListFixedInstances> for FixedInstancesSupervisor {
+    type Result = MessageResult<ListFixedInstances>;
+
+    fn handle(&mut self, _msg: ListFixedInstances, _ctx: &mut Self::Context) -> Self::Result {
+        let composite_ids = self.composites.keys().cloned().collect::<Vec<_>>();
+        let composite_states = composite_ids.iter()
+                                            .map(|id| (id, self.instance_state(id)))
+                                            .collect::<Vec<_>>();
+
+        let mut rv = self.instances
+                         .iter()
+                         .map(|(id, instance)| (id, instance.state.clone()))
+                         .chain(composite_states)
+                         .map(|(id, state)| {
+                             let state = state.as_ref();
+                             FixedInstanceSummary { instance_id: { id.clone() },
+                                                    connected:   {
+                                                        state.map(|state| *state.connected.value()).unwrap_or_default()
+                                                    },
+                                                    power:       { state.and_then(|state| state.power.clone()) },
+                                                    play:        { state.and_then(|state| state.play.clone()) }, }
+                         })
+                         .collect::<Vec<_>>();
+
+        rv.sort_by(|a, b| a.instance_id.to_string().cmp(&b.instance_id.to_string()));
 
         MessageResult(rv)
     }
 }
 
+impl Handler<NotifyInstanceState> for FixedInstancesSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyInstanceState, _ctx: &mut Self::Context) -> Self::Result {
+        let instance_id = msg.instance_id.clone();
+
+        if let Some(instance) = self.instances.get_mut(&instance_id) {
+            instance.state = Some(msg);
+        } else {
+            return;
+        }
+
+        // composites are combined again when the changes are sent, once for all of their members that changed
+        let composite_ids = self.composites
+                                .iter()
+                                .filter(|(_, composite)| composite.contains(&instance_id))
+                                .map(|(id, _)| id.clone())
+                                .collect::<Vec<_>>();
+
+        self.changed.insert(instance_id);
+        self.changed.extend(composite_ids);
+    }
+}
+
+impl Handler<NotifyFixedInstanceReports> for FixedInstancesSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyFixedInstanceReports, _ctx: &mut Self::Context) -> Self::Result {
+        let reports = self.composites
+                          .iter()
+                          .filter_map(|(id, composite)| {
+                              composite.prefix_reports(&msg.instance_id, msg.reports.clone())
+                                       .map(|reports| NotifyFixedInstanceReports { instance_id: { id.clone() },
+                                                                                   reports:     { reports }, })
+                          })
+                          .collect::<Vec<_>>();
+
+        for reports in reports {
+            self.issue_system_async(reports);
+        }
+    }
+}
+
 impl Handler<NotifyInstancePowerChannelsChanged> for FixedInstancesSupervisor {
     type Result = ();
 
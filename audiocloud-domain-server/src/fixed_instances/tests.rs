@@ -0,0 +1,259 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use chrono::{TimeZone, Utc};
+use serde_json::json;
+
+use audiocloud_api::cloud::domains::FixedInstanceRouting;
+use audiocloud_api::{AppId, AppTaskId, FixedInstanceId, ModelId, TaskId};
+
+use crate::fixed_instances::calendar::calendar_entries;
+use crate::fixed_instances::sharing::{check_claim, merge_channel_parameters, InstanceClaim};
+use crate::fixed_instances::{
+    aggregate_values, channel_reports, to_ical, CompositeInstanceConfig, FixedInstanceExtras,
+    InstanceCalendarEntryKind, InstanceInterest, MaintenanceWindow, ModelSharing, ReportAggregate, ReportDownsampler,
+};
+
+fn instance(model: &str) -> FixedInstanceId {
+    FixedInstanceId::new("distopik".to_owned(), model.to_owned(), "1".to_owned())
+}
+
+fn extras() -> FixedInstanceExtras {
+    serde_json::from_value(json!({
+                               "composite_instances": {
+                                   "distopik/strip/1": {
+                                       "members": [
+                                           { "instance_id": "distopik/pre73/1", "prefix": "pre" },
+                                           { "instance_id": "distopik/dual1084/1", "prefix": "eq" },
+                                           { "instance_id": "distopik/la2a/1", "prefix": "comp" }
+                                       ]
+                                   }
+                               },
+                               "model_sharing": {
+                                   "distopik/dual1084": "per_channel",
+                                   "distopik/la2a": "exclusive"
+                               },
+                               "report_downsampling": {
+                                   "distopik/la2a": {
+                                       "gain_reduction": { "interval_ms": 200, "aggregate": "max" }
+                                   }
+                               }
+                           })).expect("fixed instance extras parse")
+}
+
+fn channel_strip() -> (FixedInstanceId, CompositeInstanceConfig) {
+    extras().composite_instances
+            .into_iter()
+            .next()
+            .expect("one composite instance")
+}
+
+#[test]
+fn test_composite_parameters_are_split_by_prefix() {
+    let (id, strip) = channel_strip();
+
+    let split = strip.split_parameters(&id,
+                                       json!({
+                                           "pre.gain": [10],
+                                           "eq.low_gain": [2, 2],
+                                           "eq.high_gain": [-1, -1],
+                                       }))
+                     .expect("parameters split");
+
+    assert_eq!(split,
+               HashMap::from([(instance("pre73"), json!({ "gain": [10] })),
+                              (instance("dual1084"), json!({ "low_gain": [2, 2], "high_gain": [-1, -1] }))]));
+
+    assert!(strip.split_parameters(&id, json!({ "gain": [10] })).is_err(),
+            "parameters without a prefix are rejected");
+    assert!(strip.split_parameters(&id, json!({ "limiter.gain": [10] })).is_err(),
+            "parameters of unknown members are rejected");
+}
+
+#[test]
+fn test_composite_reports_are_prefixed() {
+    let (_, strip) = channel_strip();
+
+    assert_eq!(strip.prefix_reports(&instance("la2a"), json!({ "gain_reduction": [3.5] })),
+               Some(json!({ "comp.gain_reduction": [3.5] })));
+    assert_eq!(strip.prefix_reports(&instance("summatra"), json!({ "level": [0] })),
+               None);
+}
+
+#[test]
+fn test_composite_routing_spans_members() {
+    let (_, strip) = channel_strip();
+
+    let routing = |send_channel, return_channel| FixedInstanceRouting { send_count:     2,
+                                                                        send_channel:   send_channel,
+                                                                        return_count:   2,
+                                                                        return_channel: return_channel, };
+
+    let mut routed = HashMap::from([(instance("pre73"), routing(0, 0)),
+                                    (instance("dual1084"), routing(2, 2))]);
+    assert_eq!(strip.routing(&routed), None, "all of the chain must be routed");
+
+    routed.insert(instance("la2a"), routing(4, 4));
+    assert_eq!(strip.routing(&routed), Some(routing(0, 4)));
+}
+
+fn claim(task: &str, from_hour: u32, to_hour: u32, channels: &[usize]) -> InstanceClaim {
+    InstanceClaim { task_id:  AppTaskId::new(AppId::test(), TaskId::new(task.to_owned())),
+                    from:     Utc.with_ymd_and_hms(2022, 10, 21, from_hour, 0, 0).unwrap(),
+                    to:       Utc.with_ymd_and_hms(2022, 10, 21, to_hour, 0, 0).unwrap(),
+                    channels: channels.iter().copied().collect::<HashSet<_>>(), }
+}
+
+#[test]
+fn test_model_sharing_is_enforced_on_overlapping_claims() {
+    let sharing = extras().model_sharing;
+    let model = |name: &str| ModelId::new("distopik".to_owned(), name.to_owned());
+
+    assert_eq!(sharing.get(&model("dual1084")), Some(&ModelSharing::PerChannel));
+    assert_eq!(sharing.get(&model("la2a")), Some(&ModelSharing::Exclusive));
+    assert_eq!(sharing.get(&model("pre73")).copied().unwrap_or_default(),
+               ModelSharing::Shared);
+
+    let held = vec![claim("left", 10, 12, &[0])];
+    let id = instance("dual1084");
+
+    assert!(check_claim(&id, ModelSharing::PerChannel, &claim("right", 11, 13, &[1]), &held).is_ok());
+    assert!(check_claim(&id,
+                        ModelSharing::PerChannel,
+                        &claim("also-left", 11, 13, &[0, 1]),
+                        &held).is_err());
+    assert!(check_claim(&id, ModelSharing::PerChannel, &claim("unconnected", 11, 13, &[]), &held).is_err(),
+            "tasks that connect no channels hold the whole instance");
+    assert!(check_claim(&id, ModelSharing::Exclusive, &claim("right", 11, 13, &[1]), &held).is_err());
+    assert!(check_claim(&id, ModelSharing::Exclusive, &claim("later", 12, 14, &[0]), &held).is_ok(),
+            "back to back reservations do not overlap");
+    assert!(check_claim(&id, ModelSharing::Exclusive, &claim("left", 11, 13, &[0]), &held).is_ok(),
+            "tasks do not conflict with themselves");
+    assert!(check_claim(&id, ModelSharing::Shared, &claim("right", 11, 13, &[0]), &held).is_ok());
+}
+
+#[test]
+fn test_channel_parameters_and_reports_are_scoped_to_task_channels() {
+    let mut parameters = json!({ "threshold": [-10, -10], "ratio": [4, 4] });
+
+    merge_channel_parameters(&mut parameters, json!({ "threshold": [-20] }), &[1]).expect("per channel parameter");
+    assert_eq!(parameters, json!({ "threshold": [-10, -20], "ratio": [4, 4] }));
+
+    merge_channel_parameters(&mut parameters, json!({ "release": [0.3] }), &[1]).expect("new per channel parameter");
+    assert_eq!(parameters["release"], json!([null, 0.3]));
+
+    assert_eq!(merge_channel_parameters(&mut parameters, json!({ "link": true }), &[1]),
+               Err("link".to_owned()),
+               "parameters for all channels are refused");
+    assert_eq!(merge_channel_parameters(&mut parameters, json!({ "ratio": [2, 2] }), &[1]),
+               Err("ratio".to_owned()),
+               "values beyond the channels of the task are refused");
+    assert_eq!(parameters["ratio"], json!([4, 4]));
+
+    let reports = json!({ "gain_reduction": [3.5, 1.0], "temperature": 40 });
+    assert_eq!(channel_reports(&reports, &[1]),
+               json!({ "gain_reduction": [1.0], "temperature": 40 }));
+}
+
+#[test]
+fn test_calendar_lists_tasks_once_and_exports_ical() {
+    let at = |hour| Utc.with_ymd_and_hms(2022, 10, 21, hour, 0, 0).unwrap();
+
+    // the same task claims two members of a composite
+    let claims = vec![claim("late", 14, 16, &[]),
+                      claim("mix", 10, 12, &[0, 1]),
+                      claim("mix", 10, 12, &[0, 1])];
+    let maintenance = vec![MaintenanceWindow { from:   at(6),
+                                               to:     at(8),
+                                               reason: "Tube replacement, left channel".to_owned(), }];
+
+    let entries = calendar_entries(claims.iter(), maintenance.iter(), None, None);
+    assert_eq!(entries.iter().map(|entry| entry.kind).collect::<Vec<_>>(),
+               vec![InstanceCalendarEntryKind::Maintenance,
+                    InstanceCalendarEntryKind::Reservation,
+                    InstanceCalendarEntryKind::Reservation]);
+    assert_eq!(entries[1].channels, vec![0, 1]);
+
+    let entries = calendar_entries(claims.iter(), maintenance.iter(), Some(at(8)), Some(at(14)));
+    assert_eq!(entries.len(),
+               1,
+               "entries touching the range only at its ends are not listed");
+    assert_eq!(entries[0].task_id, Some(claims[1].task_id.clone()));
+
+    let entries = calendar_entries(claims.iter(), maintenance.iter(), None, Some(at(9)));
+    let ical = to_ical(&instance("la2a"), &entries, at(0));
+
+    assert!(ical.starts_with("BEGIN:VCALENDAR\r\n"));
+    assert!(ical.ends_with("END:VCALENDAR\r\n"));
+    assert!(ical.contains("DTSTART:20221021T060000Z\r\n"));
+    assert!(ical.contains("DTEND:20221021T080000Z\r\n"));
+    assert!(ical.contains("Tube replacement\\, left channel"),
+            "text values are escaped");
+    assert!(ical.split("\r\n").all(|line| line.len() <= 75), "long lines are folded");
+}
+
+#[test]
+fn test_instance_interest_sends_only_to_interested_subscribers() {
+    let mut interest = InstanceInterest::default();
+
+    interest.register("task:a".to_owned(),
+                      HashSet::from([instance("la2a"), instance("pre73")]),
+                      "a");
+    interest.register("task:b".to_owned(), HashSet::from([instance("la2a")]), "b");
+
+    let mut la2a = interest.recipients_of(&instance("la2a")).copied().collect::<Vec<_>>();
+    la2a.sort();
+    assert_eq!(la2a, vec!["a", "b"]);
+    assert_eq!(interest.recipients_of(&instance("dual1084")).count(), 0);
+
+    // registering again replaces what the subscriber is interested in
+    interest.register("task:a".to_owned(), HashSet::from([instance("dual1084")]), "a");
+    assert_eq!(interest.recipients_of(&instance("pre73")).count(), 0);
+    assert_eq!(interest.recipients_of(&instance("dual1084"))
+                       .copied()
+                       .collect::<Vec<_>>(),
+               vec!["a"]);
+
+    interest.retain(|recipient| *recipient != "b");
+    assert_eq!(interest.recipients_of(&instance("la2a")).count(), 0);
+
+    // nothing to be interested in unregisters
+    interest.register("task:a".to_owned(), HashSet::new(), "a");
+    assert!(interest.is_empty());
+}
+
+#[test]
+fn test_report_values_aggregate_per_channel() {
+    let values = [json!([0.5, 2.0]), json!([1.5, 1.0]), json!([1.0])];
+
+    assert_eq!(aggregate_values(ReportAggregate::Max, &values), json!([1.5, 2.0]));
+    assert_eq!(aggregate_values(ReportAggregate::Avg, &values), json!([1.0, 1.5]));
+    assert_eq!(aggregate_values(ReportAggregate::Last, &values), json!([1.0]));
+
+    // values that are not numbers can only be taken as they were last reported
+    assert_eq!(aggregate_values(ReportAggregate::Max, &[json!("a"), json!("b")]),
+               json!("b"));
+}
+
+#[test]
+fn test_report_downsampler_sends_reports_once_per_interval() {
+    let policies = extras().report_downsampling
+                           .remove(&ModelId::new("distopik".to_owned(), "la2a".to_owned()))
+                           .expect("la2a downsampling");
+
+    let mut downsampler = ReportDownsampler::new(policies);
+    let start = Instant::now();
+    let after = |ms| start + Duration::from_millis(ms);
+
+    // reports without a policy are sent on right away
+    assert_eq!(downsampler.push(json!({ "gain_reduction": [3.0], "output": [0.5] }), after(0)),
+               Some(json!({ "output": [0.5] })));
+    assert_eq!(downsampler.push(json!({ "gain_reduction": [6.0] }), after(20)), None);
+    assert_eq!(downsampler.push(json!({ "gain_reduction": [4.0] }), after(40)), None);
+    assert_eq!(downsampler.flush_due(after(100)), None);
+
+    assert_eq!(downsampler.flush_due(after(200)),
+               Some(json!({ "gain_reduction": [6.0] })),
+               "the highest value of the interval is sent");
+    assert_eq!(downsampler.flush_due(after(400)), None, "nothing was reported since");
+}
@@ -0,0 +1,16 @@
+use actix::Message;
+
+use crate::incidents::{Incident, IncidentId};
+use crate::DomainResult;
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<Vec<Incident>>")]
+pub struct ListIncidents {
+    pub limit: usize,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<Option<Incident>>")]
+pub struct GetIncident {
+    pub incident_id: IncidentId,
+}
@@ -0,0 +1,136 @@
+use actix::{Actor, Addr};
+use anyhow::anyhow;
+use clap::Args;
+use derive_more::{Display, From, FromStr};
+use itertools::Itertools;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use tracing::*;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use audiocloud_api::{now, AppTaskId, ClientSocketId, EngineId, FixedInstanceId, Timestamp};
+pub use messages::*;
+use supervisor::IncidentsSupervisor;
+
+use crate::db::Db;
+
+pub mod messages;
+mod supervisor;
+
+static INCIDENTS_SUPERVISOR: OnceCell<Addr<IncidentsSupervisor>> = OnceCell::new();
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Display, Hash, From, FromStr, Serialize, Deserialize)]
+#[repr(transparent)]
+pub struct IncidentId(Uuid);
+
+impl IncidentId {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+/// Related events grouped together in time, i.e. a driver disconnect followed by task errors and client disconnects
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct Incident {
+    #[schema(value_type = String)]
+    pub id:         IncidentId,
+    #[schema(value_type = String)]
+    pub opened_at:  Timestamp,
+    #[schema(value_type = String)]
+    pub updated_at: Timestamp,
+    #[schema(value_type = Option<String>)]
+    pub closed_at:  Option<Timestamp>,
+    pub summary:    String,
+    pub timeline:   Vec<IncidentEntry>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct IncidentEntry {
+    #[schema(value_type = String)]
+    pub at:      Timestamp,
+    #[schema(value_type = Object)]
+    pub source:  IncidentSource,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum IncidentSource {
+    Instance(FixedInstanceId),
+    Task(AppTaskId),
+    Socket(ClientSocketId),
+    Engine(EngineId),
+}
+
+impl IncidentSource {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            IncidentSource::Instance(_) => "instance",
+            IncidentSource::Task(_) => "task",
+            IncidentSource::Socket(_) => "socket",
+            IncidentSource::Engine(_) => "engine",
+        }
+    }
+}
+
+impl Incident {
+    pub fn new(entry: IncidentEntry) -> Self {
+        let mut rv = Self { id:         { IncidentId::new() },
+                            opened_at:  { entry.at },
+                            updated_at: { entry.at },
+                            closed_at:  { None },
+                            summary:    { String::new() },
+                            timeline:   { vec![] }, };
+
+        rv.push(entry);
+
+        rv
+    }
+
+    pub fn push(&mut self, entry: IncidentEntry) {
+        self.updated_at = entry.at;
+        self.timeline.push(entry);
+        self.summary = self.digest();
+    }
+
+    pub fn close(&mut self) {
+        self.closed_at = Some(now());
+    }
+
+    /// Produce a one-line digest such as `instance (1) → task (3) → socket (2)` by counting consecutive events per source
+    pub fn digest(&self) -> String {
+        self.timeline
+            .iter()
+            .map(|entry| entry.source.kind())
+            .dedup_with_count()
+            .map(|(count, kind)| format!("{kind} ({count})"))
+            .join(" → ")
+    }
+}
+
+#[derive(Args, Clone, Copy, Debug)]
+pub struct IncidentOpts {
+    /// Events occurring within this many seconds of the previous event are grouped into the same incident
+    #[clap(long, env, default_value = "60")]
+    pub incident_grouping_seconds: u64,
+
+    /// Maximum number of events recorded in a single incident timeline, further events are only counted in the log
+    #[clap(long, env, default_value = "500")]
+    pub incident_max_entries: usize,
+}
+
+#[instrument(skip_all, err)]
+pub fn init(db: Db, opts: IncidentOpts) -> anyhow::Result<()> {
+    let supervisor = IncidentsSupervisor::new(db, opts);
+
+    INCIDENTS_SUPERVISOR.set(supervisor.start())
+                        .map_err(|_| anyhow!("Incidents supervisor already initialized"))?;
+
+    Ok(())
+}
+
+pub fn get_incidents_supervisor() -> &'static Addr<IncidentsSupervisor> {
+    INCIDENTS_SUPERVISOR.get()
+                        .expect("Incidents supervisor not initialized")
+}
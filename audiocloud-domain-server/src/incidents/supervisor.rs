@@ -0,0 +1,198 @@
+#![allow(unused_variables)]
+
+use std::time::Duration;
+
+use actix::{Actor, AsyncContext, Context, ContextFutureSpawner, Handler, ResponseFuture, WrapFuture};
+use actix_broker::BrokerSubscribe;
+use tracing::*;
+
+use audiocloud_api::audio_engine::EngineEvent;
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::now;
+
+use crate::db::Db;
+use crate::fixed_instances::{NotifyInstanceError, NotifyInstanceState};
+use crate::incidents::{GetIncident, Incident, IncidentEntry, IncidentOpts, IncidentSource, ListIncidents};
+use crate::sockets::NotifySocketDropped;
+use crate::tasks::{NotifyEngineClockLost, NotifyEngineEvent};
+use crate::DomainResult;
+
+pub struct IncidentsSupervisor {
+    db:   Db,
+    opts: IncidentOpts,
+    open: Option<Incident>,
+}
+
+impl IncidentsSupervisor {
+    pub fn new(db: Db, opts: IncidentOpts) -> Self {
+        Self { db:   { db },
+               opts: { opts },
+               open: { None }, }
+    }
+
+    fn grouping_window(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.opts.incident_grouping_seconds as i64)
+    }
+
+    #[instrument(skip(self, ctx))]
+    fn record(&mut self, source: IncidentSource, message: String, ctx: &mut Context<Self>) {
+        let entry = IncidentEntry { at:      { now() },
+                                    source:  { source },
+                                    message: { message }, };
+
+        self.close_if_stale(ctx);
+
+        match self.open.as_mut() {
+            Some(incident) if incident.timeline.len() >= self.opts.incident_max_entries => {
+                warn!(id = %incident.id, "Incident timeline full, dropping entry");
+                return;
+            }
+            Some(incident) => incident.push(entry),
+            None => {
+                let incident = Incident::new(entry);
+                info!(id = %incident.id, "Incident opened");
+                self.open = Some(incident);
+            }
+        }
+
+        if let Some(incident) = self.open.clone() {
+            self.save(incident, ctx);
+        }
+    }
+
+    fn close_if_stale(&mut self, ctx: &mut Context<Self>) {
+        let window = self.grouping_window();
+        let stale = self.open
+                        .as_ref()
+                        .map(|incident| now() - incident.updated_at > window)
+                        .unwrap_or(false);
+
+        if stale {
+            if let Some(mut incident) = self.open.take() {
+                incident.close();
+                info!(id = %incident.id, summary = %incident.summary, "Incident closed");
+                self.save(incident, ctx);
+            }
+        }
+    }
+
+    fn save(&self, incident: Incident, ctx: &mut Context<Self>) {
+        let db = self.db.clone();
+        async move {
+            if let Err(error) = db.save_incident(&incident).await {
+                warn!(%error, id = %incident.id, "Failed to save incident");
+            }
+        }.into_actor(self)
+         .spawn(ctx);
+    }
+}
+
+impl Actor for IncidentsSupervisor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.subscribe_system_async::<NotifyInstanceState>(ctx);
+        self.subscribe_system_async::<NotifyInstanceError>(ctx);
+        self.subscribe_system_async::<NotifyEngineEvent>(ctx);
+        self.subscribe_system_async::<NotifySocketDropped>(ctx);
+        self.subscribe_system_async::<NotifyEngineClockLost>(ctx);
+
+        ctx.run_interval(Duration::from_secs(1), Self::close_if_stale);
+    }
+}
+
+impl Handler<NotifyInstanceState> for IncidentsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyInstanceState, ctx: &mut Self::Context) -> Self::Result {
+        if !*msg.connected.value() {
+            self.record(IncidentSource::Instance(msg.instance_id),
+                        "Instance driver disconnected".to_string(),
+                        ctx);
+        }
+    }
+}
+
+impl Handler<NotifyInstanceError> for IncidentsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyInstanceError, ctx: &mut Self::Context) -> Self::Result {
+        self.record(IncidentSource::Instance(msg.instance_id), msg.error, ctx);
+    }
+}
+
+impl Handler<NotifyEngineEvent> for IncidentsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyEngineEvent, ctx: &mut Self::Context) -> Self::Result {
+        match msg.event {
+            EngineEvent::Error { task_id, error } => {
+                self.record(IncidentSource::Task(task_id), format!("Engine error: {error}"), ctx);
+            }
+            EngineEvent::PlayingFailed { task_id,
+                                         play_id,
+                                         error, } => {
+                self.record(IncidentSource::Task(task_id),
+                            format!("Playing {play_id} failed: {error}"),
+                            ctx);
+            }
+            EngineEvent::RenderingFailed { task_id,
+                                           render_id,
+                                           error, } => {
+                self.record(IncidentSource::Task(task_id),
+                            format!("Rendering {render_id} failed: {error}"),
+                            ctx);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Handler<NotifySocketDropped> for IncidentsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifySocketDropped, ctx: &mut Self::Context) -> Self::Result {
+        self.record(IncidentSource::Socket(msg.socket_id), msg.reason, ctx);
+    }
+}
+
+impl Handler<NotifyEngineClockLost> for IncidentsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyEngineClockLost, ctx: &mut Self::Context) -> Self::Result {
+        let source = msg.status.source.as_deref().unwrap_or("unknown clock source");
+        let message = match (msg.status.running, msg.status.sample_rate) {
+            (false, _) => format!("Audio interface stopped running ({source})"),
+            (true, Some(sample_rate)) => format!("Audio interface lost clock ({source}, running at {sample_rate} Hz)"),
+            (true, None) => format!("Audio interface lost clock ({source})"),
+        };
+
+        self.record(IncidentSource::Engine(msg.engine_id), message, ctx);
+    }
+}
+
+impl Handler<ListIncidents> for IncidentsSupervisor {
+    type Result = ResponseFuture<DomainResult<Vec<Incident>>>;
+
+    fn handle(&mut self, msg: ListIncidents, ctx: &mut Self::Context) -> Self::Result {
+        let db = self.db.clone();
+        Box::pin(async move {
+            db.fetch_recent_incidents(msg.limit)
+              .await
+              .map_err(|error| DomainError::BadGateway { error: error.to_string(), })
+        })
+    }
+}
+
+impl Handler<GetIncident> for IncidentsSupervisor {
+    type Result = ResponseFuture<DomainResult<Option<Incident>>>;
+
+    fn handle(&mut self, msg: GetIncident, ctx: &mut Self::Context) -> Self::Result {
+        let db = self.db.clone();
+        Box::pin(async move {
+            db.fetch_incident(&msg.incident_id)
+              .await
+              .map_err(|error| DomainError::BadGateway { error: error.to_string(), })
+        })
+    }
+}
@@ -0,0 +1,23 @@
+use actix::Message;
+use serde::Deserialize;
+
+use audiocloud_api::AppTaskId;
+
+use crate::journal::JournalReplay;
+use crate::DomainResult;
+
+/// Which journal events to replay
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct JournalQuery {
+    /// Only return events with a greater sequence number, 0 for the whole journal
+    #[serde(default)]
+    pub since:   i64,
+    pub task_id: Option<AppTaskId>,
+    pub limit:   Option<usize>,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<JournalReplay>")]
+pub struct ReplayJournal {
+    pub query: JournalQuery,
+}
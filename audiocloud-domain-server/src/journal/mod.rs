@@ -0,0 +1,131 @@
+use std::str::FromStr;
+
+use actix::{Actor, Addr};
+use anyhow::anyhow;
+use clap::Args;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::*;
+use utoipa::ToSchema;
+
+use audiocloud_api::{now, AppTaskId, FixedInstanceId, Timestamp};
+pub use messages::*;
+use supervisor::JournalSupervisor;
+
+use crate::db::Db;
+
+pub mod messages;
+mod supervisor;
+
+static JOURNAL_SUPERVISOR: OnceCell<Addr<JournalSupervisor>> = OnceCell::new();
+
+/// What changed in the domain
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalEventKind {
+    TaskActivated,
+    TaskDeactivated,
+    TaskDeleted,
+    TaskState,
+    InstanceState,
+    MediaState,
+}
+
+impl JournalEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JournalEventKind::TaskActivated => "task_activated",
+            JournalEventKind::TaskDeactivated => "task_deactivated",
+            JournalEventKind::TaskDeleted => "task_deleted",
+            JournalEventKind::TaskState => "task_state",
+            JournalEventKind::InstanceState => "instance_state",
+            JournalEventKind::MediaState => "media_state",
+        }
+    }
+}
+
+impl FromStr for JournalEventKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "task_activated" => Ok(Self::TaskActivated),
+            "task_deactivated" => Ok(Self::TaskDeactivated),
+            "task_deleted" => Ok(Self::TaskDeleted),
+            "task_state" => Ok(Self::TaskState),
+            "instance_state" => Ok(Self::InstanceState),
+            "media_state" => Ok(Self::MediaState),
+            other => Err(anyhow!("Unknown journal event kind {other}")),
+        }
+    }
+}
+
+/// A domain event as it was broadcast, with its place in the journal
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct JournalEvent {
+    /// Sequence number, assigned when the event is stored
+    pub seq:         Option<i64>,
+    #[schema(value_type = String)]
+    pub at:          Timestamp,
+    #[schema(value_type = String)]
+    pub kind:        JournalEventKind,
+    #[schema(value_type = Option<String>)]
+    pub task_id:     Option<AppTaskId>,
+    #[schema(value_type = Option<String>)]
+    pub instance_id: Option<FixedInstanceId>,
+    #[schema(value_type = Object)]
+    pub payload:     Value,
+}
+
+impl JournalEvent {
+    pub fn new(kind: JournalEventKind, payload: impl Serialize) -> Self {
+        Self { seq:         { None },
+               at:          { now() },
+               kind:        { kind },
+               task_id:     { None },
+               instance_id: { None },
+               payload:     { serde_json::to_value(payload).unwrap_or(Value::Null) }, }
+    }
+
+    pub fn with_task(mut self, task_id: &AppTaskId) -> Self {
+        self.task_id = Some(task_id.clone());
+        self
+    }
+
+    pub fn with_instance(mut self, instance_id: &FixedInstanceId) -> Self {
+        self.instance_id = Some(instance_id.clone());
+        self
+    }
+}
+
+/// Events after a sequence number, and where to continue from
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct JournalReplay {
+    pub events:     Vec<JournalEvent>,
+    /// Pass as `since` to get the events that follow
+    pub next_since: i64,
+    /// Events after `since` were already trimmed from the journal, the caller has to fetch the full state instead
+    pub truncated:  bool,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct JournalOpts {
+    /// Seconds to keep domain events in the journal for replay
+    #[clap(long, env, default_value = "86400")]
+    pub journal_retention_seconds: u64,
+}
+
+#[instrument(skip_all, err)]
+pub fn init(db: Db, opts: JournalOpts) -> anyhow::Result<()> {
+    let supervisor = JournalSupervisor::new(db, opts);
+
+    JOURNAL_SUPERVISOR.set(supervisor.start())
+                      .map_err(|_| anyhow!("Journal supervisor already initialized"))?;
+
+    Ok(())
+}
+
+pub fn get_journal_supervisor() -> &'static Addr<JournalSupervisor> {
+    JOURNAL_SUPERVISOR.get().expect("Journal supervisor not initialized")
+}
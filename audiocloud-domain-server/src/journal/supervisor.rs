@@ -0,0 +1,179 @@
+#![allow(unused_variables)]
+
+use std::time::Duration;
+
+use actix::{Actor, AsyncContext, Context, ContextFutureSpawner, Handler, ResponseFuture, WrapFuture};
+use actix_broker::BrokerSubscribe;
+use serde_json::json;
+use tracing::*;
+
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::now;
+
+use crate::db::Db;
+use crate::fixed_instances::NotifyInstanceState;
+use crate::journal::{JournalEvent, JournalEventKind, JournalOpts, JournalQuery, JournalReplay, ReplayJournal};
+use crate::tasks::{
+    NotifyMediaTaskState, NotifyTaskActivated, NotifyTaskDeactivated, NotifyTaskDeleted, NotifyTaskState,
+};
+use crate::DomainResult;
+
+const DEFAULT_REPLAY_LIMIT: usize = 1000;
+const MAX_REPLAY_LIMIT: usize = 10000;
+const TRIM_INTERVAL: Duration = Duration::from_secs(3600);
+
+pub struct JournalSupervisor {
+    db:   Db,
+    opts: JournalOpts,
+}
+
+impl JournalSupervisor {
+    pub fn new(db: Db, opts: JournalOpts) -> Self {
+        Self { db:   { db },
+               opts: { opts }, }
+    }
+
+    /// Events are stored one after another, so their sequence follows the order they were broadcast in
+    fn append(&mut self, event: JournalEvent, ctx: &mut Context<Self>) {
+        let db = self.db.clone();
+
+        async move {
+            if let Err(error) = db.append_journal_event(&event).await {
+                warn!(%error, kind = event.kind.as_str(), "Failed to store journal event");
+            }
+        }.into_actor(self)
+         .wait(ctx);
+    }
+
+    fn trim(&mut self, ctx: &mut Context<Self>) {
+        let db = self.db.clone();
+        let cutoff = now() - chrono::Duration::seconds(self.opts.journal_retention_seconds as i64);
+
+        async move {
+            match db.trim_journal(cutoff).await {
+                Ok(0) => {}
+                Ok(trimmed) => debug!(trimmed, "Trimmed journal"),
+                Err(error) => warn!(%error, "Failed to trim journal"),
+            }
+        }.into_actor(self)
+         .spawn(ctx);
+    }
+}
+
+impl Actor for JournalSupervisor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.subscribe_system_async::<NotifyTaskActivated>(ctx);
+        self.subscribe_system_async::<NotifyTaskDeactivated>(ctx);
+        self.subscribe_system_async::<NotifyTaskDeleted>(ctx);
+        self.subscribe_system_async::<NotifyTaskState>(ctx);
+        self.subscribe_system_async::<NotifyInstanceState>(ctx);
+        self.subscribe_system_async::<NotifyMediaTaskState>(ctx);
+
+        self.trim(ctx);
+        ctx.run_interval(TRIM_INTERVAL, Self::trim);
+    }
+}
+
+impl Handler<NotifyTaskActivated> for JournalSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskActivated, ctx: &mut Self::Context) -> Self::Result {
+        let event = JournalEvent::new(JournalEventKind::TaskActivated, ()).with_task(&msg.task_id);
+        self.append(event, ctx);
+    }
+}
+
+impl Handler<NotifyTaskDeactivated> for JournalSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskDeactivated, ctx: &mut Self::Context) -> Self::Result {
+        let event = JournalEvent::new(JournalEventKind::TaskDeactivated, ()).with_task(&msg.task_id);
+        self.append(event, ctx);
+    }
+}
+
+impl Handler<NotifyTaskDeleted> for JournalSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskDeleted, ctx: &mut Self::Context) -> Self::Result {
+        let event = JournalEvent::new(JournalEventKind::TaskDeleted, ()).with_task(&msg.task_id);
+        self.append(event, ctx);
+    }
+}
+
+impl Handler<NotifyTaskState> for JournalSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskState, ctx: &mut Self::Context) -> Self::Result {
+        let event = JournalEvent::new(JournalEventKind::TaskState, &msg.state).with_task(&msg.task_id);
+        self.append(event, ctx);
+    }
+}
+
+impl Handler<NotifyInstanceState> for JournalSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyInstanceState, ctx: &mut Self::Context) -> Self::Result {
+        let payload = json!({
+            "power": msg.power,
+            "play": msg.play,
+            "connected": msg.connected,
+        });
+
+        let event = JournalEvent::new(JournalEventKind::InstanceState, payload).with_instance(&msg.instance_id);
+        self.append(event, ctx);
+    }
+}
+
+impl Handler<NotifyMediaTaskState> for JournalSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyMediaTaskState, ctx: &mut Self::Context) -> Self::Result {
+        let event = JournalEvent::new(JournalEventKind::MediaState, &msg.media).with_task(&msg.task_id);
+        self.append(event, ctx);
+    }
+}
+
+impl Handler<ReplayJournal> for JournalSupervisor {
+    type Result = ResponseFuture<DomainResult<JournalReplay>>;
+
+    fn handle(&mut self, msg: ReplayJournal, ctx: &mut Self::Context) -> Self::Result {
+        let db = self.db.clone();
+
+        Box::pin(async move {
+            replay(db, msg.query).await
+                                 .map_err(|error| DomainError::BadGateway { error: error.to_string(), })
+        })
+    }
+}
+
+async fn replay(db: Db, query: JournalQuery) -> anyhow::Result<JournalReplay> {
+    let limit = query.limit.unwrap_or(DEFAULT_REPLAY_LIMIT).clamp(1, MAX_REPLAY_LIMIT);
+
+    // bounds first, so that events appended while querying are not skipped by `next_since`
+    let (first, last) = db.journal_bounds().await?;
+
+    if query.since > last {
+        // the journal was reset since the caller last replayed it
+        return Ok(JournalReplay { events:     { vec![] },
+                                  next_since: { last },
+                                  truncated:  { true }, });
+    }
+
+    let events = db.query_journal_events(query.since, query.task_id.as_ref(), limit)
+                   .await?;
+
+    let truncated = query.since < last && first.map(|first| first > query.since + 1).unwrap_or(true);
+    let returned = events.last().and_then(|event| event.seq).unwrap_or(query.since);
+    let next_since = if events.len() < limit {
+        returned.max(last)
+    } else {
+        returned
+    };
+
+    Ok(JournalReplay { events:     { events },
+                       next_since: { next_since },
+                       truncated:  { truncated }, })
+}
@@ -1,22 +1,39 @@
 extern crate core;
 
+use std::collections::{HashMap, HashSet};
+
 use derive_more::IsVariant;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use audiocloud_api::domain::DomainError;
-use audiocloud_api::{SecureKey, SerializableResult};
+use audiocloud_api::{AppTaskId, SecureKey, SerializableResult, TaskPermissions, TaskSecurity};
 
+pub mod analytics;
+pub mod audit;
+pub mod automation;
+pub mod bandwidth;
 pub mod config;
+pub mod conformance;
 pub mod db;
 pub mod events;
+pub mod extensions;
 pub mod fixed_instances;
+pub mod incidents;
+pub mod journal;
 pub mod media;
 pub mod models;
 pub mod nats;
+pub mod nats_api;
 pub mod o11y;
+pub mod osc;
+pub mod rate_limit;
 pub mod rest_api;
+pub mod server;
 pub mod sockets;
+pub mod support;
 pub mod tasks;
+pub mod telemetry;
 pub mod tracker;
 
 #[cfg(test)]
@@ -28,10 +45,95 @@ pub enum ResponseMedia {
     Json,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, PartialOrd, IsVariant)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, IsVariant)]
 pub enum DomainSecurity {
     Cloud,
     SecureKey(SecureKey),
+    Token(TokenSecurity),
+}
+
+/// Permissions granted by a verified JWT bearer token, equivalent to holding a secure key for each listed task
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TokenSecurity {
+    pub subject: String,
+    pub tasks:   HashMap<AppTaskId, TaskPermissions>,
+    /// Scope per task, tasks without one can only listen
+    #[serde(default)]
+    pub scopes:  HashMap<AppTaskId, SecureKeyScope>,
+}
+
+/// What a secure key or token may do on a task, each scope includes the ones before it
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SecureKeyScope {
+    /// Receive audio, metering and task events
+    Listen,
+    /// Also play, seek, stop, render and cancel renders
+    Transport,
+    /// Also modify the task spec
+    Full,
+}
+
+/// Scopes of the secure keys of a task, keys without one can only listen
+pub type TaskKeyScopes = HashMap<SecureKey, SecureKeyScope>;
+
+/// Scopes left once the secure keys of a task are replaced with `keys`, the scope of a rotated key moves to its new key
+pub fn rekey_scopes<'a>(scopes: &TaskKeyScopes,
+                        keys: impl IntoIterator<Item = &'a SecureKey>,
+                        rotated: Option<(&SecureKey, &SecureKey)>)
+                        -> TaskKeyScopes {
+    let mut scopes = scopes.clone();
+    if let Some((old_key, new_key)) = rotated {
+        if let Some(scope) = scopes.remove(old_key) {
+            scopes.insert(new_key.clone(), scope);
+        }
+    }
+
+    let keys = keys.into_iter().collect::<HashSet<_>>();
+    scopes.retain(|secure_key, _| keys.contains(secure_key));
+
+    scopes
+}
+
+impl DomainSecurity {
+    pub fn can_on_task(&self,
+                       task_id: &AppTaskId,
+                       task_security: &TaskSecurity,
+                       predicate: impl Fn(&TaskPermissions) -> bool)
+                       -> bool {
+        match self {
+            DomainSecurity::Cloud => true,
+            DomainSecurity::SecureKey(secure_key) => task_security.security
+                                                                  .get(secure_key)
+                                                                  .map(predicate)
+                                                                  .unwrap_or_default(),
+            DomainSecurity::Token(token) => token.tasks.get(task_id).map(predicate).unwrap_or_default(),
+        }
+    }
+
+    pub fn scope_on_task(&self, task_id: &AppTaskId, key_scopes: &TaskKeyScopes) -> SecureKeyScope {
+        match self {
+            DomainSecurity::Cloud => SecureKeyScope::Full,
+            DomainSecurity::SecureKey(secure_key) => {
+                key_scopes.get(secure_key).copied().unwrap_or(SecureKeyScope::Listen)
+            }
+            DomainSecurity::Token(token) => token.scopes.get(task_id).copied().unwrap_or(SecureKeyScope::Listen),
+        }
+    }
+
+    /// Fail unless the caller has access to the task with at least the `required` scope
+    pub fn require_scope(&self,
+                         task_id: &AppTaskId,
+                         task_security: &TaskSecurity,
+                         key_scopes: &TaskKeyScopes,
+                         required: SecureKeyScope)
+                         -> DomainResult {
+        if self.can_on_task(task_id, task_security, |_| true) && self.scope_on_task(task_id, key_scopes) >= required {
+            Ok(())
+        } else {
+            Err(DomainError::AuthenticationFailed)
+        }
+    }
 }
 
 pub type DomainResult<T = ()> = Result<T, DomainError>;
@@ -11,6 +11,7 @@ use tracing::*;
 use audiocloud_api::common::time::now;
 use audiocloud_api::MediaDownload;
 
+use crate::bandwidth::{record_bandwidth, BandwidthChannel, BandwidthDirection};
 use crate::db::Db;
 use crate::media::messages::NotifyDownloadProgress;
 use crate::media::DownloadJobId;
@@ -49,11 +50,18 @@ impl Downloader {
         debug!(?source, ?download, %media_id, "starting download");
 
         async move {
+            let bytes = tokio::fs::metadata(&source).await?.len();
+
             client.put(&download.download.url)
                   .body(File::open(&source).await?)
                   .send()
                   .await?;
 
+            record_bandwidth(Some(&media_id.app_id),
+                             BandwidthChannel::Media,
+                             BandwidthDirection::Sent,
+                             bytes);
+
             if let Some(notify_url) = &download.download.notify_url {
                 client.post(notify_url)
                       .json(&json!({
@@ -0,0 +1,169 @@
+use anyhow::{anyhow, bail, ensure};
+
+/// Standard MIDI files start with the header chunk
+const HEADER_CHUNK: &[u8; 4] = b"MThd";
+const TRACK_CHUNK: &[u8; 4] = b"MTrk";
+
+/// What an uploaded standard MIDI file contains, once it is known to parse
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MidiFileSummary {
+    pub format:            u16,
+    pub tracks:            u16,
+    pub ticks_per_quarter: u16,
+    pub notes:             usize,
+    /// Position of the last event, in ticks
+    pub length_ticks:      u64,
+}
+
+/// Media objects are stored without extensions, MIDI files are told apart from audio by their header
+pub fn is_midi_file(head: &[u8]) -> bool {
+    head.starts_with(HEADER_CHUNK)
+}
+
+/// Walk every event of a standard MIDI file, so that files the engine can not read are rejected when uploaded and
+/// not when a task plays them
+pub fn summarize_midi_file(data: &[u8]) -> anyhow::Result<MidiFileSummary> {
+    let mut reader = Reader { data, pos: 0 };
+
+    ensure!(reader.take(4)? == HEADER_CHUNK, "Not a standard MIDI file");
+    let header_length = reader.u32()? as usize;
+    ensure!(header_length >= 6, "MIDI header is too short");
+
+    let format = reader.u16()?;
+    let tracks = reader.u16()?;
+    let division = reader.u16()?;
+    reader.take(header_length - 6)?;
+
+    ensure!(format <= 2, "Unsupported MIDI file format {format}");
+    ensure!(division & 0x8000 == 0, "SMPTE timed MIDI files are not supported");
+    ensure!(division > 0, "MIDI file has no ticks per quarter note");
+
+    let mut summary = MidiFileSummary { format:            { format },
+                                        tracks:            { tracks },
+                                        ticks_per_quarter: { division },
+                                        notes:             { 0 },
+                                        length_ticks:      { 0 }, };
+
+    for track in 0..tracks {
+        let id = reader.take(4)?;
+        let length = reader.u32()? as usize;
+        let chunk = reader.take(length)?;
+
+        // unknown chunks are allowed in between tracks, and do not count as one
+        if id != TRACK_CHUNK {
+            continue;
+        }
+
+        summarize_track(chunk, &mut summary).map_err(|error| anyhow!("MIDI track {track}: {error}"))?;
+    }
+
+    Ok(summary)
+}
+
+fn summarize_track(data: &[u8], summary: &mut MidiFileSummary) -> anyhow::Result<()> {
+    let mut reader = Reader { data, pos: 0 };
+    let mut running_status = None;
+    let mut tick = 0u64;
+
+    while !reader.is_empty() {
+        tick += reader.vlq()? as u64;
+
+        let status = match reader.peek()? {
+            byte if byte & 0x80 != 0 => {
+                reader.pos += 1;
+                byte
+            }
+            _ => running_status.ok_or_else(|| anyhow!("Data byte without a status at tick {tick}"))?,
+        };
+
+        match status {
+            0xFF => {
+                let kind = reader.take(1)?[0];
+                let length = reader.vlq()? as usize;
+                reader.take(length)?;
+
+                if kind == 0x2F {
+                    summary.length_ticks = summary.length_ticks.max(tick);
+                    return Ok(());
+                }
+            }
+            0xF0 | 0xF7 => {
+                let length = reader.vlq()? as usize;
+                reader.take(length)?;
+            }
+            0x80..=0xEF => {
+                let data = reader.take(channel_message_length(status))?;
+                if status & 0xF0 == 0x90 && data[1] > 0 {
+                    summary.notes += 1;
+                }
+
+                running_status = Some(status);
+            }
+            _ => bail!("Unexpected status {status:#04x} at tick {tick}"),
+        }
+
+        summary.length_ticks = summary.length_ticks.max(tick);
+    }
+
+    Ok(())
+}
+
+/// Data bytes following the status of a channel voice message
+fn channel_message_length(status: u8) -> usize {
+    match status & 0xF0 {
+        0xC0 | 0xD0 => 1,
+        _ => 2,
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos:  usize,
+}
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn peek(&self) -> anyhow::Result<u8> {
+        self.data
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| anyhow!("MIDI data ends unexpectedly"))
+    }
+
+    fn take(&mut self, count: usize) -> anyhow::Result<&'a [u8]> {
+        let end = self.pos + count;
+        ensure!(end <= self.data.len(), "MIDI data ends unexpectedly");
+
+        let rv = &self.data[self.pos..end];
+        self.pos = end;
+
+        Ok(rv)
+    }
+
+    fn u16(&mut self) -> anyhow::Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Variable length quantity, at most four bytes
+    fn vlq(&mut self) -> anyhow::Result<u32> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let byte = self.take(1)?[0];
+            value = (value << 7) | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        bail!("MIDI variable length quantity is too long")
+    }
+}
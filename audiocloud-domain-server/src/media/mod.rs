@@ -17,6 +17,7 @@ use crate::db::Db;
 
 pub mod download;
 pub mod messages;
+pub mod midi;
 mod supervisor;
 #[cfg(test)]
 mod tests;
@@ -90,12 +90,7 @@ impl MediaSupervisor {
 
                     let path = actor.get_local_path(&upload.media_id);
 
-                    match Uploader::new(actor.db.clone(),
-                                        id.clone(),
-                                        actor.client.clone(),
-                                        actor.media_root.clone(),
-                                        upload)
-                    {
+                    match Uploader::new(actor.db.clone(), id.clone(), actor.client.clone(), path, upload) {
                         Ok(uploader) => {
                             actor.uploads.insert(id, uploader.start());
                         }
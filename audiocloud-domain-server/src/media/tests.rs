@@ -16,6 +16,7 @@ use audiocloud_api::{
 use crate::db;
 use crate::db::DataOpts;
 use crate::media::download::Downloader;
+use crate::media::midi::{is_midi_file, summarize_midi_file, MidiFileSummary};
 use crate::media::upload::Uploader;
 use crate::media::{DownloadJobId, UploadJobId};
 
@@ -111,3 +112,40 @@ async fn test_upload_success() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// A format 0 file with one quarter note middle C, the note off using running status
+fn test_midi_file() -> Vec<u8> {
+    let track = [0x00, 0x90, 0x3C, 0x64, 0x83, 0x60, 0x3C, 0x00, 0x00, 0xFF, 0x2F, 0x00];
+
+    let mut data = b"MThd".to_vec();
+    data.extend_from_slice(&[0, 0, 0, 6, 0, 0, 0, 1, 0x01, 0xE0]);
+    data.extend_from_slice(b"MTrk");
+    data.extend_from_slice(&(track.len() as u32).to_be_bytes());
+    data.extend_from_slice(&track);
+
+    data
+}
+
+#[test]
+fn test_summarize_midi_file() -> anyhow::Result<()> {
+    let data = test_midi_file();
+
+    assert!(is_midi_file(&data));
+    assert!(!is_midi_file(b"RIFF"));
+
+    assert_eq!(summarize_midi_file(&data)?,
+               MidiFileSummary { format:            0,
+                                 tracks:            1,
+                                 ticks_per_quarter: 480,
+                                 notes:             1,
+                                 length_ticks:      480, });
+
+    Ok(())
+}
+
+#[test]
+fn test_truncated_midi_file_is_rejected() {
+    let data = test_midi_file();
+
+    assert!(summarize_midi_file(&data[..data.len() - 3]).is_err());
+}
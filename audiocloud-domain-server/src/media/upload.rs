@@ -1,5 +1,5 @@
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use actix::{Actor, ActorContext, ActorFutureExt, Context, ContextFutureSpawner, WrapFuture};
 use actix_broker::BrokerIssue;
@@ -7,6 +7,7 @@ use futures::TryStreamExt;
 use reqwest::Client;
 use serde_json::json;
 use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio_util::io::StreamReader;
 use tracing::*;
 
@@ -14,8 +15,10 @@ use audiocloud_api::common::media::MediaJobState;
 use audiocloud_api::common::time::now;
 use audiocloud_api::MediaUpload;
 
+use crate::bandwidth::{record_bandwidth, BandwidthChannel, BandwidthDirection};
 use crate::db::Db;
 use crate::media::messages::NotifyUploadProgress;
+use crate::media::midi::{is_midi_file, summarize_midi_file};
 use crate::media::UploadJobId;
 
 #[derive(Debug)]
@@ -69,6 +72,10 @@ impl Uploader {
                 }
             }
 
+            if let Some(dir) = destination.parent() {
+                tokio::fs::create_dir_all(dir).await?;
+            }
+
             let mut file = File::create(&destination).await?;
 
             let stream = client.get(&upload.upload.url)
@@ -79,7 +86,14 @@ impl Uploader {
 
             let mut stream = StreamReader::new(stream);
 
-            tokio::io::copy(&mut stream, &mut file).await?;
+            let bytes = tokio::io::copy(&mut stream, &mut file).await?;
+            record_bandwidth(Some(&media_id.app_id),
+                             BandwidthChannel::Media,
+                             BandwidthDirection::Received,
+                             bytes);
+            file.flush().await?;
+
+            check_midi_upload(&destination).await?;
 
             if let Some(notify_url) = upload.upload.notify_url {
                 client.post(&notify_url)
@@ -137,3 +151,24 @@ impl Actor for Uploader {
         }
     }
 }
+
+/// MIDI files are parsed as soon as they are uploaded, so a file the engine can not read fails the upload instead of
+/// the plays using it
+async fn check_midi_upload(path: &Path) -> anyhow::Result<()> {
+    let mut head = [0u8; 4];
+    let read = File::open(path).await?.read(&mut head).await?;
+    if !is_midi_file(&head[..read]) {
+        return Ok(());
+    }
+
+    match summarize_midi_file(&tokio::fs::read(path).await?) {
+        Ok(summary) => {
+            debug!(?summary, path = %path.display(), "Uploaded MIDI file");
+            Ok(())
+        }
+        Err(error) => {
+            let _ = tokio::fs::remove_file(path).await;
+            Err(error)
+        }
+    }
+}
@@ -1,16 +1,55 @@
 use std::collections::HashMap;
+use std::sync::RwLock;
 
 use anyhow::anyhow;
+use once_cell::sync::OnceCell;
 use tracing::*;
 
 use audiocloud_api::cloud::domains::{DomainConfig, DomainModelSource};
-use audiocloud_api::ModelId;
+use audiocloud_api::{Model, ModelId};
 
 use crate::db::Db;
 
+static MODELS: OnceCell<RwLock<HashMap<ModelId, Model>>> = OnceCell::new();
+
 #[instrument(skip_all, err)]
 pub async fn init(cfg: &DomainConfig, db: Db) -> anyhow::Result<()> {
-    let models = match &cfg.models {
+    let models = load_models(&cfg.models).await?;
+
+    db.delete_all_models().await?;
+
+    for (id, model) in &models {
+        debug!(%id, "registering model");
+        db.set_model(id.clone(), model.clone()).await?;
+    }
+
+    cache_models(models);
+
+    Ok(())
+}
+
+/// Keep the registered models in memory, so instances are set up without waiting on the database
+fn cache_models(models: HashMap<ModelId, Model>) {
+    match MODELS.get_or_init(Default::default).write() {
+        Ok(mut cached) => *cached = models,
+        Err(_) => warn!("Model cache lock poisoned"),
+    }
+}
+
+/// A registered model, from memory
+pub fn cached_model(model_id: &ModelId) -> Option<Model> {
+    MODELS.get()?.read().ok()?.get(model_id).cloned()
+}
+
+pub fn cached_model_count() -> usize {
+    MODELS.get()
+          .and_then(|models| models.read().ok().map(|models| models.len()))
+          .unwrap_or_default()
+}
+
+/// Read the models from their configured source, without registering them
+pub async fn load_models(source: &DomainModelSource) -> anyhow::Result<HashMap<ModelId, Model>> {
+    Ok(match source {
         DomainModelSource::Inline { models } => models.clone(),
         DomainModelSource::Local { path } => {
             let mut rv = HashMap::new();
@@ -41,14 +80,5 @@ pub async fn init(cfg: &DomainConfig, db: Db) -> anyhow::Result<()> {
             rv
         }
         DomainModelSource::Remote { url, .. } => reqwest::get(url).await?.json().await?,
-    };
-
-    db.delete_all_models().await?;
-
-    for (id, model) in models {
-        debug!(%id, "registering model");
-        db.set_model(id, model).await?;
-    }
-
-    Ok(())
+    })
 }
@@ -1,24 +1,200 @@
-use std::io;
-use std::time::Duration;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use std::{fs, io};
 
 use anyhow::anyhow;
+use clap::Args;
 use futures::{stream, Stream, StreamExt};
-use nats_aflowt::{connect, Connection, Message, Subscription};
+use nats_aflowt::{Connection, Message, Options, Subscription};
+use nkeys::KeyPair;
 use once_cell::sync::OnceCell;
 use serde::de::DeserializeOwned;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 use stream_throttle::{ThrottlePool, ThrottleRate, ThrottledStream};
+use tokio::time;
 use tracing::*;
 
 use audiocloud_api::{Codec, Json, MsgPack, Request};
 
+pub use metrics::{label_subject, traffic, SubjectTraffic};
+
+mod metrics;
+#[cfg(test)]
+mod tests;
+
 static NATS_CONNECTION: OnceCell<Connection> = OnceCell::new();
+static NATS_RETRY: OnceCell<RequestRetry> = OnceCell::new();
+static NATS_RETRYING: AtomicUsize = AtomicUsize::new(0);
+static NATS_SLOW_REQUEST: OnceCell<Option<Duration>> = OnceCell::new();
+
+#[derive(Args, Clone, Debug)]
+pub struct NatsOpts {
+    /// NATS URL, use the `tls://` scheme for TLS
+    #[clap(long, env, default_value = "nats://localhost:4222")]
+    pub nats_url: String,
+
+    /// Credentials file with the user JWT and NKey seed, as issued by `nsc`
+    #[clap(long, env)]
+    pub nats_creds_file: Option<PathBuf>,
+
+    /// File with the NKey seed of the user, for servers authenticating NKeys without JWTs
+    #[clap(long, env, conflicts_with = "nats_creds_file")]
+    pub nats_nkey_seed_file: Option<PathBuf>,
+
+    /// Require TLS even if the URL does not use the `tls://` scheme
+    #[clap(long, env)]
+    pub nats_tls_required: bool,
+
+    /// PEM encoded CA certificate to verify the server with, in addition to the system roots
+    #[clap(long, env)]
+    pub nats_tls_ca_file: Option<PathBuf>,
+
+    /// PEM encoded client certificate, for servers verifying clients
+    #[clap(long, env, requires = "nats_tls_key_file")]
+    pub nats_tls_cert_file: Option<PathBuf>,
+
+    /// PEM encoded private key of the client certificate
+    #[clap(long, env, requires = "nats_tls_cert_file")]
+    pub nats_tls_key_file: Option<PathBuf>,
+
+    /// Give up reconnecting after this many attempts in a row, 0 to keep trying forever
+    #[clap(long, env, default_value = "0")]
+    pub nats_max_reconnects: usize,
+
+    /// Milliseconds to wait for the reply to a single request attempt
+    #[clap(long, env, default_value = "2000")]
+    pub nats_request_timeout_ms: u64,
+
+    /// Milliseconds to keep retrying a request for while the broker or the responder is unreachable
+    #[clap(long, env, default_value = "10000")]
+    pub nats_request_deadline_ms: u64,
+
+    /// Milliseconds to wait before the first retry of a request, doubling with every further retry
+    #[clap(long, env, default_value = "100")]
+    pub nats_retry_backoff_ms: u64,
+
+    /// Upper bound for the milliseconds between retries of a request
+    #[clap(long, env, default_value = "2000")]
+    pub nats_retry_max_backoff_ms: u64,
+
+    /// Maximum number of requests waiting to be retried at once, further requests fail right away
+    #[clap(long, env, default_value = "1024")]
+    pub nats_retry_buffer: usize,
+
+    /// Log requests taking longer than this many milliseconds to get a reply, 0 to not log slow requests
+    #[clap(long, env, default_value = "1000")]
+    pub nats_slow_request_ms: u64,
+
+    /// Milliseconds between observations of the request latency percentiles
+    #[clap(long, env, default_value = "10000")]
+    pub nats_metrics_interval_ms: u64,
+}
+
+impl NatsOpts {
+    /// Connection options, the client authenticates with them again on every reconnect
+    fn options(&self) -> anyhow::Result<Options> {
+        let mut options = match (&self.nats_creds_file, &self.nats_nkey_seed_file) {
+            (Some(creds_file), _) => Options::with_credentials(creds_file),
+            (None, Some(seed_file)) => {
+                let seed = fs::read_to_string(seed_file)?;
+                let key_pair = KeyPair::from_seed(seed.trim())?;
+                let public_key = key_pair.public_key();
+
+                Options::with_nkey(&public_key, move |nonce| key_pair.sign(nonce).expect("NKey signature"))
+            }
+            (None, None) => Options::new(),
+        };
+
+        let max_reconnects = match self.nats_max_reconnects {
+            0 => None,
+            max => Some(max),
+        };
+
+        options = options.with_name("audiocloud-domain-server")
+                         .tls_required(self.nats_tls_required)
+                         .max_reconnects(max_reconnects)
+                         .disconnect_callback(|| warn!("Disconnected from NATS, reconnecting"))
+                         .reconnect_callback(|| info!("Reconnected to NATS"))
+                         .close_callback(|| error!("NATS connection closed, giving up on reconnecting"));
+
+        if let Some(ca_file) = &self.nats_tls_ca_file {
+            options = options.add_root_certificate(ca_file);
+        }
+
+        if let (Some(cert_file), Some(key_file)) = (&self.nats_tls_cert_file, &self.nats_tls_key_file) {
+            options = options.client_cert(cert_file, key_file);
+        }
+
+        Ok(options)
+    }
+
+    fn retry(&self) -> RequestRetry {
+        RequestRetry { attempt_timeout: { Duration::from_millis(self.nats_request_timeout_ms.max(1)) },
+                       deadline:        { Duration::from_millis(self.nats_request_deadline_ms) },
+                       backoff:         { Duration::from_millis(self.nats_retry_backoff_ms.max(1)) },
+                       max_backoff:     { Duration::from_millis(self.nats_retry_max_backoff_ms.max(1)) },
+                       buffer:          { self.nats_retry_buffer }, }
+    }
+
+    fn slow_request(&self) -> Option<Duration> {
+        match self.nats_slow_request_ms {
+            0 => None,
+            millis => Some(Duration::from_millis(millis)),
+        }
+    }
+}
+
+/// Whether a request is sent again after an attempt got no reply in time
+///
+/// An attempt that timed out may have reached the responder, with only the reply lost. Requests that act again when
+/// received twice are only retried while the broker can not deliver them, on no responders or connection errors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Idempotency {
+    /// Receiving the request twice has the same effect as once, such as setting the spec of a task
+    Idempotent,
+    /// Receiving the request twice acts twice, such as starting a play or a render
+    NotIdempotent,
+}
+
+/// How requests are retried while the broker or the responder is unreachable
+#[derive(Clone, Copy, Debug)]
+pub struct RequestRetry {
+    pub attempt_timeout: Duration,
+    pub deadline:        Duration,
+    pub backoff:         Duration,
+    pub max_backoff:     Duration,
+    pub buffer:          usize,
+}
+
+impl RequestRetry {
+    /// Delay before the given retry (counting from zero), doubling up to the maximum backoff
+    pub fn backoff(&self, retry: u32) -> Duration {
+        self.backoff
+            .checked_mul(1 << retry.min(16))
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+}
 
 #[instrument(skip_all, err)]
-pub async fn init(nats_url: &str) -> anyhow::Result<()> {
-    let conn = connect(nats_url).await?;
+pub async fn init(opts: &NatsOpts) -> anyhow::Result<()> {
+    let conn = opts.options()?.connect(&opts.nats_url).await?;
     NATS_CONNECTION.set(conn)
                    .map_err(|_| anyhow!("NATS_CONNECTION already initialized"))?;
+    NATS_RETRY.set(opts.retry())
+              .map_err(|_| anyhow!("NATS_RETRY already initialized"))?;
+    NATS_SLOW_REQUEST.set(opts.slow_request())
+                     .map_err(|_| anyhow!("NATS_SLOW_REQUEST already initialized"))?;
+
+    let mut metrics_interval = time::interval(Duration::from_millis(opts.nats_metrics_interval_ms.max(1)));
+    tokio::spawn(async move {
+        loop {
+            metrics_interval.tick().await;
+            metrics::observe_latency_percentiles();
+        }
+    });
 
     Ok(())
 }
@@ -40,6 +216,7 @@ pub fn subscribe<M: DeserializeOwned, C: Codec>(subject: String, codec: C) -> im
                                              .flat_map(move |sub: Subscription| sub.stream())
                                              .filter_map(move |msg: Message| {
                                                  let codec = codec.clone();
+                                                 metrics::record_received(&msg.subject, msg.data.len());
                                                  async move { codec.deserialize(&msg.data).ok() }
                                              })
 }
@@ -58,21 +235,165 @@ pub async fn publish<M: Serialize, C: Codec>(subject: &str, codec: C, message: M
 
     let message = codec.serialize(&message)?;
     connection.publish(&subject, &message).await?;
+    metrics::record_publish(subject, message.len());
+
+    Ok(())
+}
+
+/// Subscribe to requests on `subject`, to be answered with [`respond`]
+pub async fn subscribe_requests(subject: &str) -> anyhow::Result<impl Stream<Item = Message>> {
+    let connection = NATS_CONNECTION.get()
+                                    .ok_or_else(|| anyhow!("NATS_CONNECTION initialized"))?;
+
+    let subscription = connection.subscribe(subject).await?;
+
+    Ok(subscription.stream()
+                   .inspect(|msg: &Message| metrics::record_received(&msg.subject, msg.data.len())))
+}
+
+/// Reply to a request received on a subscription from [`subscribe_requests`]
+pub async fn respond<M: Serialize, C: Codec>(request: &Message, codec: C, message: M) -> anyhow::Result<()> {
+    let message = codec.serialize(&message)?;
+    request.respond(&message).await?;
+    metrics::record_publish(&request.subject, message.len());
 
     Ok(())
 }
 
+/// Default deadline of requests, used by the functions that do not take one
+pub fn default_request_deadline() -> Duration {
+    NATS_RETRY.get().map(|retry| retry.deadline).unwrap_or_default()
+}
+
+/// Send a request, retrying with exponential backoff until the deadline passes
+///
+/// Short broker outages are waited out instead of failing the request. Once too many requests are waiting to be
+/// retried, further ones fail on their first error so that an outage does not pile up work without bound. Attempts
+/// that timed out are only retried for idempotent requests.
+async fn request_bytes(subject: &str,
+                       payload: &[u8],
+                       deadline: Duration,
+                       idempotency: Idempotency)
+                       -> anyhow::Result<Message> {
+    let connection = NATS_CONNECTION.get()
+                                    .ok_or_else(|| anyhow!("NATS_CONNECTION initialized"))?;
+
+    let retry = NATS_RETRY.get()
+                          .copied()
+                          .ok_or_else(|| anyhow!("NATS_RETRY not initialized"))?;
+    let started = Instant::now();
+    let mut waiting = None;
+    let mut attempt = 0;
+
+    loop {
+        let remaining = deadline.saturating_sub(started.elapsed());
+        let attempt_timeout = retry.attempt_timeout.min(remaining.max(Duration::from_millis(1)));
+
+        let (error, timed_out) = match time::timeout(attempt_timeout, connection.request(subject, payload)).await {
+            Ok(Ok(reply)) => {
+                record_request(subject,
+                               payload.len(),
+                               Some(reply.data.len()),
+                               started.elapsed(),
+                               attempt + 1);
+                return Ok(reply);
+            }
+            Ok(Err(error)) => (anyhow!(error), false),
+            Err(_) => (anyhow!("no reply within {}ms", attempt_timeout.as_millis()), true),
+        };
+
+        // the attempt may have reached the responder, sending it again would act twice
+        if timed_out && idempotency == Idempotency::NotIdempotent {
+            record_request(subject, payload.len(), None, started.elapsed(), attempt + 1);
+            return Err(error.context(format!("Request to {subject} timed out, not retried as it is not idempotent")));
+        }
+
+        let backoff = retry.backoff(attempt);
+        if started.elapsed() + backoff >= deadline {
+            record_request(subject, payload.len(), None, started.elapsed(), attempt + 1);
+            return Err(error.context(format!("Request to {subject} failed after {} attempts", attempt + 1)));
+        }
+
+        if waiting.is_none() {
+            waiting = RetryWaiting::enter(retry.buffer);
+            if waiting.is_none() {
+                record_request(subject, payload.len(), None, started.elapsed(), attempt + 1);
+                return Err(error.context(format!("Request to {subject} failed, too many requests waiting to retry")));
+            }
+        }
+
+        debug!(%subject, %error, attempt, backoff_ms = backoff.as_millis() as u64, "Retrying NATS request");
+
+        time::sleep(backoff).await;
+        attempt += 1;
+    }
+}
+
+/// Record the outcome of a request in the metrics, logging it if the reply took longer than the configured threshold
+fn record_request(subject: &str, bytes_sent: usize, reply_bytes: Option<usize>, elapsed: Duration, attempts: u32) {
+    metrics::record_request(subject, bytes_sent, reply_bytes, elapsed);
+
+    if let Some(Some(slow_request)) = NATS_SLOW_REQUEST.get() {
+        if elapsed >= *slow_request {
+            warn!(%subject,
+                  label = metrics::subject_label(subject),
+                  elapsed_ms = elapsed.as_millis() as u64,
+                  attempts,
+                  replied = reply_bytes.is_some(),
+                  "Slow NATS request");
+        }
+    }
+}
+
+/// Counts a request as waiting to be retried for as long as it is alive
+struct RetryWaiting;
+
+impl RetryWaiting {
+    fn enter(buffer: usize) -> Option<Self> {
+        NATS_RETRYING.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |waiting| {
+                         if waiting < buffer {
+                             Some(waiting + 1)
+                         } else {
+                             None
+                         }
+                     })
+                     .ok()
+                     .map(|_| Self)
+    }
+}
+
+impl Drop for RetryWaiting {
+    fn drop(&mut self) {
+        NATS_RETRYING.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
 pub async fn request<R, C, S>(subject: S, codec: C, req: R) -> anyhow::Result<<R as Request>::Response>
     where R: Request,
           C: Codec,
           S: ToString
 {
-    let subject = subject.to_string();
-    let connection = NATS_CONNECTION.get()
-                                    .ok_or_else(|| anyhow!("NATS_CONNECTION initialized"))?;
+    request_within(subject,
+                   codec,
+                   req,
+                   default_request_deadline(),
+                   Idempotency::NotIdempotent).await
+}
 
+/// Request that keeps being retried for as long as `deadline`
+pub async fn request_within<R, C, S>(subject: S,
+                                     codec: C,
+                                     req: R,
+                                     deadline: Duration,
+                                     idempotency: Idempotency)
+                                     -> anyhow::Result<<R as Request>::Response>
+    where R: Request,
+          C: Codec,
+          S: ToString
+{
+    let subject = subject.to_string();
     let req = codec.serialize(&req)?;
-    let reply = connection.request(&subject, &req).await?;
+    let reply = request_bytes(&subject, &req, deadline, idempotency).await?;
     Ok(codec.deserialize(&reply.data)?)
 }
 
@@ -89,3 +410,120 @@ pub async fn request_msgpack<R, S>(subject: S, req: R) -> anyhow::Result<<R as R
 {
     request(subject, MsgPack, req).await
 }
+
+pub async fn request_msgpack_within<R, S>(subject: S,
+                                          req: R,
+                                          deadline: Duration,
+                                          idempotency: Idempotency)
+                                          -> anyhow::Result<<R as Request>::Response>
+    where R: Request,
+          S: ToString
+{
+    request_within(subject, MsgPack, req, deadline, idempotency).await
+}
+
+/// Request with types outside the `audiocloud_api` protocol, such as engine extension commands
+pub async fn request_raw_msgpack<Req, Res, S>(subject: S, req: Req) -> anyhow::Result<Res>
+    where Req: Serialize,
+          Res: DeserializeOwned,
+          S: ToString
+{
+    request_raw_msgpack_within(subject, req, default_request_deadline(), Idempotency::NotIdempotent).await
+}
+
+/// Request with types outside the `audiocloud_api` protocol that keeps being retried for as long as `deadline`
+pub async fn request_raw_msgpack_within<Req, Res, S>(subject: S,
+                                                     req: Req,
+                                                     deadline: Duration,
+                                                     idempotency: Idempotency)
+                                                     -> anyhow::Result<Res>
+    where Req: Serialize,
+          Res: DeserializeOwned,
+          S: ToString
+{
+    let subject = subject.to_string();
+    let req = MsgPack.serialize(&req)?;
+    let reply = request_bytes(&subject, &req, deadline, idempotency).await?;
+    Ok(MsgPack.deserialize(&reply.data)?)
+}
+
+/// Acknowledgement of a message stored by a JetStream stream
+#[derive(Deserialize, Clone, Debug)]
+pub struct JetStreamAck {
+    pub stream:    String,
+    pub seq:       u64,
+    #[serde(default)]
+    pub duplicate: bool,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct JetStreamApiError {
+    code:        u16,
+    #[serde(default)]
+    description: String,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+enum JetStreamApiResponse<T> {
+    Error { error: JetStreamApiError },
+    Ok(T),
+}
+
+/// Request on the JetStream API (or a subject captured by a stream), with the reply parsed as a JetStream response
+async fn jetstream_request<T: DeserializeOwned>(subject: &str, payload: &[u8], timeout: Duration) -> anyhow::Result<T> {
+    let connection = NATS_CONNECTION.get()
+                                    .ok_or_else(|| anyhow!("NATS_CONNECTION initialized"))?;
+
+    let started = Instant::now();
+    let reply = time::timeout(timeout, connection.request(subject, payload)).await;
+    let reply = match reply {
+        Ok(Ok(reply)) => reply,
+        Ok(Err(error)) => {
+            record_request(subject, payload.len(), None, started.elapsed(), 1);
+            return Err(error.into());
+        }
+        Err(_) => {
+            record_request(subject, payload.len(), None, started.elapsed(), 1);
+            return Err(anyhow!("JetStream request to {subject} timed out"));
+        }
+    };
+    record_request(subject, payload.len(), Some(reply.data.len()), started.elapsed(), 1);
+
+    match serde_json::from_slice(&reply.data)? {
+        JetStreamApiResponse::Ok(response) => Ok(response),
+        JetStreamApiResponse::Error { error } => Err(anyhow!("JetStream error {} on {subject}: {}",
+                                                             error.code,
+                                                             error.description)),
+    }
+}
+
+/// Create the file backed stream `name` capturing `subjects`, unless it exists already
+#[instrument(skip_all, err, fields(name))]
+pub async fn ensure_jetstream_stream(name: &str, subjects: &[String], timeout: Duration) -> anyhow::Result<()> {
+    let info = format!("$JS.API.STREAM.INFO.{name}");
+    if jetstream_request::<serde_json::Value>(&info, b"", timeout).await
+                                                                  .is_ok()
+    {
+        return Ok(());
+    }
+
+    let create = format!("$JS.API.STREAM.CREATE.{name}");
+    let config = serde_json::to_vec(&json!({ "name": name, "subjects": subjects, "storage": "file" }))?;
+    jetstream_request::<serde_json::Value>(&create, &config, timeout).await?;
+
+    Ok(())
+}
+
+/// Publish to a subject captured by a JetStream stream and wait for the stream to acknowledge storing it
+///
+/// Unlike [`publish`], an `Ok` means the message is persisted on the server. Without a stream capturing the subject
+/// the request times out or fails for lack of responders.
+pub async fn publish_jetstream<M: Serialize, C: Codec>(subject: &str,
+                                                       codec: C,
+                                                       message: M,
+                                                       timeout: Duration)
+                                                       -> anyhow::Result<JetStreamAck> {
+    let message = codec.serialize(&message)?;
+    jetstream_request(subject, &message, timeout).await
+}
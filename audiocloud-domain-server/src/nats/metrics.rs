@@ -0,0 +1,206 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use opentelemetry::metrics::{Counter, Histogram, ObservableGauge};
+use opentelemetry::{global, KeyValue};
+
+use crate::o11y;
+
+/// Number of the most recent request latencies of a label kept for the percentiles
+const LATENCY_WINDOW: usize = 1024;
+
+/// Label of subjects no subsystem registered
+const OTHER_SUBJECTS: &str = "other";
+
+static SUBJECT_LABELS: Lazy<Mutex<HashMap<String, &'static str>>> = Lazy::new(Default::default);
+static TRAFFIC: Lazy<Mutex<HashMap<&'static str, SubjectTraffic>>> = Lazy::new(Default::default);
+static INSTRUMENTS: Lazy<NatsInstruments> = Lazy::new(NatsInstruments::new);
+
+struct NatsInstruments {
+    requests:         Counter<u64>,
+    published:        Counter<u64>,
+    received:         Counter<u64>,
+    bytes_sent:       Counter<u64>,
+    bytes_received:   Counter<u64>,
+    request_duration: Histogram<f64>,
+    request_latency:  ObservableGauge<f64>,
+}
+
+impl NatsInstruments {
+    fn new() -> Self {
+        let meter = global::meter("audiocloud.io/nats");
+
+        let requests = meter.u64_counter("nats_requests")
+                            .with_description("Requests sent over NATS, by subject label and outcome")
+                            .init();
+        let published = meter.u64_counter("nats_published")
+                             .with_description("Messages published over NATS, by subject label")
+                             .init();
+        let received = meter.u64_counter("nats_received")
+                            .with_description("Messages received on NATS subscriptions, by subject label")
+                            .init();
+        let bytes_sent = meter.u64_counter("nats_bytes_sent")
+                              .with_description("Payload bytes of requests and published messages")
+                              .init();
+        let bytes_received = meter.u64_counter("nats_bytes_received")
+                                  .with_description("Payload bytes of replies and received messages")
+                                  .init();
+        let request_duration = meter.f64_histogram("nats_request_duration_ms")
+                                    .with_description("Time until the reply to a request, retries included")
+                                    .init();
+        let request_latency = meter.f64_observable_gauge("nats_request_latency_ms")
+                                   .with_description("Percentiles of the recent request latencies")
+                                   .init();
+
+        Self { requests:         { requests },
+               published:        { published },
+               received:         { received },
+               bytes_sent:       { bytes_sent },
+               bytes_received:   { bytes_received },
+               request_duration: { request_duration },
+               request_latency:  { request_latency }, }
+    }
+}
+
+/// Traffic on the subjects of a label since the domain server started
+#[derive(Clone, Debug, Default)]
+pub struct SubjectTraffic {
+    pub requests:       u64,
+    pub failures:       u64,
+    pub published:      u64,
+    pub received:       u64,
+    pub bytes_sent:     u64,
+    pub bytes_received: u64,
+    latencies_ms:       VecDeque<f64>,
+}
+
+impl SubjectTraffic {
+    fn push_latency(&mut self, latency_ms: f64) {
+        if self.latencies_ms.len() >= LATENCY_WINDOW {
+            self.latencies_ms.pop_front();
+        }
+        self.latencies_ms.push_back(latency_ms);
+    }
+
+    /// Latency below which `quantile` (0 to 1) of the recent requests got their reply
+    pub fn latency_percentile_ms(&self, quantile: f64) -> Option<f64> {
+        percentile(self.latencies_ms.iter().copied(), quantile)
+    }
+}
+
+/// Nearest rank percentile of `values`, `None` if there are none
+pub fn percentile(values: impl Iterator<Item = f64>, quantile: f64) -> Option<f64> {
+    let mut values = values.collect::<Vec<_>>();
+    if values.is_empty() {
+        return None;
+    }
+
+    values.sort_by(|a, b| a.total_cmp(b));
+
+    let rank = (quantile.clamp(0.0, 1.0) * values.len() as f64).ceil() as usize;
+    Some(values[rank.saturating_sub(1)])
+}
+
+/// Label the traffic on `subject` with `label`, subjects carry IDs and are too many to be metric labels themselves
+pub fn label_subject(subject: impl ToString, label: &'static str) {
+    SUBJECT_LABELS.lock().unwrap().insert(subject.to_string(), label);
+}
+
+pub(crate) fn subject_label(subject: &str) -> &'static str {
+    if subject.starts_with("$JS.API.") {
+        return "jetstream_api";
+    }
+
+    SUBJECT_LABELS.lock()
+                  .unwrap()
+                  .get(subject)
+                  .copied()
+                  .unwrap_or(OTHER_SUBJECTS)
+}
+
+fn with_traffic(label: &'static str, f: impl FnOnce(&mut SubjectTraffic)) {
+    f(TRAFFIC.lock().unwrap().entry(label).or_default());
+}
+
+pub(crate) fn record_request(subject: &str, bytes_sent: usize, reply_bytes: Option<usize>, elapsed: Duration) {
+    let label = subject_label(subject);
+    let elapsed_ms = elapsed.as_secs_f64() * 1000.0;
+    let outcome = if reply_bytes.is_some() { "ok" } else { "failed" };
+
+    with_traffic(label, |traffic| {
+        traffic.requests += 1;
+        traffic.bytes_sent += bytes_sent as u64;
+        match reply_bytes {
+            Some(bytes) => {
+                traffic.bytes_received += bytes as u64;
+                traffic.push_latency(elapsed_ms);
+            }
+            None => traffic.failures += 1,
+        }
+    });
+
+    o11y::in_context(|ctx| {
+        let attributes = [KeyValue::new("subject", label)];
+        INSTRUMENTS.requests.add(ctx,
+                                 1,
+                                 &[KeyValue::new("subject", label), KeyValue::new("outcome", outcome)]);
+        INSTRUMENTS.bytes_sent.add(ctx, bytes_sent as u64, &attributes);
+        if let Some(bytes) = reply_bytes {
+            INSTRUMENTS.bytes_received.add(ctx, bytes as u64, &attributes);
+            INSTRUMENTS.request_duration.record(ctx, elapsed_ms, &attributes);
+        }
+    });
+}
+
+pub(crate) fn record_publish(subject: &str, bytes: usize) {
+    let label = subject_label(subject);
+
+    with_traffic(label, |traffic| {
+        traffic.published += 1;
+        traffic.bytes_sent += bytes as u64;
+    });
+
+    o11y::in_context(|ctx| {
+        let attributes = [KeyValue::new("subject", label)];
+        INSTRUMENTS.published.add(ctx, 1, &attributes);
+        INSTRUMENTS.bytes_sent.add(ctx, bytes as u64, &attributes);
+    });
+}
+
+pub(crate) fn record_received(subject: &str, bytes: usize) {
+    let label = subject_label(subject);
+
+    with_traffic(label, |traffic| {
+        traffic.received += 1;
+        traffic.bytes_received += bytes as u64;
+    });
+
+    o11y::in_context(|ctx| {
+        let attributes = [KeyValue::new("subject", label)];
+        INSTRUMENTS.received.add(ctx, 1, &attributes);
+        INSTRUMENTS.bytes_received.add(ctx, bytes as u64, &attributes);
+    });
+}
+
+/// Traffic of every subject label so far
+pub fn traffic() -> HashMap<&'static str, SubjectTraffic> {
+    TRAFFIC.lock().unwrap().clone()
+}
+
+/// Observe the latency percentiles of every label, called periodically as percentiles do not add up like counters
+pub(crate) fn observe_latency_percentiles() {
+    let traffic = traffic();
+
+    o11y::in_context(|ctx| {
+        for (label, traffic) in &traffic {
+            for (quantile, name) in [(0.5, "p50"), (0.95, "p95"), (0.99, "p99")] {
+                if let Some(latency_ms) = traffic.latency_percentile_ms(quantile) {
+                    let attributes = [KeyValue::new("subject", *label), KeyValue::new("quantile", name)];
+                    INSTRUMENTS.request_latency.observe(ctx, latency_ms, &attributes);
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+use crate::nats::label_subject;
+use crate::nats::metrics::{percentile, subject_label};
+use crate::nats::RequestRetry;
+
+#[test]
+fn test_latency_percentiles_use_the_nearest_rank() {
+    let latencies = (1..=100).map(|latency| latency as f64);
+
+    assert_eq!(percentile(latencies.clone(), 0.5), Some(50.0));
+    assert_eq!(percentile(latencies.clone(), 0.95), Some(95.0));
+    assert_eq!(percentile(latencies.clone(), 0.99), Some(99.0));
+    assert_eq!(percentile(latencies.clone(), 0.0), Some(1.0));
+    assert_eq!(percentile(latencies.rev(), 1.0), Some(100.0));
+    assert_eq!(percentile([7.0].into_iter(), 0.99), Some(7.0));
+    assert_eq!(percentile(std::iter::empty(), 0.5), None);
+}
+
+#[test]
+fn test_subject_labels() {
+    label_subject("ac.inst.test.model.1.cmds", "driver_commands");
+
+    assert_eq!(subject_label("ac.inst.test.model.1.cmds"), "driver_commands");
+    assert_eq!(subject_label("$JS.API.STREAM.INFO.events"), "jetstream_api");
+    assert_eq!(subject_label("unlabelled.subject"), "other");
+}
+
+fn request_retry(backoff_ms: u64, max_backoff_ms: u64) -> RequestRetry {
+    RequestRetry { attempt_timeout: { Duration::from_millis(2000) },
+                   deadline:        { Duration::from_millis(10000) },
+                   backoff:         { Duration::from_millis(backoff_ms) },
+                   max_backoff:     { Duration::from_millis(max_backoff_ms) },
+                   buffer:          { 64 }, }
+}
+
+#[test]
+fn test_retry_backoff_doubles_up_to_the_maximum() {
+    let retry = request_retry(100, 1000);
+
+    assert_eq!(retry.backoff(0), Duration::from_millis(100));
+    assert_eq!(retry.backoff(1), Duration::from_millis(200));
+    assert_eq!(retry.backoff(2), Duration::from_millis(400));
+    assert_eq!(retry.backoff(3), Duration::from_millis(800));
+    assert_eq!(retry.backoff(4), Duration::from_millis(1000), "capped");
+    assert_eq!(retry.backoff(10), Duration::from_millis(1000));
+}
+
+#[test]
+fn test_retry_backoff_clamps_the_shift_and_does_not_overflow() {
+    let retry = request_retry(1, u64::MAX);
+
+    assert_eq!(retry.backoff(16), Duration::from_millis(1 << 16));
+    assert_eq!(retry.backoff(17), Duration::from_millis(1 << 16), "shift clamped at 16");
+    assert_eq!(retry.backoff(u32::MAX), Duration::from_millis(1 << 16));
+
+    let retry = request_retry(u64::MAX, u64::MAX);
+    assert_eq!(retry.backoff(1),
+               Duration::from_millis(u64::MAX),
+               "overflowing backoff falls back to the maximum");
+}
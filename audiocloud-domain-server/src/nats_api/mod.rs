@@ -0,0 +1,237 @@
+use std::convert::identity;
+
+use actix::{Handler, Message};
+use clap::Args;
+use futures::StreamExt;
+use nats_aflowt::Message as NatsMessage;
+use serde_json::json;
+use tracing::*;
+
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::{Codec, Json};
+pub use requests::*;
+
+use crate::audit::{audited, AuditEntry, AuditOrigin};
+use crate::fixed_instances::{get_instance_supervisor, ListFixedInstances};
+use crate::rest_api::bad_gateway;
+use crate::tasks::supervisor::TasksSupervisor;
+use crate::tasks::{get_tasks_supervisor, messages};
+use crate::{nats, to_serializable, DomainResult, DomainSecurity};
+
+mod requests;
+#[cfg(test)]
+mod tests;
+
+#[derive(Args, Clone, Debug)]
+pub struct NatsApiOpts {
+    /// Serve the domain API as JSON requests on this NATS subject, so orchestrators in the same NATS mesh can control
+    /// the domain without reaching its REST API. Requests are trusted like the cloud, restrict who may publish to the
+    /// subject with NATS permissions
+    #[clap(long, env)]
+    pub nats_api_subject: Option<String>,
+}
+
+#[instrument(skip_all, err)]
+pub async fn init(opts: NatsApiOpts) -> anyhow::Result<()> {
+    let subject = match opts.nats_api_subject {
+        Some(subject) => subject,
+        None => return Ok(()),
+    };
+
+    nats::label_subject(&subject, "domain_api");
+    let mut requests = Box::pin(nats::subscribe_requests(&subject).await?);
+
+    info!(%subject, "Serving domain API requests over NATS");
+
+    actix::spawn(async move {
+        while let Some(request) = requests.next().await {
+            actix::spawn(respond(request));
+        }
+
+        error!(%subject, "Domain API subscription ended");
+    });
+
+    Ok(())
+}
+
+async fn respond(request: NatsMessage) {
+    let result = match Json.deserialize::<DomainApiRequest>(&request.data) {
+        Ok(api_request) => {
+            trace!(?api_request, "Received domain API request");
+            handle(api_request).await
+        }
+        Err(error) => Err(DomainError::Serialization { error: format!("Invalid domain API request: {error}"), }),
+    };
+
+    if let Err(error) = nats::respond(&request, Json, to_serializable(result)).await {
+        warn!(%error, subject = %request.subject, "Failed to reply to domain API request");
+    }
+}
+
+async fn handle(request: DomainApiRequest) -> DomainResult<DomainApiResponse> {
+    use DomainApiRequest::*;
+
+    let security = DomainSecurity::Cloud;
+
+    match request {
+        ListTasks => {
+            let tasks = get_tasks_supervisor().send(messages::ListTasks)
+                                              .await
+                                              .map_err(bad_gateway)?;
+            Ok(DomainApiResponse::TaskList(tasks))
+        }
+        GetTask { task_id } => send(messages::GetTaskWithStatusAndSpec { task_id }).await
+                                                                                   .map(DomainApiResponse::Task),
+        CreateTask(create) => {
+            // task security holds secure keys, which are never written to the audit log
+            let audit = audit_entry("create_task").with_task(&create.task_id)
+                                                  .with_params(json!({ "reservations": &create.reservations,
+                                                                       "spec": &create.spec }));
+
+            let create = messages::CreateTask { task_id:      { create.task_id },
+                                                reservations: { create.reservations },
+                                                spec:         { create.spec },
+                                                security:     { create.security }, };
+
+            audited(audit, send(create)).await.map(DomainApiResponse::TaskCreated)
+        }
+        ModifyTask { task_id,
+                     revision,
+                     modify, } => {
+            let audit = audit_entry("modify_task").with_task(&task_id).with_params(&modify);
+
+            let modify = messages::ModifyTask { task_id:     { task_id },
+                                                modify_spec: { modify.modify_spec },
+                                                revision:    { revision },
+                                                security:    { security },
+                                                optional:    { false }, };
+
+            audited(audit, send(modify)).await.map(DomainApiResponse::TaskUpdated)
+        }
+        DeleteTask { task_id, revision } => {
+            let audit = audit_entry("delete_task").with_task(&task_id);
+
+            let delete = messages::DeleteTask { task_id:  { task_id },
+                                                revision: { revision },
+                                                security: { security }, };
+
+            audited(audit, send(delete)).await.map(DomainApiResponse::TaskDeleted)
+        }
+        PlayTask { task_id,
+                   revision,
+                   play,
+                   click,
+                   client_id, } => {
+            let audit = audit_entry("play_task").with_task(&task_id).with_params(&play);
+
+            let play = messages::PlayTask { task_id:   { task_id },
+                                            play:      { play },
+                                            click:     { click },
+                                            client_id: { client_id },
+                                            watermark: { None },
+                                            security:  { security },
+                                            revision:  { revision }, };
+
+            audited(audit, send(play)).await.map(DomainApiResponse::TaskPlaying)
+        }
+        PauseTask { task_id,
+                    revision,
+                    pause, } => {
+            let audit = audit_entry("pause_play_task").with_task(&task_id).with_params(&pause);
+
+            let pause = messages::PausePlayTask { task_id:  { task_id },
+                                                  pause:    { pause },
+                                                  security: { security },
+                                                  revision: { revision }, };
+
+            audited(audit, send(pause)).await.map(DomainApiResponse::TaskPlayPause)
+        }
+        ResumeTask { task_id,
+                     revision,
+                     resume, } => {
+            let audit = audit_entry("resume_play_task").with_task(&task_id).with_params(&resume);
+
+            let resume = messages::ResumePlayTask { task_id:  { task_id },
+                                                    resume:   { resume },
+                                                    security: { security },
+                                                    revision: { revision }, };
+
+            audited(audit, send(resume)).await.map(DomainApiResponse::TaskPlayPause)
+        }
+        StopPlayTask { task_id,
+                       revision,
+                       stop, } => {
+            let audit = audit_entry("stop_play_task").with_task(&task_id).with_params(&stop);
+
+            let stop = messages::StopPlayTask { task_id:  { task_id },
+                                                stop:     { stop },
+                                                security: { security },
+                                                revision: { revision }, };
+
+            audited(audit, send(stop)).await.map(DomainApiResponse::TaskPlayStopped)
+        }
+        RenderTask { task_id,
+                     revision,
+                     render,
+                     formats,
+                     normalization, } => {
+            let audit = audit_entry("render_task").with_task(&task_id).with_params(&render);
+
+            let render = messages::RenderTask { task_id:       { task_id },
+                                                render:        { render },
+                                                formats:       { formats },
+                                                normalization: { normalization },
+                                                security:      { security },
+                                                revision:      { revision }, };
+
+            audited(audit, send(render)).await.map(DomainApiResponse::TaskRendering)
+        }
+        CancelRenderTask { task_id,
+                           revision,
+                           cancel, } => {
+            let audit = audit_entry("cancel_render_task").with_task(&task_id)
+                                                         .with_params(&cancel);
+
+            let cancel = messages::CancelRenderTask { task_id:  { task_id },
+                                                      cancel:   { cancel },
+                                                      security: { security },
+                                                      revision: { revision }, };
+
+            audited(audit, send(cancel)).await
+                                        .map(DomainApiResponse::TaskRenderCancelled)
+        }
+        SeekTask { task_id,
+                   revision,
+                   seek, } => {
+            let audit = audit_entry("seek_task").with_task(&task_id).with_params(&seek);
+
+            let seek = messages::SeekTask { task_id:  { task_id },
+                                            seek:     { seek },
+                                            revision: { revision },
+                                            security: { security }, };
+
+            audited(audit, send(seek)).await.map(DomainApiResponse::TaskSought)
+        }
+        ListInstances => {
+            let instances = get_instance_supervisor().send(ListFixedInstances)
+                                                     .await
+                                                     .map_err(bad_gateway)?;
+            Ok(DomainApiResponse::Instances(instances))
+        }
+    }
+}
+
+fn audit_entry(action: &'static str) -> AuditEntry {
+    AuditEntry::new(AuditOrigin::Nats, &DomainSecurity::Cloud, action)
+}
+
+async fn send<M, T>(msg: M) -> DomainResult<T>
+    where M: Message<Result = DomainResult<T>> + Send + 'static,
+          T: Send + 'static,
+          TasksSupervisor: Handler<M>
+{
+    get_tasks_supervisor().send(msg)
+                          .await
+                          .map_err(bad_gateway)
+                          .and_then(identity)
+}
@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+
+use audiocloud_api::audio_engine::{TaskPlayStopped, TaskPlaying, TaskRenderCancelled, TaskRendering, TaskSought};
+use audiocloud_api::domain::tasks::{
+    CreateTask, ModifyTask, TaskCreated, TaskDeleted, TaskSummaryList, TaskUpdated, TaskWithStatusAndSpec,
+};
+use audiocloud_api::{
+    AppTaskId, ClientId, RequestCancelRender, RequestPlay, RequestRender, RequestSeek, RequestStopPlay,
+};
+
+use crate::fixed_instances::FixedInstanceSummary;
+use crate::tasks::engine_ext::RenderFormat;
+use crate::tasks::{RequestPausePlay, TaskClick, TaskPlayPause, TaskRenderNormalization};
+
+/// Request on the domain API subject, the NATS counterpart of the REST API
+///
+/// Requests that change a task carry the revision the REST API takes from the `If-Match` header. Playing, rendering
+/// and stopping set the desired play state of the task, which the domain then works towards. Pausing and resuming
+/// keep the play as it is and only hold its transport.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DomainApiRequest {
+    ListTasks,
+    GetTask {
+        task_id: AppTaskId,
+    },
+    CreateTask(CreateTask),
+    ModifyTask {
+        task_id:  AppTaskId,
+        revision: u64,
+        modify:   ModifyTask,
+    },
+    DeleteTask {
+        task_id:  AppTaskId,
+        revision: u64,
+    },
+    PlayTask {
+        task_id:   AppTaskId,
+        revision:  u64,
+        play:      RequestPlay,
+        #[serde(default)]
+        click:     Option<TaskClick>,
+        #[serde(default)]
+        client_id: Option<ClientId>,
+    },
+    PauseTask {
+        task_id:  AppTaskId,
+        revision: u64,
+        pause:    RequestPausePlay,
+    },
+    ResumeTask {
+        task_id:  AppTaskId,
+        revision: u64,
+        resume:   RequestPausePlay,
+    },
+    StopPlayTask {
+        task_id:  AppTaskId,
+        revision: u64,
+        stop:     RequestStopPlay,
+    },
+    RenderTask {
+        task_id:       AppTaskId,
+        revision:      u64,
+        render:        RequestRender,
+        #[serde(default)]
+        formats:       Vec<RenderFormat>,
+        #[serde(default)]
+        normalization: Option<TaskRenderNormalization>,
+    },
+    CancelRenderTask {
+        task_id:  AppTaskId,
+        revision: u64,
+        cancel:   RequestCancelRender,
+    },
+    SeekTask {
+        task_id:  AppTaskId,
+        revision: u64,
+        seek:     RequestSeek,
+    },
+    ListInstances,
+}
+
+/// Reply to a [`DomainApiRequest`], sent as the `Ok` of a serializable result
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DomainApiResponse {
+    TaskList(TaskSummaryList),
+    Task(TaskWithStatusAndSpec),
+    TaskCreated(TaskCreated),
+    TaskUpdated(TaskUpdated),
+    TaskDeleted(TaskDeleted),
+    TaskPlaying(TaskPlaying),
+    TaskPlayPause(TaskPlayPause),
+    TaskPlayStopped(TaskPlayStopped),
+    TaskRendering(TaskRendering),
+    TaskRenderCancelled(TaskRenderCancelled),
+    TaskSought(TaskSought),
+    Instances(Vec<FixedInstanceSummary>),
+}
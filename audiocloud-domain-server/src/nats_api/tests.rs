@@ -0,0 +1,64 @@
+use serde_json::{json, Value};
+
+use audiocloud_api::{AppId, AppTaskId, PlayId, TaskId};
+
+use crate::nats_api::{DomainApiRequest, DomainApiResponse};
+use crate::tasks::{RequestPausePlay, TaskPlayPause};
+
+fn task_id() -> AppTaskId {
+    AppTaskId::new(AppId::test(), TaskId::new("mix".to_owned()))
+}
+
+/// Serialize, check the JSON is what orchestrators send or receive, and check it reads back to the same JSON
+fn assert_wire_format<T>(value: T, expected: Value)
+    where T: serde::Serialize + serde::de::DeserializeOwned
+{
+    let serialized = serde_json::to_value(&value).expect("serializable");
+    assert_eq!(serialized, expected);
+
+    let deserialized = serde_json::from_value::<T>(expected.clone()).expect("deserializable");
+    assert_eq!(serde_json::to_value(&deserialized).expect("serializable"), expected);
+}
+
+#[test]
+fn test_domain_api_requests_round_trip_as_snake_case_calls() {
+    let task_id_json = serde_json::to_value(task_id()).expect("serializable");
+    let play_id_json = serde_json::to_value(PlayId::new(7)).expect("serializable");
+
+    assert_wire_format(DomainApiRequest::ListTasks, json!("list_tasks"));
+    assert_wire_format(DomainApiRequest::ListInstances, json!("list_instances"));
+    assert_wire_format(DomainApiRequest::GetTask { task_id: task_id() },
+                       json!({ "get_task": { "task_id": task_id_json } }));
+    assert_wire_format(DomainApiRequest::DeleteTask { task_id:  task_id(),
+                                                      revision: 3, },
+                       json!({ "delete_task": { "task_id": task_id_json, "revision": 3 } }));
+    assert_wire_format(DomainApiRequest::PauseTask { task_id:  task_id(),
+                                                     revision: 4,
+                                                     pause:    RequestPausePlay { play_id: PlayId::new(7), }, },
+                       json!({ "pause_task": { "task_id": task_id_json,
+                                               "revision": 4,
+                                               "pause": { "play_id": play_id_json } } }));
+    assert_wire_format(DomainApiRequest::ResumeTask { task_id:  task_id(),
+                                                      revision: 5,
+                                                      resume:   RequestPausePlay { play_id: PlayId::new(7), }, },
+                       json!({ "resume_task": { "task_id": task_id_json,
+                                                "revision": 5,
+                                                "resume": { "play_id": play_id_json } } }));
+}
+
+#[test]
+fn test_domain_api_responses_round_trip_as_snake_case_results() {
+    let play_id_json = serde_json::to_value(PlayId::new(7)).expect("serializable");
+
+    assert_wire_format(DomainApiResponse::TaskPlayPause(TaskPlayPause { play_id: PlayId::new(7),
+                                                                        paused:  true, }),
+                       json!({ "task_play_pause": { "play_id": play_id_json, "paused": true } }));
+    assert_wire_format(DomainApiResponse::Instances(vec![]), json!({ "instances": [] }));
+}
+
+#[test]
+fn test_domain_api_rejects_unknown_calls_and_missing_arguments() {
+    assert!(serde_json::from_value::<DomainApiRequest>(json!("reboot_domain")).is_err());
+    assert!(serde_json::from_value::<DomainApiRequest>(json!({ "pause_task": { "revision": 4 } })).is_err());
+    assert!(serde_json::from_value::<DomainApiRequest>(json!({ "delete_task": { "task_id": "mix" } })).is_err());
+}
@@ -14,8 +14,10 @@ use tracing_subscriber::{EnvFilter, Layer, Registry};
 use audiocloud_api::DomainId;
 
 pub use self::otlp::generate_prometheus_metrics;
+pub use self::recent_logs::recent_logs;
 
 mod otlp;
+mod recent_logs;
 mod sentry;
 
 #[derive(Args, Clone, Debug)]
@@ -55,6 +57,10 @@ pub struct O11yOpts {
     /// This is used to ship only logs to Loki, and is not used for tracing or metrics.
     #[clap(long, env)]
     loki_url: Option<String>,
+
+    /// Log lines kept in memory for support bundles, 0 to keep none
+    #[clap(long, env, default_value = "2000")]
+    recent_log_lines: usize,
 }
 
 #[derive(ValueEnum, Clone, Copy, Debug)]
@@ -111,6 +117,15 @@ pub fn init_tracing(opts: &O11yOpts) -> anyhow::Result<Box<dyn Any>> {
                              }
                          });
 
+    let registry = registry.with(if opts.recent_log_lines > 0 {
+                                     recent_logs::keep_recent_logs(opts.recent_log_lines);
+                                     Some(tracing_subscriber::fmt::layer().with_ansi(false)
+                                                                          .with_writer(recent_logs::RecentLogsWriter)
+                                                                          .with_filter(filter()))
+                                 } else {
+                                     None
+                                 });
+
     let mut guard: Box<dyn Any> = Box::new(());
 
     let registry = registry.with(if let TracingMode::Sentry = opts.tracing {
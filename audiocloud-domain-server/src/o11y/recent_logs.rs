@@ -0,0 +1,78 @@
+use std::collections::VecDeque;
+use std::io;
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use tracing_subscriber::fmt::MakeWriter;
+
+static RECENT_LOGS: Lazy<Mutex<RecentLogs>> = Lazy::new(Default::default);
+
+#[derive(Default)]
+struct RecentLogs {
+    lines:    VecDeque<String>,
+    capacity: usize,
+}
+
+/// Keep the most recent `capacity` log lines in memory, for support bundles
+pub(crate) fn keep_recent_logs(capacity: usize) {
+    if let Ok(mut logs) = RECENT_LOGS.lock() {
+        logs.capacity = capacity;
+        while logs.lines.len() > capacity {
+            logs.lines.pop_front();
+        }
+    }
+}
+
+/// Log lines kept in memory, oldest first
+pub fn recent_logs() -> Vec<String> {
+    RECENT_LOGS.lock()
+               .map(|logs| logs.lines.iter().cloned().collect())
+               .unwrap_or_default()
+}
+
+/// Writer of the formatting layer that keeps log lines in memory
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct RecentLogsWriter;
+
+impl<'a> MakeWriter<'a> for RecentLogsWriter {
+    type Writer = RecentLogLine;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RecentLogLine(Vec::new())
+    }
+}
+
+/// A formatted event, kept once the layer is done writing it
+pub(crate) struct RecentLogLine(Vec<u8>);
+
+impl io::Write for RecentLogLine {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for RecentLogLine {
+    fn drop(&mut self) {
+        let line = String::from_utf8_lossy(&self.0).trim_end().to_owned();
+        if line.is_empty() {
+            return;
+        }
+
+        if let Ok(mut logs) = RECENT_LOGS.lock() {
+            if logs.capacity == 0 {
+                return;
+            }
+
+            if logs.lines.len() == logs.capacity {
+                logs.lines.pop_front();
+            }
+
+            logs.lines.push_back(line);
+        }
+    }
+}
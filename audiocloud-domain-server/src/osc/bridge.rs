@@ -0,0 +1,456 @@
+#![allow(unused_variables)]
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use actix::{Actor, ActorFutureExt, AsyncContext, Context, ContextFutureSpawner, Handler, WrapFuture};
+use actix_broker::BrokerSubscribe;
+use futures::future::Either;
+use rosc::{encoder, OscMessage, OscPacket};
+use tokio::net::UdpSocket;
+use tracing::*;
+
+use audiocloud_api::common::change::ModifyTaskSpec;
+use audiocloud_api::common::task::ConnectionValues;
+use audiocloud_api::domain::tasks::TaskUpdated;
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::newtypes::NodeConnectionId;
+use audiocloud_api::AppTaskId;
+
+use crate::osc::{NotifyOscPacket, OscControl, OscFeedback, OscOpts};
+use crate::tasks::{
+    get_tasks_supervisor, GetTaskTrackGroups, GetTaskTransport, ModifyTask, NotifyTaskActivated,
+    NotifyTaskConnectionFaders, NotifyTaskDeactivated, NotifyTaskDeleted, NotifyTaskSpec, NotifyTaskTrackGroups,
+    PausePlayTask, RequestPausePlay, ResumePlayTask, SetTaskTrackGroups, TaskTrackGroups, TaskTransport,
+};
+use crate::DomainSecurity;
+
+/// How often surfaces that went quiet are forgotten
+const EXPIRE_SURFACES_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Translates OSC from control surfaces into commands on the selected task and sends its state back to them
+///
+/// Commands are trusted like the cloud, the bind address of the socket is what keeps strangers out.
+pub struct OscBridge {
+    socket:       Arc<UdpSocket>,
+    opts:         OscOpts,
+    /// Surfaces that sent us something, with the time of their last message
+    surfaces:     HashMap<SocketAddr, Instant>,
+    task_id:      Option<AppTaskId>,
+    transport:    TaskTransport,
+    track_groups: TaskTrackGroups,
+    revisions:    HashMap<AppTaskId, u64>,
+    faders:       HashMap<AppTaskId, HashMap<NodeConnectionId, ConnectionValues>>,
+}
+
+impl Actor for OscBridge {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.subscribe_system_async::<NotifyTaskActivated>(ctx);
+        self.subscribe_system_async::<NotifyTaskDeactivated>(ctx);
+        self.subscribe_system_async::<NotifyTaskDeleted>(ctx);
+        self.subscribe_system_async::<NotifyTaskSpec>(ctx);
+        self.subscribe_system_async::<NotifyTaskConnectionFaders>(ctx);
+        self.subscribe_system_async::<NotifyTaskTrackGroups>(ctx);
+
+        ctx.run_interval(Duration::from_millis(self.opts.osc_transport_poll_ms),
+                         Self::poll_transport);
+        ctx.run_interval(EXPIRE_SURFACES_INTERVAL, Self::expire_surfaces);
+    }
+}
+
+impl OscBridge {
+    pub fn new(socket: Arc<UdpSocket>, opts: OscOpts) -> Self {
+        Self { socket:       { socket },
+               opts:         { opts },
+               surfaces:     { HashMap::new() },
+               task_id:      { None },
+               transport:    { TaskTransport::default() },
+               track_groups: { TaskTrackGroups::default() },
+               revisions:    { HashMap::new() },
+               faders:       { HashMap::new() }, }
+    }
+
+    fn expire_surfaces(&mut self, ctx: &mut Context<Self>) {
+        let timeout = Duration::from_secs(self.opts.osc_surface_timeout_seconds);
+        self.surfaces.retain(|_, last_seen| last_seen.elapsed() < timeout);
+    }
+
+    fn send_to(&self, addr: SocketAddr, feedback: &[OscFeedback]) {
+        for feedback in feedback {
+            let packet = match encoder::encode(&OscPacket::Message(feedback.to_message())) {
+                Ok(packet) => packet,
+                Err(error) => {
+                    warn!(?error, ?feedback, "Failed to encode OSC feedback");
+                    continue;
+                }
+            };
+
+            // feedback is only a hint to the surface, so a full socket buffer drops it instead of waiting
+            if let Err(error) = self.socket.try_send_to(&packet, addr) {
+                debug!(%error, %addr, "Failed to send OSC feedback");
+            }
+        }
+    }
+
+    fn broadcast(&self, feedback: &[OscFeedback]) {
+        if feedback.is_empty() {
+            return;
+        }
+
+        for addr in self.surfaces.keys().chain(self.opts.osc_feedback_addrs.iter()) {
+            self.send_to(*addr, feedback);
+        }
+    }
+
+    /// Everything a surface needs to show the selected task
+    fn state(&self) -> Vec<OscFeedback> {
+        let mut state = vec![OscFeedback::Task(self.task_id.clone())];
+        state.extend(transport_feedback(&self.transport));
+
+        if let Some(faders) = self.task_id.as_ref().and_then(|task_id| self.faders.get(task_id)) {
+            for (connection_id, values) in faders {
+                state.extend(fader_feedback(connection_id, values));
+            }
+        }
+
+        for (name, group) in &self.track_groups.groups {
+            state.push(OscFeedback::GroupGain(name.clone(), group.gain_db));
+        }
+
+        state
+    }
+
+    fn select(&mut self, task_id: Option<AppTaskId>, ctx: &mut Context<Self>) {
+        self.task_id = task_id.clone();
+        self.transport = TaskTransport::default();
+        self.track_groups = TaskTrackGroups::default();
+
+        self.broadcast(&self.state());
+
+        let task_id = match task_id {
+            Some(task_id) => task_id,
+            None => return,
+        };
+
+        info!(%task_id, "OSC surfaces now control task");
+
+        let get = GetTaskTrackGroups { task_id:  { task_id.clone() },
+                                       security: { DomainSecurity::Cloud }, };
+
+        get_tasks_supervisor().send(get)
+                              .into_actor(self)
+                              .map(move |res, actor, ctx| match res {
+                                  Ok(Ok(track_groups)) => actor.set_track_groups(&task_id, track_groups),
+                                  Ok(Err(error)) => warn!(%error, %task_id, "Failed to get track groups for OSC"),
+                                  Err(error) => warn!(%error, %task_id, "Failed to get track groups for OSC"),
+                              })
+                              .spawn(ctx);
+
+        self.poll_transport(ctx);
+    }
+
+    fn set_track_groups(&mut self, task_id: &AppTaskId, track_groups: TaskTrackGroups) {
+        if self.task_id.as_ref() != Some(task_id) {
+            return;
+        }
+
+        let feedback = track_groups.groups
+                                   .iter()
+                                   .filter(|(name, group)| self.track_groups.groups.get(*name) != Some(*group))
+                                   .map(|(name, group)| OscFeedback::GroupGain(name.clone(), group.gain_db))
+                                   .collect::<Vec<_>>();
+
+        self.track_groups = track_groups;
+        self.broadcast(&feedback);
+    }
+
+    fn poll_transport(&mut self, ctx: &mut Context<Self>) {
+        let task_id = match self.task_id.clone() {
+            Some(task_id) => task_id,
+            None => return,
+        };
+
+        let get = GetTaskTransport { task_id:  { task_id.clone() },
+                                     security: { DomainSecurity::Cloud }, };
+
+        get_tasks_supervisor().send(get)
+                              .into_actor(self)
+                              .map(move |res, actor, ctx| match res {
+                                  Ok(Ok(transport)) => actor.set_transport(&task_id, transport),
+                                  Ok(Err(error)) => debug!(%error, %task_id, "Failed to get transport for OSC"),
+                                  Err(error) => warn!(%error, %task_id, "Failed to get transport for OSC"),
+                              })
+                              .spawn(ctx);
+    }
+
+    fn set_transport(&mut self, task_id: &AppTaskId, transport: TaskTransport) {
+        if self.task_id.as_ref() != Some(task_id) || self.transport == transport {
+            return;
+        }
+
+        self.transport = transport;
+        self.broadcast(&transport_feedback(&self.transport));
+    }
+
+    fn handle_control(&mut self, from: SocketAddr, control: OscControl, ctx: &mut Context<Self>) {
+        use OscControl::*;
+
+        match control {
+            SelectTask(task_id) => self.select(Some(task_id), ctx),
+            Refresh => self.send_to(from, &self.state()),
+            control => {
+                let task_id = match self.task_id.clone() {
+                    Some(task_id) => task_id,
+                    None => {
+                        debug!(?control, %from, "No task selected for OSC control");
+                        return;
+                    }
+                };
+
+                match control {
+                    Pause => self.pause_play(task_id, true, ctx),
+                    Resume => self.pause_play(task_id, false, ctx),
+                    ConnectionVolume { connection_id, volume } => {
+                        let values = ConnectionValues { volume: Some(volume),
+                                                        pan:    None, };
+                        self.set_connection_values(task_id, connection_id, values, ctx)
+                    }
+                    ConnectionPan { connection_id, pan } => {
+                        let values = ConnectionValues { volume: None,
+                                                        pan:    Some(pan), };
+                        self.set_connection_values(task_id, connection_id, values, ctx)
+                    }
+                    GroupGain { group, gain_db } => self.set_group_gain(task_id, group, gain_db, ctx),
+                    SelectTask(_) | Refresh => {}
+                }
+            }
+        }
+    }
+
+    fn pause_play(&mut self, task_id: AppTaskId, paused: bool, ctx: &mut Context<Self>) {
+        let play_id = match self.transport.play_id.clone() {
+            Some(play_id) => play_id,
+            None => {
+                debug!(%task_id, paused, "Task is not playing, ignoring OSC transport control");
+                return;
+            }
+        };
+
+        let request = RequestPausePlay { play_id };
+        let revision = self.revisions.get(&task_id).copied().unwrap_or_default();
+
+        let fut = if paused {
+            Either::Left(get_tasks_supervisor().send(PausePlayTask { task_id:  { task_id.clone() },
+                                                                     pause:    { request },
+                                                                     security: { DomainSecurity::Cloud },
+                                                                     revision: { revision }, }))
+        } else {
+            Either::Right(get_tasks_supervisor().send(ResumePlayTask { task_id:  { task_id.clone() },
+                                                                       resume:   { request },
+                                                                       security: { DomainSecurity::Cloud },
+                                                                       revision: { revision }, }))
+        };
+
+        fut.into_actor(self)
+           .map(move |res, actor, ctx| match res {
+               Ok(Ok(pause)) if actor.task_id.as_ref() == Some(&task_id) => {
+                   actor.transport.paused = pause.paused;
+                   actor.broadcast(&[OscFeedback::Paused(pause.paused)]);
+               }
+               Ok(Ok(_)) => {}
+               Ok(Err(error)) => warn!(%error, %task_id, paused, "Failed to pause or resume task from OSC"),
+               Err(error) => warn!(%error, %task_id, paused, "Failed to pause or resume task from OSC"),
+           })
+           .spawn(ctx);
+    }
+
+    fn set_connection_values(&mut self,
+                             task_id: AppTaskId,
+                             connection_id: NodeConnectionId,
+                             values: ConnectionValues,
+                             ctx: &mut Context<Self>) {
+        let modify = ModifyTaskSpec::SetConnectionParameterValues { connection_id: { connection_id },
+                                                                    values:        { values }, };
+
+        self.modify_task(task_id, vec![modify], true, ctx);
+    }
+
+    /// Modify the task at the last known revision, the fader moves of a surface always win over older revisions
+    fn modify_task(&mut self,
+                   task_id: AppTaskId,
+                   modify_spec: Vec<ModifyTaskSpec>,
+                   retry: bool,
+                   ctx: &mut Context<Self>) {
+        let modify = ModifyTask { task_id:     { task_id.clone() },
+                                  modify_spec: { modify_spec.clone() },
+                                  revision:    { self.revisions.get(&task_id).copied().unwrap_or_default() },
+                                  security:    { DomainSecurity::Cloud },
+                                  optional:    { false }, };
+
+        get_tasks_supervisor().send(modify)
+                              .into_actor(self)
+                              .map(move |res, actor, ctx| match res {
+                                  Ok(Ok(TaskUpdated::Updated { revision, .. })) => {
+                                      actor.revisions.insert(task_id, revision);
+                                  }
+                                  Ok(Ok(_)) => {}
+                                  Ok(Err(DomainError::TaskModificationRevisionOutOfDate { revision, .. })) if retry => {
+                                      actor.revisions.insert(task_id.clone(), revision);
+                                      actor.modify_task(task_id, modify_spec, false, ctx);
+                                  }
+                                  Ok(Err(error)) => warn!(%error, %task_id, "Failed to modify task from OSC"),
+                                  Err(error) => warn!(%error, %task_id, "Failed to modify task from OSC"),
+                              })
+                              .spawn(ctx);
+    }
+
+    fn set_group_gain(&mut self, task_id: AppTaskId, group: String, gain_db: f64, ctx: &mut Context<Self>) {
+        let mut track_groups = self.track_groups.clone();
+        match track_groups.groups.get_mut(&group) {
+            Some(track_group) => track_group.gain_db = gain_db,
+            None => {
+                debug!(%task_id, %group, "Unknown track group in OSC control");
+                return;
+            }
+        }
+
+        let set = SetTaskTrackGroups { task_id:      { task_id.clone() },
+                                       track_groups: { track_groups },
+                                       security:     { DomainSecurity::Cloud }, };
+
+        get_tasks_supervisor().send(set)
+                              .into_actor(self)
+                              .map(move |res, actor, ctx| match res {
+                                  Ok(Ok(_)) => {}
+                                  Ok(Err(error)) => {
+                                      warn!(%error, %task_id, %group, "Failed to set group gain from OSC")
+                                  }
+                                  Err(error) => warn!(%error, %task_id, %group, "Failed to set group gain from OSC"),
+                              })
+                              .spawn(ctx);
+    }
+}
+
+fn transport_feedback(transport: &TaskTransport) -> Vec<OscFeedback> {
+    vec![OscFeedback::Playing(transport.play_id.is_some()),
+         OscFeedback::Paused(transport.paused),
+         OscFeedback::Rendering(transport.rendering),]
+}
+
+fn fader_feedback(connection_id: &NodeConnectionId, values: &ConnectionValues) -> Vec<OscFeedback> {
+    let volume = values.volume
+                       .map(|volume| OscFeedback::ConnectionVolume(connection_id.clone(), volume));
+    let pan = values.pan
+                    .map(|pan| OscFeedback::ConnectionPan(connection_id.clone(), pan));
+
+    volume.into_iter().chain(pan).collect()
+}
+
+fn flatten_packet(packet: OscPacket, messages: &mut Vec<OscMessage>) {
+    match packet {
+        OscPacket::Message(message) => messages.push(message),
+        OscPacket::Bundle(bundle) => {
+            for packet in bundle.content {
+                flatten_packet(packet, messages);
+            }
+        }
+    }
+}
+
+impl Handler<NotifyOscPacket> for OscBridge {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyOscPacket, ctx: &mut Self::Context) -> Self::Result {
+        let from = msg.from;
+        self.surfaces.insert(from, Instant::now());
+
+        let mut messages = vec![];
+        flatten_packet(msg.packet, &mut messages);
+
+        for message in messages {
+            match OscControl::parse(&message) {
+                Ok(Some(control)) => self.handle_control(from, control, ctx),
+                Ok(None) => {}
+                Err(error) => debug!(%error, %from, "Ignoring OSC message"),
+            }
+        }
+    }
+}
+
+impl Handler<NotifyTaskActivated> for OscBridge {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskActivated, ctx: &mut Self::Context) -> Self::Result {
+        // surfaces follow the first task to become active until they select another one
+        if self.task_id.is_none() {
+            self.select(Some(msg.task_id), ctx);
+        }
+    }
+}
+
+impl Handler<NotifyTaskDeactivated> for OscBridge {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskDeactivated, ctx: &mut Self::Context) -> Self::Result {
+        if self.task_id.as_ref() == Some(&msg.task_id) {
+            self.select(None, ctx);
+        }
+    }
+}
+
+impl Handler<NotifyTaskDeleted> for OscBridge {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskDeleted, ctx: &mut Self::Context) -> Self::Result {
+        self.revisions.remove(&msg.task_id);
+        self.faders.remove(&msg.task_id);
+
+        if self.task_id.as_ref() == Some(&msg.task_id) {
+            self.select(None, ctx);
+        }
+    }
+}
+
+impl Handler<NotifyTaskSpec> for OscBridge {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskSpec, ctx: &mut Self::Context) -> Self::Result {
+        self.revisions.insert(msg.task_id, msg.spec.revision);
+    }
+}
+
+impl Handler<NotifyTaskConnectionFaders> for OscBridge {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskConnectionFaders, ctx: &mut Self::Context) -> Self::Result {
+        let previous = self.faders.remove(&msg.task_id).unwrap_or_default();
+
+        if self.task_id.as_ref() == Some(&msg.task_id) {
+            let mut feedback = vec![];
+            for (connection_id, values) in &msg.faders {
+                let changed = previous.get(connection_id)
+                                      .map(|prev| prev.volume != values.volume || prev.pan != values.pan)
+                                      .unwrap_or(true);
+                if changed {
+                    feedback.extend(fader_feedback(connection_id, values));
+                }
+            }
+
+            self.broadcast(&feedback);
+        }
+
+        self.faders.insert(msg.task_id, msg.faders);
+    }
+}
+
+impl Handler<NotifyTaskTrackGroups> for OscBridge {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskTrackGroups, ctx: &mut Self::Context) -> Self::Result {
+        self.set_track_groups(&msg.task_id, msg.track_groups);
+    }
+}
@@ -0,0 +1,138 @@
+use rosc::{OscMessage, OscType};
+
+use audiocloud_api::newtypes::{AppId, NodeConnectionId, TaskId};
+use audiocloud_api::AppTaskId;
+
+/// Prefix of every address the bridge receives and sends
+pub const OSC_PREFIX: &str = "/audiocloud";
+
+/// What a control surface asked for
+#[derive(Clone, Debug, PartialEq)]
+pub enum OscControl {
+    /// `/audiocloud/task s:app_id s:task_id`
+    SelectTask(AppTaskId),
+    /// `/audiocloud/transport/pause`
+    Pause,
+    /// `/audiocloud/transport/resume`
+    Resume,
+    /// `/audiocloud/connection/<connection_id>/volume f:linear_gain`
+    ConnectionVolume {
+        connection_id: NodeConnectionId,
+        volume:        f64,
+    },
+    /// `/audiocloud/connection/<connection_id>/pan f:-1..1`
+    ConnectionPan {
+        connection_id: NodeConnectionId,
+        pan:           f64,
+    },
+    /// `/audiocloud/group/<group>/gain f:dB`
+    GroupGain { group: String, gain_db: f64 },
+    /// `/audiocloud/refresh`, send the complete state back
+    Refresh,
+}
+
+impl OscControl {
+    /// Parse an incoming message, `Ok(None)` for messages that need no action such as the release of a button
+    pub fn parse(message: &OscMessage) -> Result<Option<Self>, String> {
+        let addr = message.addr
+                          .strip_prefix(OSC_PREFIX)
+                          .ok_or_else(|| format!("Address {} outside of {OSC_PREFIX}", message.addr))?;
+
+        let parts = addr.trim_start_matches('/').split('/').collect::<Vec<_>>();
+        let args = &message.args;
+
+        Ok(match parts.as_slice() {
+            ["task"] => match args.as_slice() {
+                [OscType::String(app_id), OscType::String(task_id)] => {
+                    Some(Self::SelectTask(AppTaskId { app_id:  { AppId::new(app_id.clone()) },
+                                                      task_id: { TaskId::new(task_id.clone()) }, }))
+                }
+                _ => return Err(format!("{} takes the app ID and the task ID as strings", message.addr)),
+            },
+            ["transport", "pause"] => is_pressed(args).then_some(Self::Pause),
+            ["transport", "resume"] => is_pressed(args).then_some(Self::Resume),
+            ["connection", connection_id, "volume"] => {
+                let volume = float_arg(message)?;
+                if volume < 0.0 {
+                    return Err(format!("{} takes a volume of at least 0", message.addr));
+                }
+
+                Some(Self::ConnectionVolume { connection_id: { NodeConnectionId::new(connection_id.to_string()) },
+                                              volume:        { volume }, })
+            }
+            ["connection", connection_id, "pan"] => {
+                Some(Self::ConnectionPan { connection_id: { NodeConnectionId::new(connection_id.to_string()) },
+                                           pan:           { float_arg(message)?.clamp(-1.0, 1.0) }, })
+            }
+            ["group", group, "gain"] => Some(Self::GroupGain { group:   { group.to_string() },
+                                                               gain_db: { float_arg(message)? }, }),
+            ["refresh"] => Some(Self::Refresh),
+            _ => return Err(format!("Unknown address {}", message.addr)),
+        })
+    }
+}
+
+/// State sent to the control surfaces
+#[derive(Clone, Debug, PartialEq)]
+pub enum OscFeedback {
+    Task(Option<AppTaskId>),
+    Playing(bool),
+    Paused(bool),
+    Rendering(bool),
+    ConnectionVolume(NodeConnectionId, f64),
+    ConnectionPan(NodeConnectionId, f64),
+    GroupGain(String, f64),
+}
+
+impl OscFeedback {
+    pub fn to_message(&self) -> OscMessage {
+        let (addr, args) = match self {
+            Self::Task(Some(task_id)) => ("/task".to_string(),
+                                          vec![OscType::String(task_id.app_id.to_string()),
+                                               OscType::String(task_id.task_id.to_string()),]),
+            Self::Task(None) => ("/task".to_string(), vec![]),
+            Self::Playing(playing) => ("/transport/playing".to_string(), vec![OscType::Int(*playing as i32)]),
+            Self::Paused(paused) => ("/transport/paused".to_string(), vec![OscType::Int(*paused as i32)]),
+            Self::Rendering(rendering) => ("/transport/rendering".to_string(), vec![OscType::Int(*rendering as i32)]),
+            Self::ConnectionVolume(connection_id, volume) => {
+                (format!("/connection/{connection_id}/volume"), vec![OscType::Float(*volume as f32)])
+            }
+            Self::ConnectionPan(connection_id, pan) => {
+                (format!("/connection/{connection_id}/pan"), vec![OscType::Float(*pan as f32)])
+            }
+            Self::GroupGain(group, gain_db) => (format!("/group/{group}/gain"), vec![OscType::Float(*gain_db as f32)]),
+        };
+
+        OscMessage { addr: format!("{OSC_PREFIX}{addr}"),
+                     args }
+    }
+}
+
+/// Buttons send a non-zero value when pressed and zero when released, messages without arguments count as pressed
+fn is_pressed(args: &[OscType]) -> bool {
+    match args.first() {
+        None => true,
+        Some(OscType::Float(value)) => *value != 0.0,
+        Some(OscType::Double(value)) => *value != 0.0,
+        Some(OscType::Int(value)) => *value != 0,
+        Some(OscType::Long(value)) => *value != 0,
+        Some(OscType::Bool(value)) => *value,
+        Some(_) => true,
+    }
+}
+
+fn float_arg(message: &OscMessage) -> Result<f64, String> {
+    let value = match message.args.first() {
+        Some(OscType::Float(value)) => *value as f64,
+        Some(OscType::Double(value)) => *value,
+        Some(OscType::Int(value)) => *value as f64,
+        Some(OscType::Long(value)) => *value as f64,
+        _ => return Err(format!("{} takes a number", message.addr)),
+    };
+
+    if value.is_finite() {
+        Ok(value)
+    } else {
+        Err(format!("{} takes a finite number", message.addr))
+    }
+}
@@ -0,0 +1,11 @@
+use std::net::SocketAddr;
+
+use actix::Message;
+use rosc::OscPacket;
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyOscPacket {
+    pub from:   SocketAddr,
+    pub packet: OscPacket,
+}
@@ -0,0 +1,73 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use actix::Actor;
+use clap::Args;
+use rosc::decoder;
+use tokio::net::UdpSocket;
+use tracing::*;
+
+use bridge::OscBridge;
+pub use controls::{OscControl, OscFeedback, OSC_PREFIX};
+pub use messages::*;
+
+mod bridge;
+pub mod controls;
+pub mod messages;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Args, Clone, Debug)]
+pub struct OscOpts {
+    /// Receive OSC from control surfaces on this UDP address, for example `0.0.0.0:9000`. Anyone who can reach it may
+    /// change the levels and transport of the selected task, so bind it to the studio network only
+    #[clap(long, env)]
+    pub osc_bind: Option<SocketAddr>,
+
+    /// Also send state feedback to these addresses, for surfaces that do not listen on the port they send from
+    #[clap(long, env, value_delimiter = ',')]
+    pub osc_feedback_addrs: Vec<SocketAddr>,
+
+    /// Stop sending feedback to a surface after this many seconds without a message from it
+    #[clap(long, env, default_value = "60")]
+    pub osc_surface_timeout_seconds: u64,
+
+    /// How often the transport of the selected task is checked for changes, in milliseconds
+    #[clap(long, env, default_value = "250")]
+    pub osc_transport_poll_ms: u64,
+}
+
+#[instrument(skip_all, err)]
+pub async fn init(opts: OscOpts) -> anyhow::Result<()> {
+    let bind = match opts.osc_bind {
+        Some(bind) => bind,
+        None => return Ok(()),
+    };
+
+    let socket = Arc::new(UdpSocket::bind(bind).await?);
+    let bridge = OscBridge::new(socket.clone(), opts).start();
+
+    info!(%bind, "Bridging task controls to OSC");
+
+    actix::spawn(async move {
+        let mut buf = vec![0u8; decoder::MTU];
+
+        loop {
+            let (len, from) = match socket.recv_from(&mut buf).await {
+                Ok(received) => received,
+                Err(error) => {
+                    error!(%error, "OSC socket failed");
+                    break;
+                }
+            };
+
+            match decoder::decode_udp(&buf[..len]) {
+                Ok((_, packet)) => bridge.do_send(NotifyOscPacket { from, packet }),
+                Err(error) => debug!(?error, %from, "Ignoring invalid OSC packet"),
+            }
+        }
+    });
+
+    Ok(())
+}
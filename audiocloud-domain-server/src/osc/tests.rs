@@ -0,0 +1,76 @@
+use rosc::{OscMessage, OscType};
+
+use audiocloud_api::newtypes::{AppId, NodeConnectionId, TaskId};
+use audiocloud_api::AppTaskId;
+
+use crate::osc::{OscControl, OscFeedback};
+
+fn message(addr: &str, args: Vec<OscType>) -> OscMessage {
+    OscMessage { addr: addr.to_string(),
+                 args }
+}
+
+#[test]
+fn test_task_selection_takes_app_and_task_id() {
+    let select = message("/audiocloud/task",
+                         vec![OscType::String("app".to_string()), OscType::String("task".to_string())]);
+
+    assert_eq!(OscControl::parse(&select).unwrap(),
+               Some(OscControl::SelectTask(AppTaskId { app_id:  AppId::new("app".to_string()),
+                                                       task_id: TaskId::new("task".to_string()), })));
+
+    assert!(OscControl::parse(&message("/audiocloud/task", vec![])).is_err());
+}
+
+#[test]
+fn test_transport_buttons_act_on_press_only() {
+    let pressed = message("/audiocloud/transport/pause", vec![OscType::Float(1.0)]);
+    let released = message("/audiocloud/transport/pause", vec![OscType::Float(0.0)]);
+    let bare = message("/audiocloud/transport/resume", vec![]);
+
+    assert_eq!(OscControl::parse(&pressed).unwrap(), Some(OscControl::Pause));
+    assert_eq!(OscControl::parse(&released).unwrap(), None);
+    assert_eq!(OscControl::parse(&bare).unwrap(), Some(OscControl::Resume));
+}
+
+#[test]
+fn test_connection_levels_are_validated() {
+    let volume = message("/audiocloud/connection/c1/volume", vec![OscType::Float(0.5)]);
+    let negative = message("/audiocloud/connection/c1/volume", vec![OscType::Float(-0.5)]);
+    let pan = message("/audiocloud/connection/c1/pan", vec![OscType::Double(3.0)]);
+    let text = message("/audiocloud/connection/c1/pan",
+                       vec![OscType::String("left".to_string())]);
+
+    assert_eq!(OscControl::parse(&volume).unwrap(),
+               Some(OscControl::ConnectionVolume { connection_id: NodeConnectionId::new("c1".to_string()),
+                                                   volume:        0.5, }));
+    assert!(OscControl::parse(&negative).is_err());
+    assert_eq!(OscControl::parse(&pan).unwrap(),
+               Some(OscControl::ConnectionPan { connection_id: NodeConnectionId::new("c1".to_string()),
+                                                pan:           1.0, }));
+    assert!(OscControl::parse(&text).is_err());
+}
+
+#[test]
+fn test_addresses_outside_of_prefix_are_rejected() {
+    assert!(OscControl::parse(&message("/other/refresh", vec![])).is_err());
+    assert!(OscControl::parse(&message("/audiocloud/unknown", vec![])).is_err());
+    assert_eq!(OscControl::parse(&message("/audiocloud/refresh", vec![])).unwrap(),
+               Some(OscControl::Refresh));
+}
+
+#[test]
+fn test_feedback_mirrors_control_addresses() {
+    let gain = OscFeedback::GroupGain("drums".to_string(), -6.0).to_message();
+    assert_eq!(gain.addr, "/audiocloud/group/drums/gain");
+    assert_eq!(gain.args, vec![OscType::Float(-6.0)]);
+
+    // surfaces that echo feedback set the value that is already there
+    assert_eq!(OscControl::parse(&gain).unwrap(),
+               Some(OscControl::GroupGain { group:   "drums".to_string(),
+                                            gain_db: -6.0, }));
+
+    let paused = OscFeedback::Paused(true).to_message();
+    assert_eq!(paused.addr, "/audiocloud/transport/paused");
+    assert_eq!(paused.args, vec![OscType::Int(1)]);
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tracing::*;
+
+static REST_RATE_LIMITER: Lazy<RateLimiter> = Lazy::new(RateLimiter::disabled);
+static SOCKET_RATE_LIMITER: Lazy<RateLimiter> = Lazy::new(RateLimiter::disabled);
+
+/// Rate limits this domain server reads from the `rate_limits` section of the domain config
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct RateLimits {
+    /// REST requests per client (JWT subject or IP address)
+    pub rest:         RateLimit,
+    /// Socket messages per client
+    pub socket:       RateLimit,
+    /// Forget clients that have not made a request in this many seconds
+    pub idle_seconds: u64,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self { rest:         {
+                   RateLimit { per_second: { 20.0 },
+                               burst:      { 50.0 }, }
+               },
+               socket:       {
+                   RateLimit { per_second: { 50.0 },
+                               burst:      { 100.0 }, }
+               },
+               idle_seconds: { 300 }, }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    /// Sustained rate allowed per client, 0 to disable
+    pub per_second: f64,
+    /// Number of requests a client may burst above the sustained rate
+    pub burst:      f64,
+}
+
+/// The part of the domain config the rate limits are read from
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct RateLimitConfig {
+    #[serde(default)]
+    pub rate_limits: RateLimits,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct TokenBucket {
+    tokens:     f64,
+    updated_at: Instant,
+}
+
+struct RateLimiterState {
+    per_second:  f64,
+    burst:       f64,
+    idle:        Duration,
+    buckets:     HashMap<String, TokenBucket>,
+    last_purged: Instant,
+}
+
+/// Token bucket rate limiter keyed by client, shared between all workers
+pub struct RateLimiter {
+    state: Mutex<RateLimiterState>,
+}
+
+impl RateLimiter {
+    pub fn new(per_second: f64, burst: f64, idle: Duration) -> Self {
+        Self { state: {
+                   Mutex::new(RateLimiterState { per_second:  { per_second },
+                                                 burst:       { burst.max(1.0) },
+                                                 idle:        { idle },
+                                                 buckets:     { HashMap::new() },
+                                                 last_purged: { Instant::now() }, })
+               }, }
+    }
+
+    fn disabled() -> Self {
+        Self::new(0.0, 1.0, Duration::ZERO)
+    }
+
+    /// Change the rate, keeping the buckets of the clients already seen
+    pub fn reconfigure(&self, per_second: f64, burst: f64, idle: Duration) {
+        match self.state.lock() {
+            Ok(mut state) => {
+                state.per_second = per_second;
+                state.burst = burst.max(1.0);
+                state.idle = idle;
+            }
+            Err(_) => warn!("Rate limiter lock poisoned, keeping previous limits"),
+        }
+    }
+
+    /// Take a token from the client's bucket, or return how long the client has to wait for the next one
+    pub fn check(&self, client: &str) -> Result<(), Duration> {
+        self.check_at(client, Instant::now())
+    }
+
+    /// Same as `check`, at a given time
+    pub fn check_at(&self, client: &str, now: Instant) -> Result<(), Duration> {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => {
+                warn!("Rate limiter lock poisoned, allowing request");
+                return Ok(());
+            }
+        };
+
+        if state.per_second <= 0.0 {
+            return Ok(());
+        }
+
+        if now.saturating_duration_since(state.last_purged) > state.idle {
+            let idle = state.idle;
+            state.buckets
+                 .retain(|_, bucket| now.saturating_duration_since(bucket.updated_at) <= idle);
+            state.last_purged = now;
+        }
+
+        let (per_second, burst) = (state.per_second, state.burst);
+        let bucket = state.buckets
+                          .entry(client.to_owned())
+                          .or_insert(TokenBucket { tokens:     { burst },
+                                                   updated_at: { now }, });
+
+        let elapsed = now.saturating_duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_second).min(burst);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_second))
+        }
+    }
+
+    /// Number of clients with a bucket, idle ones included until they are purged
+    pub fn client_count(&self) -> usize {
+        self.state.lock().map(|state| state.buckets.len()).unwrap_or_default()
+    }
+}
+
+pub fn get_rest_rate_limiter() -> &'static RateLimiter {
+    &REST_RATE_LIMITER
+}
+
+pub fn get_socket_rate_limiter() -> &'static RateLimiter {
+    &SOCKET_RATE_LIMITER
+}
+
+/// Apply the rate limits of a loaded domain config, on startup and whenever the config is reloaded
+pub fn configure(limits: &RateLimits) {
+    let idle = Duration::from_secs(limits.idle_seconds);
+
+    REST_RATE_LIMITER.reconfigure(limits.rest.per_second, limits.rest.burst, idle);
+    SOCKET_RATE_LIMITER.reconfigure(limits.socket.per_second, limits.socket.burst, idle);
+
+    debug!(?limits, "Rate limits configured");
+}
@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use utoipa::ToSchema;
+
+use audiocloud_api::domain::DomainError;
+
+use crate::incidents::IncidentId;
+
+/// Machine readable form of a `DomainError`, returned by the REST API in place of the bare error
+///
+/// `code` and `kind` are stable across releases, clients should branch on them and never on `message`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct ApiError {
+    /// Stable numeric error code, grouped by area (1xxx auth, 2xxx tasks, 3xxx instances, 4xxx sockets, 5xxx
+    /// server and upstream, 6xxx incidents)
+    pub code:      u32,
+    /// Stable string error code, the snake cased error variant
+    pub kind:      String,
+    /// The same request may succeed if retried later without changes
+    pub retryable: bool,
+    /// HTTP status code of the response
+    pub status:    u16,
+    /// Human readable description, not stable
+    pub message:   String,
+    /// Fields of the error variant, such as the task or socket ID involved
+    #[schema(value_type = Object)]
+    pub context:   Value,
+}
+
+/// How the REST API reports one kind of `DomainError`
+struct ErrorClass {
+    kind:      &'static str,
+    code:      u32,
+    status:    u16,
+    retryable: bool,
+}
+
+const fn class(kind: &'static str, code: u32, status: u16, retryable: bool) -> ErrorClass {
+    ErrorClass { kind,
+                 code,
+                 status,
+                 retryable }
+}
+
+/// Exhaustive on purpose, a new `DomainError` variant does not build until it has a stable code here
+fn classify(err: &DomainError) -> ErrorClass {
+    match err {
+        DomainError::AuthenticationFailed => class("authentication_failed", 1001, 401, false),
+        DomainError::TaskNotFound { .. } => class("task_not_found", 2001, 404, false),
+        DomainError::TaskExists { .. } => class("task_exists", 2002, 409, false),
+        DomainError::TaskIllegalPlayState { .. } => class("task_illegal_play_state", 2003, 409, true),
+        DomainError::TaskModification { .. } => class("task_modification", 2004, 400, false),
+        DomainError::TaskModificationRevisionOutOfDate { .. } => {
+            class("task_modification_revision_out_of_date", 2005, 409, true)
+        }
+        DomainError::TaskRevisionMalformed { .. } => class("task_revision_malformed", 2006, 400, false),
+        DomainError::TaskStreamNotFound { .. } => class("task_stream_not_found", 2007, 404, false),
+        DomainError::TaskPacketNotFound { .. } => class("task_packet_not_found", 2008, 404, false),
+        DomainError::InstanceNotFound { .. } => class("instance_not_found", 3001, 404, false),
+        DomainError::InstanceNotCapable { .. } => class("instance_not_capable", 3002, 400, false),
+        DomainError::SocketNotFound { .. } => class("socket_not_found", 4001, 404, false),
+        DomainError::SocketExists { .. } => class("socket_exists", 4002, 409, false),
+        DomainError::WebRTCError { .. } => class("web_rtc_error", 4003, 502, true),
+        DomainError::BadGateway { .. } => class("bad_gateway", 5001, 502, true),
+        DomainError::Serialization { .. } => class("serialization", 5002, 500, false),
+        DomainError::NotImplemented { .. } => class("not_implemented", 5003, 501, false),
+    }
+}
+
+const RATE_LIMITED_CODE: u32 = 5004;
+const INCIDENT_NOT_FOUND_CODE: u32 = 6001;
+
+impl ApiError {
+    /// Returned with status 429 when a client exceeds its request rate, before the request reaches a handler
+    pub fn rate_limited(retry_after_seconds: u64) -> Self {
+        Self { code:      { RATE_LIMITED_CODE },
+               kind:      { String::from("rate_limited") },
+               retryable: { true },
+               status:    { 429 },
+               message:   { format!("Rate limit exceeded, retry in {retry_after_seconds} seconds") },
+               context:   { json!({ "retry_after_seconds": retry_after_seconds }) }, }
+    }
+
+    /// Returned with status 404 when no incident has the requested ID
+    pub fn incident_not_found(incident_id: &IncidentId) -> Self {
+        Self { code:      { INCIDENT_NOT_FOUND_CODE },
+               kind:      { String::from("incident_not_found") },
+               retryable: { false },
+               status:    { 404 },
+               message:   { format!("Incident {incident_id} not found") },
+               context:   { json!({ "incident_id": incident_id }) }, }
+    }
+}
+
+impl From<&DomainError> for ApiError {
+    fn from(err: &DomainError) -> Self {
+        let class = classify(err);
+
+        Self { code:      { class.code },
+               kind:      { String::from(class.kind) },
+               retryable: { class.retryable },
+               status:    { class.status },
+               message:   { err.to_string() },
+               context:   { variant_fields(serde_json::to_value(err).unwrap_or(Value::Null)) }, }
+    }
+}
+
+/// Fields of an externally tagged enum value, without the variant name
+fn variant_fields(value: Value) -> Value {
+    match value {
+        Value::Object(map) if map.len() == 1 => map.into_iter().next().map(|(_, fields)| fields).unwrap_or_default(),
+        Value::String(_) => Value::Null,
+        other => other,
+    }
+}
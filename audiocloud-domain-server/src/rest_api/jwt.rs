@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use clap::Args;
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use once_cell::sync::OnceCell;
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use tracing::*;
+
+use audiocloud_api::{AppTaskId, TaskPermissions};
+
+use crate::config::cloud_endpoints;
+use crate::{SecureKeyScope, TokenSecurity};
+
+static JWT_VERIFIER: OnceCell<JwtVerifier> = OnceCell::new();
+
+/// Signing algorithms tokens are accepted with, the header of a token is never trusted to pick one on its own
+const ALLOWED_ALGORITHMS: [Algorithm; 2] = [Algorithm::RS256, Algorithm::ES256];
+
+#[derive(Args, Clone, Debug)]
+pub struct JwtOpts {
+    /// Accept JWT bearer tokens signed by keys published by the cloud, in addition to secure keys
+    #[clap(long, env)]
+    pub rest_jwt_enabled: bool,
+
+    /// Path of the JWKS document, relative to the cloud URL
+    #[clap(long, env, default_value = "/.well-known/jwks.json")]
+    pub rest_jwt_jwks_path: String,
+
+    /// Only accept tokens issued for this audience (`aud` claim)
+    #[clap(long, env)]
+    pub rest_jwt_audience: Option<String>,
+
+    /// Number of seconds between JWKS refreshes, so rotated signing keys are picked up
+    #[clap(long, env, default_value = "300")]
+    pub rest_jwt_jwks_refresh_seconds: u64,
+}
+
+#[derive(Deserialize, Debug)]
+struct TokenClaims {
+    sub:    String,
+    #[serde(default)]
+    tasks:  HashMap<AppTaskId, TaskPermissions>,
+    #[serde(default)]
+    scopes: HashMap<AppTaskId, SecureKeyScope>,
+}
+
+pub struct JwtVerifier {
+    keys:     RwLock<JwkSet>,
+    audience: Option<String>,
+}
+
+impl JwtVerifier {
+    pub fn new(keys: JwkSet, audience: Option<String>) -> Self {
+        Self { keys:     { RwLock::new(keys) },
+               audience: { audience }, }
+    }
+
+    pub fn verify(&self, token: &str) -> anyhow::Result<TokenSecurity> {
+        let header = decode_header(token)?;
+        let kid = header.kid.ok_or_else(|| anyhow!("Token has no key ID"))?;
+
+        let (key, algorithm) = {
+            let keys = self.keys.read().map_err(|_| anyhow!("JWKS lock poisoned"))?;
+            let jwk = keys.find(&kid).ok_or_else(|| anyhow!("Unknown signing key {kid}"))?;
+            (DecodingKey::from_jwk(jwk)?, key_algorithm(jwk, header.alg)?)
+        };
+
+        let mut validation = Validation::new(algorithm);
+        match &self.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+
+        let claims = decode::<TokenClaims>(token, &key, &validation)?.claims;
+
+        Ok(TokenSecurity { subject: { claims.sub },
+                           tasks:   { claims.tasks },
+                           scopes:  { claims.scopes }, })
+    }
+
+    fn replace_keys(&self, keys: JwkSet) {
+        match self.keys.write() {
+            Ok(mut current) => *current = keys,
+            Err(_) => warn!("JWKS lock poisoned, keeping previous keys"),
+        }
+    }
+}
+
+/// Algorithm to verify a token signed by `jwk` with, the one the key is published for or else one of the allowed ones,
+/// and never anything else the token header claims
+fn key_algorithm(jwk: &Jwk, claimed: Algorithm) -> anyhow::Result<Algorithm> {
+    let algorithm = jwk.common.algorithm.unwrap_or(claimed);
+
+    if algorithm != claimed {
+        return Err(anyhow!("Token claims {claimed:?}, but its signing key is for {algorithm:?}"));
+    }
+
+    if !ALLOWED_ALGORITHMS.contains(&algorithm) {
+        return Err(anyhow!("Tokens signed with {algorithm:?} are not accepted"));
+    }
+
+    Ok(algorithm)
+}
+
+/// Tokens are told apart from secure keys by their three dot separated segments
+pub fn looks_like_jwt(token: &str) -> bool {
+    token.split('.').count() == 3
+}
+
+pub fn get_jwt_verifier() -> Option<&'static JwtVerifier> {
+    JWT_VERIFIER.get()
+}
+
+#[instrument(skip_all, err)]
+async fn fetch_jwks(path: &str) -> anyhow::Result<JwkSet> {
+    cloud_endpoints().request(path, fetch_jwks_from).await
+}
+
+async fn fetch_jwks_from(url: Url) -> anyhow::Result<JwkSet> {
+    Ok(Client::new().get(url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<JwkSet>()
+                    .await?)
+}
+
+#[instrument(skip_all, err)]
+pub async fn init(opts: &JwtOpts) -> anyhow::Result<()> {
+    if !opts.rest_jwt_enabled {
+        return Ok(());
+    }
+
+    let path = opts.rest_jwt_jwks_path.clone();
+    let keys = fetch_jwks(&path).await?;
+    info!(%path, num_keys = keys.keys.len(), "Loaded JWKS");
+
+    JWT_VERIFIER.set(JwtVerifier::new(keys, opts.rest_jwt_audience.clone()))
+                .map_err(|_| anyhow!("JWT verifier already initialized"))?;
+
+    let refresh = Duration::from_secs(opts.rest_jwt_jwks_refresh_seconds);
+
+    actix::spawn(async move {
+        let mut interval = tokio::time::interval(refresh);
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+
+            match fetch_jwks(&path).await {
+                Ok(keys) => {
+                    if let Some(verifier) = get_jwt_verifier() {
+                        verifier.replace_keys(keys);
+                    }
+                }
+                Err(error) => {
+                    warn!(%error, %path, "Failed to refresh JWKS, keeping previous keys");
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
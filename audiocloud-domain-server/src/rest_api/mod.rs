@@ -23,14 +23,25 @@ use audiocloud_api::{AppId, AppTaskId, Codec, Json, MsgPack, SecureKey, TaskId};
 use crate::o11y::generate_prometheus_metrics;
 use crate::{DomainSecurity, ResponseMedia};
 
+pub use error::ApiError;
+
+mod error;
+pub mod jwt;
+pub mod openapi;
+pub mod rate_limit;
+#[cfg(test)]
+mod tests;
 mod v1;
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(healthz)
        .service(metrics)
+       .service(openapi::openapi_json)
        .service(web::scope("/v1").configure(v1::configure));
 }
 
+#[utoipa::path(tag = "service",
+              responses((status = 200, description = "Domain server is running", body = Object)))]
 #[get("/healthz")]
 async fn healthz() -> impl Responder {
     let res = json!({
@@ -40,6 +51,11 @@ async fn healthz() -> impl Responder {
     web::Json(res)
 }
 
+#[utoipa::path(tag = "service",
+              responses((status = 200,
+                         description = "Prometheus metrics in text format",
+                         body = String,
+                         content_type = "text/plain")))]
 #[get("/metrics")]
 async fn metrics() -> impl Responder {
     match generate_prometheus_metrics() {
@@ -59,7 +75,20 @@ impl ApiResponder {
         where T: Serialize,
               F: Future<Output = Result<T, DomainError>>
     {
-        let rv = fut.await;
+        let rv = fut.await.map_err(|err| ApiError::from(&err));
+        ApiResponse(self.0, rv)
+    }
+
+    /// Like `respond`, but answers with `not_found` when the future resolves to `None`
+    pub async fn respond_found<T, F>(self, fut: F, not_found: impl FnOnce() -> ApiError) -> ApiResponse<T>
+        where T: Serialize,
+              F: Future<Output = Result<Option<T>, DomainError>>
+    {
+        let rv = match fut.await {
+            Ok(Some(found)) => Ok(found),
+            Ok(None) => Err(not_found()),
+            Err(err) => Err(ApiError::from(&err)),
+        };
         ApiResponse(self.0, rv)
     }
 }
@@ -90,20 +119,20 @@ impl FromRequest for ApiResponder {
     }
 }
 
-pub struct ApiResponse<T>(ResponseMedia, Result<T, DomainError>);
+pub struct ApiResponse<T>(ResponseMedia, Result<T, ApiError>);
 
 impl<T> Responder for ApiResponse<T> where T: Serialize
 {
     type Body = EitherBody<BoxBody>;
 
     fn respond_to(self, _req: &HttpRequest) -> HttpResponse<Self::Body> {
-        let err_resp = |err: DomainError| {
+        let err_resp = |err: ApiError| {
             let (content, content_type) = match self.0 {
                 ResponseMedia::Json => (Json.serialize(&err).unwrap(), mime::APPLICATION_JSON.as_ref()),
                 ResponseMedia::MsgPack => (MsgPack.serialize(&err).unwrap(), mime::APPLICATION_MSGPACK.as_ref()),
             };
 
-            let status = StatusCode::from_u16(err.status_code()).unwrap();
+            let status = StatusCode::from_u16(err.status).unwrap();
             HttpResponseBuilder::new(status).content_type(content_type)
                                             .body(content)
                                             .map_into_right_body()
@@ -123,7 +152,7 @@ impl<T> Responder for ApiResponse<T> where T: Serialize
                 };
 
                 let content = match content {
-                    Err(err) => return err_resp(err),
+                    Err(err) => return err_resp(ApiError::from(&err)),
                     Ok(content) => content,
                 };
 
@@ -136,7 +165,7 @@ impl<T> Responder for ApiResponse<T> where T: Serialize
     }
 }
 
-const HEADER_AUTH_PREFIX: &'static str = "Bearer ";
+pub(crate) const HEADER_AUTH_PREFIX: &'static str = "Bearer ";
 
 impl FromRequest for DomainSecurity {
     type Error = actix_web::Error;
@@ -160,9 +189,18 @@ impl FromRequest for DomainSecurity {
                                                })?;
 
                     if !authorization.starts_with(HEADER_AUTH_PREFIX) {
-                        Err(ErrorUnauthorized(anyhow!("Authentication missing")))
-                    } else {
-                        Ok(DomainSecurity::SecureKey(SecureKey::new(authorization[HEADER_AUTH_PREFIX.len()..].to_string())))
+                        return Err(ErrorUnauthorized(anyhow!("Authentication missing")));
+                    }
+
+                    let token = &authorization[HEADER_AUTH_PREFIX.len()..];
+
+                    match jwt::get_jwt_verifier() {
+                        Some(verifier) if jwt::looks_like_jwt(token) => {
+                            verifier.verify(token)
+                                    .map(DomainSecurity::Token)
+                                    .map_err(|err| ErrorUnauthorized(anyhow!("Invalid bearer token: {err}")))
+                        }
+                        _ => Ok(DomainSecurity::SecureKey(SecureKey::new(token.to_string()))),
                     }
                 }
             }
@@ -172,11 +210,23 @@ impl FromRequest for DomainSecurity {
     }
 }
 
-#[derive(Args, Clone, Copy)]
+#[derive(Args, Clone)]
 pub struct RestOpts {
     /// Authentication strategy to use for incoming REST requests
     #[clap(long, env, default_value = "production")]
     pub rest_auth_strategy: AuthStrategy,
+
+    #[clap(flatten)]
+    pub jwt: jwt::JwtOpts,
+
+    /// Serve an interactive Swagger UI for the OpenAPI document at /swagger-ui/
+    #[clap(long, env)]
+    pub rest_swagger_ui: bool,
+
+    /// Token calendar tools pass as `?token=` to subscribe to the iCal feeds of instances, as they can not send an
+    /// authorization header. Without it only operators can read the feeds
+    #[clap(long, env)]
+    pub rest_calendar_feed_token: Option<String>,
 }
 
 #[derive(ValueEnum, Copy, Clone, IsVariant)]
@@ -0,0 +1,233 @@
+use actix_web::{get, web, Responder};
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+use utoipa_swagger_ui::{Config, SwaggerUi};
+
+use crate::analytics::{InstanceUtilization, ModelUtilization, UsageStats, UtilizationReport, UtilizationTotals};
+use crate::audit::AuditEntry;
+use crate::bandwidth::{AppBandwidthUsage, BandwidthBytes};
+use crate::automation::{AutomationRun, AutomationScript, AutomationScriptUpdate};
+use crate::config::{ConfigDiagnostic, ConfigDiagnosticSeverity, ConfigValidation};
+use crate::fixed_instances::{InstanceCalendarEntry, InstanceCalendarEntryKind};
+use crate::incidents::{Incident, IncidentEntry};
+use crate::journal::{JournalEvent, JournalReplay};
+use crate::sockets::{DataChannelStats, DrainReason, SocketDrain, SocketDrainResult, SocketStatsReport};
+use crate::tasks::engine_ext::{
+    EngineClockStatus, EngineResources, EngineTestTone, EngineTestToneInput, EngineTestToneResult, RenderFormat,
+};
+use crate::tasks::null_test::NullTestVariant;
+use crate::tasks::{
+    BarBeat, ClickTempo, EngineClockReport, EngineResourceReport, EnvelopePoint, EnvelopeShape, EnvelopeTarget,
+    FadeShape, MediaFades, MediaRate, RecallInstance, RequestPausePlay, RoutingChainCheck, RoutingVerificationState,
+    StretchMode, TaskClick, TaskDiagnostics, TaskEnvelope, TaskEnvelopes, TaskKeyScopeUpdate, TaskLatencyProfile,
+    TaskLeadIn, TaskLoudnessTarget, TaskMediaFades, TaskMediaLengths, TaskMediaRates, TaskMediaRatesState, TaskMonitor,
+    TaskNullTestRequest, TaskPlayPause, TaskPlayRequest, TaskPlaylist, TaskPunchRegion, TaskRecallSheet, TaskRecording,
+    TaskRenderNormalization, TaskRenderRequest, TaskRoutingVerification, TaskSafeMode, TaskSecureKeyRevocation,
+    TaskSecureKeyRotation, TaskSpecDiff, TaskSpecElements, TaskStreamCodec, TaskTempoMap, TaskTrackGroups,
+    TaskTrackInputUpdate, TaskWatermark, TempoChange, TrackGroup, TrackHardwareInput, TrackTake, WatermarkMode,
+};
+use crate::telemetry::{InstanceReportSeries, ReportBucket};
+use crate::SecureKeyScope;
+
+use super::v1::{
+    analytics, audit, automation, config, engines, events, incidents, instances, sockets, streaming, support, tasks,
+};
+use super::ApiError;
+
+/// OpenAPI document of the domain REST surface, generated from the handler annotations
+#[derive(OpenApi)]
+#[openapi(paths(super::healthz,
+                super::metrics,
+                tasks::list_tasks,
+                tasks::create_task,
+                tasks::get_task,
+                tasks::get_task_spec_diff,
+                tasks::get_task_safe_mode,
+                tasks::enable_task_spec_elements,
+                tasks::get_task_routing_verification,
+                tasks::verify_task_routing,
+                tasks::get_task_key_scopes,
+                tasks::set_task_key_scope,
+                tasks::rotate_task_secure_key,
+                tasks::revoke_task_secure_key,
+                tasks::get_task_track_inputs,
+                tasks::set_task_track_input,
+                tasks::set_task_recording,
+                tasks::set_task_lead_in,
+                tasks::set_task_latency_profile,
+                tasks::set_task_stream_codec,
+                tasks::set_task_loudness_target,
+                tasks::set_task_watermark,
+                tasks::get_task_tempo_map,
+                tasks::set_task_tempo_map,
+                tasks::get_task_playlist,
+                tasks::set_task_playlist,
+                tasks::get_task_track_groups,
+                tasks::set_task_track_groups,
+                tasks::get_task_envelopes,
+                tasks::set_task_envelopes,
+                tasks::get_task_fades,
+                tasks::set_task_fades,
+                tasks::get_task_media_rates,
+                tasks::set_task_media_rates,
+                tasks::get_task_monitor,
+                tasks::set_task_monitor,
+                tasks::get_task_takes,
+                tasks::get_task_diagnostics,
+                tasks::get_task_events,
+                tasks::modify_task,
+                tasks::delete_task,
+                tasks::render_task,
+                tasks::run_task_null_test,
+                tasks::generate_task_recall_sheet,
+                tasks::play_task,
+                tasks::seek_task,
+                tasks::cancel_render_task,
+                tasks::stop_play_task,
+                tasks::pause_play_task,
+                tasks::resume_play_task,
+                streaming::get_stream_stats,
+                streaming::get_stream_packet,
+                incidents::list_incidents,
+                incidents::get_incident,
+                engines::get_engine_clocks,
+                engines::get_engine_resources,
+                engines::run_engine_test_tone,
+                events::replay_events,
+                instances::get_instance_reports,
+                instances::get_instance_calendar,
+                instances::get_instance_calendar_feed,
+                sockets::list_socket_stats,
+                sockets::drain_sockets,
+                analytics::list_utilization_reports,
+                analytics::list_bandwidth_usage,
+                audit::query_audit_entries,
+                automation::list_automation_scripts,
+                automation::save_automation_script,
+                automation::delete_automation_script,
+                automation::run_automation_script,
+                config::validate_config,
+                support::create_bundle),
+          components(schemas(ApiError,
+                             TaskSpecDiff,
+                             TaskSafeMode,
+                             TaskSpecElements,
+                             TaskRoutingVerification,
+                             RoutingVerificationState,
+                             RoutingChainCheck,
+                             SecureKeyScope,
+                             TaskKeyScopeUpdate,
+                             TaskSecureKeyRotation,
+                             TaskSecureKeyRevocation,
+                             TrackHardwareInput,
+                             TaskTrackInputUpdate,
+                             TaskRecording,
+                             TaskPunchRegion,
+                             TaskLeadIn,
+                             TaskLatencyProfile,
+                             TaskStreamCodec,
+                             TaskLoudnessTarget,
+                             TaskWatermark,
+                             WatermarkMode,
+                             TaskPlayRequest,
+                             TaskRenderRequest,
+                             TaskRenderNormalization,
+                             RenderFormat,
+                             TaskNullTestRequest,
+                             NullTestVariant,
+                             RequestPausePlay,
+                             TaskPlayPause,
+                             TaskTempoMap,
+                             TaskPlaylist,
+                             TaskTrackGroups,
+                             TrackGroup,
+                             TaskEnvelopes,
+                             TaskEnvelope,
+                             EnvelopeTarget,
+                             EnvelopePoint,
+                             EnvelopeShape,
+                             TaskMediaFades,
+                             MediaFades,
+                             FadeShape,
+                             TaskMediaRates,
+                             TaskMediaRatesState,
+                             TaskMediaLengths,
+                             MediaRate,
+                             StretchMode,
+                             TaskMonitor,
+                             TaskClick,
+                             ClickTempo,
+                             TaskRecallSheet,
+                             RecallInstance,
+                             TempoChange,
+                             BarBeat,
+                             TrackTake,
+                             TaskDiagnostics,
+                             Incident,
+                             IncidentEntry,
+                             EngineClockReport,
+                             EngineClockStatus,
+                             EngineResourceReport,
+                             EngineResources,
+                             EngineTestTone,
+                             EngineTestToneInput,
+                             EngineTestToneResult,
+                             InstanceReportSeries,
+                             ReportBucket,
+                             InstanceCalendarEntry,
+                             InstanceCalendarEntryKind,
+                             SocketStatsReport,
+                             DataChannelStats,
+                             SocketDrain,
+                             SocketDrainResult,
+                             DrainReason,
+                             UtilizationReport,
+                             InstanceUtilization,
+                             ModelUtilization,
+                             UsageStats,
+                             UtilizationTotals,
+                             AppBandwidthUsage,
+                             BandwidthBytes,
+                             JournalEvent,
+                             JournalReplay,
+                             AuditEntry,
+                             AutomationScript,
+                             AutomationScriptUpdate,
+                             AutomationRun,
+                             ConfigValidation,
+                             ConfigDiagnostic,
+                             ConfigDiagnosticSeverity)),
+          modifiers(&SecureKeyAuth),
+          tags((name = "tasks", description = "Task lifecycle and transport control"),
+               (name = "streaming", description = "Cached streaming packets and statistics"),
+               (name = "incidents", description = "Grouped incident timelines, operators only"),
+               (name = "engines", description = "Audio engine health and diagnostics, operators only"),
+               (name = "events", description = "Journal of domain events for replay after reconnecting"),
+               (name = "instances", description = "Reports and booking calendars of fixed instances, operators only"),
+               (name = "sockets", description = "Network quality and draining of client sockets, operators only"),
+               (name = "analytics", description = "Utilization and bandwidth reports for studio business reporting, operators only"),
+               (name = "audit", description = "Append-only log of mutating commands, operators only"),
+               (name = "automation", description = "Scripts the domain runs on schedules and events, operators only"),
+               (name = "config", description = "Domain config checks, operators only"),
+               (name = "support", description = "Support bundles to attach to support tickets, operators only"),
+               (name = "service", description = "Health and observability")))]
+pub struct ApiDoc;
+
+struct SecureKeyAuth;
+
+impl Modify for SecureKeyAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme("secure_key", SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)));
+    }
+}
+
+#[get("/openapi.json")]
+pub async fn openapi_json() -> impl Responder {
+    web::Json(ApiDoc::openapi())
+}
+
+/// Swagger UI loads the document `openapi_json` serves, it does not serve a copy of its own
+pub fn configure_swagger_ui(cfg: &mut web::ServiceConfig) {
+    cfg.service(SwaggerUi::new("/swagger-ui/{_:.*}").config(Config::from("/openapi.json")));
+}
@@ -0,0 +1,64 @@
+use std::future::{ready, Future};
+
+use actix_web::body::EitherBody;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{AUTHORIZATION, RETRY_AFTER};
+use actix_web::HttpResponse;
+use futures::future::Either;
+use futures::TryFutureExt;
+use tracing::*;
+
+use crate::rate_limit::get_rest_rate_limiter;
+
+use super::jwt::{get_jwt_verifier, looks_like_jwt};
+use super::{ApiError, HEADER_AUTH_PREFIX};
+
+/// Paths polled by infrastructure, never rate limited
+const EXEMPT_PATHS: &[&str] = &["/healthz", "/metrics"];
+
+/// Middleware function for `App::wrap_fn`, rejecting requests over the per client rate with 429 and `Retry-After`
+pub fn limit_requests<S, B>(req: ServiceRequest,
+                            srv: &S)
+                            -> impl Future<Output = Result<ServiceResponse<EitherBody<B>>, actix_web::Error>>
+    where S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>
+{
+    let limited = match EXEMPT_PATHS.contains(&req.path()) {
+        false => get_rest_rate_limiter().check(&client_key(&req)).err(),
+        true => None,
+    };
+
+    match limited {
+        None => Either::Left(srv.call(req).map_ok(ServiceResponse::map_into_left_body)),
+        Some(retry_after) => {
+            let retry_after = retry_after.as_secs() + 1;
+            debug!(path = req.path(), retry_after, "Rate limited REST request");
+
+            let response = HttpResponse::TooManyRequests().insert_header((RETRY_AFTER, retry_after))
+                                                          .json(ApiError::rate_limited(retry_after));
+
+            Either::Right(ready(Ok(req.into_response(response).map_into_right_body())))
+        }
+    }
+}
+
+/// Clients are identified by the subject of a verified JWT, otherwise by the address of the peer they connected from.
+/// Secure keys are only checked by the handlers, so a client can not get a fresh bucket by making a key up
+fn client_key(req: &ServiceRequest) -> String {
+    let token = req.headers()
+                   .get(AUTHORIZATION)
+                   .and_then(|value| value.to_str().ok())
+                   .and_then(|authorization| authorization.strip_prefix(HEADER_AUTH_PREFIX));
+
+    if let (Some(token), Some(verifier)) = (token, get_jwt_verifier()) {
+        if looks_like_jwt(token) {
+            if let Ok(security) = verifier.verify(token) {
+                return format!("sub:{}", security.subject);
+            }
+        }
+    }
+
+    match req.peer_addr() {
+        Some(addr) => format!("ip:{}", addr.ip()),
+        None => "ip:unknown".to_owned(),
+    }
+}
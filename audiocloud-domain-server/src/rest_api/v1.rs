@@ -1,9 +1,52 @@
-use actix_web::web;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse};
 
-mod streaming;
-mod tasks;
+use audiocloud_api::domain::DomainError;
+
+use crate::rest_api::ApiError;
+use crate::{DomainResult, DomainSecurity};
+
+pub(super) mod analytics;
+pub(super) mod audit;
+pub(super) mod automation;
+pub(super) mod config;
+pub(super) mod engines;
+pub(super) mod events;
+pub(super) mod incidents;
+pub(super) mod instances;
+pub(super) mod sockets;
+pub(super) mod streaming;
+pub(super) mod support;
+pub(super) mod tasks;
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
-    cfg.service(web::scope("/streams").configure(streaming::configure))
+    cfg.service(web::scope("/analytics").configure(analytics::configure))
+       .service(web::scope("/audit").configure(audit::configure))
+       .service(web::scope("/automation").configure(automation::configure))
+       .service(web::scope("/config").configure(config::configure))
+       .service(web::scope("/engines").configure(engines::configure))
+       .service(web::scope("/events").configure(events::configure))
+       .service(web::scope("/incidents").configure(incidents::configure))
+       .service(web::scope("/instances").configure(instances::configure))
+       .service(web::scope("/sockets").configure(sockets::configure))
+       .service(web::scope("/streams").configure(streaming::configure))
+       .service(web::scope("/support-bundle").configure(support::configure))
        .service(web::scope("/tasks").configure(tasks::configure));
 }
+
+/// Operator endpoints are only available to the cloud, never to app secure keys
+fn require_operator(security: &DomainSecurity) -> DomainResult {
+    if security.is_cloud() {
+        Ok(())
+    } else {
+        Err(DomainError::AuthenticationFailed)
+    }
+}
+
+/// Error response of endpoints that do not respond with `ApiResponse`, such as the ones serving files
+fn error_response(error: DomainError) -> HttpResponse {
+    let error = ApiError::from(&error);
+    let status = StatusCode::from_u16(error.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+    HttpResponse::build(status).json(error)
+}
@@ -0,0 +1,84 @@
+use std::convert::identity;
+
+use actix_web::{get, web};
+use serde::Deserialize;
+
+use audiocloud_api::AppId;
+
+use crate::analytics::{get_analytics_supervisor, ListUtilizationReports, UtilizationReport};
+use crate::bandwidth::{get_bandwidth_supervisor, AppBandwidthUsage, ListBandwidthUsage};
+use crate::rest_api::{bad_gateway, ApiResponder, ApiResponse};
+use crate::DomainSecurity;
+
+use super::require_operator;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_utilization_reports).service(list_bandwidth_usage);
+}
+
+#[derive(Deserialize)]
+pub struct UtilizationReportsQuery {
+    window_hours: Option<u64>,
+    #[serde(default = "default_reports_limit")]
+    limit:        usize,
+}
+
+fn default_reports_limit() -> usize {
+    10
+}
+
+#[utoipa::path(context_path = "/v1/analytics",
+              tag = "analytics",
+              params(("window_hours" = Option<u64>, Query, description = "Only reports over this window, in hours"),
+                     ("limit" = Option<usize>, Query, description = "Maximum number of reports, most recent first")),
+              responses((status = 200, description = "Utilization reports", body = [UtilizationReport])))]
+#[get("/utilization")]
+async fn list_utilization_reports(responder: ApiResponder,
+                                  security: DomainSecurity,
+                                  query: web::Query<UtilizationReportsQuery>)
+                                  -> ApiResponse<Vec<UtilizationReport>> {
+    let query = query.into_inner();
+    let list = ListUtilizationReports { window_hours: { query.window_hours },
+                                        limit:        { query.limit }, };
+
+    responder.respond(async move {
+                 require_operator(&security)?;
+
+                 get_analytics_supervisor().send(list)
+                                           .await
+                                           .map_err(bad_gateway)
+                                           .and_then(identity)
+             })
+             .await
+}
+
+#[derive(Deserialize)]
+pub struct BandwidthUsageQuery {
+    month:  Option<String>,
+    app_id: Option<String>,
+}
+
+#[utoipa::path(context_path = "/v1/analytics",
+              tag = "analytics",
+              params(("month" = Option<String>, Query, description = "Month as YYYY-MM, the current month if not set"),
+                     ("app_id" = Option<String>, Query, description = "Only this app")),
+              responses((status = 200, description = "Bandwidth of the apps", body = [AppBandwidthUsage])))]
+#[get("/bandwidth")]
+async fn list_bandwidth_usage(responder: ApiResponder,
+                              security: DomainSecurity,
+                              query: web::Query<BandwidthUsageQuery>)
+                              -> ApiResponse<Vec<AppBandwidthUsage>> {
+    let query = query.into_inner();
+    let list = ListBandwidthUsage { month:  { query.month },
+                                    app_id: { query.app_id.map(AppId::new) }, };
+
+    responder.respond(async move {
+                 require_operator(&security)?;
+
+                 get_bandwidth_supervisor().send(list)
+                                           .await
+                                           .map_err(bad_gateway)
+                                           .and_then(identity)
+             })
+             .await
+}
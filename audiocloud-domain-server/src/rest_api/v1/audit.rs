@@ -0,0 +1,40 @@
+use std::convert::identity;
+
+use actix_web::{get, web};
+
+use crate::audit::{get_audit_supervisor, AuditEntry, AuditQuery, QueryAuditEntries};
+use crate::rest_api::{bad_gateway, ApiResponder, ApiResponse};
+use crate::DomainSecurity;
+
+use super::require_operator;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(query_audit_entries);
+}
+
+#[utoipa::path(context_path = "/v1/audit",
+              tag = "audit",
+              params(("task_id" = Option<String>, Query, description = "Only entries for this task"),
+                     ("instance_id" = Option<String>, Query, description = "Only entries for this fixed instance"),
+                     ("actor" = Option<String>, Query, description = "Only entries by this actor"),
+                     ("since" = Option<String>, Query, description = "Only entries recorded at or after this time"),
+                     ("before_id" = Option<i64>, Query, description = "Only entries older than this sequence number"),
+                     ("limit" = Option<usize>, Query, description = "Maximum number of entries to return, most recent first")),
+              responses((status = 200, description = "Matching audit entries", body = [AuditEntry])))]
+#[get("")]
+async fn query_audit_entries(responder: ApiResponder,
+                             security: DomainSecurity,
+                             query: web::Query<AuditQuery>)
+                             -> ApiResponse<Vec<AuditEntry>> {
+    let query = QueryAuditEntries { query: query.into_inner(), };
+
+    responder.respond(async move {
+                 require_operator(&security)?;
+
+                 get_audit_supervisor().send(query)
+                                       .await
+                                       .map_err(bad_gateway)
+                                       .and_then(identity)
+             })
+             .await
+}
@@ -0,0 +1,121 @@
+use std::convert::identity;
+
+use actix_web::web::{Json, Path};
+use actix_web::{delete, get, post, put, web};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::audit::{audited, AuditEntry, AuditOrigin};
+use crate::automation::{
+    get_automation_supervisor, AutomationRun, AutomationScript, AutomationScriptUpdate, DeleteAutomationScript,
+    ListAutomationScripts, RunAutomationScript, SaveAutomationScript,
+};
+use crate::rest_api::{bad_gateway, ApiResponder, ApiResponse};
+use crate::DomainSecurity;
+
+use super::require_operator;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_automation_scripts)
+       .service(save_automation_script)
+       .service(delete_automation_script)
+       .service(run_automation_script);
+}
+
+#[derive(Deserialize)]
+pub struct ScriptNamePath {
+    name: String,
+}
+
+#[utoipa::path(context_path = "/v1/automation",
+              tag = "automation",
+              responses((status = 200, description = "Automation scripts of the domain", body = [AutomationScript])))]
+#[get("")]
+async fn list_automation_scripts(responder: ApiResponder,
+                                 security: DomainSecurity)
+                                 -> ApiResponse<Vec<AutomationScript>> {
+    responder.respond(async move {
+                 require_operator(&security)?;
+
+                 get_automation_supervisor().send(ListAutomationScripts)
+                                            .await
+                                            .map_err(bad_gateway)
+                                            .and_then(identity)
+             })
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/automation",
+              tag = "automation",
+              params(("name" = String, Path, description = "Script name")),
+              request_body = AutomationScriptUpdate,
+              responses((status = 200, description = "Saved script", body = AutomationScript)))]
+#[put("/{name}")]
+async fn save_automation_script(responder: ApiResponder,
+                                security: DomainSecurity,
+                                path: Path<ScriptNamePath>,
+                                update: Json<AutomationScriptUpdate>)
+                                -> ApiResponse<AutomationScript> {
+    let name = path.into_inner().name;
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "save_automation_script").with_params(&update.0);
+    let save = SaveAutomationScript { name:   { name },
+                                      update: { update.into_inner() }, };
+
+    responder.respond(audited(audit, async move {
+                          require_operator(&security)?;
+
+                          get_automation_supervisor().send(save)
+                                                     .await
+                                                     .map_err(bad_gateway)
+                                                     .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/automation",
+              tag = "automation",
+              params(("name" = String, Path, description = "Script name")),
+              responses((status = 200, description = "Script deleted, or did not exist")))]
+#[delete("/{name}")]
+async fn delete_automation_script(responder: ApiResponder,
+                                  security: DomainSecurity,
+                                  path: Path<ScriptNamePath>)
+                                  -> ApiResponse<()> {
+    let name = path.into_inner().name;
+    let audit =
+        AuditEntry::new(AuditOrigin::Rest, &security, "delete_automation_script").with_params(json!({ "name": name }));
+
+    responder.respond(audited(audit, async move {
+                          require_operator(&security)?;
+
+                          get_automation_supervisor().send(DeleteAutomationScript { name })
+                                                     .await
+                                                     .map_err(bad_gateway)
+                                                     .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/automation",
+              tag = "automation",
+              params(("name" = String, Path, description = "Script name")),
+              responses((status = 200, description = "Log and actions of the run", body = AutomationRun)))]
+#[post("/{name}/run")]
+async fn run_automation_script(responder: ApiResponder,
+                               security: DomainSecurity,
+                               path: Path<ScriptNamePath>)
+                               -> ApiResponse<AutomationRun> {
+    let name = path.into_inner().name;
+    let audit =
+        AuditEntry::new(AuditOrigin::Rest, &security, "run_automation_script").with_params(json!({ "name": name }));
+
+    responder.respond(audited(audit, async move {
+                          require_operator(&security)?;
+
+                          get_automation_supervisor().send(RunAutomationScript { name })
+                                                     .await
+                                                     .map_err(bad_gateway)
+                                                     .and_then(identity)
+                      }))
+             .await
+}
@@ -0,0 +1,31 @@
+use actix_web::{post, web};
+
+use crate::config::{self, ConfigValidation};
+use crate::rest_api::{ApiResponder, ApiResponse};
+use crate::DomainSecurity;
+
+use super::require_operator;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(validate_config);
+}
+
+#[utoipa::path(context_path = "/v1/config",
+              tag = "config",
+              request_body(content = Object,
+                           description = "Candidate domain config, in the same shape as the config file"),
+              responses((status = 200,
+                         description = "Diagnostics for the candidate config, nothing is applied",
+                         body = ConfigValidation)))]
+#[post("/validate")]
+async fn validate_config(responder: ApiResponder,
+                         security: DomainSecurity,
+                         candidate: web::Json<serde_json::Value>)
+                         -> ApiResponse<ConfigValidation> {
+    responder.respond(async move {
+                 require_operator(&security)?;
+
+                 Ok(config::validate_config(candidate.into_inner()).await)
+             })
+             .await
+}
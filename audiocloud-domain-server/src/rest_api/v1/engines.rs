@@ -0,0 +1,98 @@
+use std::convert::identity;
+
+use actix_web::web::{Json, Path};
+use actix_web::{get, post, web};
+use serde::Deserialize;
+use serde_json::json;
+
+use audiocloud_api::newtypes::EngineId;
+
+use crate::audit::{audited, AuditEntry, AuditOrigin};
+use crate::rest_api::{bad_gateway, ApiResponder, ApiResponse};
+use crate::tasks::engine_ext::{EngineTestTone, EngineTestToneResult};
+use crate::tasks::{
+    get_tasks_supervisor, EngineClockReport, EngineResourceReport, GetEngineClocks, GetEngineResources,
+    RunEngineTestTone,
+};
+use crate::DomainSecurity;
+
+use super::require_operator;
+
+#[derive(Deserialize)]
+pub struct EngineIdPath {
+    engine_id: EngineId,
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_engine_clocks)
+       .service(get_engine_resources)
+       .service(run_engine_test_tone);
+}
+
+#[utoipa::path(context_path = "/v1/engines",
+              tag = "engines",
+              responses((status = 200, description = "Last clock status of each engine", body = [EngineClockReport])))]
+#[get("/clocks")]
+async fn get_engine_clocks(responder: ApiResponder, security: DomainSecurity) -> ApiResponse<Vec<EngineClockReport>> {
+    responder.respond(async move {
+                 require_operator(&security)?;
+
+                 get_tasks_supervisor().send(GetEngineClocks)
+                                       .await
+                                       .map_err(bad_gateway)
+                                       .and_then(identity)
+             })
+             .await
+}
+
+/// CPU usage, xruns, block size and track counts each engine reported last, and whether new tasks avoid it
+#[utoipa::path(context_path = "/v1/engines",
+              tag = "engines",
+              responses((status = 200, description = "Last resource usage of each engine",
+                         body = [EngineResourceReport])))]
+#[get("/resources")]
+async fn get_engine_resources(responder: ApiResponder,
+                              security: DomainSecurity)
+                              -> ApiResponse<Vec<EngineResourceReport>> {
+    responder.respond(async move {
+                 require_operator(&security)?;
+
+                 get_tasks_supervisor().send(GetEngineResources)
+                                       .await
+                                       .map_err(bad_gateway)
+                                       .and_then(identity)
+             })
+             .await
+}
+
+/// Play a tone on a hardware output of the engine and read back the peak levels of hardware inputs, to verify the
+/// patching of converters and fixed instances remotely. Responds once the tone ended.
+#[utoipa::path(context_path = "/v1/engines",
+              tag = "engines",
+              params(("engine_id" = String, Path, description = "Engine ID")),
+              request_body = EngineTestTone,
+              responses((status = 200, description = "Peak level of each measured input", body = EngineTestToneResult),
+                        (status = 502, description = "Engine busy, unreachable or did not report back")))]
+#[post("/{engine_id}/test-tone")]
+async fn run_engine_test_tone(responder: ApiResponder,
+                              security: DomainSecurity,
+                              path: Path<EngineIdPath>,
+                              tone: Json<EngineTestTone>)
+                              -> ApiResponse<EngineTestToneResult> {
+    let engine_id = path.into_inner().engine_id;
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "run_engine_test_tone");
+    let audit = audit.with_params(json!({ "engine_id": &engine_id, "tone": &tone.0 }));
+
+    let run = RunEngineTestTone { engine_id: { engine_id },
+                                  tone:      { tone.into_inner() }, };
+
+    responder.respond(audited(audit, async move {
+                          require_operator(&security)?;
+
+                          get_tasks_supervisor().send(run)
+                                                .await
+                                                .map_err(bad_gateway)
+                                                .and_then(identity)
+                      }))
+             .await
+}
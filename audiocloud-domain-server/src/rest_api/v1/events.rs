@@ -0,0 +1,49 @@
+use std::convert::identity;
+
+use actix_web::{get, web};
+
+use audiocloud_api::domain::DomainError;
+
+use crate::journal::{get_journal_supervisor, JournalQuery, JournalReplay, ReplayJournal};
+use crate::rest_api::{bad_gateway, ApiResponder, ApiResponse};
+use crate::tasks::{get_tasks_supervisor, RequireTaskScope};
+use crate::{DomainSecurity, SecureKeyScope};
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(replay_events);
+}
+
+#[utoipa::path(context_path = "/v1/events",
+              tag = "events",
+              params(("since" = Option<i64>, Query, description = "Only events after this sequence number"),
+                     ("task_id" = Option<String>, Query, description = "Only events of this task, required for apps"),
+                     ("limit" = Option<usize>, Query, description = "Maximum number of events to return, oldest first")),
+              responses((status = 200, description = "Journaled domain events", body = JournalReplay)))]
+#[get("")]
+async fn replay_events(responder: ApiResponder,
+                       security: DomainSecurity,
+                       query: web::Query<JournalQuery>)
+                       -> ApiResponse<JournalReplay> {
+    let query = query.into_inner();
+
+    responder.respond(async move {
+                 // apps only replay the events of tasks they have access to
+                 if !security.is_cloud() {
+                     let task_id = query.task_id.clone().ok_or(DomainError::AuthenticationFailed)?;
+                     let require = RequireTaskScope { task_id:  { task_id },
+                                                      security: { security },
+                                                      scope:    { SecureKeyScope::Listen }, };
+
+                     get_tasks_supervisor().send(require)
+                                           .await
+                                           .map_err(bad_gateway)
+                                           .and_then(identity)?;
+                 }
+
+                 get_journal_supervisor().send(ReplayJournal { query })
+                                         .await
+                                         .map_err(bad_gateway)
+                                         .and_then(identity)
+             })
+             .await
+}
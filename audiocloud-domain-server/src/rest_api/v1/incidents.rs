@@ -0,0 +1,74 @@
+use std::convert::identity;
+
+use actix_web::{get, web};
+use serde::Deserialize;
+
+use crate::incidents::{get_incidents_supervisor, GetIncident, Incident, IncidentId, ListIncidents};
+use crate::rest_api::{bad_gateway, ApiError, ApiResponder, ApiResponse};
+use crate::DomainSecurity;
+
+use super::require_operator;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_incidents).service(get_incident);
+}
+
+const DEFAULT_INCIDENTS_LIMIT: usize = 100;
+const MAX_INCIDENTS_LIMIT: usize = 1000;
+
+#[derive(Deserialize)]
+pub struct ListIncidentsQuery {
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct IncidentIdPath {
+    incident_id: IncidentId,
+}
+
+#[utoipa::path(context_path = "/v1/incidents",
+              tag = "incidents",
+              params(("limit" = Option<usize>, Query, description = "Most recent incidents to return, up to 1000")),
+              responses((status = 200, description = "Recent incidents", body = [Incident])))]
+#[get("")]
+async fn list_incidents(responder: ApiResponder,
+                        security: DomainSecurity,
+                        query: web::Query<ListIncidentsQuery>)
+                        -> ApiResponse<Vec<Incident>> {
+    let list = ListIncidents { limit: query.limit.unwrap_or(DEFAULT_INCIDENTS_LIMIT).min(MAX_INCIDENTS_LIMIT), };
+
+    responder.respond(async move {
+                 require_operator(&security)?;
+
+                 get_incidents_supervisor().send(list)
+                                           .await
+                                           .map_err(bad_gateway)
+                                           .and_then(identity)
+             })
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/incidents",
+              tag = "incidents",
+              params(("incident_id" = String, Path, description = "Incident ID")),
+              responses((status = 200, description = "Incident", body = Incident),
+                        (status = 404, description = "No incident with this ID", body = ApiError)))]
+#[get("/{incident_id}")]
+async fn get_incident(responder: ApiResponder,
+                      security: DomainSecurity,
+                      path: web::Path<IncidentIdPath>)
+                      -> ApiResponse<Incident> {
+    let incident_id = path.into_inner().incident_id;
+    let get = GetIncident { incident_id };
+
+    responder.respond_found(async move {
+                                require_operator(&security)?;
+
+                                get_incidents_supervisor().send(get)
+                                                          .await
+                                                          .map_err(bad_gateway)
+                                                          .and_then(identity)
+                            },
+                            || ApiError::incident_not_found(&incident_id))
+             .await
+}
@@ -0,0 +1,146 @@
+use std::convert::identity;
+
+use actix_web::{get, web, HttpResponse};
+use chrono::Utc;
+use serde::Deserialize;
+
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::{FixedInstanceId, Timestamp};
+
+use crate::fixed_instances::{
+    get_instance_supervisor, to_ical, GetInstanceCalendar, InstanceCalendarEntry, InstanceCalendarQuery,
+};
+use crate::rest_api::{bad_gateway, ApiResponder, ApiResponse, RestOpts};
+use crate::telemetry::{get_telemetry_supervisor, GetInstanceReports, InstanceReportSeries, InstanceReportsQuery};
+use crate::DomainSecurity;
+
+use super::{error_response, require_operator};
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_instance_reports)
+       .service(get_instance_calendar)
+       .service(get_instance_calendar_feed);
+}
+
+#[derive(Deserialize)]
+pub struct FixedInstanceIdPath {
+    manufacturer: String,
+    name:         String,
+    instance:     String,
+}
+
+impl Into<FixedInstanceId> for FixedInstanceIdPath {
+    fn into(self) -> FixedInstanceId {
+        let Self { manufacturer,
+                   name,
+                   instance, } = self;
+        FixedInstanceId::new(manufacturer, name, instance)
+    }
+}
+
+#[utoipa::path(context_path = "/v1/instances",
+              tag = "instances",
+              params(("manufacturer" = String, Path, description = "Manufacturer of the instance model"),
+                     ("name" = String, Path, description = "Name of the instance model"),
+                     ("instance" = String, Path, description = "Instance of the model"),
+                     ("from" = Option<String>, Query, description = "Only values reported at or after this time"),
+                     ("to" = Option<String>, Query, description = "Only values reported at or before this time"),
+                     ("report_id" = Option<String>, Query, description = "Only this report"),
+                     ("max_points" = Option<usize>, Query, description = "Maximum number of points per series, 1000 by default")),
+              responses((status = 200, description = "Reported values by report and channel", body = [InstanceReportSeries])))]
+#[get("/{manufacturer}/{name}/{instance}/reports")]
+async fn get_instance_reports(responder: ApiResponder,
+                              security: DomainSecurity,
+                              path: web::Path<FixedInstanceIdPath>,
+                              query: web::Query<InstanceReportsQuery>)
+                              -> ApiResponse<Vec<InstanceReportSeries>> {
+    let get = GetInstanceReports { instance_id: { path.into_inner().into() },
+                                   query:       { query.into_inner() }, };
+
+    responder.respond(async move {
+                 require_operator(&security)?;
+
+                 get_telemetry_supervisor().send(get)
+                                           .await
+                                           .map_err(bad_gateway)
+                                           .and_then(identity)
+             })
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/instances",
+              tag = "instances",
+              params(("manufacturer" = String, Path, description = "Manufacturer of the instance model"),
+                     ("name" = String, Path, description = "Name of the instance model"),
+                     ("instance" = String, Path, description = "Instance of the model, or of a composite instance"),
+                     ("from" = Option<String>, Query, description = "Only entries ending after this time"),
+                     ("to" = Option<String>, Query, description = "Only entries starting before this time")),
+              responses((status = 200,
+                         description = "Reservations and maintenance windows in order of their start",
+                         body = [InstanceCalendarEntry])))]
+#[get("/{manufacturer}/{name}/{instance}/calendar")]
+async fn get_instance_calendar(responder: ApiResponder,
+                               security: DomainSecurity,
+                               path: web::Path<FixedInstanceIdPath>,
+                               query: web::Query<InstanceCalendarQuery>)
+                               -> ApiResponse<Vec<InstanceCalendarEntry>> {
+    let get = GetInstanceCalendar { instance_id: { path.into_inner().into() },
+                                    query:       { query.into_inner() }, };
+
+    responder.respond(async move {
+                 require_operator(&security)?;
+
+                 get_instance_supervisor().send(get)
+                                          .await
+                                          .map_err(bad_gateway)
+                                          .and_then(identity)
+             })
+             .await
+}
+
+#[derive(Deserialize)]
+pub struct InstanceCalendarFeedQuery {
+    from:  Option<Timestamp>,
+    to:    Option<Timestamp>,
+    token: Option<String>,
+}
+
+#[utoipa::path(context_path = "/v1/instances",
+              tag = "instances",
+              params(("manufacturer" = String, Path, description = "Manufacturer of the instance model"),
+                     ("name" = String, Path, description = "Name of the instance model"),
+                     ("instance" = String, Path, description = "Instance of the model, or of a composite instance"),
+                     ("from" = Option<String>, Query, description = "Only entries ending after this time"),
+                     ("to" = Option<String>, Query, description = "Only entries starting before this time"),
+                     ("token" = Option<String>, Query, description = "Calendar feed token, in place of authorization")),
+              responses((status = 200,
+                         description = "Reservations and maintenance windows as an iCalendar feed",
+                         content_type = "text/calendar")))]
+#[get("/{manufacturer}/{name}/{instance}/calendar.ics")]
+async fn get_instance_calendar_feed(opts: web::Data<RestOpts>,
+                                    security: Option<DomainSecurity>,
+                                    path: web::Path<FixedInstanceIdPath>,
+                                    query: web::Query<InstanceCalendarFeedQuery>)
+                                    -> HttpResponse {
+    let instance_id: FixedInstanceId = path.into_inner().into();
+    let InstanceCalendarFeedQuery { from, to, token } = query.into_inner();
+
+    let feed_token_matches =
+        matches!((&token, &opts.rest_calendar_feed_token), (Some(token), Some(feed_token)) if token == feed_token);
+    if !feed_token_matches && !security.map(|security| security.is_cloud()).unwrap_or(false) {
+        return error_response(DomainError::AuthenticationFailed);
+    }
+
+    let get = GetInstanceCalendar { instance_id: { instance_id.clone() },
+                                    query:       { InstanceCalendarQuery { from, to } }, };
+
+    match get_instance_supervisor().send(get)
+                                   .await
+                                   .map_err(bad_gateway)
+                                   .and_then(identity)
+    {
+        Ok(entries) => HttpResponse::Ok().content_type("text/calendar; charset=utf-8")
+                                         .body(to_ical(&instance_id, &entries, Utc::now())),
+        Err(error) => error_response(error),
+    }
+}
@@ -0,0 +1,64 @@
+use std::convert::identity;
+
+use actix_web::web::Json;
+use actix_web::{get, post, web};
+use serde_json::json;
+
+use crate::audit::{audited, AuditEntry, AuditOrigin};
+use crate::rest_api::{bad_gateway, ApiResponder, ApiResponse};
+use crate::sockets::{
+    get_sockets_supervisor, DrainReason, DrainSockets, ListSocketStats, SocketDrain, SocketDrainResult,
+    SocketStatsReport,
+};
+use crate::DomainSecurity;
+
+use super::require_operator;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_socket_stats).service(drain_sockets);
+}
+
+/// Round trip time, jitter and loss of the pings to each connected socket, and the traffic of WebRTC data channels
+#[utoipa::path(context_path = "/v1/sockets",
+              tag = "sockets",
+              responses((status = 200, description = "Statistics of connected sockets", body = [SocketStatsReport])))]
+#[get("")]
+async fn list_socket_stats(responder: ApiResponder, security: DomainSecurity) -> ApiResponse<Vec<SocketStatsReport>> {
+    responder.respond(async move {
+                 require_operator(&security)?;
+
+                 get_sockets_supervisor().send(ListSocketStats)
+                                         .await
+                                         .map_err(bad_gateway)
+             })
+             .await
+}
+
+/// Take the domain out of service: tell every client its streams are ending and where to reconnect, close all sockets
+/// once they had time to flush and refuse new ones until the domain server restarts. Responds once the sockets closed.
+#[utoipa::path(context_path = "/v1/sockets",
+              tag = "sockets",
+              request_body = SocketDrain,
+              responses((status = 200, description = "Drained clients and sockets", body = SocketDrainResult)))]
+#[post("/drain")]
+async fn drain_sockets(responder: ApiResponder,
+                       security: DomainSecurity,
+                       drain: Json<SocketDrain>)
+                       -> ApiResponse<SocketDrainResult> {
+    let drain = drain.into_inner();
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "drain_sockets");
+    let audit = audit.with_params(json!({ "reconnect_url": &drain.reconnect_url }));
+
+    let drain = DrainSockets { reason:        { DrainReason::Maintenance },
+                               reconnect_url: { drain.reconnect_url }, };
+
+    responder.respond(audited(audit, async move {
+                          require_operator(&security)?;
+
+                          get_sockets_supervisor().send(drain)
+                                                  .await
+                                                  .map_err(bad_gateway)
+                                                  .and_then(identity)
+                      }))
+             .await
+}
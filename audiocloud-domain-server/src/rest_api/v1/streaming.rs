@@ -31,6 +31,14 @@ pub struct AppTaskPlayIdPacketPath {
     serial:  u64,
 }
 
+#[utoipa::path(context_path = "/v1/streams",
+              tag = "streaming",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID"),
+                     ("play_id" = String, Path, description = "Play ID")),
+              responses((status = 200,
+                         description = "Statistics of the packets cached for a play session",
+                         body = Object)))]
 #[get("/{app_id}/{task_id}/{play_id}")]
 pub async fn get_stream_stats(path: web::Path<AppTaskPlayIdPath>,
                               responder: ApiResponder,
@@ -51,6 +59,15 @@ pub async fn get_stream_stats(path: web::Path<AppTaskPlayIdPath>,
              .await
 }
 
+#[utoipa::path(context_path = "/v1/streams",
+              tag = "streaming",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID"),
+                     ("play_id" = String, Path, description = "Play ID"),
+                     ("serial" = u64, Path, description = "Packet serial number")),
+              responses((status = 200,
+                         description = "Cached streaming packet",
+                         body = Object)))]
 #[get("/{app_id}/{task_id}/{play_id}/packet/{serial}")]
 pub async fn get_stream_packet(path: web::Path<AppTaskPlayIdPacketPath>,
                                responder: ApiResponder,
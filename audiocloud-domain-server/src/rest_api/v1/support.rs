@@ -0,0 +1,46 @@
+use actix_web::http::header::{CONTENT_DISPOSITION, RETRY_AFTER};
+use actix_web::{post, web, HttpResponse};
+
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::now;
+
+use crate::rest_api::ApiError;
+use crate::support::{check_support_bundle_rate, create_support_bundle};
+use crate::DomainSecurity;
+
+use super::{error_response, require_operator};
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(create_bundle);
+}
+
+#[utoipa::path(context_path = "/v1/support-bundle",
+              tag = "support",
+              responses((status = 200,
+                         description = "Gzipped tar archive of recent logs, the redacted config, a metrics snapshot, \
+                                        recent journal events, fixed instance state and recent incidents",
+                         content_type = "application/gzip"),
+                        (status = 429, description = "A support bundle was created too recently", body = ApiError)))]
+#[post("")]
+async fn create_bundle(security: DomainSecurity) -> HttpResponse {
+    if let Err(error) = require_operator(&security) {
+        return error_response(error);
+    }
+
+    if let Err(retry_after) = check_support_bundle_rate() {
+        let retry_after = retry_after.as_secs() + 1;
+        return HttpResponse::TooManyRequests().insert_header((RETRY_AFTER, retry_after))
+                                              .json(ApiError::rate_limited(retry_after));
+    }
+
+    match create_support_bundle().await {
+        Ok(bundle) => {
+            let file_name = format!("support-bundle-{}.tar.gz", now().format("%Y%m%dT%H%M%SZ"));
+
+            HttpResponse::Ok().content_type("application/gzip")
+                              .insert_header((CONTENT_DISPOSITION, format!("attachment; filename=\"{file_name}\"")))
+                              .body(bundle)
+        }
+        Err(error) => error_response(DomainError::BadGateway { error: error.to_string(), }),
+    }
+}
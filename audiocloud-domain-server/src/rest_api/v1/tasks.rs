@@ -1,9 +1,14 @@
 use std::convert::identity;
 use std::str::FromStr;
 
-use actix_web::http::header::IfMatch;
+use actix::Actor;
+use actix_web::http::header::{IfMatch, CACHE_CONTROL};
 use actix_web::web::{Header, Json};
-use actix_web::{delete, get, post, web};
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use futures::stream;
+use serde_json::json;
+use tokio::sync::mpsc;
+use tracing::*;
 
 use web::Path;
 
@@ -12,30 +17,85 @@ use audiocloud_api::domain::tasks::{
     CreateTask, ModifyTask, TaskCreated, TaskDeleted, TaskSummaryList, TaskUpdated, TaskWithStatusAndSpec,
 };
 use audiocloud_api::domain::DomainError;
-use audiocloud_api::{RequestCancelRender, RequestPlay, RequestRender, RequestSeek, RequestStopPlay};
+use audiocloud_api::{AppTaskId, RequestCancelRender, RequestSeek, RequestStopPlay, TaskSecurity};
 
+use crate::audit::{audited, AuditEntry, AuditOrigin};
 use crate::rest_api::{ApiResponder, ApiResponse, AppTaskIdPath};
-use crate::tasks::{get_tasks_supervisor, messages, ListTasks};
-use crate::{rest_api, DomainResult, DomainSecurity};
+use crate::tasks::event_stream::{parse_last_event_id, TaskEventStream};
+use crate::tasks::{
+    get_tasks_supervisor, messages, ListTasks, RequestPausePlay, TaskDiagnostics, TaskEnvelopes, TaskKeyScopeUpdate,
+    TaskLatencyProfile, TaskLeadIn, TaskLoudnessTarget, TaskMediaFades, TaskMediaRates, TaskMediaRatesState,
+    TaskMonitor, TaskNullTestRequest, TaskPlayPause, TaskPlayRequest, TaskPlaylist, TaskRecallSheet, TaskRecording,
+    TaskRenderRequest, TaskRoutingVerification, TaskSafeMode, TaskSecureKeyRevocation, TaskSecureKeyRotation,
+    TaskSpecDiff, TaskSpecElements, TaskStreamCodec, TaskTakeLanes, TaskTempoMap, TaskTrackGroups,
+    TaskTrackInputUpdate, TaskTrackInputs, TaskWatermark,
+};
+use crate::{rest_api, DomainResult, DomainSecurity, SecureKeyScope, TaskKeyScopes};
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(list_tasks)
        .service(create_task)
        .service(get_task)
+       .service(get_task_spec_diff)
+       .service(get_task_safe_mode)
+       .service(enable_task_spec_elements)
+       .service(get_task_routing_verification)
+       .service(verify_task_routing)
+       .service(get_task_key_scopes)
+       .service(set_task_key_scope)
+       .service(rotate_task_secure_key)
+       .service(revoke_task_secure_key)
+       .service(get_task_track_inputs)
+       .service(set_task_track_input)
+       .service(set_task_recording)
+       .service(set_task_lead_in)
+       .service(set_task_latency_profile)
+       .service(set_task_stream_codec)
+       .service(set_task_loudness_target)
+       .service(set_task_watermark)
+       .service(get_task_tempo_map)
+       .service(set_task_tempo_map)
+       .service(get_task_playlist)
+       .service(set_task_playlist)
+       .service(get_task_track_groups)
+       .service(set_task_track_groups)
+       .service(get_task_envelopes)
+       .service(set_task_envelopes)
+       .service(get_task_fades)
+       .service(set_task_fades)
+       .service(get_task_media_rates)
+       .service(set_task_media_rates)
+       .service(get_task_monitor)
+       .service(set_task_monitor)
+       .service(get_task_takes)
+       .service(get_task_diagnostics)
+       .service(get_task_events)
        .service(modify_task)
        .service(delete_task)
        .service(render_task)
+       .service(run_task_null_test)
+       .service(generate_task_recall_sheet)
        .service(play_task)
        .service(seek_task)
        .service(cancel_render_task)
-       .service(stop_play_task);
+       .service(stop_play_task)
+       .service(pause_play_task)
+       .service(resume_play_task);
 }
 
+const LAST_EVENT_ID: &'static str = "Last-Event-ID";
+const EVENT_STREAM_BUFFER: usize = 256;
+
 fn not_implemented_yet<T>(call: &'static str) -> Result<T, DomainError> {
     Err(DomainError::NotImplemented { call:   call.to_string(),
                                       reason: "Not implemented yet".to_string(), })
 }
 
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              responses((status = 200,
+                         description = "Summaries of all tasks on this domain",
+                         body = Object)))]
 #[get("")]
 async fn list_tasks(responder: ApiResponder) -> ApiResponse<TaskSummaryList> {
     responder.respond(async move {
@@ -46,15 +106,55 @@ async fn list_tasks(responder: ApiResponder) -> ApiResponse<TaskSummaryList> {
              .await
 }
 
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              request_body(content = Object,
+                           description = "Task ID, reservations, spec and security of the task to create"),
+              responses((status = 200,
+                         description = "Task created",
+                         body = Object),
+                        (status = 409, description = "Task already exists or resources are unavailable")))]
 #[post("")]
-async fn create_task(responder: ApiResponder, create: Json<CreateTask>) -> ApiResponse<TaskCreated> {
+async fn create_task(responder: ApiResponder,
+                     security: Option<DomainSecurity>,
+                     create: Json<CreateTask>)
+                     -> ApiResponse<TaskCreated> {
+    let audit = match &security {
+        Some(security) => AuditEntry::new(AuditOrigin::Rest, security, "create_task"),
+        None => AuditEntry::anonymous(AuditOrigin::Rest, "create_task"),
+    };
+
+    // task security holds secure keys, which are never written to the audit log
+    let audit = audit.with_task(&create.0.task_id)
+                     .with_params(json!({ "reservations": &create.0.reservations, "spec": &create.0.spec }));
+
     let create = messages::CreateTask { task_id:      create.0.task_id,
                                         reservations: create.0.reservations,
                                         spec:         create.0.spec,
                                         security:     create.0.security, };
 
+    responder.respond(audited(audit, async move {
+                          get_tasks_supervisor().send(create)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              responses((status = 200,
+                         description = "Task with current status and spec",
+                         body = Object)))]
+#[get("/{app_id}/{task_id}")]
+async fn get_task(responder: ApiResponder, task_id: Path<AppTaskIdPath>) -> ApiResponse<TaskWithStatusAndSpec> {
+    let task_id = task_id.into_inner().into();
+    let get = messages::GetTaskWithStatusAndSpec { task_id };
     responder.respond(async move {
-                 get_tasks_supervisor().send(create)
+                 get_tasks_supervisor().send(get)
                                        .await
                                        .map_err(rest_api::bad_gateway)
                                        .and_then(identity)
@@ -62,10 +162,916 @@ async fn create_task(responder: ApiResponder, create: Json<CreateTask>) -> ApiRe
              .await
 }
 
-#[get("/{app_id}/{task_id}")]
-async fn get_task(responder: ApiResponder, task_id: Path<AppTaskIdPath>) -> ApiResponse<TaskWithStatusAndSpec> {
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              responses((status = 200,
+                         description = "Difference between the spec and settings of the domain and the engine",
+                         body = TaskSpecDiff)))]
+#[get("/{app_id}/{task_id}/spec/diff")]
+async fn get_task_spec_diff(responder: ApiResponder,
+                            security: DomainSecurity,
+                            task_id: Path<AppTaskIdPath>)
+                            -> ApiResponse<TaskSpecDiff> {
+    let get = messages::GetTaskSpecDiff { task_id:  { task_id.into_inner().into() },
+                                          security: { security }, };
+
+    responder.respond(async move {
+                 get_tasks_supervisor().send(get)
+                                       .await
+                                       .map_err(rest_api::bad_gateway)
+                                       .and_then(identity)
+             })
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              responses((status = 200,
+                         description = "Safe mode state and the spec elements withheld from the engine",
+                         body = TaskSafeMode)))]
+#[get("/{app_id}/{task_id}/safe-mode")]
+async fn get_task_safe_mode(responder: ApiResponder,
+                            security: DomainSecurity,
+                            task_id: Path<AppTaskIdPath>)
+                            -> ApiResponse<TaskSafeMode> {
+    let get = messages::GetTaskSafeMode { task_id:  { task_id.into_inner().into() },
+                                          security: { security }, };
+
+    responder.respond(async move {
+                 get_tasks_supervisor().send(get)
+                                       .await
+                                       .map_err(rest_api::bad_gateway)
+                                       .and_then(identity)
+             })
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body = TaskSpecElements,
+              responses((status = 200,
+                         description = "Elements sent to the engine again, they are disabled again if rejected",
+                         body = TaskSafeMode)))]
+#[post("/{app_id}/{task_id}/safe-mode/enable")]
+async fn enable_task_spec_elements(responder: ApiResponder,
+                                   security: DomainSecurity,
+                                   task_id: Path<AppTaskIdPath>,
+                                   elements: Json<TaskSpecElements>)
+                                   -> ApiResponse<TaskSafeMode> {
     let task_id = task_id.into_inner().into();
-    let get = messages::GetTaskWithStatusAndSpec { task_id };
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "enable_task_spec_elements").with_task(&task_id)
+                                                                                          .with_params(&elements.0);
+
+    let enable = messages::EnableTaskSpecElements { task_id:  { task_id },
+                                                    elements: { elements.into_inner() },
+                                                    security: { security }, };
+
+    responder.respond(audited(audit, async move {
+                          get_tasks_supervisor().send(enable)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              responses((status = 200,
+                         description = "Signal path verification of the fixed instances, with a diagnosis per channel",
+                         body = TaskRoutingVerification)))]
+#[get("/{app_id}/{task_id}/routing-verification")]
+async fn get_task_routing_verification(responder: ApiResponder,
+                                       security: DomainSecurity,
+                                       task_id: Path<AppTaskIdPath>)
+                                       -> ApiResponse<TaskRoutingVerification> {
+    let get = messages::GetTaskRoutingVerification { task_id:  { task_id.into_inner().into() },
+                                                     security: { security }, };
+
+    responder.respond(async move {
+                 get_tasks_supervisor().send(get)
+                                       .await
+                                       .map_err(rest_api::bad_gateway)
+                                       .and_then(identity)
+             })
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              responses((status = 200,
+                         description = "Verification pending, it runs once the fixed instances are ready",
+                         body = TaskRoutingVerification)))]
+#[post("/{app_id}/{task_id}/routing-verification")]
+async fn verify_task_routing(responder: ApiResponder,
+                             security: DomainSecurity,
+                             task_id: Path<AppTaskIdPath>)
+                             -> ApiResponse<TaskRoutingVerification> {
+    let task_id = task_id.into_inner().into();
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "verify_task_routing").with_task(&task_id);
+
+    let verify = messages::VerifyTaskRouting { task_id:  { task_id },
+                                               security: { security }, };
+
+    responder.respond(audited(audit, async move {
+                          get_tasks_supervisor().send(verify)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              responses((status = 200,
+                         description = "Scopes of the task secure keys, keys not listed have full access",
+                         body = Object)))]
+#[get("/{app_id}/{task_id}/key-scopes")]
+async fn get_task_key_scopes(responder: ApiResponder,
+                             security: DomainSecurity,
+                             task_id: Path<AppTaskIdPath>)
+                             -> ApiResponse<TaskKeyScopes> {
+    let get = messages::GetTaskKeyScopes { task_id:  { task_id.into_inner().into() },
+                                           security: { security }, };
+
+    responder.respond(async move {
+                 get_tasks_supervisor().send(get)
+                                       .await
+                                       .map_err(rest_api::bad_gateway)
+                                       .and_then(identity)
+             })
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body = TaskKeyScopeUpdate,
+              responses((status = 200,
+                         description = "Scopes of the task secure keys after the update",
+                         body = Object)))]
+#[post("/{app_id}/{task_id}/key-scopes")]
+async fn set_task_key_scope(responder: ApiResponder,
+                            security: DomainSecurity,
+                            task_id: Path<AppTaskIdPath>,
+                            update: Json<TaskKeyScopeUpdate>)
+                            -> ApiResponse<TaskKeyScopes> {
+    let task_id = task_id.into_inner().into();
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "set_task_key_scope").with_task(&task_id)
+                                                                                   .with_params(&update.0);
+
+    let set = messages::SetTaskKeyScope { task_id:  { task_id },
+                                          update:   { update.into_inner() },
+                                          security: { security }, };
+
+    responder.respond(audited(audit, async move {
+                          get_tasks_supervisor().send(set)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body = TaskSecureKeyRotation,
+              responses((status = 200,
+                         description = "Secure keys of the task after the rotation",
+                         body = Object)))]
+#[post("/{app_id}/{task_id}/secure-keys/rotate")]
+async fn rotate_task_secure_key(responder: ApiResponder,
+                                security: DomainSecurity,
+                                task_id: Path<AppTaskIdPath>,
+                                rotation: Json<TaskSecureKeyRotation>)
+                                -> ApiResponse<TaskSecurity> {
+    let task_id = task_id.into_inner().into();
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "rotate_task_secure_key").with_task(&task_id);
+
+    let rotate = messages::RotateTaskSecureKey { task_id:  { task_id },
+                                                 rotation: { rotation.into_inner() },
+                                                 security: { security }, };
+
+    responder.respond(audited(audit, async move {
+                          get_tasks_supervisor().send(rotate)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body = TaskSecureKeyRevocation,
+              responses((status = 200,
+                         description = "Secure keys of the task, clients using the revoked key are dropped",
+                         body = Object)))]
+#[post("/{app_id}/{task_id}/secure-keys/revoke")]
+async fn revoke_task_secure_key(responder: ApiResponder,
+                                security: DomainSecurity,
+                                task_id: Path<AppTaskIdPath>,
+                                revocation: Json<TaskSecureKeyRevocation>)
+                                -> ApiResponse<TaskSecurity> {
+    let task_id = task_id.into_inner().into();
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "revoke_task_secure_key").with_task(&task_id);
+
+    let revoke = messages::RevokeTaskSecureKey { task_id:    { task_id },
+                                                 revocation: { revocation.into_inner() },
+                                                 security:   { security }, };
+
+    responder.respond(audited(audit, async move {
+                          get_tasks_supervisor().send(revoke)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              responses((status = 200,
+                         description = "Hardware inputs of the task tracks, by track ID",
+                         body = Object)))]
+#[get("/{app_id}/{task_id}/track-inputs")]
+async fn get_task_track_inputs(responder: ApiResponder,
+                               security: DomainSecurity,
+                               task_id: Path<AppTaskIdPath>)
+                               -> ApiResponse<TaskTrackInputs> {
+    let get = messages::GetTaskTrackInputs { task_id:  { task_id.into_inner().into() },
+                                             security: { security }, };
+
+    responder.respond(async move {
+                 get_tasks_supervisor().send(get)
+                                       .await
+                                       .map_err(rest_api::bad_gateway)
+                                       .and_then(identity)
+             })
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body = TaskTrackInputUpdate,
+              responses((status = 200,
+                         description = "Hardware inputs of the task tracks after the update",
+                         body = Object)))]
+#[post("/{app_id}/{task_id}/track-inputs")]
+async fn set_task_track_input(responder: ApiResponder,
+                              security: DomainSecurity,
+                              task_id: Path<AppTaskIdPath>,
+                              update: Json<TaskTrackInputUpdate>)
+                              -> ApiResponse<TaskTrackInputs> {
+    let task_id = task_id.into_inner().into();
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "set_task_track_input").with_task(&task_id)
+                                                                                     .with_params(&update.0);
+
+    let set = messages::SetTaskTrackInput { task_id:  { task_id },
+                                            update:   { update.into_inner() },
+                                            security: { security }, };
+
+    responder.respond(audited(audit, async move {
+                          get_tasks_supervisor().send(set)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body = TaskRecording,
+              responses((status = 200,
+                         description = "Recording state of the task after the update",
+                         body = TaskRecording)))]
+#[post("/{app_id}/{task_id}/recording")]
+async fn set_task_recording(responder: ApiResponder,
+                            security: DomainSecurity,
+                            task_id: Path<AppTaskIdPath>,
+                            recording: Json<TaskRecording>)
+                            -> ApiResponse<TaskRecording> {
+    let task_id = task_id.into_inner().into();
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "set_task_recording").with_task(&task_id)
+                                                                                   .with_params(&recording.0);
+
+    let set = messages::SetTaskRecording { task_id:   { task_id },
+                                           recording: { recording.into_inner() },
+                                           security:  { security }, };
+
+    responder.respond(audited(audit, async move {
+                          get_tasks_supervisor().send(set)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body = TaskLeadIn,
+              responses((status = 200,
+                         description = "Lead-in of the task after the update",
+                         body = TaskLeadIn)))]
+#[post("/{app_id}/{task_id}/lead-in")]
+async fn set_task_lead_in(responder: ApiResponder,
+                          security: DomainSecurity,
+                          task_id: Path<AppTaskIdPath>,
+                          lead_in: Json<TaskLeadIn>)
+                          -> ApiResponse<TaskLeadIn> {
+    let task_id = task_id.into_inner().into();
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "set_task_lead_in").with_task(&task_id)
+                                                                                 .with_params(&lead_in.0);
+
+    let set = messages::SetTaskLeadIn { task_id:  { task_id },
+                                        lead_in:  { lead_in.into_inner() },
+                                        security: { security }, };
+
+    responder.respond(audited(audit, async move {
+                          get_tasks_supervisor().send(set)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body = TaskLatencyProfile,
+              responses((status = 200,
+                         description = "Latency profile of the task after the update",
+                         body = TaskLatencyProfile)))]
+#[post("/{app_id}/{task_id}/latency-profile")]
+async fn set_task_latency_profile(responder: ApiResponder,
+                                  security: DomainSecurity,
+                                  task_id: Path<AppTaskIdPath>,
+                                  profile: Json<TaskLatencyProfile>)
+                                  -> ApiResponse<TaskLatencyProfile> {
+    let task_id = task_id.into_inner().into();
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "set_task_latency_profile").with_task(&task_id)
+                                                                                         .with_params(&profile.0);
+
+    let set = messages::SetTaskLatencyProfile { task_id:  { task_id },
+                                                profile:  { profile.into_inner() },
+                                                security: { security }, };
+
+    responder.respond(audited(audit, async move {
+                          get_tasks_supervisor().send(set)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body = TaskStreamCodec,
+              responses((status = 200,
+                         description = "Codec of the task after the update, used from the next play",
+                         body = TaskStreamCodec)))]
+#[post("/{app_id}/{task_id}/stream-codec")]
+async fn set_task_stream_codec(responder: ApiResponder,
+                               security: DomainSecurity,
+                               task_id: Path<AppTaskIdPath>,
+                               codec: Json<TaskStreamCodec>)
+                               -> ApiResponse<TaskStreamCodec> {
+    let task_id = task_id.into_inner().into();
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "set_task_stream_codec").with_task(&task_id)
+                                                                                      .with_params(&codec.0);
+
+    let set = messages::SetTaskStreamCodec { task_id:  { task_id },
+                                             codec:    { codec.into_inner() },
+                                             security: { security }, };
+
+    responder.respond(audited(audit, async move {
+                          get_tasks_supervisor().send(set)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body = TaskLoudnessTarget,
+              responses((status = 200,
+                         description = "Loudness target of the task, used from the next play",
+                         body = TaskLoudnessTarget)))]
+#[post("/{app_id}/{task_id}/loudness-target")]
+async fn set_task_loudness_target(responder: ApiResponder,
+                                  security: DomainSecurity,
+                                  task_id: Path<AppTaskIdPath>,
+                                  target: Json<TaskLoudnessTarget>)
+                                  -> ApiResponse<TaskLoudnessTarget> {
+    let task_id = task_id.into_inner().into();
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "set_task_loudness_target").with_task(&task_id)
+                                                                                         .with_params(&target.0);
+
+    let set = messages::SetTaskLoudnessTarget { task_id:  { task_id },
+                                                target:   { target.into_inner() },
+                                                security: { security }, };
+
+    responder.respond(audited(audit, async move {
+                          get_tasks_supervisor().send(set)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body = TaskWatermark,
+              responses((status = 200,
+                         description = "Watermark of the task, used from the next play",
+                         body = TaskWatermark)))]
+#[post("/{app_id}/{task_id}/watermark")]
+async fn set_task_watermark(responder: ApiResponder,
+                            security: DomainSecurity,
+                            task_id: Path<AppTaskIdPath>,
+                            watermark: Json<TaskWatermark>)
+                            -> ApiResponse<TaskWatermark> {
+    let task_id = task_id.into_inner().into();
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "set_task_watermark").with_task(&task_id)
+                                                                                   .with_params(&watermark.0);
+
+    let set = messages::SetTaskWatermark { task_id:   { task_id },
+                                           watermark: { watermark.into_inner() },
+                                           security:  { security }, };
+
+    responder.respond(audited(audit, async move {
+                          get_tasks_supervisor().send(set)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              responses((status = 200,
+                         description = "Tempo changes and time signatures of the task",
+                         body = TaskTempoMap)))]
+#[get("/{app_id}/{task_id}/tempo-map")]
+async fn get_task_tempo_map(responder: ApiResponder,
+                            security: DomainSecurity,
+                            task_id: Path<AppTaskIdPath>)
+                            -> ApiResponse<TaskTempoMap> {
+    let get = messages::GetTaskTempoMap { task_id:  { task_id.into_inner().into() },
+                                          security: { security }, };
+
+    responder.respond(async move {
+                 get_tasks_supervisor().send(get)
+                                       .await
+                                       .map_err(rest_api::bad_gateway)
+                                       .and_then(identity)
+             })
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body = TaskTempoMap,
+              responses((status = 200,
+                         description = "Tempo map of the task after the update",
+                         body = TaskTempoMap)))]
+#[post("/{app_id}/{task_id}/tempo-map")]
+async fn set_task_tempo_map(responder: ApiResponder,
+                            security: DomainSecurity,
+                            task_id: Path<AppTaskIdPath>,
+                            tempo_map: Json<TaskTempoMap>)
+                            -> ApiResponse<TaskTempoMap> {
+    let task_id = task_id.into_inner().into();
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "set_task_tempo_map").with_task(&task_id)
+                                                                                   .with_params(&tempo_map.0);
+
+    let set = messages::SetTaskTempoMap { task_id:   { task_id },
+                                          tempo_map: { tempo_map.into_inner() },
+                                          security:  { security }, };
+
+    responder.respond(audited(audit, async move {
+                          get_tasks_supervisor().send(set)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              responses((status = 200,
+                         description = "Segments the plays of the task step through",
+                         body = TaskPlaylist)))]
+#[get("/{app_id}/{task_id}/playlist")]
+async fn get_task_playlist(responder: ApiResponder,
+                           security: DomainSecurity,
+                           task_id: Path<AppTaskIdPath>)
+                           -> ApiResponse<TaskPlaylist> {
+    let get = messages::GetTaskPlaylist { task_id:  { task_id.into_inner().into() },
+                                          security: { security }, };
+
+    responder.respond(async move {
+                 get_tasks_supervisor().send(get)
+                                       .await
+                                       .map_err(rest_api::bad_gateway)
+                                       .and_then(identity)
+             })
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body = TaskPlaylist,
+              responses((status = 200,
+                         description = "Playlist of the task after the update",
+                         body = TaskPlaylist)))]
+#[post("/{app_id}/{task_id}/playlist")]
+async fn set_task_playlist(responder: ApiResponder,
+                           security: DomainSecurity,
+                           task_id: Path<AppTaskIdPath>,
+                           playlist: Json<TaskPlaylist>)
+                           -> ApiResponse<TaskPlaylist> {
+    let task_id = task_id.into_inner().into();
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "set_task_playlist").with_task(&task_id)
+                                                                                  .with_params(&playlist.0);
+
+    let set = messages::SetTaskPlaylist { task_id:  { task_id },
+                                          playlist: { playlist.into_inner() },
+                                          security: { security }, };
+
+    responder.respond(audited(audit, async move {
+                          get_tasks_supervisor().send(set)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              responses((status = 200,
+                         description = "Groups of tracks with linked levels",
+                         body = TaskTrackGroups)))]
+#[get("/{app_id}/{task_id}/track-groups")]
+async fn get_task_track_groups(responder: ApiResponder,
+                               security: DomainSecurity,
+                               task_id: Path<AppTaskIdPath>)
+                               -> ApiResponse<TaskTrackGroups> {
+    let get = messages::GetTaskTrackGroups { task_id:  { task_id.into_inner().into() },
+                                             security: { security }, };
+
+    responder.respond(async move {
+                 get_tasks_supervisor().send(get)
+                                       .await
+                                       .map_err(rest_api::bad_gateway)
+                                       .and_then(identity)
+             })
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body = TaskTrackGroups,
+              responses((status = 200,
+                         description = "Track groups of the task after the update",
+                         body = TaskTrackGroups)))]
+#[post("/{app_id}/{task_id}/track-groups")]
+async fn set_task_track_groups(responder: ApiResponder,
+                               security: DomainSecurity,
+                               task_id: Path<AppTaskIdPath>,
+                               track_groups: Json<TaskTrackGroups>)
+                               -> ApiResponse<TaskTrackGroups> {
+    let task_id = task_id.into_inner().into();
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "set_task_track_groups").with_task(&task_id)
+                                                                                      .with_params(&track_groups.0);
+
+    let set = messages::SetTaskTrackGroups { task_id:      { task_id },
+                                             track_groups: { track_groups.into_inner() },
+                                             security:     { security }, };
+
+    responder.respond(audited(audit, async move {
+                          get_tasks_supervisor().send(set)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              responses((status = 200,
+                         description = "Parameters of the task automated over time",
+                         body = TaskEnvelopes)))]
+#[get("/{app_id}/{task_id}/envelopes")]
+async fn get_task_envelopes(responder: ApiResponder,
+                            security: DomainSecurity,
+                            task_id: Path<AppTaskIdPath>)
+                            -> ApiResponse<TaskEnvelopes> {
+    let get = messages::GetTaskEnvelopes { task_id:  { task_id.into_inner().into() },
+                                           security: { security }, };
+
+    responder.respond(async move {
+                 get_tasks_supervisor().send(get)
+                                       .await
+                                       .map_err(rest_api::bad_gateway)
+                                       .and_then(identity)
+             })
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body = TaskEnvelopes,
+              responses((status = 200,
+                         description = "Automation of the task after the update",
+                         body = TaskEnvelopes)))]
+#[post("/{app_id}/{task_id}/envelopes")]
+async fn set_task_envelopes(responder: ApiResponder,
+                            security: DomainSecurity,
+                            task_id: Path<AppTaskIdPath>,
+                            envelopes: Json<TaskEnvelopes>)
+                            -> ApiResponse<TaskEnvelopes> {
+    let task_id = task_id.into_inner().into();
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "set_task_envelopes").with_task(&task_id)
+                                                                                   .with_params(&envelopes.0);
+
+    let set = messages::SetTaskEnvelopes { task_id:   { task_id },
+                                           envelopes: { envelopes.into_inner() },
+                                           security:  { security }, };
+
+    responder.respond(audited(audit, async move {
+                          get_tasks_supervisor().send(set)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              responses((status = 200,
+                         description = "Fades of the track media of the task",
+                         body = TaskMediaFades)))]
+#[get("/{app_id}/{task_id}/fades")]
+async fn get_task_fades(responder: ApiResponder,
+                        security: DomainSecurity,
+                        task_id: Path<AppTaskIdPath>)
+                        -> ApiResponse<TaskMediaFades> {
+    let get = messages::GetTaskMediaFades { task_id:  { task_id.into_inner().into() },
+                                            security: { security }, };
+
+    responder.respond(async move {
+                 get_tasks_supervisor().send(get)
+                                       .await
+                                       .map_err(rest_api::bad_gateway)
+                                       .and_then(identity)
+             })
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body = TaskMediaFades,
+              responses((status = 200,
+                         description = "Fades of the track media after the update",
+                         body = TaskMediaFades)))]
+#[post("/{app_id}/{task_id}/fades")]
+async fn set_task_fades(responder: ApiResponder,
+                        security: DomainSecurity,
+                        task_id: Path<AppTaskIdPath>,
+                        fades: Json<TaskMediaFades>)
+                        -> ApiResponse<TaskMediaFades> {
+    let task_id = task_id.into_inner().into();
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "set_task_fades").with_task(&task_id)
+                                                                               .with_params(&fades.0);
+
+    let set = messages::SetTaskMediaFades { task_id:  { task_id },
+                                            fades:    { fades.into_inner() },
+                                            security: { security }, };
+
+    responder.respond(audited(audit, async move {
+                          get_tasks_supervisor().send(set)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              responses((status = 200,
+                         description = "Playback rates of the track media with their timeline lengths",
+                         body = TaskMediaRatesState)))]
+#[get("/{app_id}/{task_id}/media_rates")]
+async fn get_task_media_rates(responder: ApiResponder,
+                              security: DomainSecurity,
+                              task_id: Path<AppTaskIdPath>)
+                              -> ApiResponse<TaskMediaRatesState> {
+    let get = messages::GetTaskMediaRates { task_id:  { task_id.into_inner().into() },
+                                            security: { security }, };
+
+    responder.respond(async move {
+                 get_tasks_supervisor().send(get)
+                                       .await
+                                       .map_err(rest_api::bad_gateway)
+                                       .and_then(identity)
+             })
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body = TaskMediaRates,
+              responses((status = 200,
+                         description = "Playback rates of the track media after the update",
+                         body = TaskMediaRatesState)))]
+#[post("/{app_id}/{task_id}/media_rates")]
+async fn set_task_media_rates(responder: ApiResponder,
+                              security: DomainSecurity,
+                              task_id: Path<AppTaskIdPath>,
+                              rates: Json<TaskMediaRates>)
+                              -> ApiResponse<TaskMediaRatesState> {
+    let task_id = task_id.into_inner().into();
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "set_task_media_rates").with_task(&task_id)
+                                                                                     .with_params(&rates.0);
+
+    let set = messages::SetTaskMediaRates { task_id:  { task_id },
+                                            rates:    { rates.into_inner() },
+                                            security: { security }, };
+
+    responder.respond(audited(audit, async move {
+                          get_tasks_supervisor().send(set)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              responses((status = 200,
+                         description = "Parameters of the monitor controller of the task",
+                         body = TaskMonitor)))]
+#[get("/{app_id}/{task_id}/monitor")]
+async fn get_task_monitor(responder: ApiResponder,
+                          security: DomainSecurity,
+                          task_id: Path<AppTaskIdPath>)
+                          -> ApiResponse<TaskMonitor> {
+    let get = messages::GetTaskMonitor { task_id:  { task_id.into_inner().into() },
+                                         security: { security }, };
+
+    responder.respond(async move {
+                 get_tasks_supervisor().send(get)
+                                       .await
+                                       .map_err(rest_api::bad_gateway)
+                                       .and_then(identity)
+             })
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body = TaskMonitor,
+              responses((status = 200,
+                         description = "Parameters of the monitor controller after the update",
+                         body = TaskMonitor)))]
+#[post("/{app_id}/{task_id}/monitor")]
+async fn set_task_monitor(responder: ApiResponder,
+                          security: DomainSecurity,
+                          task_id: Path<AppTaskIdPath>,
+                          monitor: Json<TaskMonitor>)
+                          -> ApiResponse<TaskMonitor> {
+    let task_id = task_id.into_inner().into();
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "set_task_monitor").with_task(&task_id)
+                                                                                 .with_params(&monitor.0);
+
+    let set = messages::SetTaskMonitor { task_id:  { task_id },
+                                         monitor:  { monitor.into_inner() },
+                                         security: { security }, };
+
+    responder.respond(audited(audit, async move {
+                          get_tasks_supervisor().send(set)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              responses((status = 200,
+                         description = "Recorded takes of the task, by track ID",
+                         body = Object)))]
+#[get("/{app_id}/{task_id}/takes")]
+async fn get_task_takes(responder: ApiResponder,
+                        security: DomainSecurity,
+                        task_id: Path<AppTaskIdPath>)
+                        -> ApiResponse<TaskTakeLanes> {
+    let get = messages::GetTaskTakes { task_id:  { task_id.into_inner().into() },
+                                       security: { security }, };
+
+    responder.respond(async move {
+                 get_tasks_supervisor().send(get)
+                                       .await
+                                       .map_err(rest_api::bad_gateway)
+                                       .and_then(identity)
+             })
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              responses((status = 200,
+                         description = "Diagnostic bundles the engine captured of the task, oldest first",
+                         body = [TaskDiagnostics])))]
+#[get("/{app_id}/{task_id}/diagnostics")]
+async fn get_task_diagnostics(responder: ApiResponder,
+                              security: DomainSecurity,
+                              task_id: Path<AppTaskIdPath>)
+                              -> ApiResponse<Vec<TaskDiagnostics>> {
+    let get = messages::GetTaskDiagnostics { task_id:  { task_id.into_inner().into() },
+                                             security: { security }, };
+
     responder.respond(async move {
                  get_tasks_supervisor().send(get)
                                        .await
@@ -75,6 +1081,88 @@ async fn get_task(responder: ApiResponder, task_id: Path<AppTaskIdPath>) -> ApiR
              .await
 }
 
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID"),
+                     ("Last-Event-ID" = Option<String>, Header, description = "Resume packets after this event ID")),
+              responses((status = 200,
+                         description = "Server-Sent Events of task state, packet summaries, recorded takes and errors",
+                         body = String,
+                         content_type = "text/event-stream"),
+                        (status = 401, description = "The secure key or token may not listen to the task")))]
+#[get("/{app_id}/{task_id}/events")]
+async fn get_task_events(responder: ApiResponder,
+                         security: DomainSecurity,
+                         task_id: Path<AppTaskIdPath>,
+                         req: HttpRequest)
+                         -> HttpResponse {
+    let task_id: AppTaskId = task_id.into_inner().into();
+
+    // the stream is only opened for clients that may listen to the task, as the events carry its state and audio
+    let require = messages::RequireTaskScope { task_id:  { task_id.clone() },
+                                               security: { security.clone() },
+                                               scope:    { SecureKeyScope::Listen }, };
+
+    if let Err(error) = get_tasks_supervisor().send(require)
+                                              .await
+                                              .map_err(rest_api::bad_gateway)
+                                              .and_then(identity)
+    {
+        return responder.respond(async move { Err::<(), _>(error) })
+                        .await
+                        .respond_to(&req)
+                        .map_into_boxed_body();
+    }
+
+    let resume_from = req.headers()
+                         .get(LAST_EVENT_ID)
+                         .and_then(|value| value.to_str().ok())
+                         .and_then(parse_last_event_id);
+
+    let replay = match resume_from {
+        None => vec![],
+        Some((play_id, serial)) => {
+            let list = messages::ListStreamPacketsAfter { task_id:  { task_id.clone() },
+                                                          play_id:  { play_id },
+                                                          serial:   { serial },
+                                                          security: { security }, };
+
+            match get_tasks_supervisor().send(list)
+                                        .await
+                                        .map_err(rest_api::bad_gateway)
+                                        .and_then(identity)
+            {
+                Ok(packets) => packets,
+                Err(error) => {
+                    debug!(%error, %task_id, "Could not replay packets for event stream, continuing with live events");
+                    vec![]
+                }
+            }
+        }
+    };
+
+    let (tx, rx) = mpsc::channel(EVENT_STREAM_BUFFER);
+    TaskEventStream::new(task_id, tx, replay).start();
+
+    let body = stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|frame| (Ok::<_, actix_web::Error>(frame), rx))
+    });
+
+    HttpResponse::Ok().content_type("text/event-stream")
+                      .insert_header((CACHE_CONTROL, "no-cache"))
+                      .streaming(body)
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body(content = Object,
+                           description = "Modifications of the task spec, applied in order"),
+              responses((status = 200,
+                         description = "Task spec modified, returns the new revision",
+                         body = Object)))]
 #[post("/{app_id}/{task_id}/modify")]
 async fn modify_task(responder: ApiResponder,
                      security: DomainSecurity,
@@ -84,21 +1172,31 @@ async fn modify_task(responder: ApiResponder,
                      -> ApiResponse<TaskUpdated> {
     let task_id = task_id.into_inner().into();
 
-    responder.respond(async move {
-                 let modify = messages::ModifyTask { task_id:     { task_id },
-                                                     modify_spec: { modify.into_inner().modify_spec },
-                                                     revision:    { get_revision(if_match)? },
-                                                     security:    { security },
-                                                     optional:    { false }, };
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "modify_task").with_task(&task_id)
+                                                                            .with_params(&modify.0);
 
-                 get_tasks_supervisor().send(modify)
-                                       .await
-                                       .map_err(rest_api::bad_gateway)
-                                       .and_then(identity)
-             })
+    responder.respond(audited(audit, async move {
+                          let modify = messages::ModifyTask { task_id:     { task_id },
+                                                              modify_spec: { modify.into_inner().modify_spec },
+                                                              revision:    { get_revision(if_match)? },
+                                                              security:    { security },
+                                                              optional:    { false }, };
+
+                          get_tasks_supervisor().send(modify)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
              .await
 }
 
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              responses((status = 200,
+                         description = "Task deleted",
+                         body = Object)))]
 #[delete("/{app_id}/{task_id}")]
 async fn delete_task(responder: ApiResponder,
                      security: DomainSecurity,
@@ -107,65 +1205,168 @@ async fn delete_task(responder: ApiResponder,
                      -> ApiResponse<TaskDeleted> {
     let task_id = task_id.into_inner().into();
 
-    responder.respond(async move {
-                 let delete = messages::DeleteTask { task_id:  { task_id },
-                                                     revision: { get_revision(if_match)? },
-                                                     security: { security }, };
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "delete_task").with_task(&task_id);
 
-                 get_tasks_supervisor().send(delete)
-                                       .await
-                                       .map_err(rest_api::bad_gateway)
-                                       .and_then(identity)
-             })
+    responder.respond(audited(audit, async move {
+                          let delete = messages::DeleteTask { task_id:  { task_id },
+                                                              revision: { get_revision(if_match)? },
+                                                              security: { security }, };
+
+                          get_tasks_supervisor().send(delete)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
              .await
 }
 
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body = TaskRenderRequest,
+              responses((status = 200,
+                         description = "Rendering started",
+                         body = Object)))]
 #[post("/{app_id}/{task_id}/transport/render")]
 async fn render_task(responder: ApiResponder,
                      task_id: Path<AppTaskIdPath>,
-                     render: Json<RequestRender>,
+                     render: Json<TaskRenderRequest>,
                      if_match: Header<IfMatch>,
                      security: DomainSecurity)
                      -> ApiResponse<TaskRendering> {
     let task_id = task_id.into_inner().into();
 
-    responder.respond(async move {
-                 let render = messages::RenderTask { task_id:  { task_id },
-                                                     render:   { render.into_inner() },
-                                                     security: { security },
-                                                     revision: { get_revision(if_match)? }, };
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "render_task").with_task(&task_id)
+                                                                            .with_params(&render.0);
 
-                 get_tasks_supervisor().send(render)
-                                       .await
-                                       .map_err(rest_api::bad_gateway)
-                                       .and_then(identity)
-             })
+    responder.respond(audited(audit, async move {
+                          let TaskRenderRequest { render,
+                                                  formats,
+                                                  normalization, } = render.into_inner();
+
+                          let render = messages::RenderTask { task_id:       { task_id },
+                                                              render:        { render },
+                                                              formats:       { formats },
+                                                              normalization: { normalization },
+                                                              security:      { security },
+                                                              revision:      { get_revision(if_match)? }, };
+
+                          get_tasks_supervisor().send(render)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body = TaskNullTestRequest,
+              responses((status = 200,
+                         description = "Rendering of the first variant started",
+                         body = Object)))]
+#[post("/{app_id}/{task_id}/transport/null_test")]
+async fn run_task_null_test(responder: ApiResponder,
+                            task_id: Path<AppTaskIdPath>,
+                            request: Json<TaskNullTestRequest>,
+                            security: DomainSecurity)
+                            -> ApiResponse<TaskRendering> {
+    let task_id = task_id.into_inner().into();
+
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "run_task_null_test").with_task(&task_id)
+                                                                                   .with_params(&request.0);
+
+    let run = messages::RunTaskNullTest { task_id:  { task_id },
+                                          request:  { request.into_inner() },
+                                          security: { security }, };
+
+    responder.respond(audited(audit, async move {
+                          get_tasks_supervisor().send(run)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              responses((status = 200, description = "Recall sheet stored", body = TaskRecallSheet),
+                        (status = 404, description = "Task not found")))]
+#[post("/{app_id}/{task_id}/recall_sheet")]
+async fn generate_task_recall_sheet(responder: ApiResponder,
+                                    task_id: Path<AppTaskIdPath>,
+                                    security: DomainSecurity)
+                                    -> ApiResponse<TaskRecallSheet> {
+    let task_id = task_id.into_inner().into();
+
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "generate_task_recall_sheet").with_task(&task_id);
+
+    let generate = messages::GenerateTaskRecallSheet { task_id:  { task_id },
+                                                       security: { security }, };
+
+    responder.respond(audited(audit, async move {
+                          get_tasks_supervisor().send(generate)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
              .await
 }
 
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body = TaskPlayRequest,
+              responses((status = 200,
+                         description = "Playing started",
+                         body = Object)))]
 #[post("/{app_id}/{task_id}/transport/play")]
 async fn play_task(responder: ApiResponder,
                    task_id: Path<AppTaskIdPath>,
-                   play: Json<RequestPlay>,
+                   play: Json<TaskPlayRequest>,
                    if_match: Header<IfMatch>,
                    security: DomainSecurity)
                    -> ApiResponse<TaskPlaying> {
     let task_id = task_id.into_inner().into();
 
-    responder.respond(async move {
-                 let render = messages::PlayTask { task_id:  { task_id },
-                                                   play:     { play.into_inner() },
-                                                   security: { security },
-                                                   revision: { get_revision(if_match)? }, };
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "play_task").with_task(&task_id)
+                                                                          .with_params(&play.0);
 
-                 get_tasks_supervisor().send(render)
-                                       .await
-                                       .map_err(rest_api::bad_gateway)
-                                       .and_then(identity)
-             })
+    responder.respond(audited(audit, async move {
+                          let TaskPlayRequest { play, click, client_id } = play.into_inner();
+
+                          let play = messages::PlayTask { task_id:   { task_id },
+                                                          play:      { play },
+                                                          click:     { click },
+                                                          client_id: { client_id },
+                                                          watermark: { None },
+                                                          security:  { security },
+                                                          revision:  { get_revision(if_match)? }, };
+
+                          get_tasks_supervisor().send(play)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
              .await
 }
 
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body(content = Object,
+                           description = "Play ID and the segment to continue playing from"),
+              responses((status = 200,
+                         description = "Play continues from the new position",
+                         body = Object)))]
 #[post("/{app_id}/{task_id}/transport/seek")]
 async fn seek_task(responder: ApiResponder,
                    task_id: Path<AppTaskIdPath>,
@@ -173,20 +1374,33 @@ async fn seek_task(responder: ApiResponder,
                    if_match: Header<IfMatch>,
                    security: DomainSecurity)
                    -> ApiResponse<TaskSought> {
-    responder.respond(async move {
-                 let seek = messages::SeekTask { task_id:  { task_id.into_inner().into() },
-                                                 seek:     { seek.into_inner() },
-                                                 security: { security },
-                                                 revision: { get_revision(if_match)? }, };
+    let task_id = task_id.into_inner().into();
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "seek_task").with_task(&task_id)
+                                                                          .with_params(&seek.0);
 
-                 get_tasks_supervisor().send(seek)
-                                       .await
-                                       .map_err(rest_api::bad_gateway)
-                                       .and_then(identity)
-             })
+    responder.respond(audited(audit, async move {
+                          let seek = messages::SeekTask { task_id:  { task_id },
+                                                          seek:     { seek.into_inner() },
+                                                          security: { security },
+                                                          revision: { get_revision(if_match)? }, };
+
+                          get_tasks_supervisor().send(seek)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
              .await
 }
 
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body(content = Object,
+                           description = "Render ID of the render to cancel"),
+              responses((status = 200,
+                         description = "Rendering cancelled",
+                         body = Object)))]
 #[post("/{app_id}/{task_id}/transport/cancel")]
 async fn cancel_render_task(responder: ApiResponder,
                             task_id: Path<AppTaskIdPath>,
@@ -194,20 +1408,33 @@ async fn cancel_render_task(responder: ApiResponder,
                             if_match: Header<IfMatch>,
                             security: DomainSecurity)
                             -> ApiResponse<TaskRenderCancelled> {
-    responder.respond(async move {
-                 let cancel = messages::CancelRenderTask { task_id:  { task_id.into_inner().into() },
-                                                           cancel:   { cancel.into_inner() },
-                                                           security: { security },
-                                                           revision: { get_revision(if_match)? }, };
+    let task_id = task_id.into_inner().into();
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "cancel_render_task").with_task(&task_id)
+                                                                                   .with_params(&cancel.0);
 
-                 get_tasks_supervisor().send(cancel)
-                                       .await
-                                       .map_err(rest_api::bad_gateway)
-                                       .and_then(identity)
-             })
+    responder.respond(audited(audit, async move {
+                          let cancel = messages::CancelRenderTask { task_id:  { task_id },
+                                                                    cancel:   { cancel.into_inner() },
+                                                                    security: { security },
+                                                                    revision: { get_revision(if_match)? }, };
+
+                          get_tasks_supervisor().send(cancel)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
              .await
 }
 
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body(content = Object,
+                           description = "Play ID of the play to stop"),
+              responses((status = 200,
+                         description = "Playing stopped",
+                         body = Object)))]
 #[post("/{app_id}/{task_id}/transport/stop")]
 async fn stop_play_task(responder: ApiResponder,
                         task_id: Path<AppTaskIdPath>,
@@ -215,17 +1442,87 @@ async fn stop_play_task(responder: ApiResponder,
                         if_match: Header<IfMatch>,
                         security: DomainSecurity)
                         -> ApiResponse<TaskPlayStopped> {
-    responder.respond(async move {
-                 let stop = messages::StopPlayTask { task_id:  { task_id.into_inner().into() },
-                                                     stop:     { stop.into_inner() },
-                                                     security: { security },
-                                                     revision: { get_revision(if_match)? }, };
+    let task_id = task_id.into_inner().into();
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "stop_play_task").with_task(&task_id)
+                                                                               .with_params(&stop.0);
 
-                 get_tasks_supervisor().send(stop)
-                                       .await
-                                       .map_err(rest_api::bad_gateway)
-                                       .and_then(identity)
-             })
+    responder.respond(audited(audit, async move {
+                          let stop = messages::StopPlayTask { task_id:  { task_id },
+                                                              stop:     { stop.into_inner() },
+                                                              security: { security },
+                                                              revision: { get_revision(if_match)? }, };
+
+                          get_tasks_supervisor().send(stop)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body = RequestPausePlay,
+              responses((status = 200,
+                         description = "Playing paused, the project and stream stay ready to resume",
+                         body = TaskPlayPause)))]
+#[post("/{app_id}/{task_id}/transport/pause")]
+async fn pause_play_task(responder: ApiResponder,
+                         task_id: Path<AppTaskIdPath>,
+                         pause: Json<RequestPausePlay>,
+                         if_match: Header<IfMatch>,
+                         security: DomainSecurity)
+                         -> ApiResponse<TaskPlayPause> {
+    let task_id = task_id.into_inner().into();
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "pause_play_task").with_task(&task_id)
+                                                                                .with_params(&pause.0);
+
+    responder.respond(audited(audit, async move {
+                          let pause = messages::PausePlayTask { task_id:  { task_id },
+                                                                pause:    { pause.into_inner() },
+                                                                security: { security },
+                                                                revision: { get_revision(if_match)? }, };
+
+                          get_tasks_supervisor().send(pause)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
+             .await
+}
+
+#[utoipa::path(context_path = "/v1/tasks",
+              tag = "tasks",
+              params(("app_id" = String, Path, description = "App ID"),
+                     ("task_id" = String, Path, description = "Task ID")),
+              request_body = RequestPausePlay,
+              responses((status = 200,
+                         description = "Playing resumed from where it paused",
+                         body = TaskPlayPause)))]
+#[post("/{app_id}/{task_id}/transport/resume")]
+async fn resume_play_task(responder: ApiResponder,
+                          task_id: Path<AppTaskIdPath>,
+                          resume: Json<RequestPausePlay>,
+                          if_match: Header<IfMatch>,
+                          security: DomainSecurity)
+                          -> ApiResponse<TaskPlayPause> {
+    let task_id = task_id.into_inner().into();
+    let audit = AuditEntry::new(AuditOrigin::Rest, &security, "resume_play_task").with_task(&task_id)
+                                                                                 .with_params(&resume.0);
+
+    responder.respond(audited(audit, async move {
+                          let resume = messages::ResumePlayTask { task_id:  { task_id },
+                                                                  resume:   { resume.into_inner() },
+                                                                  security: { security },
+                                                                  revision: { get_revision(if_match)? }, };
+
+                          get_tasks_supervisor().send(resume)
+                                                .await
+                                                .map_err(rest_api::bad_gateway)
+                                                .and_then(identity)
+                      }))
              .await
 }
 
@@ -0,0 +1,260 @@
+use actix_web::middleware::Logger;
+use actix_web::{web, App, HttpServer};
+use clap::Parser;
+use tokio::signal::ctrl_c;
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::*;
+
+use crate::extensions::DomainExtension;
+use crate::{
+    analytics, audit, automation, bandwidth, config, db, events, extensions, fixed_instances, incidents, journal,
+    media, models, nats, nats_api, o11y, osc, rest_api, sockets, support, tasks, telemetry,
+};
+
+/// Command line and environment options of the domain server
+#[derive(Parser)]
+pub struct ServerOpts {
+    /// REST and WebSocket API port
+    #[clap(short, long, env, default_value = "7200")]
+    port: u16,
+
+    /// REST and WebSocket API host
+    #[clap(short, long, env, default_value = "0.0.0.0")]
+    bind: String,
+
+    #[clap(flatten)]
+    nats: nats::NatsOpts,
+
+    #[clap(flatten)]
+    nats_api: nats_api::NatsApiOpts,
+
+    #[clap(flatten)]
+    osc: osc::OscOpts,
+
+    #[clap(flatten)]
+    db: db::DataOpts,
+
+    #[clap(flatten)]
+    media: media::MediaOpts,
+
+    #[clap(flatten)]
+    config: config::ConfigOpts,
+
+    #[clap(flatten)]
+    fixed_instances: fixed_instances::FixedInstanceOpts,
+
+    #[clap(flatten)]
+    sockets: sockets::SocketsOpts,
+
+    #[clap(flatten)]
+    tasks: tasks::TaskOpts,
+
+    #[clap(flatten)]
+    events: events::CloudEventOpts,
+
+    #[clap(flatten)]
+    rest: rest_api::RestOpts,
+
+    #[clap(flatten)]
+    incidents: incidents::IncidentOpts,
+
+    #[clap(flatten)]
+    audit: audit::AuditOpts,
+
+    #[clap(flatten)]
+    journal: journal::JournalOpts,
+
+    #[clap(flatten)]
+    automation: automation::AutomationOpts,
+
+    #[clap(flatten)]
+    telemetry: telemetry::TelemetryOpts,
+
+    #[clap(flatten)]
+    analytics: analytics::AnalyticsOpts,
+
+    #[clap(flatten)]
+    bandwidth: bandwidth::BandwidthOpts,
+
+    #[clap(flatten)]
+    support: support::SupportOpts,
+
+    #[clap(flatten)]
+    o11y: o11y::O11yOpts,
+}
+
+/// Start every subsystem of the domain server and serve the REST and socket APIs until shut down
+///
+/// Binaries of studios with their own extensions call this with them, the stock binary with none.
+pub async fn run(mut opts: ServerOpts, extensions: Vec<Box<dyn DomainExtension>>) -> anyhow::Result<()> {
+    // the domain server is basically a bunch of timers and event handlers running on top of an sqlite database.
+
+    info!(source = %opts.config.describe(), "Loading config");
+
+    let config_push_subject = opts.config.config_push_subject.clone();
+    let (cfg, fixed_instance_extras) = config::init(opts.config).await?;
+
+    if opts.o11y.domain_id.is_empty() {
+        opts.o11y.domain_id = cfg.domain_id.clone();
+    }
+
+    info!(" ⚡ Tracing");
+
+    let _tracing_guard = o11y::init_tracing(&opts.o11y)?;
+
+    info!(" ⚡ Metrics");
+
+    let _metrics_guard = o11y::init_metrics(&opts.o11y)?;
+
+    info!(" ⚡ Database");
+
+    let db = db::init(opts.db).await?;
+
+    info!(" ⚡ Incidents");
+
+    incidents::init(db.clone(), opts.incidents)?;
+
+    info!(" ⚡ Audit");
+
+    audit::init(db.clone(), opts.audit)?;
+
+    info!(" ⚡ Journal");
+
+    journal::init(db.clone(), opts.journal)?;
+
+    info!(" ⚡ Telemetry");
+
+    telemetry::init(opts.telemetry)?;
+
+    info!(" ⚡ NATS");
+
+    let _nats_guard = nats::init(&opts.nats).await?;
+
+    config::subscribe_config_pushes(config_push_subject)?;
+
+    info!(" ⚡ Models");
+
+    models::init(&cfg, db.clone()).await?;
+
+    info!(" ⚡ Media");
+
+    let media_root = opts.media.media_root.clone();
+    media::init(opts.media, db.clone()).await?;
+
+    info!(" ⚡ Instances");
+
+    let model_sharing = fixed_instance_extras.model_sharing.clone();
+    let routing = fixed_instances::init(&cfg, fixed_instance_extras, db.clone(), &opts.fixed_instances).await?;
+
+    info!(" ⚡ Tasks (Offline)");
+
+    tasks::init(db.clone(), &opts.tasks, &cfg, routing, model_sharing, media_root)?;
+
+    info!(" ⚡ Automation");
+
+    automation::init(db.clone(), opts.automation)?;
+
+    info!(" ⚡ Analytics");
+
+    analytics::init(db.clone(), opts.analytics)?;
+
+    info!(" ⚡ Bandwidth");
+
+    bandwidth::init(db.clone(), opts.bandwidth)?;
+
+    info!(" ⚡ Support bundles");
+
+    support::init(db.clone(), opts.support)?;
+
+    info!(" ⚡ Extensions");
+
+    extensions::init(extensions)?;
+
+    info!(" ⚡ Cloud Events");
+
+    events::init(cfg.command_source.clone(), cfg.event_sink.clone(), opts.events).await?;
+
+    info!(" ⚡ Warm-up");
+
+    let warmed_up = tasks::warm_up().await?;
+    info!(models = models::cached_model_count(),
+          tasks = warmed_up.tasks,
+          active_tasks = warmed_up.active_tasks,
+          engines = warmed_up.engines,
+          routed_instances = warmed_up.routed_instances,
+          "Caches warmed up");
+
+    info!(" ⚡ Tasks (Online)");
+
+    tasks::become_online().await?;
+
+    info!(" ⚡ NATS API");
+
+    nats_api::init(opts.nats_api).await?;
+
+    info!(" ⚡ OSC");
+
+    osc::init(opts.osc).await?;
+
+    info!(" ⚡ Sockets");
+
+    sockets::init(opts.sockets).await?;
+
+    info!(bind = opts.bind,
+          port = opts.port,
+          " ==== AudioCloud Domain server ==== ");
+
+    rest_api::jwt::init(&opts.rest.jwt).await?;
+
+    let swagger_ui = opts.rest.rest_swagger_ui;
+    let rest_opts = web::Data::new(opts.rest.clone());
+    if rest_opts.rest_auth_strategy.is_development() {
+        warn!("*** development authentication strategy enabled! ***");
+    }
+
+    // create actix, signals are handled below so that sockets drain before the server stops
+    let server = HttpServer::new(move || {
+                     App::new().wrap_fn(rest_api::rate_limit::limit_requests)
+                               .wrap(Logger::default())
+                               .app_data(rest_opts.clone())
+                               .configure(rest_api::configure)
+                               .configure(|cfg| {
+                                   if swagger_ui {
+                                       rest_api::openapi::configure_swagger_ui(cfg);
+                                   }
+                               })
+                               .configure(sockets::configure)
+                 }).disable_signals()
+                   .bind((opts.bind.as_str(), opts.port))?
+                   .run();
+
+    let server_handle = server.handle();
+    actix_web::rt::spawn(async move {
+        shutdown_signal().await;
+
+        info!("Shutting down");
+
+        sockets::drain_on_shutdown().await;
+        server_handle.stop(true).await;
+    });
+
+    server.await?;
+
+    Ok(())
+}
+
+async fn shutdown_signal() {
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(error) => {
+            warn!(%error, "Failed to listen for SIGTERM, shutting down on Ctrl-C only");
+            let _ = ctrl_c().await;
+            return;
+        }
+    };
+
+    tokio::select! {
+        _ = ctrl_c() => {},
+        _ = terminate.recv() => {},
+    }
+}
@@ -0,0 +1,102 @@
+use std::time::{Duration, Instant};
+
+use clap::Args;
+
+use audiocloud_api::PlayId;
+
+/// Number of most recent pings the loss driving the bitrate is computed over, short so the bitrate follows the link
+pub const BITRATE_LOSS_WINDOW: usize = 8;
+
+/// Share of the bitrate kept when the link degrades
+const DECREASE_FACTOR: f64 = 0.7;
+
+/// Bits per second added when the link stayed clear long enough
+const INCREASE_STEP: u32 = 16_000;
+
+#[derive(Args, Clone, Debug)]
+pub struct BitrateOpts {
+    /// Keep streaming Opus at the bitrate of the codec, whatever the link quality of the clients
+    #[clap(long, env)]
+    socket_abr_disabled: bool,
+
+    /// Lowest bitrate in bits per second the domain asks engines to stream Opus at for clients with degraded links
+    #[clap(long, env, default_value = "24000")]
+    socket_abr_min_bitrate: u32,
+
+    /// Share of recent pings lost, between 0 and 1, above which the link of a client counts as degraded
+    #[clap(long, env, default_value = "0.05")]
+    socket_abr_loss_threshold: f64,
+
+    /// Smoothed round trip time in milliseconds above which the link of a client counts as degraded
+    #[clap(long, env, default_value = "400")]
+    socket_abr_rtt_threshold_ms: f64,
+
+    /// Number of seconds a link has to stay clear before the bitrate is raised again
+    #[clap(long, env, default_value = "15")]
+    socket_abr_increase_after: u64,
+}
+
+impl BitrateOpts {
+    pub fn is_enabled(&self) -> bool {
+        !self.socket_abr_disabled
+    }
+}
+
+/// Loss and round trip time of the socket a client receives its streams over
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LinkQuality {
+    pub loss:   f64,
+    pub rtt_ms: Option<f64>,
+}
+
+impl LinkQuality {
+    pub fn is_degraded(&self, opts: &BitrateOpts) -> bool {
+        self.loss > opts.socket_abr_loss_threshold
+        || self.rtt_ms
+               .map(|rtt_ms| rtt_ms > opts.socket_abr_rtt_threshold_ms)
+               .unwrap_or(false)
+    }
+}
+
+/// Bitrate a client can take of the Opus stream of a play
+///
+/// The bitrate drops by a factor as soon as the link degrades and climbs back in steps while it stays clear, never
+/// above the bitrate the codec of the task was set up with.
+#[derive(Clone, Debug)]
+pub struct BitrateController {
+    play_id:     PlayId,
+    ceiling:     u32,
+    bitrate:     u32,
+    clear_since: Instant,
+}
+
+impl BitrateController {
+    pub fn new(play_id: PlayId, ceiling: u32, now: Instant) -> Self {
+        Self { play_id:     { play_id },
+               ceiling:     { ceiling },
+               bitrate:     { ceiling },
+               clear_since: { now }, }
+    }
+
+    pub fn play_id(&self) -> PlayId {
+        self.play_id
+    }
+
+    pub fn ceiling(&self) -> u32 {
+        self.ceiling
+    }
+
+    pub fn update(&mut self, link: LinkQuality, opts: &BitrateOpts, now: Instant) -> u32 {
+        if link.is_degraded(opts) {
+            let lowered = (self.bitrate as f64 * DECREASE_FACTOR) as u32;
+            self.bitrate = lowered.max(opts.socket_abr_min_bitrate).min(self.ceiling);
+            self.clear_since = now;
+        } else if now.saturating_duration_since(self.clear_since) >= Duration::from_secs(opts.socket_abr_increase_after)
+        {
+            self.bitrate = self.bitrate.saturating_add(INCREASE_STEP).min(self.ceiling);
+            self.clear_since = now;
+        }
+
+        self.bitrate
+    }
+}
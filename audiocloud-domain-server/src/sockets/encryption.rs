@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use bytes::{Bytes, BytesMut};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use tracing::*;
+
+use audiocloud_api::{AppTaskId, SecureKey, StreamingPacket};
+
+const KEY_INFO: &[u8] = b"audiocloud streaming packet v1";
+
+const NONCE_LEN: usize = 12;
+
+/// Ciphers of streams no packet was encrypted for in this long are forgotten
+const CIPHER_IDLE: Duration = Duration::from_secs(60);
+
+/// Symmetric cipher for audio buffers in streaming packets, keyed per task and secure key
+///
+/// The key is derived with HKDF-SHA256 using the secure key as input key material and the task ID as salt, so
+/// clients holding the secure key can derive it without any additional exchange. Every encrypted buffer is the
+/// 12 byte nonce followed by the ChaCha20-Poly1305 ciphertext and tag.
+pub struct PacketCipher(ChaCha20Poly1305);
+
+impl PacketCipher {
+    pub fn derive(task_id: &AppTaskId, secure_key: &SecureKey) -> Self {
+        let hkdf = Hkdf::<Sha256>::new(Some(task_id.to_string().as_bytes()), secure_key.to_string().as_bytes());
+        let mut key = Key::default();
+        hkdf.expand(KEY_INFO, &mut key)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+
+        Self(ChaCha20Poly1305::new(&key))
+    }
+
+    pub fn encrypt(&self, buffer: &[u8]) -> anyhow::Result<Bytes> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self.0
+                             .encrypt(&nonce, buffer)
+                             .map_err(|err| anyhow!("Failed to encrypt buffer: {err}"))?;
+
+        let mut rv = BytesMut::with_capacity(nonce.len() + ciphertext.len());
+        rv.extend_from_slice(&nonce);
+        rv.extend_from_slice(&ciphertext);
+
+        Ok(rv.freeze())
+    }
+
+    /// Decrypt a buffer encrypted with the same key, failing if it was encrypted for another task or key or altered
+    pub fn decrypt(&self, buffer: &[u8]) -> anyhow::Result<Bytes> {
+        if buffer.len() < NONCE_LEN {
+            return Err(anyhow!("Encrypted buffer of {} bytes is shorter than its nonce", buffer.len()));
+        }
+
+        let (nonce, ciphertext) = buffer.split_at(NONCE_LEN);
+        let plaintext = self.0
+                            .decrypt(Nonce::from_slice(nonce), ciphertext)
+                            .map_err(|err| anyhow!("Failed to decrypt buffer: {err}"))?;
+
+        Ok(Bytes::from(plaintext))
+    }
+
+    pub fn encrypt_packet(&self, packet: &StreamingPacket) -> anyhow::Result<StreamingPacket> {
+        let mut packet = packet.clone();
+        for frame in &mut packet.audio {
+            let audio = frame.value_mut();
+            audio.buffer = self.encrypt(&audio.buffer)?;
+        }
+
+        Ok(packet)
+    }
+}
+
+/// Ciphers of the streams packets are encrypted for, derived once per task and secure key instead of per packet
+pub struct PacketCiphers {
+    ciphers:     HashMap<(AppTaskId, SecureKey), (PacketCipher, Instant)>,
+    last_purged: Instant,
+}
+
+impl Default for PacketCiphers {
+    fn default() -> Self {
+        Self { ciphers:     { HashMap::new() },
+               last_purged: { Instant::now() }, }
+    }
+}
+
+impl PacketCiphers {
+    /// Cipher of the stream of the task to a client attached with the secure key, derived on its first packet
+    pub fn get(&mut self, task_id: &AppTaskId, secure_key: &SecureKey) -> &PacketCipher {
+        self.get_at(task_id, secure_key, Instant::now())
+    }
+
+    /// Same as `get`, at a given time
+    pub fn get_at(&mut self, task_id: &AppTaskId, secure_key: &SecureKey, now: Instant) -> &PacketCipher {
+        if now.saturating_duration_since(self.last_purged) > CIPHER_IDLE {
+            self.ciphers
+                .retain(|_, (_, used_at)| now.saturating_duration_since(*used_at) <= CIPHER_IDLE);
+            self.last_purged = now;
+
+            trace!(streams = self.stream_count(), "Forgot packet ciphers of idle streams");
+        }
+
+        let (cipher, used_at) = self.ciphers
+                                    .entry((task_id.clone(), secure_key.clone()))
+                                    .or_insert_with(|| (PacketCipher::derive(task_id, secure_key), now));
+        *used_at = now;
+
+        cipher
+    }
+
+    /// Number of streams with a cipher, idle ones included until they are purged
+    pub fn stream_count(&self) -> usize {
+        self.ciphers.len()
+    }
+}
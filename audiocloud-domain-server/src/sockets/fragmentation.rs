@@ -0,0 +1,169 @@
+use std::collections::{HashMap, VecDeque};
+
+use anyhow::anyhow;
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// First byte of every fragment. MsgPack never starts a message with it, so receivers tell fragments from whole
+/// messages without any framing of their own
+pub const FRAGMENT_MARKER: u8 = 0xc1;
+
+/// Marker, sequence number of the message (u32), index of the fragment and number of fragments (both u16), little
+/// endian
+pub const FRAGMENT_HEADER_LEN: usize = 1 + 4 + 2 + 2;
+
+/// Fragments are never negotiated smaller than this, they would be mostly header
+pub const MIN_FRAGMENT_LEN: usize = 256;
+
+/// Messages still missing fragments, past this many the oldest one is given up on
+const MAX_PARTIAL_MESSAGES: usize = 16;
+
+/// Largest message a client may send in fragments, the same as a frame on the reliable WebTransport stream
+const MAX_REASSEMBLED_LEN: usize = 16 * 1024 * 1024;
+
+pub fn is_fragment(bytes: &[u8]) -> bool {
+    bytes.first() == Some(&FRAGMENT_MARKER)
+}
+
+/// Splits outgoing messages that do not fit in one message of the transport
+#[derive(Debug)]
+pub struct Fragmenter {
+    max_len:  usize,
+    sequence: u32,
+}
+
+impl Fragmenter {
+    pub fn new(max_len: usize) -> Self {
+        Self { max_len:  { max_len.max(MIN_FRAGMENT_LEN) },
+               sequence: { 0 }, }
+    }
+
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    /// Fragments of at most the negotiated length, header included. Messages that fit go out as they are
+    pub fn split(&mut self, message: Bytes) -> Vec<Bytes> {
+        self.split_within(message, self.max_len)
+    }
+
+    /// Like [`Fragmenter::split`], with fragments no longer than `limit` either
+    pub fn split_within(&mut self, message: Bytes, limit: usize) -> Vec<Bytes> {
+        let max_len = self.max_len.min(limit).max(MIN_FRAGMENT_LEN);
+        if message.len() <= max_len {
+            return vec![message];
+        }
+
+        let chunk_len = max_len - FRAGMENT_HEADER_LEN;
+        let count = (message.len() + chunk_len - 1) / chunk_len;
+        if count > u16::MAX as usize {
+            // no client could take it in fragments either, the transport may still manage
+            return vec![message];
+        }
+
+        let sequence = self.sequence;
+        self.sequence = self.sequence.wrapping_add(1);
+
+        message.chunks(chunk_len)
+               .enumerate()
+               .map(|(index, chunk)| {
+                   let mut fragment = BytesMut::with_capacity(FRAGMENT_HEADER_LEN + chunk.len());
+                   fragment.put_u8(FRAGMENT_MARKER);
+                   fragment.put_u32_le(sequence);
+                   fragment.put_u16_le(index as u16);
+                   fragment.put_u16_le(count as u16);
+                   fragment.put_slice(chunk);
+                   fragment.freeze()
+               })
+               .collect()
+    }
+}
+
+#[derive(Debug)]
+struct PartialMessage {
+    fragments: Vec<Option<Bytes>>,
+    received:  usize,
+    len:       usize,
+}
+
+/// Puts incoming fragments back together, fragments of a message may arrive in any order
+///
+/// Transports that drop messages drop fragments too, a message missing one is given up on once enough newer messages
+/// are pending.
+#[derive(Debug, Default)]
+pub struct Reassembler {
+    partial: HashMap<u32, PartialMessage>,
+    order:   VecDeque<u32>,
+}
+
+impl Reassembler {
+    /// The whole message once its last missing fragment arrived, messages that are not fragments are passed through
+    pub fn push(&mut self, bytes: Bytes) -> anyhow::Result<Option<Bytes>> {
+        if !is_fragment(&bytes) {
+            return Ok(Some(bytes));
+        }
+
+        if bytes.len() < FRAGMENT_HEADER_LEN {
+            return Err(anyhow!("Fragment of {} bytes is shorter than its header", bytes.len()));
+        }
+
+        let sequence = u32::from_le_bytes(bytes[1..5].try_into()?);
+        let index = u16::from_le_bytes(bytes[5..7].try_into()?) as usize;
+        let count = u16::from_le_bytes(bytes[7..9].try_into()?) as usize;
+
+        if index >= count {
+            return Err(anyhow!("Fragment {index} of message {sequence} is past its {count} fragments"));
+        }
+
+        if !self.partial.contains_key(&sequence) {
+            if self.order.len() == MAX_PARTIAL_MESSAGES {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.partial.remove(&oldest);
+                }
+            }
+
+            self.order.push_back(sequence);
+            self.partial.insert(sequence,
+                                PartialMessage { fragments: { vec![None; count] },
+                                                 received:  { 0 },
+                                                 len:       { 0 }, });
+        }
+
+        let partial = self.partial
+                          .get_mut(&sequence)
+                          .ok_or_else(|| anyhow!("Message {sequence} is not pending"))?;
+
+        if partial.fragments.len() != count {
+            return Err(anyhow!("Fragments of message {sequence} disagree on their count"));
+        }
+
+        let chunk = bytes.slice(FRAGMENT_HEADER_LEN..);
+        if partial.fragments[index].is_none() {
+            partial.len += chunk.len();
+            partial.received += 1;
+            partial.fragments[index] = Some(chunk);
+        }
+
+        if partial.len > MAX_REASSEMBLED_LEN {
+            self.forget(sequence);
+            return Err(anyhow!("Message {sequence} is larger than {MAX_REASSEMBLED_LEN} bytes"));
+        }
+
+        if partial.received < count {
+            return Ok(None);
+        }
+
+        let mut message = BytesMut::with_capacity(partial.len);
+        for chunk in partial.fragments.iter().flatten() {
+            message.put_slice(chunk);
+        }
+
+        self.forget(sequence);
+
+        Ok(Some(message.freeze()))
+    }
+
+    fn forget(&mut self, sequence: u32) {
+        self.partial.remove(&sequence);
+        self.order.retain(|pending| *pending != sequence);
+    }
+}
@@ -0,0 +1,231 @@
+use std::sync::RwLock;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use clap::Args;
+use hmac::{Hmac, Mac};
+use once_cell::sync::OnceCell;
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use tracing::*;
+
+use audiocloud_api::{now, ClientId, Timestamp};
+
+use crate::config::cloud_endpoints;
+
+static ICE_SERVERS: OnceCell<IceServers> = OnceCell::new();
+
+#[derive(Args, Clone, Debug)]
+pub struct IceOpts {
+    /// TURN servers to relay WebRTC through for clients behind symmetric NAT, i.e. `turn:turn.example.com:3478`
+    #[clap(long, env)]
+    turn_servers: Vec<String>,
+
+    /// Secret shared with the TURN servers (coturn `static-auth-secret`), to give each client short-lived credentials
+    #[clap(long, env)]
+    turn_secret: Option<String>,
+
+    /// Username for TURN servers without a shared secret
+    #[clap(long, env)]
+    turn_username: Option<String>,
+
+    /// Password for TURN servers without a shared secret
+    #[clap(long, env)]
+    turn_password: Option<String>,
+
+    /// Number of seconds credentials generated from the shared secret are valid for
+    #[clap(long, env, default_value = "3600")]
+    turn_credential_ttl_seconds: u64,
+
+    /// Fetch the STUN and TURN servers from this path relative to the cloud URL, instead of configuring them here
+    #[clap(long, env)]
+    ice_servers_path: Option<String>,
+
+    /// Number of seconds between fetching the STUN and TURN servers from the cloud
+    #[clap(long, env, default_value = "300")]
+    ice_servers_refresh_seconds: u64,
+}
+
+/// A STUN or TURN server, in the shape of `RTCIceServer` so clients can pass it to their peer connection as is
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct IceServer {
+    pub urls:       Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username:   Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential: Option<String>,
+}
+
+impl IceServer {
+    fn is_turn(&self) -> bool {
+        self.urls
+            .iter()
+            .any(|url| url.starts_with("turn:") || url.starts_with("turns:"))
+    }
+
+    /// URLs as libdatachannel takes them, with the credentials embedded as `turn:username:credential@host:port`
+    pub fn rtc_urls(&self) -> Vec<String> {
+        match (&self.username, &self.credential) {
+            (Some(username), Some(credential)) => {
+                let auth = format!("{}:{}@", percent_encode(username), percent_encode(credential));
+                self.urls
+                    .iter()
+                    .map(|url| match url.split_once(':') {
+                        Some((scheme, rest)) if scheme.starts_with("turn") => format!("{scheme}:{auth}{rest}"),
+                        _ => url.clone(),
+                    })
+                    .collect()
+            }
+            _ => self.urls.clone(),
+        }
+    }
+}
+
+/// STUN and TURN servers, and how to authenticate clients with the TURN servers
+pub struct IceServers {
+    servers:  RwLock<Vec<IceServer>>,
+    secret:   Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    ttl:      Duration,
+}
+
+impl IceServers {
+    pub fn new(stun_servers: &[String], opts: &IceOpts) -> Self {
+        let mut servers = vec![];
+        if !stun_servers.is_empty() {
+            servers.push(IceServer { urls:       { stun_servers.to_vec() },
+                                     username:   { None },
+                                     credential: { None }, });
+        }
+        if !opts.turn_servers.is_empty() {
+            servers.push(IceServer { urls:       { opts.turn_servers.clone() },
+                                     username:   { None },
+                                     credential: { None }, });
+        }
+
+        Self { servers:  { RwLock::new(servers) },
+               secret:   { opts.turn_secret.clone() },
+               username: { opts.turn_username.clone() },
+               password: { opts.turn_password.clone() },
+               ttl:      { Duration::from_secs(opts.turn_credential_ttl_seconds) }, }
+    }
+
+    /// Servers for a client to connect to, with TURN credentials valid for this client only if there is a shared secret
+    pub fn for_client(&self, client_id: &ClientId, at: Timestamp) -> Vec<IceServer> {
+        let servers = match self.servers.read() {
+            Ok(servers) => servers.clone(),
+            Err(_) => {
+                warn!("ICE servers lock poisoned");
+                return vec![];
+            }
+        };
+
+        servers.into_iter()
+               .map(|mut server| {
+                   if server.is_turn() && server.username.is_none() {
+                       let (username, credential) = match &self.secret {
+                           Some(secret) => {
+                               let (username, credential) = turn_credentials(secret, client_id, at, self.ttl);
+                               (Some(username), Some(credential))
+                           }
+                           None => (self.username.clone(), self.password.clone()),
+                       };
+
+                       server.username = username;
+                       server.credential = credential;
+                   }
+
+                   server
+               })
+               .collect()
+    }
+
+    fn replace(&self, servers: Vec<IceServer>) {
+        match self.servers.write() {
+            Ok(mut current) => *current = servers,
+            Err(_) => warn!("ICE servers lock poisoned, keeping previous servers"),
+        }
+    }
+}
+
+/// Credentials of the TURN REST API: the username is the expiry as a UNIX timestamp and the client ID, the
+/// credential the base64 encoded HMAC-SHA1 of the username keyed with the shared secret
+pub fn turn_credentials(secret: &str, client_id: &ClientId, at: Timestamp, ttl: Duration) -> (String, String) {
+    let expires = at.timestamp() + ttl.as_secs() as i64;
+    let username = format!("{expires}:{client_id}");
+
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(username.as_bytes());
+    let credential = base64::encode(mac.finalize().into_bytes());
+
+    (username, credential)
+}
+
+fn percent_encode(value: &str) -> String {
+    value.bytes()
+         .map(|byte| match byte {
+             b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+             _ => format!("%{byte:02X}"),
+         })
+         .collect()
+}
+
+#[instrument(skip_all, err)]
+async fn fetch_ice_servers(path: &str) -> anyhow::Result<Vec<IceServer>> {
+    cloud_endpoints().request(path, fetch_ice_servers_from).await
+}
+
+async fn fetch_ice_servers_from(url: Url) -> anyhow::Result<Vec<IceServer>> {
+    Ok(Client::new().get(url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<Vec<IceServer>>()
+                    .await?)
+}
+
+#[instrument(skip_all, err)]
+pub async fn init(stun_servers: &[String], opts: &IceOpts) -> anyhow::Result<()> {
+    ICE_SERVERS.set(IceServers::new(stun_servers, opts))
+               .map_err(|_| anyhow!("ICE servers already initialized"))?;
+
+    let path = match &opts.ice_servers_path {
+        Some(path) => path.clone(),
+        None => return Ok(()),
+    };
+
+    let servers = fetch_ice_servers(&path).await?;
+    info!(%path, num_servers = servers.len(), "Loaded ICE servers");
+    get_ice_servers().replace(servers);
+
+    let refresh = Duration::from_secs(opts.ice_servers_refresh_seconds);
+
+    actix::spawn(async move {
+        let mut interval = tokio::time::interval(refresh);
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+
+            match fetch_ice_servers(&path).await {
+                Ok(servers) => get_ice_servers().replace(servers),
+                Err(error) => {
+                    warn!(%error, %path, "Failed to refresh ICE servers, keeping previous servers");
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+pub fn get_ice_servers() -> &'static IceServers {
+    ICE_SERVERS.get().expect("ICE servers not initialized")
+}
+
+/// Servers for a client, as of now
+pub fn ice_servers_for(client_id: &ClientId) -> Vec<IceServer> {
+    get_ice_servers().for_client(client_id, now())
+}
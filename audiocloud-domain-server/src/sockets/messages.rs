@@ -1,20 +1,54 @@
+use std::collections::HashMap;
+
 use actix::{Addr, Message};
 use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-use audiocloud_api::domain::streaming::DomainServerMessage;
-use audiocloud_api::{ClientId, ClientSocketId, SocketId};
+use audiocloud_api::domain::streaming::{DomainClientMessage, DomainServerMessage};
+use audiocloud_api::{AppTaskId, ClientId, ClientSocketId, NodePadId, PlayId, RequestSeek, SocketId};
 
+use crate::sockets::ice::IceServer;
+use crate::sockets::qos::QosClass;
+use crate::sockets::stats::{DataChannelStats, SocketStatsReport};
 use crate::sockets::web_rtc::WebRtcActor;
 use crate::sockets::web_sockets::WebSocketActor;
+use crate::sockets::web_transport::WebTransportActor;
+use crate::tasks::engine_ext::{PadLoudness, PadSpectrum};
+use crate::tasks::TaskStreamCodec;
 use crate::{DomainResult, ResponseMedia};
 
 #[derive(Message, Clone, Debug)]
 #[rtype(result = "()")]
-pub enum SocketSend {
+pub struct SocketSend {
+    pub class:   QosClass,
+    /// Task whose stream the payload belongs to, for streaming packets
+    pub stream:  Option<AppTaskId>,
+    pub payload: SocketPayload,
+}
+
+#[derive(Clone, Debug)]
+pub enum SocketPayload {
     Bytes(Bytes),
     Text(String),
 }
 
+impl SocketPayload {
+    pub fn payload_len(&self) -> usize {
+        match self {
+            SocketPayload::Bytes(bytes) => bytes.len(),
+            SocketPayload::Text(text) => text.len(),
+        }
+    }
+}
+
+/// Fragment outgoing binary messages of a socket, the transport answers with the fragment length it settled on
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "Option<usize>")]
+pub struct SetFragmentation {
+    pub max_fragment_len: usize,
+}
+
 #[derive(Message, Clone, Debug)]
 #[rtype(result = "()")]
 pub enum SocketReceived {
@@ -22,6 +56,228 @@ pub enum SocketReceived {
     Text(ClientSocketId, String),
 }
 
+impl SocketReceived {
+    pub fn payload_len(&self) -> usize {
+        match self {
+            SocketReceived::Bytes(_, bytes) => bytes.len(),
+            SocketReceived::Text(_, text) => text.len(),
+        }
+    }
+}
+
+/// Anything a client sends over a socket, the API messages or the requests only this domain server understands
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum SocketRequest {
+    Api(DomainClientMessage),
+    Domain(DomainSocketRequest),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum DomainSocketRequest {
+    /// Replay the packets of a stream after the last one the client received, before live packets of the task resume
+    ///
+    /// Sent after reconnecting a dropped socket and attaching to the task again. Packets are replayed for as long
+    /// as the domain keeps them, see `PACKET_CACHE_MAX_RETENTION_MS`.
+    ResumeStream {
+        task_id:     AppTaskId,
+        play_id:     PlayId,
+        last_serial: u64,
+    },
+    /// Replay the recorded packets of a play, the last `seconds_back` seconds or all that are kept if not set, while
+    /// live packets of the task are held back until the replay is queued
+    ///
+    /// Sent by clients that join a play session late or rewind it, see `STREAM_RECORDING_SECONDS`.
+    ReplayStream {
+        task_id:      AppTaskId,
+        play_id:      PlayId,
+        #[serde(default)]
+        seconds_back: Option<f64>,
+    },
+    /// Retransmit packets of a stream the client noticed missing from the serials, over the socket the request came in
+    NackPackets {
+        task_id: AppTaskId,
+        play_id: PlayId,
+        serials: Vec<u64>,
+    },
+    /// Split binary messages sent over this socket into fragments of at most `max_fragment_len` bytes
+    ///
+    /// The transport may lower the length to what it carries in one message, the domain tells the client what it
+    /// settled on. Clients that never ask get whole messages.
+    NegotiateFragmentation { max_fragment_len: usize },
+    /// Take over the task memberships of an earlier connection of the client, with the token the domain sent it, and
+    /// continue the streams after the last packets the client received
+    ///
+    /// Sent instead of attaching to every task again after the client changed networks, see
+    /// `SOCKET_RESUMPTION_TTL`. Sockets the earlier connection still has are dropped.
+    ResumeSession {
+        token:   String,
+        #[serde(default)]
+        streams: Vec<ResumedStream>,
+    },
+    /// Move the position of the play the task is playing, with the key the client attached to the task with
+    ///
+    /// The play and its stream continue from the new position, the client hears it within one packet. The domain
+    /// answers with `sought` or `seek_failed`.
+    Seek {
+        task_id:  AppTaskId,
+        #[serde(default)]
+        revision: u64,
+        seek:     RequestSeek,
+    },
+    /// Send a chat message to the other clients attached to the task, at most `MAX_CHAT_LEN` bytes of text
+    Chat { task_id: AppTaskId, text: String },
+}
+
+/// Where a stream the client received before changing networks left off
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ResumedStream {
+    pub task_id:     AppTaskId,
+    pub play_id:     PlayId,
+    pub last_serial: u64,
+}
+
+/// Messages only this domain server sends to clients, next to the API messages
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DomainSocketNotification {
+    /// STUN and TURN servers for the client's side of a WebRTC socket, sent before the peer connection offer
+    IceServers {
+        socket_id:   SocketId,
+        ice_servers: Vec<IceServer>,
+    },
+    /// A WebRTC socket of the client failed and was dropped, the stream continues on the WebSocket it was sent on
+    TransportFallback {
+        socket_id:          SocketId,
+        fallback_socket_id: SocketId,
+        reason:             WebRtcFailure,
+    },
+    /// The client is not keeping up with the stream of a task, the oldest packets queued for it were skipped
+    StreamDegraded {
+        task_id: AppTaskId,
+        /// Packets skipped since the previous notification
+        skipped: u64,
+    },
+    /// Attaching to the task would exceed the number of sockets allowed for the task or for its secure key
+    SocketLimitReached {
+        task_id:     AppTaskId,
+        scope:       SocketLimitScope,
+        max_sockets: usize,
+        /// The socket was dropped to make room for a newer one, otherwise the attach was refused
+        evicted:     bool,
+    },
+    /// Packets of the play that follow are compressed with this codec, sent before the first packet of each play the
+    /// client receives and whenever the codec changes
+    StreamCodec {
+        task_id: AppTaskId,
+        play_id: PlayId,
+        #[serde(flatten)]
+        codec:   TaskStreamCodec,
+    },
+    /// EBU R128 loudness of the metered pads of a task, sent after the streaming packet with the same serial
+    StreamLoudness {
+        task_id:  AppTaskId,
+        play_id:  PlayId,
+        serial:   u64,
+        loudness: HashMap<NodePadId, PadLoudness>,
+    },
+    /// Spectrum of the analyzed pads of a task, sent after the streaming packet with the same serial
+    StreamSpectrum {
+        task_id:  AppTaskId,
+        play_id:  PlayId,
+        serial:   u64,
+        spectrum: HashMap<NodePadId, PadSpectrum>,
+    },
+    /// Binary messages larger than `max_fragment_len` bytes are split into fragments on this socket from now on, none
+    /// if the transport of the socket carries messages of any size whole
+    FragmentationNegotiated {
+        socket_id:        SocketId,
+        max_fragment_len: Option<usize>,
+    },
+    /// The domain is draining its sockets, they close shortly and new ones are refused until the domain is back
+    StreamEnding {
+        reason:        DrainReason,
+        /// Where to reconnect to, if the streams continue elsewhere
+        reconnect_url: Option<String>,
+        /// Tasks the client was attached to
+        tasks:         Vec<AppTaskId>,
+    },
+    /// Token to resume the session of the client from a new connection, sent when the client attaches to a task and
+    /// after each resumption. It stays good for `ttl_ms` milliseconds after the client lost its last socket.
+    ResumptionToken { token: String, ttl_ms: u64 },
+    /// The session of the token was resumed, the client is attached to the tasks again
+    SessionResumed { tasks: Vec<AppTaskId> },
+    /// The session of the token could not be resumed, the client has to attach to its tasks again
+    SessionResumeFailed { reason: String },
+    /// The play of the task continues from the position the client sought to
+    Sought { task_id: AppTaskId, play_id: PlayId },
+    /// The play of the task could not be sought, it continues where it was
+    SeekFailed {
+        task_id: AppTaskId,
+        play_id: PlayId,
+        reason:  String,
+    },
+    /// The client sent messages faster than its rate limit allows, the message was dropped and the next one is
+    /// accepted after `retry_after_ms`
+    RateLimited { retry_after_ms: u64 },
+    /// A chat message another client attached to the task sent, dropped first when the client falls behind
+    Chat {
+        task_id: AppTaskId,
+        from:    ClientId,
+        text:    String,
+    },
+}
+
+/// Why the domain drains its sockets
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DrainReason {
+    /// The domain server process is exiting
+    Shutdown,
+    /// An operator took the domain out of service
+    Maintenance,
+}
+
+impl DrainReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DrainReason::Shutdown => "shutdown",
+            DrainReason::Maintenance => "maintenance",
+        }
+    }
+}
+
+/// What the sockets over a limit were counted by
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SocketLimitScope {
+    SecureKey,
+    Task,
+}
+
+/// Why a WebRTC socket was given up on
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebRtcFailure {
+    /// ICE negotiation failed to find a working pair of candidates
+    IceFailed,
+    /// The peer connection did not open a data channel within `SOCKET_INIT_TIMEOUT`
+    IceTimeout,
+    /// No pong came back over the data channel within `WEB_RTC_STALL_TIMEOUT`
+    Stalled,
+}
+
+impl WebRtcFailure {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebRtcFailure::IceFailed => "ice_failed",
+            WebRtcFailure::IceTimeout => "ice_timeout",
+            WebRtcFailure::Stalled => "stalled",
+        }
+    }
+}
+
 #[derive(Message, Clone, Debug)]
 #[rtype(result = "DomainResult")]
 pub struct RegisterWebSocket {
@@ -29,12 +285,26 @@ pub struct RegisterWebSocket {
     pub socket_id: ClientSocketId,
 }
 
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult")]
+pub struct RegisterWebTransportSocket {
+    pub address:   Addr<WebTransportActor>,
+    pub socket_id: ClientSocketId,
+}
+
 #[derive(Message, Clone, Debug)]
 #[rtype(result = "()")]
 pub struct SocketConnected {
     pub socket_id: ClientSocketId,
 }
 
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyWebRtcFailed {
+    pub socket_id: ClientSocketId,
+    pub reason:    WebRtcFailure,
+}
+
 #[derive(Message, Clone, Debug)]
 #[rtype(result = "()")]
 pub struct RegisterWebRtcSocket {
@@ -57,3 +327,55 @@ pub struct SendToClient {
     pub message:   DomainServerMessage,
     pub media:     ResponseMedia,
 }
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifySocketDropped {
+    pub socket_id: ClientSocketId,
+    pub reason:    String,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyStreamDegraded {
+    pub socket_id: ClientSocketId,
+    pub task_id:   AppTaskId,
+    pub skipped:   u64,
+}
+
+/// Network statistics of every connected socket
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "Vec<SocketStatsReport>")]
+pub struct ListSocketStats;
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyDataChannelStats {
+    pub socket_id: ClientSocketId,
+    pub stats:     DataChannelStats,
+}
+
+/// Tell every client its streams are ending, refuse new sockets and close all sockets once their queues had time to
+/// flush
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<SocketDrainResult>")]
+pub struct DrainSockets {
+    pub reason:        DrainReason,
+    /// Overrides `SOCKET_DRAIN_RECONNECT_URL`
+    pub reconnect_url: Option<String>,
+}
+
+/// Request to drain the sockets of the domain for maintenance
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, ToSchema)]
+pub struct SocketDrain {
+    /// Where clients should reconnect to, `SOCKET_DRAIN_RECONNECT_URL` if not set
+    #[serde(default)]
+    pub reconnect_url: Option<String>,
+}
+
+/// Sockets that were told their streams are ending and closed
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+pub struct SocketDrainResult {
+    pub clients: usize,
+    pub sockets: usize,
+}
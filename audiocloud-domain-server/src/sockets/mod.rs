@@ -1,38 +1,123 @@
 use actix::{Actor, Addr};
 use anyhow::anyhow;
-use clap::Args;
+use clap::{Args, ValueEnum};
 use nanoid::nanoid;
 use once_cell::sync::OnceCell;
 use tracing::*;
 
 use audiocloud_api::{SecureKey, SocketId};
+pub use encryption::PacketCipher;
+pub use ice::IceServer;
 pub use messages::*;
+pub use stats::{DataChannelStats, SocketStatsReport};
 pub use supervisor::SocketsSupervisor;
 pub use web_sockets::configure;
 
+mod bitrate;
+mod encryption;
+mod fragmentation;
+mod ice;
 mod messages;
+mod qos;
+mod resumption;
+mod serialization;
+mod shards;
+mod stats;
 mod supervisor;
 mod web_rtc;
 mod web_sockets;
+mod web_transport;
+
+#[cfg(test)]
+mod tests;
 
 static SOCKETS_SUPERVISOR: OnceCell<Addr<SocketsSupervisor>> = OnceCell::new();
+static SOCKETS_QOS: OnceCell<qos::QosOpts> = OnceCell::new();
 
 #[derive(Args, Clone, Debug)]
 pub struct SocketsOpts {
     #[clap(flatten)]
     web_rtc: web_rtc::WebRtcOpts,
 
-    /// Number of milliseconds to wait between pinging sockets (RTC or WebSockets)
+    #[clap(flatten)]
+    web_transport: web_transport::WebTransportOpts,
+
+    #[clap(flatten)]
+    qos: qos::QosOpts,
+
+    #[clap(flatten)]
+    bitrate: bitrate::BitrateOpts,
+
+    /// Number of milliseconds to wait between pinging sockets (RTC, WebTransport or WebSockets)
     #[clap(long, env, default_value = "2500")]
     socket_ping_interval: u64,
 
+    /// Pings without a reply after this many milliseconds count towards the loss in the socket statistics
+    #[clap(long, env, default_value = "2000")]
+    socket_ping_loss_timeout: u64,
+
     /// If no ping reply is received after this many milliseconds, the socket is considered dead and will be dropped
     #[clap(long, env, default_value = "15000")]
     socket_drop_timeout: u64,
 
+    /// If no ping reply comes back over a connected WebRTC socket in this many milliseconds, the data channel is
+    /// considered stalled and the client falls back to its WebSocket
+    #[clap(long, env, default_value = "5000")]
+    web_rtc_stall_timeout: u64,
+
     /// If the socket fails to initialize (fully connect) in this many milliseconds, the socket is considered dead and will be dropped
     #[clap(long, env, default_value = "15000")]
     socket_init_timeout: u64,
+
+    /// Number of threads streaming packets are encoded and encrypted on, each task on one of them, 0 to encode them on
+    /// the thread of the sockets supervisor
+    #[clap(long, env, default_value = "4")]
+    socket_packet_shards: usize,
+
+    /// Number of threads large batches of replayed packets are encoded on in parallel, 0 to encode them on the thread
+    /// of their packet shard
+    #[clap(long, env, default_value = "2")]
+    socket_serialization_threads: usize,
+
+    /// Encrypt audio in streaming packets with a key derived from the client's secure key, for deployments where TLS
+    /// terminates at a proxy
+    #[clap(long, env)]
+    socket_packet_encryption: bool,
+
+    /// Maximum number of sockets attached to a task with the same secure key, unlimited if not set
+    #[clap(long, env)]
+    socket_max_per_secure_key: Option<usize>,
+
+    /// Maximum number of sockets attached to a task, unlimited if not set
+    #[clap(long, env)]
+    socket_max_per_task: Option<usize>,
+
+    /// What to do when a client attaching to a task would exceed one of the socket limits
+    #[clap(long, env, default_value = "reject")]
+    socket_limit_policy: SocketLimitPolicy,
+
+    /// Where clients should reconnect to when the domain drains its sockets, sent with the notification that streams
+    /// are ending
+    #[clap(long, env)]
+    socket_drain_reconnect_url: Option<String>,
+
+    /// Number of milliseconds sockets have to flush their queues after being told that streams are ending, before they
+    /// are closed
+    #[clap(long, env, default_value = "2000")]
+    socket_drain_timeout: u64,
+
+    /// Milliseconds a client that lost all of its sockets can resume its session from a new connection with its
+    /// resumption token, 0 to not issue tokens
+    #[clap(long, env, default_value = "60000")]
+    socket_resumption_ttl: u64,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SocketLimitPolicy {
+    /// Refuse to attach the new client
+    Reject,
+    /// Drop the oldest sockets attached to the task to make room for the new client
+    EvictOldest,
 }
 
 fn get_next_socket_id() -> SocketId {
@@ -46,18 +131,42 @@ struct SocketMembership {
 }
 
 #[instrument(skip_all, err)]
-pub fn init(cfg: SocketsOpts) -> anyhow::Result<()> {
+pub async fn init(cfg: SocketsOpts) -> anyhow::Result<()> {
     let web_rtc_cfg = cfg.web_rtc.clone();
+    let web_transport_cfg = cfg.web_transport.clone();
+
+    SOCKETS_QOS.set(cfg.qos.clone())
+               .map_err(|_| anyhow!("Sockets QoS options already initialized"))?;
+    serialization::init(cfg.socket_serialization_threads)?;
     let supervisor = SocketsSupervisor::new(cfg);
 
-    web_rtc::init(&web_rtc_cfg)?;
+    web_rtc::init(&web_rtc_cfg).await?;
 
     SOCKETS_SUPERVISOR.set(supervisor.start())
                       .map_err(|_| anyhow!("Sockets supervisor already initialized"))?;
 
+    web_transport::init(&web_transport_cfg).await?;
+
     Ok(())
 }
 
+fn get_qos_opts() -> &'static qos::QosOpts {
+    SOCKETS_QOS.get().expect("Sockets QoS options not initialized")
+}
+
 pub fn get_sockets_supervisor() -> &'static Addr<SocketsSupervisor> {
     SOCKETS_SUPERVISOR.get().expect("Sockets supervisor not initialized")
 }
+
+/// Drain the sockets before the process exits, so clients learn where to reconnect instead of having their
+/// connections reset
+pub async fn drain_on_shutdown() {
+    let drain = DrainSockets { reason:        { DrainReason::Shutdown },
+                               reconnect_url: { None }, };
+
+    match get_sockets_supervisor().send(drain).await {
+        Ok(Ok(result)) => info!(clients = result.clients, sockets = result.sockets, "Drained sockets"),
+        Ok(Err(error)) => warn!(%error, "Failed to drain sockets"),
+        Err(error) => warn!(%error, "Failed to reach sockets supervisor to drain sockets"),
+    }
+}
@@ -0,0 +1,315 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use clap::{Args, ValueEnum};
+use tracing::*;
+
+use audiocloud_api::domain::streaming::DomainServerMessage;
+use audiocloud_api::{AppTaskId, ClientSocketId, TaskEvent};
+
+use crate::sockets::{get_sockets_supervisor, DomainSocketNotification, NotifyStreamDegraded, SocketPayload};
+
+/// A client is told at most this often that packets of a stream are being skipped
+const DEGRADED_NOTIFY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Delivery class of an outgoing socket message, listed from highest to lowest priority
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum QosClass {
+    State,
+    /// Audio of tasks with the low latency profile
+    Realtime,
+    Audio,
+    Meters,
+    Chat,
+}
+
+impl QosClass {
+    pub const ALL: [QosClass; 5] = [QosClass::State,
+                                    QosClass::Realtime,
+                                    QosClass::Audio,
+                                    QosClass::Meters,
+                                    QosClass::Chat];
+
+    pub fn classify(message: &DomainServerMessage) -> Self {
+        match message {
+            DomainServerMessage::TaskEvent { event: TaskEvent::StreamingPacket { .. },
+                                             .. } => QosClass::Audio,
+            DomainServerMessage::TaskEvent { .. }
+            | DomainServerMessage::PeerConnectionResponse { .. }
+            | DomainServerMessage::AnswerPeerConnectionResponse { .. }
+            | DomainServerMessage::SubmitPeerConnectionCandidate { .. }
+            | DomainServerMessage::ModifyTaskSpecResponse { .. }
+            | DomainServerMessage::AttachToTaskResponse { .. }
+            | DomainServerMessage::DetachFromTaskResponse { .. }
+            | DomainServerMessage::Ping { .. } => QosClass::State,
+        }
+    }
+
+    /// Class of the messages only this domain server sends, readings sent with the stream are meters
+    pub fn classify_notification(notification: &DomainSocketNotification) -> Self {
+        match notification {
+            DomainSocketNotification::StreamLoudness { .. } | DomainSocketNotification::StreamSpectrum { .. } => {
+                QosClass::Meters
+            }
+            DomainSocketNotification::Chat { .. } => QosClass::Chat,
+            DomainSocketNotification::IceServers { .. }
+            | DomainSocketNotification::TransportFallback { .. }
+            | DomainSocketNotification::StreamDegraded { .. }
+            | DomainSocketNotification::SocketLimitReached { .. }
+            | DomainSocketNotification::StreamCodec { .. }
+            | DomainSocketNotification::FragmentationNegotiated { .. }
+            | DomainSocketNotification::StreamEnding { .. }
+            | DomainSocketNotification::ResumptionToken { .. }
+            | DomainSocketNotification::SessionResumed { .. }
+            | DomainSocketNotification::SessionResumeFailed { .. }
+            | DomainSocketNotification::Sought { .. }
+            | DomainSocketNotification::SeekFailed { .. }
+            | DomainSocketNotification::RateLimited { .. } => QosClass::State,
+        }
+    }
+
+    /// Classes whose messages are packets of a stream, the client is told when they are skipped
+    fn is_stream(self) -> bool {
+        matches!(self, QosClass::Realtime | QosClass::Audio)
+    }
+
+    /// Task whose stream a message belongs to, streaming packets are queued per task the client is attached to
+    pub fn stream(message: &DomainServerMessage) -> Option<AppTaskId> {
+        match message {
+            DomainServerMessage::TaskEvent { task_id,
+                                             event: TaskEvent::StreamingPacket { .. }, } => Some(task_id.clone()),
+            _ => None,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum QosDropPolicy {
+    /// When the queue is full, drop the oldest queued message to make room for the new one
+    Oldest,
+
+    /// When the queue is full, drop the incoming message
+    Newest,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct QosOpts {
+    /// Number of queued state messages (responses, task state, pings) per socket above which a warning is logged,
+    /// state messages are never dropped
+    #[clap(long, env, default_value = "1024")]
+    socket_qos_state_queue: usize,
+
+    /// Maximum number of queued audio streaming packets of low latency tasks per socket and task, kept short so that a
+    /// slow client skips ahead instead of falling behind
+    #[clap(long, env, default_value = "4")]
+    socket_qos_realtime_queue: usize,
+
+    /// Drop policy for audio streaming packets of low latency tasks when the queue is full
+    #[clap(long, env, default_value = "oldest")]
+    socket_qos_realtime_drop: QosDropPolicy,
+
+    /// Maximum number of queued audio streaming packets per socket and task
+    #[clap(long, env, default_value = "64")]
+    socket_qos_audio_queue: usize,
+
+    /// Drop policy for audio streaming packets when the queue is full
+    #[clap(long, env, default_value = "oldest")]
+    socket_qos_audio_drop: QosDropPolicy,
+
+    /// Maximum number of queued metering messages per socket
+    #[clap(long, env, default_value = "4")]
+    socket_qos_meters_queue: usize,
+
+    /// Drop policy for metering messages when the queue is full
+    #[clap(long, env, default_value = "oldest")]
+    socket_qos_meters_drop: QosDropPolicy,
+
+    /// Maximum number of queued chat messages per socket
+    #[clap(long, env, default_value = "64")]
+    socket_qos_chat_queue: usize,
+
+    /// Drop policy for chat messages when the queue is full
+    #[clap(long, env, default_value = "newest")]
+    socket_qos_chat_drop: QosDropPolicy,
+
+    /// Most bytes a WebSocket writes every 5 ms, the rest stays queued where the drop policies apply
+    #[clap(long, env, default_value = "65536")]
+    socket_qos_web_socket_flush_bytes: usize,
+}
+
+impl QosOpts {
+    pub fn web_socket_flush_bytes(&self) -> usize {
+        self.socket_qos_web_socket_flush_bytes
+    }
+
+    /// High-water mark of a queue, and how it drops messages above it, `None` if it never does
+    fn limits(&self, class: QosClass) -> (usize, Option<QosDropPolicy>) {
+        match class {
+            QosClass::State => (self.socket_qos_state_queue, None),
+            QosClass::Realtime => (self.socket_qos_realtime_queue, Some(self.socket_qos_realtime_drop)),
+            QosClass::Audio => (self.socket_qos_audio_queue, Some(self.socket_qos_audio_drop)),
+            QosClass::Meters => (self.socket_qos_meters_queue, Some(self.socket_qos_meters_drop)),
+            QosClass::Chat => (self.socket_qos_chat_queue, Some(self.socket_qos_chat_drop)),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct QueuedPayload {
+    stream:  Option<AppTaskId>,
+    payload: SocketPayload,
+}
+
+/// Packets of a stream skipped since the client was last told
+#[derive(Debug, Default)]
+struct StreamDegradation {
+    skipped:     u64,
+    notified_at: Option<Instant>,
+}
+
+/// Per-socket outgoing queues, one for every QoS class
+///
+/// Streaming packets count towards the high-water mark of the task they belong to, so a busy stream can not push the
+/// packets of another task the client is attached to out of the queue.
+#[derive(Debug)]
+pub struct QosQueues {
+    opts:     QosOpts,
+    queues:   [VecDeque<QueuedPayload>; 5],
+    dropped:  [u64; 5],
+    degraded: HashMap<AppTaskId, StreamDegradation>,
+}
+
+impl QosQueues {
+    pub fn new(opts: QosOpts) -> Self {
+        Self { opts:     { opts },
+               queues:   { Default::default() },
+               dropped:  { Default::default() },
+               degraded: { Default::default() }, }
+    }
+
+    pub fn push(&mut self, class: QosClass, stream: Option<AppTaskId>, payload: SocketPayload) {
+        let (high_water, policy) = self.opts.limits(class);
+        let queue = &mut self.queues[class.index()];
+        let queued = queue.iter().filter(|queued| queued.stream == stream).count();
+
+        if queued >= high_water {
+            match policy {
+                None => {
+                    if queued == high_water {
+                        warn!(?class, queued, "queue above high-water mark, client is falling behind");
+                    }
+                }
+                Some(policy) => {
+                    self.dropped[class.index()] += 1;
+                    trace!(?class,
+                           ?policy,
+                           ?stream,
+                           dropped = self.dropped[class.index()],
+                           "queue full, dropping");
+
+                    if let (Some(task_id), true) = (&stream, class.is_stream()) {
+                        self.degraded.entry(task_id.clone()).or_default().skipped += 1;
+                    }
+
+                    match policy {
+                        QosDropPolicy::Oldest => {
+                            if let Some(oldest) = queue.iter().position(|queued| queued.stream == stream) {
+                                queue.remove(oldest);
+                            }
+                        }
+                        QosDropPolicy::Newest => {
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+
+        queue.push_back(QueuedPayload { stream, payload });
+    }
+
+    /// Streams with packets skipped since the client was last told, with the number of packets skipped
+    pub fn take_degraded(&mut self, at: Instant) -> Vec<(AppTaskId, u64)> {
+        let mut rv = vec![];
+
+        for (task_id, degradation) in &mut self.degraded {
+            let due =
+                degradation.notified_at
+                           .map(|notified_at| at.saturating_duration_since(notified_at) >= DEGRADED_NOTIFY_INTERVAL)
+                           .unwrap_or(true);
+
+            if degradation.skipped > 0 && due {
+                rv.push((task_id.clone(), degradation.skipped));
+                degradation.skipped = 0;
+                degradation.notified_at = Some(at);
+            }
+        }
+
+        rv
+    }
+
+    /// What the supervisor is told about the streams of the socket that are skipping packets
+    pub fn degraded_reports(&mut self, socket_id: &ClientSocketId, at: Instant) -> Vec<NotifyStreamDegraded> {
+        self.take_degraded(at)
+            .into_iter()
+            .map(|(task_id, skipped)| NotifyStreamDegraded { socket_id: { socket_id.clone() },
+                                                             task_id:   { task_id },
+                                                             skipped:   { skipped }, })
+            .collect()
+    }
+
+    /// Have the supervisor tell the client about streams that are skipping packets
+    pub fn report_degraded(&mut self, socket_id: &ClientSocketId) {
+        for report in self.degraded_reports(socket_id, Instant::now()) {
+            get_sockets_supervisor().do_send(report);
+        }
+    }
+
+    /// Next message to send, taking from higher priority classes first
+    pub fn pop(&mut self) -> Option<SocketPayload> {
+        self.pop_classified().map(|(_, payload)| payload)
+    }
+
+    /// Messages to write in one flush, highest priority first, until `budget` bytes are taken
+    ///
+    /// The first message is taken whatever its size, so a message larger than the budget does not block the queues.
+    pub fn pop_batch(&mut self, budget: usize) -> Vec<SocketPayload> {
+        let mut batch = vec![];
+        let mut taken = 0;
+
+        while taken < budget {
+            let next_len = match QosClass::ALL.iter()
+                                              .find_map(|class| self.queues[class.index()].front())
+            {
+                Some(queued) => queued.payload.payload_len(),
+                None => break,
+            };
+
+            if !batch.is_empty() && taken + next_len > budget {
+                break;
+            }
+
+            match self.pop() {
+                Some(payload) => {
+                    taken += next_len;
+                    batch.push(payload);
+                }
+                None => break,
+            }
+        }
+
+        batch
+    }
+
+    /// Like [`QosQueues::pop`], for transports that deliver some classes differently
+    pub fn pop_classified(&mut self) -> Option<(QosClass, SocketPayload)> {
+        QosClass::ALL.iter().find_map(|class| {
+                                self.queues[class.index()].pop_front()
+                                                          .map(|queued| (*class, queued.payload))
+                            })
+    }
+}
@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use nanoid::nanoid;
+
+use audiocloud_api::{AppTaskId, ClientId, SecureKey};
+
+/// Length of resumption tokens, longer than socket ids since a token stands in for the secure keys of the client
+const RESUMPTION_TOKEN_LEN: usize = 32;
+
+pub(crate) fn new_resumption_token() -> String {
+    nanoid!(RESUMPTION_TOKEN_LEN)
+}
+
+/// Memberships of a client that lost all of its sockets, until it resumes them or they expire
+#[derive(Debug)]
+pub struct ParkedSession {
+    pub client_id:   ClientId,
+    pub memberships: HashMap<AppTaskId, SecureKey>,
+    pub expires_at:  Instant,
+}
+
+/// Parked sessions by the resumption token of their client
+#[derive(Debug, Default)]
+pub struct ParkedSessions {
+    sessions: HashMap<String, ParkedSession>,
+}
+
+impl ParkedSessions {
+    pub fn park(&mut self, token: String, session: ParkedSession) {
+        self.sessions.insert(token, session);
+    }
+
+    /// Take the session of the token, tokens are good for one resumption and not at all once expired
+    pub fn take(&mut self, token: &str, now: Instant) -> Option<ParkedSession> {
+        self.sessions.remove(token).filter(|session| session.expires_at > now)
+    }
+
+    /// Forget expired sessions, returning how many there were
+    pub fn expire(&mut self, now: Instant) -> usize {
+        let before = self.sessions.len();
+        self.sessions.retain(|_, session| session.expires_at > now);
+
+        before - self.sessions.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+}
@@ -0,0 +1,124 @@
+use std::cell::RefCell;
+use std::time::Instant;
+
+use anyhow::anyhow;
+use bytes::Bytes;
+use once_cell::sync::{Lazy, OnceCell};
+use opentelemetry::metrics::Histogram;
+use opentelemetry::{global, KeyValue};
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use serde::Serialize;
+
+use crate::sockets::SocketPayload;
+use crate::{o11y, ResponseMedia};
+
+/// Batches with at least this many messages are encoded in parallel on the serialization pool
+pub const PARALLEL_BATCH_LEN: usize = 8;
+
+/// Encoding buffers that grew past this many bytes are released instead of kept for the next message
+const MAX_RETAINED_BUFFER: usize = 1024 * 1024;
+
+static SERIALIZATION_POOL: OnceCell<ThreadPool> = OnceCell::new();
+
+static SERIALIZATION_SECONDS: Lazy<Histogram<f64>> = Lazy::new(|| {
+    let meter = global::meter("audiocloud.io/sockets");
+
+    meter.f64_histogram("socket_serialization_seconds")
+         .with_description("Time spent encoding messages sent to sockets, by kind and media")
+         .init()
+});
+
+thread_local! {
+    static BUFFER: RefCell<Vec<u8>> = RefCell::new(Vec::new());
+}
+
+/// Start the pool large batches of socket messages are encoded on, none if `threads` is 0
+pub fn init(threads: usize) -> anyhow::Result<()> {
+    if threads == 0 {
+        return Ok(());
+    }
+
+    let pool = ThreadPoolBuilder::new().num_threads(threads)
+                                       .thread_name(|index| format!("socket-serialization-{index}"))
+                                       .build()?;
+
+    SERIALIZATION_POOL.set(pool)
+                      .map_err(|_| anyhow!("Socket serialization pool already initialized"))
+}
+
+/// Encode a message in the media of the socket, into a buffer kept by the calling thread for the next message
+///
+/// MsgPack is written with field names, the same as [`audiocloud_api::MsgPack`] encodes.
+pub fn encode_payload<T: Serialize>(message: &T,
+                                    media: ResponseMedia,
+                                    kind: &'static str)
+                                    -> anyhow::Result<SocketPayload> {
+    let started = Instant::now();
+
+    let payload = BUFFER.with(|buffer| encode_into(&mut buffer.borrow_mut(), message, media))?;
+
+    let media = match media {
+        ResponseMedia::MsgPack => "msgpack",
+        ResponseMedia::Json => "json",
+    };
+
+    o11y::in_context(|ctx| {
+        SERIALIZATION_SECONDS.record(ctx,
+                                     started.elapsed().as_secs_f64(),
+                                     &[KeyValue::new("kind", kind), KeyValue::new("media", media)]);
+    });
+
+    Ok(payload)
+}
+
+fn encode_into<T: Serialize>(buffer: &mut Vec<u8>, message: &T, media: ResponseMedia) -> anyhow::Result<SocketPayload> {
+    buffer.clear();
+
+    let payload = match media {
+        ResponseMedia::MsgPack => {
+            rmp_serde::encode::write_named(buffer, message)?;
+            SocketPayload::Bytes(Bytes::copy_from_slice(buffer))
+        }
+        ResponseMedia::Json => {
+            serde_json::to_writer(&mut *buffer, message)?;
+            SocketPayload::Text(String::from_utf8(buffer.clone())?)
+        }
+    };
+
+    if buffer.capacity() > MAX_RETAINED_BUFFER {
+        *buffer = Vec::new();
+    }
+
+    Ok(payload)
+}
+
+/// Encode MsgPack bytes of a message, see [`encode_payload`]
+pub fn encode_msgpack<T: Serialize>(message: &T, kind: &'static str) -> anyhow::Result<Bytes> {
+    match encode_payload(message, ResponseMedia::MsgPack, kind)? {
+        SocketPayload::Bytes(bytes) => Ok(bytes),
+        SocketPayload::Text(text) => Ok(text.into()),
+    }
+}
+
+/// Encode messages in order, spread over the serialization pool when there are enough of them to be worth it
+///
+/// Blocks until all messages are encoded, so it should only be called off the threads of actors that have to stay
+/// responsive.
+pub fn encode_batch<T: Serialize + Sync>(messages: &[T],
+                                         media: ResponseMedia,
+                                         kind: &'static str)
+                                         -> Vec<anyhow::Result<SocketPayload>> {
+    match SERIALIZATION_POOL.get() {
+        Some(pool) if messages.len() >= PARALLEL_BATCH_LEN => {
+            pool.install(|| {
+                    messages.par_iter()
+                            .map(|message| encode_payload(message, media, kind))
+                            .collect()
+                })
+        }
+        _ => messages.iter()
+                     .map(|message| encode_payload(message, media, kind))
+                     .collect(),
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use actix::{Actor, Addr, Arbiter, Context, Handler, Message, Recipient};
+use anyhow::anyhow;
+use bytes::Bytes;
+use tracing::*;
+
+use audiocloud_api::domain::streaming::DomainServerMessage;
+use audiocloud_api::{AppTaskId, SecureKey, StreamingPacket, TaskEvent};
+
+use crate::sockets::encryption::PacketCiphers;
+use crate::sockets::qos::QosClass;
+use crate::sockets::serialization::{encode_batch, encode_msgpack};
+use crate::sockets::{DomainSocketNotification, SocketPayload, SocketSend};
+use crate::ResponseMedia;
+
+/// A socket a streaming packet goes out on, with the secure key to encrypt its audio with if packets are encrypted
+pub struct FanOutTarget {
+    pub socket:     Recipient<SocketSend>,
+    pub secure_key: Option<SecureKey>,
+}
+
+/// Encode a streaming packet for every socket receiving it and hand it to them
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct FanOutPacket {
+    pub task_id:       AppTaskId,
+    pub packet:        StreamingPacket,
+    pub class:         QosClass,
+    pub targets:       Vec<FanOutTarget>,
+    /// Sent to every socket right after the packet, such as the loudness metered while the packet was collected
+    pub notifications: Vec<DomainSocketNotification>,
+}
+
+/// Encode packets replayed to one socket, in the order they are listed
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ReplayPackets {
+    pub task_id: AppTaskId,
+    pub packets: Vec<StreamingPacket>,
+    pub media:   ResponseMedia,
+    pub target:  FanOutTarget,
+}
+
+/// Encodes streaming packets on an arbiter of its own, so that packets of different tasks are encoded in parallel
+pub struct PacketShard {
+    index:   usize,
+    ciphers: PacketCiphers,
+}
+
+impl Actor for PacketShard {
+    type Context = Context<Self>;
+}
+
+impl Handler<FanOutPacket> for PacketShard {
+    type Result = ();
+
+    #[instrument(name = "fan_out_packet", skip_all, fields(shard = self.index, task_id = %msg.task_id))]
+    fn handle(&mut self, msg: FanOutPacket, _ctx: &mut Self::Context) -> Self::Result {
+        fan_out_packet(msg, &mut self.ciphers);
+    }
+}
+
+impl Handler<ReplayPackets> for PacketShard {
+    type Result = ();
+
+    #[instrument(name = "replay_packets", skip_all, fields(shard = self.index, task_id = %msg.task_id))]
+    fn handle(&mut self, msg: ReplayPackets, _ctx: &mut Self::Context) -> Self::Result {
+        replay_packets(msg, &mut self.ciphers);
+    }
+}
+
+/// Routes the packets of each task to one of the shards, all packets of a task to the same one so they stay in order
+#[derive(Default)]
+pub struct PacketShards {
+    shards: Vec<Addr<PacketShard>>,
+}
+
+impl PacketShards {
+    pub fn start(count: usize) -> Self {
+        let shards = (0..count).map(|index| {
+                                   PacketShard::start_in_arbiter(&Arbiter::new().handle(), move |_| {
+                                       PacketShard { index:   { index },
+                                                     ciphers: { Default::default() }, }
+                                   })
+                               })
+                               .collect();
+
+        Self { shards }
+    }
+
+    /// Shard the packets of the task are encoded on, none if packets are encoded by the sockets supervisor itself
+    pub fn shard_for(&self, task_id: &AppTaskId) -> Option<&Addr<PacketShard>> {
+        self.shards.get(shard_index(task_id, self.shards.len())?)
+    }
+}
+
+pub fn shard_index(task_id: &AppTaskId, shards: usize) -> Option<usize> {
+    if shards == 0 {
+        return None;
+    }
+
+    let mut hasher = DefaultHasher::new();
+    task_id.to_string().hash(&mut hasher);
+
+    Some((hasher.finish() % shards as u64) as usize)
+}
+
+/// Encode the packet once for all sockets receiving it in the clear, and once for each socket receiving it encrypted
+pub fn fan_out_packet(msg: FanOutPacket, ciphers: &mut PacketCiphers) {
+    let FanOutPacket { task_id,
+                       packet,
+                       class,
+                       targets,
+                       notifications, } = msg;
+
+    let mut clear = None;
+    let notifications = notifications.into_iter()
+                                     .filter_map(|notification| {
+                                         let class = QosClass::classify_notification(&notification);
+                                         encode_notification(&task_id, notification).map(|payload| (class, payload))
+                                     })
+                                     .collect::<Vec<_>>();
+
+    for target in targets {
+        let payload = match &target.secure_key {
+            Some(secure_key) => ciphers.get(&task_id, secure_key)
+                                       .encrypt_packet(&packet)
+                                       .and_then(|packet| encode_packet(&task_id, packet)),
+            None => clear.get_or_insert_with(|| encode_packet(&task_id, packet.clone()))
+                         .as_ref()
+                         .map(Bytes::clone)
+                         .map_err(|error| anyhow!("{error}")),
+        };
+
+        match payload {
+            Ok(payload) => target.socket.do_send(SocketSend { class:   { class },
+                                                              stream:  { Some(task_id.clone()) },
+                                                              payload: { SocketPayload::Bytes(payload) }, }),
+            Err(error) => {
+                warn!(%error, %task_id, serial = packet.serial, "Failed to encode streaming packet");
+                continue;
+            }
+        }
+
+        for (class, notification) in &notifications {
+            target.socket.do_send(SocketSend { class:   { *class },
+                                               stream:  { Some(task_id.clone()) },
+                                               payload: { SocketPayload::Bytes(notification.clone()) }, });
+        }
+    }
+}
+
+/// Encode replayed packets in order, on the serialization pool when there are many, and send them in that order
+///
+/// Replays go through the shard of the task, so live packets sent after them can not overtake them.
+pub fn replay_packets(msg: ReplayPackets, ciphers: &mut PacketCiphers) {
+    let ReplayPackets { task_id,
+                        packets,
+                        media,
+                        target, } = msg;
+
+    let cipher = target.secure_key
+                       .as_ref()
+                       .map(|secure_key| ciphers.get(&task_id, secure_key));
+
+    let messages = packets.into_iter()
+                          .map(|packet| match cipher {
+                              Some(cipher) => cipher.encrypt_packet(&packet),
+                              None => Ok(packet),
+                          })
+                          .map(|packet| packet.map(|packet| packet_message(&task_id, packet)))
+                          .collect::<anyhow::Result<Vec<_>>>();
+
+    let messages = match messages {
+        Ok(messages) => messages,
+        Err(error) => {
+            warn!(%error, %task_id, "Failed to encrypt replayed packets");
+            return;
+        }
+    };
+
+    for payload in encode_batch(&messages, media, "replay") {
+        match payload {
+            // replayed packets are late already, they travel with the stable audio class whatever the latency profile
+            Ok(payload) => target.socket.do_send(SocketSend { class:   { QosClass::Audio },
+                                                              stream:  { Some(task_id.clone()) },
+                                                              payload: { payload }, }),
+            Err(error) => {
+                warn!(%error, %task_id, "Failed to encode replayed packet");
+                break;
+            }
+        }
+    }
+}
+
+fn encode_notification(task_id: &AppTaskId, notification: DomainSocketNotification) -> Option<Bytes> {
+    match encode_msgpack(&notification, "notification") {
+        Ok(payload) => Some(payload),
+        Err(error) => {
+            warn!(%error, %task_id, "Failed to encode packet notification");
+            None
+        }
+    }
+}
+
+fn packet_message(task_id: &AppTaskId, packet: StreamingPacket) -> DomainServerMessage {
+    let event = TaskEvent::StreamingPacket { packet };
+
+    DomainServerMessage::TaskEvent { task_id: { task_id.clone() },
+                                     event:   { event }, }
+}
+
+fn encode_packet(task_id: &AppTaskId, packet: StreamingPacket) -> anyhow::Result<Bytes> {
+    encode_msgpack(&packet_message(task_id, packet), "packet")
+}
@@ -0,0 +1,171 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use audiocloud_api::{ClientId, SocketId};
+
+use crate::sockets::bitrate::LinkQuality;
+
+/// Number of most recent pings the loss of a socket is computed over
+const LOSS_WINDOW: usize = 100;
+
+/// Network quality of a socket, measured with the pings the supervisor sends to keep sockets alive
+#[derive(Debug, Default)]
+pub struct SocketStats {
+    pending:         HashMap<String, Instant>,
+    outcomes:        VecDeque<bool>,
+    pings_sent:      u64,
+    pongs_received:  u64,
+    rtt_ms:          Option<f64>,
+    smoothed_rtt_ms: Option<f64>,
+    jitter_ms:       f64,
+    data_channel:    Option<DataChannelStats>,
+    /// Payloads are sent through shared references of the socket
+    bytes_sent:      AtomicU64,
+    bytes_received:  u64,
+}
+
+impl SocketStats {
+    pub fn ping_sent(&mut self, challenge: String, at: Instant) {
+        self.pings_sent += 1;
+        self.pending.insert(challenge, at);
+    }
+
+    /// Account for the pong answering a ping and return the round trip time in milliseconds, if the ping is known
+    ///
+    /// The smoothed RTT follows RFC 6298 and the jitter RFC 3550, with the RTT taking the place of the transit time.
+    pub fn pong_received(&mut self, challenge: &str, at: Instant) -> Option<f64> {
+        let sent_at = self.pending.remove(challenge)?;
+        let rtt_ms = at.saturating_duration_since(sent_at).as_secs_f64() * 1000.0;
+
+        if let Some(previous_ms) = self.rtt_ms {
+            self.jitter_ms += ((rtt_ms - previous_ms).abs() - self.jitter_ms) / 16.0;
+        }
+
+        self.smoothed_rtt_ms = Some(match self.smoothed_rtt_ms {
+                                        Some(smoothed_ms) => smoothed_ms * 7.0 / 8.0 + rtt_ms / 8.0,
+                                        None => rtt_ms,
+                                    });
+
+        self.rtt_ms = Some(rtt_ms);
+        self.pongs_received += 1;
+        self.push_outcome(true);
+
+        Some(rtt_ms)
+    }
+
+    /// Count pings without a pong for longer than `timeout` as lost and return how many were
+    pub fn expire_pings(&mut self, at: Instant, timeout: Duration) -> usize {
+        let before = self.pending.len();
+        self.pending
+            .retain(|_, sent_at| at.saturating_duration_since(*sent_at) < timeout);
+
+        let lost = before - self.pending.len();
+        for _ in 0..lost {
+            self.push_outcome(false);
+        }
+
+        lost
+    }
+
+    /// Share of the recent pings that were lost, between 0 and 1
+    pub fn loss(&self) -> f64 {
+        if self.outcomes.is_empty() {
+            0.0
+        } else {
+            self.outcomes.iter().filter(|answered| !**answered).count() as f64 / self.outcomes.len() as f64
+        }
+    }
+
+    /// Loss over the `pings` most recent pings and the smoothed round trip time, which the stream bitrate follows
+    pub fn link_quality(&self, pings: usize) -> LinkQuality {
+        let recent = self.outcomes.iter().rev().take(pings);
+        let (count, lost) = recent.fold((0, 0), |(count, lost), answered| {
+                                      (count + 1, lost + usize::from(!answered))
+                                  });
+
+        LinkQuality { loss:   {
+                          if count == 0 {
+                              0.0
+                          } else {
+                              lost as f64 / count as f64
+                          }
+                      },
+                      rtt_ms: { self.smoothed_rtt_ms }, }
+    }
+
+    pub fn payload_sent(&self, bytes: u64) {
+        self.bytes_sent.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn payload_received(&mut self, bytes: u64) {
+        self.bytes_received += bytes;
+    }
+
+    pub fn set_data_channel(&mut self, stats: DataChannelStats) {
+        self.data_channel = Some(stats);
+    }
+
+    pub fn report(&self, client_id: &ClientId, socket_id: &SocketId, transport: &str) -> SocketStatsReport {
+        SocketStatsReport { client_id:       { client_id.clone() },
+                            socket_id:       { socket_id.clone() },
+                            transport:       { transport.to_owned() },
+                            rtt_ms:          { self.rtt_ms },
+                            smoothed_rtt_ms: { self.smoothed_rtt_ms },
+                            jitter_ms:       { self.jitter_ms },
+                            loss:            { self.loss() },
+                            pings_sent:      { self.pings_sent },
+                            pongs_received:  { self.pongs_received },
+                            bytes_sent:      { self.bytes_sent.load(Ordering::Relaxed) },
+                            bytes_received:  { self.bytes_received },
+                            data_channel:    { self.data_channel.clone() }, }
+    }
+
+    fn push_outcome(&mut self, answered: bool) {
+        if self.outcomes.len() >= LOSS_WINDOW {
+            self.outcomes.pop_front();
+        }
+
+        self.outcomes.push_back(answered);
+    }
+}
+
+/// Traffic of a WebRTC data channel, as counted by its socket actor
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct DataChannelStats {
+    /// Bytes handed to the data channel that it has not sent yet
+    pub buffered_bytes:    usize,
+    pub bytes_sent:        u64,
+    pub bytes_received:    u64,
+    pub messages_sent:     u64,
+    pub messages_received: u64,
+}
+
+/// Network quality of a connected socket
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct SocketStatsReport {
+    #[schema(value_type = String)]
+    pub client_id:       ClientId,
+    #[schema(value_type = String)]
+    pub socket_id:       SocketId,
+    /// `web_rtc`, `web_transport` or `web_socket`
+    pub transport:       String,
+    /// Round trip time of the last answered ping
+    pub rtt_ms:          Option<f64>,
+    pub smoothed_rtt_ms: Option<f64>,
+    /// Mean deviation between the round trip times of consecutive pings
+    pub jitter_ms:       f64,
+    /// Share of the last 100 pings that were not answered in time, between 0 and 1
+    pub loss:            f64,
+    pub pings_sent:      u64,
+    pub pongs_received:  u64,
+    /// Payload bytes of the messages sent to the socket, whichever transport carried them
+    pub bytes_sent:      u64,
+    /// Payload bytes of the messages received from the socket
+    pub bytes_received:  u64,
+    /// Only WebRTC sockets have a data channel
+    pub data_channel:    Option<DataChannelStats>,
+}
@@ -10,31 +10,79 @@ use audiocloud_api::domain::streaming::DomainServerMessage::{AnswerPeerConnectio
 use audiocloud_api::domain::streaming::PeerConnectionCreated;
 use audiocloud_api::domain::DomainError;
 use audiocloud_api::newtypes::AppTaskId;
-use audiocloud_api::{ClientId, ClientSocketId, RequestId, SecureKey, SerializableResult, TaskSecurity, Timestamped};
+use audiocloud_api::{
+    AppId, ClientId, ClientSocketId, PlayId, RequestId, SecureKey, SerializableResult, StreamingPacket, TaskSecurity,
+    Timestamped,
+};
 use sockets::{SocketActorAddr, SupervisedSocket};
 
+use crate::sockets::bitrate::BitrateController;
+use crate::sockets::ice::ice_servers_for;
+use crate::sockets::resumption::ParkedSessions;
+use crate::sockets::encryption::PacketCiphers;
+use crate::sockets::shards::PacketShards;
 use crate::sockets::web_rtc::{AddRemoteIceCandidate, SetPeerAnswer, WebRtcActor};
-use crate::sockets::{get_next_socket_id, SocketId, SocketsOpts};
-use crate::{DomainResult, ResponseMedia};
+use crate::sockets::{get_next_socket_id, DomainSocketNotification, DrainReason, SocketId, SocketsOpts};
+use crate::tasks::TaskStreamCodec;
+use crate::{DomainResult, ResponseMedia, TaskKeyScopes};
 
 use super::messages::*;
 
+mod bitrate;
+mod chat;
+mod drain;
+mod fallback;
+mod fragmentation;
 mod handle_task_events;
+mod limits;
 mod packets;
 mod receive;
+mod resumption;
 mod sockets;
+mod stats;
 mod timers;
+mod transport;
 
 pub struct SocketsSupervisor {
-    opts:     SocketsOpts,
-    clients:  HashMap<ClientId, SupervisedClient>,
-    security: HashMap<AppTaskId, TaskSecurity>,
+    opts:            SocketsOpts,
+    clients:         HashMap<ClientId, SupervisedClient>,
+    security:        HashMap<AppTaskId, TaskSecurity>,
+    key_scopes:      HashMap<AppTaskId, TaskKeyScopes>,
+    /// Set once the sockets were drained, new sockets are refused from then on
+    draining:        Option<DrainReason>,
+    /// Bitrate last requested for the Opus stream of each task, tasks streaming at the bitrate of their codec have none
+    stream_bitrates: HashMap<AppTaskId, (PlayId, u32)>,
+    /// Actors encoding the streaming packets of tasks for their sockets, on threads of their own
+    shards:          PacketShards,
+    /// Ciphers of the streams encrypted here when there are no shards
+    ciphers:         PacketCiphers,
+    /// Memberships of clients without sockets, until they resume them
+    parked:          ParkedSessions,
 }
 
 #[derive(Debug, Default)]
 pub struct SupervisedClient {
     pub sockets:     HashMap<SocketId, SupervisedSocket>,
     pub memberships: HashMap<AppTaskId, SecureKey>,
+    /// Live packets held back while cached packets of the task are replayed
+    pub resuming:    HashMap<AppTaskId, Vec<StreamingPacket>>,
+    /// Play and codec last advertised to the client for each task
+    pub codecs:      HashMap<AppTaskId, (PlayId, TaskStreamCodec)>,
+    /// Bitrate the link of the client can take for each task streaming Opus
+    pub bitrates:    HashMap<AppTaskId, BitrateController>,
+    /// Token the client resumes its memberships from a new connection with, once it attached to a task
+    pub resumption:  Option<String>,
+}
+
+impl SupervisedClient {
+    /// App of the tasks the client is attached to, which bytes not belonging to a stream are counted against. Clients
+    /// attached to tasks of several apps have none
+    pub(crate) fn app_id(&self) -> Option<&AppId> {
+        let mut apps = self.memberships.keys().map(|task_id| &task_id.app_id);
+        let first = apps.next()?;
+
+        apps.all(|app_id| app_id == first).then_some(first)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -49,26 +97,51 @@ impl Actor for SocketsSupervisor {
 
     fn started(&mut self, ctx: &mut Self::Context) {
         self.register_timers(ctx);
+        self.subscribe_task_events(ctx);
     }
 }
 
 impl SocketsSupervisor {
     pub fn new(opts: SocketsOpts) -> Self {
-        Self { opts:     { opts },
-               clients:  { Default::default() },
-               security: { Default::default() }, }
+        let shards = PacketShards::start(opts.socket_packet_shards);
+
+        Self { opts:            { opts },
+               clients:         { Default::default() },
+               security:        { Default::default() },
+               key_scopes:      { Default::default() },
+               draining:        { None },
+               stream_bitrates: { Default::default() },
+               shards:          { shards },
+               ciphers:         { Default::default() },
+               parked:          { Default::default() }, }
     }
 
     fn request_peer_connection(&mut self, request: SocketContext, ctx: &mut Context<SocketsSupervisor>) {
         let socket_id = ClientSocketId::new(request.socket_id.client_id.clone(), get_next_socket_id());
         let initiator_socket_id = request.socket_id.clone();
         let opts = self.opts.clone();
+        let ice_servers = ice_servers_for(&initiator_socket_id.client_id);
+
+        if let Some(reason) = self.draining {
+            let error =
+                DomainError::WebRTCError { error: format!("Domain is draining sockets for {}", reason.as_str()), };
+            let response = PeerConnectionResponse { request_id: { request.request_id },
+                                                    result:     { SerializableResult::Error(error) }, };
+
+            let _ = self.send_to_socket_by_id(&request.socket_id, response, request.media, ctx);
+            return;
+        }
 
-        let result = match WebRtcActor::new(socket_id.clone(), initiator_socket_id.clone(), &opts.web_rtc) {
+        let result = match WebRtcActor::new(socket_id.clone(),
+                                            initiator_socket_id.clone(),
+                                            &opts.web_rtc,
+                                            &ice_servers)
+        {
             Ok((actor, local_description)) => {
                 let socket = SupervisedSocket { actor_addr:    { SocketActorAddr::WebRtc(actor) },
                                                 init_complete: { Timestamped::new(false) },
-                                                last_pong_at:  { Instant::now() }, };
+                                                last_pong_at:  { Instant::now() },
+                                                stats:         { Default::default() }, };
 
                 self.clients
                     .entry(initiator_socket_id.client_id.clone())
@@ -76,6 +149,15 @@ impl SocketsSupervisor {
                     .sockets
                     .insert(socket_id.socket_id.clone(), socket);
 
+                // the client needs the servers, and its TURN credentials, before it answers the offer
+                let notification = DomainSocketNotification::IceServers { socket_id:   { socket_id.socket_id.clone() },
+                                                                          ice_servers: { ice_servers }, };
+                if let Err(error) =
+                    self.send_notification_to_socket_by_id(&initiator_socket_id, notification, request.media, ctx)
+                {
+                    warn!(%error, "Failed to send ICE servers");
+                }
+
                 let res = PeerConnectionCreated::Created { socket_id:          { socket_id },
                                                            remote_description: { local_description }, };
                 SerializableResult::Ok(res)
@@ -140,7 +222,7 @@ impl SocketsSupervisor {
                             .spawn(ctx);
                         None
                     }
-                    SocketActorAddr::WebSocket(_) => {
+                    SocketActorAddr::WebSocket(_) | SocketActorAddr::WebTransport(_) => {
                         warn!(%rtc_socket_id, "Socket is not a WebRTC socket, dropping message");
                         Some(DomainError::SocketNotFound { socket_id: rtc_socket_id, })
                     }
@@ -183,7 +265,7 @@ impl SocketsSupervisor {
                             .spawn(ctx);
                         None
                     }
-                    SocketActorAddr::WebSocket(_) => {
+                    SocketActorAddr::WebSocket(_) | SocketActorAddr::WebTransport(_) => {
                         warn!(%rtc_socket_id, "Socket is not a WebRTC socket, dropping message");
                         Some(SerializableResult::Error(DomainError::SocketNotFound { socket_id: rtc_socket_id, }))
                     }
@@ -201,24 +283,46 @@ impl SocketsSupervisor {
     }
 }
 
-impl Handler<RegisterWebSocket> for SocketsSupervisor {
-    type Result = DomainResult;
+impl SocketsSupervisor {
+    /// Add a socket that is connected as soon as it exists, unlike WebRTC sockets which connect after negotiation
+    fn register_connected_socket(&mut self, socket_id: ClientSocketId, actor_addr: SocketActorAddr) -> DomainResult {
+        if let Some(reason) = self.draining {
+            // the socket actor stops when registration fails, dropping the connection
+            let error = format!("Domain is draining sockets for {}", reason.as_str());
+            return Err(DomainError::BadGateway { error });
+        }
 
-    fn handle(&mut self, msg: RegisterWebSocket, ctx: &mut Self::Context) -> Self::Result {
-        let client = self.clients.entry(msg.socket_id.client_id.clone()).or_default();
-        if client.sockets.contains_key(&msg.socket_id.socket_id) {
-            return Err(DomainError::SocketExists { socket_id: msg.socket_id.clone(), });
+        let client = self.clients.entry(socket_id.client_id.clone()).or_default();
+        if client.sockets.contains_key(&socket_id.socket_id) {
+            return Err(DomainError::SocketExists { socket_id: socket_id.clone(), });
         }
 
-        client.sockets.insert(msg.socket_id.socket_id,
-                              SupervisedSocket { actor_addr:    { SocketActorAddr::WebSocket(msg.address) },
+        client.sockets.insert(socket_id.socket_id,
+                              SupervisedSocket { actor_addr:    { actor_addr },
                                                  init_complete: { Timestamped::new(true) },
-                                                 last_pong_at:  { Instant::now() }, });
+                                                 last_pong_at:  { Instant::now() },
+                                                 stats:         { Default::default() }, });
 
         Ok(())
     }
 }
 
+impl Handler<RegisterWebSocket> for SocketsSupervisor {
+    type Result = DomainResult;
+
+    fn handle(&mut self, msg: RegisterWebSocket, ctx: &mut Self::Context) -> Self::Result {
+        self.register_connected_socket(msg.socket_id, SocketActorAddr::WebSocket(msg.address))
+    }
+}
+
+impl Handler<RegisterWebTransportSocket> for SocketsSupervisor {
+    type Result = DomainResult;
+
+    fn handle(&mut self, msg: RegisterWebTransportSocket, ctx: &mut Self::Context) -> Self::Result {
+        self.register_connected_socket(msg.socket_id, SocketActorAddr::WebTransport(msg.address))
+    }
+}
+
 impl Handler<SocketConnected> for SocketsSupervisor {
     type Result = ();
 
@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use actix::Context;
+use actix_broker::BrokerIssue;
+use tracing::*;
+
+use audiocloud_api::{AppTaskId, PlayId};
+
+use crate::sockets::bitrate::{BitrateController, BITRATE_LOSS_WINDOW};
+use crate::sockets::SocketsSupervisor;
+use crate::tasks::{NotifyStreamQuality, TaskStreamCodec};
+
+/// Bitrate the clients of a play can take, and the bitrate of its codec
+struct PlayBitrate {
+    play_id: PlayId,
+    ceiling: u32,
+    bitrate: u32,
+}
+
+impl SocketsSupervisor {
+    /// Follow the link quality of the clients receiving Opus streams and ask the engines to stream at the bitrate the
+    /// weakest client of each play can take
+    pub(crate) fn adapt_stream_bitrates(&mut self, ctx: &mut Context<Self>) {
+        let now = Instant::now();
+        let opts = &self.opts.bitrate;
+
+        let links = self.clients
+                        .iter()
+                        .filter_map(|(client_id, client)| {
+                            self.best_socket(client)
+                                .map(|socket| (client_id.clone(), socket.stats.link_quality(BITRATE_LOSS_WINDOW)))
+                        })
+                        .collect::<Vec<_>>();
+
+        let mut plays = HashMap::<AppTaskId, PlayBitrate>::new();
+
+        for (client_id, link) in links {
+            let client = match self.clients.get_mut(&client_id) {
+                Some(client) => client,
+                None => continue,
+            };
+
+            client.bitrates.retain(|task_id, _| client.codecs.contains_key(task_id));
+
+            for (task_id, (play_id, codec)) in &client.codecs {
+                let ceiling = match codec {
+                    TaskStreamCodec::Opus { bitrate, .. } => *bitrate,
+                    TaskStreamCodec::Flac => continue,
+                };
+
+                let controller = client.bitrates
+                                       .entry(task_id.clone())
+                                       .or_insert_with(|| BitrateController::new(*play_id, ceiling, now));
+
+                // every play starts over at the bitrate of its codec
+                if controller.play_id() != *play_id || controller.ceiling() != ceiling {
+                    *controller = BitrateController::new(*play_id, ceiling, now);
+                }
+
+                let bitrate = controller.update(link, opts, now);
+
+                let play = plays.entry(task_id.clone())
+                                .or_insert(PlayBitrate { play_id: { *play_id },
+                                                         ceiling: { ceiling },
+                                                         bitrate: { bitrate }, });
+                if play.play_id == *play_id {
+                    play.bitrate = play.bitrate.min(bitrate);
+                }
+            }
+        }
+
+        self.stream_bitrates.retain(|task_id, _| plays.contains_key(task_id));
+
+        for (task_id, play) in plays {
+            let requested = self.stream_bitrates.get(&task_id).copied();
+            let current = match requested {
+                Some((play_id, bitrate)) if play_id == play.play_id => bitrate,
+                _ => play.ceiling,
+            };
+
+            if current == play.bitrate {
+                continue;
+            }
+
+            debug!(%task_id, play_id = %play.play_id, from = current, to = play.bitrate, "Adapting stream bitrate");
+
+            self.stream_bitrates
+                .insert(task_id.clone(), (play.play_id, play.bitrate));
+            self.issue_system_async(NotifyStreamQuality { task_id: { task_id },
+                                                          play_id: { play.play_id },
+                                                          bitrate: { play.bitrate }, });
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use actix::Context;
+use tracing::*;
+
+use audiocloud_api::{AppTaskId, ClientSocketId};
+
+use crate::sockets::{DomainSocketNotification, SocketsSupervisor};
+
+/// Longest chat message relayed, in bytes
+pub const MAX_CHAT_LEN: usize = 4096;
+
+impl SocketsSupervisor {
+    /// Relay a chat message to the other clients attached to the task, the sender has to be attached to it too
+    pub(crate) fn relay_chat(&mut self,
+                             socket_id: ClientSocketId,
+                             task_id: AppTaskId,
+                             text: String,
+                             ctx: &mut Context<Self>) {
+        let attached = self.clients
+                           .get(&socket_id.client_id)
+                           .map(|client| client.memberships.contains_key(&task_id))
+                           .unwrap_or_default();
+
+        if !attached {
+            warn!(%socket_id, %task_id, "Client is not attached to the task, dropping chat message");
+            return;
+        }
+
+        if text.len() > MAX_CHAT_LEN {
+            warn!(%socket_id, %task_id, len = text.len(), "Chat message too long, dropping");
+            return;
+        }
+
+        let recipients = self.clients
+                             .iter()
+                             .filter(|(client_id, client)| {
+                                 **client_id != socket_id.client_id && client.memberships.contains_key(&task_id)
+                             })
+                             .map(|(client_id, _)| client_id.clone())
+                             .collect::<Vec<_>>();
+
+        for client_id in recipients {
+            let notification = DomainSocketNotification::Chat { task_id: { task_id.clone() },
+                                                                from:    { socket_id.client_id.clone() },
+                                                                text:    { text.clone() }, };
+
+            if let Err(error) = self.send_notification_to_client(&client_id, notification, ctx) {
+                debug!(%error, %client_id, "Failed to relay chat message");
+            }
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+use actix::fut::LocalBoxActorFuture;
+use actix::{ActorFutureExt, Handler, WrapFuture};
+use actix_broker::BrokerIssue;
+use tracing::*;
+
+use audiocloud_api::ClientSocketId;
+
+use crate::sockets::{
+    DomainSocketNotification, DrainSockets, NotifySocketDropped, SocketDrainResult, SocketsSupervisor,
+};
+use crate::{DomainResult, ResponseMedia};
+
+impl Handler<DrainSockets> for SocketsSupervisor {
+    type Result = LocalBoxActorFuture<Self, DomainResult<SocketDrainResult>>;
+
+    fn handle(&mut self, msg: DrainSockets, ctx: &mut Self::Context) -> Self::Result {
+        let DrainSockets { reason, reconnect_url } = msg;
+        let reconnect_url = reconnect_url.or_else(|| self.opts.socket_drain_reconnect_url.clone());
+
+        info!(reason = reason.as_str(), ?reconnect_url, "Draining sockets");
+
+        self.draining = Some(reason);
+        self.flush_held_packets();
+
+        let socket_ids = self.clients
+                             .iter()
+                             .flat_map(|(client_id, client)| {
+                                 client.sockets
+                                       .keys()
+                                       .map(move |socket_id| ClientSocketId::new(client_id.clone(), socket_id.clone()))
+                             })
+                             .collect::<Vec<_>>();
+
+        for socket_id in &socket_ids {
+            let tasks = self.clients
+                            .get(&socket_id.client_id)
+                            .map(|client| client.memberships.keys().cloned().collect())
+                            .unwrap_or_default();
+
+            let notification = DomainSocketNotification::StreamEnding { reason:        { reason },
+                                                                        reconnect_url: { reconnect_url.clone() },
+                                                                        tasks:         { tasks }, };
+
+            if let Err(error) =
+                self.send_notification_to_socket_by_id(socket_id, notification, ResponseMedia::MsgPack, ctx)
+            {
+                warn!(%error, %socket_id, "Failed to tell socket that streams are ending");
+            }
+        }
+
+        let result = SocketDrainResult { clients: { self.clients.len() },
+                                         sockets: { socket_ids.len() }, };
+
+        let wait = Duration::from_millis(self.opts.socket_drain_timeout);
+
+        // the socket actors keep flushing their queues while we wait, the notification goes out before the close
+        actix::clock::sleep(wait).into_actor(self)
+                                 .map(move |_, actor, _ctx| {
+                                     actor.close_drained(socket_ids);
+                                     Ok(result)
+                                 })
+                                 .boxed_local()
+    }
+}
+
+impl SocketsSupervisor {
+    fn close_drained(&mut self, socket_ids: Vec<ClientSocketId>) {
+        let reason = match self.draining {
+            Some(reason) => format!("Domain draining for {}", reason.as_str()),
+            None => return,
+        };
+
+        for socket_id in socket_ids {
+            self.remove_socket(&socket_id);
+            self.issue_system_async(NotifySocketDropped { socket_id: { socket_id },
+                                                          reason:    { reason.clone() }, });
+        }
+    }
+}
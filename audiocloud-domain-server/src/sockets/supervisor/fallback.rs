@@ -0,0 +1,124 @@
+use std::time::Duration;
+
+use actix::{Context, Handler};
+use actix_broker::BrokerIssue;
+use once_cell::sync::Lazy;
+use opentelemetry::metrics::Counter;
+use opentelemetry::{global, KeyValue};
+use tracing::*;
+
+use audiocloud_api::ClientSocketId;
+
+use crate::sockets::{
+    DomainSocketNotification, NotifySocketDropped, NotifyWebRtcFailed, SocketsSupervisor, WebRtcFailure,
+};
+use crate::{o11y, ResponseMedia};
+
+static FALLBACKS: Lazy<Counter<u64>> = Lazy::new(|| {
+    let meter = global::meter("audiocloud.io/sockets");
+
+    meter.u64_counter("socket_web_rtc_fallbacks")
+         .with_description("Failed WebRTC sockets, by reason and whether a WebSocket took over")
+         .init()
+});
+
+impl SocketsSupervisor {
+    /// Give up on WebRTC sockets that never connected or stopped answering pings
+    pub(crate) fn fall_back_from_failed_web_rtc(&mut self, ctx: &mut Context<Self>) {
+        let stall_timeout = Duration::from_millis(self.opts.web_rtc_stall_timeout);
+        let mut failed = vec![];
+
+        for (client_id, client) in &self.clients {
+            for (socket_id, socket) in &client.sockets {
+                if !socket.actor_addr.is_web_rtc() {
+                    continue;
+                }
+
+                let reason = if socket.is_init_timed_out(self.opts.socket_init_timeout) {
+                    Some(WebRtcFailure::IceTimeout)
+                } else if *socket.init_complete.value() && socket.last_pong_at.elapsed() >= stall_timeout {
+                    Some(WebRtcFailure::Stalled)
+                } else {
+                    None
+                };
+
+                if let Some(reason) = reason {
+                    failed.push((ClientSocketId::new(client_id.clone(), socket_id.clone()), reason));
+                }
+            }
+        }
+
+        for (socket_id, reason) in failed {
+            self.fall_back_to_web_socket(&socket_id, reason, ctx);
+        }
+    }
+
+    /// Drop a failed WebRTC socket and tell the client over a WebSocket, which carries its streams from now on
+    ///
+    /// Memberships belong to the client rather than the socket, so packets of the tasks it is attached to go to the
+    /// best socket left without attaching again.
+    pub(crate) fn fall_back_to_web_socket(&mut self,
+                                          socket_id: &ClientSocketId,
+                                          reason: WebRtcFailure,
+                                          ctx: &mut Context<Self>) {
+        let drop_timeout = self.opts.socket_drop_timeout;
+        let client = match self.clients.get_mut(&socket_id.client_id) {
+            Some(client) => client,
+            None => return,
+        };
+
+        // dropping the supervised socket disconnects the WebRTC actor
+        if client.sockets.remove(&socket_id.socket_id).is_none() {
+            return;
+        }
+
+        let fallback = client.sockets
+                             .iter()
+                             .filter(|(_, socket)| socket.actor_addr.is_web_socket())
+                             .filter(|(_, socket)| *socket.init_complete.value())
+                             .find(|(_, socket)| socket.is_valid(drop_timeout))
+                             .map(|(id, _)| id.clone());
+
+        self.issue_system_async(NotifySocketDropped { socket_id: { socket_id.clone() },
+                                                      reason:    { format!("WebRTC {}", reason.as_str()) }, });
+
+        let fell_back = match fallback {
+            Some(fallback_socket_id) => {
+                info!(%socket_id, %fallback_socket_id, reason = reason.as_str(), "Falling back to WebSocket");
+
+                let fallback_id = ClientSocketId::new(socket_id.client_id.clone(), fallback_socket_id.clone());
+                let notification =
+                    DomainSocketNotification::TransportFallback { socket_id:          { socket_id.socket_id.clone() },
+                                                                  fallback_socket_id: { fallback_socket_id },
+                                                                  reason:             { reason }, };
+
+                if let Err(error) =
+                    self.send_notification_to_socket_by_id(&fallback_id, notification, ResponseMedia::MsgPack, ctx)
+                {
+                    warn!(%error, %socket_id, "Failed to send fallback notification");
+                }
+
+                true
+            }
+            None => {
+                warn!(%socket_id, reason = reason.as_str(), "WebRTC socket failed with no WebSocket to fall back to");
+                false
+            }
+        };
+
+        o11y::in_context(|ctx| {
+            FALLBACKS.add(ctx,
+                          1,
+                          &[KeyValue::new("reason", reason.as_str()),
+                            KeyValue::new("fell_back", fell_back)]);
+        });
+    }
+}
+
+impl Handler<NotifyWebRtcFailed> for SocketsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyWebRtcFailed, ctx: &mut Self::Context) -> Self::Result {
+        self.fall_back_to_web_socket(&msg.socket_id, msg.reason, ctx);
+    }
+}
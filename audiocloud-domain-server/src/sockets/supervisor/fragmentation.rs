@@ -0,0 +1,54 @@
+use actix::{ActorFutureExt, Context, ContextFutureSpawner, WrapFuture};
+use tracing::*;
+
+use audiocloud_api::ClientSocketId;
+
+use crate::sockets::{DomainSocketNotification, SetFragmentation, SocketsSupervisor};
+use crate::ResponseMedia;
+
+impl SocketsSupervisor {
+    /// Have the transport of the socket fragment large messages, and tell the client the fragment length it settled on
+    pub(crate) fn negotiate_fragmentation(&mut self,
+                                          socket_id: ClientSocketId,
+                                          max_fragment_len: usize,
+                                          media: ResponseMedia,
+                                          ctx: &mut Context<Self>) {
+        let socket = match self.clients
+                               .get(&socket_id.client_id)
+                               .and_then(|client| client.sockets.get(&socket_id.socket_id))
+        {
+            Some(socket) => socket,
+            None => {
+                warn!(%socket_id, "Socket not found, not negotiating fragmentation");
+                return;
+            }
+        };
+
+        let request = socket.actor_addr
+                            .fragmentation_recipient()
+                            .send(SetFragmentation { max_fragment_len });
+
+        request.into_actor(self)
+               .map(move |res, actor, ctx| {
+                   let max_fragment_len = match res {
+                       Ok(max_fragment_len) => max_fragment_len,
+                       Err(error) => {
+                           warn!(%error, %socket_id, "Failed to negotiate fragmentation");
+                           return;
+                       }
+                   };
+
+                   debug!(%socket_id, ?max_fragment_len, "Negotiated fragmentation");
+
+                   let negotiated_socket_id = socket_id.socket_id.clone();
+                   let notification =
+                       DomainSocketNotification::FragmentationNegotiated { socket_id:        { negotiated_socket_id },
+                                                                           max_fragment_len: { max_fragment_len }, };
+
+                   if let Err(error) = actor.send_notification_to_socket_by_id(&socket_id, notification, media, ctx) {
+                       warn!(%error, %socket_id, "Failed to tell client about fragmentation");
+                   }
+               })
+               .spawn(ctx);
+    }
+}
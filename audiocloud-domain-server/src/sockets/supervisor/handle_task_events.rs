@@ -1,7 +1,9 @@
-use actix::Handler;
+use actix::{Context, Handler};
+use actix_broker::BrokerSubscribe;
 
 use crate::sockets::SocketsSupervisor;
-use crate::tasks::{NotifyTaskDeleted, NotifyTaskSecurity};
+use crate::tasks::messages::NotifyStreamingPacket;
+use crate::tasks::{NotifyTaskDeleted, NotifyTaskKeyScopes, NotifyTaskSecurity};
 
 impl Handler<NotifyTaskDeleted> for SocketsSupervisor {
     type Result = ();
@@ -11,6 +13,9 @@ impl Handler<NotifyTaskDeleted> for SocketsSupervisor {
             clients.memberships.remove(&msg.task_id);
         }
 
+        self.security.remove(&msg.task_id);
+        self.key_scopes.remove(&msg.task_id);
+
         self.prune_unlinked_access();
     }
 }
@@ -20,5 +25,23 @@ impl Handler<NotifyTaskSecurity> for SocketsSupervisor {
 
     fn handle(&mut self, msg: NotifyTaskSecurity, ctx: &mut Self::Context) -> Self::Result {
         self.security.insert(msg.task_id.clone(), msg.security.clone());
+        self.prune_unlinked_access();
+    }
+}
+
+impl Handler<NotifyTaskKeyScopes> for SocketsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskKeyScopes, ctx: &mut Self::Context) -> Self::Result {
+        self.key_scopes.insert(msg.task_id, msg.scopes);
+    }
+}
+
+impl SocketsSupervisor {
+    pub(crate) fn subscribe_task_events(&self, ctx: &mut Context<Self>) {
+        self.subscribe_system_async::<NotifyTaskDeleted>(ctx);
+        self.subscribe_system_async::<NotifyTaskSecurity>(ctx);
+        self.subscribe_system_async::<NotifyTaskKeyScopes>(ctx);
+        self.subscribe_system_async::<NotifyStreamingPacket>(ctx);
     }
 }
@@ -0,0 +1,108 @@
+use actix::Context;
+use actix_broker::BrokerIssue;
+use tracing::*;
+
+use audiocloud_api::{AppTaskId, ClientId, ClientSocketId, SecureKey};
+
+use crate::sockets::{
+    DomainSocketNotification, NotifySocketDropped, SocketLimitPolicy, SocketLimitScope, SocketsSupervisor,
+};
+use crate::ResponseMedia;
+
+impl SocketsSupervisor {
+    /// Make room for a client attaching to a task with `secure_key`, within the limits of sockets per secure key and
+    /// per task
+    ///
+    /// Depending on the policy, the oldest sockets of other clients attached to the task are dropped, or the client is
+    /// refused with the limit it would exceed.
+    pub(crate) fn enforce_socket_limits(&mut self,
+                                        client_id: &ClientId,
+                                        task_id: &AppTaskId,
+                                        secure_key: &SecureKey,
+                                        ctx: &mut Context<Self>)
+                                        -> Result<(), (SocketLimitScope, usize)> {
+        let limits = [(SocketLimitScope::SecureKey, self.opts.socket_max_per_secure_key),
+                      (SocketLimitScope::Task, self.opts.socket_max_per_task)];
+
+        let incoming = self.clients
+                           .get(client_id)
+                           .map(|client| client.sockets.len())
+                           .unwrap_or_default()
+                           .max(1);
+
+        for (scope, max_sockets) in limits {
+            let max_sockets = match max_sockets {
+                Some(max_sockets) => max_sockets,
+                None => continue,
+            };
+
+            loop {
+                let attached = self.attached_sockets(client_id, task_id, secure_key, scope);
+                if attached.len() + incoming <= max_sockets {
+                    break;
+                }
+
+                match (self.opts.socket_limit_policy, attached.first()) {
+                    (SocketLimitPolicy::EvictOldest, Some(oldest)) => {
+                        let oldest = oldest.clone();
+                        self.evict_socket(&oldest, task_id, scope, max_sockets, ctx);
+                    }
+                    _ => return Err((scope, max_sockets)),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sockets of other clients attached to the task, with the same secure key if the scope is the key, oldest first
+    fn attached_sockets(&self,
+                        client_id: &ClientId,
+                        task_id: &AppTaskId,
+                        secure_key: &SecureKey,
+                        scope: SocketLimitScope)
+                        -> Vec<ClientSocketId> {
+        let mut rv = self.clients
+                         .iter()
+                         .filter(|(other_id, _)| *other_id != client_id)
+                         .filter(|(_, client)| match (client.memberships.get(task_id), scope) {
+                             (Some(other_key), SocketLimitScope::SecureKey) => other_key == secure_key,
+                             (Some(_), SocketLimitScope::Task) => true,
+                             (None, _) => false,
+                         })
+                         .flat_map(|(other_id, client)| {
+                             client.sockets.iter().map(move |(socket_id, socket)| {
+                                                      (socket.init_complete.elapsed(),
+                                                       ClientSocketId::new(other_id.clone(), socket_id.clone()))
+                                                  })
+                         })
+                         .collect::<Vec<_>>();
+
+        rv.sort_by(|(a, _), (b, _)| b.cmp(a));
+        rv.into_iter().map(|(_, socket_id)| socket_id).collect()
+    }
+
+    fn evict_socket(&mut self,
+                    socket_id: &ClientSocketId,
+                    task_id: &AppTaskId,
+                    scope: SocketLimitScope,
+                    max_sockets: usize,
+                    ctx: &mut Context<Self>) {
+        info!(%socket_id, %task_id, ?scope, max_sockets, "Evicting socket over the socket limit");
+
+        let notification = DomainSocketNotification::SocketLimitReached { task_id:     { task_id.clone() },
+                                                                          scope:       { scope },
+                                                                          max_sockets: { max_sockets },
+                                                                          evicted:     { true }, };
+
+        if let Err(error) = self.send_notification_to_socket_by_id(socket_id, notification, ResponseMedia::MsgPack, ctx)
+        {
+            warn!(%error, %socket_id, "Failed to tell evicted socket about the socket limit");
+        }
+
+        // dropping the supervised socket disconnects its actor, after the notification queued before
+        self.remove_socket(socket_id);
+        self.issue_system_async(NotifySocketDropped { socket_id: { socket_id.clone() },
+                                                      reason:    { "Socket limit reached".to_string() }, });
+    }
+}
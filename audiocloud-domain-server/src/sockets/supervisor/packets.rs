@@ -1,13 +1,27 @@
-use actix::Handler;
-
+use actix::{ActorFutureExt, Context, ContextFutureSpawner, Handler, WrapFuture};
+use anyhow::anyhow;
+use once_cell::sync::Lazy;
+use opentelemetry::metrics::Counter;
+use opentelemetry::{global, KeyValue};
 use tracing::*;
 
-use audiocloud_api::domain::streaming::DomainServerMessage;
-use audiocloud_api::{AppTaskId, TaskEvent, TaskPermissions};
+use audiocloud_api::{AppTaskId, ClientSocketId, PlayId, SecureKey, StreamingPacket, TaskPermissions};
+
+use crate::sockets::qos::QosClass;
+use crate::sockets::shards::{fan_out_packet, replay_packets, FanOutPacket, FanOutTarget, ReplayPackets};
+use crate::sockets::supervisor::{SupervisedClient, SupervisedSocket};
+use crate::sockets::{DomainSocketNotification, NotifyStreamDegraded, SocketsSupervisor};
+use crate::tasks::messages::{ListRecordedPackets, ListStreamPackets, ListStreamPacketsAfter, NotifyStreamingPacket};
+use crate::tasks::{get_stream_recorder, get_tasks_supervisor, TaskLatencyProfile};
+use crate::{o11y, DomainSecurity, ResponseMedia, SecureKeyScope};
 
-use crate::sockets::supervisor::SupervisedClient;
-use crate::sockets::SocketsSupervisor;
-use crate::tasks::messages::NotifyStreamingPacket;
+static PACKETS_SKIPPED: Lazy<Counter<u64>> = Lazy::new(|| {
+    let meter = global::meter("audiocloud.io/sockets");
+
+    meter.u64_counter("socket_stream_packets_skipped")
+         .with_description("Streaming packets dropped from the queues of clients that could not keep up, by transport")
+         .init()
+});
 
 impl Handler<NotifyStreamingPacket> for SocketsSupervisor {
     type Result = ();
@@ -16,20 +30,367 @@ impl Handler<NotifyStreamingPacket> for SocketsSupervisor {
     fn handle(&mut self, msg: NotifyStreamingPacket, ctx: &mut Self::Context) -> Self::Result {
         // TODO: at some point we may want a lookup from app tasks to clients
 
+        for client in self.clients.values_mut() {
+            if let Some(held) = client.resuming.get_mut(&msg.task_id) {
+                held.push(msg.packet.clone());
+            }
+        }
+
+        let advertised = (msg.packet.play_id, msg.codec);
+        let mut advertise = vec![];
+        let mut targets = vec![];
+
         for (client_id, client) in &self.clients {
+            if client.resuming.contains_key(&msg.task_id) {
+                continue;
+            }
+
             if self.client_can_on_task(client, &msg.task_id, TaskPermissions::can_audio) {
-                let event = TaskEvent::StreamingPacket { packet: msg.packet.clone(), };
-                let msg = DomainServerMessage::TaskEvent { task_id: { msg.task_id.clone() },
-                                                           event:   { event }, };
-                if let Err(error) = self.send_to_client(client_id, msg, ctx) {
-                    warn!(%error, %client_id, "Failed to send streaming packet to client");
+                if client.codecs.get(&msg.task_id) != Some(&advertised) {
+                    let notification = DomainSocketNotification::StreamCodec { task_id: { msg.task_id.clone() },
+                                                                               play_id: { msg.packet.play_id },
+                                                                               codec:   { msg.codec }, };
+
+                    match self.send_notification_to_client(client_id, notification, ctx) {
+                        Ok(()) => advertise.push(client_id.clone()),
+                        Err(error) => warn!(%error, %client_id, "Failed to advertise stream codec to client"),
+                    }
+                }
+
+                match self.best_socket(client) {
+                    Some(socket) => targets.push(self.fan_out_target(client, &msg.task_id, socket)),
+                    None => warn!(%client_id, "No valid socket to send streaming packet to client"),
                 }
             }
         }
+
+        let class = match msg.latency_profile {
+            TaskLatencyProfile::Stable => QosClass::Audio,
+            TaskLatencyProfile::LowLatency => QosClass::Realtime,
+        };
+
+        if !targets.is_empty() {
+            let fan_out = FanOutPacket { task_id:       { msg.task_id.clone() },
+                                         packet:        { msg.packet.clone() },
+                                         class:         { class },
+                                         targets:       { targets },
+                                         notifications: { packet_notifications(&msg) }, };
+
+            match self.shards.shard_for(&msg.task_id) {
+                Some(shard) => shard.do_send(fan_out),
+                None => fan_out_packet(fan_out, &mut self.ciphers),
+            }
+        }
+
+        for client_id in advertise {
+            if let Some(client) = self.clients.get_mut(&client_id) {
+                client.codecs.insert(msg.task_id.clone(), advertised);
+            }
+        }
     }
 }
 
+impl Handler<NotifyStreamDegraded> for SocketsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyStreamDegraded, ctx: &mut Self::Context) -> Self::Result {
+        let transport = match self.clients
+                                  .get(&msg.socket_id.client_id)
+                                  .and_then(|client| client.sockets.get(&msg.socket_id.socket_id))
+        {
+            Some(socket) => socket.transport(),
+            None => return,
+        };
+
+        debug!(socket_id = %msg.socket_id, task_id = %msg.task_id, skipped = msg.skipped, "Stream degraded");
+
+        o11y::in_context(|ctx| {
+            PACKETS_SKIPPED.add(ctx, msg.skipped, &[KeyValue::new("transport", transport)]);
+        });
+
+        let notification = DomainSocketNotification::StreamDegraded { task_id: { msg.task_id },
+                                                                      skipped: { msg.skipped }, };
+
+        if let Err(error) =
+            self.send_notification_to_socket_by_id(&msg.socket_id, notification, ResponseMedia::MsgPack, ctx)
+        {
+            warn!(%error, socket_id = %msg.socket_id, "Failed to send stream degraded notification");
+        }
+    }
+}
+
+/// Most packets a client may ask to be retransmitted with one NACK
+const MAX_NACK_PACKETS: usize = 256;
+
 impl SocketsSupervisor {
+    /// Replay cached packets of a stream the client missed while its socket was reconnecting
+    ///
+    /// Live packets of the task are held back for the client until the replay is queued, so the client receives the
+    /// stream in order. If the packets are no longer cached the client resumes with live packets and sees the gap in
+    /// the serials.
+    pub(crate) fn resume_stream(&mut self,
+                                socket_id: ClientSocketId,
+                                task_id: AppTaskId,
+                                play_id: PlayId,
+                                last_serial: u64,
+                                media: ResponseMedia,
+                                ctx: &mut Context<Self>) {
+        let secure_key = match self.audio_key_for_client(&socket_id, &task_id) {
+            Some(secure_key) => secure_key,
+            None => {
+                warn!(%socket_id, %task_id, "Client not attached to task with audio access, not resuming stream");
+                return;
+            }
+        };
+
+        if let Some(client) = self.clients.get_mut(&socket_id.client_id) {
+            client.resuming.entry(task_id.clone()).or_default();
+        }
+
+        let list = ListStreamPacketsAfter { task_id:  { task_id.clone() },
+                                            play_id:  { play_id },
+                                            serial:   { last_serial },
+                                            security: { DomainSecurity::SecureKey(secure_key) }, };
+
+        get_tasks_supervisor().send(list)
+                              .into_actor(self)
+                              .map(move |res, actor, _ctx| {
+                                  let packets = match res {
+                                      Ok(Ok(packets)) => packets,
+                                      Ok(Err(error)) => {
+                                          debug!(%error, %socket_id, %task_id, "Could not replay packets");
+                                          vec![]
+                                      }
+                                      Err(error) => {
+                                          warn!(%error, %socket_id, %task_id, "Failed to list cached packets");
+                                          vec![]
+                                      }
+                                  };
+
+                                  actor.finish_resume(&socket_id, &task_id, &play_id, packets, media);
+                              })
+                              .spawn(ctx);
+    }
+
+    /// Replay the recorded packets of a play to a client joining late or rewinding, before live packets continue
+    ///
+    /// Like resuming, live packets of the task are held back until the replay is queued. If the play is not recorded
+    /// the client continues with live packets only.
+    pub(crate) fn replay_stream(&mut self,
+                                socket_id: ClientSocketId,
+                                task_id: AppTaskId,
+                                play_id: PlayId,
+                                seconds_back: Option<f64>,
+                                media: ResponseMedia,
+                                ctx: &mut Context<Self>) {
+        if self.audio_key_for_client(&socket_id, &task_id).is_none() {
+            warn!(%socket_id, %task_id, "Client not attached to task with audio access, not replaying stream");
+            return;
+        }
+
+        let recorder = match get_stream_recorder() {
+            Some(recorder) => recorder,
+            None => {
+                debug!(%socket_id, %task_id, "Streams are not recorded, not replaying stream");
+                return;
+            }
+        };
+
+        if let Some(client) = self.clients.get_mut(&socket_id.client_id) {
+            client.resuming.entry(task_id.clone()).or_default();
+        }
+
+        let list = ListRecordedPackets { task_id:      { task_id.clone() },
+                                         play_id:      { play_id },
+                                         seconds_back: { seconds_back }, };
+
+        recorder.send(list)
+                .into_actor(self)
+                .map(move |res, actor, _ctx| {
+                    let packets = match res {
+                        Ok(Ok(packets)) => packets,
+                        Ok(Err(error)) => {
+                            debug!(%error, %socket_id, %task_id, "Could not replay recorded packets");
+                            vec![]
+                        }
+                        Err(error) => {
+                            warn!(%error, %socket_id, %task_id, "Failed to list recorded packets");
+                            vec![]
+                        }
+                    };
+
+                    actor.finish_resume(&socket_id, &task_id, &play_id, packets, media);
+                })
+                .spawn(ctx);
+    }
+
+    /// Retransmit packets the client reported missing, those no longer cached are skipped
+    pub(crate) fn retransmit_packets(&mut self,
+                                     socket_id: ClientSocketId,
+                                     task_id: AppTaskId,
+                                     play_id: PlayId,
+                                     serials: Vec<u64>,
+                                     media: ResponseMedia,
+                                     ctx: &mut Context<Self>) {
+        let secure_key = match self.audio_key_for_client(&socket_id, &task_id) {
+            Some(secure_key) => secure_key,
+            None => {
+                warn!(%socket_id, %task_id, "Client not attached to task with audio access, ignoring NACK");
+                return;
+            }
+        };
+
+        if serials.len() > MAX_NACK_PACKETS {
+            debug!(%socket_id, %task_id, count = serials.len(), "NACK too long, retransmitting the oldest packets");
+        }
+
+        let list = ListStreamPackets { task_id:  { task_id.clone() },
+                                       play_id:  { play_id },
+                                       serials:  { Self::nack_serials(serials) },
+                                       security: { DomainSecurity::SecureKey(secure_key) }, };
+
+        get_tasks_supervisor().send(list)
+                              .into_actor(self)
+                              .map(move |res, actor, _ctx| {
+                                  let packets = match res {
+                                      Ok(Ok(packets)) => packets,
+                                      Ok(Err(error)) => {
+                                          debug!(%error, %socket_id, %task_id, "Could not retransmit packets");
+                                          return;
+                                      }
+                                      Err(error) => {
+                                          warn!(%error, %socket_id, %task_id, "Failed to list cached packets");
+                                          return;
+                                      }
+                                  };
+
+                                  trace!(%socket_id, %task_id, count = packets.len(), "Retransmitting packets");
+
+                                  if let Err(error) = actor.replay_packets(&socket_id, &task_id, packets, media) {
+                                      warn!(%error, %socket_id, %task_id, "Failed to retransmit packets");
+                                  }
+                              })
+                              .spawn(ctx);
+    }
+
+    /// Serials of a NACK to retransmit, each once and at most `MAX_NACK_PACKETS` of the oldest
+    pub(crate) fn nack_serials(mut serials: Vec<u64>) -> Vec<u64> {
+        serials.sort_unstable();
+        serials.dedup();
+        serials.truncate(MAX_NACK_PACKETS);
+        serials
+    }
+
+    /// Secure key the client attached to the task with, if it gives access to the audio
+    fn audio_key_for_client(&self, socket_id: &ClientSocketId, task_id: &AppTaskId) -> Option<SecureKey> {
+        match self.clients.get(&socket_id.client_id) {
+            Some(client) if self.client_can_on_task(client, task_id, TaskPermissions::can_audio) => {
+                client.memberships.get(task_id).cloned()
+            }
+            _ => None,
+        }
+    }
+
+    fn finish_resume(&mut self,
+                     socket_id: &ClientSocketId,
+                     task_id: &AppTaskId,
+                     play_id: &PlayId,
+                     mut packets: Vec<StreamingPacket>,
+                     media: ResponseMedia) {
+        let held = self.clients
+                       .get_mut(&socket_id.client_id)
+                       .and_then(|client| client.resuming.remove(task_id))
+                       .unwrap_or_default();
+
+        // live packets that arrived while listing may be in the cache already
+        let replayed_up_to = packets.last().map(|packet| packet.serial);
+        packets.extend(held.into_iter().filter(|packet| {
+                                           &packet.play_id != play_id
+                                           || replayed_up_to.map(|serial| packet.serial > serial).unwrap_or(true)
+                                       }));
+
+        debug!(%socket_id, %task_id, count = packets.len(), "Resuming stream");
+
+        if let Err(error) = self.replay_packets(socket_id, task_id, packets, media) {
+            warn!(%error, %socket_id, %task_id, "Failed to replay packets");
+        }
+    }
+
+    /// Hand packets to the shard of the task to encode and send to the socket, encoded here if there are no shards
+    fn replay_packets(&mut self,
+                      socket_id: &ClientSocketId,
+                      task_id: &AppTaskId,
+                      packets: Vec<StreamingPacket>,
+                      media: ResponseMedia)
+                      -> anyhow::Result<()> {
+        let client = self.clients
+                         .get(&socket_id.client_id)
+                         .ok_or_else(|| anyhow!("Client {} not found", socket_id.client_id))?;
+
+        let socket = client.sockets
+                           .get(&socket_id.socket_id)
+                           .ok_or_else(|| anyhow!("Socket {socket_id} not found"))?;
+
+        let target = self.fan_out_target(client, task_id, socket);
+
+        self.send_replay(ReplayPackets { task_id: { task_id.clone() },
+                                         packets: { packets },
+                                         media:   { media },
+                                         target:  { target }, });
+
+        Ok(())
+    }
+
+    fn send_replay(&mut self, replay: ReplayPackets) {
+        match self.shards.shard_for(&replay.task_id) {
+            Some(shard) => shard.do_send(replay),
+            None => replay_packets(replay, &mut self.ciphers),
+        }
+    }
+
+    /// Send the live packets held back for clients that are still resuming, so they are not lost when sockets close
+    pub(crate) fn flush_held_packets(&mut self) {
+        let held = self.clients
+                       .iter_mut()
+                       .flat_map(|(client_id, client)| {
+                           client.resuming
+                                 .drain()
+                                 .map(move |(task_id, packets)| (client_id.clone(), task_id, packets))
+                       })
+                       .collect::<Vec<_>>();
+
+        for (client_id, task_id, packets) in held {
+            let target =
+                self.clients.get(&client_id).and_then(|client| {
+                                                self.best_socket(client)
+                                                    .map(|socket| self.fan_out_target(client, &task_id, socket))
+                                            });
+
+            match target {
+                Some(target) => self.send_replay(ReplayPackets { task_id: { task_id },
+                                                                 packets: { packets },
+                                                                 media:   { ResponseMedia::MsgPack },
+                                                                 target:  { target }, }),
+                None => warn!(%client_id, %task_id, "No valid socket to send held streaming packets to client"),
+            }
+        }
+    }
+
+    /// Socket a packet goes out on, with the key to encrypt it with if packets are encrypted
+    fn fan_out_target(&self,
+                      client: &SupervisedClient,
+                      task_id: &AppTaskId,
+                      socket: &SupervisedSocket)
+                      -> FanOutTarget {
+        let secure_key = client.memberships
+                               .get(task_id)
+                               .filter(|_| self.opts.socket_packet_encryption)
+                               .cloned();
+
+        FanOutTarget { socket:     { socket.actor_addr.recipient() },
+                       secure_key: { secure_key }, }
+    }
+
     pub fn client_can_on_task(&self,
                               client: &SupervisedClient,
                               task_id: &AppTaskId,
@@ -45,4 +406,36 @@ impl SocketsSupervisor {
               })
               .unwrap_or_default()
     }
+
+    /// Scope of the key the client attached to the task with, `None` if the client is not attached
+    pub fn client_scope_on_task(&self, client: &SupervisedClient, task_id: &AppTaskId) -> Option<SecureKeyScope> {
+        client.memberships.get(task_id).map(|secure_key| {
+                                           self.key_scopes
+                                               .get(task_id)
+                                               .and_then(|scopes| scopes.get(secure_key))
+                                               .copied()
+                                               .unwrap_or(SecureKeyScope::Listen)
+                                       })
+    }
+}
+
+/// Readings the engine reported while the packet was collected, sent to the clients right after it
+fn packet_notifications(msg: &NotifyStreamingPacket) -> Vec<DomainSocketNotification> {
+    let mut notifications = vec![];
+
+    if !msg.loudness.is_empty() {
+        notifications.push(DomainSocketNotification::StreamLoudness { task_id:  { msg.task_id.clone() },
+                                                                      play_id:  { msg.packet.play_id },
+                                                                      serial:   { msg.packet.serial },
+                                                                      loudness: { msg.loudness.clone() }, });
+    }
+
+    if !msg.spectrum.is_empty() {
+        notifications.push(DomainSocketNotification::StreamSpectrum { task_id:  { msg.task_id.clone() },
+                                                                      play_id:  { msg.packet.play_id },
+                                                                      serial:   { msg.packet.serial },
+                                                                      spectrum: { msg.spectrum.clone() }, });
+    }
+
+    notifications
 }
@@ -6,19 +6,24 @@ use tracing::*;
 
 use audiocloud_api::domain::streaming::{DomainClientMessage, DomainServerMessage};
 use audiocloud_api::domain::DomainError;
-use audiocloud_api::{Codec, MsgPack};
+use audiocloud_api::{ClientSocketId, Codec, MsgPack};
 
-use crate::sockets::supervisor::SocketContext;
-use crate::sockets::{SocketReceived, SocketsSupervisor};
+use crate::audit::{self, audited, AuditEntry, AuditOrigin};
+use crate::bandwidth::{record_bandwidth, BandwidthChannel, BandwidthDirection};
+use crate::rate_limit::get_socket_rate_limiter;
+use crate::sockets::messages::{DomainSocketRequest, SocketRequest};
+use crate::sockets::supervisor::{stats, SocketContext};
+use crate::sockets::{DomainSocketNotification, SocketReceived, SocketsSupervisor};
 use crate::tasks::{get_tasks_supervisor, messages};
-use crate::{to_serializable, DomainSecurity, ResponseMedia};
+use crate::{to_serializable, DomainSecurity, ResponseMedia, SecureKeyScope};
 
 impl SocketsSupervisor {
     #[instrument(skip_all)]
     pub fn on_socket_message_received(&mut self, message: SocketReceived, ctx: &mut <Self as Actor>::Context) {
+        let bytes = message.payload_len() as u64;
+
         let (request, socket_id, use_json) = match message {
-            SocketReceived::Bytes(socket_id, bytes) => match MsgPack.deserialize::<DomainClientMessage>(bytes.as_ref())
-            {
+            SocketReceived::Bytes(socket_id, bytes) => match MsgPack.deserialize::<SocketRequest>(bytes.as_ref()) {
                 Ok(request) => (request, socket_id, false),
                 Err(error) => {
                     warn!(%error, %socket_id, "Failed to decode message, dropping socket");
@@ -38,6 +43,10 @@ impl SocketsSupervisor {
 
         trace!(?request, %socket_id, use_json, "Received");
 
+        let app_id = self.clients
+                         .get(&socket_id.client_id)
+                         .and_then(|client| client.app_id().cloned());
+
         let socket = match self.clients.get_mut(&socket_id.client_id) {
             None => {
                 warn!(%socket_id, "Received message from unknown client, dropping message");
@@ -52,28 +61,107 @@ impl SocketsSupervisor {
             },
         };
 
+        socket.stats.payload_received(bytes);
+        record_bandwidth(app_id.as_ref(),
+                         BandwidthChannel::Socket,
+                         BandwidthDirection::Received,
+                         bytes);
+
         let response_media = if use_json {
             ResponseMedia::Json
         } else {
             ResponseMedia::MsgPack
         };
 
+        if !matches!(request, SocketRequest::Api(DomainClientMessage::Pong { .. })) {
+            if let Err(retry_after) = get_socket_rate_limiter().check(&socket_id.client_id.to_string()) {
+                debug!(%socket_id, ?retry_after, "Client over socket message rate limit, dropping message");
+
+                let notification =
+                    DomainSocketNotification::RateLimited { retry_after_ms: retry_after.as_millis() as u64, };
+                if let Err(error) =
+                    self.send_notification_to_socket_by_id(&socket_id, notification, response_media, ctx)
+                {
+                    warn!(%error, %socket_id, "Failed to tell socket it is rate limited");
+                }
+                return;
+            }
+        }
+
+        let request = match request {
+            SocketRequest::Api(request) => request,
+            SocketRequest::Domain(DomainSocketRequest::ResumeStream { task_id,
+                                                                      play_id,
+                                                                      last_serial, }) => {
+                self.resume_stream(socket_id, task_id, play_id, last_serial, response_media, ctx);
+                return;
+            }
+            SocketRequest::Domain(DomainSocketRequest::ReplayStream { task_id,
+                                                                      play_id,
+                                                                      seconds_back, }) => {
+                self.replay_stream(socket_id, task_id, play_id, seconds_back, response_media, ctx);
+                return;
+            }
+            SocketRequest::Domain(DomainSocketRequest::NackPackets { task_id,
+                                                                     play_id,
+                                                                     serials, }) => {
+                self.retransmit_packets(socket_id, task_id, play_id, serials, response_media, ctx);
+                return;
+            }
+            SocketRequest::Domain(DomainSocketRequest::NegotiateFragmentation { max_fragment_len }) => {
+                self.negotiate_fragmentation(socket_id, max_fragment_len, response_media, ctx);
+                return;
+            }
+            SocketRequest::Domain(DomainSocketRequest::ResumeSession { token, streams }) => {
+                self.resume_session(socket_id, token, streams, response_media, ctx);
+                return;
+            }
+            SocketRequest::Domain(DomainSocketRequest::Seek { task_id,
+                                                              revision,
+                                                              seek, }) => {
+                self.seek_task(socket_id, task_id, revision, seek, response_media, ctx);
+                return;
+            }
+            SocketRequest::Domain(DomainSocketRequest::Chat { task_id, text }) => {
+                self.relay_chat(socket_id, task_id, text, ctx);
+                return;
+            }
+        };
+
         match request {
             DomainClientMessage::RequestModifyTaskSpec { request_id,
                                                          task_id,
                                                          modify_spec,
                                                          optional,
                                                          revision, } => {
-                // TODO: get security
-                let security = DomainSecurity::Cloud;
+                let audit = socket_audit_entry(&socket_id, "modify_task").with_task(&task_id)
+                                                                         .with_params(&modify_spec);
+
+                // sockets act with the key the client attached with, listen and transport keys can not edit the spec
+                let client = self.clients.get(&socket_id.client_id);
+                let secure_key = client.and_then(|client| client.memberships.get(&task_id)).cloned();
+                let scope = client.and_then(|client| self.client_scope_on_task(client, &task_id));
+
+                let security = match (secure_key, scope) {
+                    (Some(secure_key), Some(SecureKeyScope::Full)) => DomainSecurity::SecureKey(secure_key),
+                    _ => {
+                        let result = Err(DomainError::AuthenticationFailed);
+                        audit::record(audit.with_result(&result));
+
+                        let result = DomainServerMessage::ModifyTaskSpecResponse { request_id,
+                                                                                   result: to_serializable(result) };
+                        let _ = self.send_to_socket_by_id(&socket_id, result, response_media, ctx);
+                        return;
+                    }
+                };
+
                 let task_fut = get_tasks_supervisor().send(messages::ModifyTask { modify_spec,
                                                                                   security,
                                                                                   task_id,
                                                                                   revision,
                                                                                   optional: false });
-                task_fut.map_err(bad_gateway)
-                        .and_then(fut::ready)
-                        .into_actor(self)
+                let task_fut = audited(audit, task_fut.map_err(bad_gateway).and_then(fut::ready));
+                task_fut.into_actor(self)
                         .map(move |res, actor, ctx| {
                             let result = to_serializable(res);
                             let result = DomainServerMessage::ModifyTaskSpecResponse { request_id, result };
@@ -109,9 +197,27 @@ impl SocketsSupervisor {
             DomainClientMessage::RequestAttachToTask { request_id,
                                                        task_id,
                                                        secure_key, } => {
+                let audit = socket_audit_entry(&socket_id, "attach_to_task").with_task(&task_id);
                 let secure_key_is_valid = matches!(self.security.get(&task_id), Some(track_security) if track_security.security.contains_key(&secure_key));
 
-                let result = if secure_key_is_valid {
+                let result = if !secure_key_is_valid {
+                    Err(DomainError::AuthenticationFailed)
+                } else if let Err((scope, max_sockets)) =
+                    self.enforce_socket_limits(&socket_id.client_id, &task_id, &secure_key, ctx)
+                {
+                    // there is no error for limits, the notification tells the client why the key was refused
+                    let notification = DomainSocketNotification::SocketLimitReached { task_id:     { task_id.clone() },
+                                                                                      scope:       { scope },
+                                                                                      max_sockets: { max_sockets },
+                                                                                      evicted:     { false }, };
+                    if let Err(error) =
+                        self.send_notification_to_socket_by_id(&socket_id, notification, response_media, ctx)
+                    {
+                        warn!(%error, %socket_id, "Failed to tell socket about the socket limit");
+                    }
+
+                    Err(DomainError::AuthenticationFailed)
+                } else {
                     let socket_id = socket_id.clone();
                     self.clients
                         .entry(socket_id.client_id.clone())
@@ -120,16 +226,22 @@ impl SocketsSupervisor {
                         .insert(task_id, secure_key);
 
                     Ok(())
-                } else {
-                    Err(DomainError::AuthenticationFailed)
                 };
 
+                audit::record(audit.with_result(&result));
+
+                let attached = result.is_ok();
                 let response = DomainServerMessage::AttachToTaskResponse { request_id,
                                                                            result: to_serializable(result) };
 
                 let _ = self.send_to_socket_by_id(&socket_id, response, response_media, ctx);
+
+                if attached {
+                    self.send_resumption_token(&socket_id, response_media, ctx);
+                }
             }
             DomainClientMessage::RequestDetachFromTask { request_id, task_id } => {
+                let audit = socket_audit_entry(&socket_id, "detach_from_task").with_task(&task_id);
                 let result = match self.clients
                                        .get_mut(&socket_id.client_id)
                                        .and_then(|client| client.memberships.remove(&task_id))
@@ -138,6 +250,8 @@ impl SocketsSupervisor {
                     None => Err(DomainError::TaskNotFound { task_id }),
                 };
 
+                audit::record(audit.with_result(&result));
+
                 let response = DomainServerMessage::DetachFromTaskResponse { request_id,
                                                                              result: to_serializable(result) };
 
@@ -145,11 +259,23 @@ impl SocketsSupervisor {
             }
             DomainClientMessage::Pong { challenge, response } => {
                 socket.last_pong_at = Instant::now();
+
+                let transport = socket.transport();
+                if let Some(rtt_ms) = socket.stats.pong_received(&challenge, socket.last_pong_at) {
+                    stats::record_rtt(transport, rtt_ms);
+                }
             }
         }
     }
 }
 
-fn bad_gateway(error: MailboxError) -> DomainError {
+pub(super) fn bad_gateway(error: MailboxError) -> DomainError {
     DomainError::BadGateway { error: error.to_string(), }
 }
+
+/// Socket commands are attributed to the client, as sockets carry no credentials of their own
+pub(super) fn socket_audit_entry(socket_id: &ClientSocketId, action: &str) -> AuditEntry {
+    let mut entry = AuditEntry::anonymous(AuditOrigin::Socket, action);
+    entry.actor = format!("client:{}", socket_id.client_id);
+    entry
+}
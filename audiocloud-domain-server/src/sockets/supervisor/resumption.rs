@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use actix::Context;
+use actix_broker::BrokerIssue;
+use tracing::*;
+
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::{AppTaskId, ClientId, ClientSocketId, SecureKey};
+
+use crate::audit;
+use crate::sockets::resumption::{new_resumption_token, ParkedSession};
+use crate::sockets::{DomainSocketNotification, NotifySocketDropped, ResumedStream, SocketsSupervisor};
+use crate::{DomainResult, ResponseMedia};
+
+use super::receive::socket_audit_entry;
+
+impl SocketsSupervisor {
+    /// Send the client its resumption token, creating one if it has none yet
+    pub(crate) fn send_resumption_token(&mut self,
+                                        socket_id: &ClientSocketId,
+                                        media: ResponseMedia,
+                                        ctx: &mut Context<Self>) {
+        let ttl_ms = self.opts.socket_resumption_ttl;
+        if ttl_ms == 0 {
+            return;
+        }
+
+        let token = match self.clients.get_mut(&socket_id.client_id) {
+            Some(client) => client.resumption.get_or_insert_with(new_resumption_token).clone(),
+            None => return,
+        };
+
+        let notification = DomainSocketNotification::ResumptionToken { token, ttl_ms };
+        if let Err(error) = self.send_notification_to_socket_by_id(socket_id, notification, media, ctx) {
+            warn!(%error, %socket_id, "Failed to send resumption token");
+        }
+    }
+
+    /// Attach the client to the tasks of the session of the token and resume its streams
+    ///
+    /// Memberships whose secure key the task no longer has, or that would exceed the socket limits, are left out.
+    pub(crate) fn resume_session(&mut self,
+                                 socket_id: ClientSocketId,
+                                 token: String,
+                                 streams: Vec<ResumedStream>,
+                                 media: ResponseMedia,
+                                 ctx: &mut Context<Self>) {
+        let audit = socket_audit_entry(&socket_id, "resume_session");
+
+        let memberships = match self.draining {
+            Some(_) => None,
+            None => self.take_session(&socket_id.client_id, &token),
+        };
+
+        let memberships = match memberships {
+            Some(memberships) => memberships,
+            None => {
+                let result: DomainResult = Err(DomainError::AuthenticationFailed);
+                audit::record(audit.with_result(&result));
+
+                let reason = "Unknown or expired resumption token".to_owned();
+                let notification = DomainSocketNotification::SessionResumeFailed { reason };
+                if let Err(error) = self.send_notification_to_socket_by_id(&socket_id, notification, media, ctx) {
+                    warn!(%error, %socket_id, "Failed to tell socket the session was not resumed");
+                }
+                return;
+            }
+        };
+
+        let mut tasks = vec![];
+        for (task_id, secure_key) in memberships {
+            let key_is_valid =
+                matches!(self.security.get(&task_id), Some(security) if security.security.contains_key(&secure_key));
+
+            if !key_is_valid {
+                debug!(%socket_id, %task_id, "Secure key no longer valid, not resuming membership");
+                continue;
+            }
+
+            if let Err((scope, max_sockets)) =
+                self.enforce_socket_limits(&socket_id.client_id, &task_id, &secure_key, ctx)
+            {
+                debug!(%socket_id, %task_id, ?scope, max_sockets, "Socket limit reached, not resuming membership");
+                continue;
+            }
+
+            if let Some(client) = self.clients.get_mut(&socket_id.client_id) {
+                client.memberships.insert(task_id.clone(), secure_key);
+                tasks.push(task_id);
+            }
+        }
+
+        let result: DomainResult = Ok(());
+        audit::record(audit.with_result(&result));
+
+        // tokens are good for one resumption, the client gets a new one with the result
+        if let Some(client) = self.clients.get_mut(&socket_id.client_id) {
+            client.resumption = None;
+        }
+
+        let notification = DomainSocketNotification::SessionResumed { tasks: { tasks.clone() }, };
+        if let Err(error) = self.send_notification_to_socket_by_id(&socket_id, notification, media, ctx) {
+            warn!(%error, %socket_id, "Failed to tell socket the session was resumed");
+        }
+
+        self.send_resumption_token(&socket_id, media, ctx);
+
+        for ResumedStream { task_id,
+                            play_id,
+                            last_serial, } in streams
+        {
+            if tasks.contains(&task_id) {
+                self.resume_stream(socket_id.clone(), task_id, play_id, last_serial, media, ctx);
+            }
+        }
+    }
+
+    /// Memberships of the session of the token, taken from a live client that had it or from the parked sessions
+    ///
+    /// A live client that had the token loses its sockets, it is the same client on a connection it no longer uses.
+    fn take_session(&mut self, client_id: &ClientId, token: &str) -> Option<HashMap<AppTaskId, SecureKey>> {
+        let live = self.clients
+                       .iter()
+                       .find(|(_, client)| client.resumption.as_deref() == Some(token))
+                       .map(|(live_client_id, _)| live_client_id.clone());
+
+        match live {
+            Some(live_client_id) if &live_client_id == client_id => {
+                self.clients.get(client_id).map(|client| client.memberships.clone())
+            }
+            Some(live_client_id) => {
+                let client = self.clients.remove(&live_client_id)?;
+                for socket_id in client.sockets.keys() {
+                    let socket_id = ClientSocketId::new(live_client_id.clone(), socket_id.clone());
+                    self.issue_system_async(NotifySocketDropped { socket_id: { socket_id },
+                                                              reason:    { "Session resumed elsewhere".to_owned() }, });
+                }
+
+                Some(client.memberships)
+            }
+            None => {
+                let session = self.parked.take(token, Instant::now())?;
+                debug!(client_id = %session.client_id, "Resuming parked session");
+
+                Some(session.memberships)
+            }
+        }
+    }
+
+    /// Forget clients without sockets, parking the memberships of those that have a resumption token
+    pub(crate) fn park_disconnected_clients(&mut self) {
+        let disconnected = self.clients
+                               .iter()
+                               .filter(|(_, client)| client.sockets.is_empty())
+                               .map(|(client_id, _)| client_id.clone())
+                               .collect::<Vec<_>>();
+
+        let expires_at = Instant::now() + Duration::from_millis(self.opts.socket_resumption_ttl);
+
+        for client_id in disconnected {
+            let client = match self.clients.remove(&client_id) {
+                Some(client) => client,
+                None => continue,
+            };
+
+            if let Some(token) = client.resumption.filter(|_| !client.memberships.is_empty()) {
+                debug!(%client_id, "Parking session of disconnected client");
+                self.parked.park(token,
+                                 ParkedSession { client_id:   { client_id },
+                                                 memberships: { client.memberships },
+                                                 expires_at:  { expires_at }, });
+            }
+        }
+    }
+
+    pub(crate) fn expire_parked_sessions(&mut self) {
+        let expired = self.parked.expire(Instant::now());
+        if expired > 0 {
+            debug!(expired, parked = self.parked.len(), "Expired parked sessions");
+        }
+    }
+}
@@ -1,19 +1,25 @@
 use std::time::{Duration, Instant};
 
-use actix::{Actor, Addr, Context, ContextFutureSpawner, Handler, WrapFuture};
+use actix::{Actor, Addr, Context, Handler, Recipient};
 use anyhow::anyhow;
 use derive_more::IsVariant;
-use futures::FutureExt;
-use itertools::Itertools;
-
 use tracing::*;
 
 use audiocloud_api::domain::streaming::DomainServerMessage;
-use audiocloud_api::{ClientId, ClientSocketId, Codec, MsgPack, Timestamped};
+use audiocloud_api::{AppTaskId, ClientId, ClientSocketId, Timestamped};
 
+use crate::bandwidth::{record_bandwidth, BandwidthChannel, BandwidthDirection};
+use crate::sockets::qos::QosClass;
+use crate::sockets::serialization::encode_payload;
+use crate::sockets::stats::SocketStats;
+use crate::sockets::supervisor::SupervisedClient;
 use crate::sockets::web_rtc::WebRtcActor;
 use crate::sockets::web_sockets::WebSocketActor;
-use crate::sockets::{Disconnect, SendToClient, SocketReceived, SocketSend, SocketsSupervisor};
+use crate::sockets::web_transport::WebTransportActor;
+use crate::sockets::{
+    Disconnect, DomainSocketNotification, SendToClient, SetFragmentation, SocketPayload, SocketReceived, SocketSend,
+    SocketsSupervisor,
+};
 use crate::ResponseMedia;
 
 #[derive(Debug)]
@@ -21,15 +27,25 @@ pub struct SupervisedSocket {
     pub actor_addr:    SocketActorAddr,
     pub last_pong_at:  Instant,
     pub init_complete: Timestamped<bool>,
+    pub stats:         SocketStats,
 }
 
 impl SupervisedSocket {
     pub(crate) fn score(&self) -> usize {
         match self.actor_addr {
             SocketActorAddr::WebRtc(_) => 10,
+            SocketActorAddr::WebTransport(_) => 5,
             SocketActorAddr::WebSocket(_) => 1,
         }
     }
+
+    pub(crate) fn transport(&self) -> &'static str {
+        match self.actor_addr {
+            SocketActorAddr::WebRtc(_) => "web_rtc",
+            SocketActorAddr::WebTransport(_) => "web_transport",
+            SocketActorAddr::WebSocket(_) => "web_socket",
+        }
+    }
 }
 
 impl Drop for SupervisedSocket {
@@ -37,6 +53,7 @@ impl Drop for SupervisedSocket {
         match &self.actor_addr {
             SocketActorAddr::WebRtc(socket) => socket.do_send(Disconnect),
             SocketActorAddr::WebSocket(socket) => socket.do_send(Disconnect),
+            SocketActorAddr::WebTransport(socket) => socket.do_send(Disconnect),
         };
     }
 }
@@ -45,6 +62,25 @@ impl Drop for SupervisedSocket {
 pub enum SocketActorAddr {
     WebRtc(Addr<WebRtcActor>),
     WebSocket(Addr<WebSocketActor>),
+    WebTransport(Addr<WebTransportActor>),
+}
+
+impl SocketActorAddr {
+    pub fn recipient(&self) -> Recipient<SocketSend> {
+        match self {
+            SocketActorAddr::WebRtc(addr) => addr.clone().recipient(),
+            SocketActorAddr::WebSocket(addr) => addr.clone().recipient(),
+            SocketActorAddr::WebTransport(addr) => addr.clone().recipient(),
+        }
+    }
+
+    pub fn fragmentation_recipient(&self) -> Recipient<SetFragmentation> {
+        match self {
+            SocketActorAddr::WebRtc(addr) => addr.clone().recipient(),
+            SocketActorAddr::WebSocket(addr) => addr.clone().recipient(),
+            SocketActorAddr::WebTransport(addr) => addr.clone().recipient(),
+        }
+    }
 }
 
 impl SupervisedSocket {
@@ -60,6 +96,7 @@ impl SupervisedSocket {
             let connected = match &self.actor_addr {
                 SocketActorAddr::WebRtc(addr) => addr.connected(),
                 SocketActorAddr::WebSocket(addr) => addr.connected(),
+                SocketActorAddr::WebTransport(addr) => addr.connected(),
             };
 
             if !connected {
@@ -101,37 +138,97 @@ impl SocketsSupervisor {
             None => {}
             Some(client) => match client.sockets.get(&id.socket_id) {
                 None => warn!(%id, ?message, "Socket not found, dropping message"),
-                Some(socket) => self.send_to_socket(socket, message, media, ctx)?,
+                Some(socket) => self.send_to_socket(client, socket, message, media, ctx)?,
             },
         }
 
         Ok(())
     }
 
-    #[instrument(skip_all, err)]
     pub(crate) fn send_to_socket(&self,
+                                 client: &SupervisedClient,
                                  socket: &SupervisedSocket,
                                  message: DomainServerMessage,
                                  media: ResponseMedia,
                                  ctx: &mut Context<SocketsSupervisor>)
                                  -> anyhow::Result<()> {
-        let cmd = match media {
-            ResponseMedia::MsgPack => SocketSend::Bytes(MsgPack.serialize(&message)?.into()),
-            ResponseMedia::Json => SocketSend::Text(serde_json::to_string(&message)?),
-        };
+        let class = QosClass::classify(&message);
+        self.send_classified_to_socket(client, socket, class, message, media, ctx)
+    }
+
+    #[instrument(skip_all, err)]
+    pub(crate) fn send_classified_to_socket(&self,
+                                            client: &SupervisedClient,
+                                            socket: &SupervisedSocket,
+                                            class: QosClass,
+                                            message: DomainServerMessage,
+                                            media: ResponseMedia,
+                                            ctx: &mut Context<SocketsSupervisor>)
+                                            -> anyhow::Result<()> {
+        let stream = QosClass::stream(&message);
+        let payload = encode_payload(&message, media, "message")?;
+        self.send_payload_to_socket(client, socket, class, stream, payload, ctx);
+
+        Ok(())
+    }
+
+    /// Send one of the messages only this domain server sends, see [`DomainSocketNotification`]
+    pub(crate) fn send_notification_to_socket_by_id(&self,
+                                                    id: &ClientSocketId,
+                                                    notification: DomainSocketNotification,
+                                                    media: ResponseMedia,
+                                                    ctx: &mut Context<SocketsSupervisor>)
+                                                    -> anyhow::Result<()> {
+        match self.clients
+                  .get(&id.client_id)
+                  .and_then(|client| Some((client, client.sockets.get(&id.socket_id)?)))
+        {
+            None => warn!(%id, ?notification, "Socket not found, dropping notification"),
+            Some((client, socket)) => {
+                let class = QosClass::classify_notification(&notification);
+                let payload = encode_payload(&notification, media, "notification")?;
+                self.send_payload_to_socket(client, socket, class, None, payload, ctx);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Hand a payload to the socket actor without waiting for it, so a slow client never holds up the supervisor
+    ///
+    /// The socket actor queues the payload and drops what its client can not keep up with. Stream packets are counted
+    /// against the app of their task, everything else against the app of the client.
+    fn send_payload_to_socket(&self,
+                              client: &SupervisedClient,
+                              socket: &SupervisedSocket,
+                              class: QosClass,
+                              stream: Option<AppTaskId>,
+                              payload: SocketPayload,
+                              _ctx: &mut Context<SocketsSupervisor>) {
+        let bytes = payload.payload_len() as u64;
+        let app_id = stream.as_ref()
+                           .map(|task_id| &task_id.app_id)
+                           .or_else(|| client.app_id());
+
+        socket.stats.payload_sent(bytes);
+        record_bandwidth(app_id, BandwidthChannel::Socket, BandwidthDirection::Sent, bytes);
+
+        let cmd = SocketSend { class, stream, payload };
 
         match &socket.actor_addr {
             SocketActorAddr::WebRtc(web_rtc) => {
                 debug!(?cmd, "sending to WebRTC socket");
-                web_rtc.send(cmd).map(drop).into_actor(self).spawn(ctx);
+                web_rtc.do_send(cmd);
             }
             SocketActorAddr::WebSocket(web_socket) => {
                 debug!(?cmd, "sending to WebSocket socket");
-                web_socket.send(cmd).map(drop).into_actor(self).spawn(ctx);
+                web_socket.do_send(cmd);
+            }
+            SocketActorAddr::WebTransport(web_transport) => {
+                debug!(?cmd, "sending to WebTransport socket");
+                web_transport.do_send(cmd);
             }
         }
-
-        Ok(())
     }
 
     #[instrument(skip(self))]
@@ -146,22 +243,27 @@ impl SocketsSupervisor {
         }
     }
 
-    #[instrument(skip(self, ctx, msg), err)]
     pub(crate) fn send_to_client(&self,
                                  client_id: &ClientId,
                                  msg: DomainServerMessage,
                                  ctx: &mut Context<Self>)
                                  -> anyhow::Result<()> {
+        let class = QosClass::classify(&msg);
+        self.send_classified_to_client(client_id, class, msg, ctx)
+    }
+
+    #[instrument(skip(self, ctx, msg), err)]
+    pub(crate) fn send_classified_to_client(&self,
+                                            client_id: &ClientId,
+                                            class: QosClass,
+                                            msg: DomainServerMessage,
+                                            ctx: &mut Context<Self>)
+                                            -> anyhow::Result<()> {
         if let Some(client) = self.clients.get(client_id) {
-            let best_socket = client.sockets
-                                    .values()
-                                    .filter(|socket| *socket.init_complete.value())
-                                    .filter(|socket| socket.is_valid(self.opts.socket_drop_timeout))
-                                    .sorted_by_key(|socket| socket.score())
-                                    .next();
-
-            if let Some(socket) = best_socket {
-                if let Err(error) = self.send_to_socket(socket, msg, ResponseMedia::MsgPack, ctx) {
+            if let Some(socket) = self.best_socket(client) {
+                if let Err(error) =
+                    self.send_classified_to_socket(client, socket, class, msg, ResponseMedia::MsgPack, ctx)
+                {
                     warn!(%error, "Failed to send to client's best socket");
                 }
 
@@ -173,6 +275,32 @@ impl SocketsSupervisor {
             Err(anyhow!("Client {client_id} not found"))
         }
     }
+
+    /// Send a notification over the best socket of the client
+    pub(crate) fn send_notification_to_client(&self,
+                                              client_id: &ClientId,
+                                              notification: DomainSocketNotification,
+                                              ctx: &mut Context<Self>)
+                                              -> anyhow::Result<()> {
+        let (client, socket) = self.clients
+                                   .get(client_id)
+                                   .and_then(|client| Some((client, self.best_socket(client)?)))
+                                   .ok_or_else(|| anyhow!("No valid socket for client {client_id} found"))?;
+
+        let class = QosClass::classify_notification(&notification);
+        let payload = encode_payload(&notification, ResponseMedia::MsgPack, "notification")?;
+        self.send_payload_to_socket(client, socket, class, None, payload, ctx);
+
+        Ok(())
+    }
+
+    pub(crate) fn best_socket<'a>(&self, client: &'a SupervisedClient) -> Option<&'a SupervisedSocket> {
+        client.sockets
+              .values()
+              .filter(|socket| *socket.init_complete.value())
+              .filter(|socket| socket.is_valid(self.opts.socket_drop_timeout))
+              .max_by_key(|socket| socket.score())
+    }
 }
 
 impl Handler<SocketReceived> for SocketsSupervisor {
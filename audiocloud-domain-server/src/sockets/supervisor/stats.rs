@@ -0,0 +1,107 @@
+use std::time::{Duration, Instant};
+
+use actix::Handler;
+use once_cell::sync::Lazy;
+use opentelemetry::metrics::{Counter, Histogram};
+use opentelemetry::{global, KeyValue};
+
+use audiocloud_api::ClientSocketId;
+
+use crate::o11y;
+use crate::sockets::{ListSocketStats, NotifyDataChannelStats, SocketStatsReport, SocketsSupervisor};
+
+struct SocketInstruments {
+    rtt:        Histogram<f64>,
+    pings_sent: Counter<u64>,
+    pings_lost: Counter<u64>,
+}
+
+static INSTRUMENTS: Lazy<SocketInstruments> = Lazy::new(|| {
+    let meter = global::meter("audiocloud.io/sockets");
+
+    let rtt = meter.f64_histogram("socket_rtt_ms")
+                   .with_description("Round trip time of socket pings, by transport")
+                   .init();
+    let pings_sent = meter.u64_counter("socket_pings_sent")
+                          .with_description("Pings sent to sockets, by transport")
+                          .init();
+    let pings_lost = meter.u64_counter("socket_pings_lost")
+                          .with_description("Pings not answered within SOCKET_PING_LOSS_TIMEOUT, by transport")
+                          .init();
+
+    SocketInstruments { rtt:        { rtt },
+                        pings_sent: { pings_sent },
+                        pings_lost: { pings_lost }, }
+});
+
+pub(crate) fn record_rtt(transport: &'static str, rtt_ms: f64) {
+    o11y::in_context(|ctx| {
+        INSTRUMENTS.rtt
+                   .record(ctx, rtt_ms, &[KeyValue::new("transport", transport)]);
+    });
+}
+
+impl SocketsSupervisor {
+    /// Remember the challenge of a ping, to measure the round trip once the pong answers it
+    pub(crate) fn record_ping(&mut self, socket_id: &ClientSocketId, challenge: String) {
+        if let Some(socket) = self.clients
+                                  .get_mut(&socket_id.client_id)
+                                  .and_then(|client| client.sockets.get_mut(&socket_id.socket_id))
+        {
+            socket.stats.ping_sent(challenge, Instant::now());
+
+            let transport = socket.transport();
+            o11y::in_context(|ctx| {
+                INSTRUMENTS.pings_sent
+                           .add(ctx, 1, &[KeyValue::new("transport", transport)]);
+            });
+        }
+    }
+
+    pub(crate) fn expire_unanswered_pings(&mut self) {
+        let timeout = Duration::from_millis(self.opts.socket_ping_loss_timeout);
+        let now = Instant::now();
+
+        for client in self.clients.values_mut() {
+            for socket in client.sockets.values_mut() {
+                let lost = socket.stats.expire_pings(now, timeout);
+                if lost > 0 {
+                    let transport = socket.transport();
+                    o11y::in_context(|ctx| {
+                        INSTRUMENTS.pings_lost
+                                   .add(ctx, lost as u64, &[KeyValue::new("transport", transport)]);
+                    });
+                }
+            }
+        }
+    }
+}
+
+impl Handler<ListSocketStats> for SocketsSupervisor {
+    type Result = Vec<SocketStatsReport>;
+
+    fn handle(&mut self, _msg: ListSocketStats, _ctx: &mut Self::Context) -> Self::Result {
+        self.clients
+            .iter()
+            .flat_map(|(client_id, client)| {
+                client.sockets
+                      .iter()
+                      .filter(|(_, socket)| *socket.init_complete.value())
+                      .map(move |(socket_id, socket)| socket.stats.report(client_id, socket_id, socket.transport()))
+            })
+            .collect()
+    }
+}
+
+impl Handler<NotifyDataChannelStats> for SocketsSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyDataChannelStats, _ctx: &mut Self::Context) -> Self::Result {
+        if let Some(socket) = self.clients
+                                  .get_mut(&msg.socket_id.client_id)
+                                  .and_then(|client| client.sockets.get_mut(&msg.socket_id.socket_id))
+        {
+            socket.stats.set_data_channel(msg.stats);
+        }
+    }
+}
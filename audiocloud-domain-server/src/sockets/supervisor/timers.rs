@@ -1,26 +1,36 @@
 use std::time::Duration;
 
 use actix::{AsyncContext, Context};
+use actix_broker::BrokerIssue;
 use nanoid::nanoid;
 
 use tracing::*;
 
 use audiocloud_api::domain::streaming::DomainServerMessage;
+use audiocloud_api::ClientSocketId;
 
-use crate::sockets::SocketsSupervisor;
+use crate::sockets::{NotifySocketDropped, SocketsSupervisor};
 use crate::ResponseMedia;
 
 impl SocketsSupervisor {
     pub(crate) fn cleanup_stale_sockets(&mut self, ctx: &mut Context<Self>) {
+        self.fall_back_from_failed_web_rtc(ctx);
+
         let max_init_wait_time = chrono::Duration::milliseconds(self.opts.socket_init_timeout as i64);
 
-        for client in self.clients.values_mut() {
+        let mut dropped = vec![];
+
+        for (client_id, client) in self.clients.iter_mut() {
             client.sockets.retain(|id, socket| {
                               if socket.is_init_timed_out(self.opts.socket_init_timeout) {
                                   debug!(%id, "Supervisor cleaning up un-initialized socket");
+                                  dropped.push((ClientSocketId::new(client_id.clone(), id.clone()),
+                                                "Socket failed to initialize"));
                                   false
                               } else if !socket.is_valid(self.opts.socket_drop_timeout) {
                                   debug!(%id, "Supervisor cleaning up timed-out or disconnected socket");
+                                  dropped.push((ClientSocketId::new(client_id.clone(), id.clone()),
+                                                "Socket timed out or disconnected"));
                                   false
                               } else {
                                   true
@@ -28,11 +38,21 @@ impl SocketsSupervisor {
                           });
         }
 
+        for (socket_id, reason) in dropped {
+            self.issue_system_async(NotifySocketDropped { socket_id: { socket_id },
+                                                          reason:    { reason.to_string() }, });
+        }
+
         self.prune_unlinked_access();
+        self.expire_parked_sessions();
     }
 
     #[instrument(skip_all)]
     pub(crate) fn ping_active_sockets(&mut self, ctx: &mut Context<Self>) {
+        self.expire_unanswered_pings();
+
+        let mut pinged = vec![];
+
         for (client_id, client) in &self.clients {
             for (socket_id, socket) in &client.sockets {
                 if !socket.is_valid(self.opts.socket_drop_timeout) {
@@ -40,26 +60,63 @@ impl SocketsSupervisor {
                     continue;
                 }
 
-                if let Err(error) = self.send_to_socket(socket,
-                                                        DomainServerMessage::Ping { challenge: nanoid!() },
-                                                        ResponseMedia::MsgPack,
-                                                        ctx)
+                let challenge = nanoid!();
+
+                match self.send_to_socket(client,
+                                          socket,
+                                          DomainServerMessage::Ping { challenge: { challenge.clone() }, },
+                                          ResponseMedia::MsgPack,
+                                          ctx)
                 {
-                    warn!(%error, %socket_id, "Failed to ping socket");
+                    Ok(()) => pinged.push((ClientSocketId::new(client_id.clone(), socket_id.clone()), challenge)),
+                    Err(error) => warn!(%error, %socket_id, "Failed to ping socket"),
                 }
             }
         }
 
-        self.clients.retain(|_, clients| !clients.sockets.is_empty());
+        for (socket_id, challenge) in pinged {
+            self.record_ping(&socket_id, challenge);
+        }
+
+        self.park_disconnected_clients();
     }
 
+    /// Disconnect clients attached to a task with a secure key the task no longer has
     pub(crate) fn prune_unlinked_access(&mut self) {
-        // NOT sure we need this anymore
+        let security = &self.security;
+        let mut dropped = vec![];
+
+        for (client_id, client) in self.clients.iter_mut() {
+            let revoked = client.memberships
+                                .iter()
+                                .any(|(task_id, secure_key)| match security.get(task_id) {
+                                    Some(task_security) => !task_security.security.contains_key(secure_key),
+                                    None => false,
+                                });
+
+            if revoked {
+                debug!(%client_id, "Disconnecting client attached with a revoked secure key");
+                dropped.extend(client.sockets
+                                     .drain()
+                                     .map(|(socket_id, _)| ClientSocketId::new(client_id.clone(), socket_id)));
+                client.memberships.clear();
+            }
+        }
+
+        for socket_id in dropped {
+            self.issue_system_async(NotifySocketDropped { socket_id: { socket_id },
+                                                          reason:    { "Secure key revoked".to_string() }, });
+        }
     }
 
     pub(crate) fn register_timers(&mut self, ctx: &mut Context<Self>) {
         ctx.run_interval(Duration::from_millis(20), Self::cleanup_stale_sockets);
         ctx.run_interval(Duration::from_millis(self.opts.socket_ping_interval),
                          Self::ping_active_sockets);
+
+        if self.opts.bitrate.is_enabled() {
+            ctx.run_interval(Duration::from_millis(self.opts.socket_ping_interval),
+                             Self::adapt_stream_bitrates);
+        }
     }
 }
@@ -0,0 +1,78 @@
+use actix::{fut, ActorFutureExt, Context, ContextFutureSpawner, WrapFuture};
+use futures::TryFutureExt;
+use tracing::*;
+
+use audiocloud_api::audio_engine::TaskSought;
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::{AppTaskId, ClientSocketId, PlayId, RequestSeek};
+
+use crate::audit::{self, audited};
+use crate::sockets::{DomainSocketNotification, SocketsSupervisor};
+use crate::tasks::{get_tasks_supervisor, messages};
+use crate::{DomainResult, DomainSecurity, ResponseMedia};
+
+use super::receive::{bad_gateway, socket_audit_entry};
+
+impl SocketsSupervisor {
+    /// Seek the play of a task with the key the client attached with, the tasks supervisor checks it may transport
+    pub(crate) fn seek_task(&mut self,
+                            socket_id: ClientSocketId,
+                            task_id: AppTaskId,
+                            revision: u64,
+                            seek: RequestSeek,
+                            media: ResponseMedia,
+                            ctx: &mut Context<Self>) {
+        let audit = socket_audit_entry(&socket_id, "seek_task").with_task(&task_id)
+                                                               .with_params(&seek);
+        let play_id = seek.play_id;
+
+        let secure_key = self.clients
+                             .get(&socket_id.client_id)
+                             .and_then(|client| client.memberships.get(&task_id))
+                             .cloned();
+
+        let secure_key = match secure_key {
+            Some(secure_key) => secure_key,
+            None => {
+                let result = Err(DomainError::AuthenticationFailed);
+                audit::record(audit.with_result(&result));
+                self.send_seek_notification(&socket_id, task_id, play_id, result, media, ctx);
+                return;
+            }
+        };
+
+        let seek = messages::SeekTask { task_id:  { task_id.clone() },
+                                        seek:     { seek },
+                                        revision: { revision },
+                                        security: { DomainSecurity::SecureKey(secure_key) }, };
+
+        let task_fut = get_tasks_supervisor().send(seek)
+                                             .map_err(bad_gateway)
+                                             .and_then(fut::ready);
+
+        audited(audit, task_fut).into_actor(self)
+                                .map(move |res, actor, ctx| {
+                                    actor.send_seek_notification(&socket_id, task_id, play_id, res, media, ctx);
+                                })
+                                .spawn(ctx);
+    }
+
+    fn send_seek_notification(&mut self,
+                              socket_id: &ClientSocketId,
+                              task_id: AppTaskId,
+                              play_id: PlayId,
+                              res: DomainResult<TaskSought>,
+                              media: ResponseMedia,
+                              ctx: &mut Context<Self>) {
+        let notification = match res {
+            Ok(_) => DomainSocketNotification::Sought { task_id, play_id },
+            Err(error) => DomainSocketNotification::SeekFailed { task_id: { task_id },
+                                                                 play_id: { play_id },
+                                                                 reason:  { error.to_string() }, },
+        };
+
+        if let Err(error) = self.send_notification_to_socket_by_id(socket_id, notification, media, ctx) {
+            warn!(%error, %socket_id, "Failed to tell client about the seek");
+        }
+    }
+}
@@ -0,0 +1,591 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use chrono::{TimeZone, Utc};
+use clap::{Args, Command, FromArgMatches, ValueEnum};
+use serde_json::json;
+
+use audiocloud_api::audio_engine::CompressedAudio;
+use audiocloud_api::domain::streaming::DiffStamped;
+use audiocloud_api::{
+    AppId, AppTaskId, ClientId, ClientSocketId, Codec, MsgPack, PlayId, SecureKey, SocketId, StreamingPacket, TaskId,
+};
+
+use crate::sockets::bitrate::{BitrateController, BitrateOpts, LinkQuality, BITRATE_LOSS_WINDOW};
+use crate::sockets::encryption::{PacketCipher, PacketCiphers};
+use crate::sockets::fragmentation::{is_fragment, Fragmenter, Reassembler, FRAGMENT_HEADER_LEN};
+use crate::sockets::ice::{turn_credentials, IceServer};
+use crate::sockets::qos::{QosClass, QosOpts, QosQueues};
+use crate::sockets::resumption::{new_resumption_token, ParkedSession, ParkedSessions};
+use crate::sockets::serialization::{encode_batch, encode_payload, PARALLEL_BATCH_LEN};
+use crate::sockets::shards::shard_index;
+use crate::sockets::stats::SocketStats;
+use crate::sockets::web_transport::parse_session_path;
+use crate::sockets::{
+    DomainSocketNotification, DomainSocketRequest, DrainReason, ResumedStream, SocketLimitPolicy, SocketLimitScope,
+    SocketPayload, SocketsSupervisor, WebRtcFailure,
+};
+use crate::tasks::supervisor::TasksSupervisor;
+use crate::ResponseMedia;
+
+#[test]
+fn test_web_transport_session_path_names_client_and_socket() {
+    let id = parse_session_path("/wt/client/socket").expect("valid path");
+    assert_eq!(id.client_id.to_string(), "client");
+    assert_eq!(id.socket_id.to_string(), "socket");
+
+    assert!(parse_session_path("/ws/client/socket").is_none());
+    assert!(parse_session_path("/wt/client").is_none());
+    assert!(parse_session_path("/wt//socket").is_none());
+}
+
+#[test]
+fn test_transport_fallback_notification_names_both_sockets() {
+    let notification =
+        DomainSocketNotification::TransportFallback { socket_id:          { SocketId::new("rtc".to_owned()) },
+                                                      fallback_socket_id: { SocketId::new("ws".to_owned()) },
+                                                      reason:             { WebRtcFailure::Stalled }, };
+
+    assert_eq!(serde_json::to_value(&notification).expect("serializable"),
+               json!({"transport_fallback": {"socket_id": "rtc", "fallback_socket_id": "ws", "reason": "stalled"}}));
+}
+
+#[test]
+fn test_socket_limit_notification_tells_evicted_from_refused() {
+    let task_id = AppTaskId::new(AppId::test(), TaskId::new("monitor".to_owned()));
+    let notification = DomainSocketNotification::SocketLimitReached { task_id:     { task_id.clone() },
+                                                                      scope:       { SocketLimitScope::SecureKey },
+                                                                      max_sockets: { 4 },
+                                                                      evicted:     { true }, };
+
+    assert_eq!(serde_json::to_value(&notification).expect("serializable"),
+               json!({"socket_limit_reached": {"task_id": task_id,
+                                               "scope": "secure_key",
+                                               "max_sockets": 4,
+                                               "evicted": true}}));
+
+    assert_eq!(SocketLimitPolicy::from_str("evict-oldest", false).expect("valid policy"),
+               SocketLimitPolicy::EvictOldest);
+}
+
+#[test]
+fn test_stream_ending_notification_names_reconnect_url_and_tasks() {
+    let task_id = AppTaskId::new(AppId::test(), TaskId::new("mix".to_owned()));
+    let notification =
+        DomainSocketNotification::StreamEnding { reason:        { DrainReason::Maintenance },
+                                                 reconnect_url: { Some("wss://backup.example.com".to_owned()) },
+                                                 tasks:         { vec![task_id.clone()] }, };
+
+    assert_eq!(serde_json::to_value(&notification).expect("serializable"),
+               json!({"stream_ending": {"reason": "maintenance",
+                                        "reconnect_url": "wss://backup.example.com",
+                                        "tasks": [task_id]}}));
+}
+
+#[test]
+fn test_turn_credentials_expire_and_name_the_client() {
+    let at = Utc.timestamp_opt(1666342800, 0).unwrap();
+    let client_id = ClientId::new("client-1".to_owned());
+
+    let (username, credential) = turn_credentials("north-studio-secret", &client_id, at, Duration::from_secs(3600));
+
+    assert_eq!(username, "1666346400:client-1");
+    assert_eq!(credential, "t6nPXkR6U4eNLtoezHvk246pS20=");
+}
+
+#[test]
+fn test_ice_server_credentials_are_embedded_in_turn_urls() {
+    let server = IceServer { urls:       {
+                                 vec!["turn:turn.example.com:3478".to_owned(),
+                                      "stun:stun.example.com:3478".to_owned()]
+                             },
+                             username:   { Some("1666346400:client-1".to_owned()) },
+                             credential: { Some("t6nPXkR6U4eNLtoezHvk246pS20=".to_owned()) }, };
+
+    assert_eq!(server.rtc_urls(),
+               vec!["turn:1666346400%3Aclient-1:t6nPXkR6U4eNLtoezHvk246pS20%3D@turn.example.com:3478".to_owned(),
+                    "stun:stun.example.com:3478".to_owned()]);
+
+    assert_eq!(serde_json::to_value(&server).expect("serializable")["username"],
+               json!("1666346400:client-1"));
+}
+
+#[test]
+fn test_socket_stats_measure_rtt_jitter_and_loss() {
+    let start = Instant::now();
+    let at = |ms: u64| start + Duration::from_millis(ms);
+    let mut stats = SocketStats::default();
+
+    stats.ping_sent("a".to_owned(), at(0));
+    assert_eq!(stats.pong_received("a", at(40)), Some(40.0));
+
+    stats.ping_sent("b".to_owned(), at(2_500));
+    assert_eq!(stats.pong_received("b", at(2_580)), Some(80.0));
+    assert_eq!(stats.pong_received("b", at(2_600)), None, "pongs are counted once");
+
+    stats.ping_sent("c".to_owned(), at(5_000));
+    stats.ping_sent("d".to_owned(), at(7_500));
+    assert_eq!(stats.expire_pings(at(7_600), Duration::from_millis(2_000)), 1);
+
+    let report = stats.report(&ClientId::new("client".to_owned()),
+                              &SocketId::new("ws".to_owned()),
+                              "web_socket");
+
+    assert_eq!(report.rtt_ms, Some(80.0));
+    assert_eq!(report.smoothed_rtt_ms, Some(45.0));
+    assert_eq!(report.jitter_ms, 2.5);
+    assert_eq!(report.loss, 1.0 / 3.0);
+    assert_eq!(report.pings_sent, 4);
+    assert_eq!(report.pongs_received, 2);
+    assert_eq!(report.data_channel, None);
+}
+
+#[test]
+fn test_bitrate_drops_on_degraded_links_and_climbs_back_when_clear() {
+    let matches = BitrateOpts::augment_args(Command::new("test")).get_matches_from(["test"]);
+    let opts = BitrateOpts::from_arg_matches(&matches).expect("valid bitrate options");
+
+    let start = Instant::now();
+    let at = |secs: u64| start + Duration::from_secs(secs);
+
+    let mut stats = SocketStats::default();
+    for (serial, secs) in [0, 1, 2, 3].into_iter().enumerate() {
+        stats.ping_sent(serial.to_string(), at(secs));
+    }
+    stats.pong_received("0", at(0) + Duration::from_millis(50));
+    stats.expire_pings(at(10), Duration::from_secs(2));
+
+    let lossy = stats.link_quality(BITRATE_LOSS_WINDOW);
+    assert_eq!(lossy.loss, 0.75);
+    assert!(lossy.is_degraded(&opts));
+
+    let clear = LinkQuality { loss:   { 0.0 },
+                              rtt_ms: { Some(50.0) }, };
+    let slow = LinkQuality { loss:   { 0.0 },
+                             rtt_ms: { Some(900.0) }, };
+
+    let mut controller = BitrateController::new(PlayId::new(1), 128_000, at(0));
+    assert_eq!(controller.update(lossy, &opts, at(1)), 89_600);
+    assert_eq!(controller.update(slow, &opts, at(2)), 62_720);
+    for secs in 3..8 {
+        controller.update(lossy, &opts, at(secs));
+    }
+    assert_eq!(controller.update(lossy, &opts, at(8)),
+               24_000,
+               "never below the minimum bitrate");
+
+    assert_eq!(controller.update(clear, &opts, at(20)),
+               24_000,
+               "the link has to stay clear for a while");
+    assert_eq!(controller.update(clear, &opts, at(23)), 40_000);
+    for secs in 1..10 {
+        controller.update(clear, &opts, at(23 + secs * 15));
+    }
+    assert_eq!(controller.update(clear, &opts, at(500)),
+               128_000,
+               "never above the bitrate of the codec");
+}
+
+#[test]
+fn test_packets_of_a_task_are_always_encoded_on_the_same_shard() {
+    let task = |name: &str| AppTaskId::new(AppId::test(), TaskId::new(name.to_owned()));
+
+    assert_eq!(shard_index(&task("mix"), 0),
+               None,
+               "no shards, the supervisor encodes packets");
+    assert_eq!(shard_index(&task("mix"), 1), Some(0));
+    assert_eq!(shard_index(&task("mix"), 4), shard_index(&task("mix"), 4));
+
+    let used = (0..64).filter_map(|index| shard_index(&task(&format!("task-{index}")), 4))
+                      .collect::<std::collections::HashSet<_>>();
+    assert!(used.iter().all(|shard| *shard < 4));
+    assert_eq!(used.len(), 4, "tasks spread over all shards");
+}
+
+fn cipher_for(task: &str, key: &str) -> PacketCipher {
+    PacketCipher::derive(&AppTaskId::new(AppId::test(), TaskId::new(task.to_owned())),
+                         &SecureKey::new(key.to_owned()))
+}
+
+#[test]
+fn test_packet_cipher_round_trips_buffers_and_packets() -> anyhow::Result<()> {
+    let cipher = cipher_for("mix", "key");
+    let buffer = b"flac frames".as_slice();
+
+    let encrypted = cipher.encrypt(buffer)?;
+    assert_eq!(encrypted.len(), 12 + buffer.len() + 16, "nonce, ciphertext and tag");
+    assert_ne!(&encrypted[12..12 + buffer.len()], buffer);
+    assert_eq!(cipher.decrypt(&encrypted)?, Bytes::from_static(buffer));
+    assert_eq!(cipher_for("mix", "key").decrypt(&encrypted)?,
+               Bytes::from_static(buffer),
+               "clients holding the secure key derive the same key");
+    assert_ne!(cipher.encrypt(buffer)?,
+               encrypted,
+               "every buffer has a nonce of its own");
+
+    let mut packet = StreamingPacket::default();
+    for (stream_pos, audio) in [b"first".as_slice(), b"second".as_slice()].into_iter().enumerate() {
+        let audio = CompressedAudio { play_id:      { PlayId::new(1) },
+                                      timeline_pos: { stream_pos as f64 },
+                                      stream_pos:   { stream_pos as u64 },
+                                      buffer:       { Bytes::from_static(audio) },
+                                      num_samples:  { 1 },
+                                      last:         { false }, };
+        packet.audio.push(DiffStamped::new(packet.created_at, audio));
+    }
+
+    let mut encrypted = cipher.encrypt_packet(&packet)?;
+    let decrypted = encrypted.audio
+                             .iter_mut()
+                             .map(|frame| cipher.decrypt(&frame.value_mut().buffer))
+                             .collect::<anyhow::Result<Vec<_>>>()?;
+    assert_eq!(decrypted,
+               vec![Bytes::from_static(b"first"), Bytes::from_static(b"second")]);
+
+    Ok(())
+}
+
+#[test]
+fn test_packet_cipher_rejects_tampered_and_foreign_buffers() -> anyhow::Result<()> {
+    let cipher = cipher_for("mix", "key");
+    let encrypted = cipher.encrypt(b"flac frames")?;
+
+    for index in [0, 12, encrypted.len() - 1] {
+        let mut tampered = encrypted.to_vec();
+        tampered[index] ^= 1;
+        assert!(cipher.decrypt(&tampered).is_err(), "byte {index} flipped");
+    }
+
+    assert!(cipher.decrypt(&encrypted[..encrypted.len() - 1]).is_err(),
+            "truncated tag");
+    assert!(cipher.decrypt(&encrypted[..8]).is_err(), "shorter than the nonce");
+    assert!(cipher_for("mix", "other key").decrypt(&encrypted).is_err());
+    assert!(cipher_for("other task", "key").decrypt(&encrypted).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_packet_ciphers_are_derived_once_per_stream_and_forgotten_when_idle() -> anyhow::Result<()> {
+    let task_id = AppTaskId::new(AppId::test(), TaskId::new("mix".to_owned()));
+    let key = SecureKey::new("key".to_owned());
+    let other_key = SecureKey::new("other key".to_owned());
+    let now = Instant::now();
+    let mut ciphers = PacketCiphers::default();
+
+    let encrypted = ciphers.get_at(&task_id, &key, now).encrypt(b"first")?;
+    assert_eq!(ciphers.get_at(&task_id, &key, now + Duration::from_secs(1))
+                      .decrypt(&encrypted)?,
+               Bytes::from_static(b"first"));
+    assert_eq!(ciphers.stream_count(), 1);
+
+    ciphers.get_at(&task_id, &other_key, now + Duration::from_secs(30));
+    assert_eq!(ciphers.stream_count(), 2);
+
+    ciphers.get_at(&task_id, &other_key, now + Duration::from_secs(85));
+    assert_eq!(ciphers.stream_count(),
+               1,
+               "stream of the first key idle for over a minute");
+
+    Ok(())
+}
+
+fn qos_queues(args: &[&str]) -> QosQueues {
+    let matches = QosOpts::augment_args(Command::new("test")).get_matches_from(args);
+    QosQueues::new(QosOpts::from_arg_matches(&matches).expect("valid QoS options"))
+}
+
+fn payload(serial: u8) -> SocketPayload {
+    SocketPayload::Bytes(Bytes::from(vec![serial]))
+}
+
+fn popped(queues: &mut QosQueues) -> Vec<(QosClass, Vec<u8>)> {
+    std::iter::from_fn(|| queues.pop_classified()).map(|(class, payload)| match payload {
+                                                      SocketPayload::Bytes(bytes) => (class, bytes.to_vec()),
+                                                      SocketPayload::Text(text) => (class, text.into_bytes()),
+                                                  })
+                                                  .collect()
+}
+
+#[test]
+fn test_qos_queues_skip_oldest_audio_per_stream_and_keep_state() {
+    let mut queues = qos_queues(&["test", "--socket-qos-audio-queue", "2", "--socket-qos-state-queue", "1"]);
+    let busy = AppTaskId::new(AppId::test(), TaskId::new("busy".to_owned()));
+    let quiet = AppTaskId::new(AppId::test(), TaskId::new("quiet".to_owned()));
+
+    queues.push(QosClass::Audio, Some(quiet.clone()), payload(10));
+    for serial in 1..=4 {
+        queues.push(QosClass::Audio, Some(busy.clone()), payload(serial));
+    }
+    queues.push(QosClass::State, None, payload(20));
+    queues.push(QosClass::State, None, payload(21));
+
+    assert_eq!(popped(&mut queues),
+               vec![(QosClass::State, vec![20]),
+                    (QosClass::State, vec![21]),
+                    (QosClass::Audio, vec![10]),
+                    (QosClass::Audio, vec![3]),
+                    (QosClass::Audio, vec![4])]);
+
+    let start = Instant::now();
+    assert_eq!(queues.take_degraded(start), vec![(busy.clone(), 2)]);
+
+    queues.push(QosClass::Audio, Some(busy.clone()), payload(5));
+    queues.push(QosClass::Audio, Some(busy.clone()), payload(6));
+    queues.push(QosClass::Audio, Some(busy.clone()), payload(7));
+    assert_eq!(queues.take_degraded(start + Duration::from_millis(500)),
+               vec![],
+               "clients are not told more than once a second");
+    assert_eq!(queues.take_degraded(start + Duration::from_secs(1)), vec![(busy, 1)]);
+}
+
+#[test]
+fn test_slow_web_socket_client_is_told_its_stream_degraded() {
+    let mut queues = qos_queues(&["test", "--socket-qos-audio-queue", "4"]);
+    let task_id = AppTaskId::new(AppId::test(), TaskId::new("slow".to_owned()));
+    let socket_id = ClientSocketId::new(ClientId::new("client".to_owned()), SocketId::new("ws".to_owned()));
+    let start = Instant::now();
+
+    // the engine sends three packets every tick, the connection only takes two bytes
+    let mut received = vec![];
+    for tick in 0..4u8 {
+        for packet in 0..3 {
+            queues.push(QosClass::Audio, Some(task_id.clone()), payload(tick * 3 + packet));
+        }
+
+        received.extend(queues.pop_batch(2));
+    }
+
+    assert_eq!(received.len(), 8);
+
+    let reports = queues.degraded_reports(&socket_id, start);
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].socket_id, socket_id);
+    assert_eq!(reports[0].task_id, task_id);
+    assert_eq!(reports[0].skipped, 2);
+}
+
+#[test]
+fn test_web_socket_flush_takes_at_least_one_message() {
+    let mut queues = qos_queues(&["test"]);
+    queues.push(QosClass::State, None, SocketPayload::Bytes(Bytes::from(vec![0; 16])));
+    queues.push(QosClass::State, None, payload(1));
+
+    assert_eq!(queues.pop_batch(8).len(),
+               1,
+               "a message over the budget still goes out on its own");
+    assert_eq!(queues.pop_batch(8).len(), 1);
+    assert!(queues.pop_batch(8).is_empty());
+}
+
+#[test]
+fn test_readings_and_chat_have_queues_of_their_own() {
+    let task_id = AppTaskId::new(AppId::test(), TaskId::new("metered".to_owned()));
+    let loudness = DomainSocketNotification::StreamLoudness { task_id:  { task_id.clone() },
+                                                              play_id:  { PlayId::new(1) },
+                                                              serial:   { 3 },
+                                                              loudness: { Default::default() }, };
+    let chat = DomainSocketNotification::Chat { task_id: { task_id.clone() },
+                                                from:    { ClientId::new("client".to_owned()) },
+                                                text:    { "take two".to_owned() }, };
+
+    assert_eq!(QosClass::classify_notification(&loudness), QosClass::Meters);
+    assert_eq!(QosClass::classify_notification(&chat), QosClass::Chat);
+    assert_eq!(QosClass::classify_notification(&DomainSocketNotification::RateLimited { retry_after_ms: 10 }),
+               QosClass::State);
+
+    let mut queues = qos_queues(&["test", "--socket-qos-meters-queue", "1", "--socket-qos-chat-queue", "1"]);
+    queues.push(QosClass::Chat, Some(task_id.clone()), payload(1));
+    queues.push(QosClass::Chat, Some(task_id.clone()), payload(2));
+    queues.push(QosClass::Meters, Some(task_id.clone()), payload(3));
+    queues.push(QosClass::Meters, Some(task_id.clone()), payload(4));
+    queues.push(QosClass::Audio, Some(task_id.clone()), payload(5));
+
+    assert_eq!(popped(&mut queues),
+               vec![(QosClass::Audio, vec![5]),
+                    (QosClass::Meters, vec![4]),
+                    (QosClass::Chat, vec![1])]);
+    assert_eq!(queues.take_degraded(Instant::now()),
+               vec![],
+               "skipped meters and chat are not skipped packets");
+}
+
+#[test]
+fn test_chat_request_and_notification() -> anyhow::Result<()> {
+    let request = json!({ "chat": { "task_id": "app/task", "text": "take two" } });
+    let request = serde_json::from_value::<DomainSocketRequest>(request)?;
+    assert!(matches!(request, DomainSocketRequest::Chat { text, .. } if text == "take two"));
+
+    let notification =
+        DomainSocketNotification::Chat { task_id: { AppTaskId::new(AppId::test(), TaskId::new("task".to_owned())) },
+                                         from:    { ClientId::new("client".to_owned()) },
+                                         text:    { "take two".to_owned() }, };
+    assert_eq!(serde_json::to_value(&notification)?["chat"]["from"], json!("client"));
+
+    Ok(())
+}
+
+#[test]
+fn test_serialization_matches_api_codec_and_keeps_batch_order() {
+    let message = json!({ "task_id": "app/task", "serial": 7, "audio": [1, 2, 3] });
+
+    match encode_payload(&message, ResponseMedia::MsgPack, "test").expect("encode msgpack") {
+        SocketPayload::Bytes(bytes) => assert_eq!(bytes.to_vec(), MsgPack.serialize(&message).expect("api codec")),
+        other => panic!("expected bytes, got {other:?}"),
+    }
+
+    let messages = (0..PARALLEL_BATCH_LEN * 2).map(|serial| json!({ "serial": serial }))
+                                              .collect::<Vec<_>>();
+    let encoded = encode_batch(&messages, ResponseMedia::Json, "test");
+    let encoded = encoded.into_iter()
+                         .map(|payload| match payload {
+                             Ok(SocketPayload::Text(text)) => text,
+                             other => panic!("expected text, got {other:?}"),
+                         })
+                         .collect::<Vec<_>>();
+
+    let expected = messages.iter().map(|message| message.to_string()).collect::<Vec<_>>();
+    assert_eq!(encoded, expected);
+}
+
+#[test]
+fn test_fragments_reassemble_in_any_order() -> anyhow::Result<()> {
+    let message = Bytes::from((0..2000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>());
+    let mut fragmenter = Fragmenter::new(512);
+
+    let mut fragments = fragmenter.split(message.clone());
+    assert_eq!(fragments.len(), 4);
+    assert!(fragments.iter()
+                     .all(|fragment| is_fragment(fragment) && fragment.len() <= 512));
+    assert!(fragments[0].len() > FRAGMENT_HEADER_LEN);
+
+    fragments.reverse();
+
+    let mut reassembler = Reassembler::default();
+    let mut reassembled = vec![];
+    for fragment in fragments {
+        reassembled.extend(reassembler.push(fragment)?);
+    }
+
+    assert_eq!(reassembled, vec![message]);
+
+    // messages that fit go out whole, and MsgPack messages pass the reassembler untouched
+    let small = Bytes::from(MsgPack.serialize(&json!({"pong": {"challenge": "c"}}))
+                                   .expect("api codec"));
+    assert_eq!(fragmenter.split(small.clone()), vec![small.clone()]);
+    assert_eq!(reassembler.push(small.clone())?, Some(small));
+
+    Ok(())
+}
+
+fn parked_session(client: &str, expires_at: Instant) -> ParkedSession {
+    let task_id = AppTaskId::new(AppId::test(), TaskId::new("mix".to_owned()));
+
+    ParkedSession { client_id:   { ClientId::new(client.to_owned()) },
+                    memberships: { [(task_id, SecureKey::new("key".to_owned()))].into_iter().collect() },
+                    expires_at:  { expires_at }, }
+}
+
+#[test]
+fn test_parked_sessions_resume_once_and_expire() {
+    let now = Instant::now();
+    let mut parked = ParkedSessions::default();
+
+    let token = new_resumption_token();
+    assert_ne!(token, new_resumption_token());
+
+    parked.park(token.clone(), parked_session("roaming", now + Duration::from_secs(60)));
+    parked.park("stale".to_owned(), parked_session("gone", now + Duration::from_secs(1)));
+
+    assert!(parked.take("unknown", now).is_none());
+    assert!(parked.take("stale", now + Duration::from_secs(2)).is_none());
+
+    let session = parked.take(&token, now).expect("parked session");
+    assert_eq!(session.client_id.to_string(), "roaming");
+    assert_eq!(session.memberships.len(), 1);
+    assert!(parked.take(&token, now).is_none());
+
+    parked.park(token, parked_session("roaming", now + Duration::from_secs(60)));
+    assert_eq!(parked.expire(now + Duration::from_secs(61)), 1);
+    assert_eq!(parked.len(), 0);
+}
+
+#[test]
+fn test_resume_session_request_names_token_and_streams() {
+    let task_id = AppTaskId::new(AppId::test(), TaskId::new("mix".to_owned()));
+    let streams = json!([{"task_id": task_id, "play_id": 7, "last_serial": 120}]);
+    let request =
+        serde_json::from_value(json!({"resume_session": {"token": "abc", "streams": streams}})).expect("valid request");
+
+    let stream = ResumedStream { task_id:     { task_id },
+                                 play_id:     { PlayId::new(7) },
+                                 last_serial: { 120 }, };
+
+    match request {
+        DomainSocketRequest::ResumeSession { token, streams } => {
+            assert_eq!(token, "abc");
+            assert_eq!(streams, vec![stream]);
+        }
+        other => panic!("unexpected request {other:?}"),
+    }
+
+    let notification = DomainSocketNotification::ResumptionToken { token:  { "abc".to_owned() },
+                                                                   ttl_ms: { 60_000 }, };
+
+    assert_eq!(serde_json::to_value(&notification).expect("serializable"),
+               json!({"resumption_token": {"token": "abc", "ttl_ms": 60_000}}));
+}
+
+#[test]
+fn test_seek_request_and_notifications() {
+    let task_id = AppTaskId::new(AppId::test(), TaskId::new("mix".to_owned()));
+    let seek = json!({"play_id": 7, "segment": {"start": 0.0, "length": 60.0}, "start_at": 12.5, "looping": false});
+    let request = serde_json::from_value(json!({"seek": {"task_id": task_id, "seek": seek}})).expect("valid request");
+
+    match request {
+        DomainSocketRequest::Seek { task_id: sought_task_id,
+                                    revision,
+                                    seek, } => {
+            assert_eq!(sought_task_id, task_id);
+            assert_eq!(revision, 0);
+            assert_eq!(seek.play_id, PlayId::new(7));
+            assert_eq!(seek.start_at, 12.5);
+        }
+        other => panic!("unexpected request {other:?}"),
+    }
+
+    let sought = DomainSocketNotification::Sought { task_id: { task_id.clone() },
+                                                    play_id: { PlayId::new(7) }, };
+    assert_eq!(serde_json::to_value(&sought).expect("serializable"),
+               json!({"sought": {"task_id": task_id, "play_id": 7}}));
+}
+
+#[test]
+fn test_nack_retransmits_only_cached_packets_in_order() {
+    let play_id = PlayId::new(1);
+    let mut cache = HashMap::new();
+
+    for serial in [3, 4, 5, 7] {
+        let mut packet = StreamingPacket::default();
+        packet.play_id = play_id;
+        packet.serial = serial;
+        TasksSupervisor::cache_packet(&mut cache, packet);
+    }
+
+    let serials = SocketsSupervisor::nack_serials(vec![7, 1, 4, 7, 6, 3, 4]);
+    assert_eq!(serials, vec![1, 3, 4, 6, 7]);
+
+    let packets = TasksSupervisor::cached_packets(&cache[&play_id], &serials);
+    assert_eq!(packets.iter().map(|packet| packet.serial).collect::<Vec<_>>(),
+               vec![3, 4, 7],
+               "packets no longer cached are skipped");
+}
+
+#[test]
+fn test_long_nack_is_truncated_to_the_oldest_serials() {
+    // MAX_NACK_PACKETS is 256
+    let serials = SocketsSupervisor::nack_serials((0..300).rev().chain(0..10).collect());
+
+    assert_eq!(serials, (0..256).collect::<Vec<_>>());
+}
@@ -20,21 +20,33 @@ use tracing::*;
 use audiocloud_api::domain::streaming::DomainServerMessage;
 use audiocloud_api::ClientSocketId;
 
-use crate::sockets::messages::{SocketReceived, SocketSend};
-
-use crate::sockets::{get_sockets_supervisor, Disconnect, SendToClient, SocketConnected};
+use crate::sockets::fragmentation::{Fragmenter, Reassembler};
+use crate::sockets::ice::{self, IceOpts, IceServer};
+use crate::sockets::messages::{
+    NotifyDataChannelStats, NotifyWebRtcFailed, SetFragmentation, SocketPayload, SocketReceived, SocketSend,
+    WebRtcFailure,
+};
+use crate::sockets::qos::QosQueues;
+use crate::sockets::stats::DataChannelStats;
+use crate::sockets::{get_qos_opts, get_sockets_supervisor, Disconnect, SendToClient, SocketConnected};
 use crate::ResponseMedia;
 
+/// How often a connected WebRTC socket reports the traffic of its data channel to the supervisor
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
 #[derive(Args, Clone, Debug)]
 pub struct WebRtcOpts {
     /// Enable WebRTC transport support (used only if the app supports it as well)
     #[clap(long, env)]
     enable_web_rtc: bool,
 
-    /// List of ICE servers to use for WebRTC connections
+    /// List of STUN servers to use for WebRTC connections
     #[clap(long, env, default_value = "stun:stun.l.google.com:19302")]
     ice_servers: Vec<String>,
 
+    #[clap(flatten)]
+    ice: IceOpts,
+
     /// Beginning of UDP port range to use for WebRTC (inclusive)
     #[clap(long, env, default_value = "30000")]
     web_rtc_port_min: u16,
@@ -50,6 +62,14 @@ pub struct WebRtcOpts {
     /// Use native WebRTC ordering of packets instead of reordering in the clients
     #[clap(long, env)]
     web_rtc_use_native_ordering: bool,
+
+    /// Stop draining the outgoing QoS queues while the data channel has more than this many bytes buffered
+    #[clap(long, env, default_value = "262144")]
+    web_rtc_max_buffered_bytes: usize,
+
+    /// Largest message sent over the data channel to clients that negotiated fragmentation, larger ones are split
+    #[clap(long, env, default_value = "16384")]
+    web_rtc_max_message_bytes: usize,
 }
 
 struct ActorConnectionHandler {
@@ -88,7 +108,10 @@ impl PeerConnectionHandler for ActorConnectionHandler {
 
     fn on_connection_state_change(&mut self, state: ConnectionState) {
         match state {
-            ConnectionState::Disconnected | ConnectionState::Failed | ConnectionState::Closed => {
+            ConnectionState::Failed => {
+                self.actor.do_send(Failed);
+            }
+            ConnectionState::Disconnected | ConnectionState::Closed => {
                 self.actor.do_send(Closed);
             }
             _ => {}
@@ -124,17 +147,26 @@ pub struct WebRtcActor {
     peer_connection:     Box<RtcPeerConnection<ActorConnectionHandler>>,
     data_channel:        Box<RtcDataChannel<ActorDataChannelHandler>>,
     connected:           bool,
+    queues:              QosQueues,
+    max_buffered:        usize,
+    max_message:         usize,
+    /// Set once the client negotiated fragmentation, until then messages go out whole
+    fragmenter:          Option<Fragmenter>,
+    reassembler:         Reassembler,
+    stats:               DataChannelStats,
 }
 
 impl WebRtcActor {
     pub fn new(id: ClientSocketId,
                initiator_socket_id: ClientSocketId,
-               opts: &WebRtcOpts)
+               opts: &WebRtcOpts,
+               ice_servers: &[IceServer])
                -> anyhow::Result<(Addr<Self>, String)> {
-        let config = RtcConfig::new(&opts.ice_servers).enable_ice_tcp()
-                                                      .enable_ice_udp_mux()
-                                                      .port_range_begin(opts.web_rtc_port_min)
-                                                      .port_range_end(opts.web_rtc_port_max);
+        let ice_servers = ice_servers.iter().flat_map(IceServer::rtc_urls).collect::<Vec<_>>();
+        let config = RtcConfig::new(&ice_servers).enable_ice_tcp()
+                                                 .enable_ice_udp_mux()
+                                                 .port_range_begin(opts.web_rtc_port_min)
+                                                 .port_range_end(opts.web_rtc_port_max);
 
         let mut reliability = Reliability::default().max_retransmits(opts.web_rtc_max_retransmits);
 
@@ -145,6 +177,9 @@ impl WebRtcActor {
         let data_channel_init = DataChannelInit::default().reliability(reliability);
 
         let mut local_description = String::new();
+        let queues = QosQueues::new(get_qos_opts().clone());
+        let max_buffered = opts.web_rtc_max_buffered_bytes;
+        let max_message = opts.web_rtc_max_message_bytes;
 
         let actor = Self::create({
             let local_description = &mut local_description;
@@ -165,7 +200,13 @@ impl WebRtcActor {
                        data_channel,
                        id,
                        initiator_socket_id,
-                       connected }
+                       connected,
+                       queues,
+                       max_buffered,
+                       max_message,
+                       fragmenter: None,
+                       reassembler: Default::default(),
+                       stats: Default::default() }
             }
         });
 
@@ -173,10 +214,58 @@ impl WebRtcActor {
     }
 }
 
+impl WebRtcActor {
+    fn flush(&mut self, _ctx: &mut Context<Self>) {
+        if !self.connected {
+            return;
+        }
+
+        while self.data_channel.buffered_amount() < self.max_buffered {
+            let bytes = match self.queues.pop() {
+                Some(SocketPayload::Bytes(bytes)) => bytes,
+                Some(SocketPayload::Text(_)) => continue,
+                None => break,
+            };
+
+            // a message missing a fragment is lost anyway, so all fragments of a message go out together
+            let fragments = match self.fragmenter.as_mut() {
+                Some(fragmenter) => fragmenter.split(bytes),
+                None => vec![bytes],
+            };
+
+            for fragment in fragments {
+                match self.data_channel.send(&fragment[..]) {
+                    Ok(()) => {
+                        self.stats.bytes_sent += fragment.len() as u64;
+                        self.stats.messages_sent += 1;
+                    }
+                    Err(error) => warn!(%error, "Failed to send"),
+                }
+            }
+        }
+    }
+}
+
+impl WebRtcActor {
+    fn report_stats(&mut self, _ctx: &mut Context<Self>) {
+        if !self.connected {
+            return;
+        }
+
+        self.stats.buffered_bytes = self.data_channel.buffered_amount();
+
+        get_sockets_supervisor().do_send(NotifyDataChannelStats { socket_id: { self.id.clone() },
+                                                                  stats:     { self.stats.clone() }, });
+    }
+}
+
 impl Actor for WebRtcActor {
     type Context = Context<Self>;
 
-    fn started(&mut self, _ctx: &mut Self::Context) {}
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(Duration::from_millis(5), Self::flush);
+        ctx.run_interval(STATS_INTERVAL, Self::report_stats);
+    }
 }
 
 impl Handler<Closed> for WebRtcActor {
@@ -188,11 +277,34 @@ impl Handler<Closed> for WebRtcActor {
     }
 }
 
+impl Handler<Failed> for WebRtcActor {
+    type Result = ();
+
+    fn handle(&mut self, _: Failed, ctx: &mut Self::Context) {
+        warn!(id = %self.id, "WebRTC connection failed");
+        get_sockets_supervisor().do_send(NotifyWebRtcFailed { socket_id: { self.id.clone() },
+                                                              reason:    { WebRtcFailure::IceFailed }, });
+        ctx.stop();
+    }
+}
+
 impl Handler<OnDataChannelMessage> for WebRtcActor {
     type Result = ();
 
     fn handle(&mut self, msg: OnDataChannelMessage, ctx: &mut Self::Context) -> Self::Result {
-        get_sockets_supervisor().send(SocketReceived::Bytes(self.id.clone(), msg.0))
+        self.stats.bytes_received += msg.0.len() as u64;
+        self.stats.messages_received += 1;
+
+        let message = match self.reassembler.push(msg.0) {
+            Ok(Some(message)) => message,
+            Ok(None) => return,
+            Err(error) => {
+                warn!(id = %self.id, %error, "Dropping malformed fragment");
+                return;
+            }
+        };
+
+        get_sockets_supervisor().send(SocketReceived::Bytes(self.id.clone(), message))
                                 .map(drop)
                                 .into_actor(self)
                                 .spawn(ctx);
@@ -217,18 +329,27 @@ impl Handler<OnLocalIceCandidate> for WebRtcActor {
 impl Handler<SocketSend> for WebRtcActor {
     type Result = ();
 
-    fn handle(&mut self, msg: SocketSend, _ctx: &mut Self::Context) -> Self::Result {
-        match (msg, self.connected) {
-            (SocketSend::Bytes(bytes), true) => {
-                if let Err(error) = self.data_channel.send(&bytes[..]) {
-                    warn!(%error, "Failed to send");
-                }
-            }
-            _ => {}
+    fn handle(&mut self, msg: SocketSend, ctx: &mut Self::Context) -> Self::Result {
+        if self.connected {
+            self.queues.push(msg.class, msg.stream, msg.payload);
+            self.flush(ctx);
+            self.queues.report_degraded(&self.id);
         }
     }
 }
 
+impl Handler<SetFragmentation> for WebRtcActor {
+    type Result = Option<usize>;
+
+    fn handle(&mut self, msg: SetFragmentation, _ctx: &mut Self::Context) -> Self::Result {
+        let fragmenter = Fragmenter::new(msg.max_fragment_len.min(self.max_message));
+        let max_fragment_len = fragmenter.max_len();
+        self.fragmenter = Some(fragmenter);
+
+        Some(max_fragment_len)
+    }
+}
+
 impl Handler<SetPeerAnswer> for WebRtcActor {
     type Result = anyhow::Result<()>;
 
@@ -297,6 +418,10 @@ pub struct OnLocalIceCandidate(Option<String>);
 #[rtype(result = "()")]
 pub struct Closed;
 
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Failed;
+
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct Opened;
@@ -313,8 +438,8 @@ pub struct SetPeerAnswer {
     pub answer: String,
 }
 
-pub fn init(_opts: &WebRtcOpts) -> anyhow::Result<()> {
+pub async fn init(opts: &WebRtcOpts) -> anyhow::Result<()> {
     // datachannel::configure_logging();
 
-    Ok(())
+    ice::init(&opts.ice_servers, &opts.ice).await
 }
@@ -15,8 +15,9 @@ use tracing::*;
 use audiocloud_api::newtypes::SecureKey;
 use audiocloud_api::ClientSocketId;
 
-use crate::sockets::messages::{RegisterWebSocket, SocketReceived, SocketSend};
-use crate::sockets::{get_sockets_supervisor, Disconnect};
+use crate::sockets::messages::{RegisterWebSocket, SetFragmentation, SocketPayload, SocketReceived, SocketSend};
+use crate::sockets::qos::QosQueues;
+use crate::sockets::{get_qos_opts, get_sockets_supervisor, Disconnect};
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(ws_handler);
@@ -32,13 +33,16 @@ async fn ws_handler(req: HttpRequest, id: web::Path<ClientSocketId>, stream: web
     let id = id.into_inner();
     debug!(%id, "connected web_socket with");
 
-    let resp = ws::start(WebSocketActor { id }, &req, stream);
+    let queues = QosQueues::new(get_qos_opts().clone());
+
+    let resp = ws::start(WebSocketActor { id, queues }, &req, stream);
     resp
 }
 
 #[derive(Debug)]
 pub struct WebSocketActor {
-    id: ClientSocketId,
+    id:     ClientSocketId,
+    queues: QosQueues,
 }
 
 impl Actor for WebSocketActor {
@@ -47,13 +51,15 @@ impl Actor for WebSocketActor {
     fn started(&mut self, ctx: &mut Self::Context) {
         debug!(id = %self.id, "WebSocket started");
 
+        ctx.run_interval(Duration::from_millis(5), Self::flush);
+
         let register_cmd = RegisterWebSocket { address:   ctx.address(),
                                                socket_id: self.id.clone(), };
 
         get_sockets_supervisor().send(register_cmd)
                                 .into_actor(self)
                                 .map(|res, act, ctx| {
-                                    if res.is_err() {
+                                    if !matches!(res, Ok(Ok(()))) {
                                         warn!(id = %act.id, "Failed to register websocket actor, giving up");
                                         ctx.stop();
                                     }
@@ -66,6 +72,25 @@ impl Actor for WebSocketActor {
     }
 }
 
+impl WebSocketActor {
+    /// Write up to `SOCKET_QOS_WEB_SOCKET_FLUSH_BYTES` of the queued messages, highest priority first
+    ///
+    /// The context only runs the actor while the connection takes writes, so the ticks stop while the client is not
+    /// reading and messages stay in the queues, where the drop policies of their classes apply.
+    fn flush(&mut self, ctx: &mut WebsocketContext<Self>) {
+        for payload in self.queues.pop_batch(get_qos_opts().web_socket_flush_bytes()) {
+            match payload {
+                SocketPayload::Bytes(bytes) => {
+                    ctx.binary(bytes);
+                }
+                SocketPayload::Text(text) => {
+                    ctx.text(text);
+                }
+            }
+        }
+    }
+}
+
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebSocketActor {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
         match msg {
@@ -98,14 +123,18 @@ impl Handler<SocketSend> for WebSocketActor {
     type Result = ();
 
     fn handle(&mut self, msg: SocketSend, ctx: &mut Self::Context) {
-        match msg {
-            SocketSend::Bytes(bytes) => {
-                ctx.binary(bytes);
-            }
-            SocketSend::Text(text) => {
-                ctx.text(text);
-            }
-        }
+        // queued until the next flush, so messages arriving in a burst are written in priority order
+        self.queues.push(msg.class, msg.stream, msg.payload);
+        self.queues.report_degraded(&self.id);
+    }
+}
+
+impl Handler<SetFragmentation> for WebSocketActor {
+    type Result = Option<usize>;
+
+    fn handle(&mut self, msg: SetFragmentation, ctx: &mut Self::Context) -> Self::Result {
+        // WebSocket frames carry messages of any size, splitting them would only add headers
+        None
     }
 }
 
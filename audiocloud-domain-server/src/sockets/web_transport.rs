@@ -0,0 +1,336 @@
+#![allow(unused_variables)]
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix::{
+    Actor, ActorContext, ActorFutureExt, Addr, AsyncContext, Context, ContextFutureSpawner, Handler, Message,
+    WrapFuture,
+};
+use anyhow::anyhow;
+use bytes::Bytes;
+use clap::Args;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::*;
+use wtransport::endpoint::IncomingSession;
+use wtransport::{Certificate, Connection, Endpoint, RecvStream, SendStream, ServerConfig};
+
+use audiocloud_api::{ClientId, ClientSocketId, SocketId};
+
+use crate::sockets::fragmentation::{Fragmenter, Reassembler, MIN_FRAGMENT_LEN};
+use crate::sockets::messages::{
+    RegisterWebTransportSocket, SetFragmentation, SocketPayload, SocketReceived, SocketSend,
+};
+use crate::sockets::qos::{QosClass, QosQueues};
+use crate::sockets::{get_qos_opts, get_sockets_supervisor, Disconnect};
+
+/// Largest message accepted on the reliable stream of a session
+const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+#[derive(Args, Clone, Debug)]
+pub struct WebTransportOpts {
+    /// Accept WebTransport (HTTP/3) sessions on this UDP address, for example `0.0.0.0:7201`
+    #[clap(long, env)]
+    web_transport_bind: Option<SocketAddr>,
+
+    /// PEM certificate chain presented to WebTransport clients, browsers only connect over TLS
+    #[clap(long, env)]
+    web_transport_cert_file: Option<PathBuf>,
+
+    /// PEM private key of the WebTransport certificate
+    #[clap(long, env)]
+    web_transport_key_file: Option<PathBuf>,
+
+    /// Stop draining the outgoing QoS queues while this many messages wait to be written to the reliable stream
+    #[clap(long, env, default_value = "64")]
+    web_transport_max_pending_frames: usize,
+}
+
+pub async fn init(opts: &WebTransportOpts) -> anyhow::Result<()> {
+    let bind = match opts.web_transport_bind {
+        Some(bind) => bind,
+        None => return Ok(()),
+    };
+
+    let (cert_file, key_file) = match (&opts.web_transport_cert_file, &opts.web_transport_key_file) {
+        (Some(cert_file), Some(key_file)) => (cert_file, key_file),
+        _ => return Err(anyhow!("WebTransport needs a certificate and a private key")),
+    };
+
+    let config = ServerConfig::builder().with_bind_address(bind)
+                                        .with_certificate(Certificate::load(cert_file, key_file).await?)
+                                        .build();
+
+    let endpoint = Endpoint::server(config)?;
+    let max_pending = opts.web_transport_max_pending_frames;
+
+    info!(%bind, "Accepting WebTransport sessions");
+
+    actix::spawn(async move {
+        loop {
+            let session = endpoint.accept().await;
+            actix::spawn(async move {
+                if let Err(error) = accept_session(session, max_pending).await {
+                    debug!(%error, "Failed to accept WebTransport session");
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+/// Sessions are opened on `/wt/{client_id}/{socket_id}`, like WebSockets, and carry the same messages
+pub(super) fn parse_session_path(path: &str) -> Option<ClientSocketId> {
+    match path.trim_start_matches('/').split('/').collect::<Vec<_>>().as_slice() {
+        ["wt", client_id, socket_id] if !client_id.is_empty() && !socket_id.is_empty() => {
+            Some(ClientSocketId::new(ClientId::new(client_id.to_string()),
+                                     SocketId::new(socket_id.to_string())))
+        }
+        _ => None,
+    }
+}
+
+async fn accept_session(session: IncomingSession, max_pending: usize) -> anyhow::Result<()> {
+    let request = session.await?;
+
+    let id = match parse_session_path(request.path()) {
+        Some(id) => id,
+        None => {
+            let path = request.path().to_owned();
+            request.not_found().await;
+            return Err(anyhow!("Unknown WebTransport path {path}"));
+        }
+    };
+
+    let connection = Arc::new(request.accept().await?);
+
+    // the client opens one bidirectional stream for messages that have to arrive, the rest travels as datagrams
+    let (send, recv) = connection.accept_bi().await?;
+
+    debug!(%id, "connected web_transport with");
+
+    WebTransportActor::create(move |ctx| {
+        let (frames, pending) = mpsc::channel(max_pending);
+        let addr = ctx.address();
+
+        let tasks = vec![actix::spawn(write_frames(send, pending, addr.clone())),
+                         actix::spawn(read_frames(id.clone(), recv, addr.clone())),
+                         actix::spawn(read_datagrams(id.clone(), connection.clone(), addr)),];
+
+        WebTransportActor { id:         { id },
+                            connection: { connection },
+                            frames:     { frames },
+                            queues:     { QosQueues::new(get_qos_opts().clone()) },
+                            fragmenter: { None },
+                            tasks:      { tasks }, }
+    });
+
+    Ok(())
+}
+
+async fn write_frames(mut send: SendStream, mut pending: mpsc::Receiver<Bytes>, actor: Addr<WebTransportActor>) {
+    while let Some(frame) = pending.recv().await {
+        let len = (frame.len() as u32).to_be_bytes();
+
+        if let Err(error) = write_frame(&mut send, &len, &frame).await {
+            debug!(%error, "WebTransport stream write failed");
+            break;
+        }
+    }
+
+    actor.do_send(Closed);
+}
+
+async fn write_frame(send: &mut SendStream, len: &[u8], frame: &[u8]) -> std::io::Result<()> {
+    AsyncWriteExt::write_all(send, len).await?;
+    AsyncWriteExt::write_all(send, frame).await
+}
+
+async fn read_frames(id: ClientSocketId, mut recv: RecvStream, actor: Addr<WebTransportActor>) {
+    let mut len = [0u8; 4];
+
+    loop {
+        if let Err(error) = AsyncReadExt::read_exact(&mut recv, &mut len).await {
+            debug!(%id, %error, "WebTransport stream read failed");
+            break;
+        }
+
+        let len = u32::from_be_bytes(len) as usize;
+        if len > MAX_FRAME_SIZE {
+            warn!(%id, len, "WebTransport message too large, dropping socket");
+            break;
+        }
+
+        let mut frame = vec![0u8; len];
+        if let Err(error) = AsyncReadExt::read_exact(&mut recv, &mut frame).await {
+            debug!(%id, %error, "WebTransport stream read failed");
+            break;
+        }
+
+        get_sockets_supervisor().do_send(SocketReceived::Bytes(id.clone(), frame.into()));
+    }
+
+    actor.do_send(Closed);
+}
+
+async fn read_datagrams(id: ClientSocketId, connection: Arc<Connection>, actor: Addr<WebTransportActor>) {
+    let mut reassembler = Reassembler::default();
+
+    loop {
+        match connection.receive_datagram().await {
+            Ok(datagram) => match reassembler.push(Bytes::copy_from_slice(&datagram)) {
+                Ok(Some(message)) => get_sockets_supervisor().do_send(SocketReceived::Bytes(id.clone(), message)),
+                Ok(None) => {}
+                Err(error) => warn!(%id, %error, "Dropping malformed fragment"),
+            },
+            Err(error) => {
+                debug!(%id, %error, "WebTransport connection closed");
+                break;
+            }
+        }
+    }
+
+    actor.do_send(Closed);
+}
+
+pub struct WebTransportActor {
+    id:         ClientSocketId,
+    connection: Arc<Connection>,
+    /// Messages waiting to be written to the reliable stream
+    frames:     mpsc::Sender<Bytes>,
+    queues:     QosQueues,
+    /// Set once the client negotiated fragmentation, until then datagrams that do not fit take the reliable stream
+    fragmenter: Option<Fragmenter>,
+    /// Reading and writing the session, aborted with the actor so that the connection closes
+    tasks:      Vec<JoinHandle<()>>,
+}
+
+impl WebTransportActor {
+    /// Audio and meters are worthless once late, so they skip retransmission whenever they fit in a datagram
+    fn is_unreliable(class: QosClass) -> bool {
+        matches!(class, QosClass::Realtime | QosClass::Audio | QosClass::Meters)
+    }
+
+    fn flush(&mut self, ctx: &mut Context<Self>) {
+        while self.frames.capacity() > 0 {
+            let (class, payload) = match self.queues.pop_classified() {
+                Some(next) => next,
+                None => break,
+            };
+
+            let bytes = match payload {
+                SocketPayload::Bytes(bytes) => bytes,
+                SocketPayload::Text(text) => Bytes::from(text),
+            };
+
+            let max_datagram = self.connection.max_datagram_size();
+            let fits_datagram = max_datagram.map(|max| bytes.len() <= max).unwrap_or(false);
+
+            // too large for a datagram, fragments keep it off the reliable stream if the client takes them
+            let fragmenter = self.fragmenter
+                                 .as_mut()
+                                 .zip(max_datagram)
+                                 .filter(|(_, max)| *max >= MIN_FRAGMENT_LEN);
+
+            if Self::is_unreliable(class) && fits_datagram {
+                self.send_datagram(bytes);
+            } else if let Some((fragmenter, max)) = fragmenter.filter(|_| Self::is_unreliable(class)) {
+                for fragment in fragmenter.split_within(bytes, max) {
+                    self.send_datagram(fragment);
+                }
+            } else if self.frames.try_send(bytes).is_err() {
+                warn!(id = %self.id, "WebTransport stream closed");
+                ctx.stop();
+                break;
+            }
+        }
+    }
+
+    fn send_datagram(&self, bytes: Bytes) {
+        if let Err(error) = self.connection.send_datagram(bytes) {
+            debug!(id = %self.id, %error, "Failed to send datagram");
+        }
+    }
+}
+
+impl Actor for WebTransportActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        debug!(id = %self.id, "WebTransport started");
+
+        let register_cmd = RegisterWebTransportSocket { address:   ctx.address(),
+                                                        socket_id: self.id.clone(), };
+
+        get_sockets_supervisor().send(register_cmd)
+                                .into_actor(self)
+                                .map(|res, act, ctx| {
+                                    if !matches!(res, Ok(Ok(()))) {
+                                        warn!(id = %act.id, "Failed to register WebTransport actor, giving up");
+                                        ctx.stop();
+                                    }
+                                })
+                                .wait(ctx);
+
+        ctx.run_interval(Duration::from_millis(5), Self::flush);
+    }
+
+    fn stopped(&mut self, ctx: &mut Self::Context) {
+        debug!(id = %self.id, "WebTransport stopped");
+
+        for task in self.tasks.drain(..) {
+            task.abort();
+        }
+    }
+}
+
+impl Handler<SocketSend> for WebTransportActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: SocketSend, ctx: &mut Self::Context) -> Self::Result {
+        self.queues.push(msg.class, msg.stream, msg.payload);
+        self.flush(ctx);
+        self.queues.report_degraded(&self.id);
+    }
+}
+
+impl Handler<SetFragmentation> for WebTransportActor {
+    type Result = Option<usize>;
+
+    fn handle(&mut self, msg: SetFragmentation, ctx: &mut Self::Context) -> Self::Result {
+        // without datagrams everything takes the reliable stream, which carries messages of any size
+        let max_datagram = self.connection.max_datagram_size()?;
+        let fragmenter = Fragmenter::new(msg.max_fragment_len.min(max_datagram));
+        let max_fragment_len = fragmenter.max_len();
+        self.fragmenter = Some(fragmenter);
+
+        Some(max_fragment_len)
+    }
+}
+
+impl Handler<Closed> for WebTransportActor {
+    type Result = ();
+
+    fn handle(&mut self, _: Closed, ctx: &mut Self::Context) {
+        debug!(id = %self.id, "Closing WebTransport session");
+        ctx.stop();
+    }
+}
+
+impl Handler<Disconnect> for WebTransportActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: Disconnect, ctx: &mut Self::Context) -> Self::Result {
+        debug!(id = %self.id, "Asked to disconnect");
+        ctx.run_later(Duration::default(), |_, ctx| ctx.stop());
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Closed;
@@ -0,0 +1,186 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
+use clap::Args;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use serde_json::Value;
+use tracing::*;
+
+use audiocloud_api::{now, Timestamp};
+
+use crate::config;
+use crate::db::Db;
+use crate::fixed_instances::{get_instance_supervisor, ListFixedInstances};
+use crate::incidents::{get_incidents_supervisor, ListIncidents};
+use crate::o11y::{generate_prometheus_metrics, recent_logs};
+use crate::rate_limit::RateLimiter;
+
+#[cfg(test)]
+mod tests;
+
+static SUPPORT_BUNDLES: OnceCell<SupportBundles> = OnceCell::new();
+
+/// Keys of config objects whose values are replaced in support bundles, matched case insensitively anywhere in the key
+const REDACTED_KEYS: &[&str] = &["password", "secret", "token", "key", "credential"];
+
+const REDACTED: &str = "<redacted>";
+
+#[derive(Args, Clone, Debug)]
+pub struct SupportOpts {
+    /// Seconds between support bundles, requests for another one sooner are refused. 0 to not limit them
+    #[clap(long, env, default_value = "300")]
+    pub support_bundle_interval_seconds: u64,
+
+    /// Most recent journal events included in support bundles
+    #[clap(long, env, default_value = "1000")]
+    pub support_bundle_journal_events: usize,
+
+    /// Most recent incidents included in support bundles
+    #[clap(long, env, default_value = "50")]
+    pub support_bundle_incidents: usize,
+}
+
+struct SupportBundles {
+    db:      Db,
+    opts:    SupportOpts,
+    limiter: Option<RateLimiter>,
+}
+
+/// When and by which domain server version a support bundle was created, the first file of the archive
+#[derive(Serialize, Clone, Debug)]
+struct SupportBundleManifest {
+    created_at: Timestamp,
+    version:    &'static str,
+    files:      Vec<String>,
+}
+
+#[instrument(skip_all, err)]
+pub fn init(db: Db, opts: SupportOpts) -> anyhow::Result<()> {
+    let limiter = match opts.support_bundle_interval_seconds {
+        0 => None,
+        interval => {
+            let interval = Duration::from_secs(interval);
+            Some(RateLimiter::new(1.0 / interval.as_secs_f64(), 1.0, interval))
+        }
+    };
+
+    SUPPORT_BUNDLES.set(SupportBundles { db, opts, limiter })
+                   .map_err(|_| anyhow!("Support bundles already initialized"))?;
+
+    Ok(())
+}
+
+/// Take the turn of the next support bundle, or return how long to wait for it
+pub fn check_support_bundle_rate() -> Result<(), Duration> {
+    match SUPPORT_BUNDLES.get().and_then(|bundles| bundles.limiter.as_ref()) {
+        Some(limiter) => limiter.check("support-bundle"),
+        None => Ok(()),
+    }
+}
+
+/// A gzipped tar archive of recent logs, the redacted config, a metrics snapshot, recent journal events, the state of
+/// the fixed instances and recent incidents
+///
+/// Parts that can not be collected are left out, with the reason in `errors.txt`.
+pub async fn create_support_bundle() -> anyhow::Result<Vec<u8>> {
+    let bundles = SUPPORT_BUNDLES.get()
+                                 .ok_or_else(|| anyhow!("Support bundles not initialized"))?;
+
+    let mut files = vec![];
+    let mut errors = vec![];
+
+    files.push(("logs.txt".to_owned(), recent_logs().join("\n").into_bytes()));
+
+    match config::loaded_config() {
+        Some(mut value) => {
+            redact_config(&mut value);
+            files.push(("config.json".to_owned(), serde_json::to_vec_pretty(&value)?));
+        }
+        None => errors.push("config.json: no config loaded".to_owned()),
+    }
+
+    let parts = [("metrics.txt", generate_prometheus_metrics().map(String::into_bytes)),
+                 ("journal.json", journal_events(&bundles.db, bundles.opts.support_bundle_journal_events).await),
+                 ("instances.json", fixed_instances().await),
+                 ("incidents.json", incidents(bundles.opts.support_bundle_incidents).await)];
+
+    for (name, part) in parts {
+        match part {
+            Ok(contents) => files.push((name.to_owned(), contents)),
+            Err(error) => errors.push(format!("{name}: {error}")),
+        }
+    }
+
+    if !errors.is_empty() {
+        files.push(("errors.txt".to_owned(), errors.join("\n").into_bytes()));
+    }
+
+    let manifest = SupportBundleManifest { created_at: { now() },
+                                           version:    { env!("CARGO_PKG_VERSION") },
+                                           files:      { files.iter().map(|(name, _)| name.clone()).collect() }, };
+
+    files.insert(0, ("manifest.json".to_owned(), serde_json::to_vec_pretty(&manifest)?));
+
+    write_archive(&files)
+}
+
+async fn journal_events(db: &Db, count: usize) -> anyhow::Result<Vec<u8>> {
+    let (_, last) = db.journal_bounds().await?;
+    let events = db.query_journal_events((last - count as i64).max(0), None, count)
+                   .await?;
+
+    Ok(serde_json::to_vec_pretty(&events)?)
+}
+
+async fn fixed_instances() -> anyhow::Result<Vec<u8>> {
+    let instances = get_instance_supervisor().send(ListFixedInstances).await?;
+
+    Ok(serde_json::to_vec_pretty(&instances)?)
+}
+
+async fn incidents(limit: usize) -> anyhow::Result<Vec<u8>> {
+    let incidents = get_incidents_supervisor().send(ListIncidents { limit })
+                                              .await?
+                                              .map_err(|error| anyhow!("{error}"))?;
+
+    Ok(serde_json::to_vec_pretty(&incidents)?)
+}
+
+/// Replace the values of config keys that look like they hold secrets, at any depth
+pub fn redact_config(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                let key = key.to_lowercase();
+                if REDACTED_KEYS.iter().any(|redacted| key.contains(redacted)) && !value.is_null() {
+                    *value = Value::String(REDACTED.to_owned());
+                } else {
+                    redact_config(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact_config),
+        _ => {}
+    }
+}
+
+/// Gzipped tar archive of the files, in order, all under a `support-bundle/` folder
+pub fn write_archive(files: &[(String, Vec<u8>)]) -> anyhow::Result<Vec<u8>> {
+    let mut archive = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    let modified = now().timestamp().max(0) as u64;
+
+    for (name, contents) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(modified);
+        header.set_cksum();
+
+        archive.append_data(&mut header, format!("support-bundle/{name}"), contents.as_slice())?;
+    }
+
+    Ok(archive.into_inner()?.finish()?)
+}
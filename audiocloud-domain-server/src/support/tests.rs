@@ -0,0 +1,49 @@
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+use serde_json::json;
+
+use crate::support::{redact_config, write_archive};
+
+#[test]
+fn test_config_redaction_keeps_structure() {
+    let mut config = json!({
+        "domain_id": "studio",
+        "nats": { "password": "hunter2", "url": "nats://localhost" },
+        "instances": [{ "driver": { "api_key": "abc", "apiToken": null } }],
+        "secure_keys": { "app": "xyz" },
+    });
+
+    redact_config(&mut config);
+
+    assert_eq!(config,
+               json!({
+                   "domain_id": "studio",
+                   "nats": { "password": "<redacted>", "url": "nats://localhost" },
+                   "instances": [{ "driver": { "api_key": "<redacted>", "apiToken": null } }],
+                   "secure_keys": "<redacted>",
+               }));
+}
+
+#[test]
+fn test_support_archive_contains_files_in_order() -> anyhow::Result<()> {
+    let files = vec![("manifest.json".to_owned(), b"{}".to_vec()),
+                     ("logs.txt".to_owned(), b"first\nsecond".to_vec())];
+
+    let bytes = write_archive(&files)?;
+
+    let mut archive = tar::Archive::new(GzDecoder::new(bytes.as_slice()));
+    let mut read = vec![];
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let mut contents = vec![];
+        entry.read_to_end(&mut contents)?;
+        read.push((entry.path()?.to_string_lossy().to_string(), contents));
+    }
+
+    assert_eq!(read,
+               vec![("support-bundle/manifest.json".to_owned(), b"{}".to_vec()),
+                    ("support-bundle/logs.txt".to_owned(), b"first\nsecond".to_vec())]);
+
+    Ok(())
+}
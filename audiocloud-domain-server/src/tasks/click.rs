@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use audiocloud_api::common::task::TaskSpec;
+use audiocloud_api::newtypes::MixerNodeId;
+
+/// Most bars a click counts in
+pub const MAX_CLICK_COUNT_IN_BARS: u32 = 16;
+
+/// Click track the engine generates for a play and sends to a mixer, counting in musicians who track through it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskClick {
+    /// Mixer the click is sent to, usually the one the musicians monitor
+    #[schema(value_type = String)]
+    pub mixer_id:      MixerNodeId,
+    /// Bars of click before the play starts, counted back from the start of its pre-roll
+    #[serde(default)]
+    pub count_in_bars: u32,
+    /// Keep clicking through the play after the count-in
+    #[serde(default)]
+    pub through_play:  bool,
+    /// Level of the click in dBFS, the first beat of each bar is accented at this level and the others are 6 dB lower
+    #[serde(default = "default_click_level_db")]
+    pub level_db:      f64,
+    /// Tempo and time signature of the click, the tempo map of the task if none
+    #[serde(default)]
+    pub tempo:         Option<ClickTempo>,
+}
+
+fn default_click_level_db() -> f64 {
+    -12.0
+}
+
+/// Fixed tempo and time signature of a click
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ClickTempo {
+    /// Quarter notes per minute
+    pub bpm:         f64,
+    pub numerator:   u32,
+    pub denominator: u32,
+}
+
+impl TaskClick {
+    /// Check that the click is sent to a mixer of the task and that it clicks at all
+    pub fn validate(&self, spec: &TaskSpec) -> Result<(), String> {
+        self.validate_mixers(|mixer_id| spec.mixers.contains_key(mixer_id))
+    }
+
+    /// Validate against the mixers of a task, `has_mixer` tells whether a mixer is in it
+    pub fn validate_mixers(&self, has_mixer: impl Fn(&MixerNodeId) -> bool) -> Result<(), String> {
+        if !has_mixer(&self.mixer_id) {
+            return Err(format!("Mixer {} is not in the task", self.mixer_id));
+        }
+        if self.count_in_bars == 0 && !self.through_play {
+            return Err("Click neither counts in nor clicks through the play".to_owned());
+        }
+        if self.count_in_bars > MAX_CLICK_COUNT_IN_BARS {
+            return Err(format!("Count-in of {} bars is longer than {MAX_CLICK_COUNT_IN_BARS} bars",
+                               self.count_in_bars));
+        }
+        if !self.level_db.is_finite() || self.level_db > 0.0 {
+            return Err(format!("Click level {} dB is above full scale", self.level_db));
+        }
+        if let Some(tempo) = &self.tempo {
+            if !tempo.bpm.is_finite() || tempo.bpm <= 0.0 {
+                return Err(format!("Click has invalid tempo {}", tempo.bpm));
+            }
+            if tempo.numerator == 0 || !tempo.denominator.is_power_of_two() || tempo.denominator > 64 {
+                return Err(format!("Click has invalid time signature {}/{}",
+                                   tempo.numerator, tempo.denominator));
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,489 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use audiocloud_api::common::media::{PlayId, RenderId};
+use audiocloud_api::common::task::{NodePadId, TaskSpec, TimeSegment};
+use audiocloud_api::newtypes::{AppTaskId, MixerNodeId, TrackMediaId, TrackNodeId};
+
+use crate::tasks::watermark::PlayWatermark;
+use crate::tasks::{
+    TaskClick, TaskEnvelopes, TaskLatencyProfile, TaskLeadIn, TaskMediaFades, TaskMediaRates, TaskPlaylist,
+    TaskPunchRegion, TaskStreamCodec, TaskTempoMap, TaskTrackInputs,
+};
+
+/// Engine commands the `audiocloud_api` engine protocol does not describe (yet)
+///
+/// They are sent MsgPack encoded on the `.ext` sibling of the engine command subject and engines reply with a MsgPack
+/// encoded `Result<(), String>`. Engines that do not support a command reply with an error.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum EngineExtCommand {
+    SetTrackInputs {
+        task_id: AppTaskId,
+        inputs:  TaskTrackInputs,
+    },
+    /// While armed, plays record the tracks with hardware inputs
+    SetRecording {
+        task_id:     AppTaskId,
+        armed:       bool,
+        /// While looping, report every pass as a take instead of only the last one
+        #[serde(default)]
+        loop_record: bool,
+        /// Only record within the region, on the tracks it arms
+        #[serde(default)]
+        punch:       Option<TaskPunchRegion>,
+    },
+    /// Click track of the next play, generated by the engine and sent to the mixer of the click
+    SetClick { task_id: AppTaskId, click: TaskClick },
+    /// Lead-in of the following plays, counted in with the metronome
+    SetLeadIn { task_id: AppTaskId, lead_in: TaskLeadIn },
+    /// Tempo changes and time signatures of the project, replacing the ones set before
+    SetTempoMap {
+        task_id:   AppTaskId,
+        tempo_map: TaskTempoMap,
+    },
+    /// Latency profile of the following plays, the engine picks the encoder block size for it
+    SetLatencyProfile {
+        task_id: AppTaskId,
+        profile: TaskLatencyProfile,
+    },
+    /// Codec the following plays are streamed with
+    SetStreamCodec {
+        task_id: AppTaskId,
+        codec:   TaskStreamCodec,
+    },
+    /// Integrated loudness the following plays are streamed at, none streams them at the engine default
+    SetLoudnessTarget {
+        task_id:     AppTaskId,
+        target_lufs: Option<f64>,
+    },
+    /// Bitrate of the stream of a play, applied right away. Only Opus streams change their bitrate, engines refuse it
+    /// for lossless streams
+    SetStreamQuality {
+        task_id: AppTaskId,
+        play_id: PlayId,
+        bitrate: u32,
+    },
+    /// Segments the following plays step through back to back, an empty playlist plays the segment of the play
+    SetPlaylist {
+        task_id:  AppTaskId,
+        playlist: TaskPlaylist,
+    },
+    /// Parameters written as envelopes of the project, replacing the envelopes set before
+    SetEnvelopes {
+        task_id:   AppTaskId,
+        envelopes: TaskEnvelopes,
+    },
+    /// Fades of the track media, written into their media items
+    SetMediaFades {
+        task_id: AppTaskId,
+        fades:   TaskMediaFades,
+    },
+    /// Playback rates of the track media, written into their media items. Engines reply with
+    /// [`EngineExtEvent::MediaLengths`]
+    SetMediaRates {
+        task_id: AppTaskId,
+        rates:   TaskMediaRates,
+    },
+    /// Linear gain of the streamed audio, and the mixer sent to the master instead of the mixer of the play
+    SetMonitor {
+        task_id: AppTaskId,
+        gain:    f64,
+        source:  Option<MixerNodeId>,
+    },
+    /// Spectrum reports of the following plays, none stops them
+    SetSpectrum {
+        task_id:  AppTaskId,
+        spectrum: Option<EngineSpectrumSettings>,
+    },
+    /// Watermark of the stream of the next play, sent right before it. None streams the play without one
+    SetWatermark {
+        task_id:   AppTaskId,
+        play_id:   PlayId,
+        watermark: Option<PlayWatermark>,
+    },
+    /// Pause the transport of a play, keeping the project and the streaming encoder as they are
+    PausePlay { task_id: AppTaskId, play_id: PlayId },
+    /// Continue a paused play from where it paused
+    ResumePlay { task_id: AppTaskId, play_id: PlayId },
+    /// Play a sine tone on a hardware output and measure the hardware inputs, reported with
+    /// [`EngineExtEvent::TestToneMeasured`] once the tone ends. Engines refuse while a task plays or renders.
+    StartTestTone { test_id: String, tone: EngineTestTone },
+    /// Save a diagnostic bundle of the task to the shared media root, reported with
+    /// [`EngineExtEvent::DiagnosticsCaptured`]
+    CaptureDiagnostics { task_id: AppTaskId, error: String },
+    /// Convert the file of a render to the formats once it finished, reported with [`EngineExtEvent::RenderOutputs`]
+    SetRenderFormats {
+        task_id:   AppTaskId,
+        render_id: RenderId,
+        formats:   Vec<RenderFormat>,
+    },
+}
+
+/// Engine events the `audiocloud_api` engine protocol does not describe (yet), published MsgPack encoded on
+/// [`engine_ext_event_subject`]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum EngineExtEvent {
+    /// A recording of a track finished, `path` is relative to the shared media root
+    ///
+    /// Loop recordings report one event per pass, with the `pass` number and the part of the file it occupies.
+    TakeRecorded {
+        task_id:       AppTaskId,
+        track_id:      TrackNodeId,
+        segment:       TimeSegment,
+        path:          String,
+        #[serde(default)]
+        pass:          Option<usize>,
+        #[serde(default)]
+        media_segment: Option<TimeSegment>,
+    },
+    /// Clock of the audio interface of the engine, reported when it changes and periodically in between
+    ClockStatus { status: EngineClockStatus },
+    /// Load of the engine and the sessions it runs, reported periodically
+    Resources { resources: EngineResources },
+    /// Peak levels the hardware inputs received while a test tone played
+    TestToneMeasured {
+        test_id: String,
+        result:  EngineTestToneResult,
+    },
+    /// EBU R128 loudness of the pads the engine meters, reported with the audio of a play
+    Loudness {
+        task_id:  AppTaskId,
+        play_id:  PlayId,
+        loudness: HashMap<NodePadId, PadLoudness>,
+    },
+    /// Spectrum of the pads the engine analyzes, reported at the rate the spectrum reports were set up with
+    Spectrum {
+        task_id:  AppTaskId,
+        play_id:  PlayId,
+        spectrum: HashMap<NodePadId, PadSpectrum>,
+    },
+    /// A render was cancelled after `rendered_length` seconds of it, what was rendered is kept at `partial_path`,
+    /// relative to the shared media root, unless nothing was
+    RenderingCancelled {
+        task_id:         AppTaskId,
+        render_id:       RenderId,
+        partial_path:    Option<String>,
+        rendered_length: f64,
+    },
+    /// A diagnostic bundle of the task was saved, `path` is relative to the shared media root
+    ///
+    /// The bundle is a JSON document with the error, the status of the engine, the commands the engine received for
+    /// the task most recently and the project file of the task.
+    DiagnosticsCaptured {
+        task_id: AppTaskId,
+        error:   String,
+        path:    String,
+    },
+    /// Timeline lengths of the track media of the task in seconds, reported when their rates or the spec of the task
+    /// changed
+    MediaLengths {
+        task_id: AppTaskId,
+        lengths: HashMap<TrackMediaId, f64>,
+    },
+    /// The file of a finished render was converted to the formats set for it, one output per format
+    RenderOutputs {
+        task_id:   AppTaskId,
+        render_id: RenderId,
+        outputs:   Vec<EngineRenderOutput>,
+    },
+    /// The engine started afresh, after a crash or a restart, and reloaded the sessions it had open before from its
+    /// session state, with the [`spec_hash`] of the spec each of them was reloaded with
+    ///
+    /// Whatever the sessions played or rendered is gone, as is everything set with extension commands.
+    Started { sessions: HashMap<AppTaskId, String> },
+}
+
+impl EngineExtCommand {
+    /// Sets one of the [`TaskSettings`](crate::tasks::TaskSettings) of a task
+    pub fn is_task_setting(&self) -> bool {
+        matches!(self,
+                 EngineExtCommand::SetTrackInputs { .. }
+                 | EngineExtCommand::SetTempoMap { .. }
+                 | EngineExtCommand::SetEnvelopes { .. }
+                 | EngineExtCommand::SetMediaFades { .. }
+                 | EngineExtCommand::SetLoudnessTarget { .. }
+                 | EngineExtCommand::SetWatermark { .. })
+    }
+}
+
+impl EngineExtEvent {
+    /// Task the event is about, engine wide events are about none
+    pub fn task_id(&self) -> Option<&AppTaskId> {
+        match self {
+            EngineExtEvent::TakeRecorded { task_id, .. }
+            | EngineExtEvent::Loudness { task_id, .. }
+            | EngineExtEvent::Spectrum { task_id, .. }
+            | EngineExtEvent::RenderingCancelled { task_id, .. }
+            | EngineExtEvent::DiagnosticsCaptured { task_id, .. }
+            | EngineExtEvent::RenderOutputs { task_id, .. }
+            | EngineExtEvent::MediaLengths { task_id, .. } => Some(task_id),
+            EngineExtEvent::ClockStatus { .. }
+            | EngineExtEvent::Resources { .. }
+            | EngineExtEvent::TestToneMeasured { .. }
+            | EngineExtEvent::Started { .. } => None,
+        }
+    }
+}
+
+/// Loudness of a pad as EBU R128 meters show it, each reading `None` while the pad is silent
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, ToSchema)]
+pub struct PadLoudness {
+    /// Loudness over the last 400 ms, in LUFS
+    #[serde(default)]
+    pub momentary_lufs:  Option<f64>,
+    /// Loudness over the last 3 s, in LUFS
+    #[serde(default)]
+    pub short_term_lufs: Option<f64>,
+    /// Highest true peak since the previous reading, in dBTP
+    #[serde(default)]
+    pub true_peak_db:    Option<f64>,
+}
+
+/// How finely and how often an engine analyzes the spectrum of the pads it streams
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct EngineSpectrumSettings {
+    /// Bands between 20 Hz and the Nyquist frequency, spaced evenly on a logarithmic scale
+    pub bands:   usize,
+    /// Spectrum reports per second
+    pub rate_hz: f64,
+}
+
+/// Spectrum of a pad, the level of each band in dBFS from the lowest band up
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, ToSchema)]
+pub struct PadSpectrum {
+    /// Lower edge of the lowest band, in Hz
+    pub min_hz: f64,
+    /// Upper edge of the highest band, in Hz
+    pub max_hz: f64,
+    pub bands:  Vec<f64>,
+}
+
+/// Clock source and lock status of the audio interface an engine runs on
+///
+/// Host APIs rarely expose the lock state of the converters directly, so engines derive it: the interface is locked
+/// while audio runs at the expected sample rate without new audio thread underruns.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct EngineClockStatus {
+    /// Clock source, i.e. `word clock` or `internal`, as far as the engine knows it
+    #[serde(default)]
+    pub source:               Option<String>,
+    #[serde(default)]
+    pub device:               Option<String>,
+    #[serde(default)]
+    pub sample_rate:          Option<f64>,
+    #[serde(default)]
+    pub expected_sample_rate: Option<f64>,
+    pub running:              bool,
+    /// Audio thread underruns since the engine started
+    #[serde(default)]
+    pub underruns:            u64,
+    pub locked:               bool,
+}
+
+/// Resource usage of an engine, to keep new tasks away from engines close to dropping audio
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct EngineResources {
+    /// CPU time the engine process used since the previous report, in percent of all cores
+    #[serde(default)]
+    pub cpu_usage:  Option<f64>,
+    /// Audio device underruns (xruns) since the engine started
+    #[serde(default)]
+    pub xruns:      u64,
+    /// Samples per audio device block
+    #[serde(default)]
+    pub block_size: Option<u32>,
+    /// Tracks in the project of each session
+    #[serde(default)]
+    #[schema(value_type = Object)]
+    pub tracks:     HashMap<AppTaskId, usize>,
+}
+
+impl EngineResources {
+    /// The engine uses more than `max_cpu_usage` percent of the CPU, or had xruns since the `previous` report
+    pub fn is_overloaded(&self, previous: Option<&EngineResources>, max_cpu_usage: f64) -> bool {
+        let cpu_saturated = matches!(self.cpu_usage, Some(cpu_usage) if cpu_usage > max_cpu_usage);
+        let new_xruns = matches!(previous, Some(previous) if self.xruns > previous.xruns);
+
+        cpu_saturated || new_xruns
+    }
+}
+
+/// Longest test tone an engine is asked to play
+pub const MAX_TEST_TONE_DURATION_MS: u64 = 30_000;
+
+/// A sine tone on one hardware output of an engine, to verify the patching of the converters and the fixed instances
+/// connected to them
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct EngineTestTone {
+    /// Hardware output channel the tone plays on, zero based like the channels of fixed instance routing
+    pub output_channel: usize,
+    /// Hardware input channels to measure while the tone plays
+    pub input_channels: Vec<usize>,
+    #[serde(default = "default_test_tone_frequency")]
+    pub frequency:      f64,
+    /// Level of the tone, in dBFS
+    #[serde(default = "default_test_tone_level_db")]
+    pub level_db:       f64,
+    #[serde(default = "default_test_tone_duration_ms")]
+    pub duration_ms:    u64,
+}
+
+fn default_test_tone_frequency() -> f64 {
+    1_000.0
+}
+
+fn default_test_tone_level_db() -> f64 {
+    -18.0
+}
+
+fn default_test_tone_duration_ms() -> u64 {
+    2_000
+}
+
+impl EngineTestTone {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.input_channels.is_empty() {
+            return Err("No input channels to measure".to_owned());
+        }
+        if !self.frequency.is_finite() || self.frequency < 20.0 || self.frequency > 20_000.0 {
+            return Err(format!("Frequency {} is outside of 20 Hz to 20 kHz", self.frequency));
+        }
+        // a full scale tone at the wrong output can damage monitors or the ears of whoever is in the room
+        if !self.level_db.is_finite() || self.level_db > -6.0 {
+            return Err(format!("Level {} dBFS is above the -6 dBFS allowed for test tones",
+                               self.level_db));
+        }
+        if self.duration_ms == 0 || self.duration_ms > MAX_TEST_TONE_DURATION_MS {
+            return Err(format!("Duration {} ms is outside of 1 to {MAX_TEST_TONE_DURATION_MS} ms",
+                               self.duration_ms));
+        }
+
+        Ok(())
+    }
+}
+
+/// Peak level of one hardware input while a test tone played
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct EngineTestToneInput {
+    pub channel: usize,
+    /// Highest peak, in dBFS, or `None` when the input stayed silent
+    pub peak_db: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct EngineTestToneResult {
+    pub output_channel: usize,
+    pub inputs:         Vec<EngineTestToneInput>,
+}
+
+pub fn engine_ext_command_subject(engine_command_subject: &str) -> String {
+    format!("{engine_command_subject}.ext")
+}
+
+pub fn engine_ext_event_subject(engine_command_subject: &str) -> String {
+    format!("{engine_command_subject}.ext.events")
+}
+
+/// Hash of a spec that engines compute the same way, see [`json_hash`]
+pub fn spec_hash(spec: &TaskSpec) -> String {
+    json_hash(&serde_json::to_value(spec).unwrap_or_default())
+}
+
+/// The 64 bit FNV-1a hash of the JSON of a value with the keys of all objects sorted, in hex
+pub fn json_hash(value: &Value) -> String {
+    let mut json = String::new();
+    write_canonical_json(value, &mut json);
+
+    let hash = json.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+                               (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+                           });
+
+    format!("{hash:016x}")
+}
+
+fn write_canonical_json(value: &Value, json: &mut String) {
+    match value {
+        Value::Object(object) => {
+            let mut keys = object.keys().collect::<Vec<_>>();
+            keys.sort();
+
+            json.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                json.push_str(&Value::String(key.clone()).to_string());
+                json.push(':');
+                write_canonical_json(&object[key], json);
+            }
+            json.push('}');
+        }
+        Value::Array(items) => {
+            json.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                write_canonical_json(item, json);
+            }
+            json.push(']');
+        }
+        other => json.push_str(&other.to_string()),
+    }
+}
+
+/// A format a render is converted to once it finished, in addition to the file the engine renders
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case", tag = "format")]
+pub enum RenderFormat {
+    Wav { bit_depth: u32 },
+    Flac { bit_depth: u32 },
+    Mp3 { bitrate_kbps: u32 },
+}
+
+impl RenderFormat {
+    /// Short name of the format, like `wav-24` or `mp3-320`, part of the names of converted files
+    pub fn key(&self) -> String {
+        match self {
+            RenderFormat::Wav { bit_depth } => format!("wav-{bit_depth}"),
+            RenderFormat::Flac { bit_depth } => format!("flac-{bit_depth}"),
+            RenderFormat::Mp3 { bitrate_kbps } => format!("mp3-{bitrate_kbps}"),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            RenderFormat::Wav { bit_depth: 16 | 24 | 32, } => Ok(()),
+            RenderFormat::Flac { bit_depth: 16 | 24 } => Ok(()),
+            RenderFormat::Mp3 { bitrate_kbps: 32..=320 } => Ok(()),
+            RenderFormat::Wav { bit_depth } => Err(format!("WAV bit depth {bit_depth} is not one of 16, 24 or 32")),
+            RenderFormat::Flac { bit_depth } => Err(format!("FLAC bit depth {bit_depth} is not one of 16 or 24")),
+            RenderFormat::Mp3 { bitrate_kbps } => {
+                Err(format!("MP3 bitrate {bitrate_kbps} kbps is outside of 32 to 320 kbps"))
+            }
+        }
+    }
+}
+
+/// Validate the formats of a render, each format can be requested once
+pub fn validate_render_formats(formats: &[RenderFormat]) -> Result<(), String> {
+    let mut keys = HashSet::new();
+    for format in formats {
+        format.validate()?;
+        if !keys.insert(format.key()) {
+            return Err(format!("Render format {} is requested more than once", format.key()));
+        }
+    }
+
+    Ok(())
+}
+
+/// A render converted to one format, `result` is the path relative to the shared media root or why it failed
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EngineRenderOutput {
+    pub format: RenderFormat,
+    pub result: Result<String, String>,
+}
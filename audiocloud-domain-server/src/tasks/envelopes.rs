@@ -0,0 +1,111 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use audiocloud_api::newtypes::{DynamicInstanceNodeId, NodeConnectionId};
+
+/// Parameter changes over time the engine writes as envelopes, plays and renders follow them instead of the static
+/// connection values and parameters of the spec
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskEnvelopes {
+    #[serde(default)]
+    pub envelopes: Vec<TaskEnvelope>,
+}
+
+/// Values of one parameter over time, points in timeline order
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskEnvelope {
+    pub target: EnvelopeTarget,
+    pub points: Vec<EnvelopePoint>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum EnvelopeTarget {
+    /// Volume of a connection, as the linear gain connection values use
+    ConnectionVolume {
+        #[schema(value_type = String)]
+        connection_id: NodeConnectionId,
+    },
+    /// Pan of a connection, from -1 (left) to 1 (right)
+    ConnectionPan {
+        #[schema(value_type = String)]
+        connection_id: NodeConnectionId,
+    },
+    /// Normalized value of a parameter of a dynamic instance, by parameter name
+    DynamicInstanceParameter {
+        #[schema(value_type = String)]
+        dynamic_id: DynamicInstanceNodeId,
+        parameter:  String,
+    },
+}
+
+impl EnvelopeTarget {
+    fn value_range(&self) -> (f64, f64) {
+        match self {
+            EnvelopeTarget::ConnectionVolume { .. } => (0.0, f64::MAX),
+            EnvelopeTarget::ConnectionPan { .. } => (-1.0, 1.0),
+            EnvelopeTarget::DynamicInstanceParameter { .. } => (0.0, 1.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct EnvelopePoint {
+    /// Seconds on the timeline of the task
+    pub time:  f64,
+    pub value: f64,
+    /// How the value moves on to the next point
+    #[serde(default)]
+    pub shape: EnvelopeShape,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EnvelopeShape {
+    #[default]
+    Linear,
+    /// Keep the value until the next point
+    Square,
+    /// Ease out of the point and into the next one
+    Smooth,
+}
+
+impl TaskEnvelopes {
+    pub fn is_empty(&self) -> bool {
+        self.envelopes.is_empty()
+    }
+
+    /// Check that every target has one envelope, with points in timeline order and values in the range of the target
+    pub fn validate(&self) -> Result<(), String> {
+        let mut targets = HashSet::new();
+
+        for (index, envelope) in self.envelopes.iter().enumerate() {
+            if !targets.insert(&envelope.target) {
+                return Err(format!("Envelope {index} has the target of an earlier envelope"));
+            }
+            if envelope.points.is_empty() {
+                return Err(format!("Envelope {index} has no points"));
+            }
+
+            let (min, max) = envelope.target.value_range();
+            let mut previous = 0.0;
+
+            for (point_index, point) in envelope.points.iter().enumerate() {
+                if !point.time.is_finite() || point.time < previous {
+                    return Err(format!("Point {point_index} of envelope {index} is at invalid time {}",
+                                       point.time));
+                }
+                if !point.value.is_finite() || point.value < min || point.value > max {
+                    return Err(format!("Point {point_index} of envelope {index} has invalid value {}",
+                                       point.value));
+                }
+
+                previous = point.time;
+            }
+        }
+
+        Ok(())
+    }
+}
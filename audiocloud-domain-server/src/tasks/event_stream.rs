@@ -0,0 +1,310 @@
+#![allow(unused_variables)]
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use actix::{Actor, ActorContext, AsyncContext, Context, Handler};
+use actix_broker::BrokerSubscribe;
+use bytes::Bytes;
+use serde::Serialize;
+use serde_json::json;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tracing::*;
+
+use audiocloud_api::audio_engine::EngineEvent;
+use audiocloud_api::{AppTaskId, NodePadId, PlayId, StreamingPacket, Timestamp};
+
+use crate::tasks::engine_ext::{PadLoudness, PadSpectrum};
+use crate::tasks::{
+    BarBeat, NotifyEngineEvent, NotifyStreamingPacket, NotifyTaskDiagnostics, NotifyTaskEngineRestarted,
+    NotifyTaskMediaLengths, NotifyTaskNullTest, NotifyTaskRecallSheet, NotifyTaskRenderCancelled,
+    NotifyTaskRenderNormalized, NotifyTaskRenderOutputs, NotifyTaskRoutingVerification, NotifyTaskSafeMode,
+    NotifyTaskState, NotifyTaskTake,
+};
+
+/// Relays events of a single task to a Server-Sent Events response body
+pub struct TaskEventStream {
+    task_id: AppTaskId,
+    sender:  mpsc::Sender<Bytes>,
+    replay:  Vec<StreamingPacket>,
+}
+
+/// Streaming packet without audio and metering payloads, for clients that only follow progress
+#[derive(Serialize, Clone, Debug)]
+pub struct StreamingPacketSummary {
+    pub play_id:          PlayId,
+    pub serial:           u64,
+    pub created_at:       Timestamp,
+    pub num_audio_frames: usize,
+    pub num_pad_meters:   usize,
+    /// Musical position of the last audio in the packet, not known for replayed packets
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bar_beat:         Option<BarBeat>,
+    /// Playlist segment of the last audio in the packet, not known for replayed packets
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub playlist_index:   Option<usize>,
+    /// Loudness of the metered pads, not known for replayed packets
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub loudness:         HashMap<NodePadId, PadLoudness>,
+    /// Spectrum of the analyzed pads, not known for replayed packets
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub spectrum:         HashMap<NodePadId, PadSpectrum>,
+}
+
+impl StreamingPacketSummary {
+    /// Summary of a replayed packet, of which only the packet itself is known
+    fn replayed(packet: &StreamingPacket) -> Self {
+        Self { play_id:          { packet.play_id.clone() },
+               serial:           { packet.serial },
+               created_at:       { packet.created_at },
+               num_audio_frames: { packet.audio.len() },
+               num_pad_meters:   { packet.pad_metering.len() },
+               bar_beat:         { None },
+               playlist_index:   { None },
+               loudness:         { HashMap::new() },
+               spectrum:         { HashMap::new() }, }
+    }
+
+    fn live(msg: NotifyStreamingPacket) -> Self {
+        let mut summary = Self::replayed(&msg.packet);
+
+        summary.bar_beat = msg.bar_beat;
+        summary.playlist_index = msg.playlist_index;
+        summary.loudness = msg.loudness;
+        summary.spectrum = msg.spectrum;
+
+        summary
+    }
+}
+
+/// Event IDs of packet events are `{play_id}:{serial}`, so clients can resume after the last packet seen
+pub fn parse_last_event_id(id: &str) -> Option<(PlayId, u64)> {
+    let (play_id, serial) = id.split_once(':')?;
+    Some((play_id.parse().ok()?, serial.parse().ok()?))
+}
+
+impl TaskEventStream {
+    pub fn new(task_id: AppTaskId, sender: mpsc::Sender<Bytes>, replay: Vec<StreamingPacket>) -> Self {
+        Self { task_id: { task_id },
+               sender:  { sender },
+               replay:  { replay }, }
+    }
+
+    fn send_event(&mut self, id: Option<String>, event: &str, data: impl Serialize, ctx: &mut Context<Self>) {
+        let data = match serde_json::to_string(&data) {
+            Ok(data) => data,
+            Err(error) => {
+                warn!(%error, task_id = %self.task_id, event, "Failed to serialize event");
+                return;
+            }
+        };
+
+        let mut frame = String::new();
+        if let Some(id) = id {
+            frame.push_str(&format!("id: {id}\n"));
+        }
+        frame.push_str(&format!("event: {event}\ndata: {data}\n\n"));
+
+        self.send_frame(Bytes::from(frame), ctx);
+    }
+
+    fn send_packet(&mut self, summary: StreamingPacketSummary, ctx: &mut Context<Self>) {
+        let id = format!("{}:{}", summary.play_id, summary.serial);
+        self.send_event(Some(id), "packet", summary, ctx);
+    }
+
+    fn send_frame(&mut self, frame: Bytes, ctx: &mut Context<Self>) {
+        match self.sender.try_send(frame) {
+            Ok(_) => {}
+            Err(TrySendError::Full(_)) => {
+                warn!(task_id = %self.task_id, "Event stream client is not keeping up, dropping event");
+            }
+            Err(TrySendError::Closed(_)) => {
+                debug!(task_id = %self.task_id, "Event stream client disconnected");
+                ctx.stop();
+            }
+        }
+    }
+
+    fn send_keep_alive(&mut self, ctx: &mut Context<Self>) {
+        self.send_frame(Bytes::from_static(b": keep-alive\n\n"), ctx);
+    }
+}
+
+impl Actor for TaskEventStream {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.subscribe_system_async::<NotifyTaskState>(ctx);
+        self.subscribe_system_async::<NotifyStreamingPacket>(ctx);
+        self.subscribe_system_async::<NotifyEngineEvent>(ctx);
+        self.subscribe_system_async::<NotifyTaskSafeMode>(ctx);
+        self.subscribe_system_async::<NotifyTaskRoutingVerification>(ctx);
+        self.subscribe_system_async::<NotifyTaskTake>(ctx);
+        self.subscribe_system_async::<NotifyTaskDiagnostics>(ctx);
+        self.subscribe_system_async::<NotifyTaskRenderCancelled>(ctx);
+        self.subscribe_system_async::<NotifyTaskRenderOutputs>(ctx);
+        self.subscribe_system_async::<NotifyTaskRenderNormalized>(ctx);
+        self.subscribe_system_async::<NotifyTaskNullTest>(ctx);
+        self.subscribe_system_async::<NotifyTaskMediaLengths>(ctx);
+        self.subscribe_system_async::<NotifyTaskRecallSheet>(ctx);
+        self.subscribe_system_async::<NotifyTaskEngineRestarted>(ctx);
+
+        for packet in std::mem::take(&mut self.replay) {
+            self.send_packet(StreamingPacketSummary::replayed(&packet), ctx);
+        }
+
+        ctx.run_interval(Duration::from_secs(15), Self::send_keep_alive);
+    }
+}
+
+impl Handler<NotifyTaskState> for TaskEventStream {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskState, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id == self.task_id {
+            self.send_event(None, "state", msg.state, ctx);
+        }
+    }
+}
+
+impl Handler<NotifyStreamingPacket> for TaskEventStream {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyStreamingPacket, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id == self.task_id {
+            self.send_packet(StreamingPacketSummary::live(msg), ctx);
+        }
+    }
+}
+
+impl Handler<NotifyTaskSafeMode> for TaskEventStream {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskSafeMode, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id == self.task_id {
+            self.send_event(None, "safe_mode", msg.safe_mode, ctx);
+        }
+    }
+}
+
+impl Handler<NotifyTaskRoutingVerification> for TaskEventStream {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskRoutingVerification, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id == self.task_id {
+            self.send_event(None, "routing_verification", msg.verification, ctx);
+        }
+    }
+}
+
+impl Handler<NotifyTaskTake> for TaskEventStream {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskTake, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id == self.task_id {
+            self.send_event(None, "take", msg.take, ctx);
+        }
+    }
+}
+
+impl Handler<NotifyTaskDiagnostics> for TaskEventStream {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskDiagnostics, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id == self.task_id {
+            self.send_event(None, "diagnostics", msg.diagnostics, ctx);
+        }
+    }
+}
+
+impl Handler<NotifyTaskRenderCancelled> for TaskEventStream {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskRenderCancelled, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id == self.task_id {
+            self.send_event(None, "render_cancelled", msg.partial, ctx);
+        }
+    }
+}
+
+impl Handler<NotifyTaskRenderOutputs> for TaskEventStream {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskRenderOutputs, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id == self.task_id {
+            self.send_event(None, "render_outputs", msg.outputs, ctx);
+        }
+    }
+}
+
+impl Handler<NotifyTaskRenderNormalized> for TaskEventStream {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskRenderNormalized, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id == self.task_id {
+            self.send_event(None, "render_normalized", msg.normalized, ctx);
+        }
+    }
+}
+
+impl Handler<NotifyTaskNullTest> for TaskEventStream {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskNullTest, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id == self.task_id {
+            self.send_event(None, "null_test", msg.report, ctx);
+        }
+    }
+}
+
+impl Handler<NotifyTaskMediaLengths> for TaskEventStream {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskMediaLengths, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id == self.task_id {
+            self.send_event(None, "media_lengths", msg.lengths, ctx);
+        }
+    }
+}
+
+impl Handler<NotifyTaskRecallSheet> for TaskEventStream {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskRecallSheet, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id == self.task_id {
+            self.send_event(None, "recall_sheet", msg.sheet, ctx);
+        }
+    }
+}
+
+impl Handler<NotifyTaskEngineRestarted> for TaskEventStream {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskEngineRestarted, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id == self.task_id {
+            let data = json!({ "engine_id": msg.engine_id, "restored": msg.restored, "interrupted": msg.interrupted });
+            self.send_event(None, "engine_restarted", data, ctx);
+        }
+    }
+}
+
+impl Handler<NotifyEngineEvent> for TaskEventStream {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyEngineEvent, ctx: &mut Self::Context) -> Self::Result {
+        if msg.event.task_id() != &self.task_id {
+            return;
+        }
+
+        let error = match msg.event {
+            EngineEvent::Error { error, .. } => json!({ "error": error }),
+            EngineEvent::PlayingFailed { play_id, error, .. } => json!({ "play_id": play_id, "error": error }),
+            EngineEvent::RenderingFailed { render_id, error, .. } => json!({ "render_id": render_id, "error": error }),
+            _ => return,
+        };
+
+        self.send_event(None, "error", error, ctx);
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use audiocloud_api::common::task::{TaskSpec, TimeSegment};
+use audiocloud_api::newtypes::TrackMediaId;
+
+/// Fades of the media on the tracks of a task, by track media ID, written into the media items by the engine
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskMediaFades {
+    #[serde(default)]
+    #[schema(value_type = Object)]
+    pub media: HashMap<TrackMediaId, MediaFades>,
+}
+
+/// Fades of one track media, in seconds
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct MediaFades {
+    /// Fade in from the start of the media segment
+    #[serde(default)]
+    pub fade_in:   f64,
+    /// Fade out to the end of the media segment
+    #[serde(default)]
+    pub fade_out:  f64,
+    /// Start this long before the timeline segment, with as much of the media before the media segment as a handle,
+    /// fading in while the media on the track ending where this one starts fades out
+    #[serde(default)]
+    pub crossfade: f64,
+    #[serde(default)]
+    pub shape:     FadeShape,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FadeShape {
+    #[default]
+    Linear,
+    /// Level changes quickly at the start of the fade
+    FastStart,
+    /// Level changes quickly at the end of the fade
+    FastEnd,
+    /// Level changes slowly at both ends of the fade
+    SCurve,
+}
+
+impl TaskMediaFades {
+    pub fn is_empty(&self) -> bool {
+        self.media.is_empty()
+    }
+
+    /// Check that the fades are of media in the spec and fit within its media segment, and that crossfades have a
+    /// handle of media before the segment to fade in over
+    pub fn validate(&self, spec: &TaskSpec) -> Result<(), String> {
+        self.validate_segments(|media_id| {
+                spec.tracks
+                    .values()
+                    .find_map(|track| track.media.get(media_id))
+                    .map(|media| (media.media_segment.clone(), media.timeline_segment.clone()))
+            })
+    }
+
+    /// Validate against the media and timeline segments of each track media, `None` if it is not in the task
+    pub fn validate_segments(&self,
+                             segments: impl Fn(&TrackMediaId) -> Option<(TimeSegment, TimeSegment)>)
+                             -> Result<(), String> {
+        for (media_id, fades) in &self.media {
+            let (media_segment, timeline_segment) =
+                segments(media_id).ok_or_else(|| format!("Track media {media_id} is not in the task"))?;
+
+            for (name, length) in [("Fade in", fades.fade_in),
+                                   ("Fade out", fades.fade_out),
+                                   ("Crossfade", fades.crossfade)]
+            {
+                if !length.is_finite() || length < 0.0 {
+                    return Err(format!("{name} of track media {media_id} has invalid length {length}"));
+                }
+            }
+
+            if fades.fade_in + fades.fade_out > media_segment.length {
+                return Err(format!("Fades of track media {media_id} are longer than its media segment"));
+            }
+            if fades.crossfade > media_segment.start {
+                return Err(format!("Crossfade of track media {media_id} is longer than the media before its segment"));
+            }
+            if fades.crossfade > timeline_segment.start {
+                return Err(format!("Crossfade of track media {media_id} starts before the timeline"));
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use audiocloud_api::common::task::TaskSpec;
+use audiocloud_api::newtypes::TrackMediaId;
+
+/// Slowest playback rate of track media
+pub const MIN_MEDIA_RATE: f64 = 0.25;
+
+/// Fastest playback rate of track media
+pub const MAX_MEDIA_RATE: f64 = 4.0;
+
+/// Playback rates of the media on the tracks of a task, by track media ID, written into the media items by the engine
+///
+/// Media plays its media segment at the rate, taking the length of the segment divided by the rate on the timeline
+/// from the start of its timeline segment.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskMediaRates {
+    #[serde(default)]
+    #[schema(value_type = Object)]
+    pub media: HashMap<TrackMediaId, MediaRate>,
+}
+
+/// Playback rate of one track media
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct MediaRate {
+    /// Playback rate, 2.0 plays the media twice as fast
+    #[serde(default = "default_rate")]
+    pub rate:    f64,
+    #[serde(default)]
+    pub stretch: StretchMode,
+}
+
+fn default_rate() -> f64 {
+    1.0
+}
+
+impl Default for MediaRate {
+    fn default() -> Self {
+        Self { rate:    { default_rate() },
+               stretch: { StretchMode::default() }, }
+    }
+}
+
+/// How media playing at a rate other than 1.0 is stretched
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StretchMode {
+    /// Pitch follows the rate, as on tape
+    Varispeed,
+    /// Keep the pitch, with the time stretch the engine uses by default
+    #[default]
+    Balanced,
+    /// Keep the pitch, favoring tonal material such as vocals and pads
+    Tonal,
+    /// Keep the pitch, favoring transients such as drums
+    Transient,
+}
+
+/// Timeline lengths of the track media at their rates as the engine last reported them, in seconds
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskMediaLengths {
+    #[serde(default)]
+    #[schema(value_type = Object)]
+    pub media: HashMap<TrackMediaId, f64>,
+}
+
+/// Rates of the track media of a task, with the lengths they take on the timeline
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskMediaRatesState {
+    pub rates:   TaskMediaRates,
+    pub lengths: TaskMediaLengths,
+}
+
+impl TaskMediaRates {
+    pub fn is_empty(&self) -> bool {
+        self.media.is_empty()
+    }
+
+    /// Check that the rates are of media in the spec and within range
+    pub fn validate(&self, spec: &TaskSpec) -> Result<(), String> {
+        self.validate_media(|media_id| spec.tracks.values().any(|track| track.media.contains_key(media_id)))
+    }
+
+    /// Validate against the media of a task, `has_media` tells whether a track media is in it
+    pub fn validate_media(&self, has_media: impl Fn(&TrackMediaId) -> bool) -> Result<(), String> {
+        for (media_id, rate) in &self.media {
+            if !has_media(media_id) {
+                return Err(format!("Track media {media_id} is not in the task"));
+            }
+            if !(MIN_MEDIA_RATE..=MAX_MEDIA_RATE).contains(&rate.rate) {
+                return Err(format!("Rate {} of track media {media_id} is out of range", rate.rate));
+            }
+        }
+
+        Ok(())
+    }
+}
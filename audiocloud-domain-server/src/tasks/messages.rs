@@ -1,41 +1,100 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use actix::Message;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use audiocloud_api::audio_engine::event::EngineEvent;
 use audiocloud_api::common::change::TaskState;
 use audiocloud_api::common::media::{MediaObject, RenderId};
 
-use audiocloud_api::common::task::TaskSpec;
+use audiocloud_api::common::task::{ConnectionValues, NodePadId, TaskSpec, TimeSegment};
 use audiocloud_api::domain::streaming::StreamStats;
 use audiocloud_api::domain::tasks::{
     TaskCreated, TaskDeleted, TaskPlayStopped, TaskPlaying, TaskRenderCancelled, TaskRendering, TaskSought,
     TaskSummaryList, TaskUpdated, TaskWithStatusAndSpec,
 };
-use audiocloud_api::newtypes::{AppMediaObjectId, AppTaskId, EngineId};
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::newtypes::{AppMediaObjectId, AppTaskId, EngineId, NodeConnectionId, TrackNodeId};
 use audiocloud_api::{
-    CreateTaskReservation, CreateTaskSecurity, CreateTaskSpec, ModifyTaskSpec, PlayId, RequestCancelRender,
-    RequestPlay, RequestRender, RequestSeek, RequestStopPlay, StreamingPacket, TaskReservation, TaskSecurity,
+    ClientId, CreateTaskReservation, CreateTaskSecurity, CreateTaskSpec, ModifyTaskSpec, PadMetering, PlayId,
+    RequestCancelRender, RequestPlay, RequestRender, RequestSeek, RequestStopPlay, SecureKey, StreamingPacket,
+    TaskPlayState, TaskReservation, TaskSecurity, Timestamp,
 };
 
-use crate::{DomainResult, DomainSecurity};
+use crate::tasks::click::TaskClick;
+use crate::tasks::engine_ext::{
+    EngineClockStatus, EngineExtEvent, EngineResources, EngineTestTone, EngineTestToneResult, PadLoudness, PadSpectrum,
+    RenderFormat,
+};
+use crate::tasks::envelopes::TaskEnvelopes;
+use crate::tasks::fades::TaskMediaFades;
+use crate::tasks::media_rates::{TaskMediaLengths, TaskMediaRates, TaskMediaRatesState};
+use crate::tasks::monitor::TaskMonitor;
+use crate::tasks::null_test::{NullTestMetrics, TaskNullTestRequest};
+use crate::tasks::playlist::TaskPlaylist;
+use crate::tasks::recall_sheet::TaskRecallSheet;
+use crate::tasks::render_normalization::{RenderLoudness, TaskRenderNormalization};
+use crate::tasks::routing_verification::TaskRoutingVerification;
+use crate::tasks::tempo_map::{BarBeat, TaskTempoMap};
+use crate::tasks::track_groups::TaskTrackGroups;
+use crate::tasks::watermark::{PlayWatermark, TaskWatermark};
+use crate::tasks::TaskOpts;
+use crate::{DomainResult, DomainSecurity, SecureKeyScope, TaskKeyScopes};
 
 #[derive(Message, Clone, Debug)]
 #[rtype(result = "DomainResult<TaskRendering>")]
 pub struct RenderTask {
-    pub task_id:  AppTaskId,
-    pub render:   RequestRender,
-    pub security: DomainSecurity,
-    pub revision: u64,
+    pub task_id:       AppTaskId,
+    pub render:        RequestRender,
+    /// Formats the render is converted to once it finished
+    pub formats:       Vec<RenderFormat>,
+    /// Loudness the render is normalized to once it finished
+    pub normalization: Option<TaskRenderNormalization>,
+    pub security:      DomainSecurity,
+    pub revision:      u64,
+}
+
+/// A render request with what to make of the render once it finished
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct TaskRenderRequest {
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    pub render:        RequestRender,
+    #[serde(default)]
+    pub formats:       Vec<RenderFormat>,
+    #[serde(default)]
+    pub normalization: Option<TaskRenderNormalization>,
+}
+
+/// A play request with the click the engine generates for it
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct TaskPlayRequest {
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    pub play:      RequestPlay,
+    #[serde(default)]
+    pub click:     Option<TaskClick>,
+    /// Client whose sockets receive the stream of the play, named by the watermark of the task. The client requesting
+    /// the play is named if not set
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub client_id: Option<ClientId>,
 }
 
 #[derive(Message, Clone, Debug)]
 #[rtype(result = "DomainResult<TaskPlaying>")]
 pub struct PlayTask {
-    pub task_id:  AppTaskId,
-    pub play:     RequestPlay,
-    pub security: DomainSecurity,
-    pub revision: u64,
+    pub task_id:   AppTaskId,
+    pub play:      RequestPlay,
+    /// Click track generated for the play
+    pub click:     Option<TaskClick>,
+    /// Client the stream of the play is watermarked for
+    pub client_id: Option<ClientId>,
+    /// Watermark of the stream of the play, set by the tasks supervisor from the watermark of the task
+    pub watermark: Option<PlayWatermark>,
+    pub security:  DomainSecurity,
+    pub revision:  u64,
 }
 
 #[derive(Message, Clone, Debug)]
@@ -55,6 +114,18 @@ pub struct DeleteTask {
     pub security: DomainSecurity,
 }
 
+impl DeleteTask {
+    /// A task is only deleted by a client that has seen its latest spec revision
+    pub fn check_revision(&self, current: u64) -> DomainResult {
+        if self.revision < current {
+            Err(DomainError::TaskModificationRevisionOutOfDate { task_id:  { self.task_id.clone() },
+                                                                 revision: { current }, })
+        } else {
+            Ok(())
+        }
+    }
+}
+
 #[derive(Message, Clone, Debug)]
 #[rtype(result = "()")]
 pub struct NotifyTaskDeleted {
@@ -140,6 +211,20 @@ pub struct NotifyRenderFailed {
 #[rtype(result = "()")]
 pub struct BecomeOnline;
 
+/// Start the actors of the tasks reserved for now and label their engine subjects before the domain goes online
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "TasksWarmedUp")]
+pub struct WarmUpTasks;
+
+/// What the tasks supervisor had ready when the warm-up finished
+#[derive(Clone, Debug, Default)]
+pub struct TasksWarmedUp {
+    pub tasks:            usize,
+    pub active_tasks:     usize,
+    pub engines:          usize,
+    pub routed_instances: usize,
+}
+
 #[derive(Message, Clone, Debug)]
 #[rtype(result = "TaskSummaryList")]
 pub struct ListTasks;
@@ -187,11 +272,100 @@ pub struct StopPlayTask {
     pub revision: u64,
 }
 
+/// Play to pause or resume
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct RequestPausePlay {
+    #[schema(value_type = String)]
+    pub play_id: PlayId,
+}
+
+/// Whether a play is paused after pausing or resuming it
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct TaskPlayPause {
+    #[schema(value_type = String)]
+    pub play_id: PlayId,
+    pub paused:  bool,
+}
+
+/// Pause a play, keeping the engine project, instances and stream as they are so that it resumes right away
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskPlayPause>")]
+pub struct PausePlayTask {
+    pub task_id:  AppTaskId,
+    pub pause:    RequestPausePlay,
+    pub security: DomainSecurity,
+    pub revision: u64,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskPlayPause>")]
+pub struct ResumePlayTask {
+    pub task_id:  AppTaskId,
+    pub resume:   RequestPausePlay,
+    pub security: DomainSecurity,
+    pub revision: u64,
+}
+
+/// What the transport of a task is doing, for clients that did not start the play themselves
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TaskTransport {
+    pub play_id:   Option<PlayId>,
+    pub paused:    bool,
+    pub rendering: bool,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskTransport>")]
+pub struct GetTaskTransport {
+    pub task_id:  AppTaskId,
+    pub security: DomainSecurity,
+}
+
 #[derive(Message, Clone, Debug)]
 #[rtype(result = "()")]
 pub struct NotifyStreamingPacket {
-    pub task_id: AppTaskId,
-    pub packet:  StreamingPacket,
+    pub task_id:         AppTaskId,
+    pub packet:          StreamingPacket,
+    /// Position of the last audio in the packet on the tempo map of the task
+    pub bar_beat:        Option<BarBeat>,
+    /// Index of the playlist segment the last audio in the packet belongs to, if the task has a playlist
+    pub playlist_index:  Option<usize>,
+    /// Latency profile of the task, sockets queue low latency packets apart from the others
+    pub latency_profile: TaskLatencyProfile,
+    /// Codec the audio in the packet is compressed with, clients are told before they get packets of a new codec
+    pub codec:           TaskStreamCodec,
+    /// Latest loudness of the pads the engine meters, while the packet was collected
+    pub loudness:        HashMap<NodePadId, PadLoudness>,
+    /// Latest spectrum of the pads the engine analyzes, while the packet was collected
+    pub spectrum:        HashMap<NodePadId, PadSpectrum>,
+}
+
+/// Loudness the engine of the task metered, handed to the task actor to go out with the next packet
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskLoudness {
+    pub task_id:  AppTaskId,
+    pub play_id:  PlayId,
+    pub loudness: HashMap<NodePadId, PadLoudness>,
+}
+
+/// Metering of a task as it arrives from the engine, for the meter capture to record
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskMetering {
+    pub task_id:       AppTaskId,
+    pub at:            Timestamp,
+    pub peak_metering: HashMap<NodePadId, PadMetering>,
+    pub loudness:      HashMap<NodePadId, PadLoudness>,
+}
+
+/// Spectrum the engine of the task analyzed, handed to the task actor to go out with the next packet
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskSpectrum {
+    pub task_id:  AppTaskId,
+    pub play_id:  PlayId,
+    pub spectrum: HashMap<NodePadId, PadSpectrum>,
 }
 
 #[derive(Message, Clone, Debug)]
@@ -210,3 +384,926 @@ pub struct GetStreamPacket {
     pub serial:   u64,
     pub security: DomainSecurity,
 }
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<Vec<StreamingPacket>>")]
+pub struct ListStreamPacketsAfter {
+    pub task_id:  AppTaskId,
+    pub play_id:  PlayId,
+    pub serial:   u64,
+    pub security: DomainSecurity,
+}
+
+/// Packets of a play recorded to disk, those of the last `seconds_back` seconds or all that are kept if not set
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<Vec<StreamingPacket>>")]
+pub struct ListRecordedPackets {
+    pub task_id:      AppTaskId,
+    pub play_id:      PlayId,
+    pub seconds_back: Option<f64>,
+}
+
+/// Cached packets of a stream by serial, serials no longer cached are left out
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<Vec<StreamingPacket>>")]
+pub struct ListStreamPackets {
+    pub task_id:  AppTaskId,
+    pub play_id:  PlayId,
+    pub serials:  Vec<u64>,
+    pub security: DomainSecurity,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskSpecDiff>")]
+pub struct GetTaskSpecDiff {
+    pub task_id:  AppTaskId,
+    pub security: DomainSecurity,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct TaskSpecDiff {
+    #[schema(value_type = Object)]
+    pub task_id:         AppTaskId,
+    pub domain_revision: u64,
+    pub engine_revision: Option<u64>,
+    #[schema(value_type = Option<String>)]
+    pub engine_acked_at: Option<Timestamp>,
+    pub in_sync:         bool,
+    pub diff:            String,
+}
+
+/// Elements of a task spec, identified by their node or connection IDs
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct TaskSpecElements {
+    #[serde(default)]
+    pub fixed:       HashSet<String>,
+    #[serde(default)]
+    pub mixers:      HashSet<String>,
+    #[serde(default)]
+    pub connections: HashSet<String>,
+}
+
+/// Safe mode state of a task, entered when the engine repeatedly rejects the task spec
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct TaskSafeMode {
+    #[schema(value_type = Object)]
+    pub task_id:              AppTaskId,
+    pub active:               bool,
+    pub consecutive_failures: usize,
+    #[schema(value_type = Option<String>)]
+    pub entered_at:           Option<Timestamp>,
+    pub last_error:           Option<String>,
+    /// Elements withheld from the engine
+    pub disabled:             TaskSpecElements,
+    /// Elements currently being re-enabled, disabled again if the engine rejects them
+    pub pending:              TaskSpecElements,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskSafeMode {
+    pub task_id:   AppTaskId,
+    pub safe_mode: TaskSafeMode,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskSafeMode>")]
+pub struct GetTaskSafeMode {
+    pub task_id:  AppTaskId,
+    pub security: DomainSecurity,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskSafeMode>")]
+pub struct EnableTaskSpecElements {
+    pub task_id:  AppTaskId,
+    pub elements: TaskSpecElements,
+    pub security: DomainSecurity,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskRoutingVerification {
+    pub task_id:      AppTaskId,
+    pub verification: TaskRoutingVerification,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskRoutingVerification>")]
+pub struct GetTaskRoutingVerification {
+    pub task_id:  AppTaskId,
+    pub security: DomainSecurity,
+}
+
+/// Verify the routing of a task again, e.g. after fixing a patch that failed verification
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskRoutingVerification>")]
+pub struct VerifyTaskRouting {
+    pub task_id:  AppTaskId,
+    pub security: DomainSecurity,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskKeyScopes {
+    pub task_id: AppTaskId,
+    pub scopes:  TaskKeyScopes,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskKeyScopes>")]
+pub struct GetTaskKeyScopes {
+    pub task_id:  AppTaskId,
+    pub security: DomainSecurity,
+}
+
+/// Fail unless the caller has access to the task with at least the given scope, for data kept outside of the task
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult")]
+pub struct RequireTaskScope {
+    pub task_id:  AppTaskId,
+    pub security: DomainSecurity,
+    pub scope:    SecureKeyScope,
+}
+
+/// Restrict a secure key of a task to a scope, or give it full access again when `scope` is `None`
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct TaskKeyScopeUpdate {
+    #[schema(value_type = String)]
+    pub secure_key: SecureKey,
+    pub scope:      Option<SecureKeyScope>,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskKeyScopes>")]
+pub struct SetTaskKeyScope {
+    pub task_id:  AppTaskId,
+    pub update:   TaskKeyScopeUpdate,
+    pub security: DomainSecurity,
+}
+
+/// Replace a secure key of a task with a new one carrying the same permissions and scope
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct TaskSecureKeyRotation {
+    #[schema(value_type = String)]
+    pub secure_key: SecureKey,
+    /// Key to rotate to, a random one is generated when missing
+    #[schema(value_type = Option<String>)]
+    pub new_key:    Option<SecureKey>,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskSecurity>")]
+pub struct RotateTaskSecureKey {
+    pub task_id:  AppTaskId,
+    pub rotation: TaskSecureKeyRotation,
+    pub security: DomainSecurity,
+}
+
+/// Remove a secure key from a task, clients attached with it are disconnected
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct TaskSecureKeyRevocation {
+    #[schema(value_type = String)]
+    pub secure_key: SecureKey,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskSecurity>")]
+pub struct RevokeTaskSecureKey {
+    pub task_id:    AppTaskId,
+    pub revocation: TaskSecureKeyRevocation,
+    pub security:   DomainSecurity,
+}
+
+/// A live hardware input a track records and monitors, so it can be tracked to instead of only playing media
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct TrackHardwareInput {
+    /// First interface input channel, numbered as in the fixed instance routing config. The track takes as many
+    /// consecutive channels as it has
+    pub channel: usize,
+}
+
+pub type TaskTrackInputs = HashMap<TrackNodeId, TrackHardwareInput>;
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskTrackInputs {
+    pub task_id: AppTaskId,
+    pub inputs:  TaskTrackInputs,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskTrackInputs>")]
+pub struct GetTaskTrackInputs {
+    pub task_id:  AppTaskId,
+    pub security: DomainSecurity,
+}
+
+/// Record a track from a hardware input, or go back to playing its media only when `input` is `None`
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct TaskTrackInputUpdate {
+    #[schema(value_type = String)]
+    pub track_id: TrackNodeId,
+    pub input:    Option<TrackHardwareInput>,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskTrackInputs>")]
+pub struct SetTaskTrackInput {
+    pub task_id:  AppTaskId,
+    pub update:   TaskTrackInputUpdate,
+    pub security: DomainSecurity,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyEngineExtEvent {
+    pub engine_id: EngineId,
+    pub event:     EngineExtEvent,
+}
+
+/// Whether plays record the tracks with hardware inputs, each recording becomes a take
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskRecording {
+    pub armed:       bool,
+    /// While looping, every pass over the segment becomes a take of its own instead of only the last one
+    #[serde(default)]
+    pub loop_record: bool,
+    /// Only record within this region of the timeline while the play goes on around it
+    #[serde(default)]
+    pub punch:       Option<TaskPunchRegion>,
+}
+
+impl TaskRecording {
+    /// Check that the punch region is a part of the timeline the engine can record
+    pub fn validate(&self) -> Result<(), String> {
+        let punch = match &self.punch {
+            Some(punch) => punch,
+            None => return Ok(()),
+        };
+
+        if !punch.segment.start.is_finite() || punch.segment.start < 0.0 {
+            return Err(format!("Punch region starts at invalid time {}", punch.segment.start));
+        }
+        if !punch.segment.length.is_finite() || punch.segment.length <= 0.0 {
+            return Err(format!("Punch region has invalid length {}", punch.segment.length));
+        }
+
+        Ok(())
+    }
+}
+
+/// Region of the timeline recordings punch in and out of, the takes recorded span the region
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskPunchRegion {
+    #[schema(value_type = Object)]
+    pub segment: TimeSegment,
+    /// Tracks with hardware inputs that record, all of them if empty. Tracks not in the spec are ignored
+    #[serde(default)]
+    #[schema(value_type = Vec<String>)]
+    pub tracks:  HashSet<TrackNodeId>,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskRecording {
+    pub task_id:   AppTaskId,
+    pub recording: TaskRecording,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskRecording>")]
+pub struct SetTaskRecording {
+    pub task_id:   AppTaskId,
+    pub recording: TaskRecording,
+    pub security:  DomainSecurity,
+}
+
+/// Musical lead-in of plays and recordings, the engine starts playing `pre_roll` seconds and `count_in_bars` bars of
+/// metronome clicks before the requested position
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskLeadIn {
+    /// Seconds of the project played before the requested position
+    #[serde(default)]
+    pub pre_roll:      f64,
+    /// Bars of metronome clicks before the pre-roll, at the tempo of the project
+    #[serde(default)]
+    pub count_in_bars: u32,
+}
+
+impl TaskLeadIn {
+    pub fn is_none(&self) -> bool {
+        self.pre_roll <= 0.0 && self.count_in_bars == 0
+    }
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskLeadIn {
+    pub task_id: AppTaskId,
+    pub lead_in: TaskLeadIn,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskLeadIn>")]
+pub struct SetTaskLeadIn {
+    pub task_id:  AppTaskId,
+    pub lead_in:  TaskLeadIn,
+    pub security: DomainSecurity,
+}
+
+/// How a task trades latency for stability of its stream
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskLatencyProfile {
+    /// Larger encoder blocks, batched packets and deep socket queues that ride out network hiccups
+    #[default]
+    Stable,
+    /// Small encoder blocks, packets sent as soon as they hold audio and shallow socket queues that drop stale audio
+    /// instead of falling behind, for tracking and remote monitoring
+    LowLatency,
+}
+
+impl TaskLatencyProfile {
+    pub fn max_packet_age_ms(&self, opts: &TaskOpts) -> usize {
+        match self {
+            TaskLatencyProfile::Stable => opts.max_packet_age_ms,
+            TaskLatencyProfile::LowLatency => opts.low_latency_max_packet_age_ms.min(opts.max_packet_age_ms),
+        }
+    }
+
+    pub fn max_packet_audio_frames(&self, opts: &TaskOpts) -> usize {
+        match self {
+            TaskLatencyProfile::Stable => opts.max_packet_audio_frames,
+            TaskLatencyProfile::LowLatency => opts.low_latency_max_packet_audio_frames
+                                                  .min(opts.max_packet_audio_frames)
+                                                  .max(1),
+        }
+    }
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskLatencyProfile {
+    pub task_id: AppTaskId,
+    pub profile: TaskLatencyProfile,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskLatencyProfile>")]
+pub struct SetTaskLatencyProfile {
+    pub task_id:  AppTaskId,
+    pub profile:  TaskLatencyProfile,
+    pub security: DomainSecurity,
+}
+
+/// Samples per Opus frame at 48 kHz, 2.5 to 60 ms
+pub const OPUS_FRAME_SIZES: [usize; 6] = [120, 240, 480, 960, 1920, 2880];
+
+/// Codec the audio a task streams is compressed with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case", tag = "codec")]
+pub enum TaskStreamCodec {
+    /// Lossless, at the sample rate and bit depth of the play
+    #[default]
+    Flac,
+    /// Lossy, at 48 kHz whatever the sample rate of the play, one Opus packet per compressed audio buffer so web
+    /// clients can decode it with WebCodecs
+    Opus {
+        /// Bits per second, 6000 to 510000
+        bitrate:    u32,
+        /// Samples per frame at 48 kHz, one of 120, 240, 480, 960, 1920 or 2880
+        frame_size: usize,
+    },
+}
+
+impl TaskStreamCodec {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            TaskStreamCodec::Flac => Ok(()),
+            TaskStreamCodec::Opus { bitrate, frame_size } => {
+                if !(6_000..=510_000).contains(bitrate) {
+                    return Err(format!("Opus bitrate must be between 6000 and 510000 bits per second, not {bitrate}"));
+                }
+
+                if !OPUS_FRAME_SIZES.contains(frame_size) {
+                    return Err(format!("Opus frame size must be one of {OPUS_FRAME_SIZES:?} samples, not \
+                                        {frame_size}"));
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskStreamCodec {
+    pub task_id: AppTaskId,
+    pub codec:   TaskStreamCodec,
+}
+
+/// Bitrate the clients of a play can take, issued by the sockets supervisor as their links degrade and recover
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyStreamQuality {
+    pub task_id: AppTaskId,
+    pub play_id: PlayId,
+    /// Bits per second
+    pub bitrate: u32,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskStreamCodec>")]
+pub struct SetTaskStreamCodec {
+    pub task_id:  AppTaskId,
+    pub codec:    TaskStreamCodec,
+    pub security: DomainSecurity,
+}
+
+/// Integrated loudness the engine normalizes the stream of a task to, so plays compared against each other are not
+/// biased by their level. Metering and renders are not affected
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskLoudnessTarget {
+    /// Target in LUFS, -70 to 0. Tasks without one stream at the engine default, if the engine has one
+    #[serde(default)]
+    pub target_lufs: Option<f64>,
+}
+
+impl TaskLoudnessTarget {
+    pub fn validate(&self) -> Result<(), String> {
+        match self.target_lufs {
+            Some(target_lufs) if !(-70.0..=0.0).contains(&target_lufs) => {
+                Err(format!("Loudness target must be between -70 and 0 LUFS, not {target_lufs}"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskLoudnessTarget {
+    pub task_id: AppTaskId,
+    pub target:  TaskLoudnessTarget,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskLoudnessTarget>")]
+pub struct SetTaskLoudnessTarget {
+    pub task_id:  AppTaskId,
+    pub target:   TaskLoudnessTarget,
+    pub security: DomainSecurity,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskWatermark {
+    pub task_id:   AppTaskId,
+    pub watermark: TaskWatermark,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskWatermark>")]
+pub struct SetTaskWatermark {
+    pub task_id:   AppTaskId,
+    pub watermark: TaskWatermark,
+    pub security:  DomainSecurity,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskTempoMap {
+    pub task_id:   AppTaskId,
+    pub tempo_map: TaskTempoMap,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskTempoMap>")]
+pub struct SetTaskTempoMap {
+    pub task_id:   AppTaskId,
+    pub tempo_map: TaskTempoMap,
+    pub security:  DomainSecurity,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskTempoMap>")]
+pub struct GetTaskTempoMap {
+    pub task_id:  AppTaskId,
+    pub security: DomainSecurity,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskPlaylist {
+    pub task_id:  AppTaskId,
+    pub playlist: TaskPlaylist,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskPlaylist>")]
+pub struct SetTaskPlaylist {
+    pub task_id:  AppTaskId,
+    pub playlist: TaskPlaylist,
+    pub security: DomainSecurity,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskPlaylist>")]
+pub struct GetTaskPlaylist {
+    pub task_id:  AppTaskId,
+    pub security: DomainSecurity,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskTrackGroups {
+    pub task_id:      AppTaskId,
+    pub track_groups: TaskTrackGroups,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskTrackGroups>")]
+pub struct SetTaskTrackGroups {
+    pub task_id:      AppTaskId,
+    pub track_groups: TaskTrackGroups,
+    pub security:     DomainSecurity,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskTrackGroups>")]
+pub struct GetTaskTrackGroups {
+    pub task_id:  AppTaskId,
+    pub security: DomainSecurity,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskEnvelopes {
+    pub task_id:   AppTaskId,
+    pub envelopes: TaskEnvelopes,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskEnvelopes>")]
+pub struct SetTaskEnvelopes {
+    pub task_id:   AppTaskId,
+    pub envelopes: TaskEnvelopes,
+    pub security:  DomainSecurity,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskEnvelopes>")]
+pub struct GetTaskEnvelopes {
+    pub task_id:  AppTaskId,
+    pub security: DomainSecurity,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskMediaFades {
+    pub task_id: AppTaskId,
+    pub fades:   TaskMediaFades,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskMediaFades>")]
+pub struct SetTaskMediaFades {
+    pub task_id:  AppTaskId,
+    pub fades:    TaskMediaFades,
+    pub security: DomainSecurity,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskMediaFades>")]
+pub struct GetTaskMediaFades {
+    pub task_id:  AppTaskId,
+    pub security: DomainSecurity,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskMediaRates {
+    pub task_id: AppTaskId,
+    pub rates:   TaskMediaRates,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskMediaRatesState>")]
+pub struct SetTaskMediaRates {
+    pub task_id:  AppTaskId,
+    pub rates:    TaskMediaRates,
+    pub security: DomainSecurity,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskMediaRatesState>")]
+pub struct GetTaskMediaRates {
+    pub task_id:  AppTaskId,
+    pub security: DomainSecurity,
+}
+
+/// The engine reported the timeline lengths of the track media of a task at their rates
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskMediaLengths {
+    pub task_id: AppTaskId,
+    pub lengths: TaskMediaLengths,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskMonitor {
+    pub task_id: AppTaskId,
+    pub monitor: TaskMonitor,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskMonitor>")]
+pub struct SetTaskMonitor {
+    pub task_id:  AppTaskId,
+    pub monitor:  TaskMonitor,
+    pub security: DomainSecurity,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskMonitor>")]
+pub struct GetTaskMonitor {
+    pub task_id:  AppTaskId,
+    pub security: DomainSecurity,
+}
+
+/// Connection values set by clients on a task, before the track groups are applied
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskConnectionFaders {
+    pub task_id: AppTaskId,
+    pub faders:  HashMap<NodeConnectionId, ConnectionValues>,
+}
+
+/// A recording of a track, registered as a media object. Put it in a track media spec to select it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TrackTake {
+    #[schema(value_type = String)]
+    pub media_id:      AppMediaObjectId,
+    #[schema(value_type = String)]
+    pub track_id:      TrackNodeId,
+    /// Number of the take among the takes of the track over the same segment, starting at 1
+    pub take:          usize,
+    #[schema(value_type = Object)]
+    pub segment:       TimeSegment,
+    /// Loop pass the take was recorded in, starting at 1, for takes of a loop recording
+    #[serde(default)]
+    pub pass:          Option<usize>,
+    /// Part of the media object holding the take, the passes of a loop recording share one file
+    #[serde(default)]
+    #[schema(value_type = Option<Object>)]
+    pub media_segment: Option<TimeSegment>,
+    #[schema(value_type = String)]
+    pub recorded_at:   Timestamp,
+}
+
+impl TrackTake {
+    pub fn overlaps(&self, track_id: &TrackNodeId, segment: &TimeSegment) -> bool {
+        &self.track_id == track_id && self.segment.start < segment.end() && segment.start < self.segment.end()
+    }
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskTake {
+    pub task_id: AppTaskId,
+    pub take:    TrackTake,
+}
+
+/// A diagnostic bundle the engine captured of a task when it reported an error, registered as a media object
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskDiagnostics {
+    #[schema(value_type = String)]
+    pub media_id:    AppMediaObjectId,
+    /// The error the engine reported
+    pub error:       String,
+    #[schema(value_type = String)]
+    pub captured_at: Timestamp,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskDiagnostics {
+    pub task_id:     AppTaskId,
+    pub diagnostics: TaskDiagnostics,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<Vec<TaskDiagnostics>>")]
+pub struct GetTaskDiagnostics {
+    pub task_id:  AppTaskId,
+    pub security: DomainSecurity,
+}
+
+/// What a cancelled render of a task rendered before it was cancelled
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskPartialRender {
+    #[schema(value_type = String)]
+    pub render_id:       RenderId,
+    /// Media object of the partially rendered file, `None` when nothing was rendered
+    #[schema(value_type = Option<String>)]
+    pub media_id:        Option<AppMediaObjectId>,
+    /// Seconds of the render segment rendered
+    pub rendered_length: f64,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskRenderCancelled {
+    pub task_id: AppTaskId,
+    pub partial: TaskPartialRender,
+}
+
+/// A finished render converted to one of the formats requested with it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskRenderOutput {
+    pub format:   RenderFormat,
+    /// Media object of the converted file, `None` when the conversion failed
+    #[schema(value_type = Option<String>)]
+    pub media_id: Option<AppMediaObjectId>,
+    pub error:    Option<String>,
+}
+
+/// The formats a finished render of a task was converted to
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskRenderOutputs {
+    #[schema(value_type = String)]
+    pub render_id: RenderId,
+    pub outputs:   Vec<TaskRenderOutput>,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskRenderOutputs {
+    pub task_id: AppTaskId,
+    pub outputs: TaskRenderOutputs,
+}
+
+/// A render of a task finished, `path` is where the engine rendered to
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskRenderFinished {
+    pub task_id:       AppTaskId,
+    pub render_id:     RenderId,
+    pub path:          String,
+    pub normalization: Option<TaskRenderNormalization>,
+}
+
+/// A finished render normalized to the loudness requested with it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskRenderNormalized {
+    #[schema(value_type = String)]
+    pub render_id: RenderId,
+    /// Media object of the normalized file, `None` when the normalization failed
+    #[schema(value_type = Option<String>)]
+    pub media_id:  Option<AppMediaObjectId>,
+    pub loudness:  Option<RenderLoudness>,
+    pub error:     Option<String>,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskRenderNormalized {
+    pub task_id:    AppTaskId,
+    pub normalized: TaskRenderNormalized,
+}
+
+/// Render both variants of a null test and compare them, responds once the first variant is rendering
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskRendering>")]
+pub struct RunTaskNullTest {
+    pub task_id:  AppTaskId,
+    pub request:  TaskNullTestRequest,
+    pub security: DomainSecurity,
+}
+
+/// How the renders of a null test compare
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskNullTestReport {
+    #[schema(value_type = String)]
+    pub render_a:   RenderId,
+    #[schema(value_type = String)]
+    pub render_b:   RenderId,
+    /// Spec revisions the variants were based on
+    pub revision_a: u64,
+    pub revision_b: u64,
+    /// Media object the report is stored as, `None` when the null test failed
+    #[schema(value_type = Option<String>)]
+    pub media_id:   Option<AppMediaObjectId>,
+    pub metrics:    Option<NullTestMetrics>,
+    pub error:      Option<String>,
+}
+
+/// Both renders of a null test finished, `path_a` and `path_b` are where the engine rendered them to
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskNullTestRendered {
+    pub task_id: AppTaskId,
+    pub report:  TaskNullTestReport,
+    pub path_a:  String,
+    pub path_b:  String,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskNullTest {
+    pub task_id: AppTaskId,
+    pub report:  TaskNullTestReport,
+}
+
+/// Takes of a task by track, in recording order
+pub type TaskTakeLanes = HashMap<TrackNodeId, Vec<TrackTake>>;
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskTakeLanes>")]
+pub struct GetTaskTakes {
+    pub task_id:  AppTaskId,
+    pub security: DomainSecurity,
+}
+
+/// Last clock status an engine reported
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct EngineClockReport {
+    #[schema(value_type = String)]
+    pub engine_id:  EngineId,
+    #[schema(value_type = String)]
+    pub updated_at: Timestamp,
+    pub status:     EngineClockStatus,
+}
+
+/// Issued when the audio interface of an engine loses clock, or when its first report is already unlocked
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyEngineClockLost {
+    pub engine_id: EngineId,
+    pub status:    EngineClockStatus,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<Vec<EngineClockReport>>")]
+pub struct GetEngineClocks;
+
+/// Last resource usage an engine reported, new tasks are not allocated to it while it is `overloaded`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct EngineResourceReport {
+    #[schema(value_type = String)]
+    pub engine_id:  EngineId,
+    #[schema(value_type = String)]
+    pub updated_at: Timestamp,
+    pub resources:  EngineResources,
+    pub overloaded: bool,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<Vec<EngineResourceReport>>")]
+pub struct GetEngineResources;
+
+/// Play a test tone on an engine and wait for the levels its inputs measured
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<EngineTestToneResult>")]
+pub struct RunEngineTestTone {
+    pub engine_id: EngineId,
+    pub tone:      EngineTestTone,
+}
+
+/// Write down the parameters of the fixed instances of a task, storing the recall sheet as media objects of its app
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<TaskRecallSheet>")]
+pub struct GenerateTaskRecallSheet {
+    pub task_id:  AppTaskId,
+    pub security: DomainSecurity,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskRecallSheet {
+    pub task_id: AppTaskId,
+    pub sheet:   TaskRecallSheet,
+}
+
+/// Issued when an engine reports it started afresh, with the sessions it reloaded and the hashes of their specs
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyEngineStarted {
+    pub engine_id: EngineId,
+    pub sessions:  HashMap<AppTaskId, String>,
+}
+
+/// Issued when a task set its spec again on an engine that started afresh
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct NotifyTaskEngineRestarted {
+    pub task_id:     AppTaskId,
+    pub engine_id:   EngineId,
+    /// The engine reloaded the session with the spec the task had set, otherwise the session is created anew
+    pub restored:    bool,
+    /// Play or render of the task the restart cut short
+    pub interrupted: Option<TaskPlayState>,
+}
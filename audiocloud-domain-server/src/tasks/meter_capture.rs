@@ -0,0 +1,381 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use actix::{Actor, ActorFutureExt, Context, ContextFutureSpawner, Handler, WrapFuture};
+use actix_broker::BrokerSubscribe;
+use anyhow::anyhow;
+use clap::Args;
+use tracing::*;
+
+use audiocloud_api::{AppMediaObjectId, AppTaskId, MediaObject, MediaObjectId, NodePadId, PadMetering};
+
+use crate::db::Db;
+use crate::tasks::engine_ext::PadLoudness;
+use crate::tasks::{NotifyTaskDeactivated, NotifyTaskMetering};
+
+/// First bytes of every meter capture file, the last one is the version of the format
+pub const METER_CAPTURE_MAGIC: &[u8; 8] = b"ACMETER\x01";
+
+const RECORD_PAD: u8 = 0x01;
+const RECORD_PEAKS: u8 = 0x02;
+const RECORD_LOUDNESS: u8 = 0x03;
+
+#[derive(Args, Clone, Debug)]
+pub struct MeterCaptureOpts {
+    /// Record the metering of every task to a file, registered as a media object of the app when the task ends
+    #[clap(long, env)]
+    pub meter_capture: bool,
+
+    /// Milliseconds between captured readings of a pad, peaks in between are held
+    #[clap(long, env, default_value = "100")]
+    pub meter_capture_interval_ms: u64,
+}
+
+/// A reading read back from a meter capture file
+#[derive(Clone, Debug, PartialEq)]
+pub enum CapturedMeter {
+    /// Highest linear peak of each channel since the previous reading of the pad
+    Peaks {
+        at:     i64,
+        pad_id: String,
+        peaks:  Vec<f32>,
+    },
+    /// Latest loudness of the pad, not a number where the engine had no reading
+    Loudness {
+        at:              i64,
+        pad_id:          String,
+        momentary_lufs:  f32,
+        short_term_lufs: f32,
+        true_peak_db:    f32,
+    },
+}
+
+#[derive(Default)]
+struct PendingPad {
+    index:        u16,
+    written_at:   Option<i64>,
+    last_seen_at: i64,
+    peaks:        Option<Vec<f64>>,
+    loudness:     Option<PadLoudness>,
+}
+
+/// Writes metering to the compact binary format of meter capture files, at most one reading per pad and interval
+///
+/// After [`METER_CAPTURE_MAGIC`] come records, each starting with its kind. A pad record (`0x01`) names a pad with
+/// an index (u16) and the length (u16) and UTF-8 bytes of its ID, later records refer to the pad by index. A peaks
+/// record (`0x02`) has milliseconds since the epoch (i64), the pad index, a channel count (u8) and the peak of each
+/// channel (f32). A loudness record (`0x03`) has milliseconds, the pad index and momentary, short-term loudness and
+/// true peak (f32 each). All numbers are little endian. Appending to a file starts the pad indices over, a pad record
+/// replaces the pad an index named before.
+pub struct MeterCaptureWriter<W: Write> {
+    out:      W,
+    interval: i64,
+    pads:     HashMap<NodePadId, PendingPad>,
+}
+
+impl<W: Write> MeterCaptureWriter<W> {
+    pub fn new(mut out: W, interval_ms: u64, with_header: bool) -> anyhow::Result<Self> {
+        if with_header {
+            out.write_all(METER_CAPTURE_MAGIC)?;
+        }
+
+        Ok(Self { out:      { out },
+                  interval: { interval_ms as i64 },
+                  pads:     { HashMap::new() }, })
+    }
+
+    pub fn push_peaks(&mut self, at: i64, pad_id: &NodePadId, metering: &PadMetering) -> anyhow::Result<()> {
+        let pad = self.pad(pad_id)?;
+        let peaks = pad.peaks.get_or_insert_with(Vec::new);
+
+        peaks.resize(peaks.len().max(metering.volume.len()), 0.0);
+        for (peak, volume) in peaks.iter_mut().zip(&metering.volume) {
+            *peak = peak.max(volume.abs());
+        }
+
+        self.write_due(at, pad_id)
+    }
+
+    pub fn push_loudness(&mut self, at: i64, pad_id: &NodePadId, loudness: &PadLoudness) -> anyhow::Result<()> {
+        self.pad(pad_id)?.loudness = Some(loudness.clone());
+        self.write_due(at, pad_id)
+    }
+
+    /// Write the readings held back by the interval and hand back the output
+    pub fn finish(mut self) -> anyhow::Result<W> {
+        let pending = self.pads
+                          .iter()
+                          .map(|(pad_id, pad)| (pad.last_seen_at, pad_id.clone()))
+                          .collect::<Vec<_>>();
+
+        for (at, pad_id) in pending {
+            self.write_pending(at, &pad_id)?;
+        }
+
+        self.out.flush()?;
+
+        Ok(self.out)
+    }
+
+    fn pad(&mut self, pad_id: &NodePadId) -> anyhow::Result<&mut PendingPad> {
+        if !self.pads.contains_key(pad_id) {
+            let index = u16::try_from(self.pads.len()).map_err(|_| anyhow!("Too many pads to capture"))?;
+            let name = pad_id.to_string();
+            let name_len = u16::try_from(name.len()).map_err(|_| anyhow!("Pad ID {name} too long to capture"))?;
+
+            self.out.write_all(&[RECORD_PAD])?;
+            self.out.write_all(&index.to_le_bytes())?;
+            self.out.write_all(&name_len.to_le_bytes())?;
+            self.out.write_all(name.as_bytes())?;
+
+            self.pads.insert(pad_id.clone(),
+                             PendingPad { index: { index },
+                                          ..Default::default() });
+        }
+
+        self.pads
+            .get_mut(pad_id)
+            .ok_or_else(|| anyhow!("Pad {pad_id} not captured"))
+    }
+
+    fn write_due(&mut self, at: i64, pad_id: &NodePadId) -> anyhow::Result<()> {
+        let pad = self.pad(pad_id)?;
+        pad.last_seen_at = at;
+
+        match pad.written_at {
+            Some(written_at) if at - written_at < self.interval => Ok(()),
+            _ => self.write_pending(at, pad_id),
+        }
+    }
+
+    fn write_pending(&mut self, at: i64, pad_id: &NodePadId) -> anyhow::Result<()> {
+        let pad = self.pads
+                      .get_mut(pad_id)
+                      .ok_or_else(|| anyhow!("Pad {pad_id} not captured"))?;
+
+        let index = pad.index;
+        let peaks = pad.peaks.take();
+        let loudness = pad.loudness.take();
+
+        if peaks.is_none() && loudness.is_none() {
+            return Ok(());
+        }
+
+        pad.written_at = Some(at);
+
+        if let Some(peaks) = peaks {
+            let count = peaks.len().min(u8::MAX as usize);
+
+            self.out.write_all(&[RECORD_PEAKS])?;
+            self.out.write_all(&at.to_le_bytes())?;
+            self.out.write_all(&index.to_le_bytes())?;
+            self.out.write_all(&[count as u8])?;
+            for peak in &peaks[..count] {
+                self.out.write_all(&(*peak as f32).to_le_bytes())?;
+            }
+        }
+
+        if let Some(loudness) = loudness {
+            self.out.write_all(&[RECORD_LOUDNESS])?;
+            self.out.write_all(&at.to_le_bytes())?;
+            self.out.write_all(&index.to_le_bytes())?;
+            for value in [loudness.momentary_lufs, loudness.short_term_lufs, loudness.true_peak_db] {
+                self.out.write_all(&(value.unwrap_or(f64::NAN) as f32).to_le_bytes())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Read back the readings of a meter capture file, in the order they were captured
+pub fn read_meter_capture(bytes: &[u8]) -> anyhow::Result<Vec<CapturedMeter>> {
+    let mut rest = bytes.strip_prefix(METER_CAPTURE_MAGIC.as_slice())
+                        .ok_or_else(|| anyhow!("Not a meter capture file"))?;
+
+    let mut pads = HashMap::<u16, String>::new();
+    let mut readings = vec![];
+
+    while let Some((&kind, record)) = rest.split_first() {
+        rest = record;
+        match kind {
+            RECORD_PAD => {
+                let index = u16::from_le_bytes(take(&mut rest, 2)?.try_into()?);
+                let len = u16::from_le_bytes(take(&mut rest, 2)?.try_into()?) as usize;
+                pads.insert(index, String::from_utf8(take(&mut rest, len)?.to_vec())?);
+            }
+            RECORD_PEAKS => {
+                let (at, pad_id) = read_stamp(&mut rest, &pads)?;
+                let count = take(&mut rest, 1)?[0] as usize;
+                let peaks = take(&mut rest, count * 4)?.chunks(4)
+                                                       .map(|peak| Ok(f32::from_le_bytes(peak.try_into()?)))
+                                                       .collect::<anyhow::Result<_>>()?;
+
+                readings.push(CapturedMeter::Peaks { at, pad_id, peaks });
+            }
+            RECORD_LOUDNESS => {
+                let (at, pad_id) = read_stamp(&mut rest, &pads)?;
+                let mut value = || -> anyhow::Result<f32> { Ok(f32::from_le_bytes(take(&mut rest, 4)?.try_into()?)) };
+
+                readings.push(CapturedMeter::Loudness { at:              { at },
+                                                        pad_id:          { pad_id },
+                                                        momentary_lufs:  { value()? },
+                                                        short_term_lufs: { value()? },
+                                                        true_peak_db:    { value()? }, });
+            }
+            other => return Err(anyhow!("Unknown meter capture record {other:#04x}")),
+        }
+    }
+
+    Ok(readings)
+}
+
+fn read_stamp(rest: &mut &[u8], pads: &HashMap<u16, String>) -> anyhow::Result<(i64, String)> {
+    let at = i64::from_le_bytes(take(rest, 8)?.try_into()?);
+    let index = u16::from_le_bytes(take(rest, 2)?.try_into()?);
+    let pad_id = pads.get(&index)
+                     .cloned()
+                     .ok_or_else(|| anyhow!("Reading of pad {index} before the pad was named"))?;
+
+    Ok((at, pad_id))
+}
+
+fn take<'a>(rest: &mut &'a [u8], len: usize) -> anyhow::Result<&'a [u8]> {
+    if rest.len() < len {
+        return Err(anyhow!("Truncated meter capture record"));
+    }
+
+    let (taken, remaining) = rest.split_at(len);
+    *rest = remaining;
+
+    Ok(taken)
+}
+
+struct TaskCapture {
+    media_id: AppMediaObjectId,
+    path:     PathBuf,
+    writer:   MeterCaptureWriter<BufWriter<File>>,
+}
+
+/// Captures the metering of active tasks to files in the media root, one file per task that grows over activations
+pub struct MeterCapture {
+    opts:       MeterCaptureOpts,
+    media_root: PathBuf,
+    db:         Db,
+    captures:   HashMap<AppTaskId, TaskCapture>,
+}
+
+impl MeterCapture {
+    pub fn new(opts: MeterCaptureOpts, media_root: PathBuf, db: Db) -> Self {
+        Self { opts:       { opts },
+               media_root: { media_root },
+               db:         { db },
+               captures:   { HashMap::new() }, }
+    }
+
+    /// Media object of the capture, at the path media objects of the app are uploaded from
+    fn open_capture(&self, task_id: &AppTaskId) -> anyhow::Result<TaskCapture> {
+        let media_id = AppMediaObjectId::new(task_id.app_id.clone(),
+                                             MediaObjectId::new(format!("meters-{}", task_id.task_id)));
+
+        let path = self.media_root
+                       .join(media_id.app_id.as_str())
+                       .join(media_id.media_id.as_str());
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let with_header = file.metadata()?.len() == 0;
+        let writer = MeterCaptureWriter::new(BufWriter::new(file), self.opts.meter_capture_interval_ms, with_header)?;
+
+        Ok(TaskCapture { media_id: { media_id },
+                         path:     { path },
+                         writer:   { writer }, })
+    }
+
+    fn capture(&mut self, msg: NotifyTaskMetering) -> anyhow::Result<()> {
+        if !self.captures.contains_key(&msg.task_id) {
+            let capture = self.open_capture(&msg.task_id)?;
+            self.captures.insert(msg.task_id.clone(), capture);
+        }
+
+        let writer = &mut self.captures
+                              .get_mut(&msg.task_id)
+                              .ok_or_else(|| anyhow!("Capture of task {} not open", msg.task_id))?
+                              .writer;
+
+        let at = msg.at.timestamp_millis();
+
+        for (pad_id, metering) in &msg.peak_metering {
+            writer.push_peaks(at, pad_id, metering)?;
+        }
+
+        for (pad_id, loudness) in &msg.loudness {
+            writer.push_loudness(at, pad_id, loudness)?;
+        }
+
+        Ok(())
+    }
+
+    /// Close the capture of the task and register its file as a media object
+    fn finish_capture(&mut self, task_id: AppTaskId, ctx: &mut Context<Self>) {
+        let TaskCapture { media_id, path, writer } = match self.captures.remove(&task_id) {
+            Some(capture) => capture,
+            None => return,
+        };
+
+        if let Err(error) = writer.finish() {
+            warn!(%error, %task_id, "Failed to finish meter capture");
+        }
+
+        let media = MediaObject { id:       { media_id.clone() },
+                                  metadata: { None },
+                                  path:     { Some(path.to_string_lossy().to_string()) },
+                                  download: { None },
+                                  upload:   { None },
+                                  revision: { 0 }, };
+
+        let db = self.db.clone();
+
+        async move { db.save_media(media).await }.into_actor(self)
+                                                 .map(move |res, _actor, _ctx| match res {
+                                                     Ok(()) => info!(%task_id, %media_id, "Meter capture saved"),
+                                                     Err(error) => {
+                                                         warn!(%error, %task_id, "Failed to save meter capture")
+                                                     }
+                                                 })
+                                                 .spawn(ctx);
+    }
+}
+
+impl Actor for MeterCapture {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.subscribe_system_async::<NotifyTaskMetering>(ctx);
+        self.subscribe_system_async::<NotifyTaskDeactivated>(ctx);
+    }
+}
+
+impl Handler<NotifyTaskMetering> for MeterCapture {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskMetering, _ctx: &mut Self::Context) -> Self::Result {
+        let task_id = msg.task_id.clone();
+        if let Err(error) = self.capture(msg) {
+            warn!(%error, %task_id, "Failed to capture metering");
+        }
+    }
+}
+
+impl Handler<NotifyTaskDeactivated> for MeterCapture {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskDeactivated, ctx: &mut Self::Context) -> Self::Result {
+        self.finish_capture(msg.task_id, ctx);
+    }
+}
@@ -1,21 +1,69 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
 use actix::{Actor, Addr};
 use anyhow::anyhow;
 use clap::Args;
 use once_cell::sync::OnceCell;
 use tracing::*;
 
+use audiocloud_api::audio_engine::EngineCommand;
 use audiocloud_api::cloud::domains::{DomainConfig, FixedInstanceRoutingMap};
+pub use click::{ClickTempo, TaskClick};
+use engine_ext::EngineSpectrumSettings;
+pub use envelopes::{EnvelopePoint, EnvelopeShape, EnvelopeTarget, TaskEnvelope, TaskEnvelopes};
+pub use fades::{FadeShape, MediaFades, TaskMediaFades};
+pub use media_rates::{MediaRate, StretchMode, TaskMediaLengths, TaskMediaRates, TaskMediaRatesState};
 pub use messages::*;
+use meter_capture::{MeterCapture, MeterCaptureOpts};
+pub use monitor::TaskMonitor;
+pub use null_test::{NullTestBand, NullTestMetrics, TaskNullTestRequest};
+use null_test::{NullTestOpts, NullTester};
+pub use playlist::TaskPlaylist;
+pub use recall_sheet::{RecallInstance, TaskRecallSheet};
+pub use render_normalization::TaskRenderNormalization;
+use render_normalization::{RenderNormalizationOpts, RenderNormalizer};
+pub use settings::TaskSettings;
+pub use routing_verification::{
+    plan_routing_chains, RoutingChain, RoutingChainCheck, RoutingVerificationState, TaskRoutingVerification,
+};
+use stream_recorder::{StreamRecorder, StreamRecorderOpts};
 use supervisor::TasksSupervisor;
+pub use tempo_map::{BarBeat, TaskTempoMap, TempoChange};
+pub use track_groups::{TaskTrackGroups, TrackGroup};
+pub use watermark::{PlayWatermark, TaskWatermark, WatermarkMode};
 
 use crate::db::Db;
+use crate::fixed_instances::ModelSharingMap;
+use crate::nats::Idempotency;
 
+pub mod click;
+pub mod engine_ext;
+pub mod envelopes;
+pub mod event_stream;
+pub mod fades;
+pub mod media_rates;
 pub mod messages;
+pub mod meter_capture;
+pub mod monitor;
+pub mod null_test;
+pub mod playlist;
+pub mod recall_sheet;
+pub mod render_normalization;
+pub mod routing_verification;
+pub mod settings;
+pub mod stream_continuity;
+pub mod stream_recorder;
 pub mod supervisor;
 mod task;
 mod task_engine;
 mod task_fixed_instance;
 mod task_media_objects;
+pub mod tempo_map;
+#[cfg(test)]
+mod tests;
+pub mod track_groups;
+pub mod watermark;
 
 static TASKS_SUPERVISOR: OnceCell<Addr<TasksSupervisor>> = OnceCell::new();
 
@@ -23,28 +71,63 @@ pub fn get_tasks_supervisor() -> &'static Addr<TasksSupervisor> {
     TASKS_SUPERVISOR.get().expect("Tasks supervisor not initialized")
 }
 
+static STREAM_RECORDER: OnceCell<Addr<StreamRecorder>> = OnceCell::new();
+
+/// The stream recorder, if play sessions are recorded
+pub fn get_stream_recorder() -> Option<&'static Addr<StreamRecorder>> {
+    STREAM_RECORDER.get()
+}
+
 #[instrument(skip_all, err)]
-pub fn init(db: Db, opts: &TaskOpts, config: &DomainConfig, routing: FixedInstanceRoutingMap) -> anyhow::Result<()> {
-    let supervisor = TasksSupervisor::new(db, opts, config, routing)?;
+pub fn init(db: Db,
+            opts: &TaskOpts,
+            config: &DomainConfig,
+            routing: FixedInstanceRoutingMap,
+            model_sharing: ModelSharingMap,
+            media_root: PathBuf)
+            -> anyhow::Result<()> {
+    if opts.meter_capture.meter_capture {
+        MeterCapture::new(opts.meter_capture.clone(), media_root.clone(), db.clone()).start();
+    }
+
+    NullTester::new(opts.null_test.clone(), media_root.clone(), db.clone()).start();
+    RenderNormalizer::new(opts.render_normalization.clone(), media_root.clone(), db.clone()).start();
+
+    let supervisor = TasksSupervisor::new(db, opts, config, routing, model_sharing, media_root)?;
 
     TASKS_SUPERVISOR.set(supervisor.start())
                     .map_err(|_| anyhow!("Tasks supervisor already initialized"))?;
 
+    if opts.stream_recorder.is_enabled() {
+        STREAM_RECORDER.set(StreamRecorder::new(opts.stream_recorder.clone()).start())
+                       .map_err(|_| anyhow!("Stream recorder already initialized"))?;
+    }
+
     Ok(())
 }
 
+/// Load what the first requests to the domain would otherwise wait for, before it goes online
+#[instrument(skip_all, err)]
+pub async fn warm_up() -> anyhow::Result<TasksWarmedUp> {
+    Ok(get_tasks_supervisor().send(WarmUpTasks).await?)
+}
+
 #[instrument(skip_all, err)]
 pub async fn become_online() -> anyhow::Result<()> {
     get_tasks_supervisor().send(BecomeOnline).await?;
     Ok(())
 }
 
-#[derive(Args, Clone, Debug, Copy)]
+#[derive(Args, Clone, Debug)]
 pub struct TaskOpts {
     /// Number of seconds to keep task information in the supervisor before forgetting it
     #[clap(long, env, default_value = "3600")]
     pub task_grace_seconds: usize,
 
+    /// Seconds between compactions of the database, which drop what is older than the task grace period
+    #[clap(long, env, default_value = "3600")]
+    pub db_compaction_interval_seconds: u64,
+
     /// Send streaming packets to clients as soon as they exceed specified age in milliseconds (even if no audio captured)
     #[clap(long, env, default_value = "250")]
     pub max_packet_age_ms: usize,
@@ -53,7 +136,110 @@ pub struct TaskOpts {
     #[clap(long, env, default_value = "4")]
     pub max_packet_audio_frames: usize,
 
+    /// Maximum age in milliseconds of streaming packets of tasks with the low latency profile
+    #[clap(long, env, default_value = "20")]
+    pub low_latency_max_packet_age_ms: usize,
+
+    /// Maximum count of compressed audio buffers in streaming packets of tasks with the low latency profile
+    #[clap(long, env, default_value = "1")]
+    pub low_latency_max_packet_audio_frames: usize,
+
+    /// Bands of the spectrum engines report of the streamed mixer output, 0 disables spectrum reports
+    #[clap(long, env, default_value = "0")]
+    pub spectrum_bands: usize,
+
+    /// Spectrum reports per second engines compute, streaming packets carry the latest one
+    #[clap(long, env, default_value = "10")]
+    pub spectrum_rate_hz: f64,
+
     /// Milliseconds to keep streaming packets cached if for redelivery
     #[clap(long, env, default_value = "60000")]
     pub packet_cache_max_retention_ms: usize,
+
+    /// Withhold the failing elements of the task spec from the engine after it rejects the spec this many times in a
+    /// row, 0 to disable
+    #[clap(long, env, default_value = "3")]
+    pub safe_mode_spec_failures: usize,
+
+    /// Comma separated IDs of engines that mix natively without REAPER, preferred for tasks without instances
+    #[clap(long, env, value_delimiter = ',')]
+    pub native_engines: Vec<String>,
+
+    /// CPU usage of an engine, in percent of all cores, above which it counts as overloaded and gets no new tasks
+    #[clap(long, env, default_value = "85")]
+    pub engine_max_cpu_usage: f64,
+
+    /// Send a test tone through every reserved fixed instance before a task may play or render
+    #[clap(long, env)]
+    pub verify_task_routing: bool,
+
+    /// Level of the routing verification tone, in dBFS
+    #[clap(long, env, default_value = "-18", allow_hyphen_values = true)]
+    pub routing_verification_level_db: f64,
+
+    /// How far in dB the returned level may be from the sent level before the chain counts as broken
+    #[clap(long, env, default_value = "12")]
+    pub routing_verification_tolerance_db: f64,
+
+    /// Milliseconds the routing verification tone plays through each instance channel
+    #[clap(long, env, default_value = "500")]
+    pub routing_verification_duration_ms: u64,
+
+    /// Milliseconds to keep retrying spec and instance updates to the engine while NATS is unreachable
+    #[clap(long, env, default_value = "30000")]
+    pub engine_spec_deadline_ms: u64,
+
+    /// Milliseconds to keep retrying transport commands to the engine while NATS is unreachable
+    #[clap(long, env, default_value = "5000")]
+    pub engine_transport_deadline_ms: u64,
+
+    /// Seconds between diagnostic bundles engines capture of a task when they report errors, 0 disables them
+    #[clap(long, env, default_value = "60")]
+    pub diagnostics_interval_seconds: u64,
+
+    #[clap(flatten)]
+    pub stream_recorder: StreamRecorderOpts,
+
+    #[clap(flatten)]
+    pub meter_capture: MeterCaptureOpts,
+
+    #[clap(flatten)]
+    pub render_normalization: RenderNormalizationOpts,
+
+    #[clap(flatten)]
+    pub null_test: NullTestOpts,
+}
+
+impl TaskOpts {
+    /// How long a command to the engine may be retried, a late transport command is worse than a failed one
+    pub fn engine_command_deadline(&self, cmd: &EngineCommand) -> Duration {
+        match cmd {
+            EngineCommand::SetSpec { .. } | EngineCommand::ModifySpec { .. } | EngineCommand::Instances { .. } => {
+                Duration::from_millis(self.engine_spec_deadline_ms)
+            }
+            _ => self.engine_transport_deadline(),
+        }
+    }
+
+    /// Whether a command to the engine is sent again after an attempt timed out, a play or render would start twice
+    pub fn engine_command_idempotency(&self, cmd: &EngineCommand) -> Idempotency {
+        match cmd {
+            EngineCommand::SetSpec { .. } | EngineCommand::Instances { .. } => Idempotency::Idempotent,
+            _ => Idempotency::NotIdempotent,
+        }
+    }
+
+    pub fn engine_transport_deadline(&self) -> Duration {
+        Duration::from_millis(self.engine_transport_deadline_ms)
+    }
+
+    /// Spectrum reports engines are asked for, none while they are disabled
+    pub fn spectrum(&self) -> Option<EngineSpectrumSettings> {
+        if self.spectrum_bands == 0 || self.spectrum_rate_hz <= 0.0 {
+            return None;
+        }
+
+        Some(EngineSpectrumSettings { bands:   { self.spectrum_bands },
+                                      rate_hz: { self.spectrum_rate_hz }, })
+    }
 }
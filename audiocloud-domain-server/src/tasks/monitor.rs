@@ -0,0 +1,84 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use audiocloud_api::common::task::TaskSpec;
+use audiocloud_api::newtypes::MixerNodeId;
+
+/// Highest monitor level, in dB
+pub const MAX_MONITOR_LEVEL_DB: f64 = 12.0;
+
+/// Monitor controller built into every task, a software instance the domain implements without a driver
+///
+/// The level, dim and mute set the gain of the streamed audio, the source selects the mixer sent to the master.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskMonitor {
+    /// Monitor level in dB
+    #[serde(default)]
+    pub level_db: f64,
+    /// Lower the monitor level by `dim_db`
+    #[serde(default)]
+    pub dim:      bool,
+    /// How much dimming lowers the monitor level, in dB
+    #[serde(default = "default_dim_db")]
+    pub dim_db:   f64,
+    /// Silence the monitor
+    #[serde(default)]
+    pub mute:     bool,
+    /// Mixer to monitor instead of the mixer of the play
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub source:   Option<MixerNodeId>,
+}
+
+fn default_dim_db() -> f64 {
+    -20.0
+}
+
+impl Default for TaskMonitor {
+    fn default() -> Self {
+        Self { level_db: { 0.0 },
+               dim:      { false },
+               dim_db:   { default_dim_db() },
+               mute:     { false },
+               source:   { None }, }
+    }
+}
+
+impl TaskMonitor {
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Linear gain of the streamed audio
+    pub fn gain(&self) -> f64 {
+        if self.mute {
+            return 0.0;
+        }
+
+        let dim_db = if self.dim { self.dim_db } else { 0.0 };
+
+        10f64.powf((self.level_db + dim_db) / 20.0)
+    }
+
+    /// Check that the levels are within range and the source is a mixer of the task
+    pub fn validate(&self, spec: &TaskSpec) -> Result<(), String> {
+        self.validate_mixers(|mixer_id| spec.mixers.contains_key(mixer_id))
+    }
+
+    /// Validate against the mixers of a task, `has_mixer` tells whether a mixer is in it
+    pub fn validate_mixers(&self, has_mixer: impl Fn(&MixerNodeId) -> bool) -> Result<(), String> {
+        if !self.level_db.is_finite() || self.level_db > MAX_MONITOR_LEVEL_DB {
+            return Err(format!("Monitor level {} dB is above {MAX_MONITOR_LEVEL_DB} dB", self.level_db));
+        }
+        if !self.dim_db.is_finite() || self.dim_db > 0.0 {
+            return Err(format!("Dim level {} dB does not lower the monitor level", self.dim_db));
+        }
+        if let Some(source) = &self.source {
+            if !has_mixer(source) {
+                return Err(format!("Source mixer {source} is not in the task"));
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,343 @@
+use std::f64::consts::{PI, SQRT_2};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use actix::{Actor, ActorFutureExt, Context, ContextFutureSpawner, Handler, WrapFuture};
+use actix_broker::{BrokerIssue, BrokerSubscribe};
+use anyhow::bail;
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use tracing::*;
+use utoipa::ToSchema;
+
+use audiocloud_api::{AppMediaObjectId, MediaObject, MediaObjectId, ModifyTaskSpec, RequestRender};
+
+use crate::db::Db;
+use crate::tasks::{NotifyTaskNullTest, NotifyTaskNullTestRendered, TaskNullTestReport};
+
+/// Centers of the octave bands the spectral difference is measured in, in Hz
+const OCTAVE_BANDS_HZ: [f64; 10] = [31.5, 63.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0];
+
+/// Power levels below this count as silence, about -200 dB
+const SILENCE_POWER: f64 = 1e-20;
+
+#[derive(Args, Clone, Debug)]
+pub struct NullTestOpts {
+    /// ffmpeg binary that decodes the renders of null tests
+    #[clap(long, env, default_value = "ffmpeg")]
+    pub null_test_ffmpeg: PathBuf,
+
+    /// Sample rate the renders of a null test are compared at
+    #[clap(long, env, default_value = "48000")]
+    pub null_test_sample_rate: u32,
+}
+
+/// Two variants of a task rendered one after the other and compared, i.e. to prove the recall of hardware instances
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct TaskNullTestRequest {
+    pub a: NullTestVariant,
+    pub b: NullTestVariant,
+}
+
+/// A spec revision of the task, with modifications on top of it such as a snapshot of parameter values
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct NullTestVariant {
+    #[schema(value_type = Object)]
+    pub render:      RequestRender,
+    /// Revision of the spec to render, the current one if none. Only recent revisions are kept
+    #[serde(default)]
+    pub revision:    Option<u64>,
+    #[serde(default)]
+    #[schema(value_type = Vec<Object>)]
+    pub modify_spec: Vec<ModifyTaskSpec>,
+}
+
+impl TaskNullTestRequest {
+    /// Check that both variants render the same segment of the same mixer, as different renders
+    pub fn validate(&self) -> Result<(), String> {
+        let (a, b) = (&self.a.render, &self.b.render);
+
+        if a.render_id == b.render_id {
+            return Err(format!("Both variants render as {}", a.render_id));
+        }
+        if a.mixer_id != b.mixer_id {
+            return Err(format!("Variants render mixers {} and {}", a.mixer_id, b.mixer_id));
+        }
+        if a.segment.start != b.segment.start || a.segment.length != b.segment.length {
+            return Err("Variants render different segments".to_owned());
+        }
+        if !(a.segment.length > 0.0) {
+            return Err(format!("Segment length {} is not positive", a.segment.length));
+        }
+
+        Ok(())
+    }
+}
+
+/// How far render B is from render A, levels in dB
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct NullTestMetrics {
+    /// Level of the difference of the renders relative to render A, the lower the deeper the null
+    pub null_depth_db:      f64,
+    /// Level of the difference of the renders, in dBFS
+    pub residual_rms_db:    f64,
+    /// Highest sample of the difference of the renders, in dBFS
+    pub residual_peak_db:   f64,
+    /// Level of render B relative to render A
+    pub gain_difference_db: f64,
+    /// Samples compared per channel, the shorter render sets how many
+    pub length_samples:     usize,
+    pub bands:              Vec<NullTestBand>,
+}
+
+/// Spectral difference of the renders in one octave band
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct NullTestBand {
+    pub center_hz:          f64,
+    /// Level of render B relative to render A in the band
+    pub gain_difference_db: f64,
+    /// Level of the difference of the renders relative to render A in the band
+    pub null_depth_db:      f64,
+}
+
+/// Compare two renders given as samples by channel, at `sample_rate`
+pub fn compare_renders(a: &[Vec<f32>], b: &[Vec<f32>], sample_rate: u32) -> NullTestMetrics {
+    let length = a.iter()
+                  .chain(b.iter())
+                  .map(|channel| channel.len())
+                  .min()
+                  .unwrap_or_default();
+
+    let mut power = Power::default();
+    let mut peak = 0f64;
+
+    for (a, b) in a.iter().zip(b.iter()) {
+        for (a, b) in a[..length].iter().zip(&b[..length]) {
+            let (a, b) = (*a as f64, *b as f64);
+            power.add(a, b);
+            peak = peak.max((a - b).abs());
+        }
+    }
+
+    let bands = OCTAVE_BANDS_HZ.iter()
+                               .filter(|center_hz| **center_hz < sample_rate as f64 * 0.45)
+                               .map(|center_hz| {
+                                   let mut band = Power::default();
+                                   for (a, b) in a.iter().zip(b.iter()) {
+                                       let mut filter_a = BandPass::octave(*center_hz, sample_rate);
+                                       let mut filter_b = filter_a.clone();
+                                       for (a, b) in a[..length].iter().zip(&b[..length]) {
+                                           band.add(filter_a.process(*a as f64), filter_b.process(*b as f64));
+                                       }
+                                   }
+
+                                   NullTestBand { center_hz:          { *center_hz },
+                                                  gain_difference_db: { relative_db(band.b, band.a) },
+                                                  null_depth_db:      { relative_db(band.residual, band.a) }, }
+                               })
+                               .collect();
+
+    NullTestMetrics { null_depth_db:      { relative_db(power.residual, power.a) },
+                      residual_rms_db:    { power_db(power.residual / power.samples.max(1) as f64) },
+                      residual_peak_db:   { power_db(peak * peak) },
+                      gain_difference_db: { relative_db(power.b, power.a) },
+                      length_samples:     { length },
+                      bands:              { bands }, }
+}
+
+/// Energies of both renders and of their difference
+#[derive(Default)]
+struct Power {
+    a:        f64,
+    b:        f64,
+    residual: f64,
+    samples:  usize,
+}
+
+impl Power {
+    fn add(&mut self, a: f64, b: f64) {
+        self.a += a * a;
+        self.b += b * b;
+        self.residual += (a - b) * (a - b);
+        self.samples += 1;
+    }
+}
+
+fn power_db(power: f64) -> f64 {
+    10.0 * power.max(SILENCE_POWER).log10()
+}
+
+/// Level of `power` relative to `reference`, or to full scale if the reference is silent
+fn relative_db(power: f64, reference: f64) -> f64 {
+    if reference < SILENCE_POWER {
+        power_db(power)
+    } else {
+        power_db(power) - power_db(reference)
+    }
+}
+
+/// Band pass biquad one octave wide, with a gain of 0 dB at its center
+#[derive(Clone)]
+struct BandPass {
+    b0: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    x:  [f64; 2],
+    y:  [f64; 2],
+}
+
+impl BandPass {
+    fn octave(center_hz: f64, sample_rate: u32) -> Self {
+        let w0 = 2.0 * PI * center_hz / sample_rate as f64;
+        let alpha = w0.sin() / (2.0 * SQRT_2);
+        let a0 = 1.0 + alpha;
+
+        Self { b0: { alpha / a0 },
+               b2: { -alpha / a0 },
+               a1: { -2.0 * w0.cos() / a0 },
+               a2: { (1.0 - alpha) / a0 },
+               x:  { [0.0; 2] },
+               y:  { [0.0; 2] }, }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b0 * x + self.b2 * self.x[1] - self.a1 * self.y[0] - self.a2 * self.y[1];
+
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+
+        y
+    }
+}
+
+/// Compares the renders of null tests once both finished, storing the report as a media object of the app of the task
+pub struct NullTester {
+    opts:       NullTestOpts,
+    media_root: PathBuf,
+    db:         Db,
+}
+
+impl NullTester {
+    pub fn new(opts: NullTestOpts, media_root: PathBuf, db: Db) -> Self {
+        Self { opts:       { opts },
+               media_root: { media_root },
+               db:         { db }, }
+    }
+}
+
+impl Actor for NullTester {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.subscribe_system_async::<NotifyTaskNullTestRendered>(ctx);
+    }
+}
+
+impl Handler<NotifyTaskNullTestRendered> for NullTester {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskNullTestRendered, ctx: &mut Self::Context) -> Self::Result {
+        let NotifyTaskNullTestRendered { task_id,
+                                         mut report,
+                                         path_a,
+                                         path_b, } = msg;
+
+        let render_a = report.render_a.clone();
+        let media_id = AppMediaObjectId::new(task_id.app_id.clone(),
+                                             MediaObjectId::new(format!("null-test-{render_a}")));
+
+        let relative = PathBuf::from("renders").join(task_id.app_id.as_str())
+                                               .join(format!("{render_a}-null-test.json"));
+
+        // engines report absolute paths, or paths relative to the shared media root
+        let path_a = self.media_root.join(path_a);
+        let path_b = self.media_root.join(path_b);
+        let destination = self.media_root.join(&relative);
+        let opts = self.opts.clone();
+        let db = self.db.clone();
+
+        let media = MediaObject { id:       { media_id.clone() },
+                                  metadata: { None },
+                                  path:     { Some(relative.to_string_lossy().to_string()) },
+                                  download: { None },
+                                  upload:   { None },
+                                  revision: { 0 }, };
+
+        async move {
+            let job = move || compare(&opts, &path_a, &path_b);
+
+            let compared = match actix_web::rt::task::spawn_blocking(job).await {
+                Ok(compared) => compared,
+                Err(error) => Err(error.into()),
+            };
+
+            match compared {
+                Ok(metrics) => {
+                    info!(%task_id, %render_a, null_depth_db = metrics.null_depth_db, "Null test compared");
+                    report.metrics = Some(metrics);
+                    report.media_id = Some(media_id);
+
+                    if let Err(error) = save_report(&db, media, &destination, &report).await {
+                        warn!(%error, %task_id, %render_a, "Failed to store null test report");
+                        report.media_id = None;
+                    }
+                }
+                Err(error) => {
+                    warn!(%error, %task_id, %render_a, "Failed to compare null test renders");
+                    report.error = Some(error.to_string());
+                }
+            }
+
+            NotifyTaskNullTest { task_id, report }
+        }.into_actor(self)
+         .map(|notify, actor, _ctx| actor.issue_system_async(notify))
+         .spawn(ctx);
+    }
+}
+
+async fn save_report(db: &Db,
+                     media: MediaObject,
+                     destination: &Path,
+                     report: &TaskNullTestReport)
+                     -> anyhow::Result<()> {
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    fs::write(destination, serde_json::to_vec_pretty(report)?)?;
+    db.save_media(media).await?;
+
+    Ok(())
+}
+
+fn compare(opts: &NullTestOpts, path_a: &Path, path_b: &Path) -> anyhow::Result<NullTestMetrics> {
+    let a = decode(opts, path_a)?;
+    let b = decode(opts, path_b)?;
+
+    Ok(compare_renders(&a, &b, opts.null_test_sample_rate))
+}
+
+/// Decode a render to stereo samples at the sample rate renders are compared at
+fn decode(opts: &NullTestOpts, path: &Path) -> anyhow::Result<Vec<Vec<f32>>> {
+    let output = Command::new(&opts.null_test_ffmpeg).args(["-nostdin", "-hide_banner", "-i"])
+                                                     .arg(path)
+                                                     .args(["-f", "f32le", "-ac", "2", "-ar"])
+                                                     .arg(opts.null_test_sample_rate.to_string())
+                                                     .arg("-")
+                                                     .output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let last_line = stderr.lines().last().unwrap_or_default();
+        bail!("ffmpeg exited with {}: {last_line}", output.status);
+    }
+
+    let mut channels = vec![Vec::with_capacity(output.stdout.len() / 8); 2];
+    for (i, sample) in output.stdout.chunks_exact(4).enumerate() {
+        channels[i % 2].push(f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]));
+    }
+
+    Ok(channels)
+}
@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use audiocloud_api::common::task::TimeSegment;
+
+/// Segments the plays of a task step through back to back, in order, without a gap between them. Looping plays
+/// start over with the first segment after the last one
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskPlaylist {
+    #[serde(default)]
+    #[schema(value_type = Vec<Object>)]
+    pub segments: Vec<TimeSegment>,
+}
+
+impl TaskPlaylist {
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Check that every segment is a part of the timeline the engine can play
+    pub fn validate(&self) -> Result<(), String> {
+        for (index, segment) in self.segments.iter().enumerate() {
+            if !segment.start.is_finite() || segment.start < 0.0 {
+                return Err(format!("Segment {index} starts at invalid time {}", segment.start));
+            }
+            if !segment.length.is_finite() || segment.length <= 0.0 {
+                return Err(format!("Segment {index} has invalid length {}", segment.length));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Index of the segment playing at `timeline_pos`, given the segment that played before
+    ///
+    /// The same part of the timeline may be in the playlist more than once, so the segments from the current one on
+    /// are tried before starting over from the first one.
+    pub fn segment_index_at(&self, current: Option<usize>, timeline_pos: f64) -> Option<usize> {
+        let contains = |index: &usize| {
+            let segment = &self.segments[*index];
+            timeline_pos >= segment.start && timeline_pos < segment.end()
+        };
+
+        let from = current.filter(|current| *current < self.segments.len())
+                          .unwrap_or_default();
+
+        (from..self.segments.len()).chain(0..from).find(contains)
+    }
+}
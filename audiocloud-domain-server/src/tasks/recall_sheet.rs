@@ -0,0 +1,159 @@
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use audiocloud_api::common::task::{InstanceParameters, TaskSpec};
+use audiocloud_api::newtypes::FixedInstanceNodeId;
+use audiocloud_api::{AppMediaObjectId, AppTaskId, FixedInstanceId, Model, ModelId, TaskReservation, Timestamp};
+
+/// Parameters of the fixed instances of a task written down at one revision of its spec, so that the analog settings
+/// can be recalled by hand once the hardware can not be driven remotely
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskRecallSheet {
+    #[schema(value_type = String)]
+    pub task_id:       AppTaskId,
+    /// Revision of the spec the parameters are of
+    pub revision:      u64,
+    #[schema(value_type = String)]
+    pub generated_at:  Timestamp,
+    #[schema(value_type = String)]
+    pub reserved_from: Timestamp,
+    #[schema(value_type = String)]
+    pub reserved_to:   Timestamp,
+    /// Fixed instances in the order of their node IDs
+    pub instances:     Vec<RecallInstance>,
+    /// Media object of the JSON document, once stored
+    #[schema(value_type = Option<String>)]
+    pub media_id:      Option<AppMediaObjectId>,
+    /// Media object of the printable HTML document, once stored
+    #[schema(value_type = Option<String>)]
+    pub html_media_id: Option<AppMediaObjectId>,
+}
+
+/// Parameters of one fixed instance of a task, with the model it is an instance of
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct RecallInstance {
+    #[schema(value_type = String)]
+    pub fixed_id:    FixedInstanceNodeId,
+    #[schema(value_type = String)]
+    pub instance_id: FixedInstanceId,
+    #[schema(value_type = String)]
+    pub model_id:    ModelId,
+    /// The model as registered with the domain, `None` if it is not known
+    #[schema(value_type = Option<Object>)]
+    pub model:       Option<Model>,
+    #[schema(value_type = Object)]
+    pub parameters:  InstanceParameters,
+}
+
+impl TaskRecallSheet {
+    /// Recall sheet of the fixed instances in the spec, `model` looks up the models of the instances
+    pub fn new(task_id: AppTaskId,
+               spec: &TaskSpec,
+               reservations: &TaskReservation,
+               generated_at: Timestamp,
+               model: impl Fn(&ModelId) -> Option<Model>)
+               -> Self {
+        let mut instances = spec.fixed
+                                .iter()
+                                .map(|(fixed_id, fixed)| {
+                                    let model_id = fixed.instance_id.model_id();
+
+                                    RecallInstance { fixed_id:    { fixed_id.clone() },
+                                                     instance_id: { fixed.instance_id.clone() },
+                                                     model:       { model(&model_id) },
+                                                     model_id:    { model_id },
+                                                     parameters:  { fixed.parameters.clone() }, }
+                                })
+                                .collect::<Vec<_>>();
+
+        instances.sort_by_key(|instance| instance.fixed_id.to_string());
+
+        Self { task_id:       { task_id },
+               revision:      { spec.revision },
+               generated_at:  { generated_at },
+               reserved_from: { reservations.from },
+               reserved_to:   { reservations.to },
+               instances:     { instances },
+               media_id:      { None },
+               html_media_id: { None }, }
+    }
+
+    /// Printable HTML document with a table of parameters per instance
+    pub fn to_html(&self) -> String {
+        let title = escape_html(&format!("Recall sheet of {} revision {}", self.task_id, self.revision));
+        let mut html = String::new();
+
+        let _ = write!(html,
+                       "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+                        <style>body {{ font-family: sans-serif; }} table {{ border-collapse: collapse; }} \
+                        th, td {{ border: 1px solid #888; padding: 2px 8px; text-align: left; }} \
+                        section {{ break-inside: avoid; }}</style>\n</head>\n<body>\n<h1>{title}</h1>\n");
+
+        let _ = write!(html,
+                       "<p>Generated at {}, reserved from {} to {}</p>\n",
+                       self.generated_at.to_rfc3339(),
+                       self.reserved_from.to_rfc3339(),
+                       self.reserved_to.to_rfc3339());
+
+        for instance in &self.instances {
+            let _ = write!(html,
+                           "<section>\n<h2>{}</h2>\n<p>Instance {}, model {}",
+                           escape_html(&instance.fixed_id.to_string()),
+                           escape_html(&instance.instance_id.to_string()),
+                           escape_html(&instance.model_id.to_string()));
+
+            if let Some(model) = &instance.model {
+                let _ = write!(html, ", {} inputs, {} outputs", model.inputs.len(), model.outputs.len());
+            }
+
+            html.push_str("</p>\n<table>\n<tr><th>Parameter</th><th>Value</th></tr>\n");
+
+            for (name, value) in recall_values(&instance.parameters) {
+                let _ = write!(html,
+                               "<tr><td>{}</td><td>{}</td></tr>\n",
+                               escape_html(&name),
+                               escape_html(&value));
+            }
+
+            html.push_str("</table>\n</section>\n");
+        }
+
+        html.push_str("</body>\n</html>\n");
+
+        html
+    }
+}
+
+/// Parameter values by name in the order of their names, with the values of channels separated by slashes
+pub fn recall_values(parameters: &InstanceParameters) -> Vec<(String, String)> {
+    let mut values = match parameters {
+        Value::Object(parameters) => parameters.iter()
+                                               .map(|(name, value)| (name.clone(), recall_value(value)))
+                                               .collect::<Vec<_>>(),
+        Value::Null => vec![],
+        other => vec![(String::new(), recall_value(other))],
+    };
+
+    values.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    values
+}
+
+fn recall_value(value: &Value) -> String {
+    match value {
+        Value::Null => "-".to_owned(),
+        Value::String(value) => value.clone(),
+        Value::Array(channels) => channels.iter().map(recall_value).collect::<Vec<_>>().join(" / "),
+        other => other.to_string(),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use actix::{Actor, ActorFutureExt, Context, ContextFutureSpawner, Handler, WrapFuture};
+use actix_broker::{BrokerIssue, BrokerSubscribe};
+use anyhow::{anyhow, bail};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use tracing::*;
+use utoipa::ToSchema;
+
+use audiocloud_api::common::media::RenderId;
+use audiocloud_api::{AppMediaObjectId, AppTaskId, MediaObject, MediaObjectId};
+
+use crate::db::Db;
+use crate::tasks::{NotifyTaskRenderFinished, NotifyTaskRenderNormalized, TaskRenderNormalized};
+
+/// Loudness range the normalization keeps, in LU, as streaming services measure it
+const TARGET_LRA: f64 = 11.0;
+
+#[derive(Args, Clone, Debug)]
+pub struct RenderNormalizationOpts {
+    /// ffmpeg binary that normalizes the loudness of renders
+    #[clap(long, env, default_value = "ffmpeg")]
+    pub render_normalization_ffmpeg: PathBuf,
+
+    /// Sample rate of normalized renders, the loudness filter of ffmpeg resamples what it processes
+    #[clap(long, env, default_value = "48000")]
+    pub render_normalization_sample_rate: u32,
+}
+
+/// Loudness a render is normalized to once it finished, with true peaks limited to a ceiling
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, ToSchema)]
+pub struct TaskRenderNormalization {
+    /// Integrated loudness, in LUFS
+    #[serde(default = "default_target_lufs")]
+    pub target_lufs:  f64,
+    /// Ceiling of the true peaks, in dBTP
+    #[serde(default = "default_true_peak_db")]
+    pub true_peak_db: f64,
+}
+
+fn default_target_lufs() -> f64 {
+    -14.0
+}
+
+fn default_true_peak_db() -> f64 {
+    -1.0
+}
+
+impl TaskRenderNormalization {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.target_lufs.is_finite() || self.target_lufs < -70.0 || self.target_lufs > -5.0 {
+            return Err(format!("Target loudness {} LUFS is outside of -70 to -5 LUFS", self.target_lufs));
+        }
+        if !self.true_peak_db.is_finite() || self.true_peak_db < -9.0 || self.true_peak_db > 0.0 {
+            return Err(format!("True peak ceiling {} dBTP is outside of -9 to 0 dBTP",
+                               self.true_peak_db));
+        }
+
+        Ok(())
+    }
+}
+
+/// Loudness of a render before and after it was normalized
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, ToSchema)]
+pub struct RenderLoudness {
+    pub input_lufs:          f64,
+    pub input_true_peak_db:  f64,
+    /// Loudness range, in LU
+    pub input_lra:           f64,
+    pub output_lufs:         f64,
+    pub output_true_peak_db: f64,
+}
+
+/// Measurements the `loudnorm` filter of ffmpeg prints when it is done
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoudnormReport {
+    pub input_i:       f64,
+    pub input_tp:      f64,
+    pub input_lra:     f64,
+    pub input_thresh:  f64,
+    pub output_i:      f64,
+    pub output_tp:     f64,
+    pub target_offset: f64,
+}
+
+/// The report is the last JSON object ffmpeg writes to stderr, with every value as a string
+pub fn parse_loudnorm_report(output: &str) -> anyhow::Result<LoudnormReport> {
+    let start = output.rfind('{')
+                      .ok_or_else(|| anyhow!("No loudness report in ffmpeg output"))?;
+    let end = output[start..].find('}')
+                             .ok_or_else(|| anyhow!("Loudness report in ffmpeg output is cut short"))?;
+
+    let values = serde_json::from_str::<HashMap<String, String>>(&output[start..=start + end])?;
+    let value = |key: &str| -> anyhow::Result<f64> {
+        let value = values.get(key).ok_or_else(|| anyhow!("Loudness report has no {key}"))?;
+        value.trim()
+             .parse()
+             .map_err(|error| anyhow!("Loudness report {key} {value:?}: {error}"))
+    };
+
+    Ok(LoudnormReport { input_i:       { value("input_i")? },
+                        input_tp:      { value("input_tp")? },
+                        input_lra:     { value("input_lra")? },
+                        input_thresh:  { value("input_thresh")? },
+                        output_i:      { value("output_i")? },
+                        output_tp:     { value("output_tp")? },
+                        target_offset: { value("target_offset")? }, })
+}
+
+/// The `loudnorm` filter measuring a render, or applying the measurement of an earlier pass linearly
+pub fn loudnorm_filter(normalization: &TaskRenderNormalization, measured: Option<&LoudnormReport>) -> String {
+    let mut options = vec![format!("I={}", normalization.target_lufs),
+                           format!("TP={}", normalization.true_peak_db),
+                           format!("LRA={TARGET_LRA}")];
+
+    if let Some(measured) = measured {
+        options.extend([format!("measured_I={}", measured.input_i),
+                        format!("measured_TP={}", measured.input_tp),
+                        format!("measured_LRA={}", measured.input_lra),
+                        format!("measured_thresh={}", measured.input_thresh),
+                        format!("offset={}", measured.target_offset),
+                        "linear=true".to_owned()]);
+    }
+
+    options.push("print_format=json".to_owned());
+
+    format!("loudnorm={}", options.join(":"))
+}
+
+/// Normalizes the loudness of finished renders requested with a normalization, into files registered as media objects
+/// of the app of the task
+///
+/// Renders are measured in a first pass of ffmpeg and normalized in a second one, leaving the rendered file as it is.
+pub struct RenderNormalizer {
+    opts:       RenderNormalizationOpts,
+    media_root: PathBuf,
+    db:         Db,
+}
+
+impl RenderNormalizer {
+    pub fn new(opts: RenderNormalizationOpts, media_root: PathBuf, db: Db) -> Self {
+        Self { opts:       { opts },
+               media_root: { media_root },
+               db:         { db }, }
+    }
+}
+
+impl Actor for RenderNormalizer {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.subscribe_system_async::<NotifyTaskRenderFinished>(ctx);
+    }
+}
+
+impl Handler<NotifyTaskRenderFinished> for RenderNormalizer {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskRenderFinished, ctx: &mut Self::Context) -> Self::Result {
+        let NotifyTaskRenderFinished { task_id,
+                                       render_id,
+                                       path,
+                                       normalization, } = msg;
+
+        let normalization = match normalization {
+            Some(normalization) => normalization,
+            None => return,
+        };
+
+        let media_id = AppMediaObjectId::new(task_id.app_id.clone(),
+                                             MediaObjectId::new(format!("render-{render_id}-normalized")));
+
+        let relative = PathBuf::from("renders").join(task_id.app_id.as_str())
+                                               .join(format!("{render_id}-normalized.wav"));
+
+        // engines report absolute paths, or paths relative to the shared media root
+        let source = self.media_root.join(path);
+        let destination = self.media_root.join(&relative);
+        let opts = self.opts.clone();
+        let db = self.db.clone();
+
+        let media = MediaObject { id:       { media_id.clone() },
+                                  metadata: { None },
+                                  path:     { Some(relative.to_string_lossy().to_string()) },
+                                  download: { None },
+                                  upload:   { None },
+                                  revision: { 0 }, };
+
+        async move {
+            let job = move || normalize(&opts, &source, &destination, &normalization);
+            let loudness = actix_web::rt::task::spawn_blocking(job).await??;
+            db.save_media(media).await?;
+
+            Ok(loudness)
+        }.into_actor(self)
+         .map(move |res: anyhow::Result<RenderLoudness>, actor, _ctx| {
+             let normalized = match res {
+                 Ok(loudness) => {
+                     info!(%task_id, %render_id, ?loudness, "Render normalized");
+                     TaskRenderNormalized { render_id: { render_id },
+                                            media_id:  { Some(media_id) },
+                                            loudness:  { Some(loudness) },
+                                            error:     { None }, }
+                 }
+                 Err(error) => {
+                     warn!(%error, %task_id, %render_id, "Failed to normalize render");
+                     TaskRenderNormalized { render_id: { render_id },
+                                            media_id:  { None },
+                                            loudness:  { None },
+                                            error:     { Some(error.to_string()) }, }
+                 }
+             };
+
+             actor.issue_system_async(NotifyTaskRenderNormalized { task_id, normalized });
+         })
+         .spawn(ctx);
+    }
+}
+
+fn normalize(opts: &RenderNormalizationOpts,
+             source: &Path,
+             destination: &Path,
+             normalization: &TaskRenderNormalization)
+             -> anyhow::Result<RenderLoudness> {
+    let mut measure = input_args(source);
+    measure.extend(["-af".into(),
+                    loudnorm_filter(normalization, None).into(),
+                    "-f".into(),
+                    "null".into(),
+                    "-".into()]);
+
+    let measured = parse_loudnorm_report(&run_ffmpeg(&opts.render_normalization_ffmpeg, measure)?)?;
+    if !measured.input_i.is_finite() {
+        bail!("Render is silent, there is no loudness to normalize");
+    }
+
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut apply = input_args(source);
+    apply.extend(["-af".into(),
+                  loudnorm_filter(normalization, Some(&measured)).into(),
+                  "-ar".into(),
+                  opts.render_normalization_sample_rate.to_string().into(),
+                  "-c:a".into(),
+                  "pcm_s24le".into(),
+                  destination.into()]);
+
+    let applied = parse_loudnorm_report(&run_ffmpeg(&opts.render_normalization_ffmpeg, apply)?)?;
+
+    Ok(RenderLoudness { input_lufs:          { measured.input_i },
+                        input_true_peak_db:  { measured.input_tp },
+                        input_lra:           { measured.input_lra },
+                        output_lufs:         { applied.output_i },
+                        output_true_peak_db: { applied.output_tp }, })
+}
+
+fn input_args(source: &Path) -> Vec<OsString> {
+    vec!["-nostdin".into(),
+         "-y".into(),
+         "-hide_banner".into(),
+         "-i".into(),
+         source.into()]
+}
+
+/// Run ffmpeg to completion, returning what it wrote to stderr
+fn run_ffmpeg(ffmpeg: &Path, args: Vec<OsString>) -> anyhow::Result<String> {
+    let output = Command::new(ffmpeg).args(args).output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+
+    if !output.status.success() {
+        let last_line = stderr.lines().last().unwrap_or_default();
+        bail!("ffmpeg exited with {}: {last_line}", output.status);
+    }
+
+    Ok(stderr)
+}
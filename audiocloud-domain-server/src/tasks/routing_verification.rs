@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use audiocloud_api::cloud::domains::FixedInstanceRouting;
+use audiocloud_api::{FixedInstanceId, Timestamp};
+
+use crate::tasks::engine_ext::{EngineTestTone, EngineTestToneResult};
+
+/// Where a routing verification of a task is at
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RoutingVerificationState {
+    /// Routing is not verified, the task plays right away
+    Disabled,
+    /// Waiting for the fixed instances to be powered and ready
+    Pending,
+    Running,
+    Passed,
+    /// At least one chain is broken, the task does not play or render until a verification passes
+    Failed,
+}
+
+/// Signal path verification of the fixed instances of a task
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct TaskRoutingVerification {
+    pub state:       RoutingVerificationState,
+    #[schema(value_type = Option<String>)]
+    pub verified_at: Option<Timestamp>,
+    pub chains:      Vec<RoutingChainCheck>,
+}
+
+impl TaskRoutingVerification {
+    pub fn new(state: RoutingVerificationState) -> Self {
+        Self { state:       { state },
+               verified_at: { None },
+               chains:      { vec![] }, }
+    }
+
+    pub fn allows_playing(&self) -> bool {
+        matches!(self.state,
+                 RoutingVerificationState::Disabled | RoutingVerificationState::Passed)
+    }
+}
+
+/// One channel of a fixed instance: the engine output feeding it and the engine input it is expected to return on
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoutingChain {
+    pub instance_id:     FixedInstanceId,
+    pub channel:         usize,
+    pub send_channel:    usize,
+    pub return_channels: Vec<usize>,
+    pub expected_return: usize,
+}
+
+/// Outcome of sending a tone through one channel of a fixed instance
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct RoutingChainCheck {
+    #[schema(value_type = String)]
+    pub instance_id:     FixedInstanceId,
+    /// Channel of the instance, zero based
+    pub channel:         usize,
+    pub send_channel:    usize,
+    pub expected_return: usize,
+    pub passed:          bool,
+    /// Level arriving on the expected return, in dBFS
+    pub return_peak_db:  Option<f64>,
+    pub diagnosis:       String,
+}
+
+/// A chain per sent channel of each routed instance. Instances with fewer returns than sends return the extra
+/// channels on their last return, for example a stereo send into a mono return.
+pub fn plan_routing_chains(routing: &HashMap<FixedInstanceId, FixedInstanceRouting>) -> Vec<RoutingChain> {
+    let mut instances = routing.iter()
+                               .filter(|(_, routing)| routing.send_count > 0 && routing.return_count > 0)
+                               .collect::<Vec<_>>();
+
+    instances.sort_by_key(|(instance_id, _)| instance_id.to_string());
+
+    let mut rv = vec![];
+    for (instance_id, routing) in instances {
+        let return_channels = (0..routing.return_count).map(|channel| routing.return_channel + channel)
+                                                       .collect::<Vec<_>>();
+
+        for channel in 0..routing.send_count {
+            let expected_return = routing.return_channel + channel.min(routing.return_count - 1);
+            rv.push(RoutingChain { instance_id:     { instance_id.clone() },
+                                   channel:         { channel },
+                                   send_channel:    { routing.send_channel + channel },
+                                   return_channels: { return_channels.clone() },
+                                   expected_return: { expected_return }, });
+        }
+    }
+
+    rv
+}
+
+impl RoutingChain {
+    pub fn test_tone(&self, level_db: f64, duration_ms: u64) -> EngineTestTone {
+        EngineTestTone { output_channel: { self.send_channel },
+                         input_channels: { self.return_channels.clone() },
+                         frequency:      { 1_000.0 },
+                         level_db:       { level_db },
+                         duration_ms:    { duration_ms }, }
+    }
+
+    /// Compare what came back on the returns to the `level_db` tone that was sent
+    pub fn evaluate(&self,
+                    level_db: f64,
+                    tolerance_db: f64,
+                    result: Result<EngineTestToneResult, String>)
+                    -> RoutingChainCheck {
+        let (passed, return_peak_db, diagnosis) = match result {
+            Err(error) => (false, None, format!("Test tone failed: {error}")),
+            Ok(result) => {
+                let peak_of = |channel: usize| {
+                    result.inputs
+                          .iter()
+                          .find(|input| input.channel == channel)
+                          .and_then(|input| input.peak_db)
+                };
+
+                let expected = peak_of(self.expected_return);
+                let elsewhere = self.return_channels
+                                    .iter()
+                                    .filter(|channel| **channel != self.expected_return)
+                                    .filter(|channel| peak_of(**channel).is_some())
+                                    .copied()
+                                    .collect::<Vec<_>>();
+
+                let diagnosis = match (expected, elsewhere.is_empty()) {
+                    (None, true) => format!("No signal returned, check the patch from output {} into the instance \
+                                             and from the instance into input {}",
+                                            self.send_channel, self.expected_return),
+                    (None, false) => format!("Signal returned on input {elsewhere:?} instead of {}, the returns \
+                                              are crossed",
+                                             self.expected_return),
+                    (Some(peak), _) if (peak - level_db).abs() > tolerance_db => {
+                        format!("Returned {peak:.1} dBFS for {level_db:.1} dBFS sent, more than {tolerance_db:.1} dB \
+                                 off, check the gain of the instance and the converter calibration")
+                    }
+                    (Some(peak), _) => format!("Returned {peak:.1} dBFS for {level_db:.1} dBFS sent"),
+                };
+
+                let passed = matches!(expected, Some(peak) if (peak - level_db).abs() <= tolerance_db);
+
+                (passed, expected, diagnosis)
+            }
+        };
+
+        RoutingChainCheck { instance_id:     { self.instance_id.clone() },
+                            channel:         { self.channel },
+                            send_channel:    { self.send_channel },
+                            expected_return: { self.expected_return },
+                            passed:          { passed },
+                            return_peak_db:  { return_peak_db },
+                            diagnosis:       { diagnosis }, }
+    }
+}
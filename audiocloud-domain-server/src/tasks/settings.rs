@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+
+use crate::tasks::{
+    TaskEnvelopes, TaskLoudnessTarget, TaskMediaFades, TaskTempoMap, TaskTrackGroups, TaskTrackInputs, TaskWatermark,
+};
+
+/// Settings of a task the engine gets next to the task spec, persisted together so they survive a restart
+///
+/// Every field defaults, so settings persisted before a field was added still load.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TaskSettings {
+    pub track_inputs:    TaskTrackInputs,
+    pub tempo_map:       TaskTempoMap,
+    pub track_groups:    TaskTrackGroups,
+    pub envelopes:       TaskEnvelopes,
+    pub fades:           TaskMediaFades,
+    pub loudness_target: TaskLoudnessTarget,
+    pub watermark:       TaskWatermark,
+}
@@ -0,0 +1,56 @@
+/// Where a piece of compressed audio falls relative to the audio of the same play before it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StreamStep {
+    /// First audio of the play
+    Start,
+    /// Follows the previous audio
+    Continuous,
+    /// Follows the previous audio, but the timeline went back because a loop wrapped
+    LoopWrap,
+    /// Audio between the previous and this one never arrived
+    Gap { missing: u64 },
+    /// Starts before the previous audio ended, it was delivered twice
+    Repeated,
+}
+
+/// Follows the stream positions of the audio of a play, telling loop wraps apart from audio that went missing
+///
+/// Engines keep counting stream positions across loop wraps, so a play that loops stays one continuous stream even
+/// though its timeline jumps back at the end of every pass.
+#[derive(Clone, Debug, Default)]
+pub struct StreamContinuity {
+    next_stream_pos: Option<u64>,
+    timeline_pos:    Option<f64>,
+    pub passes:      u64,
+    pub gaps:        u64,
+}
+
+impl StreamContinuity {
+    pub fn push(&mut self, stream_pos: u64, num_samples: u64, timeline_pos: f64) -> StreamStep {
+        let step = match self.next_stream_pos {
+            None => StreamStep::Start,
+            Some(next) if stream_pos > next => StreamStep::Gap { missing: stream_pos - next, },
+            Some(next) if stream_pos < next => StreamStep::Repeated,
+            Some(_) if matches!(self.timeline_pos, Some(previous) if timeline_pos < previous) => StreamStep::LoopWrap,
+            Some(_) => StreamStep::Continuous,
+        };
+
+        match step {
+            StreamStep::LoopWrap => self.passes += 1,
+            StreamStep::Gap { .. } => self.gaps += 1,
+            // the stream did not advance, keep waiting for the audio after what we already have
+            StreamStep::Repeated => return step,
+            _ => {}
+        }
+
+        self.next_stream_pos = Some(stream_pos + num_samples);
+        self.timeline_pos = Some(timeline_pos);
+
+        step
+    }
+
+    /// The play was sought, the timeline of the audio that follows may go back without a loop wrapping
+    pub fn seek(&mut self) {
+        self.timeline_pos = None;
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::{HashMap, VecDeque};
+use std::convert::identity;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use actix::{Actor, AsyncContext, Context, Handler, ResponseFuture};
+use actix_broker::BrokerSubscribe;
+use anyhow::anyhow;
+use clap::Args;
+use tracing::*;
+
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::{now, AppTaskId, Codec, MsgPack, PlayId, StreamingPacket, Timestamp};
+
+use crate::tasks::{ListRecordedPackets, NotifyStreamingPacket, NotifyTaskDeleted};
+use crate::DomainResult;
+
+/// Bytes in front of every recorded packet: serial, milliseconds since the epoch and length of the packet
+const RECORD_HEADER_LEN: usize = 8 + 8 + 4;
+
+#[derive(Args, Clone, Debug)]
+pub struct StreamRecorderOpts {
+    /// Directory the packets of play sessions are recorded to, so late joining clients can be served from it
+    #[clap(long, env, default_value = "stream_recordings")]
+    pub stream_recording_dir: PathBuf,
+
+    /// Seconds of each play session kept on disk, 0 disables recording
+    #[clap(long, env, default_value = "300")]
+    pub stream_recording_seconds: u64,
+
+    /// Seconds of packets in each recording file, whole files are dropped once they are older than the recording
+    #[clap(long, env, default_value = "10")]
+    pub stream_recording_segment_seconds: u64,
+}
+
+impl StreamRecorderOpts {
+    pub fn is_enabled(&self) -> bool {
+        self.stream_recording_seconds > 0
+    }
+
+    fn retention(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.stream_recording_seconds as i64)
+    }
+
+    fn segment_duration(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.stream_recording_segment_seconds.max(1) as i64)
+    }
+}
+
+/// A file of consecutive packets of a play
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedSegment {
+    pub path:         PathBuf,
+    pub started_at:   Timestamp,
+    pub ended_at:     Timestamp,
+    pub first_serial: u64,
+    pub last_serial:  u64,
+}
+
+/// Packets of a play recorded to segment files in a directory of its own, oldest segment first
+pub struct PlayRecording {
+    dir:              PathBuf,
+    segment_duration: chrono::Duration,
+    segments:         VecDeque<RecordedSegment>,
+    file:             Option<File>,
+}
+
+impl PlayRecording {
+    pub fn new(dir: PathBuf, segment_duration: chrono::Duration) -> Self {
+        Self { dir:              { dir },
+               segment_duration: { segment_duration },
+               segments:         { VecDeque::new() },
+               file:             { None }, }
+    }
+
+    /// Append an encoded packet, starting a new segment when the current one is long enough
+    pub fn append(&mut self, serial: u64, at: Timestamp, payload: &[u8]) -> anyhow::Result<()> {
+        let current = self.segments
+                          .back()
+                          .filter(|segment| at - segment.started_at < self.segment_duration);
+
+        if current.is_none() || self.file.is_none() {
+            fs::create_dir_all(&self.dir)?;
+
+            let path = self.dir.join(format!("{serial:020}.dvr"));
+            self.file = Some(OpenOptions::new().create(true).append(true).open(&path)?);
+            self.segments.push_back(RecordedSegment { path:         { path },
+                                                      started_at:   { at },
+                                                      ended_at:     { at },
+                                                      first_serial: { serial },
+                                                      last_serial:  { serial }, });
+        }
+
+        let mut record = Vec::with_capacity(RECORD_HEADER_LEN + payload.len());
+        record.extend_from_slice(&serial.to_le_bytes());
+        record.extend_from_slice(&at.timestamp_millis().to_le_bytes());
+        record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        record.extend_from_slice(payload);
+
+        // one write per packet, so a replay reading the file never sees half of one
+        self.file
+            .as_mut()
+            .ok_or_else(|| anyhow!("Recording segment not open"))?
+            .write_all(&record)?;
+
+        if let Some(segment) = self.segments.back_mut() {
+            segment.ended_at = at;
+            segment.last_serial = serial;
+        }
+
+        Ok(())
+    }
+
+    /// Delete the segments whose last packet was recorded before `cutoff`
+    pub fn trim(&mut self, cutoff: Timestamp) -> anyhow::Result<()> {
+        while let Some(segment) = self.segments.front().filter(|segment| segment.ended_at < cutoff) {
+            if self.segments.len() == 1 {
+                self.file = None;
+            }
+
+            fs::remove_file(&segment.path)?;
+            self.segments.pop_front();
+        }
+
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Segments with packets recorded at or after `since`
+    pub fn segments_since(&self, since: Timestamp) -> Vec<RecordedSegment> {
+        self.segments
+            .iter()
+            .filter(|segment| segment.ended_at >= since)
+            .cloned()
+            .collect()
+    }
+
+    /// Delete the recording with all of its segments
+    pub fn remove(self) -> anyhow::Result<()> {
+        drop(self.file);
+        if self.dir.exists() {
+            fs::remove_dir_all(&self.dir)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Read the encoded packets recorded in `segments` at or after `since`, with their serials, in recording order
+pub fn read_segments(segments: &[RecordedSegment], since: Timestamp) -> anyhow::Result<Vec<(u64, Vec<u8>)>> {
+    let since = since.timestamp_millis();
+    let mut packets = vec![];
+
+    for segment in segments {
+        let mut bytes = vec![];
+        File::open(&segment.path)?.read_to_end(&mut bytes)?;
+
+        let mut rest = bytes.as_slice();
+        while rest.len() >= RECORD_HEADER_LEN {
+            let serial = u64::from_le_bytes(rest[0..8].try_into()?);
+            let at = i64::from_le_bytes(rest[8..16].try_into()?);
+            let len = u32::from_le_bytes(rest[16..20].try_into()?) as usize;
+
+            let record = rest.get(RECORD_HEADER_LEN..RECORD_HEADER_LEN + len)
+                             .ok_or_else(|| anyhow!("Truncated packet {serial} in {}", segment.path.display()))?;
+
+            if at >= since {
+                packets.push((serial, record.to_vec()));
+            }
+
+            rest = &rest[RECORD_HEADER_LEN + len..];
+        }
+    }
+
+    Ok(packets)
+}
+
+/// Records the packets of every play session to disk for a bounded time, to replay them to clients joining late
+pub struct StreamRecorder {
+    opts:       StreamRecorderOpts,
+    recordings: HashMap<AppTaskId, HashMap<PlayId, PlayRecording>>,
+}
+
+impl StreamRecorder {
+    pub fn new(opts: StreamRecorderOpts) -> Self {
+        Self { opts:       { opts },
+               recordings: { HashMap::new() }, }
+    }
+
+    fn play_dir(&self, task_id: &AppTaskId, play_id: &PlayId) -> PathBuf {
+        self.opts
+            .stream_recording_dir
+            .join(task_id.to_string().replace('/', "_"))
+            .join(play_id.to_string())
+    }
+
+    /// Recordings of a previous run can not be replayed, the plays they belong to are gone
+    fn remove_stale_recordings(&self) {
+        let dir = &self.opts.stream_recording_dir;
+        if !dir.exists() {
+            return;
+        }
+
+        match globwalk::GlobWalkerBuilder::from_patterns(dir, &["**/*.dvr"]).build() {
+            Ok(walker) => {
+                for entry in walker.filter_map(Result::ok) {
+                    if let Err(error) = fs::remove_file(entry.path()) {
+                        warn!(%error, path = %entry.path().display(), "Failed to remove stale stream recording");
+                    }
+                }
+            }
+            Err(error) => warn!(%error, "Failed to look for stale stream recordings"),
+        }
+    }
+
+    fn trim_recordings(&mut self, _ctx: &mut Context<Self>) {
+        let cutoff = now() - self.opts.retention();
+
+        for (task_id, plays) in &mut self.recordings {
+            for (play_id, recording) in plays.iter_mut() {
+                if let Err(error) = recording.trim(cutoff) {
+                    warn!(%error, %task_id, %play_id, "Failed to trim stream recording");
+                }
+            }
+
+            plays.retain(|_, recording| !recording.is_empty());
+        }
+
+        self.recordings.retain(|_, plays| !plays.is_empty());
+    }
+}
+
+impl Actor for StreamRecorder {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.remove_stale_recordings();
+
+        self.subscribe_system_async::<NotifyStreamingPacket>(ctx);
+        self.subscribe_system_async::<NotifyTaskDeleted>(ctx);
+
+        ctx.run_interval(Duration::from_secs(1), Self::trim_recordings);
+    }
+}
+
+impl Handler<NotifyStreamingPacket> for StreamRecorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyStreamingPacket, _ctx: &mut Self::Context) -> Self::Result {
+        let play_id = msg.packet.play_id;
+        let dir = self.play_dir(&msg.task_id, &play_id);
+        let segment_duration = self.opts.segment_duration();
+
+        let recording = self.recordings
+                            .entry(msg.task_id.clone())
+                            .or_default()
+                            .entry(play_id)
+                            .or_insert_with(|| PlayRecording::new(dir, segment_duration));
+
+        let recorded = MsgPack.serialize(&msg.packet)
+                              .map_err(anyhow::Error::from)
+                              .and_then(|payload| recording.append(msg.packet.serial, now(), &payload));
+
+        if let Err(error) = recorded {
+            warn!(%error, task_id = %msg.task_id, %play_id, serial = msg.packet.serial, "Failed to record packet");
+        }
+    }
+}
+
+impl Handler<NotifyTaskDeleted> for StreamRecorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskDeleted, _ctx: &mut Self::Context) -> Self::Result {
+        for (play_id, recording) in self.recordings.remove(&msg.task_id).unwrap_or_default() {
+            if let Err(error) = recording.remove() {
+                warn!(%error, task_id = %msg.task_id, %play_id, "Failed to delete stream recording");
+            }
+        }
+    }
+}
+
+impl Handler<ListRecordedPackets> for StreamRecorder {
+    type Result = ResponseFuture<DomainResult<Vec<StreamingPacket>>>;
+
+    fn handle(&mut self, msg: ListRecordedPackets, _ctx: &mut Self::Context) -> Self::Result {
+        let task_id = msg.task_id;
+        let play_id = msg.play_id;
+
+        let recording = match self.recordings.get(&task_id).and_then(|plays| plays.get(&play_id)) {
+            Some(recording) => recording,
+            None => return Box::pin(async move { Err(DomainError::TaskStreamNotFound { task_id, play_id }) }),
+        };
+
+        let since = match msg.seconds_back {
+            Some(seconds_back) => now() - chrono::Duration::milliseconds((seconds_back.max(0.0) * 1000.0) as i64),
+            None => now() - self.opts.retention(),
+        };
+
+        let segments = recording.segments_since(since);
+        let read = actix_web::rt::task::spawn_blocking(move || read_segments(&segments, since));
+
+        Box::pin(async move {
+            let packets =
+                read.await
+                    .map_err(anyhow::Error::from)
+                    .and_then(identity)
+                    .map_err(|error| DomainError::BadGateway { error: format!("Failed to read recording: \
+                                                                                     {error}"), })?;
+
+            packets.into_iter()
+                   .map(|(serial, payload)| {
+                       MsgPack.deserialize::<StreamingPacket>(&payload)
+                              .map_err(|error| DomainError::Serialization { error: format!("Recorded packet {serial}: \
+                                                                                        {error}"), })
+                   })
+                   .collect()
+        })
+    }
+}
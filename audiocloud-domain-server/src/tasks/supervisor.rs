@@ -1,10 +1,12 @@
 #![allow(unused_variables)]
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 
 use actix::{Actor, Addr, Context, Handler};
 use opentelemetry::global;
 use opentelemetry::metrics::ObservableGauge;
+use tokio::sync::oneshot;
 use tracing::*;
 
 use audiocloud_api::cloud::domains::{DomainConfig, DomainEngineConfig, FixedInstanceRoutingMap};
@@ -14,50 +16,118 @@ use audiocloud_api::{
     DomainId, FixedInstanceId, PlayId, StreamingPacket, Task, TaskReservation, TaskSecurity, TaskSpec, Timestamped,
 };
 
-use crate::db::Db;
+use crate::db::{Db, DbStats};
+use crate::fixed_instances::ModelSharingMap;
 use crate::o11y;
+use crate::tasks::engine_ext::EngineTestToneResult;
 use crate::tasks::messages::BecomeOnline;
 use crate::tasks::task::TaskActor;
 use crate::tasks::TaskOpts;
+use crate::tasks::{
+    EngineClockReport, EngineResourceReport, TaskDiagnostics, TaskEnvelopes, TaskLatencyProfile, TaskLeadIn,
+    TaskLoudnessTarget, TaskMediaFades, TaskMediaLengths, TaskMediaRates, TaskMonitor, TaskPlaylist, TaskRecording,
+    TaskStreamCodec, TaskTempoMap, TaskTrackGroups, TaskTrackInputs, TaskWatermark, TrackTake,
+};
+use crate::TaskKeyScopes;
 
 mod cancel_render;
 mod create_task;
+mod db_maintenance;
 mod delete_task;
+mod diagnostics;
+mod engine_clocks;
+mod engine_resources;
+mod envelopes;
+mod fades;
+mod get_spec_diff;
 mod get_task;
 mod handle_engine_events;
+mod handle_engine_ext_events;
 mod handle_instance_events;
 mod handle_media_events;
 mod handle_task_events;
+mod key_scopes;
+mod latency_profile;
+mod lead_in;
 mod list_tasks;
+mod loudness_target;
+mod media_rates;
 mod modify_task;
+mod monitor;
+mod null_test;
 mod packets;
+mod pause_play;
 mod play_task;
+mod playlist;
+mod recall_sheet;
 mod render_task;
+mod routing_verification;
+mod safe_mode;
+mod secure_keys;
+mod settings;
 mod seek_task;
 mod stop_play;
+mod stream_codec;
+mod takes;
 mod task_timers;
+mod tempo_map;
+mod test_tone;
+mod track_groups;
+mod track_inputs;
+mod warm_up;
+mod watermark;
 
 pub struct TasksSupervisor {
     db:                        Db,
     opts:                      TaskOpts,
+    /// Where recall sheets are stored, relative paths of media objects are resolved against it
+    media_root:                PathBuf,
     domain_config:             DomainConfig,
     tasks:                     HashMap<AppTaskId, SupervisedTask>,
     engines:                   HashMap<EngineId, ReferencedEngine>,
-    fixed_instance_membership: HashMap<FixedInstanceId, AppTaskId>,
+    /// Tasks using each fixed instance, with the channels of the instance they connect to
+    fixed_instance_membership: HashMap<FixedInstanceId, HashMap<AppTaskId, HashSet<usize>>>,
     fixed_instance_routing:    FixedInstanceRoutingMap,
+    model_sharing:             ModelSharingMap,
     num_tasks:                 ObservableGauge<u64>,
     num_active_tasks:          ObservableGauge<u64>,
+    db_rows:                   ObservableGauge<u64>,
+    db_size_bytes:             ObservableGauge<u64>,
+    db_stats:                  DbStats,
+    engine_clocks:             HashMap<EngineId, EngineClockReport>,
+    engine_resources:          HashMap<EngineId, EngineResourceReport>,
+    pending_test_tones:        HashMap<String, oneshot::Sender<EngineTestToneResult>>,
     online:                    bool,
 }
 
 struct SupervisedTask {
-    pub domain_id:    DomainId,
-    pub reservations: TaskReservation,
-    pub spec:         TaskSpec,
-    pub security:     TaskSecurity,
-    pub state:        TaskState,
-    pub actor:        Option<Addr<TaskActor>>,
-    pub packet_cache: HashMap<PlayId, HashMap<u64, Timestamped<StreamingPacket>>>,
+    pub domain_id:       DomainId,
+    pub reservations:    TaskReservation,
+    pub spec:            TaskSpec,
+    pub security:        TaskSecurity,
+    pub key_scopes:      TaskKeyScopes,
+    pub state:           TaskState,
+    pub actor:           Option<Addr<TaskActor>>,
+    pub packet_cache:    HashMap<PlayId, HashMap<u64, Timestamped<StreamingPacket>>>,
+    pub track_inputs:    TaskTrackInputs,
+    pub recording:       TaskRecording,
+    pub lead_in:         TaskLeadIn,
+    pub latency_profile: TaskLatencyProfile,
+    pub stream_codec:    TaskStreamCodec,
+    pub loudness_target: TaskLoudnessTarget,
+    pub watermark:       TaskWatermark,
+    pub tempo_map:       TaskTempoMap,
+    pub playlist:        TaskPlaylist,
+    pub track_groups:    TaskTrackGroups,
+    pub envelopes:       TaskEnvelopes,
+    pub fades:           TaskMediaFades,
+    pub media_rates:     TaskMediaRates,
+    /// Timeline lengths of the track media at their rates, as the engine last reported them
+    pub media_lengths:   TaskMediaLengths,
+    pub monitor:         TaskMonitor,
+    pub takes:           Vec<TrackTake>,
+    /// Diagnostic bundles the engine captured of the task, oldest first
+    pub diagnostics:     Vec<TaskDiagnostics>,
 }
 
 struct ReferencedEngine {
@@ -65,7 +135,13 @@ struct ReferencedEngine {
 }
 
 impl TasksSupervisor {
-    pub fn new(db: Db, opts: &TaskOpts, cfg: &DomainConfig, routing: FixedInstanceRoutingMap) -> anyhow::Result<Self> {
+    pub fn new(db: Db,
+               opts: &TaskOpts,
+               cfg: &DomainConfig,
+               routing: FixedInstanceRoutingMap,
+               model_sharing: ModelSharingMap,
+               media_root: PathBuf)
+               -> anyhow::Result<Self> {
         let meter = global::meter("audiocloud.io/tasks_total");
         let num_tasks = meter.u64_observable_gauge("tasks")
                              .with_description("Total number of tasks")
@@ -75,6 +151,15 @@ impl TasksSupervisor {
                                     .with_description("Total number of active tasks")
                                     .init();
 
+        let db_meter = global::meter("audiocloud.io/db");
+        let db_rows = db_meter.u64_observable_gauge("db_rows")
+                              .with_description("Rows in each database table")
+                              .init();
+
+        let db_size_bytes = db_meter.u64_observable_gauge("db_size_bytes")
+                                    .with_description("Size of the database on disk, in bytes")
+                                    .init();
+
         let tasks = cfg.tasks
             .iter()
             .filter(|(id, task)| {
@@ -95,31 +180,71 @@ impl TasksSupervisor {
 
         Ok(Self { db:                        { db },
                   opts:                      { opts.clone() },
+                  media_root:                { media_root },
                   domain_config:             { cfg.clone() },
                   fixed_instance_membership: { HashMap::new() },
                   fixed_instance_routing:    { routing },
+                  model_sharing:             { model_sharing },
                   tasks:                     { tasks },
                   engines:                   { engines },
                   num_tasks:                 { num_tasks },
                   num_active_tasks:          { num_active_tasks },
+                  db_rows:                   { db_rows },
+                  db_size_bytes:             { db_size_bytes },
+                  db_stats:                  { Default::default() },
+                  engine_clocks:             { HashMap::new() },
+                  engine_resources:          { HashMap::new() },
+                  pending_test_tones:        { HashMap::new() },
                   online:                    { false }, })
     }
 
     fn create_task_actor((id, task): (&AppTaskId, &Task)) -> (AppTaskId, SupervisedTask) {
         (id.clone(),
-         SupervisedTask { domain_id:    { task.domain_id.clone() },
-                          reservations: { task.reservations.clone() },
-                          spec:         { task.spec.clone() },
-                          security:     { task.security.clone() },
-                          state:        { Default::default() },
-                          actor:        { None },
-                          packet_cache: { Default::default() }, })
+         SupervisedTask { domain_id:       { task.domain_id.clone() },
+                          reservations:    { task.reservations.clone() },
+                          spec:            { task.spec.clone() },
+                          security:        { task.security.clone() },
+                          key_scopes:      { Default::default() },
+                          state:           { Default::default() },
+                          actor:           { None },
+                          packet_cache:    { Default::default() },
+                          track_inputs:    { Default::default() },
+                          recording:       { Default::default() },
+                          lead_in:         { Default::default() },
+                          latency_profile: { Default::default() },
+                          stream_codec:    { Default::default() },
+                          loudness_target: { Default::default() },
+                          watermark:       { Default::default() },
+                          tempo_map:       { Default::default() },
+                          playlist:        { Default::default() },
+                          track_groups:    { Default::default() },
+                          envelopes:       { Default::default() },
+                          fades:           { Default::default() },
+                          media_rates:     { Default::default() },
+                          media_lengths:   { Default::default() },
+                          monitor:         { Default::default() },
+                          takes:           { Default::default() },
+                          diagnostics:     { Default::default() }, })
     }
 
     fn allocate_engine(&self, id: &AppTaskId, spec: &TaskSpec) -> Option<EngineId> {
-        // TODO: we know we only have one engine, so we always pick the first
-        let engine_id = self.engines.keys().next().cloned();
-        info!(?engine_id, %id, "Allocated engine for task");
+        // tasks made only of tracks and mixers are bounced offline by a native engine, anything with fixed or dynamic
+        // instances needs REAPER. a native engine can not run instances, so it is never the fallback
+        let wants_native = spec.fixed.is_empty() && spec.dynamic.is_empty();
+        let is_native = |engine_id: &EngineId| self.opts.native_engines.contains(&engine_id.to_string());
+
+        // an overloaded engine would drop audio of the tasks it already runs, the task waits for another engine
+        let available = move || {
+            self.engines
+                .keys()
+                .filter(move |engine_id| !self.is_engine_overloaded(engine_id))
+        };
+
+        let engine_id = available().find(|engine_id| is_native(engine_id) == wants_native)
+                                   .or_else(|| available().find(|engine_id| !is_native(engine_id)))
+                                   .cloned();
+
+        info!(?engine_id, %id, wants_native, "Allocated engine for task");
         engine_id
     }
 }
@@ -128,6 +253,10 @@ impl Actor for TasksSupervisor {
     type Context = Context<Self>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
+        self.restore_task_permissions(ctx);
+        self.restore_task_key_scopes(ctx);
+        self.restore_track_takes(ctx);
+        self.restore_task_settings(ctx);
         self.subscribe_task_events(ctx);
         self.subscribe_instance_events(ctx);
         self.subscribe_media_events(ctx);
@@ -135,6 +264,7 @@ impl Actor for TasksSupervisor {
 
         self.register_task_timers(ctx);
         self.register_packet_cache_cleanup(ctx);
+        self.register_db_maintenance(ctx);
     }
 }
 
@@ -1,12 +1,15 @@
 use actix::fut::LocalBoxActorFuture;
-use actix::{fut, ActorFutureExt, Handler, WrapFuture};
+use actix::{fut, ActorFutureExt, Context, ContextFutureSpawner, Handler, WrapFuture};
+use actix_broker::BrokerIssue;
+use tracing::*;
 
 use audiocloud_api::audio_engine::TaskRenderCancelled;
-
+use audiocloud_api::common::media::RenderId;
 use audiocloud_api::domain::DomainError;
+use audiocloud_api::{AppMediaObjectId, AppTaskId, MediaObject, MediaObjectId};
 
-use crate::tasks::CancelRenderTask;
-use crate::DomainResult;
+use crate::tasks::{CancelRenderTask, NotifyTaskRenderCancelled, TaskPartialRender};
+use crate::{DomainResult, SecureKeyScope};
 
 use super::TasksSupervisor;
 
@@ -16,6 +19,10 @@ impl Handler<CancelRenderTask> for TasksSupervisor {
     fn handle(&mut self, msg: CancelRenderTask, ctx: &mut Self::Context) -> Self::Result {
         use DomainError::*;
 
+        if let Err(error) = self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Transport) {
+            return fut::err(error).into_actor(self).boxed_local();
+        }
+
         if let Some(task) = self.tasks.get(&msg.task_id).and_then(|task| task.actor.as_ref()) {
             let task_id = msg.task_id.clone();
             task.send(msg)
@@ -33,3 +40,54 @@ impl Handler<CancelRenderTask> for TasksSupervisor {
         }
     }
 }
+
+impl TasksSupervisor {
+    /// Stop the task and register what a cancelled render rendered as a media object of the app of the task
+    pub(crate) fn on_render_cancelled(&mut self,
+                                      task_id: AppTaskId,
+                                      render_id: RenderId,
+                                      partial_path: Option<String>,
+                                      rendered_length: f64,
+                                      ctx: &mut Context<Self>) {
+        let media = partial_path.map(|path| partial_render_media(&task_id, &render_id, path));
+
+        let partial = TaskPartialRender { render_id:       { render_id },
+                                          media_id:        { media.as_ref().map(|media| media.id.clone()) },
+                                          rendered_length: { rendered_length }, };
+
+        let mut notify = NotifyTaskRenderCancelled { task_id, partial };
+
+        if let Some(actor) = self.tasks.get(&notify.task_id).and_then(|task| task.actor.as_ref()) {
+            actor.do_send(notify.clone());
+        }
+
+        let db = self.db.clone();
+
+        async move {
+            match media {
+                Some(media) => db.save_media(media).await,
+                None => Ok(()),
+            }
+        }.into_actor(self)
+         .map(move |res, actor, _ctx| {
+             if let Err(error) = res {
+                 warn!(%error, task_id = %notify.task_id, "Failed to save partially rendered file");
+                 notify.partial.media_id = None;
+             }
+
+             actor.issue_system_async(notify);
+         })
+         .spawn(ctx);
+    }
+}
+
+fn partial_render_media(task_id: &AppTaskId, render_id: &RenderId, path: String) -> MediaObject {
+    let media_id = MediaObjectId::new(format!("render-{render_id}-partial"));
+
+    MediaObject { id:       { AppMediaObjectId::new(task_id.app_id.clone(), media_id) },
+                  metadata: { None },
+                  path:     { Some(path) },
+                  download: { None },
+                  upload:   { None },
+                  revision: { 0 }, }
+}
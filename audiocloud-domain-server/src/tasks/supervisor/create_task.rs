@@ -1,31 +1,92 @@
-use actix::Handler;
+use actix::fut::LocalBoxActorFuture;
+use actix::{fut, ActorFutureExt, Context, Handler, WrapFuture};
 
 use audiocloud_api::domain::tasks::TaskCreated;
 use audiocloud_api::domain::DomainError;
+use audiocloud_api::newtypes::AppTaskId;
+use audiocloud_api::{TaskReservation, TaskSecurity, TaskSpec};
 
+use crate::fixed_instances::{get_instance_supervisor, ReserveFixedInstances};
 use crate::tasks::supervisor::{SupervisedTask, TasksSupervisor};
 use crate::tasks::CreateTask;
 use crate::DomainResult;
 
 impl Handler<CreateTask> for TasksSupervisor {
-    type Result = DomainResult<TaskCreated>;
+    type Result = LocalBoxActorFuture<Self, DomainResult<TaskCreated>>;
 
     fn handle(&mut self, msg: CreateTask, ctx: &mut Self::Context) -> Self::Result {
         if self.tasks.contains_key(&msg.task_id) {
-            return Err(DomainError::TaskExists { task_id: msg.task_id });
+            return fut::err(DomainError::TaskExists { task_id: msg.task_id }).into_actor(self)
+                                                                             .boxed_local();
         }
 
-        self.tasks.insert(msg.task_id.clone(),
-                          SupervisedTask { domain_id:    { self.domain_config.domain_id.clone() },
-                                           reservations: { msg.reservations.into() },
-                                           spec:         { msg.spec.into() },
-                                           security:     { msg.security.into() },
-                                           state:        { Default::default() },
-                                           actor:        { None },
-                                           packet_cache: { Default::default() }, });
+        let reservations: TaskReservation = msg.reservations.into();
+        let spec: TaskSpec = msg.spec.into();
 
-        self.run_task_timers(ctx);
+        // the instance supervisor refuses instances that overlapping tasks hold and that can not be shared
+        let reserve = ReserveFixedInstances { task_id:     { msg.task_id.clone() },
+                                              reservation: { reservations.clone() },
+                                              spec:        { spec.clone() }, };
 
-        Ok(TaskCreated::Created { task_id: msg.task_id.clone(), })
+        get_instance_supervisor().send(reserve)
+                                 .into_actor(self)
+                                 .map(move |res, actor, ctx| {
+                                     match res {
+                                         Ok(Ok(())) => {}
+                                         Ok(Err(error)) => return Err(error),
+                                         Err(error) => {
+                                             return Err(DomainError::BadGateway { error: error.to_string(), })
+                                         }
+                                     }
+
+                                     actor.insert_task(msg.task_id, reservations, spec, msg.security.into(), ctx)
+                                 })
+                                 .boxed_local()
+    }
+}
+
+impl TasksSupervisor {
+    fn insert_task(&mut self,
+                   task_id: AppTaskId,
+                   reservations: TaskReservation,
+                   spec: TaskSpec,
+                   security: TaskSecurity,
+                   ctx: &mut Context<Self>)
+                   -> DomainResult<TaskCreated> {
+        if self.tasks.contains_key(&task_id) {
+            return Err(DomainError::TaskExists { task_id });
+        }
+
+        self.tasks.insert(task_id.clone(),
+                          SupervisedTask { domain_id:       { self.domain_config.domain_id.clone() },
+                                           reservations:    { reservations },
+                                           spec:            { spec },
+                                           security:        { security },
+                                           key_scopes:      { Default::default() },
+                                           state:           { Default::default() },
+                                           actor:           { None },
+                                           packet_cache:    { Default::default() },
+                                           track_inputs:    { Default::default() },
+                                           recording:       { Default::default() },
+                                           lead_in:         { Default::default() },
+                                           latency_profile: { Default::default() },
+                                           stream_codec:    { Default::default() },
+                                           loudness_target: { Default::default() },
+                                           watermark:       { Default::default() },
+                                           tempo_map:       { Default::default() },
+                                           playlist:        { Default::default() },
+                                           track_groups:    { Default::default() },
+                                           envelopes:       { Default::default() },
+                                           fades:           { Default::default() },
+                                           media_rates:     { Default::default() },
+                                           media_lengths:   { Default::default() },
+                                           monitor:         { Default::default() },
+                                           takes:           { Default::default() },
+                                           diagnostics:     { Default::default() }, });
+
+        // the actor of the task starts once the keys persisted for it are applied
+        self.restore_task_secure_keys(task_id.clone(), ctx);
+
+        Ok(TaskCreated::Created { task_id })
     }
 }
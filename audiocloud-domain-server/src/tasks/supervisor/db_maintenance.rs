@@ -0,0 +1,58 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use actix::{ActorFutureExt, AsyncContext, Context, ContextFutureSpawner, WrapFuture};
+use tracing::*;
+
+use audiocloud_api::now;
+
+use crate::db::DbStats;
+use crate::tasks::supervisor::TasksSupervisor;
+
+/// How often the row counts and size of the database are refreshed for the metrics
+const DB_STATS_INTERVAL: Duration = Duration::from_secs(60);
+
+impl TasksSupervisor {
+    pub(crate) fn register_db_maintenance(&mut self, ctx: &mut Context<Self>) {
+        self.refresh_db_stats(ctx);
+
+        ctx.run_interval(DB_STATS_INTERVAL, Self::refresh_db_stats);
+        ctx.run_interval(Duration::from_secs(self.opts.db_compaction_interval_seconds.max(1)),
+                         Self::compact_db);
+    }
+
+    fn refresh_db_stats(&mut self, ctx: &mut Context<Self>) {
+        let db = self.db.clone();
+
+        async move { db.stats().await }.into_actor(self)
+                                       .map(Self::on_db_stats)
+                                       .spawn(ctx);
+    }
+
+    fn on_db_stats(res: anyhow::Result<DbStats>, actor: &mut Self, ctx: &mut Context<Self>) {
+        match res {
+            Ok(stats) => actor.db_stats = stats,
+            Err(error) => warn!(%error, "Failed to read database stats"),
+        }
+    }
+
+    /// Drop what the supervisor forgot more than a task grace period ago, the same age after which tasks are forgotten
+    fn compact_db(&mut self, ctx: &mut Context<Self>) {
+        let db = self.db.clone();
+        let cutoff = now() - chrono::Duration::seconds(self.opts.task_grace_seconds as i64);
+        let live_tasks = self.tasks.keys().cloned().collect::<HashSet<_>>();
+
+        async move {
+            match db.compact(cutoff, &live_tasks).await {
+                Ok(compaction) => {
+                    debug!(media_jobs = compaction.media_jobs,
+                           task_rows = compaction.task_rows,
+                           "Compacted database")
+                }
+                Err(error) => warn!(%error, "Failed to compact database"),
+            }
+        }.into_actor(self)
+         .map(|_, actor, ctx| actor.refresh_db_stats(ctx))
+         .spawn(ctx);
+    }
+}
@@ -1,19 +1,60 @@
 use actix::Handler;
+use actix_broker::BrokerIssue;
+use tracing::*;
 
 use audiocloud_api::domain::tasks::TaskDeleted;
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::newtypes::AppTaskId;
 
 use crate::tasks::supervisor::TasksSupervisor;
-use crate::tasks::DeleteTask;
-use crate::DomainResult;
+use crate::tasks::{DeleteTask, NotifyTaskDeleted};
+use crate::{DomainResult, SecureKeyScope};
 
-impl TasksSupervisor {}
+impl TasksSupervisor {
+    /// Delete what is persisted for a task the supervisor no longer has, and tell everyone else to forget it
+    pub(super) fn forget_task(&mut self, task_id: AppTaskId) {
+        let db = self.db.clone();
+        let persisted_task_id = task_id.clone();
+        actix::spawn(async move {
+            if let Err(error) = db.delete_task_permissions(&persisted_task_id).await {
+                warn!(%error, task_id = %persisted_task_id, "Failed to delete persisted task permissions");
+            }
+            if let Err(error) = db.delete_task_key_scopes(&persisted_task_id).await {
+                warn!(%error, task_id = %persisted_task_id, "Failed to delete persisted key scopes");
+            }
+            if let Err(error) = db.delete_track_takes(&persisted_task_id).await {
+                warn!(%error, task_id = %persisted_task_id, "Failed to delete persisted track takes");
+            }
+            if let Err(error) = db.delete_task_settings(&persisted_task_id).await {
+                warn!(%error, task_id = %persisted_task_id, "Failed to delete persisted task settings");
+            }
+        });
+
+        self.issue_system_async(NotifyTaskDeleted { task_id });
+    }
+}
 
 impl Handler<DeleteTask> for TasksSupervisor {
     type Result = DomainResult<TaskDeleted>;
 
     fn handle(&mut self, msg: DeleteTask, ctx: &mut Self::Context) -> Self::Result {
-        if self.tasks.contains_key(&msg.task_id) {}
+        use DomainError::*;
+
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Full)?;
+
+        let revision = match self.tasks.get(&msg.task_id) {
+            Some(task) => task.spec.revision,
+            None => return Err(TaskNotFound { task_id: msg.task_id }),
+        };
+
+        msg.check_revision(revision)?;
+
+        // the task actor closes the engine session and stops once it hears about the deletion
+        self.tasks.remove(&msg.task_id);
+        info!(task_id = %msg.task_id, revision, "Task deleted");
+
+        self.forget_task(msg.task_id.clone());
 
-        todo!()
+        Ok(TaskDeleted::Deleted { task_id: msg.task_id })
     }
 }
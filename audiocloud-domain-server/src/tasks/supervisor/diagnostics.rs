@@ -0,0 +1,75 @@
+use actix::{ActorFutureExt, Context, ContextFutureSpawner, Handler, WrapFuture};
+use actix_broker::BrokerIssue;
+use tracing::*;
+use uuid::Uuid;
+
+use audiocloud_api::{now, AppMediaObjectId, AppTaskId, MediaObject, MediaObjectId};
+
+use crate::tasks::{GetTaskDiagnostics, NotifyTaskDiagnostics, TaskDiagnostics};
+use crate::{DomainResult, SecureKeyScope};
+
+use super::TasksSupervisor;
+
+/// Diagnostic bundles kept listed on a task, older ones remain media objects of the app
+const MAX_TASK_DIAGNOSTICS: usize = 32;
+
+impl TasksSupervisor {
+    /// Register a diagnostic bundle the engine captured as a media object of the app of the task
+    pub(crate) fn save_diagnostics(&mut self,
+                                   task_id: AppTaskId,
+                                   error: String,
+                                   path: String,
+                                   ctx: &mut Context<Self>) {
+        let media_id = AppMediaObjectId::new(task_id.app_id.clone(),
+                                             MediaObjectId::new(format!("diagnostics-{}", Uuid::new_v4())));
+
+        let media = MediaObject { id:       { media_id.clone() },
+                                  metadata: { None },
+                                  path:     { Some(path) },
+                                  download: { None },
+                                  upload:   { None },
+                                  revision: { 0 }, };
+
+        let diagnostics = TaskDiagnostics { media_id:    { media_id },
+                                            error:       { error },
+                                            captured_at: { now() }, };
+
+        let db = self.db.clone();
+
+        async move { db.save_media(media).await }.into_actor(self)
+                                                 .map(move |res, actor, _ctx| match res {
+                                                     Ok(()) => actor.on_diagnostics_saved(task_id, diagnostics),
+                                                     Err(error) => {
+                                                         warn!(%error, %task_id, "Failed to save diagnostics")
+                                                     }
+                                                 })
+                                                 .spawn(ctx);
+    }
+
+    fn on_diagnostics_saved(&mut self, task_id: AppTaskId, diagnostics: TaskDiagnostics) {
+        warn!(%task_id, media_id = %diagnostics.media_id, error = %diagnostics.error, "Engine diagnostics captured");
+
+        if let Some(task) = self.tasks.get_mut(&task_id) {
+            if task.diagnostics.len() == MAX_TASK_DIAGNOSTICS {
+                task.diagnostics.remove(0);
+            }
+
+            task.diagnostics.push(diagnostics.clone());
+        }
+
+        self.issue_system_async(NotifyTaskDiagnostics { task_id, diagnostics });
+    }
+}
+
+impl Handler<GetTaskDiagnostics> for TasksSupervisor {
+    type Result = DomainResult<Vec<TaskDiagnostics>>;
+
+    fn handle(&mut self, msg: GetTaskDiagnostics, _ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Listen)?;
+
+        Ok(self.tasks
+               .get(&msg.task_id)
+               .map(|task| task.diagnostics.clone())
+               .unwrap_or_default())
+    }
+}
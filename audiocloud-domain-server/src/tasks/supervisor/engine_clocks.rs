@@ -0,0 +1,41 @@
+use actix::Handler;
+use actix_broker::BrokerIssue;
+use tracing::*;
+
+use audiocloud_api::newtypes::EngineId;
+use audiocloud_api::now;
+
+use crate::tasks::engine_ext::EngineClockStatus;
+use crate::tasks::supervisor::TasksSupervisor;
+use crate::tasks::{EngineClockReport, GetEngineClocks, NotifyEngineClockLost};
+use crate::DomainResult;
+
+impl TasksSupervisor {
+    pub(crate) fn on_engine_clock_status(&mut self, engine_id: EngineId, status: EngineClockStatus) {
+        let was_locked = self.engine_clocks
+                             .get(&engine_id)
+                             .map(|report| report.status.locked)
+                             .unwrap_or(true);
+
+        if was_locked && !status.locked {
+            warn!(%engine_id, ?status, "Audio interface lost clock");
+            self.issue_system_async(NotifyEngineClockLost { engine_id: { engine_id.clone() },
+                                                            status:    { status.clone() }, });
+        } else if !was_locked && status.locked {
+            info!(%engine_id, ?status, "Audio interface regained clock");
+        }
+
+        self.engine_clocks.insert(engine_id.clone(),
+                                  EngineClockReport { engine_id:  { engine_id },
+                                                      updated_at: { now() },
+                                                      status:     { status }, });
+    }
+}
+
+impl Handler<GetEngineClocks> for TasksSupervisor {
+    type Result = DomainResult<Vec<EngineClockReport>>;
+
+    fn handle(&mut self, msg: GetEngineClocks, ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.engine_clocks.values().cloned().collect())
+    }
+}
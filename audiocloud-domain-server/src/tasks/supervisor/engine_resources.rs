@@ -0,0 +1,52 @@
+use actix::Handler;
+use tracing::*;
+
+use audiocloud_api::newtypes::EngineId;
+use audiocloud_api::now;
+
+use crate::tasks::engine_ext::EngineResources;
+use crate::tasks::supervisor::TasksSupervisor;
+use crate::tasks::{EngineResourceReport, GetEngineResources};
+use crate::DomainResult;
+
+/// Resource reports older than this are not trusted to tell an engine is overloaded, engines report every few seconds
+const RESOURCES_STALE_AFTER_SECONDS: i64 = 30;
+
+impl TasksSupervisor {
+    pub(crate) fn on_engine_resources(&mut self, engine_id: EngineId, resources: EngineResources) {
+        let previous = self.engine_resources.get(&engine_id);
+        let was_overloaded = previous.map(|report| report.overloaded).unwrap_or(false);
+        let overloaded =
+            resources.is_overloaded(previous.map(|report| &report.resources), self.opts.engine_max_cpu_usage);
+
+        if !was_overloaded && overloaded {
+            warn!(%engine_id, ?resources, "Engine overloaded, not allocating new tasks to it");
+        } else if was_overloaded && !overloaded {
+            info!(%engine_id, ?resources, "Engine no longer overloaded");
+        }
+
+        self.engine_resources.insert(engine_id.clone(),
+                                     EngineResourceReport { engine_id:  { engine_id },
+                                                            updated_at: { now() },
+                                                            resources:  { resources },
+                                                            overloaded: { overloaded }, });
+    }
+
+    /// The last resource report of the engine, if recent, tells it is overloaded
+    pub(crate) fn is_engine_overloaded(&self, engine_id: &EngineId) -> bool {
+        let stale_after = chrono::Duration::seconds(RESOURCES_STALE_AFTER_SECONDS);
+
+        self.engine_resources
+            .get(engine_id)
+            .map(|report| report.overloaded && now() - report.updated_at < stale_after)
+            .unwrap_or(false)
+    }
+}
+
+impl Handler<GetEngineResources> for TasksSupervisor {
+    type Result = DomainResult<Vec<EngineResourceReport>>;
+
+    fn handle(&mut self, msg: GetEngineResources, ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.engine_resources.values().cloned().collect())
+    }
+}
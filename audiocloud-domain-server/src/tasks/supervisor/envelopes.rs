@@ -0,0 +1,46 @@
+use actix::Handler;
+use actix_broker::BrokerIssue;
+
+use audiocloud_api::domain::DomainError;
+
+use crate::tasks::{GetTaskEnvelopes, NotifyTaskEnvelopes, SetTaskEnvelopes, TaskEnvelopes};
+use crate::{DomainResult, SecureKeyScope};
+
+use super::TasksSupervisor;
+
+impl Handler<SetTaskEnvelopes> for TasksSupervisor {
+    type Result = DomainResult<TaskEnvelopes>;
+
+    fn handle(&mut self, msg: SetTaskEnvelopes, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Full)?;
+
+        msg.envelopes
+           .validate()
+           .map_err(|error| DomainError::Serialization { error: { format!("Invalid envelopes: {error}") }, })?;
+
+        let task = self.tasks
+                       .get_mut(&msg.task_id)
+                       .ok_or_else(|| DomainError::TaskNotFound { task_id: msg.task_id.clone(), })?;
+
+        task.envelopes = msg.envelopes.clone();
+        self.persist_task_settings(&msg.task_id);
+
+        self.issue_system_async(NotifyTaskEnvelopes { task_id:   { msg.task_id },
+                                                      envelopes: { msg.envelopes.clone() }, });
+
+        Ok(msg.envelopes)
+    }
+}
+
+impl Handler<GetTaskEnvelopes> for TasksSupervisor {
+    type Result = DomainResult<TaskEnvelopes>;
+
+    fn handle(&mut self, msg: GetTaskEnvelopes, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Listen)?;
+
+        Ok(self.tasks
+               .get(&msg.task_id)
+               .map(|task| task.envelopes.clone())
+               .unwrap_or_default())
+    }
+}
@@ -0,0 +1,46 @@
+use actix::Handler;
+use actix_broker::BrokerIssue;
+
+use audiocloud_api::domain::DomainError;
+
+use crate::tasks::{GetTaskMediaFades, NotifyTaskMediaFades, SetTaskMediaFades, TaskMediaFades};
+use crate::{DomainResult, SecureKeyScope};
+
+use super::TasksSupervisor;
+
+impl Handler<SetTaskMediaFades> for TasksSupervisor {
+    type Result = DomainResult<TaskMediaFades>;
+
+    fn handle(&mut self, msg: SetTaskMediaFades, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Full)?;
+
+        let task = self.tasks
+                       .get_mut(&msg.task_id)
+                       .ok_or_else(|| DomainError::TaskNotFound { task_id: msg.task_id.clone(), })?;
+
+        msg.fades
+           .validate(&task.spec)
+           .map_err(|error| DomainError::Serialization { error: { format!("Invalid fades: {error}") }, })?;
+
+        task.fades = msg.fades.clone();
+        self.persist_task_settings(&msg.task_id);
+
+        self.issue_system_async(NotifyTaskMediaFades { task_id: { msg.task_id },
+                                                       fades:   { msg.fades.clone() }, });
+
+        Ok(msg.fades)
+    }
+}
+
+impl Handler<GetTaskMediaFades> for TasksSupervisor {
+    type Result = DomainResult<TaskMediaFades>;
+
+    fn handle(&mut self, msg: GetTaskMediaFades, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Listen)?;
+
+        Ok(self.tasks
+               .get(&msg.task_id)
+               .map(|task| task.fades.clone())
+               .unwrap_or_default())
+    }
+}
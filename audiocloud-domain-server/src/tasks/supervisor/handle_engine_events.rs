@@ -1,9 +1,17 @@
+use std::collections::HashMap;
+
 use actix::{Context, Handler};
-use actix_broker::BrokerSubscribe;
+use actix_broker::{Broker, BrokerIssue, BrokerSubscribe, SystemBroker};
+use futures::StreamExt;
 use tracing::*;
 
+use audiocloud_api::audio_engine::EngineCommand;
+use audiocloud_api::{AppTaskId, EngineId, SerializableResult};
+
+use crate::nats;
+use crate::tasks::engine_ext::{engine_ext_event_subject, EngineExtEvent};
 use crate::tasks::supervisor::TasksSupervisor;
-use crate::tasks::NotifyEngineEvent;
+use crate::tasks::{NotifyEngineEvent, NotifyEngineExtEvent, NotifyEngineStarted};
 
 impl Handler<NotifyEngineEvent> for TasksSupervisor {
     type Result = ();
@@ -24,5 +32,52 @@ impl Handler<NotifyEngineEvent> for TasksSupervisor {
 impl TasksSupervisor {
     pub(crate) fn subscribe_engine_events(&self, ctx: &mut Context<Self>) {
         self.subscribe_system_async::<NotifyEngineEvent>(ctx);
+        self.subscribe_system_async::<NotifyEngineExtEvent>(ctx);
+
+        for engine_id in self.engines.keys().cloned() {
+            let subject = engine_ext_event_subject(&engine_id.engine_command_subject());
+            nats::label_subject(&subject, "engine_ext_events");
+            actix::spawn(async move {
+                let mut events = Box::pin(nats::subscribe_msgpack::<EngineExtEvent>(subject));
+                while let Some(event) = events.next().await {
+                    Broker::<SystemBroker>::issue_async(NotifyEngineExtEvent { engine_id: { engine_id.clone() },
+                                                                               event:     { event }, });
+                }
+            });
+        }
+    }
+
+    /// Task actors of the engine set their specs again, sessions the engine reloaded for tasks the domain no longer
+    /// runs are closed
+    pub(crate) fn on_engine_started(&mut self, engine_id: EngineId, sessions: HashMap<AppTaskId, String>) {
+        info!(%engine_id, sessions = sessions.len(), "Engine started afresh");
+
+        for task_id in sessions.keys() {
+            if self.tasks
+                   .get(task_id)
+                   .map(|task| task.actor.is_some())
+                   .unwrap_or(false)
+            {
+                continue;
+            }
+
+            warn!(%engine_id, %task_id, "Closing session the engine reloaded for an inactive task");
+
+            let close = EngineCommand::Close { task_id: task_id.clone(), };
+            let deadline = self.opts.engine_command_deadline(&close);
+            let idempotency = self.opts.engine_command_idempotency(&close);
+            let subject = engine_id.engine_command_subject();
+            let task_id = task_id.clone();
+
+            actix::spawn(async move {
+                match nats::request_msgpack_within(subject, close, deadline, idempotency).await {
+                    Ok(SerializableResult::Error(error)) => warn!(%error, %task_id, "Engine failed to close session"),
+                    Err(error) => warn!(%error, %task_id, "Failed to deliver close to engine"),
+                    _ => {}
+                }
+            });
+        }
+
+        self.issue_system_async(NotifyEngineStarted { engine_id, sessions });
     }
 }
@@ -0,0 +1,70 @@
+use actix::Handler;
+
+use crate::tasks::engine_ext::EngineExtEvent;
+use crate::tasks::supervisor::TasksSupervisor;
+use crate::tasks::{NotifyEngineExtEvent, NotifyTaskLoudness, NotifyTaskSpectrum};
+
+impl Handler<NotifyEngineExtEvent> for TasksSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyEngineExtEvent, ctx: &mut Self::Context) -> Self::Result {
+        match msg.event {
+            EngineExtEvent::TakeRecorded { task_id,
+                                           track_id,
+                                           segment,
+                                           path,
+                                           pass,
+                                           media_segment, } => {
+                let take = self.new_take(&task_id, track_id, segment, pass, media_segment);
+                self.save_take(task_id, take, path, ctx);
+            }
+            EngineExtEvent::RenderingCancelled { task_id,
+                                                 render_id,
+                                                 partial_path,
+                                                 rendered_length, } => {
+                self.on_render_cancelled(task_id, render_id, partial_path, rendered_length, ctx);
+            }
+            EngineExtEvent::DiagnosticsCaptured { task_id, error, path } => {
+                self.save_diagnostics(task_id, error, path, ctx);
+            }
+            EngineExtEvent::RenderOutputs { task_id,
+                                            render_id,
+                                            outputs, } => {
+                self.on_render_outputs(task_id, render_id, outputs, ctx);
+            }
+            EngineExtEvent::MediaLengths { task_id, lengths } => {
+                self.on_media_lengths(task_id, lengths);
+            }
+            EngineExtEvent::ClockStatus { status } => {
+                self.on_engine_clock_status(msg.engine_id, status);
+            }
+            EngineExtEvent::Resources { resources } => {
+                self.on_engine_resources(msg.engine_id, resources);
+            }
+            EngineExtEvent::TestToneMeasured { test_id, result } => {
+                self.on_test_tone_measured(msg.engine_id, test_id, result);
+            }
+            EngineExtEvent::Started { sessions } => {
+                self.on_engine_started(msg.engine_id, sessions);
+            }
+            EngineExtEvent::Loudness { task_id,
+                                       play_id,
+                                       loudness, } => {
+                if let Some(actor) = self.tasks.get(&task_id).and_then(|task| task.actor.as_ref()) {
+                    actor.do_send(NotifyTaskLoudness { task_id,
+                                                       play_id,
+                                                       loudness });
+                }
+            }
+            EngineExtEvent::Spectrum { task_id,
+                                       play_id,
+                                       spectrum, } => {
+                if let Some(actor) = self.tasks.get(&task_id).and_then(|task| task.actor.as_ref()) {
+                    actor.do_send(NotifyTaskSpectrum { task_id,
+                                                       play_id,
+                                                       spectrum });
+                }
+            }
+        }
+    }
+}
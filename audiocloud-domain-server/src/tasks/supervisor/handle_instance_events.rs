@@ -1,23 +1,58 @@
 use actix::{Context, Handler};
 use actix_broker::BrokerSubscribe;
 
-use crate::fixed_instances::NotifyFixedInstanceReports;
+use crate::config::{NotifyDomainConfiguration, NotifyFixedInstanceRouting};
+use crate::fixed_instances::{channel_reports, sorted_channels, ModelSharing, NotifyFixedInstanceReports};
 use crate::tasks::supervisor::TasksSupervisor;
 
 impl Handler<NotifyFixedInstanceReports> for TasksSupervisor {
     type Result = ();
 
     fn handle(&mut self, msg: NotifyFixedInstanceReports, ctx: &mut Self::Context) -> Self::Result {
-        if let Some(task_id) = self.fixed_instance_membership.get(&msg.instance_id) {
+        let members = match self.fixed_instance_membership.get(&msg.instance_id) {
+            Some(members) => members,
+            None => return,
+        };
+
+        // tasks sharing an instance per channel see the reports of the channels they connect, as if it were theirs
+        let per_channel = self.model_sharing.get(&msg.instance_id.model_id()) == Some(&ModelSharing::PerChannel);
+
+        for (task_id, channels) in members {
             if let Some(actor_addr) = self.tasks.get(task_id).and_then(|task| task.actor.as_ref()) {
-                actor_addr.do_send(msg);
+                let reports = if per_channel && !channels.is_empty() {
+                    channel_reports(&msg.reports, &sorted_channels(channels))
+                } else {
+                    msg.reports.clone()
+                };
+
+                actor_addr.do_send(NotifyFixedInstanceReports { instance_id: { msg.instance_id.clone() },
+                                                                reports:     { reports }, });
             }
         }
     }
 }
 
+impl Handler<NotifyFixedInstanceRouting> for TasksSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyFixedInstanceRouting, ctx: &mut Self::Context) -> Self::Result {
+        // task actors subscribe on their own, this is for tasks started from now on
+        self.fixed_instance_routing = msg.routing;
+    }
+}
+
+impl Handler<NotifyDomainConfiguration> for TasksSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyDomainConfiguration, ctx: &mut Self::Context) -> Self::Result {
+        self.model_sharing = msg.extras.model_sharing;
+    }
+}
+
 impl TasksSupervisor {
     pub(crate) fn subscribe_instance_events(&self, ctx: &mut Context<Self>) {
         self.subscribe_system_async::<NotifyFixedInstanceReports>(ctx);
+        self.subscribe_system_async::<NotifyFixedInstanceRouting>(ctx);
+        self.subscribe_system_async::<NotifyDomainConfiguration>(ctx);
     }
 }
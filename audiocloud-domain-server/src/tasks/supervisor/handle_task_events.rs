@@ -1,6 +1,7 @@
 use actix::{Context, Handler};
 use actix_broker::BrokerSubscribe;
 
+use crate::fixed_instances::task_instance_channels;
 use crate::tasks::supervisor::TasksSupervisor;
 use crate::tasks::{NotifyTaskReservation, NotifyTaskSecurity, NotifyTaskSpec, NotifyTaskState};
 
@@ -19,13 +20,18 @@ impl Handler<NotifyTaskSpec> for TasksSupervisor {
 
     fn handle(&mut self, msg: NotifyTaskSpec, ctx: &mut Self::Context) -> Self::Result {
         // clear all previous associations with the same task ID
-        self.fixed_instance_membership
-            .retain(|_, task_id| task_id != &msg.task_id);
+        for members in self.fixed_instance_membership.values_mut() {
+            members.remove(&msg.task_id);
+        }
+
+        self.fixed_instance_membership.retain(|_, members| !members.is_empty());
 
-        // associate task ID with all the current fixed instance IDs
-        for fixed_instance_id in msg.spec.get_fixed_instance_ids() {
+        // associate task ID with all the current fixed instance IDs, and the channels it connects on each
+        for (fixed_instance_id, channels) in task_instance_channels(&msg.spec) {
             self.fixed_instance_membership
-                .insert(fixed_instance_id.clone(), msg.task_id.clone());
+                .entry(fixed_instance_id)
+                .or_default()
+                .insert(msg.task_id.clone(), channels);
         }
 
         if let Some(task) = self.tasks.get_mut(&msg.task_id) {
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+
+use actix::fut::LocalBoxActorFuture;
+use actix::{fut, ActorFutureExt, Context, ContextFutureSpawner, Handler, WrapFuture};
+use actix_broker::BrokerIssue;
+use tracing::*;
+
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::AppTaskId;
+
+use crate::tasks::{GetTaskKeyScopes, NotifyTaskKeyScopes, RequireTaskScope, SetTaskKeyScope};
+use crate::{DomainResult, DomainSecurity, SecureKeyScope, TaskKeyScopes};
+
+use super::TasksSupervisor;
+
+impl TasksSupervisor {
+    /// Fail unless `security` has access to the task with at least the `required` scope
+    pub(super) fn require_scope(&self,
+                                task_id: &AppTaskId,
+                                security: &DomainSecurity,
+                                required: SecureKeyScope)
+                                -> DomainResult {
+        match self.tasks.get(task_id) {
+            Some(task) => security.require_scope(task_id, &task.security, &task.key_scopes, required),
+            None => Err(DomainError::TaskNotFound { task_id: task_id.clone(), }),
+        }
+    }
+
+    /// Attach the key scopes set before a restart to the configured tasks
+    pub(crate) fn restore_task_key_scopes(&self, ctx: &mut Context<Self>) {
+        let db = self.db.clone();
+
+        async move { db.fetch_all_task_key_scopes().await }.into_actor(self)
+                                                           .map(Self::on_task_key_scopes_restored)
+                                                           .wait(ctx);
+    }
+
+    fn on_task_key_scopes_restored(res: anyhow::Result<HashMap<AppTaskId, TaskKeyScopes>>,
+                                   actor: &mut Self,
+                                   ctx: &mut Context<Self>) {
+        match res {
+            Ok(key_scopes) => {
+                for (task_id, scopes) in key_scopes {
+                    if let Some(task) = actor.tasks.get_mut(&task_id) {
+                        debug!(%task_id, keys = scopes.len(), "Restored persisted key scopes");
+                        task.key_scopes = scopes;
+                    }
+                }
+            }
+            Err(error) => warn!(%error, "Failed to restore persisted key scopes"),
+        }
+    }
+
+    /// Persist the key scopes of a task, then apply them and let the sockets know
+    pub(super) fn update_task_key_scopes(&mut self,
+                                         task_id: AppTaskId,
+                                         scopes: TaskKeyScopes)
+                                         -> LocalBoxActorFuture<Self, DomainResult<TaskKeyScopes>> {
+        let db = self.db.clone();
+        let persisted = scopes.clone();
+        let persisted_task_id = task_id.clone();
+
+        async move { db.save_task_key_scopes(&persisted_task_id, &persisted).await }
+            .into_actor(self)
+            .map(move |res, actor, ctx| {
+                if let Err(error) = res {
+                    warn!(%error, %task_id, "Failed to persist key scopes");
+                    let error = format!("Failed to persist key scopes: {error}");
+                    return Err(DomainError::BadGateway { error });
+                }
+
+                let task = actor.tasks
+                                .get_mut(&task_id)
+                                .ok_or_else(|| DomainError::TaskNotFound { task_id: task_id.clone(), })?;
+
+                task.key_scopes = scopes.clone();
+
+                actor.issue_system_async(NotifyTaskKeyScopes { task_id: { task_id },
+                                                               scopes:  { scopes.clone() }, });
+
+                Ok(scopes)
+            })
+            .boxed_local()
+    }
+}
+
+impl Handler<GetTaskKeyScopes> for TasksSupervisor {
+    type Result = DomainResult<TaskKeyScopes>;
+
+    fn handle(&mut self, msg: GetTaskKeyScopes, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Full)?;
+
+        Ok(self.tasks
+               .get(&msg.task_id)
+               .map(|task| task.key_scopes.clone())
+               .unwrap_or_default())
+    }
+}
+
+impl Handler<RequireTaskScope> for TasksSupervisor {
+    type Result = DomainResult;
+
+    fn handle(&mut self, msg: RequireTaskScope, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, msg.scope)
+    }
+}
+
+impl Handler<SetTaskKeyScope> for TasksSupervisor {
+    type Result = LocalBoxActorFuture<Self, DomainResult<TaskKeyScopes>>;
+
+    fn handle(&mut self, msg: SetTaskKeyScope, ctx: &mut Self::Context) -> Self::Result {
+        if let Err(error) = self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Full) {
+            return fut::err(error).into_actor(self).boxed_local();
+        }
+
+        let mut scopes = match self.tasks.get(&msg.task_id) {
+            Some(task) => task.key_scopes.clone(),
+            None => {
+                return fut::err(DomainError::TaskNotFound { task_id: msg.task_id }).into_actor(self)
+                                                                                   .boxed_local()
+            }
+        };
+
+        match msg.update.scope {
+            Some(scope) => scopes.insert(msg.update.secure_key, scope),
+            None => scopes.remove(&msg.update.secure_key),
+        };
+
+        self.update_task_key_scopes(msg.task_id, scopes)
+    }
+}
@@ -0,0 +1,28 @@
+use actix::Handler;
+use actix_broker::BrokerIssue;
+
+use audiocloud_api::domain::DomainError;
+
+use crate::tasks::{NotifyTaskLatencyProfile, SetTaskLatencyProfile, TaskLatencyProfile};
+use crate::{DomainResult, SecureKeyScope};
+
+use super::TasksSupervisor;
+
+impl Handler<SetTaskLatencyProfile> for TasksSupervisor {
+    type Result = DomainResult<TaskLatencyProfile>;
+
+    fn handle(&mut self, msg: SetTaskLatencyProfile, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Transport)?;
+
+        let task = self.tasks
+                       .get_mut(&msg.task_id)
+                       .ok_or_else(|| DomainError::TaskNotFound { task_id: msg.task_id.clone(), })?;
+
+        task.latency_profile = msg.profile;
+
+        self.issue_system_async(NotifyTaskLatencyProfile { task_id: { msg.task_id },
+                                                           profile: { msg.profile }, });
+
+        Ok(msg.profile)
+    }
+}
@@ -0,0 +1,32 @@
+use actix::Handler;
+use actix_broker::BrokerIssue;
+
+use audiocloud_api::domain::DomainError;
+
+use crate::tasks::{NotifyTaskLeadIn, SetTaskLeadIn, TaskLeadIn};
+use crate::{DomainResult, SecureKeyScope};
+
+use super::TasksSupervisor;
+
+impl Handler<SetTaskLeadIn> for TasksSupervisor {
+    type Result = DomainResult<TaskLeadIn>;
+
+    fn handle(&mut self, msg: SetTaskLeadIn, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Transport)?;
+
+        let task = self.tasks
+                       .get_mut(&msg.task_id)
+                       .ok_or_else(|| DomainError::TaskNotFound { task_id: msg.task_id.clone(), })?;
+
+        // negative lengths are as good as none
+        let lead_in = TaskLeadIn { pre_roll:      { msg.lead_in.pre_roll.max(0.0) },
+                                   count_in_bars: { msg.lead_in.count_in_bars }, };
+
+        task.lead_in = lead_in;
+
+        self.issue_system_async(NotifyTaskLeadIn { task_id: { msg.task_id },
+                                                   lead_in: { lead_in }, });
+
+        Ok(lead_in)
+    }
+}
@@ -0,0 +1,33 @@
+use actix::Handler;
+use actix_broker::BrokerIssue;
+
+use audiocloud_api::domain::DomainError;
+
+use crate::tasks::{NotifyTaskLoudnessTarget, SetTaskLoudnessTarget, TaskLoudnessTarget};
+use crate::{DomainResult, SecureKeyScope};
+
+use super::TasksSupervisor;
+
+impl Handler<SetTaskLoudnessTarget> for TasksSupervisor {
+    type Result = DomainResult<TaskLoudnessTarget>;
+
+    fn handle(&mut self, msg: SetTaskLoudnessTarget, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Transport)?;
+
+        msg.target
+           .validate()
+           .map_err(|error| DomainError::Serialization { error: { format!("Invalid loudness target: {error}") }, })?;
+
+        let task = self.tasks
+                       .get_mut(&msg.task_id)
+                       .ok_or_else(|| DomainError::TaskNotFound { task_id: msg.task_id.clone(), })?;
+
+        task.loudness_target = msg.target;
+        self.persist_task_settings(&msg.task_id);
+
+        self.issue_system_async(NotifyTaskLoudnessTarget { task_id: { msg.task_id },
+                                                           target:  { msg.target }, });
+
+        Ok(msg.target)
+    }
+}
@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+
+use actix::Handler;
+use actix_broker::BrokerIssue;
+
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::newtypes::{AppTaskId, TrackMediaId};
+
+use crate::tasks::{
+    GetTaskMediaRates, NotifyTaskMediaLengths, NotifyTaskMediaRates, SetTaskMediaRates, TaskMediaLengths,
+    TaskMediaRatesState,
+};
+use crate::{DomainResult, SecureKeyScope};
+
+use super::TasksSupervisor;
+
+impl Handler<SetTaskMediaRates> for TasksSupervisor {
+    type Result = DomainResult<TaskMediaRatesState>;
+
+    fn handle(&mut self, msg: SetTaskMediaRates, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Full)?;
+
+        let task = self.tasks
+                       .get_mut(&msg.task_id)
+                       .ok_or_else(|| DomainError::TaskNotFound { task_id: msg.task_id.clone(), })?;
+
+        msg.rates
+           .validate(&task.spec)
+           .map_err(|error| DomainError::Serialization { error: { format!("Invalid media rates: {error}") }, })?;
+
+        task.media_rates = msg.rates.clone();
+
+        // the lengths stay as they were until the engine reports them at the new rates
+        let state = TaskMediaRatesState { rates:   { msg.rates.clone() },
+                                          lengths: { task.media_lengths.clone() }, };
+
+        self.issue_system_async(NotifyTaskMediaRates { task_id: { msg.task_id },
+                                                       rates:   { msg.rates }, });
+
+        Ok(state)
+    }
+}
+
+impl Handler<GetTaskMediaRates> for TasksSupervisor {
+    type Result = DomainResult<TaskMediaRatesState>;
+
+    fn handle(&mut self, msg: GetTaskMediaRates, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Listen)?;
+
+        Ok(self.tasks
+               .get(&msg.task_id)
+               .map(|task| TaskMediaRatesState { rates:   { task.media_rates.clone() },
+                                                 lengths: { task.media_lengths.clone() }, })
+               .unwrap_or_default())
+    }
+}
+
+impl TasksSupervisor {
+    /// Keep the timeline lengths of the track media the engine reported, telling the clients following the task
+    pub(crate) fn on_media_lengths(&mut self, task_id: AppTaskId, lengths: HashMap<TrackMediaId, f64>) {
+        let task = match self.tasks.get_mut(&task_id) {
+            Some(task) => task,
+            None => return,
+        };
+
+        let lengths = TaskMediaLengths { media: lengths };
+        if task.media_lengths == lengths {
+            return;
+        }
+
+        task.media_lengths = lengths.clone();
+
+        self.issue_system_async(NotifyTaskMediaLengths { task_id, lengths });
+    }
+}
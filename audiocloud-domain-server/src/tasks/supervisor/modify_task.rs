@@ -7,7 +7,7 @@ use audiocloud_api::domain::DomainError;
 
 use crate::tasks::supervisor::SupervisedTask;
 use crate::tasks::ModifyTask;
-use crate::DomainResult;
+use crate::{DomainResult, SecureKeyScope};
 
 use super::TasksSupervisor;
 
@@ -17,6 +17,10 @@ impl Handler<ModifyTask> for TasksSupervisor {
     fn handle(&mut self, msg: ModifyTask, ctx: &mut Self::Context) -> Self::Result {
         use DomainError::*;
 
+        if let Err(error) = self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Full) {
+            return fut::err(error).into_actor(self).boxed_local();
+        }
+
         match self.tasks.get_mut(&msg.task_id) {
             Some(task) => match task.actor.as_ref() {
                 Some(actor) => actor.send(msg)
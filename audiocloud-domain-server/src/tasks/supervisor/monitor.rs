@@ -0,0 +1,45 @@
+use actix::Handler;
+use actix_broker::BrokerIssue;
+
+use audiocloud_api::domain::DomainError;
+
+use crate::tasks::{GetTaskMonitor, NotifyTaskMonitor, SetTaskMonitor, TaskMonitor};
+use crate::{DomainResult, SecureKeyScope};
+
+use super::TasksSupervisor;
+
+impl Handler<SetTaskMonitor> for TasksSupervisor {
+    type Result = DomainResult<TaskMonitor>;
+
+    fn handle(&mut self, msg: SetTaskMonitor, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Transport)?;
+
+        let task = self.tasks
+                       .get_mut(&msg.task_id)
+                       .ok_or_else(|| DomainError::TaskNotFound { task_id: msg.task_id.clone(), })?;
+
+        msg.monitor
+           .validate(&task.spec)
+           .map_err(|error| DomainError::Serialization { error: { format!("Invalid monitor: {error}") }, })?;
+
+        task.monitor = msg.monitor.clone();
+
+        self.issue_system_async(NotifyTaskMonitor { task_id: { msg.task_id },
+                                                    monitor: { msg.monitor.clone() }, });
+
+        Ok(msg.monitor)
+    }
+}
+
+impl Handler<GetTaskMonitor> for TasksSupervisor {
+    type Result = DomainResult<TaskMonitor>;
+
+    fn handle(&mut self, msg: GetTaskMonitor, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Listen)?;
+
+        Ok(self.tasks
+               .get(&msg.task_id)
+               .map(|task| task.monitor.clone())
+               .unwrap_or_default())
+    }
+}
@@ -0,0 +1,43 @@
+use actix::fut::LocalBoxActorFuture;
+use actix::{fut, ActorFutureExt, Handler, WrapFuture};
+
+use audiocloud_api::audio_engine::TaskRendering;
+use audiocloud_api::domain::DomainError;
+
+use crate::tasks::RunTaskNullTest;
+use crate::{DomainResult, SecureKeyScope};
+
+use super::TasksSupervisor;
+
+impl Handler<RunTaskNullTest> for TasksSupervisor {
+    type Result = LocalBoxActorFuture<Self, DomainResult<TaskRendering>>;
+
+    fn handle(&mut self, msg: RunTaskNullTest, ctx: &mut Self::Context) -> Self::Result {
+        use DomainError::*;
+
+        if let Err(error) = self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Transport) {
+            return fut::err(error).into_actor(self).boxed_local();
+        }
+
+        if let Err(error) = msg.request.validate() {
+            let error = Serialization { error: { format!("Invalid null test: {error}") }, };
+            return fut::err(error).into_actor(self).boxed_local();
+        }
+
+        if let Some(task) = self.tasks.get(&msg.task_id).and_then(|task| task.actor.as_ref()) {
+            let task_id = msg.task_id.clone();
+            task.send(msg)
+                .into_actor(self)
+                .map(move |res, actor, ctx| match res {
+                    Ok(result) => result,
+                    Err(err) => {
+                        Err(BadGateway { error: format!("Task actor {task_id} failed to run null test: {err}"), })
+                    }
+                })
+                .boxed_local()
+        } else {
+            fut::err(TaskNotFound { task_id: msg.task_id.clone(), }).into_actor(self)
+                                                                    .boxed_local()
+        }
+    }
+}
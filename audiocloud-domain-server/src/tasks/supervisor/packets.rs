@@ -1,15 +1,17 @@
+use std::collections::HashMap;
 use std::time::Duration;
 
 use actix::{AsyncContext, Context, Handler};
+use itertools::Itertools;
 
 use audiocloud_api::domain::streaming::StreamStats;
 use audiocloud_api::domain::DomainError;
-use audiocloud_api::{StreamingPacket, Timestamped};
+use audiocloud_api::{PlayId, StreamingPacket, Timestamped};
 
 use crate::tasks::messages::NotifyStreamingPacket;
 use crate::tasks::supervisor::TasksSupervisor;
-use crate::tasks::{GenerateStreamStats, GetStreamPacket};
-use crate::DomainResult;
+use crate::tasks::{GenerateStreamStats, GetStreamPacket, ListStreamPackets, ListStreamPacketsAfter};
+use crate::{DomainResult, SecureKeyScope};
 
 impl TasksSupervisor {
     pub(crate) fn update_packet_cache(&mut self, ctx: &mut Context<Self>) {
@@ -29,6 +31,27 @@ impl TasksSupervisor {
     pub(crate) fn register_packet_cache_cleanup(&self, ctx: &mut Context<Self>) {
         ctx.run_interval(Duration::from_millis(250), Self::update_packet_cache);
     }
+
+    /// Keep a packet to resume and retransmit the stream of its play from
+    pub(crate) fn cache_packet(packet_cache: &mut HashMap<PlayId, HashMap<u64, Timestamped<StreamingPacket>>>,
+                               packet: StreamingPacket) {
+        packet_cache.entry(packet.play_id)
+                    .or_default()
+                    .insert(packet.serial, Timestamped::new(packet));
+    }
+
+    /// The cached packets of a play with the requested serials, in order and each once. Serials no longer cached are
+    /// skipped
+    pub(crate) fn cached_packets(packet_cache: &HashMap<u64, Timestamped<StreamingPacket>>,
+                                 serials: &[u64])
+                                 -> Vec<StreamingPacket> {
+        serials.iter()
+               .sorted()
+               .dedup()
+               .filter_map(|serial| packet_cache.get(serial))
+               .map(|packet| packet.value().clone())
+               .collect()
+    }
 }
 
 impl Handler<NotifyStreamingPacket> for TasksSupervisor {
@@ -36,10 +59,7 @@ impl Handler<NotifyStreamingPacket> for TasksSupervisor {
 
     fn handle(&mut self, msg: NotifyStreamingPacket, ctx: &mut Self::Context) -> Self::Result {
         if let Some(task) = self.tasks.get_mut(&msg.task_id) {
-            task.packet_cache
-                .entry(msg.packet.play_id.clone())
-                .or_default()
-                .insert(msg.packet.serial, Timestamped::new(msg.packet.clone()));
+            Self::cache_packet(&mut task.packet_cache, msg.packet);
         }
     }
 }
@@ -48,6 +68,8 @@ impl Handler<GenerateStreamStats> for TasksSupervisor {
     type Result = DomainResult<StreamStats>;
 
     fn handle(&mut self, msg: GenerateStreamStats, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Listen)?;
+
         let task_id = msg.task_id;
         let play_id = msg.play_id;
 
@@ -74,6 +96,8 @@ impl Handler<GetStreamPacket> for TasksSupervisor {
     type Result = DomainResult<StreamingPacket>;
 
     fn handle(&mut self, msg: GetStreamPacket, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Listen)?;
+
         let task_id = msg.task_id;
         let play_id = msg.play_id;
         let serial = msg.serial;
@@ -92,3 +116,45 @@ impl Handler<GetStreamPacket> for TasksSupervisor {
         }
     }
 }
+
+impl Handler<ListStreamPacketsAfter> for TasksSupervisor {
+    type Result = DomainResult<Vec<StreamingPacket>>;
+
+    fn handle(&mut self, msg: ListStreamPacketsAfter, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Listen)?;
+
+        let task_id = msg.task_id;
+        let play_id = msg.play_id;
+
+        match self.tasks.get(&task_id) {
+            None => Err(DomainError::TaskNotFound { task_id }),
+            Some(task) => match task.packet_cache.get(&play_id) {
+                None => Err(DomainError::TaskStreamNotFound { task_id, play_id }),
+                Some(packet_cache) => Ok(packet_cache.iter()
+                                                     .filter(|(serial, _)| **serial > msg.serial)
+                                                     .sorted_by_key(|(serial, _)| **serial)
+                                                     .map(|(_, packet)| packet.value().clone())
+                                                     .collect()),
+            },
+        }
+    }
+}
+
+impl Handler<ListStreamPackets> for TasksSupervisor {
+    type Result = DomainResult<Vec<StreamingPacket>>;
+
+    fn handle(&mut self, msg: ListStreamPackets, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Listen)?;
+
+        let task_id = msg.task_id;
+        let play_id = msg.play_id;
+
+        match self.tasks.get(&task_id) {
+            None => Err(DomainError::TaskNotFound { task_id }),
+            Some(task) => match task.packet_cache.get(&play_id) {
+                None => Err(DomainError::TaskStreamNotFound { task_id, play_id }),
+                Some(packet_cache) => Ok(Self::cached_packets(packet_cache, &msg.serials)),
+            },
+        }
+    }
+}
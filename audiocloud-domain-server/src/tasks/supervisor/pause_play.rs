@@ -0,0 +1,92 @@
+use actix::fut::LocalBoxActorFuture;
+use actix::{fut, ActorFutureExt, Handler, WrapFuture};
+
+use audiocloud_api::domain::DomainError;
+
+use crate::tasks::{GetTaskTransport, PausePlayTask, ResumePlayTask, TaskPlayPause, TaskTransport};
+use crate::{DomainResult, SecureKeyScope};
+
+use super::TasksSupervisor;
+
+impl Handler<PausePlayTask> for TasksSupervisor {
+    type Result = LocalBoxActorFuture<Self, DomainResult<TaskPlayPause>>;
+
+    fn handle(&mut self, msg: PausePlayTask, ctx: &mut Self::Context) -> Self::Result {
+        use DomainError::*;
+
+        if let Err(error) = self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Transport) {
+            return fut::err(error).into_actor(self).boxed_local();
+        }
+
+        if let Some(task) = self.tasks.get(&msg.task_id).and_then(|task| task.actor.as_ref()) {
+            let task_id = msg.task_id.clone();
+            task.send(msg)
+                .into_actor(self)
+                .map(move |res, actor, ctx| match res {
+                    Ok(result) => result,
+                    Err(err) => Err(BadGateway { error: format!("Task actor {task_id} failed to pause: {err}"), }),
+                })
+                .boxed_local()
+        } else {
+            fut::err(TaskNotFound { task_id: msg.task_id.clone(), }).into_actor(self)
+                                                                    .boxed_local()
+        }
+    }
+}
+
+impl Handler<ResumePlayTask> for TasksSupervisor {
+    type Result = LocalBoxActorFuture<Self, DomainResult<TaskPlayPause>>;
+
+    fn handle(&mut self, msg: ResumePlayTask, ctx: &mut Self::Context) -> Self::Result {
+        use DomainError::*;
+
+        if let Err(error) = self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Transport) {
+            return fut::err(error).into_actor(self).boxed_local();
+        }
+
+        if let Some(task) = self.tasks.get(&msg.task_id).and_then(|task| task.actor.as_ref()) {
+            let task_id = msg.task_id.clone();
+            task.send(msg)
+                .into_actor(self)
+                .map(move |res, actor, ctx| match res {
+                    Ok(result) => result,
+                    Err(err) => Err(BadGateway { error: format!("Task actor {task_id} failed to resume: {err}"), }),
+                })
+                .boxed_local()
+        } else {
+            fut::err(TaskNotFound { task_id: msg.task_id.clone(), }).into_actor(self)
+                                                                    .boxed_local()
+        }
+    }
+}
+
+impl Handler<GetTaskTransport> for TasksSupervisor {
+    type Result = LocalBoxActorFuture<Self, DomainResult<TaskTransport>>;
+
+    fn handle(&mut self, msg: GetTaskTransport, ctx: &mut Self::Context) -> Self::Result {
+        use DomainError::*;
+
+        if let Err(error) = self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Listen) {
+            return fut::err(error).into_actor(self).boxed_local();
+        }
+
+        match self.tasks.get(&msg.task_id) {
+            Some(task) => match task.actor.as_ref() {
+                Some(actor) => {
+                    let task_id = msg.task_id.clone();
+                    actor.send(msg)
+                         .into_actor(self)
+                         .map(move |res, actor, ctx| match res {
+                             Ok(result) => result,
+                             Err(err) => Err(BadGateway { error: format!("Task actor {task_id} failed: {err}"), }),
+                         })
+                         .boxed_local()
+                }
+                // a task without an actor is not active on an engine, so it can not be playing
+                None => fut::ok(TaskTransport::default()).into_actor(self).boxed_local(),
+            },
+            None => fut::err(TaskNotFound { task_id: msg.task_id.clone(), }).into_actor(self)
+                                                                            .boxed_local(),
+        }
+    }
+}
@@ -6,16 +6,24 @@ use audiocloud_api::audio_engine::TaskPlaying;
 use audiocloud_api::domain::DomainError;
 
 use crate::tasks::PlayTask;
-use crate::DomainResult;
+use crate::{DomainResult, SecureKeyScope};
 
 use super::TasksSupervisor;
 
 impl Handler<PlayTask> for TasksSupervisor {
     type Result = LocalBoxActorFuture<Self, DomainResult<TaskPlaying>>;
 
-    fn handle(&mut self, msg: PlayTask, ctx: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, mut msg: PlayTask, ctx: &mut Self::Context) -> Self::Result {
         use DomainError::*;
 
+        if let Err(error) = self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Transport) {
+            return fut::err(error).into_actor(self).boxed_local();
+        }
+
+        if let Some(task) = self.tasks.get(&msg.task_id) {
+            msg.watermark = task.watermark.for_play(msg.client_id.as_ref(), &msg.security);
+        }
+
         if let Some(task) = self.tasks.get(&msg.task_id).and_then(|task| task.actor.as_ref()) {
             let task_id = msg.task_id.clone();
             task.send(msg)
@@ -0,0 +1,45 @@
+use actix::Handler;
+use actix_broker::BrokerIssue;
+
+use audiocloud_api::domain::DomainError;
+
+use crate::tasks::{GetTaskPlaylist, NotifyTaskPlaylist, SetTaskPlaylist, TaskPlaylist};
+use crate::{DomainResult, SecureKeyScope};
+
+use super::TasksSupervisor;
+
+impl Handler<SetTaskPlaylist> for TasksSupervisor {
+    type Result = DomainResult<TaskPlaylist>;
+
+    fn handle(&mut self, msg: SetTaskPlaylist, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Transport)?;
+
+        msg.playlist
+           .validate()
+           .map_err(|error| DomainError::Serialization { error: { format!("Invalid playlist: {error}") }, })?;
+
+        let task = self.tasks
+                       .get_mut(&msg.task_id)
+                       .ok_or_else(|| DomainError::TaskNotFound { task_id: msg.task_id.clone(), })?;
+
+        task.playlist = msg.playlist.clone();
+
+        self.issue_system_async(NotifyTaskPlaylist { task_id:  { msg.task_id },
+                                                     playlist: { msg.playlist.clone() }, });
+
+        Ok(msg.playlist)
+    }
+}
+
+impl Handler<GetTaskPlaylist> for TasksSupervisor {
+    type Result = DomainResult<TaskPlaylist>;
+
+    fn handle(&mut self, msg: GetTaskPlaylist, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Listen)?;
+
+        Ok(self.tasks
+               .get(&msg.task_id)
+               .map(|task| task.playlist.clone())
+               .unwrap_or_default())
+    }
+}
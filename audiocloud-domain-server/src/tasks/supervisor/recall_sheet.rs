@@ -0,0 +1,104 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use actix::fut::LocalBoxActorFuture;
+use actix::{fut, ActorFutureExt, Handler, WrapFuture};
+use actix_broker::BrokerIssue;
+use tracing::*;
+
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::{now, AppMediaObjectId, AppTaskId, MediaObject, MediaObjectId};
+
+use crate::db::Db;
+use crate::models::cached_model;
+use crate::tasks::{GenerateTaskRecallSheet, NotifyTaskRecallSheet, TaskRecallSheet};
+use crate::{DomainResult, SecureKeyScope};
+
+use super::TasksSupervisor;
+
+impl Handler<GenerateTaskRecallSheet> for TasksSupervisor {
+    type Result = LocalBoxActorFuture<Self, DomainResult<TaskRecallSheet>>;
+
+    fn handle(&mut self, msg: GenerateTaskRecallSheet, ctx: &mut Self::Context) -> Self::Result {
+        use DomainError::*;
+
+        if let Err(error) = self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Listen) {
+            return fut::err(error).into_actor(self).boxed_local();
+        }
+
+        let task = match self.tasks.get(&msg.task_id) {
+            Some(task) => task,
+            None => {
+                return fut::err(TaskNotFound { task_id: msg.task_id.clone(), }).into_actor(self)
+                                                                               .boxed_local()
+            }
+        };
+
+        let sheet = TaskRecallSheet::new(msg.task_id.clone(), &task.spec, &task.reservations, now(), cached_model);
+        let db = self.db.clone();
+        let media_root = self.media_root.clone();
+        let task_id = msg.task_id;
+
+        async move { save_recall_sheet(&db, &media_root, sheet).await }.into_actor(self)
+                                                                       .map(move |res, actor, ctx| {
+                                                                           actor.on_recall_sheet_saved(task_id, res)
+                                                                       })
+                                                                       .boxed_local()
+    }
+}
+
+impl TasksSupervisor {
+    fn on_recall_sheet_saved(&mut self,
+                             task_id: AppTaskId,
+                             res: anyhow::Result<TaskRecallSheet>)
+                             -> DomainResult<TaskRecallSheet> {
+        match res {
+            Ok(sheet) => {
+                info!(%task_id, revision = sheet.revision, "Recall sheet stored");
+                self.issue_system_async(NotifyTaskRecallSheet { task_id,
+                                                                sheet: sheet.clone() });
+                Ok(sheet)
+            }
+            Err(error) => {
+                warn!(%error, %task_id, "Failed to store recall sheet");
+                Err(DomainError::BadGateway { error: format!("Failed to store recall sheet: {error}"), })
+            }
+        }
+    }
+}
+
+/// Write the recall sheet as JSON and HTML into the folder of the app and register both as media objects
+async fn save_recall_sheet(db: &Db, media_root: &Path, mut sheet: TaskRecallSheet) -> anyhow::Result<TaskRecallSheet> {
+    let task_id = sheet.task_id.clone();
+    let name = format!("recall-{}-{}", task_id.task_id, sheet.revision);
+
+    let json_id = AppMediaObjectId::new(task_id.app_id.clone(), MediaObjectId::new(name.clone()));
+    let html_id = AppMediaObjectId::new(task_id.app_id.clone(), MediaObjectId::new(format!("{name}-html")));
+
+    let json_path = PathBuf::from("recalls").join(task_id.app_id.as_str())
+                                            .join(format!("{name}.json"));
+    let html_path = PathBuf::from("recalls").join(task_id.app_id.as_str())
+                                            .join(format!("{name}.html"));
+
+    sheet.media_id = Some(json_id.clone());
+    sheet.html_media_id = Some(html_id.clone());
+
+    if let Some(parent) = media_root.join(&json_path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    fs::write(media_root.join(&json_path), serde_json::to_vec_pretty(&sheet)?)?;
+    fs::write(media_root.join(&html_path), sheet.to_html())?;
+
+    for (media_id, path) in [(json_id, json_path), (html_id, html_path)] {
+        db.save_media(MediaObject { id:       { media_id },
+                                    metadata: { None },
+                                    path:     { Some(path.to_string_lossy().to_string()) },
+                                    download: { None },
+                                    upload:   { None },
+                                    revision: { 0 }, })
+          .await?;
+    }
+
+    Ok(sheet)
+}
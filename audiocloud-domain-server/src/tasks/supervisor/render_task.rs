@@ -1,12 +1,17 @@
 use actix::fut::LocalBoxActorFuture;
-use actix::{fut, ActorFutureExt, Handler, WrapFuture};
+use actix::{fut, ActorFutureExt, Context, ContextFutureSpawner, Handler, WrapFuture};
+use actix_broker::BrokerIssue;
+use tracing::*;
 
 use audiocloud_api::audio_engine::TaskRendering;
-
+use audiocloud_api::common::media::RenderId;
 use audiocloud_api::domain::DomainError;
+use audiocloud_api::{AppMediaObjectId, AppTaskId, MediaObject, MediaObjectId};
 
-use crate::tasks::RenderTask;
-use crate::DomainResult;
+use crate::db::Db;
+use crate::tasks::engine_ext::{validate_render_formats, EngineRenderOutput, RenderFormat};
+use crate::tasks::{NotifyTaskRenderOutputs, RenderTask, TaskRenderNormalization, TaskRenderOutput, TaskRenderOutputs};
+use crate::{DomainResult, SecureKeyScope};
 
 use super::TasksSupervisor;
 
@@ -16,6 +21,20 @@ impl Handler<RenderTask> for TasksSupervisor {
     fn handle(&mut self, msg: RenderTask, ctx: &mut Self::Context) -> Self::Result {
         use DomainError::*;
 
+        if let Err(error) = self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Transport) {
+            return fut::err(error).into_actor(self).boxed_local();
+        }
+
+        if let Err(error) = validate_render_formats(&msg.formats) {
+            let error = Serialization { error: { format!("Invalid render formats: {error}") }, };
+            return fut::err(error).into_actor(self).boxed_local();
+        }
+
+        if let Some(Err(error)) = msg.normalization.as_ref().map(TaskRenderNormalization::validate) {
+            let error = Serialization { error: { format!("Invalid render normalization: {error}") }, };
+            return fut::err(error).into_actor(self).boxed_local();
+        }
+
         if let Some(task) = self.tasks.get(&msg.task_id).and_then(|task| task.actor.as_ref()) {
             let task_id = msg.task_id.clone();
             task.send(msg)
@@ -31,3 +50,61 @@ impl Handler<RenderTask> for TasksSupervisor {
         }
     }
 }
+
+impl TasksSupervisor {
+    /// Register the converted files of a finished render as media objects of the app of the task
+    pub(crate) fn on_render_outputs(&mut self,
+                                    task_id: AppTaskId,
+                                    render_id: RenderId,
+                                    outputs: Vec<EngineRenderOutput>,
+                                    ctx: &mut Context<Self>) {
+        let saved = save_render_outputs(self.db.clone(), task_id.clone(), render_id, outputs);
+
+        saved.into_actor(self)
+             .map(move |outputs, actor, _ctx| actor.issue_system_async(NotifyTaskRenderOutputs { task_id, outputs }))
+             .spawn(ctx);
+    }
+}
+
+async fn save_render_outputs(db: Db,
+                             task_id: AppTaskId,
+                             render_id: RenderId,
+                             outputs: Vec<EngineRenderOutput>)
+                             -> TaskRenderOutputs {
+    let mut saved = vec![];
+
+    for EngineRenderOutput { format, result } in outputs {
+        let media = result.map(|path| render_output_media(&task_id, &render_id, &format, path));
+        let media_id = match media {
+            Ok(media) => {
+                let media_id = media.id.clone();
+                match db.save_media(media).await {
+                    Ok(()) => Ok(media_id),
+                    Err(error) => {
+                        warn!(%error, %task_id, format = format.key(), "Failed to save converted render");
+                        Err(error.to_string())
+                    }
+                }
+            }
+            Err(error) => Err(error),
+        };
+
+        saved.push(TaskRenderOutput { format:   { format },
+                                      media_id: { media_id.as_ref().ok().cloned() },
+                                      error:    { media_id.err() }, });
+    }
+
+    TaskRenderOutputs { render_id,
+                        outputs: saved }
+}
+
+fn render_output_media(task_id: &AppTaskId, render_id: &RenderId, format: &RenderFormat, path: String) -> MediaObject {
+    let media_id = MediaObjectId::new(format!("render-{render_id}-{}", format.key()));
+
+    MediaObject { id:       { AppMediaObjectId::new(task_id.app_id.clone(), media_id) },
+                  metadata: { None },
+                  path:     { Some(path) },
+                  download: { None },
+                  upload:   { None },
+                  revision: { 0 }, }
+}
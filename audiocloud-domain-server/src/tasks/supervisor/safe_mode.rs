@@ -0,0 +1,63 @@
+use actix::fut::LocalBoxActorFuture;
+use actix::{fut, ActorFutureExt, Handler, WrapFuture};
+
+use audiocloud_api::domain::DomainError;
+
+use crate::tasks::{EnableTaskSpecElements, GetTaskSafeMode, TaskSafeMode};
+use crate::{DomainResult, SecureKeyScope};
+
+use super::TasksSupervisor;
+
+impl Handler<GetTaskSafeMode> for TasksSupervisor {
+    type Result = LocalBoxActorFuture<Self, DomainResult<TaskSafeMode>>;
+
+    fn handle(&mut self, msg: GetTaskSafeMode, ctx: &mut Self::Context) -> Self::Result {
+        use DomainError::*;
+
+        if let Err(error) = self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Listen) {
+            return fut::err(error).into_actor(self).boxed_local();
+        }
+
+        if let Some(task) = self.tasks.get(&msg.task_id).and_then(|task| task.actor.as_ref()) {
+            let task_id = msg.task_id.clone();
+            task.send(msg)
+                .into_actor(self)
+                .map(move |res, actor, ctx| match res {
+                    Ok(result) => result,
+                    Err(err) => {
+                        Err(BadGateway { error: format!("Task actor {task_id} failed to get safe mode: {err}"), })
+                    }
+                })
+                .boxed_local()
+        } else {
+            fut::err(TaskNotFound { task_id: msg.task_id.clone(), }).into_actor(self)
+                                                                    .boxed_local()
+        }
+    }
+}
+
+impl Handler<EnableTaskSpecElements> for TasksSupervisor {
+    type Result = LocalBoxActorFuture<Self, DomainResult<TaskSafeMode>>;
+
+    fn handle(&mut self, msg: EnableTaskSpecElements, ctx: &mut Self::Context) -> Self::Result {
+        use DomainError::*;
+
+        if let Err(error) = self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Full) {
+            return fut::err(error).into_actor(self).boxed_local();
+        }
+
+        if let Some(task) = self.tasks.get(&msg.task_id).and_then(|task| task.actor.as_ref()) {
+            let task_id = msg.task_id.clone();
+            task.send(msg)
+                .into_actor(self)
+                .map(move |res, actor, ctx| match res {
+                    Ok(result) => result,
+                    Err(err) => Err(BadGateway { error: format!("Task actor {task_id} failed to enable spec elements: {err}"), }),
+                })
+                .boxed_local()
+        } else {
+            fut::err(TaskNotFound { task_id: msg.task_id.clone(), }).into_actor(self)
+                                                                    .boxed_local()
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+
+use actix::fut::LocalBoxActorFuture;
+use actix::{fut, ActorFutureExt, Context, ContextFutureSpawner, Handler, WrapFuture};
+use actix_broker::BrokerIssue;
+use tracing::*;
+use uuid::Uuid;
+
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::{AppTaskId, SecureKey, TaskSecurity};
+
+use crate::tasks::{NotifyTaskKeyScopes, NotifyTaskSecurity, RevokeTaskSecureKey, RotateTaskSecureKey};
+use crate::{rekey_scopes, DomainResult, DomainSecurity, SecureKeyScope};
+
+use super::TasksSupervisor;
+
+impl TasksSupervisor {
+    /// Replace the secure keys of configured tasks with the ones persisted after rotations and revocations
+    pub(crate) fn restore_task_permissions(&self, ctx: &mut Context<Self>) {
+        let db = self.db.clone();
+
+        async move { db.fetch_all_task_permissions().await }.into_actor(self)
+                                                            .map(Self::on_task_permissions_restored)
+                                                            .wait(ctx);
+    }
+
+    fn on_task_permissions_restored(res: anyhow::Result<HashMap<AppTaskId, TaskSecurity>>,
+                                    actor: &mut Self,
+                                    ctx: &mut Context<Self>) {
+        match res {
+            Ok(permissions) => {
+                for (task_id, security) in permissions {
+                    if let Some(task) = actor.tasks.get_mut(&task_id) {
+                        debug!(%task_id, "Restored persisted task permissions");
+                        task.security = security;
+                    }
+                }
+            }
+            Err(error) => warn!(%error, "Failed to restore persisted task permissions"),
+        }
+    }
+
+    /// Apply the secure keys and scopes persisted for a task that is created again, such as after a restart of the
+    /// domain, before its actor starts with the keys it was first created with
+    pub(super) fn restore_task_secure_keys(&mut self, task_id: AppTaskId, ctx: &mut Context<Self>) {
+        let db = self.db.clone();
+        let persisted_task_id = task_id.clone();
+
+        async move {
+            let security = db.fetch_task_permissions(&persisted_task_id).await?;
+            let scopes = db.fetch_task_key_scopes(&persisted_task_id).await?;
+            Ok::<_, anyhow::Error>((security, scopes))
+        }.into_actor(self)
+         .map(move |res, actor, ctx| {
+             match (res, actor.tasks.get_mut(&task_id)) {
+                 (Ok((security, scopes)), Some(task)) => {
+                     if let Some(security) = security {
+                         debug!(%task_id, "Restored persisted task permissions");
+                         task.security = security;
+                     }
+                     if let Some(scopes) = scopes {
+                         debug!(%task_id, keys = scopes.len(), "Restored persisted key scopes");
+                         task.key_scopes = scopes;
+                     }
+                 }
+                 (Err(error), _) => warn!(%error, %task_id, "Failed to restore persisted task permissions"),
+                 _ => {}
+             }
+
+             actor.run_task_timers(ctx);
+         })
+         .wait(ctx);
+    }
+
+    /// Persist a new set of secure keys for a task and the scopes left for them, then apply both and let the sockets
+    /// drop revoked clients
+    fn update_task_security(&mut self,
+                            task_id: AppTaskId,
+                            security: TaskSecurity,
+                            rotated: Option<(SecureKey, SecureKey)>)
+                            -> LocalBoxActorFuture<Self, DomainResult<TaskSecurity>> {
+        let scopes = match self.tasks.get(&task_id) {
+            Some(task) => rekey_scopes(&task.key_scopes,
+                                       security.security.keys(),
+                                       rotated.as_ref().map(|(old_key, new_key)| (old_key, new_key))),
+            None => {
+                return fut::err(DomainError::TaskNotFound { task_id }).into_actor(self)
+                                                                      .boxed_local()
+            }
+        };
+
+        let db = self.db.clone();
+        let persisted = security.clone();
+        let persisted_scopes = scopes.clone();
+        let persisted_task_id = task_id.clone();
+
+        async move {
+            db.save_task_permissions(&persisted_task_id, &persisted).await?;
+            db.save_task_key_scopes(&persisted_task_id, &persisted_scopes).await
+        }.into_actor(self)
+         .map(move |res, actor, ctx| {
+             if let Err(error) = res {
+                 warn!(%error, %task_id, "Failed to persist task permissions");
+                 let error = format!("Failed to persist task permissions: {error}");
+                 return Err(DomainError::BadGateway { error });
+             }
+
+             let task = actor.tasks
+                             .get_mut(&task_id)
+                             .ok_or_else(|| DomainError::TaskNotFound { task_id: task_id.clone(), })?;
+
+             task.security = security.clone();
+             task.key_scopes = scopes.clone();
+
+             actor.issue_system_async(NotifyTaskSecurity { task_id:  { task_id.clone() },
+                                                           security: { security.clone() }, });
+             actor.issue_system_async(NotifyTaskKeyScopes { task_id: { task_id },
+                                                            scopes:  { scopes }, });
+
+             Ok(security)
+         })
+         .boxed_local()
+    }
+
+    fn task_security_for_update(&self,
+                                task_id: &AppTaskId,
+                                security: &DomainSecurity,
+                                secure_key: &SecureKey)
+                                -> DomainResult<TaskSecurity> {
+        self.require_scope(task_id, security, SecureKeyScope::Full)?;
+
+        match self.tasks.get(task_id) {
+            // unknown keys are reported as an authentication failure, so callers can not probe for valid keys
+            Some(task) if task.security.security.contains_key(secure_key) => Ok(task.security.clone()),
+            Some(_) => Err(DomainError::AuthenticationFailed),
+            None => Err(DomainError::TaskNotFound { task_id: task_id.clone(), }),
+        }
+    }
+}
+
+impl Handler<RotateTaskSecureKey> for TasksSupervisor {
+    type Result = LocalBoxActorFuture<Self, DomainResult<TaskSecurity>>;
+
+    fn handle(&mut self, msg: RotateTaskSecureKey, ctx: &mut Self::Context) -> Self::Result {
+        let old_key = msg.rotation.secure_key;
+        let mut security = match self.task_security_for_update(&msg.task_id, &msg.security, &old_key) {
+            Ok(security) => security,
+            Err(error) => return fut::err(error).into_actor(self).boxed_local(),
+        };
+
+        let new_key = msg.rotation
+                         .new_key
+                         .unwrap_or_else(|| SecureKey::new(Uuid::new_v4().to_string()));
+
+        if security.security.contains_key(&new_key) {
+            return fut::err(DomainError::AuthenticationFailed).into_actor(self)
+                                                              .boxed_local();
+        }
+
+        if let Some(permissions) = security.security.remove(&old_key) {
+            security.security.insert(new_key.clone(), permissions);
+        }
+
+        self.update_task_security(msg.task_id, security, Some((old_key, new_key)))
+    }
+}
+
+impl Handler<RevokeTaskSecureKey> for TasksSupervisor {
+    type Result = LocalBoxActorFuture<Self, DomainResult<TaskSecurity>>;
+
+    fn handle(&mut self, msg: RevokeTaskSecureKey, ctx: &mut Self::Context) -> Self::Result {
+        let secure_key = msg.revocation.secure_key;
+        let mut security = match self.task_security_for_update(&msg.task_id, &msg.security, &secure_key) {
+            Ok(security) => security,
+            Err(error) => return fut::err(error).into_actor(self).boxed_local(),
+        };
+
+        security.security.remove(&secure_key);
+
+        self.update_task_security(msg.task_id, security, None)
+    }
+}
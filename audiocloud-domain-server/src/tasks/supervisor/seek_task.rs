@@ -6,7 +6,7 @@ use audiocloud_api::audio_engine::TaskSought;
 use audiocloud_api::domain::DomainError;
 
 use crate::tasks::SeekTask;
-use crate::DomainResult;
+use crate::{DomainResult, SecureKeyScope};
 
 use super::TasksSupervisor;
 
@@ -16,6 +16,10 @@ impl Handler<SeekTask> for TasksSupervisor {
     fn handle(&mut self, msg: SeekTask, ctx: &mut Self::Context) -> Self::Result {
         use DomainError::*;
 
+        if let Err(error) = self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Transport) {
+            return fut::err(error).into_actor(self).boxed_local();
+        }
+
         if let Some(task) = self.tasks.get(&msg.task_id).and_then(|task| task.actor.as_ref()) {
             let task_id = msg.task_id.clone();
             task.send(msg)
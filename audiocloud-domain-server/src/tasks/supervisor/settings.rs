@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+
+use actix::{ActorFutureExt, Context, ContextFutureSpawner, WrapFuture};
+use tracing::*;
+
+use audiocloud_api::AppTaskId;
+
+use crate::tasks::TaskSettings;
+
+use super::{SupervisedTask, TasksSupervisor};
+
+impl SupervisedTask {
+    pub fn settings(&self) -> TaskSettings {
+        TaskSettings { track_inputs:    { self.track_inputs.clone() },
+                       tempo_map:       { self.tempo_map.clone() },
+                       track_groups:    { self.track_groups.clone() },
+                       envelopes:       { self.envelopes.clone() },
+                       fades:           { self.fades.clone() },
+                       loudness_target: { self.loudness_target },
+                       watermark:       { self.watermark }, }
+    }
+
+    fn restore_settings(&mut self, settings: TaskSettings) {
+        self.track_inputs = settings.track_inputs;
+        self.tempo_map = settings.tempo_map;
+        self.track_groups = settings.track_groups;
+        self.envelopes = settings.envelopes;
+        self.fades = settings.fades;
+        self.loudness_target = settings.loudness_target;
+        self.watermark = settings.watermark;
+    }
+}
+
+impl TasksSupervisor {
+    /// Attach the settings persisted before a restart to the configured tasks, before their actors start
+    pub(crate) fn restore_task_settings(&self, ctx: &mut Context<Self>) {
+        let db = self.db.clone();
+
+        async move { db.fetch_all_task_settings().await }.into_actor(self)
+                                                         .map(Self::on_task_settings_restored)
+                                                         .wait(ctx);
+    }
+
+    fn on_task_settings_restored(res: anyhow::Result<HashMap<AppTaskId, TaskSettings>>,
+                                 actor: &mut Self,
+                                 ctx: &mut Context<Self>) {
+        match res {
+            Ok(settings) => {
+                for (task_id, settings) in settings {
+                    if let Some(task) = actor.tasks.get_mut(&task_id) {
+                        debug!(%task_id, "Restored persisted task settings");
+                        task.restore_settings(settings);
+                    }
+                }
+            }
+            Err(error) => warn!(%error, "Failed to restore persisted task settings"),
+        }
+    }
+
+    /// Persist the settings of a task after one of them changed
+    pub(crate) fn persist_task_settings(&self, task_id: &AppTaskId) {
+        let settings = match self.tasks.get(task_id) {
+            Some(task) => task.settings(),
+            None => return,
+        };
+
+        let db = self.db.clone();
+        let task_id = task_id.clone();
+        actix::spawn(async move {
+            if let Err(error) = db.save_task_settings(&task_id, &settings).await {
+                warn!(%error, %task_id, "Failed to persist task settings");
+            }
+        });
+    }
+}
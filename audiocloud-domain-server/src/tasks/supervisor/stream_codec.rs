@@ -0,0 +1,32 @@
+use actix::Handler;
+use actix_broker::BrokerIssue;
+
+use audiocloud_api::domain::DomainError;
+
+use crate::tasks::{NotifyTaskStreamCodec, SetTaskStreamCodec, TaskStreamCodec};
+use crate::{DomainResult, SecureKeyScope};
+
+use super::TasksSupervisor;
+
+impl Handler<SetTaskStreamCodec> for TasksSupervisor {
+    type Result = DomainResult<TaskStreamCodec>;
+
+    fn handle(&mut self, msg: SetTaskStreamCodec, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Transport)?;
+
+        msg.codec
+           .validate()
+           .map_err(|error| DomainError::Serialization { error: { format!("Invalid stream codec: {error}") }, })?;
+
+        let task = self.tasks
+                       .get_mut(&msg.task_id)
+                       .ok_or_else(|| DomainError::TaskNotFound { task_id: msg.task_id.clone(), })?;
+
+        task.stream_codec = msg.codec;
+
+        self.issue_system_async(NotifyTaskStreamCodec { task_id: { msg.task_id },
+                                                        codec:   { msg.codec }, });
+
+        Ok(msg.codec)
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+
+use actix::{ActorFutureExt, Context, ContextFutureSpawner, Handler, WrapFuture};
+use actix_broker::BrokerIssue;
+use tracing::*;
+use uuid::Uuid;
+
+use audiocloud_api::common::task::TimeSegment;
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::{now, AppMediaObjectId, AppTaskId, MediaObject, MediaObjectId, TrackNodeId};
+
+use crate::tasks::{
+    GetTaskTakes, NotifyTaskRecording, NotifyTaskTake, SetTaskRecording, TaskRecording, TaskTakeLanes, TrackTake,
+};
+use crate::{DomainResult, SecureKeyScope};
+
+use super::TasksSupervisor;
+
+impl TasksSupervisor {
+    /// Attach the takes recorded before a restart to the configured tasks
+    pub(crate) fn restore_track_takes(&self, ctx: &mut Context<Self>) {
+        let db = self.db.clone();
+
+        async move { db.fetch_all_track_takes().await }.into_actor(self)
+                                                       .map(Self::on_track_takes_restored)
+                                                       .wait(ctx);
+    }
+
+    fn on_track_takes_restored(res: anyhow::Result<HashMap<AppTaskId, Vec<TrackTake>>>,
+                               actor: &mut Self,
+                               ctx: &mut Context<Self>) {
+        match res {
+            Ok(takes) => {
+                for (task_id, takes) in takes {
+                    if let Some(task) = actor.tasks.get_mut(&task_id) {
+                        debug!(%task_id, count = takes.len(), "Restored persisted track takes");
+                        task.takes = takes;
+                    }
+                }
+            }
+            Err(error) => warn!(%error, "Failed to restore persisted track takes"),
+        }
+    }
+
+    /// A take the engine recorded, numbered after the earlier takes over the same segment of the track
+    pub(crate) fn new_take(&self,
+                           task_id: &AppTaskId,
+                           track_id: TrackNodeId,
+                           segment: TimeSegment,
+                           pass: Option<usize>,
+                           media_segment: Option<TimeSegment>)
+                           -> TrackTake {
+        let media_id = AppMediaObjectId::new(task_id.app_id.clone(),
+                                             MediaObjectId::new(format!("take-{}", Uuid::new_v4())));
+
+        TrackTake { media_id:      { media_id },
+                    take:          { self.next_take_number(task_id, &track_id, &segment) },
+                    track_id:      { track_id },
+                    segment:       { segment },
+                    pass:          { pass },
+                    media_segment: { media_segment },
+                    recorded_at:   { now() }, }
+    }
+
+    /// Register a take as a media object and persist it, its number is taken right away so that the passes of a
+    /// loop recording arriving back to back are numbered in order
+    pub(crate) fn save_take(&mut self, task_id: AppTaskId, take: TrackTake, path: String, ctx: &mut Context<Self>) {
+        let task = match self.tasks.get_mut(&task_id) {
+            Some(task) => task,
+            None => {
+                warn!(%task_id, track_id = %take.track_id, "Dropping take recorded for unknown task");
+                return;
+            }
+        };
+
+        task.takes.push(take.clone());
+
+        let media = MediaObject { id:       { take.media_id.clone() },
+                                  metadata: { None },
+                                  path:     { Some(path) },
+                                  download: { None },
+                                  upload:   { None },
+                                  revision: { 0 }, };
+
+        let db = self.db.clone();
+        let persisted = take.clone();
+        let persisted_task_id = task_id.clone();
+
+        async move {
+            db.save_media(media).await?;
+            db.save_track_take(&persisted_task_id, &persisted).await
+        }.into_actor(self)
+         .map(move |res, actor, ctx| match res {
+             Ok(()) => {
+                 info!(%task_id, media_id = %take.media_id, track_id = %take.track_id, take = take.take,
+                       "Take recorded");
+                 actor.issue_system_async(NotifyTaskTake { task_id, take });
+             }
+             Err(error) => {
+                 warn!(%error, %task_id, "Failed to persist recorded take");
+                 if let Some(task) = actor.tasks.get_mut(&task_id) {
+                     task.takes.retain(|existing| existing.media_id != take.media_id);
+                 }
+             }
+         })
+         .spawn(ctx);
+    }
+
+    fn next_take_number(&self, task_id: &AppTaskId, track_id: &TrackNodeId, segment: &TimeSegment) -> usize {
+        let earlier = self.tasks
+                          .get(task_id)
+                          .map(|task| {
+                              task.takes
+                                  .iter()
+                                  .filter(|take| take.overlaps(track_id, segment))
+                                  .count()
+                          })
+                          .unwrap_or_default();
+
+        earlier + 1
+    }
+}
+
+impl Handler<SetTaskRecording> for TasksSupervisor {
+    type Result = DomainResult<TaskRecording>;
+
+    fn handle(&mut self, msg: SetTaskRecording, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Transport)?;
+
+        msg.recording
+           .validate()
+           .map_err(|error| DomainError::Serialization { error: { format!("Invalid recording: {error}") }, })?;
+
+        let task = self.tasks
+                       .get_mut(&msg.task_id)
+                       .ok_or_else(|| DomainError::TaskNotFound { task_id: msg.task_id.clone(), })?;
+
+        task.recording = msg.recording.clone();
+
+        self.issue_system_async(NotifyTaskRecording { task_id:   { msg.task_id },
+                                                      recording: { msg.recording.clone() }, });
+
+        Ok(msg.recording)
+    }
+}
+
+impl Handler<GetTaskTakes> for TasksSupervisor {
+    type Result = DomainResult<TaskTakeLanes>;
+
+    fn handle(&mut self, msg: GetTaskTakes, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Listen)?;
+
+        let mut lanes = TaskTakeLanes::new();
+        if let Some(task) = self.tasks.get(&msg.task_id) {
+            for take in &task.takes {
+                lanes.entry(take.track_id.clone()).or_default().push(take.clone());
+            }
+        }
+
+        Ok(lanes)
+    }
+}
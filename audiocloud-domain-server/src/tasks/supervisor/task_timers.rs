@@ -3,6 +3,7 @@ use std::time::Duration;
 
 use actix::{Actor, AsyncContext, Context};
 use actix_broker::BrokerIssue;
+use opentelemetry::KeyValue;
 use tracing::*;
 
 use audiocloud_api::now;
@@ -10,7 +11,7 @@ use audiocloud_api::now;
 use crate::o11y;
 use crate::tasks::supervisor::TasksSupervisor;
 use crate::tasks::task::TaskActor;
-use crate::tasks::{NotifyTaskActivated, NotifyTaskDeactivated, NotifyTaskDeleted};
+use crate::tasks::{NotifyTaskActivated, NotifyTaskDeactivated, NotifyTaskKeyScopes};
 
 impl TasksSupervisor {
     pub(crate) fn register_task_timers(&mut self, ctx: &mut Context<Self>) {
@@ -32,6 +33,12 @@ impl TasksSupervisor {
             self.num_active_tasks.observe(ctx,
                                           self.tasks.values().filter(|task| task.actor.is_some()).count() as u64,
                                           &[]);
+
+            for (table, rows) in &self.db_stats.table_rows {
+                self.db_rows
+                    .observe(ctx, *rows, &[KeyValue::new("table", table.clone())]);
+            }
+            self.db_size_bytes.observe(ctx, self.db_stats.size_bytes, &[]);
         });
     }
 
@@ -66,11 +73,11 @@ impl TasksSupervisor {
                   });
 
         for task_id in deleted {
-            self.issue_system_async(NotifyTaskDeleted { task_id });
+            self.forget_task(task_id);
         }
     }
 
-    fn create_pending_task_actors(&mut self) {
+    pub(crate) fn create_pending_task_actors(&mut self) {
         // generate an actor map to later assign
         let mut actors = HashMap::new();
 
@@ -84,10 +91,26 @@ impl TasksSupervisor {
                                          task.reservations.clone(),
                                          task.spec.clone(),
                                          task.security.clone(),
-                                         self.fixed_instance_routing.clone())
+                                         self.fixed_instance_routing.clone(),
+                                         task.track_inputs.clone(),
+                                         task.recording.clone(),
+                                         task.lead_in,
+                                         task.latency_profile,
+                                         task.stream_codec,
+                                         task.loudness_target,
+                                         task.watermark,
+                                         task.tempo_map.clone(),
+                                         task.playlist.clone(),
+                                         task.track_groups.clone(),
+                                         task.envelopes.clone(),
+                                         task.fades.clone(),
+                                         task.media_rates.clone(),
+                                         task.monitor.clone())
                     {
                         Ok(actor) => {
                             self.issue_system_async(NotifyTaskActivated { task_id: task_id.clone(), });
+                            self.issue_system_async(NotifyTaskKeyScopes { task_id: { task_id.clone() },
+                                                                          scopes:  { task.key_scopes.clone() }, });
                             actors.insert(task_id.clone(), actor.start());
                         }
                         Err(error) => {
@@ -0,0 +1,46 @@
+use actix::Handler;
+use actix_broker::BrokerIssue;
+
+use audiocloud_api::domain::DomainError;
+
+use crate::tasks::{GetTaskTempoMap, NotifyTaskTempoMap, SetTaskTempoMap, TaskTempoMap};
+use crate::{DomainResult, SecureKeyScope};
+
+use super::TasksSupervisor;
+
+impl Handler<SetTaskTempoMap> for TasksSupervisor {
+    type Result = DomainResult<TaskTempoMap>;
+
+    fn handle(&mut self, msg: SetTaskTempoMap, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Full)?;
+
+        msg.tempo_map
+           .validate()
+           .map_err(|error| DomainError::Serialization { error: { format!("Invalid tempo map: {error}") }, })?;
+
+        let task = self.tasks
+                       .get_mut(&msg.task_id)
+                       .ok_or_else(|| DomainError::TaskNotFound { task_id: msg.task_id.clone(), })?;
+
+        task.tempo_map = msg.tempo_map.clone();
+        self.persist_task_settings(&msg.task_id);
+
+        self.issue_system_async(NotifyTaskTempoMap { task_id:   { msg.task_id },
+                                                     tempo_map: { msg.tempo_map.clone() }, });
+
+        Ok(msg.tempo_map)
+    }
+}
+
+impl Handler<GetTaskTempoMap> for TasksSupervisor {
+    type Result = DomainResult<TaskTempoMap>;
+
+    fn handle(&mut self, msg: GetTaskTempoMap, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Listen)?;
+
+        Ok(self.tasks
+               .get(&msg.task_id)
+               .map(|task| task.tempo_map.clone())
+               .unwrap_or_default())
+    }
+}
@@ -0,0 +1,82 @@
+use std::time::Duration;
+
+use actix::{fut, ActorFutureExt, Handler, ResponseActFuture, WrapFuture};
+use nanoid::nanoid;
+use tokio::sync::oneshot;
+use tokio::time::timeout;
+use tracing::*;
+
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::newtypes::EngineId;
+
+use crate::nats;
+use crate::tasks::engine_ext::{engine_ext_command_subject, EngineExtCommand, EngineTestToneResult};
+use crate::tasks::supervisor::TasksSupervisor;
+use crate::tasks::RunEngineTestTone;
+use crate::DomainResult;
+
+/// Time the engine gets on top of the tone to set it up, measure and report back
+const TEST_TONE_GRACE: Duration = Duration::from_secs(5);
+
+impl TasksSupervisor {
+    pub(crate) fn on_test_tone_measured(&mut self, engine_id: EngineId, test_id: String, result: EngineTestToneResult) {
+        match self.pending_test_tones.remove(&test_id) {
+            Some(sender) => {
+                let _ = sender.send(result);
+            }
+            None => debug!(%engine_id, %test_id, "Test tone measured after nobody waited for it anymore"),
+        }
+    }
+}
+
+impl Handler<RunEngineTestTone> for TasksSupervisor {
+    type Result = ResponseActFuture<Self, DomainResult<EngineTestToneResult>>;
+
+    fn handle(&mut self, msg: RunEngineTestTone, ctx: &mut Self::Context) -> Self::Result {
+        if let Err(error) = msg.tone.validate() {
+            return Box::pin(fut::err(DomainError::Serialization { error: { format!("Invalid test tone: {error}") }, }));
+        }
+
+        if !self.engines.contains_key(&msg.engine_id) {
+            let error = format!("Unknown engine {}", msg.engine_id);
+            return Box::pin(fut::err(DomainError::BadGateway { error }));
+        }
+
+        let test_id = nanoid!();
+        let (sender, receiver) = oneshot::channel();
+        self.pending_test_tones.insert(test_id.clone(), sender);
+
+        let engine_id = msg.engine_id;
+        let wait = Duration::from_millis(msg.tone.duration_ms) + TEST_TONE_GRACE;
+        let subject = engine_ext_command_subject(&engine_id.engine_command_subject());
+        let cmd = EngineExtCommand::StartTestTone { test_id: { test_id.clone() },
+                                                    tone:    { msg.tone }, };
+
+        info!(%engine_id, %test_id, "Starting test tone");
+
+        let run = async move {
+            match nats::request_raw_msgpack::<_, Result<(), String>, _>(subject, cmd).await {
+                Ok(Ok(())) => {}
+                Ok(Err(error)) => {
+                    return Err(DomainError::BadGateway { error: { format!("Engine refused test tone: {error}") }, })
+                }
+                Err(error) => {
+                    return Err(DomainError::BadGateway { error: { format!("Failed to deliver test tone: {error}") }, })
+                }
+            }
+
+            match timeout(wait, receiver).await {
+                Ok(Ok(result)) => Ok(result),
+                _ => {
+                    let error = format!("Engine {engine_id} did not report the test tone");
+                    Err(DomainError::BadGateway { error })
+                }
+            }
+        };
+
+        Box::pin(run.into_actor(self).map(move |res, actor, _| {
+                                         actor.pending_test_tones.remove(&test_id);
+                                         res
+                                     }))
+    }
+}
@@ -0,0 +1,46 @@
+use actix::Handler;
+use actix_broker::BrokerIssue;
+
+use audiocloud_api::domain::DomainError;
+
+use crate::tasks::{GetTaskTrackGroups, NotifyTaskTrackGroups, SetTaskTrackGroups, TaskTrackGroups};
+use crate::{DomainResult, SecureKeyScope};
+
+use super::TasksSupervisor;
+
+impl Handler<SetTaskTrackGroups> for TasksSupervisor {
+    type Result = DomainResult<TaskTrackGroups>;
+
+    fn handle(&mut self, msg: SetTaskTrackGroups, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Full)?;
+
+        msg.track_groups
+           .validate()
+           .map_err(|error| DomainError::Serialization { error: { format!("Invalid track groups: {error}") }, })?;
+
+        let task = self.tasks
+                       .get_mut(&msg.task_id)
+                       .ok_or_else(|| DomainError::TaskNotFound { task_id: msg.task_id.clone(), })?;
+
+        task.track_groups = msg.track_groups.clone();
+        self.persist_task_settings(&msg.task_id);
+
+        self.issue_system_async(NotifyTaskTrackGroups { task_id:      { msg.task_id },
+                                                        track_groups: { msg.track_groups.clone() }, });
+
+        Ok(msg.track_groups)
+    }
+}
+
+impl Handler<GetTaskTrackGroups> for TasksSupervisor {
+    type Result = DomainResult<TaskTrackGroups>;
+
+    fn handle(&mut self, msg: GetTaskTrackGroups, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Listen)?;
+
+        Ok(self.tasks
+               .get(&msg.task_id)
+               .map(|task| task.track_groups.clone())
+               .unwrap_or_default())
+    }
+}
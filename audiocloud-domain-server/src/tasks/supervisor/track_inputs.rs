@@ -0,0 +1,85 @@
+use std::ops::Range;
+
+use actix::Handler;
+use actix_broker::BrokerIssue;
+
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::newtypes::TrackNodeId;
+use audiocloud_api::AppTaskId;
+
+use crate::tasks::{GetTaskTrackInputs, NotifyTaskTrackInputs, SetTaskTrackInput, TaskTrackInputs, TrackHardwareInput};
+use crate::{DomainResult, SecureKeyScope};
+
+use super::TasksSupervisor;
+
+impl TasksSupervisor {
+    /// Fail if the input channels of a track are taken by the return of a fixed instance
+    fn check_track_input(&self,
+                         task_id: &AppTaskId,
+                         track_id: &TrackNodeId,
+                         input: &TrackHardwareInput)
+                         -> DomainResult {
+        // tracks not in the spec (yet) record stereo until they are added
+        let channels = self.tasks
+                           .get(task_id)
+                           .and_then(|task| task.spec.tracks.get(track_id))
+                           .map(|track| track.channels.num_channels())
+                           .unwrap_or(2);
+
+        let inputs = input.channel..input.channel + channels;
+        let overlaps = |other: &Range<usize>| inputs.start < other.end && other.start < inputs.end;
+
+        for (instance_id, routing) in self.fixed_instance_routing.iter() {
+            if overlaps(&(routing.return_channel..routing.return_channel + routing.return_count)) {
+                let operation =
+                    format!("Track {track_id} recording from inputs {inputs:?} used by the instance return");
+                return Err(DomainError::InstanceNotCapable { instance_id: { instance_id.clone() },
+                                                             operation:   { operation }, });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Handler<GetTaskTrackInputs> for TasksSupervisor {
+    type Result = DomainResult<TaskTrackInputs>;
+
+    fn handle(&mut self, msg: GetTaskTrackInputs, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Full)?;
+
+        Ok(self.tasks
+               .get(&msg.task_id)
+               .map(|task| task.track_inputs.clone())
+               .unwrap_or_default())
+    }
+}
+
+impl Handler<SetTaskTrackInput> for TasksSupervisor {
+    type Result = DomainResult<TaskTrackInputs>;
+
+    fn handle(&mut self, msg: SetTaskTrackInput, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Full)?;
+
+        if let Some(input) = &msg.update.input {
+            self.check_track_input(&msg.task_id, &msg.update.track_id, input)?;
+        }
+
+        let task = self.tasks
+                       .get_mut(&msg.task_id)
+                       .ok_or_else(|| DomainError::TaskNotFound { task_id: msg.task_id.clone(), })?;
+
+        match msg.update.input {
+            Some(input) => task.track_inputs.insert(msg.update.track_id, input),
+            None => task.track_inputs.remove(&msg.update.track_id),
+        };
+
+        let inputs = task.track_inputs.clone();
+        self.persist_task_settings(&msg.task_id);
+
+        self.issue_system_async(NotifyTaskTrackInputs { task_id: { msg.task_id },
+                                                        inputs:  { inputs.clone() }, });
+
+        Ok(inputs)
+    }
+}
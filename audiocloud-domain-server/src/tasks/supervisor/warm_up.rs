@@ -0,0 +1,33 @@
+use actix::{Handler, MessageResult};
+use tracing::*;
+
+use crate::nats;
+use crate::tasks::engine_ext::engine_ext_command_subject;
+use crate::tasks::supervisor::TasksSupervisor;
+use crate::tasks::{TasksWarmedUp, WarmUpTasks};
+
+impl Handler<WarmUpTasks> for TasksSupervisor {
+    type Result = MessageResult<WarmUpTasks>;
+
+    fn handle(&mut self, msg: WarmUpTasks, ctx: &mut Self::Context) -> Self::Result {
+        // the actors compute their engine specs as they start, which is what the first play of a task waited for
+        self.create_pending_task_actors();
+
+        for engine_id in self.engines.keys() {
+            let engine_command_subject = engine_id.engine_command_subject();
+            nats::label_subject(&engine_command_subject, "engine_commands");
+            nats::label_subject(engine_ext_command_subject(&engine_command_subject),
+                                "engine_ext_commands");
+        }
+
+        let warmed_up =
+            TasksWarmedUp { tasks:            { self.tasks.len() },
+                            active_tasks:     { self.tasks.values().filter(|task| task.actor.is_some()).count() },
+                            engines:          { self.engines.len() },
+                            routed_instances: { self.fixed_instance_routing.len() }, };
+
+        debug!(?warmed_up, "Tasks warmed up");
+
+        MessageResult(warmed_up)
+    }
+}
@@ -0,0 +1,33 @@
+use actix::Handler;
+use actix_broker::BrokerIssue;
+
+use audiocloud_api::domain::DomainError;
+
+use crate::tasks::{NotifyTaskWatermark, SetTaskWatermark, TaskWatermark};
+use crate::{DomainResult, SecureKeyScope};
+
+use super::TasksSupervisor;
+
+impl Handler<SetTaskWatermark> for TasksSupervisor {
+    type Result = DomainResult<TaskWatermark>;
+
+    fn handle(&mut self, msg: SetTaskWatermark, ctx: &mut Self::Context) -> Self::Result {
+        self.require_scope(&msg.task_id, &msg.security, SecureKeyScope::Full)?;
+
+        msg.watermark
+           .validate()
+           .map_err(|error| DomainError::Serialization { error: { format!("Invalid watermark: {error}") }, })?;
+
+        let task = self.tasks
+                       .get_mut(&msg.task_id)
+                       .ok_or_else(|| DomainError::TaskNotFound { task_id: msg.task_id.clone(), })?;
+
+        task.watermark = msg.watermark;
+        self.persist_task_settings(&msg.task_id);
+
+        self.issue_system_async(NotifyTaskWatermark { task_id:   { msg.task_id },
+                                                      watermark: { msg.watermark }, });
+
+        Ok(msg.watermark)
+    }
+}
@@ -1,6 +1,6 @@
 #![allow(unused_variables)]
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::time::Duration;
 
 use actix::{Actor, ActorFutureExt, AsyncContext, Context, ContextFutureSpawner, WrapFuture};
@@ -9,30 +9,57 @@ use tracing::*;
 
 use audiocloud_api::audio_engine::{EngineCommand, EngineError};
 use audiocloud_api::cloud::domains::FixedInstanceRouting;
+use audiocloud_api::common::media::RenderId;
+use audiocloud_api::common::task::{ConnectionValues, NodePadId};
+use audiocloud_api::newtypes::NodeConnectionId;
 use audiocloud_api::{
-    AppMediaObjectId, AppTaskId, DomainId, EngineId, FixedInstanceId, SerializableResult, StreamingPacket,
-    TaskReservation, TaskSecurity, TaskSpec,
+    now, AppMediaObjectId, AppTaskId, DomainId, EngineId, FixedInstanceId, PlayId, SerializableResult, StreamingPacket,
+    TaskReservation, TaskSecurity, TaskSpec, Timestamp,
 };
 
 use crate::config::NotifyFixedInstanceRouting;
-use crate::fixed_instances::{get_instance_supervisor, GetMultipleFixedInstanceState};
+use crate::fixed_instances::{get_instance_supervisor, RegisterInstanceInterest};
 use crate::nats;
+use crate::tasks::engine_ext::{engine_ext_command_subject, PadLoudness, PadSpectrum};
+use crate::tasks::stream_continuity::StreamContinuity;
 use crate::tasks::task_engine::TaskEngine;
-use crate::tasks::{NotifyTaskActivated, NotifyTaskReservation, NotifyTaskSecurity, NotifyTaskSpec, TaskOpts};
+use crate::tasks::{
+    NotifyEngineStarted, NotifyStreamQuality, NotifyTaskActivated, NotifyTaskDeleted, NotifyTaskEnvelopes,
+    NotifyTaskLatencyProfile, NotifyTaskLeadIn, NotifyTaskLoudnessTarget, NotifyTaskMediaFades, NotifyTaskMediaRates,
+    NotifyTaskMonitor, NotifyTaskPlaylist, NotifyTaskRecording, NotifyTaskReservation, NotifyTaskSecurity,
+    NotifyTaskSpec, NotifyTaskStreamCodec, NotifyTaskTempoMap, NotifyTaskTrackGroups, NotifyTaskTrackInputs,
+    NotifyTaskWatermark, RoutingVerificationState, TaskEnvelopes, TaskLatencyProfile, TaskLeadIn, TaskLoudnessTarget,
+    TaskMediaFades, TaskMediaRates, TaskMonitor, TaskOpts, TaskPlaylist, TaskRecording, TaskRenderNormalization,
+    TaskRoutingVerification, TaskSettings, TaskStreamCodec, TaskTempoMap, TaskTrackGroups, TaskTrackInputs,
+    TaskWatermark,
+};
+
+use null_test::NullTestJob;
+pub(crate) use safe_mode::SafeModeState;
 
 use super::task_fixed_instance::TaskFixedInstances;
 use super::task_media_objects::TaskMediaObjects;
 
 mod cancel_render;
+mod delete_task;
+mod diagnostics;
+mod get_spec_diff;
 mod handle_engine_events;
 mod handle_instance_events;
 mod handle_media_events;
 mod modify_task;
+mod null_test;
 mod packet_handling;
+mod pause_play;
 mod play_task;
 mod render_task;
+mod routing_verification;
+mod safe_mode;
 mod seek_task;
+mod settings;
 mod stop_play;
+mod track_groups;
+mod track_inputs;
 
 pub struct TaskActor {
     id:                     AppTaskId,
@@ -47,7 +74,45 @@ pub struct TaskActor {
     fixed_instances:        TaskFixedInstances,
     media_objects:          TaskMediaObjects,
     engine:                 TaskEngine,
+    engine_spec:            Option<(Timestamp, TaskSpec)>,
+    /// Settings as of the last settings command the engine acknowledged, None until it acknowledged one
+    engine_settings:        Option<TaskSettings>,
+    /// Earlier revisions of the spec, oldest first
+    spec_history:           VecDeque<TaskSpec>,
+    spec_failures:          usize,
+    safe_mode:              Option<SafeModeState>,
     packet:                 StreamingPacket,
+    packet_timeline_pos:    Option<f64>,
+    packet_continuity:      Option<(PlayId, StreamContinuity)>,
+    /// Serial of the next streaming packet, increasing over all plays of the task so clients can spot lost packets
+    packet_serial:          u64,
+    /// Latest loudness of the metered pads, sent with the next packet
+    packet_loudness:        HashMap<NodePadId, PadLoudness>,
+    /// Latest spectrum of the analyzed pads, sent with the next packet
+    packet_spectrum:        HashMap<NodePadId, PadSpectrum>,
+    track_inputs:           TaskTrackInputs,
+    recording:              TaskRecording,
+    lead_in:                TaskLeadIn,
+    latency_profile:        TaskLatencyProfile,
+    stream_codec:           TaskStreamCodec,
+    loudness_target:        TaskLoudnessTarget,
+    watermark:              TaskWatermark,
+    tempo_map:              TaskTempoMap,
+    playlist:               TaskPlaylist,
+    playlist_index:         Option<usize>,
+    track_groups:           TaskTrackGroups,
+    /// Connection values set by the client, the engine gets them scaled by the gains of the track groups
+    connection_faders:      HashMap<NodeConnectionId, ConnectionValues>,
+    envelopes:              TaskEnvelopes,
+    fades:                  TaskMediaFades,
+    media_rates:            TaskMediaRates,
+    monitor:                TaskMonitor,
+    routing_verification:   TaskRoutingVerification,
+    /// When the engine was last asked for a diagnostic bundle, bundles of errors in quick succession are skipped
+    diagnostics_requested:  Option<Timestamp>,
+    /// Loudness the current render is normalized to once it finished
+    render_normalization:   Option<(RenderId, TaskRenderNormalization)>,
+    null_test:              Option<NullTestJob>,
 }
 
 impl Actor for TaskActor {
@@ -64,6 +129,25 @@ impl Actor for TaskActor {
 
         // subscribe to routing changes
         self.subscribe_system_async::<NotifyFixedInstanceRouting>(ctx);
+        self.subscribe_system_async::<NotifyTaskTrackInputs>(ctx);
+        self.subscribe_system_async::<NotifyTaskRecording>(ctx);
+        self.subscribe_system_async::<NotifyTaskLeadIn>(ctx);
+        self.subscribe_system_async::<NotifyTaskLatencyProfile>(ctx);
+        self.subscribe_system_async::<NotifyTaskStreamCodec>(ctx);
+        self.subscribe_system_async::<NotifyTaskLoudnessTarget>(ctx);
+        self.subscribe_system_async::<NotifyTaskWatermark>(ctx);
+        self.subscribe_system_async::<NotifyStreamQuality>(ctx);
+        self.subscribe_system_async::<NotifyTaskTempoMap>(ctx);
+        self.subscribe_system_async::<NotifyTaskPlaylist>(ctx);
+        self.subscribe_system_async::<NotifyTaskTrackGroups>(ctx);
+        self.subscribe_system_async::<NotifyTaskEnvelopes>(ctx);
+        self.subscribe_system_async::<NotifyTaskMediaFades>(ctx);
+        self.subscribe_system_async::<NotifyTaskMediaRates>(ctx);
+        self.subscribe_system_async::<NotifyTaskMonitor>(ctx);
+        self.subscribe_system_async::<NotifyEngineStarted>(ctx);
+        self.subscribe_system_async::<NotifyTaskDeleted>(ctx);
+
+        self.register_instance_interest(ctx);
 
         // inform the engine that we want to start a task
         self.set_engine_spec(ctx);
@@ -80,9 +164,31 @@ impl TaskActor {
                reservations: TaskReservation,
                spec: TaskSpec,
                security: TaskSecurity,
-               routing: HashMap<FixedInstanceId, FixedInstanceRouting>)
+               routing: HashMap<FixedInstanceId, FixedInstanceRouting>,
+               track_inputs: TaskTrackInputs,
+               recording: TaskRecording,
+               lead_in: TaskLeadIn,
+               latency_profile: TaskLatencyProfile,
+               stream_codec: TaskStreamCodec,
+               loudness_target: TaskLoudnessTarget,
+               watermark: TaskWatermark,
+               tempo_map: TaskTempoMap,
+               playlist: TaskPlaylist,
+               track_groups: TaskTrackGroups,
+               envelopes: TaskEnvelopes,
+               fades: TaskMediaFades,
+               media_rates: TaskMediaRates,
+               monitor: TaskMonitor)
                -> anyhow::Result<Self> {
         let engine_command_subject = engine_id.engine_command_subject();
+        nats::label_subject(&engine_command_subject, "engine_commands");
+        nats::label_subject(engine_ext_command_subject(&engine_command_subject),
+                            "engine_ext_commands");
+        let routing_verification = if opts.verify_task_routing {
+            RoutingVerificationState::Pending
+        } else {
+            RoutingVerificationState::Disabled
+        };
 
         Ok(Self { id:                     { id.clone() },
                   engine_id:              { engine_id },
@@ -96,29 +202,143 @@ impl TaskActor {
                   fixed_instances:        { TaskFixedInstances::default() },
                   media_objects:          { TaskMediaObjects::default() },
                   engine:                 { TaskEngine::new(id.clone()) },
-                  packet:                 { Default::default() }, })
+                  engine_spec:            { None },
+                  engine_settings:        { None },
+                  spec_history:           { VecDeque::new() },
+                  spec_failures:          { 0 },
+                  safe_mode:              { None },
+                  packet:                 { Default::default() },
+                  packet_timeline_pos:    { None },
+                  packet_continuity:      { None },
+                  packet_serial:          { 0 },
+                  packet_loudness:        { HashMap::new() },
+                  packet_spectrum:        { HashMap::new() },
+                  track_inputs:           { track_inputs },
+                  recording:              { recording },
+                  lead_in:                { lead_in },
+                  latency_profile:        { latency_profile },
+                  stream_codec:           { stream_codec },
+                  loudness_target:        { loudness_target },
+                  watermark:              { watermark },
+                  tempo_map:              { tempo_map },
+                  playlist:               { playlist },
+                  playlist_index:         { None },
+                  track_groups:           { track_groups },
+                  connection_faders:      { HashMap::new() },
+                  envelopes:              { envelopes },
+                  fades:                  { fades },
+                  media_rates:            { media_rates },
+                  monitor:                { monitor },
+                  routing_verification:   { TaskRoutingVerification::new(routing_verification) },
+                  diagnostics_requested:  { None },
+                  render_normalization:   { None },
+                  null_test:              { None }, })
     }
 
     fn update(&mut self, ctx: &mut <Self as Actor>::Context) {
-        self.engine
-            .set_instances_are_ready(self.fixed_instances.update(&self.spec));
+        let instances_ready = self.fixed_instances.update(&self.spec);
+
+        self.engine.set_instances_are_ready(instances_ready);
+        self.update_routing_verification(instances_ready, ctx);
 
         if let Some(engine_cmd) = self.engine.update() {
-            nats::request_msgpack(self.engine_command_subject.clone(), engine_cmd).into_actor(self)
-                                                                                  .map(Self::handle_engine_response)
-                                                                                  .spawn(ctx)
+            self.send_engine_command(engine_cmd, ctx);
         }
     }
 
     fn set_engine_spec(&mut self, ctx: &mut Context<TaskActor>) {
         let cmd = EngineCommand::SetSpec { task_id:     { self.id.clone() },
-                                           spec:        { self.spec.clone() },
+                                           spec:        { self.effective_spec() },
                                            instances:   { self.engine_fixed_instance_routing() },
                                            media_ready: { self.engine_media_paths() }, };
 
-        nats::request_msgpack(self.engine_command_subject.clone(), cmd).into_actor(self)
-                                                                       .map(Self::handle_engine_response)
-                                                                       .spawn(ctx);
+        self.send_engine_command(cmd, ctx);
+    }
+
+    fn send_engine_command(&mut self, cmd: EngineCommand, ctx: &mut Context<TaskActor>) {
+        // remember which spec we are sending, so we know what the engine has once it acknowledges
+        let sent_spec = match &cmd {
+            EngineCommand::SetSpec { spec, .. } => Some(spec.clone()),
+            _ => None,
+        };
+
+        // the track groups reach the engine as spec modifications of the connection levels
+        let sent_settings = matches!(&cmd, EngineCommand::ModifySpec { .. }).then(|| self.settings());
+
+        let deadline = self.opts.engine_command_deadline(&cmd);
+        let idempotency = self.opts.engine_command_idempotency(&cmd);
+
+        nats::request_msgpack_within(self.engine_command_subject.clone(), cmd, deadline, idempotency)
+            .into_actor(self)
+            .map(move |res, actor, ctx| actor.handle_engine_ack(sent_spec, sent_settings, res, ctx))
+            .spawn(ctx);
+    }
+
+    fn handle_engine_ack(&mut self,
+                         sent_spec: Option<TaskSpec>,
+                         sent_settings: Option<TaskSettings>,
+                         res: anyhow::Result<SerializableResult<(), EngineError>>,
+                         ctx: &mut Context<Self>) {
+        if let (Some(settings), Ok(SerializableResult::Ok(_))) = (sent_settings, &res) {
+            self.engine_settings = Some(settings);
+        }
+
+        if let Some(spec) = sent_spec {
+            match &res {
+                Ok(SerializableResult::Ok(_)) => {
+                    self.engine_spec = Some((now(), spec));
+                    self.on_engine_spec_applied(ctx);
+
+                    // a recreated engine session has forgotten about the hardware inputs
+                    if !self.track_inputs.is_empty() {
+                        self.set_engine_track_inputs(ctx);
+                    }
+                    if self.recording.armed {
+                        self.set_engine_recording(ctx);
+                    }
+                    if !self.lead_in.is_none() {
+                        self.set_engine_lead_in(ctx);
+                    }
+                    if self.latency_profile != TaskLatencyProfile::Stable {
+                        self.set_engine_latency_profile(ctx);
+                    }
+                    if self.stream_codec != TaskStreamCodec::Flac {
+                        self.set_engine_stream_codec(ctx);
+                    }
+                    if self.loudness_target.target_lufs.is_some() {
+                        self.set_engine_loudness_target(ctx);
+                    }
+                    if !self.tempo_map.is_empty() {
+                        self.set_engine_tempo_map(ctx);
+                    }
+                    if !self.playlist.is_empty() {
+                        self.set_engine_playlist(ctx);
+                    }
+                    if self.has_connection_levels() {
+                        self.set_engine_connection_levels(ctx);
+                    }
+                    if !self.envelopes.is_empty() {
+                        self.set_engine_envelopes(ctx);
+                    }
+                    if !self.fades.is_empty() {
+                        self.set_engine_media_fades(ctx);
+                    }
+                    if !self.media_rates.is_empty() {
+                        self.set_engine_media_rates(ctx);
+                    }
+                    if !self.monitor.is_default() {
+                        self.set_engine_monitor(ctx);
+                    }
+                    if self.opts.spectrum().is_some() {
+                        self.set_engine_spectrum(ctx);
+                    }
+                }
+                Ok(SerializableResult::Error(error)) => self.on_engine_spec_failed(&spec, error.to_string(), ctx),
+                Err(error) => self.on_engine_spec_failed(&spec, error.to_string(), ctx),
+            }
+        }
+
+        Self::handle_engine_response(res, self, ctx);
     }
 
     fn handle_engine_response(res: anyhow::Result<SerializableResult<(), EngineError>>,
@@ -135,10 +355,14 @@ impl TaskActor {
         }
     }
 
-    fn update_fixed_instance_state(&self, ctx: &mut <Self as Actor>::Context) {
-        get_instance_supervisor().send(GetMultipleFixedInstanceState { instance_ids: self.reservations
-                                                                                         .fixed_instances
-                                                                                         .clone(), })
+    /// Have the states of the reserved instances sent to us when they change, starting with what they are now
+    fn register_instance_interest(&self, ctx: &mut <Self as Actor>::Context) {
+        // the supervisor forgets us once we stop, the task actor of the same task registers itself again
+        let register = RegisterInstanceInterest { subscriber:   { format!("task:{}", self.id) },
+                                                  instance_ids: { self.reservations.fixed_instances.clone() },
+                                                  recipient:    { ctx.address().recipient() }, };
+
+        get_instance_supervisor().send(register)
                                  .into_actor(self)
                                  .map(|res, actor, ctx| {
                                      if let Ok(state) = res {
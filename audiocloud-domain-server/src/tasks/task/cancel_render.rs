@@ -2,9 +2,10 @@ use actix::Handler;
 
 use audiocloud_api::audio_engine::{EngineCommand, TaskRenderCancelled};
 use audiocloud_api::domain::DomainError;
+use audiocloud_api::DesiredTaskPlayState;
 
 use crate::tasks::task::TaskActor;
-use crate::tasks::CancelRenderTask;
+use crate::tasks::{CancelRenderTask, NotifyTaskRenderCancelled};
 use crate::DomainResult;
 
 impl Handler<CancelRenderTask> for TaskActor {
@@ -27,3 +28,15 @@ impl Handler<CancelRenderTask> for TaskActor {
         }
     }
 }
+
+impl Handler<NotifyTaskRenderCancelled> for TaskActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskRenderCancelled, ctx: &mut Self::Context) -> Self::Result {
+        if &self.id == &msg.task_id && self.engine.get_actual_play_state().is_rendering(&msg.partial.render_id) {
+            self.engine.set_desired_state(DesiredTaskPlayState::Stopped);
+            self.engine.set_actual_stopped();
+            self.on_null_test_render_failed(&msg.partial.render_id, "Render was cancelled".to_owned());
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use actix::{ActorContext, Handler};
+use tracing::*;
+
+use audiocloud_api::audio_engine::EngineCommand;
+use audiocloud_api::SerializableResult;
+
+use crate::nats;
+use crate::tasks::task::TaskActor;
+use crate::tasks::NotifyTaskDeleted;
+
+impl Handler<NotifyTaskDeleted> for TaskActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskDeleted, ctx: &mut Self::Context) -> Self::Result {
+        if &self.id != &msg.task_id {
+            return;
+        }
+
+        info!(id = %self.id, "Task deleted, closing engine session");
+
+        // the close outlives the actor, which stops right away
+        let close = EngineCommand::Close { task_id: self.id.clone(), };
+        let deadline = self.opts.engine_command_deadline(&close);
+        let idempotency = self.opts.engine_command_idempotency(&close);
+        let subject = self.engine_command_subject.clone();
+        let task_id = self.id.clone();
+
+        actix::spawn(async move {
+            match nats::request_msgpack_within(subject, close, deadline, idempotency).await {
+                Ok(SerializableResult::Error(error)) => warn!(%error, %task_id, "Engine failed to close session"),
+                Err(error) => warn!(%error, %task_id, "Failed to deliver close to engine"),
+                _ => {}
+            }
+        });
+
+        ctx.stop();
+    }
+}
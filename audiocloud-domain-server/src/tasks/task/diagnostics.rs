@@ -0,0 +1,25 @@
+use actix::Context;
+
+use audiocloud_api::now;
+
+use crate::tasks::engine_ext::EngineExtCommand;
+use crate::tasks::task::TaskActor;
+
+impl TaskActor {
+    /// Ask the engine to capture a diagnostic bundle of the task, unless it was asked to within the interval
+    pub(crate) fn request_engine_diagnostics(&mut self, error: String, ctx: &mut Context<Self>) {
+        let interval = chrono::Duration::seconds(self.opts.diagnostics_interval_seconds as i64);
+        if self.opts.diagnostics_interval_seconds == 0
+           || matches!(self.diagnostics_requested, Some(requested) if now() - requested < interval)
+        {
+            return;
+        }
+
+        self.diagnostics_requested = Some(now());
+
+        let cmd = EngineExtCommand::CaptureDiagnostics { task_id: { self.id.clone() },
+                                                         error:   { error }, };
+
+        self.send_engine_ext_command(cmd, ctx);
+    }
+}
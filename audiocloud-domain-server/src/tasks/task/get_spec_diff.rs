@@ -0,0 +1,55 @@
+use actix::Handler;
+use serde::Serialize;
+use similar::TextDiff;
+
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::TaskSpec;
+
+use crate::tasks::task::TaskActor;
+use crate::tasks::{GetTaskSpecDiff, TaskSettings, TaskSpecDiff};
+use crate::DomainResult;
+
+/// What the diff compares, the spec with the settings the engine gets next to it
+#[derive(Serialize)]
+struct DiffedTask<'a> {
+    #[serde(flatten)]
+    spec:     &'a TaskSpec,
+    settings: &'a TaskSettings,
+}
+
+impl Handler<GetTaskSpecDiff> for TaskActor {
+    type Result = DomainResult<TaskSpecDiff>;
+
+    fn handle(&mut self, msg: GetTaskSpecDiff, ctx: &mut Self::Context) -> Self::Result {
+        let settings = self.settings();
+        let engine_settings = self.engine_settings.clone().unwrap_or_default();
+
+        let domain = task_to_text(&self.spec, &settings)?;
+        let engine = match &self.engine_spec {
+            Some((_, spec)) => task_to_text(spec, &engine_settings)?,
+            None => String::new(),
+        };
+
+        let in_sync =
+            self.engine_spec.as_ref().map(|(_, spec)| spec == &self.spec) == Some(true) && engine_settings == settings;
+
+        let diff = TextDiff::from_lines(&engine, &domain).unified_diff()
+                                                         .context_radius(3)
+                                                         .header("engine", "domain")
+                                                         .to_string();
+
+        Ok(TaskSpecDiff { task_id:         { self.id.clone() },
+                          domain_revision: { self.spec.revision },
+                          engine_revision: { self.engine_spec.as_ref().map(|(_, spec)| spec.revision) },
+                          engine_acked_at: { self.engine_spec.as_ref().map(|(acked_at, _)| *acked_at) },
+                          in_sync:         { in_sync },
+                          diff:            { diff }, })
+    }
+}
+
+fn task_to_text(spec: &TaskSpec, settings: &TaskSettings) -> DomainResult<String> {
+    let task = DiffedTask { spec:     { spec },
+                            settings: { settings }, };
+
+    serde_yaml::to_string(&task).map_err(|error| DomainError::Serialization { error: error.to_string(), })
+}
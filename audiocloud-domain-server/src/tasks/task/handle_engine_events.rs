@@ -1,11 +1,20 @@
+use std::collections::HashMap;
+
 use actix::Handler;
+use actix_broker::BrokerIssue;
+use tracing::*;
 
 use audiocloud_api::audio_engine::EngineEvent;
+use audiocloud_api::common::media::RenderId;
 
-use audiocloud_api::DesiredTaskPlayState;
+use audiocloud_api::{DesiredTaskPlayState, TaskPlayState};
 
+use crate::tasks::engine_ext::spec_hash;
 use crate::tasks::task::TaskActor;
-use crate::tasks::NotifyEngineEvent;
+use crate::tasks::{
+    NotifyEngineEvent, NotifyEngineStarted, NotifyTaskEngineRestarted, NotifyTaskLoudness, NotifyTaskRenderFinished,
+    NotifyTaskSpectrum, TaskRenderNormalization,
+};
 
 impl Handler<NotifyEngineEvent> for TaskActor {
     type Result = ();
@@ -30,6 +39,7 @@ impl Handler<NotifyEngineEvent> for TaskActor {
                       dynamic_reports, } => {
                 if &self.id == &task_id && self.engine.should_be_playing(&play_id) {
                     self.engine.set_actual_playing(play_id);
+                    self.capture_metering(&peak_metering, &HashMap::new());
                     self.merge_peak_meters(peak_metering);
                     self.push_compressed_audio(audio);
                     self.maybe_send_packet();
@@ -56,6 +66,14 @@ impl Handler<NotifyEngineEvent> for TaskActor {
                 if &self.id == &task_id {
                     self.engine.set_desired_state(DesiredTaskPlayState::Stopped);
                     self.engine.set_actual_stopped();
+
+                    self.on_null_test_render_finished(&render_id, &path);
+
+                    let normalization = self.take_render_normalization(&render_id);
+                    self.issue_system_async(NotifyTaskRenderFinished { task_id,
+                                                                       render_id,
+                                                                       path,
+                                                                       normalization });
                 }
             }
             RenderingFailed { task_id,
@@ -64,13 +82,83 @@ impl Handler<NotifyEngineEvent> for TaskActor {
                 if &self.id == &task_id {
                     self.engine.set_desired_state(DesiredTaskPlayState::Stopped);
                     self.engine.set_actual_stopped();
+                    self.take_render_normalization(&render_id);
+                    self.on_null_test_render_failed(&render_id, error);
                 }
             }
             Error { task_id, error } => {
                 if &self.id == &task_id {
                     // do not modify desired states..
+                    self.request_engine_diagnostics(error, ctx);
                 }
             }
         }
     }
 }
+
+impl Handler<NotifyEngineStarted> for TaskActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyEngineStarted, ctx: &mut Self::Context) -> Self::Result {
+        if &self.engine_id != &msg.engine_id {
+            return;
+        }
+
+        let restored = match (&self.engine_spec, msg.sessions.get(&self.id)) {
+            (Some((_, spec)), Some(hash)) => &spec_hash(spec) == hash,
+            _ => false,
+        };
+
+        let interrupted = self.engine.on_engine_started();
+        if let Some(TaskPlayState::Rendering(render)) = &interrupted {
+            self.take_render_normalization(&render.render_id);
+            self.on_null_test_render_failed(&render.render_id, "Engine restarted while rendering".to_owned());
+        }
+
+        warn!(id = %self.id, restored, ?interrupted, "Engine restarted, setting spec again");
+
+        // once the engine acknowledges the spec, everything else the session had is set again
+        self.engine_spec = None;
+        self.engine_settings = None;
+        self.set_engine_spec(ctx);
+
+        self.issue_system_async(NotifyTaskEngineRestarted { task_id:     { self.id.clone() },
+                                                            engine_id:   { msg.engine_id },
+                                                            restored:    { restored },
+                                                            interrupted: { interrupted }, });
+    }
+}
+
+impl TaskActor {
+    fn take_render_normalization(&mut self, render_id: &RenderId) -> Option<TaskRenderNormalization> {
+        match self.render_normalization.take() {
+            Some((normalized_render_id, normalization)) if &normalized_render_id == render_id => Some(normalization),
+            other => {
+                self.render_normalization = other;
+                None
+            }
+        }
+    }
+}
+
+impl Handler<NotifyTaskLoudness> for TaskActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskLoudness, ctx: &mut Self::Context) -> Self::Result {
+        if &self.id == &msg.task_id && self.engine.should_be_playing(&msg.play_id) {
+            self.capture_metering(&HashMap::new(), &msg.loudness);
+            self.packet_loudness.extend(msg.loudness);
+        }
+    }
+}
+
+impl Handler<NotifyTaskSpectrum> for TaskActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskSpectrum, ctx: &mut Self::Context) -> Self::Result {
+        // reports arrive faster than packets go out when the rate is high, packets carry the latest one
+        if &self.id == &msg.task_id && self.engine.should_be_playing(&msg.play_id) {
+            self.packet_spectrum.extend(msg.spectrum);
+        }
+    }
+}
@@ -2,6 +2,7 @@ use std::collections::HashMap;
 
 use actix::{Context, Handler};
 
+use audiocloud_api::audio_engine::EngineCommand;
 use audiocloud_api::domain::streaming::DiffStamped;
 use audiocloud_api::FixedInstanceId;
 
@@ -21,12 +22,26 @@ impl Handler<NotifyFixedInstanceReports> for TaskActor {
     }
 }
 
+impl Handler<NotifyInstanceState> for TaskActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyInstanceState, ctx: &mut Self::Context) -> Self::Result {
+        self.fixed_instances.notify_instance_state_changed(msg);
+    }
+}
+
 impl Handler<NotifyFixedInstanceRouting> for TaskActor {
     type Result = ();
 
     fn handle(&mut self, msg: NotifyFixedInstanceRouting, ctx: &mut Self::Context) -> Self::Result {
+        if self.fixed_instance_routing == msg.routing {
+            return;
+        }
+
         self.fixed_instance_routing = msg.routing;
-        // TODO: update engine?
+        self.engine
+            .enqueue(EngineCommand::Instances { task_id:   { self.id.clone() },
+                                                instances: { self.engine_fixed_instance_routing() }, });
     }
 }
 
@@ -22,22 +22,28 @@ impl Handler<ModifyTask> for TaskActor {
                 Err(DomainError::TaskModificationRevisionOutOfDate { task_id:  self.id.clone(),
                                                                      revision: self.spec.revision, })
             }
-        } else if play_state.is_rendering_any() {
+        } else if play_state.is_rendering_any() || self.null_test.is_some() {
             Err(DomainError::TaskIllegalPlayState { task_id: self.id.clone(),
                                                     state:   play_state.into(), })
         } else {
             let mut clone = self.spec.clone();
-            for update in msg.modify_spec {
+            for update in msg.modify_spec.iter().cloned() {
                 clone.modify(update)
                      .map_err(|error| DomainError::TaskModification { task_id: self.id.clone(),
                                                                       error })?;
             }
 
             clone.revision += 1;
-            self.spec = clone;
+            let replaced = std::mem::replace(&mut self.spec, clone);
+            self.on_spec_modified(&replaced);
+            self.remember_spec_revision(replaced);
+
+            // the engine gets the connection values once it acknowledges the spec, scaled by the track groups
+            self.remember_connection_faders(&msg.modify_spec);
+
             self.engine
                 .enqueue(EngineCommand::SetSpec { task_id:     self.id.clone(),
-                                                  spec:        self.spec.clone(),
+                                                  spec:        self.effective_spec(),
                                                   instances:   self.fixed_instance_routing.clone(),
                                                   media_ready: self.media_objects.ready_for_engine(), });
 
@@ -0,0 +1,196 @@
+use std::mem;
+
+use actix::Handler;
+use actix_broker::BrokerIssue;
+
+use audiocloud_api::audio_engine::{EngineCommand, TaskRendering};
+use audiocloud_api::common::media::RenderId;
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::{DesiredInstancePlayState, DesiredTaskPlayState, RequestRender, TaskSpec};
+
+use crate::tasks::null_test::NullTestVariant;
+use crate::tasks::task::TaskActor;
+use crate::tasks::{
+    NotifyTaskNullTest, NotifyTaskNullTestRendered, RunTaskNullTest, TaskNullTestReport, TaskNullTestRequest,
+};
+use crate::DomainResult;
+
+/// Earlier spec revisions kept for null tests
+const SPEC_HISTORY_LEN: usize = 16;
+
+/// A null test in progress, the task has the spec of the variant being rendered until it ends
+pub(crate) struct NullTestJob {
+    request:    TaskNullTestRequest,
+    /// Spec of the task before the null test started, restored once it ends
+    spec:       TaskSpec,
+    revision_a: u64,
+    revision_b: u64,
+    /// Spec of variant B, until it is rendered
+    spec_b:     Option<TaskSpec>,
+    /// Where variant A was rendered to, once it finished
+    path_a:     Option<String>,
+}
+
+impl NullTestJob {
+    fn report(&self) -> TaskNullTestReport {
+        TaskNullTestReport { render_a:   { self.request.a.render.render_id.clone() },
+                             render_b:   { self.request.b.render.render_id.clone() },
+                             revision_a: { self.revision_a },
+                             revision_b: { self.revision_b },
+                             media_id:   { None },
+                             metrics:    { None },
+                             error:      { None }, }
+    }
+
+    fn has_render(&self, render_id: &RenderId) -> bool {
+        &self.request.a.render.render_id == render_id || &self.request.b.render.render_id == render_id
+    }
+}
+
+impl Handler<RunTaskNullTest> for TaskActor {
+    type Result = DomainResult<TaskRendering>;
+
+    fn handle(&mut self, msg: RunTaskNullTest, ctx: &mut Self::Context) -> Self::Result {
+        self.check_routing_verified()?;
+        self.check_no_null_test()?;
+
+        let play_state = self.engine.get_actual_play_state();
+        if play_state.is_rendering_any() {
+            return Err(DomainError::TaskIllegalPlayState { task_id: { self.id.clone() },
+                                                           state:   { play_state.into() }, });
+        }
+
+        let spec_a = self.null_test_spec(&msg.request.a)?;
+        let spec_b = self.null_test_spec(&msg.request.b)?;
+        let render = msg.request.a.render.clone();
+
+        let rv = TaskRendering::Rendering { task_id:   { self.id.clone() },
+                                            render_id: { render.render_id.clone() }, };
+
+        let job = NullTestJob { revision_a: { msg.request.a.revision.unwrap_or(self.spec.revision) },
+                                revision_b: { msg.request.b.revision.unwrap_or(self.spec.revision) },
+                                request:    { msg.request },
+                                spec:       { mem::replace(&mut self.spec, spec_a) },
+                                spec_b:     { Some(spec_b) },
+                                path_a:     { None }, };
+
+        self.null_test = Some(job);
+        self.render_null_test_variant(render);
+
+        Ok(rv)
+    }
+}
+
+impl TaskActor {
+    /// The spec is swapped for the variants while a null test runs, it must not change until the test ends
+    pub(crate) fn check_no_null_test(&self) -> DomainResult<()> {
+        if self.null_test.is_none() {
+            return Ok(());
+        }
+
+        Err(DomainError::TaskIllegalPlayState { task_id: { self.id.clone() },
+                                                state:   { self.engine.get_actual_play_state().into() }, })
+    }
+
+    /// Keep a revision of the spec that was replaced, for null tests of earlier revisions
+    pub(crate) fn remember_spec_revision(&mut self, spec: TaskSpec) {
+        if self.spec_history.len() >= SPEC_HISTORY_LEN {
+            self.spec_history.pop_front();
+        }
+
+        self.spec_history.push_back(spec);
+    }
+
+    fn null_test_spec(&self, variant: &NullTestVariant) -> DomainResult<TaskSpec> {
+        let mut spec = match variant.revision {
+            None => self.spec.clone(),
+            Some(revision) if revision == self.spec.revision => self.spec.clone(),
+            Some(revision) => {
+                let error = format!("Revision {revision} of the spec is not kept for null tests");
+                self.spec_history
+                    .iter()
+                    .find(|spec| spec.revision == revision)
+                    .cloned()
+                    .ok_or_else(|| DomainError::TaskRevisionMalformed { error })?
+            }
+        };
+
+        for update in variant.modify_spec.iter().cloned() {
+            spec.modify(update)
+                .map_err(|error| DomainError::TaskModification { task_id: self.id.clone(),
+                                                                 error })?;
+        }
+
+        // the variants take the place of the current revision while they are rendered
+        spec.revision = self.spec.revision;
+
+        Ok(spec)
+    }
+
+    fn render_null_test_variant(&mut self, render: RequestRender) {
+        self.engine
+            .enqueue(EngineCommand::SetSpec { task_id:     self.id.clone(),
+                                              spec:        self.effective_spec(),
+                                              instances:   self.fixed_instance_routing.clone(),
+                                              media_ready: self.media_objects.ready_for_engine(), });
+
+        let desired_instance_state = DesiredInstancePlayState::Rendering { length:    { render.segment.length },
+                                                                           render_id: { render.render_id.clone() }, };
+
+        self.fixed_instances.set_desired_state(desired_instance_state);
+        self.engine.set_desired_state(DesiredTaskPlayState::Render(render));
+    }
+
+    /// Render variant B once variant A finished, compare the renders once both finished
+    pub(crate) fn on_null_test_render_finished(&mut self, render_id: &RenderId, path: &str) {
+        let job = match self.null_test.as_mut() {
+            Some(job) if job.has_render(render_id) => job,
+            _ => return,
+        };
+
+        if &job.request.a.render.render_id == render_id {
+            job.path_a = Some(path.to_owned());
+            let render = job.request.b.render.clone();
+
+            if let Some(spec_b) = job.spec_b.take() {
+                self.spec = spec_b;
+            }
+
+            self.render_null_test_variant(render);
+        } else if let Some((report, path_a)) = self.end_null_test() {
+            self.issue_system_async(NotifyTaskNullTestRendered { task_id: { self.id.clone() },
+                                                                 report:  { report },
+                                                                 path_a:  { path_a.unwrap_or_default() },
+                                                                 path_b:  { path.to_owned() }, });
+        }
+    }
+
+    /// A render of the null test failed or was cancelled, the null test ends without comparing
+    pub(crate) fn on_null_test_render_failed(&mut self, render_id: &RenderId, error: String) {
+        if !matches!(&self.null_test, Some(job) if job.has_render(render_id)) {
+            return;
+        }
+
+        if let Some((mut report, _)) = self.end_null_test() {
+            report.error = Some(error);
+
+            self.issue_system_async(NotifyTaskNullTest { task_id: { self.id.clone() },
+                                                         report:  { report }, });
+        }
+    }
+
+    /// Go back to the spec of the task, with the report of the null test and where variant A was rendered to
+    fn end_null_test(&mut self) -> Option<(TaskNullTestReport, Option<String>)> {
+        let job = self.null_test.take()?;
+        let report = job.report();
+
+        self.spec = job.spec;
+        self.engine
+            .enqueue(EngineCommand::SetSpec { task_id:     self.id.clone(),
+                                              spec:        self.effective_spec(),
+                                              instances:   self.fixed_instance_routing.clone(),
+                                              media_ready: self.media_objects.ready_for_engine(), });
+
+        Some((report, job.path_a))
+    }
+}
@@ -2,12 +2,15 @@ use std::collections::HashMap;
 use std::mem;
 
 use actix_broker::BrokerIssue;
+use tracing::*;
 
 use audiocloud_api::audio_engine::CompressedAudio;
 use audiocloud_api::domain::streaming::DiffStamped;
-use audiocloud_api::{now, NodePadId, PadMetering};
+use audiocloud_api::{now, NodePadId, PadMetering, PlayId, StreamingPacket};
 
-use crate::tasks::messages::NotifyStreamingPacket;
+use crate::tasks::engine_ext::PadLoudness;
+use crate::tasks::messages::{NotifyStreamingPacket, NotifyTaskMetering};
+use crate::tasks::stream_continuity::{StreamContinuity, StreamStep};
 use crate::tasks::task::TaskActor;
 
 impl TaskActor {
@@ -21,21 +24,106 @@ impl TaskActor {
         }
     }
 
+    /// Hand metering to the meter capture as it arrives, packets only carry what is left of it once they go out
+    pub(crate) fn capture_metering(&self,
+                                   peak_metering: &HashMap<NodePadId, PadMetering>,
+                                   loudness: &HashMap<NodePadId, PadLoudness>) {
+        if self.opts.meter_capture.meter_capture {
+            self.issue_system_async(NotifyTaskMetering { task_id:       { self.id.clone() },
+                                                         at:            { now() },
+                                                         peak_metering: { peak_metering.clone() },
+                                                         loudness:      { loudness.clone() }, });
+        }
+    }
+
     pub(crate) fn push_compressed_audio(&mut self, audio: CompressedAudio) {
-        if self.engine.should_be_playing(&audio.play_id) {
+        if self.engine.should_be_playing(&audio.play_id) && self.is_next_audio(&audio) {
+            self.packet_timeline_pos = Some(audio.timeline_pos);
+            if !self.playlist.is_empty() {
+                self.playlist_index = self.playlist.segment_index_at(self.playlist_index, audio.timeline_pos);
+            }
             self.packet.audio.push(DiffStamped::new(self.packet.created_at, audio));
         }
     }
 
+    /// Check that the audio continues the stream of the play, a loop wrapping is not a discontinuity
+    fn is_next_audio(&mut self, audio: &CompressedAudio) -> bool {
+        if !matches!(&self.packet_continuity, Some((play_id, _)) if play_id == &audio.play_id) {
+            self.packet_continuity = Some((audio.play_id, StreamContinuity::default()));
+        }
+
+        let continuity = match self.packet_continuity.as_mut() {
+            Some((_, continuity)) => continuity,
+            None => return true,
+        };
+
+        match continuity.push(audio.stream_pos, audio.num_samples as u64, audio.timeline_pos) {
+            StreamStep::Gap { missing } => {
+                warn!(id = %self.id,
+                      play_id = %audio.play_id,
+                      missing,
+                      gaps = continuity.gaps,
+                      "Audio missing from stream");
+                true
+            }
+            StreamStep::Repeated => {
+                debug!(id = %self.id,
+                       play_id = %audio.play_id,
+                       stream_pos = audio.stream_pos,
+                       "Dropping repeated audio");
+                false
+            }
+            StreamStep::LoopWrap => {
+                trace!(id = %self.id, play_id = %audio.play_id, passes = continuity.passes, "Loop wrapped");
+                true
+            }
+            StreamStep::Start | StreamStep::Continuous => true,
+        }
+    }
+
+    /// Take the packet collected so far. It belongs to the play the audio continues and takes the next serial of the
+    /// task, serials keep counting across plays
+    pub(crate) fn seal_packet(packet: &mut StreamingPacket,
+                              play_id: Option<PlayId>,
+                              next_serial: &mut u64)
+                              -> StreamingPacket {
+        let mut packet = mem::take(packet);
+        if let Some(play_id) = play_id {
+            packet.play_id = play_id;
+        }
+
+        packet.serial = *next_serial;
+        *next_serial += 1;
+
+        packet
+    }
+
     pub(crate) fn maybe_send_packet(&mut self) {
         let packet_age = now() - self.packet.created_at;
         let packet_num_audio_frames = self.packet.audio.len();
-        let max_packet_age = chrono::Duration::milliseconds(self.opts.max_packet_age_ms as i64);
+        let max_packet_age_ms = self.latency_profile.max_packet_age_ms(&self.opts);
+        let max_packet_age = chrono::Duration::milliseconds(max_packet_age_ms as i64);
+        let max_packet_audio_frames = self.latency_profile.max_packet_audio_frames(&self.opts);
+
+        if packet_age >= max_packet_age || packet_num_audio_frames >= max_packet_audio_frames {
+            let play_id = self.packet_continuity.as_ref().map(|(play_id, _)| *play_id);
+            let packet = Self::seal_packet(&mut self.packet, play_id, &mut self.packet_serial);
+
+            let bar_beat = self.packet_timeline_pos
+                               .take()
+                               .map(|timeline_pos| self.tempo_map.bar_beat_at(timeline_pos));
+
+            let loudness = mem::take(&mut self.packet_loudness);
+            let spectrum = mem::take(&mut self.packet_spectrum);
 
-        if packet_age >= max_packet_age || packet_num_audio_frames >= self.opts.max_packet_audio_frames {
-            let packet = mem::take(&mut self.packet);
-            self.issue_system_async(NotifyStreamingPacket { task_id: { self.id.clone() },
-                                                            packet:  { packet }, });
+            self.issue_system_async(NotifyStreamingPacket { task_id:         { self.id.clone() },
+                                                            packet:          { packet },
+                                                            bar_beat:        { bar_beat },
+                                                            playlist_index:  { self.playlist_index },
+                                                            latency_profile: { self.latency_profile },
+                                                            codec:           { self.stream_codec },
+                                                            loudness:        { loudness },
+                                                            spectrum:        { spectrum }, });
         }
     }
 }
@@ -0,0 +1,99 @@
+use actix::{fut, ActorFutureExt, Handler, ResponseActFuture, WrapFuture};
+
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::{PlayId, TaskPlayState};
+
+use crate::nats::{self, Idempotency};
+use crate::tasks::engine_ext::{engine_ext_command_subject, EngineExtCommand};
+use crate::tasks::task::TaskActor;
+use crate::tasks::{GetTaskTransport, PausePlayTask, ResumePlayTask, TaskPlayPause, TaskTransport};
+use crate::DomainResult;
+
+impl TaskActor {
+    /// Pause or resume the engine transport, the play counts as paused once the engine confirms it
+    fn set_engine_paused(&mut self,
+                         play_id: PlayId,
+                         paused: bool)
+                         -> ResponseActFuture<Self, DomainResult<TaskPlayPause>> {
+        let is_playing = matches!(self.engine.get_actual_play_state(),
+                                  TaskPlayState::Playing(play) if play.play_id == play_id);
+
+        if !is_playing || (paused && self.recording.armed) {
+            let error = DomainError::TaskIllegalPlayState { task_id: { self.id.clone() },
+                                                            state:   { self.engine.get_actual_play_state().into() }, };
+
+            return Box::pin(fut::err(error));
+        }
+
+        if self.engine.is_paused(&play_id) == paused {
+            return Box::pin(fut::ok(TaskPlayPause { play_id: { play_id },
+                                                    paused:  { paused }, }));
+        }
+
+        let task_id = self.id.clone();
+        let cmd = if paused {
+            EngineExtCommand::PausePlay { task_id: { task_id },
+                                          play_id: { play_id.clone() }, }
+        } else {
+            EngineExtCommand::ResumePlay { task_id: { task_id },
+                                           play_id: { play_id.clone() }, }
+        };
+
+        let subject = engine_ext_command_subject(&self.engine_command_subject);
+        let request = nats::request_raw_msgpack_within(subject,
+                                                       cmd,
+                                                       self.opts.engine_transport_deadline(),
+                                                       Idempotency::NotIdempotent);
+
+        Box::pin(request.into_actor(self)
+                        .map(move |res, actor, ctx| actor.on_engine_paused(play_id, paused, res)))
+    }
+
+    fn on_engine_paused(&mut self,
+                        play_id: PlayId,
+                        paused: bool,
+                        res: anyhow::Result<Result<(), String>>)
+                        -> DomainResult<TaskPlayPause> {
+        match res {
+            Ok(Ok(())) => {}
+            Ok(Err(error)) => return Err(DomainError::BadGateway { error }),
+            Err(error) => return Err(DomainError::BadGateway { error: error.to_string(), }),
+        }
+
+        self.engine.set_paused(&play_id, paused);
+
+        Ok(TaskPlayPause { play_id: { play_id },
+                           paused:  { paused }, })
+    }
+}
+
+impl Handler<PausePlayTask> for TaskActor {
+    type Result = ResponseActFuture<Self, DomainResult<TaskPlayPause>>;
+
+    fn handle(&mut self, msg: PausePlayTask, ctx: &mut Self::Context) -> Self::Result {
+        self.set_engine_paused(msg.pause.play_id, true)
+    }
+}
+
+impl Handler<ResumePlayTask> for TaskActor {
+    type Result = ResponseActFuture<Self, DomainResult<TaskPlayPause>>;
+
+    fn handle(&mut self, msg: ResumePlayTask, ctx: &mut Self::Context) -> Self::Result {
+        self.set_engine_paused(msg.resume.play_id, false)
+    }
+}
+
+impl Handler<GetTaskTransport> for TaskActor {
+    type Result = DomainResult<TaskTransport>;
+
+    fn handle(&mut self, msg: GetTaskTransport, ctx: &mut Self::Context) -> Self::Result {
+        Ok(match self.engine.get_actual_play_state() {
+            TaskPlayState::Playing(play) => TaskTransport { play_id:   { Some(play.play_id.clone()) },
+                                                            paused:    { self.engine.is_paused(&play.play_id) },
+                                                            rendering: { false }, },
+            TaskPlayState::Rendering(_) => TaskTransport { rendering: true,
+                                                           ..Default::default() },
+            _ => TaskTransport::default(),
+        })
+    }
+}
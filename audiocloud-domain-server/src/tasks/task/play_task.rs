@@ -2,8 +2,10 @@ use actix::Handler;
 
 use audiocloud_api::audio_engine::TaskPlaying;
 
+use audiocloud_api::domain::DomainError;
 use audiocloud_api::{DesiredInstancePlayState, DesiredTaskPlayState};
 
+use crate::tasks::engine_ext::EngineExtCommand;
 use crate::tasks::task::TaskActor;
 use crate::tasks::PlayTask;
 use crate::DomainResult;
@@ -14,12 +16,31 @@ impl Handler<PlayTask> for TaskActor {
     fn handle(&mut self, msg: PlayTask, ctx: &mut Self::Context) -> Self::Result {
         // TODO: check play_id history
 
+        self.check_routing_verified()?;
+        self.check_no_null_test()?;
+
+        if let Some(click) = &msg.click {
+            click.validate(&self.spec)
+                 .map_err(|error| DomainError::Serialization { error: { format!("Invalid click: {error}") }, })?;
+        }
+
         let rv = TaskPlaying::Playing { task_id: { self.id.clone() },
                                         play_id: { msg.play.play_id.clone() }, };
 
+        self.send_engine_ext_command(EngineExtCommand::SetWatermark { task_id:   { self.id.clone() },
+                                                                      play_id:   { msg.play.play_id.clone() },
+                                                                      watermark: { msg.watermark }, },
+                                     ctx);
+
         let desired_instance_state = DesiredInstancePlayState::Playing { play_id: { msg.play.play_id.clone() }, };
         let desired_task_state = DesiredTaskPlayState::Play(msg.play);
 
+        if let Some(click) = msg.click {
+            self.send_engine_ext_command(EngineExtCommand::SetClick { task_id: { self.id.clone() },
+                                                                      click:   { click }, },
+                                         ctx);
+        }
+
         self.fixed_instances.set_desired_state(desired_instance_state);
         self.engine.set_desired_state(desired_task_state);
 
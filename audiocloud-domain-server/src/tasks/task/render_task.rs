@@ -4,6 +4,7 @@ use audiocloud_api::audio_engine::TaskRendering;
 
 use audiocloud_api::{DesiredInstancePlayState, DesiredTaskPlayState};
 
+use crate::tasks::engine_ext::EngineExtCommand;
 use crate::tasks::task::TaskActor;
 use crate::tasks::RenderTask;
 use crate::DomainResult;
@@ -14,13 +15,27 @@ impl Handler<RenderTask> for TaskActor {
     fn handle(&mut self, msg: RenderTask, ctx: &mut Self::Context) -> Self::Result {
         // TODO: check render_id history
 
+        self.check_routing_verified()?;
+        self.check_no_null_test()?;
+
         let rv = TaskRendering::Rendering { task_id:   { self.id.clone() },
                                             render_id: { msg.render.render_id.clone() }, };
 
         let desired_instance_state = DesiredInstancePlayState::Rendering { length:    { msg.render.segment.length },
                                                                            render_id: { msg.render.render_id.clone() }, };
+        let render_id = msg.render.render_id.clone();
         let desired_task_state = DesiredTaskPlayState::Render(msg.render);
 
+        self.render_normalization = msg.normalization
+                                       .map(|normalization| (render_id.clone(), normalization));
+
+        if !msg.formats.is_empty() {
+            self.send_engine_ext_command(EngineExtCommand::SetRenderFormats { task_id:   { self.id.clone() },
+                                                                              render_id: { render_id },
+                                                                              formats:   { msg.formats }, },
+                                         ctx);
+        }
+
         self.fixed_instances.set_desired_state(desired_instance_state);
         self.engine.set_desired_state(desired_task_state);
 
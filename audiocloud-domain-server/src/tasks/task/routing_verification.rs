@@ -0,0 +1,140 @@
+use actix::{ActorFutureExt, Context, ContextFutureSpawner, Handler, WrapFuture};
+use actix_broker::BrokerIssue;
+use tracing::*;
+
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::{now, TaskPlayState};
+
+use crate::tasks::task::TaskActor;
+use crate::tasks::{
+    get_tasks_supervisor, plan_routing_chains, GetTaskRoutingVerification, NotifyTaskRoutingVerification,
+    RoutingChainCheck, RoutingVerificationState, RunEngineTestTone, TaskRoutingVerification, VerifyTaskRouting,
+};
+use crate::DomainResult;
+
+impl TaskActor {
+    /// Start a pending verification once the fixed instances are ready and the engine has the spec
+    pub(crate) fn update_routing_verification(&mut self, instances_ready: bool, ctx: &mut Context<Self>) {
+        if instances_ready
+           && self.engine_spec.is_some()
+           && self.routing_verification.state == RoutingVerificationState::Pending
+        {
+            self.verify_routing(ctx);
+        }
+    }
+
+    /// Play and render only go ahead once every chain returned the tone
+    pub(crate) fn check_routing_verified(&self) -> DomainResult<()> {
+        let verification = &self.routing_verification;
+        if verification.allows_playing() {
+            return Ok(());
+        }
+
+        match verification.chains.iter().find(|chain| !chain.passed) {
+            Some(chain) if verification.state == RoutingVerificationState::Failed => {
+                let operation = format!("Routing verification of channel {}: {}", chain.channel, chain.diagnosis);
+                Err(DomainError::InstanceNotCapable { instance_id: { chain.instance_id.clone() },
+                                                      operation:   { operation }, })
+            }
+            _ => {
+                let error = format!("Routing of task {} is not verified yet", self.id);
+                Err(DomainError::BadGateway { error })
+            }
+        }
+    }
+
+    fn verify_routing(&mut self, ctx: &mut Context<Self>) {
+        let chains = plan_routing_chains(&self.engine_fixed_instance_routing());
+        let engine_id = self.engine_id.clone();
+        let level_db = self.opts.routing_verification_level_db;
+        let tolerance_db = self.opts.routing_verification_tolerance_db;
+        let duration_ms = self.opts.routing_verification_duration_ms;
+
+        info!(id = %self.id, chains = chains.len(), "Verifying routing of fixed instances");
+
+        self.routing_verification = TaskRoutingVerification::new(RoutingVerificationState::Running);
+        self.notify_routing_verification();
+
+        // one tone at a time, the engine refuses to play two at once
+        let run = async move {
+            let mut checks = vec![];
+            for chain in chains {
+                let tone = RunEngineTestTone { engine_id: { engine_id.clone() },
+                                               tone:      { chain.test_tone(level_db, duration_ms) }, };
+
+                let result = match get_tasks_supervisor().send(tone).await {
+                    Ok(Ok(result)) => Ok(result),
+                    Ok(Err(error)) => Err(error.to_string()),
+                    Err(error) => Err(error.to_string()),
+                };
+
+                checks.push(chain.evaluate(level_db, tolerance_db, result));
+            }
+
+            checks
+        };
+
+        run.into_actor(self)
+           .map(|checks, actor, ctx| actor.on_routing_verified(checks))
+           .spawn(ctx);
+    }
+
+    fn on_routing_verified(&mut self, chains: Vec<RoutingChainCheck>) {
+        let mut state = RoutingVerificationState::Passed;
+        for chain in chains.iter().filter(|chain| !chain.passed) {
+            warn!(id = %self.id,
+                  instance_id = %chain.instance_id,
+                  channel = chain.channel,
+                  diagnosis = %chain.diagnosis,
+                  "Routing verification failed");
+
+            state = RoutingVerificationState::Failed;
+        }
+
+        if state == RoutingVerificationState::Passed {
+            info!(id = %self.id, "Routing verification passed");
+        }
+
+        self.routing_verification = TaskRoutingVerification { state:       { state },
+                                                              verified_at: { Some(now()) },
+                                                              chains:      { chains }, };
+
+        self.notify_routing_verification();
+    }
+
+    fn notify_routing_verification(&mut self) {
+        self.issue_system_async(NotifyTaskRoutingVerification { task_id:      { self.id.clone() },
+                                                                verification: { self.routing_verification.clone() }, });
+    }
+}
+
+impl Handler<GetTaskRoutingVerification> for TaskActor {
+    type Result = DomainResult<TaskRoutingVerification>;
+
+    fn handle(&mut self, msg: GetTaskRoutingVerification, ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.routing_verification.clone())
+    }
+}
+
+impl Handler<VerifyTaskRouting> for TaskActor {
+    type Result = DomainResult<TaskRoutingVerification>;
+
+    fn handle(&mut self, msg: VerifyTaskRouting, ctx: &mut Self::Context) -> Self::Result {
+        match self.routing_verification.state {
+            RoutingVerificationState::Pending | RoutingVerificationState::Running => {}
+            _ => {
+                // the tone would play over the session and the engine refuses it anyway
+                let play_state = self.engine.get_actual_play_state();
+                if !matches!(play_state, TaskPlayState::Stopped) {
+                    return Err(DomainError::TaskIllegalPlayState { task_id: { self.id.clone() },
+                                                                   state:   { play_state.into() }, });
+                }
+
+                self.routing_verification = TaskRoutingVerification::new(RoutingVerificationState::Pending);
+                self.notify_routing_verification();
+            }
+        }
+
+        Ok(self.routing_verification.clone())
+    }
+}
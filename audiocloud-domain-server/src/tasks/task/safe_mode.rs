@@ -0,0 +1,337 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use actix::{Context, Handler};
+use actix_broker::BrokerIssue;
+use serde::Serialize;
+use tracing::*;
+
+use audiocloud_api::common::task::NodeConnection;
+use audiocloud_api::{now, InputPadId, OutputPadId, TaskSpec, Timestamp};
+
+use crate::tasks::task::TaskActor;
+use crate::tasks::{EnableTaskSpecElements, GetTaskSafeMode, NotifyTaskSafeMode, TaskSafeMode, TaskSpecElements};
+use crate::DomainResult;
+
+pub struct SafeModeState {
+    entered_at: Timestamp,
+    last_error: String,
+    disabled:   TaskSpecElements,
+    pending:    TaskSpecElements,
+}
+
+impl TaskSpecElements {
+    pub fn of(spec: &TaskSpec) -> Self {
+        Self { fixed:       { spec.fixed.keys().map(ToString::to_string).collect() },
+               mixers:      { spec.mixers.keys().map(ToString::to_string).collect() },
+               connections: { spec.connections.keys().map(ToString::to_string).collect() }, }
+    }
+
+    /// Elements of `spec` added or changed since `previous`, the spec the engine last accepted
+    pub fn changed(previous: Option<&TaskSpec>, spec: &TaskSpec) -> Self {
+        Self { fixed:       { changed_keys(previous.map(|previous| &previous.fixed), &spec.fixed) },
+               mixers:      { changed_keys(previous.map(|previous| &previous.mixers), &spec.mixers) },
+               connections: { changed_keys(previous.map(|previous| &previous.connections), &spec.connections) }, }
+    }
+
+    /// Elements to disable after the engine rejected a spec made of `all`: the ones the engine error names, otherwise
+    /// the `changed` ones, and every element only if neither narrows it down
+    pub fn failed(all: &TaskSpecElements, changed: TaskSpecElements, error: &str) -> Self {
+        let named = all.named_in(error);
+
+        if !named.is_empty() {
+            named
+        } else if !changed.is_empty() {
+            changed
+        } else {
+            all.clone()
+        }
+    }
+
+    /// Elements whose ID appears as a whole word in `text`
+    pub fn named_in(&self, text: &str) -> Self {
+        let named = |ids: &HashSet<String>| ids.iter().filter(|id| mentions(text, id)).cloned().collect();
+
+        Self { fixed:       { named(&self.fixed) },
+               mixers:      { named(&self.mixers) },
+               connections: { named(&self.connections) }, }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fixed.is_empty() && self.mixers.is_empty() && self.connections.is_empty()
+    }
+
+    fn extend(&mut self, other: TaskSpecElements) {
+        self.fixed.extend(other.fixed);
+        self.mixers.extend(other.mixers);
+        self.connections.extend(other.connections);
+    }
+
+    /// Elements of `self` not in `other`
+    pub fn without(&self, other: &TaskSpecElements) -> Self {
+        Self { fixed:       { self.fixed.difference(&other.fixed).cloned().collect() },
+               mixers:      { self.mixers.difference(&other.mixers).cloned().collect() },
+               connections: { self.connections.difference(&other.connections).cloned().collect() }, }
+    }
+
+    /// Forget the elements no longer in the spec
+    pub fn retain_in(&mut self, present: &TaskSpecElements) {
+        self.fixed.retain(|id| present.fixed.contains(id));
+        self.mixers.retain(|id| present.mixers.contains(id));
+        self.connections.retain(|id| present.connections.contains(id));
+    }
+
+    /// Remove the elements of `other` from `self`, returning the ones that were actually present
+    fn take(&mut self, other: &TaskSpecElements) -> TaskSpecElements {
+        TaskSpecElements { fixed:       {
+                               other.fixed
+                                    .iter()
+                                    .filter(|id| self.fixed.remove(*id))
+                                    .cloned()
+                                    .collect()
+                           },
+                           mixers:      {
+                               other.mixers
+                                    .iter()
+                                    .filter(|id| self.mixers.remove(*id))
+                                    .cloned()
+                                    .collect()
+                           },
+                           connections: {
+                               other.connections
+                                    .iter()
+                                    .filter(|id| self.connections.remove(*id))
+                                    .cloned()
+                                    .collect()
+                           }, }
+    }
+
+    /// A connection from or to a disabled node can not be created either
+    fn touches(&self, connection: &NodeConnection) -> bool {
+        let from = match &connection.from {
+            OutputPadId::FixedInstanceOutput(id) => self.fixed.contains(&id.to_string()),
+            OutputPadId::MixerOutput(id) => self.mixers.contains(&id.to_string()),
+            _ => false,
+        };
+
+        let to = match &connection.to {
+            InputPadId::FixedInstanceInput(id) => self.fixed.contains(&id.to_string()),
+            InputPadId::MixerInput(id) => self.mixers.contains(&id.to_string()),
+            _ => false,
+        };
+
+        from || to
+    }
+}
+
+fn changed_keys<K, V>(previous: Option<&HashMap<K, V>>, current: &HashMap<K, V>) -> HashSet<String>
+    where K: Eq + Hash + ToString,
+          V: Serialize
+{
+    current.iter()
+           .filter(|(id, value)| match previous.and_then(|previous| previous.get(*id)) {
+               Some(previous) => serde_json::to_value(previous).ok() != serde_json::to_value(value).ok(),
+               None => true,
+           })
+           .map(|(id, _)| id.to_string())
+           .collect()
+}
+
+fn mentions(text: &str, id: &str) -> bool {
+    let is_id_char = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+
+    text.match_indices(id).any(|(start, _)| {
+                              let before = text[..start].chars().next_back();
+                              let after = text[start + id.len()..].chars().next();
+                              !before.map(is_id_char).unwrap_or(false) && !after.map(is_id_char).unwrap_or(false)
+                          })
+}
+
+impl SafeModeState {
+    pub fn new(error: String, disabled: TaskSpecElements) -> Self {
+        Self { entered_at: { now() },
+               last_error: { error },
+               disabled:   { disabled },
+               pending:    { Default::default() }, }
+    }
+
+    pub fn disabled(&self) -> &TaskSpecElements {
+        &self.disabled
+    }
+
+    pub fn pending(&self) -> &TaskSpecElements {
+        &self.pending
+    }
+
+    /// The engine accepted the spec, returns the pending elements it accepted with it
+    pub fn accepted(&mut self) -> TaskSpecElements {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// The engine rejected the spec, returns the elements disabled because of it
+    ///
+    /// The pending elements are the suspects when there are any, otherwise whatever else the engine error points at.
+    pub fn rejected(&mut self, error: String, failed: TaskSpecElements) -> TaskSpecElements {
+        self.last_error = error;
+
+        let failed = match std::mem::take(&mut self.pending) {
+            pending if !pending.is_empty() => pending,
+            _ => failed.without(&self.disabled),
+        };
+
+        self.disabled.extend(failed.clone());
+        failed
+    }
+
+    /// Move disabled elements to pending, returns the ones that were disabled
+    pub fn enable(&mut self, elements: &TaskSpecElements) -> TaskSpecElements {
+        let enabled = self.disabled.take(elements);
+        self.pending.extend(enabled.clone());
+        enabled
+    }
+
+    /// Elements a modification made during safe mode adds or changes are tried like re-enabled ones, and disabled if
+    /// the engine rejects them. Elements the modification removed are forgotten.
+    pub fn modified(&mut self, changed: TaskSpecElements, present: &TaskSpecElements) {
+        self.disabled.retain_in(present);
+        self.pending.retain_in(present);
+        self.pending.extend(changed.without(&self.disabled));
+    }
+}
+
+impl TaskActor {
+    /// The spec as sent to the engine, without any elements disabled by safe mode
+    pub(crate) fn effective_spec(&self) -> TaskSpec {
+        let mut spec = self.spec.clone();
+
+        if let Some(safe_mode) = &self.safe_mode {
+            let disabled = &safe_mode.disabled;
+            spec.fixed.retain(|id, _| !disabled.fixed.contains(&id.to_string()));
+            spec.mixers.retain(|id, _| !disabled.mixers.contains(&id.to_string()));
+            spec.connections.retain(|id, connection| {
+                                !disabled.connections.contains(&id.to_string()) && !disabled.touches(connection)
+                            });
+        }
+
+        spec
+    }
+
+    pub(crate) fn on_engine_spec_applied(&mut self, ctx: &mut Context<Self>) {
+        self.spec_failures = 0;
+
+        let (accepted, exited) = match self.safe_mode.as_mut() {
+            Some(safe_mode) => (safe_mode.accepted(), safe_mode.disabled.is_empty()),
+            None => return,
+        };
+
+        if !accepted.is_empty() {
+            info!(id = %self.id, ?accepted, "Engine accepted re-enabled task spec elements");
+        }
+
+        if exited {
+            info!(id = %self.id, "Task left safe mode");
+            self.safe_mode = None;
+        }
+
+        if exited || !accepted.is_empty() {
+            self.notify_safe_mode();
+        }
+    }
+
+    pub(crate) fn on_engine_spec_failed(&mut self, sent: &TaskSpec, error: String, ctx: &mut Context<Self>) {
+        self.spec_failures += 1;
+
+        let accepted = self.engine_spec.as_ref().map(|(_, spec)| spec);
+        let failed = TaskSpecElements::failed(&TaskSpecElements::of(sent),
+                                              TaskSpecElements::changed(accepted, sent),
+                                              &error);
+
+        match self.safe_mode.as_mut() {
+            Some(safe_mode) => {
+                let disabled = safe_mode.rejected(error, failed);
+
+                if disabled.is_empty() {
+                    warn!(id = %self.id, "Engine rejected safe mode spec, not retrying");
+                } else {
+                    warn!(id = %self.id, ?disabled, "Engine rejected task spec elements, disabling them");
+                    self.set_engine_spec(ctx);
+                }
+            }
+            None => {
+                let threshold = self.opts.safe_mode_spec_failures;
+                if threshold == 0 || self.spec_failures < threshold {
+                    return;
+                }
+
+                warn!(id = %self.id, failures = self.spec_failures, disabled = ?failed, "Task entering safe mode");
+
+                self.safe_mode = Some(SafeModeState::new(error, failed));
+
+                self.set_engine_spec(ctx);
+            }
+        }
+
+        self.notify_safe_mode();
+    }
+
+    /// Apply the safe mode filter to a modification of the spec from `previous`
+    pub(crate) fn on_spec_modified(&mut self, previous: &TaskSpec) {
+        let changed = TaskSpecElements::changed(Some(previous), &self.spec);
+        let present = TaskSpecElements::of(&self.spec);
+
+        if let Some(safe_mode) = self.safe_mode.as_mut() {
+            safe_mode.modified(changed, &present);
+            self.notify_safe_mode();
+        }
+    }
+
+    fn safe_mode_summary(&self) -> TaskSafeMode {
+        let safe_mode = self.safe_mode.as_ref();
+
+        TaskSafeMode { task_id:              { self.id.clone() },
+                       active:               { safe_mode.is_some() },
+                       consecutive_failures: { self.spec_failures },
+                       entered_at:           { safe_mode.map(|safe_mode| safe_mode.entered_at) },
+                       last_error:           { safe_mode.map(|safe_mode| safe_mode.last_error.clone()) },
+                       disabled:             {
+                           safe_mode.map(|safe_mode| safe_mode.disabled.clone())
+                                    .unwrap_or_default()
+                       },
+                       pending:              {
+                           safe_mode.map(|safe_mode| safe_mode.pending.clone()).unwrap_or_default()
+                       }, }
+    }
+
+    fn notify_safe_mode(&mut self) {
+        self.issue_system_async(NotifyTaskSafeMode { task_id:   { self.id.clone() },
+                                                     safe_mode: { self.safe_mode_summary() }, });
+    }
+}
+
+impl Handler<GetTaskSafeMode> for TaskActor {
+    type Result = DomainResult<TaskSafeMode>;
+
+    fn handle(&mut self, msg: GetTaskSafeMode, ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.safe_mode_summary())
+    }
+}
+
+impl Handler<EnableTaskSpecElements> for TaskActor {
+    type Result = DomainResult<TaskSafeMode>;
+
+    fn handle(&mut self, msg: EnableTaskSpecElements, ctx: &mut Self::Context) -> Self::Result {
+        let safe_mode = match self.safe_mode.as_mut() {
+            Some(safe_mode) => safe_mode,
+            None => return Ok(self.safe_mode_summary()),
+        };
+
+        let enabled = safe_mode.enable(&msg.elements);
+        if !enabled.is_empty() {
+            info!(id = %self.id, ?enabled, "Re-enabling task spec elements");
+            self.set_engine_spec(ctx);
+            self.notify_safe_mode();
+        }
+
+        Ok(self.safe_mode_summary())
+    }
+}
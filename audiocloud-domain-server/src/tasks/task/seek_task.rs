@@ -33,6 +33,13 @@ impl Handler<SeekTask> for TaskActor {
                     .enqueue(EngineCommand::UpdatePlay { task_id: { msg.task_id },
                                                          update:  { update }, });
 
+                // the play and its stream go on from the new position, the stream positions keep counting
+                if let Some((continuity_play_id, continuity)) = self.packet_continuity.as_mut() {
+                    if continuity_play_id == &play_id {
+                        continuity.seek();
+                    }
+                }
+
                 Ok(sought)
             }
             _ => Err(DomainError::TaskIllegalPlayState { task_id: { msg.task_id.clone() },
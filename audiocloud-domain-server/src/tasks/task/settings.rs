@@ -0,0 +1,27 @@
+use actix::Handler;
+
+use crate::tasks::task::TaskActor;
+use crate::tasks::{NotifyTaskWatermark, TaskSettings};
+
+impl TaskActor {
+    pub(crate) fn settings(&self) -> TaskSettings {
+        TaskSettings { track_inputs:    { self.track_inputs.clone() },
+                       tempo_map:       { self.tempo_map.clone() },
+                       track_groups:    { self.track_groups.clone() },
+                       envelopes:       { self.envelopes.clone() },
+                       fades:           { self.fades.clone() },
+                       loudness_target: { self.loudness_target },
+                       watermark:       { self.watermark }, }
+    }
+}
+
+impl Handler<NotifyTaskWatermark> for TaskActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskWatermark, ctx: &mut Self::Context) -> Self::Result {
+        // the engine gets the watermark with the next play, see the supervisor's `PlayTask` handler
+        if msg.task_id == self.id {
+            self.watermark = msg.watermark;
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use actix::{Context, Handler};
+use actix_broker::BrokerIssue;
+
+use audiocloud_api::audio_engine::EngineCommand;
+use audiocloud_api::common::change::ModifyTaskSpec;
+use audiocloud_api::common::task::ConnectionValues;
+
+use crate::tasks::task::TaskActor;
+use crate::tasks::{NotifyTaskConnectionFaders, NotifyTaskTrackGroups};
+
+impl TaskActor {
+    /// Send the connection levels of the track groups and the client faders to the engine
+    pub(crate) fn set_engine_connection_levels(&mut self, ctx: &mut Context<Self>) {
+        let transaction = self.track_groups
+                              .connection_levels(&self.effective_spec(), &self.connection_faders);
+        if transaction.is_empty() {
+            return;
+        }
+
+        let cmd = EngineCommand::ModifySpec { task_id:     { self.id.clone() },
+                                              transaction: { transaction },
+                                              instances:   { self.engine_fixed_instance_routing() },
+                                              media_ready: { self.engine_media_paths() }, };
+
+        self.send_engine_command(cmd, ctx);
+    }
+
+    /// Keep the connection values set by a spec modification, group gains are applied on top of them
+    pub(crate) fn remember_connection_faders(&mut self, modify_spec: &[ModifyTaskSpec]) {
+        let mut changed = false;
+
+        for modification in modify_spec {
+            if let ModifyTaskSpec::SetConnectionParameterValues { connection_id, values } = modification {
+                changed = true;
+                let fader = self.connection_faders
+                                .entry(connection_id.clone())
+                                .or_insert(ConnectionValues { volume: None,
+                                                              pan:    None, });
+                if values.volume.is_some() {
+                    fader.volume = values.volume;
+                }
+                if values.pan.is_some() {
+                    fader.pan = values.pan;
+                }
+            }
+        }
+
+        let count = self.connection_faders.len();
+        let connections = &self.spec.connections;
+        self.connection_faders.retain(|id, _| connections.contains_key(id));
+
+        if changed || self.connection_faders.len() != count {
+            self.issue_system_async(NotifyTaskConnectionFaders { task_id: { self.id.clone() },
+                                                                 faders:  { self.connection_faders.clone() }, });
+        }
+    }
+
+    pub(crate) fn has_connection_levels(&self) -> bool {
+        !self.track_groups.is_empty() || !self.connection_faders.is_empty()
+    }
+}
+
+impl Handler<NotifyTaskTrackGroups> for TaskActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskTrackGroups, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id != self.id || msg.track_groups == self.track_groups {
+            return;
+        }
+
+        self.track_groups = msg.track_groups;
+        self.set_engine_connection_levels(ctx);
+    }
+}
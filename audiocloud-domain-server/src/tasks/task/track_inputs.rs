@@ -0,0 +1,324 @@
+use actix::{ActorFutureExt, Context, ContextFutureSpawner, Handler, WrapFuture};
+use tracing::*;
+
+use audiocloud_api::PlayId;
+
+use crate::nats;
+use crate::tasks::engine_ext::{engine_ext_command_subject, EngineExtCommand};
+use crate::tasks::task::TaskActor;
+use crate::tasks::{
+    NotifyStreamQuality, NotifyTaskEnvelopes, NotifyTaskLatencyProfile, NotifyTaskLeadIn, NotifyTaskLoudnessTarget,
+    NotifyTaskMediaFades, NotifyTaskMediaRates, NotifyTaskMonitor, NotifyTaskPlaylist, NotifyTaskRecording,
+    NotifyTaskStreamCodec, NotifyTaskTempoMap, NotifyTaskTrackInputs, TaskStreamCodec,
+};
+
+impl TaskActor {
+    /// Tell the engine which tracks record from hardware inputs, engines keep them across spec changes
+    pub(crate) fn set_engine_track_inputs(&mut self, ctx: &mut Context<Self>) {
+        let cmd = EngineExtCommand::SetTrackInputs { task_id: { self.id.clone() },
+                                                     inputs:  { self.track_inputs.clone() }, };
+
+        self.send_engine_ext_command(cmd, ctx);
+    }
+
+    /// Arm or disarm recording of the tracks with hardware inputs on the engine
+    pub(crate) fn set_engine_recording(&mut self, ctx: &mut Context<Self>) {
+        let cmd = EngineExtCommand::SetRecording { task_id:     { self.id.clone() },
+                                                   armed:       { self.recording.armed },
+                                                   loop_record: { self.recording.loop_record },
+                                                   punch:       { self.recording.punch.clone() }, };
+
+        self.send_engine_ext_command(cmd, ctx);
+    }
+
+    /// Tell the engine how to lead into the following plays
+    pub(crate) fn set_engine_lead_in(&mut self, ctx: &mut Context<Self>) {
+        let cmd = EngineExtCommand::SetLeadIn { task_id: { self.id.clone() },
+                                                lead_in: { self.lead_in }, };
+
+        self.send_engine_ext_command(cmd, ctx);
+    }
+
+    /// Tell the engine how to encode the following plays
+    pub(crate) fn set_engine_latency_profile(&mut self, ctx: &mut Context<Self>) {
+        let cmd = EngineExtCommand::SetLatencyProfile { task_id: { self.id.clone() },
+                                                        profile: { self.latency_profile }, };
+
+        self.send_engine_ext_command(cmd, ctx);
+    }
+
+    /// Tell the engine which codec to stream the following plays with
+    pub(crate) fn set_engine_stream_codec(&mut self, ctx: &mut Context<Self>) {
+        let cmd = EngineExtCommand::SetStreamCodec { task_id: { self.id.clone() },
+                                                     codec:   { self.stream_codec }, };
+
+        self.send_engine_ext_command(cmd, ctx);
+    }
+
+    /// Tell the engine the loudness to normalize the following plays to
+    pub(crate) fn set_engine_loudness_target(&mut self, ctx: &mut Context<Self>) {
+        let cmd = EngineExtCommand::SetLoudnessTarget { task_id:     { self.id.clone() },
+                                                        target_lufs: { self.loudness_target.target_lufs }, };
+
+        self.send_engine_ext_command(cmd, ctx);
+    }
+
+    /// Tell the engine how finely and how often to analyze the spectrum of the following plays
+    pub(crate) fn set_engine_spectrum(&mut self, ctx: &mut Context<Self>) {
+        let cmd = EngineExtCommand::SetSpectrum { task_id:  { self.id.clone() },
+                                                  spectrum: { self.opts.spectrum() }, };
+
+        self.send_engine_ext_command(cmd, ctx);
+    }
+
+    /// Ask the engine to stream a play at another bitrate, only Opus streams can change it
+    pub(crate) fn set_engine_stream_quality(&mut self, play_id: PlayId, bitrate: u32, ctx: &mut Context<Self>) {
+        let cmd = EngineExtCommand::SetStreamQuality { task_id: { self.id.clone() },
+                                                       play_id: { play_id },
+                                                       bitrate: { bitrate }, };
+
+        self.send_engine_ext_command(cmd, ctx);
+    }
+
+    /// Write the tempo map into the engine project
+    pub(crate) fn set_engine_tempo_map(&mut self, ctx: &mut Context<Self>) {
+        let cmd = EngineExtCommand::SetTempoMap { task_id:   { self.id.clone() },
+                                                  tempo_map: { self.tempo_map.clone() }, };
+
+        self.send_engine_ext_command(cmd, ctx);
+    }
+
+    /// Tell the engine which segments the following plays step through
+    pub(crate) fn set_engine_playlist(&mut self, ctx: &mut Context<Self>) {
+        let cmd = EngineExtCommand::SetPlaylist { task_id:  { self.id.clone() },
+                                                  playlist: { self.playlist.clone() }, };
+
+        self.send_engine_ext_command(cmd, ctx);
+    }
+
+    /// Tell the engine which parameters to automate, replacing the envelopes written before
+    pub(crate) fn set_engine_envelopes(&mut self, ctx: &mut Context<Self>) {
+        let cmd = EngineExtCommand::SetEnvelopes { task_id:   { self.id.clone() },
+                                                   envelopes: { self.envelopes.clone() }, };
+
+        self.send_engine_ext_command(cmd, ctx);
+    }
+
+    /// Tell the engine the fades of the track media, the media items are written again with them
+    pub(crate) fn set_engine_media_fades(&mut self, ctx: &mut Context<Self>) {
+        let cmd = EngineExtCommand::SetMediaFades { task_id: { self.id.clone() },
+                                                    fades:   { self.fades.clone() }, };
+
+        self.send_engine_ext_command(cmd, ctx);
+    }
+
+    /// Tell the engine the playback rates of the track media, it reports the lengths they take on the timeline back
+    pub(crate) fn set_engine_media_rates(&mut self, ctx: &mut Context<Self>) {
+        let cmd = EngineExtCommand::SetMediaRates { task_id: { self.id.clone() },
+                                                    rates:   { self.media_rates.clone() }, };
+
+        self.send_engine_ext_command(cmd, ctx);
+    }
+
+    /// Tell the engine the gain of the streamed audio and the mixer to monitor, as the monitor controller sets them
+    pub(crate) fn set_engine_monitor(&mut self, ctx: &mut Context<Self>) {
+        let cmd = EngineExtCommand::SetMonitor { task_id: { self.id.clone() },
+                                                 gain:    { self.monitor.gain() },
+                                                 source:  { self.monitor.source.clone() }, };
+
+        self.send_engine_ext_command(cmd, ctx);
+    }
+
+    pub(crate) fn send_engine_ext_command(&mut self, cmd: EngineExtCommand, ctx: &mut Context<Self>) {
+        let subject = engine_ext_command_subject(&self.engine_command_subject);
+        let sent_settings = cmd.is_task_setting().then(|| self.settings());
+
+        nats::request_raw_msgpack(subject, cmd).into_actor(self)
+                                               .map(move |res, actor, ctx| {
+                                                   if let (Some(settings), Ok(Ok(()))) = (sent_settings, &res) {
+                                                       actor.engine_settings = Some(settings);
+                                                   }
+
+                                                   Self::handle_engine_ext_response(res, actor, ctx)
+                                               })
+                                               .spawn(ctx);
+    }
+
+    fn handle_engine_ext_response(res: anyhow::Result<Result<(), String>>, actor: &mut Self, ctx: &mut Context<Self>) {
+        match res {
+            Ok(Err(error)) => error!(%error, id = %actor.id, "Engine extension command failed"),
+            Err(error) => error!(%error, id = %actor.id, "Failed to deliver extension command to engine"),
+            _ => {}
+        }
+    }
+}
+
+impl Handler<NotifyTaskTrackInputs> for TaskActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskTrackInputs, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id != self.id || msg.inputs == self.track_inputs {
+            return;
+        }
+
+        self.track_inputs = msg.inputs;
+        self.set_engine_track_inputs(ctx);
+    }
+}
+
+impl Handler<NotifyTaskRecording> for TaskActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskRecording, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id != self.id || msg.recording == self.recording {
+            return;
+        }
+
+        self.recording = msg.recording;
+        self.set_engine_recording(ctx);
+    }
+}
+
+impl Handler<NotifyTaskLeadIn> for TaskActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskLeadIn, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id != self.id || msg.lead_in == self.lead_in {
+            return;
+        }
+
+        self.lead_in = msg.lead_in;
+        self.set_engine_lead_in(ctx);
+    }
+}
+
+impl Handler<NotifyTaskLatencyProfile> for TaskActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskLatencyProfile, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id != self.id || msg.profile == self.latency_profile {
+            return;
+        }
+
+        self.latency_profile = msg.profile;
+        self.set_engine_latency_profile(ctx);
+    }
+}
+
+impl Handler<NotifyTaskStreamCodec> for TaskActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskStreamCodec, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id != self.id || msg.codec == self.stream_codec {
+            return;
+        }
+
+        self.stream_codec = msg.codec;
+        self.set_engine_stream_codec(ctx);
+    }
+}
+
+impl Handler<NotifyTaskLoudnessTarget> for TaskActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskLoudnessTarget, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id != self.id || msg.target == self.loudness_target {
+            return;
+        }
+
+        self.loudness_target = msg.target;
+        self.set_engine_loudness_target(ctx);
+    }
+}
+
+impl Handler<NotifyStreamQuality> for TaskActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyStreamQuality, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id != self.id || !matches!(self.stream_codec, TaskStreamCodec::Opus { .. }) {
+            return;
+        }
+
+        debug!(id = %self.id, play_id = %msg.play_id, bitrate = msg.bitrate, "Adapting stream bitrate");
+        self.set_engine_stream_quality(msg.play_id, msg.bitrate, ctx);
+    }
+}
+
+impl Handler<NotifyTaskTempoMap> for TaskActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskTempoMap, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id != self.id || msg.tempo_map == self.tempo_map {
+            return;
+        }
+
+        self.tempo_map = msg.tempo_map;
+        self.set_engine_tempo_map(ctx);
+    }
+}
+
+impl Handler<NotifyTaskPlaylist> for TaskActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskPlaylist, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id != self.id || msg.playlist == self.playlist {
+            return;
+        }
+
+        self.playlist = msg.playlist;
+        self.playlist_index = None;
+        self.set_engine_playlist(ctx);
+    }
+}
+
+impl Handler<NotifyTaskEnvelopes> for TaskActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskEnvelopes, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id != self.id || msg.envelopes == self.envelopes {
+            return;
+        }
+
+        self.envelopes = msg.envelopes;
+        self.set_engine_envelopes(ctx);
+    }
+}
+
+impl Handler<NotifyTaskMediaFades> for TaskActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskMediaFades, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id != self.id || msg.fades == self.fades {
+            return;
+        }
+
+        self.fades = msg.fades;
+        self.set_engine_media_fades(ctx);
+    }
+}
+
+impl Handler<NotifyTaskMediaRates> for TaskActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskMediaRates, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id != self.id || msg.rates == self.media_rates {
+            return;
+        }
+
+        self.media_rates = msg.rates;
+        self.set_engine_media_rates(ctx);
+    }
+}
+
+impl Handler<NotifyTaskMonitor> for TaskActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyTaskMonitor, ctx: &mut Self::Context) -> Self::Result {
+        if msg.task_id != self.id || msg.monitor == self.monitor {
+            return;
+        }
+
+        self.monitor = msg.monitor;
+        self.set_engine_monitor(ctx);
+    }
+}
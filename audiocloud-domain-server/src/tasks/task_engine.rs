@@ -13,9 +13,39 @@ pub struct TaskEngine {
     instances_are_ready: Timestamped<bool>,
     media_is_ready:      Timestamped<bool>,
     commands:            VecDeque<Timestamped<EngineCommand>>,
+    pause:               PlayPause,
     version:             u64,
 }
 
+/// The play whose engine transport stands still while it stays desired and actually playing
+#[derive(Clone, Debug, Default)]
+pub struct PlayPause {
+    paused: Option<PlayId>,
+}
+
+impl PlayPause {
+    pub fn set(&mut self, play_id: &PlayId, paused: bool) {
+        self.paused = if paused { Some(play_id.clone()) } else { None };
+    }
+
+    pub fn is_paused(&self, play_id: &PlayId) -> bool {
+        self.paused.as_ref() == Some(play_id)
+    }
+
+    /// Anything newly desired, another play, a render or stopping, starts unpaused
+    pub fn desired_changed(&mut self) {
+        self.paused = None;
+    }
+
+    /// The engine reported what it actually does, `playing` when it plays. Re-reports of the paused play keep it
+    /// paused, anything else ends the pause
+    pub fn actual_changed(&mut self, playing: Option<&PlayId>) {
+        if self.paused.as_ref() != playing {
+            self.paused = None;
+        }
+    }
+}
+
 impl TaskEngine {
     pub fn new(id: AppTaskId) -> Self {
         Self { id:                  { id },
@@ -25,6 +55,7 @@ impl TaskEngine {
                instances_are_ready: { Default::default() },
                media_is_ready:      { Default::default() },
                commands:            { Default::default() },
+               pause:               { Default::default() },
                version:             { 0 }, }
     }
 
@@ -39,6 +70,7 @@ impl TaskEngine {
     pub fn set_desired_state(&mut self, desired: DesiredTaskPlayState) -> u64 {
         if self.desired_play_state.value() != &desired {
             self.desired_play_state = Timestamped::new(desired);
+            self.pause.desired_changed();
             self.tracker.reset();
             self.version + 1
         } else {
@@ -55,6 +87,10 @@ impl TaskEngine {
     }
 
     pub fn update(&mut self) -> Option<EngineCommand> {
+        if let Some(cmd) = self.commands.pop_front() {
+            return Some(cmd.value().clone());
+        }
+
         if self.actual_play_state
                .value()
                .satisfies(self.desired_play_state.value())
@@ -92,6 +128,13 @@ impl TaskEngine {
     }
 
     pub fn set_actual_state(&mut self, actual: TaskPlayState) {
+        let playing = match &actual {
+            TaskPlayState::Playing(play) => Some(&play.play_id),
+            _ => None,
+        };
+
+        self.pause.actual_changed(playing);
+
         self.actual_play_state = Timestamped::new(actual);
         self.tracker.reset();
     }
@@ -116,6 +159,36 @@ impl TaskEngine {
         }
     }
 
+    /// A paused play stays desired and actually playing, only the engine transport stands still
+    pub fn set_paused(&mut self, play_id: &PlayId, paused: bool) {
+        self.pause.set(play_id, paused);
+    }
+
+    pub fn is_paused(&self, play_id: &PlayId) -> bool {
+        self.pause.is_paused(play_id)
+    }
+
+    /// The engine started afresh and forgot the session, with what it played or rendered. Returns the play or render
+    /// that was cut short, which is not retried
+    pub fn on_engine_started(&mut self) -> Option<TaskPlayState> {
+        let interrupted = match (self.desired_play_state.value(), self.actual_play_state.value()) {
+            (_, TaskPlayState::Playing(play)) | (DesiredTaskPlayState::Play(play), _) => {
+                Some(TaskPlayState::Playing(play.clone()))
+            }
+            (_, TaskPlayState::Rendering(render)) | (DesiredTaskPlayState::Render(render), _) => {
+                Some(TaskPlayState::Rendering(render.clone()))
+            }
+            _ => None,
+        };
+
+        // commands queued for the old session are superseded by setting the spec again
+        self.commands.clear();
+        self.set_desired_state(DesiredTaskPlayState::Stopped);
+        self.set_actual_stopped();
+
+        interrupted
+    }
+
     pub fn should_be_playing(&self, play_id: &PlayId) -> bool {
         matches!(self.desired_play_state.value(), DesiredTaskPlayState::Play(play) if &play.play_id == play_id)
     }
@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Tempo and time signature of the task from `time` seconds on, until the next change
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TempoChange {
+    pub time:        f64,
+    /// Quarter notes per minute
+    pub bpm:         f64,
+    pub numerator:   u32,
+    pub denominator: u32,
+}
+
+impl Default for TempoChange {
+    /// Tempo of the engine project before the first change
+    fn default() -> Self {
+        Self { time:        { 0.0 },
+               bpm:         { 120.0 },
+               numerator:   { 4 },
+               denominator: { 4 }, }
+    }
+}
+
+impl TempoChange {
+    pub fn bar_length(&self) -> f64 {
+        self.numerator as f64 * (60.0 / self.bpm) * (4.0 / self.denominator as f64)
+    }
+
+    fn same_time_signature(&self, other: &TempoChange) -> bool {
+        self.numerator == other.numerator && self.denominator == other.denominator
+    }
+}
+
+/// Tempo changes and time signatures of a task, in time order. Before the first change the task is at 120 BPM in 4/4
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskTempoMap {
+    #[serde(default)]
+    pub changes: Vec<TempoChange>,
+}
+
+/// Musical position on the tempo map, bars and beats count from 1 and beats are in units of the time signature
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct BarBeat {
+    pub bar:      u32,
+    pub beat:     u32,
+    /// Part of the beat elapsed, from 0 up to 1
+    pub fraction: f64,
+}
+
+impl TaskTempoMap {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Check that the changes are in time order and describe tempos and time signatures the engine can play
+    pub fn validate(&self) -> Result<(), String> {
+        let mut previous = None;
+
+        for (index, change) in self.changes.iter().enumerate() {
+            if !change.time.is_finite() || change.time < 0.0 {
+                return Err(format!("Tempo change {index} is at invalid time {}", change.time));
+            }
+            if matches!(previous, Some(previous) if change.time <= previous) {
+                return Err(format!("Tempo change {index} at {} is not after the change before it",
+                                   change.time));
+            }
+            if !change.bpm.is_finite() || change.bpm <= 0.0 {
+                return Err(format!("Tempo change {index} has invalid tempo {}", change.bpm));
+            }
+            if change.numerator == 0 || !change.denominator.is_power_of_two() || change.denominator > 64 {
+                return Err(format!("Tempo change {index} has invalid time signature {}/{}",
+                                   change.numerator, change.denominator));
+            }
+
+            previous = Some(change.time);
+        }
+
+        Ok(())
+    }
+
+    /// Bar and beat at `time` seconds into the task. A time signature change starts a new bar, like it does in REAPER
+    pub fn bar_beat_at(&self, time: f64) -> BarBeat {
+        let time = time.max(0.0);
+        let mut current = TempoChange::default();
+        let mut bars = 0.0;
+
+        for change in self.changes.iter().take_while(|change| change.time <= time) {
+            bars += (change.time - current.time) / current.bar_length();
+            if !change.same_time_signature(&current) {
+                bars = (bars - BAR_EPSILON).ceil();
+            }
+
+            current = *change;
+        }
+
+        // positions right on a bar line belong to the bar that starts there
+        bars += (time - current.time) / current.bar_length() + BAR_EPSILON;
+
+        let bar = bars.floor();
+        let beats = (bars - bar) * current.numerator as f64;
+        let beat = beats.floor();
+
+        BarBeat { bar:      { bar as u32 + 1 },
+                  beat:     { beat as u32 + 1 },
+                  fraction: { (beats - beat).clamp(0.0, 1.0) }, }
+    }
+}
+
+const BAR_EPSILON: f64 = 1e-9;
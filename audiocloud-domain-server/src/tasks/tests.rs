@@ -0,0 +1,1005 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{TimeZone, Utc};
+use clap::Parser;
+use serde_json::{json, Value};
+
+use audiocloud_api::cloud::domains::FixedInstanceRouting;
+use audiocloud_api::common::task::{ConnectionValues, TimeSegment};
+use audiocloud_api::domain::DomainError;
+use audiocloud_api::newtypes::{
+    DynamicInstanceNodeId, FixedInstanceNodeId, MixerNodeId, NodeConnectionId, TrackMediaId, TrackNodeId,
+};
+use audiocloud_api::{
+    AppId, AppTaskId, ClientId, FixedInstanceId, NodePadId, OutputPadId, PadMetering, PlayId, SecureKey,
+    StreamingPacket, TaskId, Timestamp,
+};
+
+use crate::tasks::engine_ext::{
+    json_hash, validate_render_formats, EngineResources, EngineSpectrumSettings, EngineTestTone, EngineTestToneInput,
+    EngineTestToneResult, PadLoudness, RenderFormat,
+};
+use crate::tasks::meter_capture::{read_meter_capture, CapturedMeter, MeterCaptureWriter};
+use crate::tasks::null_test::compare_renders;
+use crate::tasks::recall_sheet::recall_values;
+use crate::tasks::render_normalization::{loudnorm_filter, parse_loudnorm_report, TaskRenderNormalization};
+use crate::tasks::stream_continuity::{StreamContinuity, StreamStep};
+use crate::tasks::stream_recorder::{read_segments, PlayRecording};
+use crate::tasks::supervisor::TasksSupervisor;
+use crate::tasks::task::{SafeModeState, TaskActor};
+use crate::tasks::task_engine::{PlayPause, TaskEngine};
+use crate::tasks::watermark::watermark_payload;
+use crate::tasks::{
+    plan_routing_chains, BarBeat, ClickTempo, DeleteTask, EnvelopePoint, EnvelopeShape, EnvelopeTarget, FadeShape,
+    MediaFades, MediaRate, RecallInstance, StretchMode, TaskClick, TaskEnvelope, TaskEnvelopes, TaskLatencyProfile,
+    TaskLoudnessTarget, TaskMediaFades, TaskMediaRates, TaskMonitor, TaskOpts, TaskPlaylist, TaskPunchRegion,
+    TaskRecallSheet, TaskRecording, TaskSpecElements, TaskStreamCodec, TaskTempoMap, TaskTrackGroups, TaskWatermark,
+    TempoChange, TrackGroup, WatermarkMode,
+};
+use crate::DomainSecurity;
+
+fn change(time: f64, bpm: f64, numerator: u32, denominator: u32) -> TempoChange {
+    TempoChange { time:        { time },
+                  bpm:         { bpm },
+                  numerator:   { numerator },
+                  denominator: { denominator }, }
+}
+
+fn bar_beat(map: &TaskTempoMap, time: f64) -> (u32, u32) {
+    let BarBeat { bar, beat, .. } = map.bar_beat_at(time);
+    (bar, beat)
+}
+
+#[test]
+fn test_empty_tempo_map_is_120_bpm_in_four_four() {
+    let map = TaskTempoMap::default();
+
+    assert_eq!(bar_beat(&map, 0.0), (1, 1));
+    assert_eq!(bar_beat(&map, 0.5), (1, 2));
+    assert_eq!(bar_beat(&map, 2.0), (2, 1));
+    assert_eq!(bar_beat(&map, 7.75), (4, 4));
+
+    let position = map.bar_beat_at(0.25);
+    assert!((position.fraction - 0.5).abs() < 1e-6);
+}
+
+#[test]
+fn test_tempo_changes_keep_counting_bars() {
+    // two bars of 4/4 at 120, then 60 BPM
+    let map = TaskTempoMap { changes: vec![change(4.0, 60.0, 4, 4)], };
+
+    assert_eq!(bar_beat(&map, 3.5), (2, 4));
+    assert_eq!(bar_beat(&map, 4.0), (3, 1));
+    assert_eq!(bar_beat(&map, 5.0), (3, 2));
+    assert_eq!(bar_beat(&map, 8.0), (4, 1));
+}
+
+#[test]
+fn test_time_signature_change_starts_a_new_bar() {
+    // one and a half bars of 4/4, then 6/8 where eighth notes last 0.25 seconds at 120 BPM
+    let map = TaskTempoMap { changes: vec![change(3.0, 120.0, 6, 8)], };
+
+    assert_eq!(bar_beat(&map, 3.0), (3, 1));
+    assert_eq!(bar_beat(&map, 3.25), (3, 2));
+    assert_eq!(bar_beat(&map, 4.5), (4, 1));
+}
+
+#[test]
+fn test_tempo_map_validation() {
+    assert!(TaskTempoMap::default().validate().is_ok());
+    assert!(TaskTempoMap { changes: vec![change(0.0, 90.0, 7, 8), change(10.0, 120.0, 4, 4)], }.validate()
+                                                                                               .is_ok());
+
+    assert!(TaskTempoMap { changes: vec![change(10.0, 90.0, 4, 4), change(5.0, 120.0, 4, 4)], }.validate()
+                                                                                               .is_err());
+    assert!(TaskTempoMap { changes: vec![change(0.0, 0.0, 4, 4)], }.validate()
+                                                                   .is_err());
+    assert!(TaskTempoMap { changes: vec![change(0.0, 120.0, 4, 3)], }.validate()
+                                                                     .is_err());
+    assert!(TaskTempoMap { changes: vec![change(-1.0, 120.0, 4, 4)], }.validate()
+                                                                      .is_err());
+}
+
+#[test]
+fn test_test_tone_defaults_and_validation() {
+    let tone: EngineTestTone = serde_json::from_str(r#"{"output_channel": 4, "input_channels": [4, 5]}"#).unwrap();
+    assert_eq!(tone.frequency, 1_000.0);
+    assert_eq!(tone.level_db, -18.0);
+    assert!(tone.validate().is_ok());
+
+    assert!(EngineTestTone { input_channels: vec![],
+                             ..tone.clone() }.validate()
+                                             .is_err());
+    assert!(EngineTestTone { level_db: 0.0,
+                             ..tone.clone() }.validate()
+                                             .is_err());
+    assert!(EngineTestTone { frequency: 10.0,
+                             ..tone.clone() }.validate()
+                                             .is_err());
+    assert!(EngineTestTone { duration_ms: 60_000,
+                             ..tone }.validate()
+                                     .is_err());
+}
+
+fn stereo_in_mono_out() -> HashMap<FixedInstanceId, FixedInstanceRouting> {
+    let instance_id = FixedInstanceId::new("distopik".to_owned(), "dual1084".to_owned(), "1".to_owned());
+    let routing = FixedInstanceRouting { send_count:     { 2 },
+                                         send_channel:   { 4 },
+                                         return_count:   { 1 },
+                                         return_channel: { 8 }, };
+
+    HashMap::from([(instance_id, routing)])
+}
+
+fn tone_result(peaks: &[(usize, Option<f64>)]) -> EngineTestToneResult {
+    EngineTestToneResult { output_channel: { 0 },
+                           inputs:         {
+                               peaks.iter()
+                                    .map(|(channel, peak_db)| EngineTestToneInput { channel: { *channel },
+                                                                                    peak_db: { *peak_db }, })
+                                    .collect()
+                           }, }
+}
+
+#[test]
+fn test_routing_chains_per_sent_channel() {
+    let chains = plan_routing_chains(&stereo_in_mono_out());
+
+    assert_eq!(chains.len(), 2);
+    assert_eq!((chains[0].send_channel, chains[0].expected_return), (4, 8));
+    assert_eq!((chains[1].send_channel, chains[1].expected_return), (5, 8));
+    assert_eq!(chains[1].return_channels, vec![8]);
+}
+
+#[test]
+fn test_routing_chain_diagnosis() {
+    let mut routing = stereo_in_mono_out();
+    for routing in routing.values_mut() {
+        routing.return_count = 2;
+    }
+
+    let chain = &plan_routing_chains(&routing)[1];
+
+    let check = chain.evaluate(-18.0, 6.0, Ok(tone_result(&[(8, None), (9, Some(-20.0))])));
+    assert!(check.passed);
+    assert_eq!(check.return_peak_db, Some(-20.0));
+
+    let check = chain.evaluate(-18.0, 6.0, Ok(tone_result(&[(8, None), (9, None)])));
+    assert!(!check.passed);
+    assert!(check.diagnosis.contains("output 5"));
+
+    let check = chain.evaluate(-18.0, 6.0, Ok(tone_result(&[(8, Some(-18.0)), (9, None)])));
+    assert!(!check.passed);
+    assert!(check.diagnosis.contains("crossed"));
+
+    let check = chain.evaluate(-18.0, 6.0, Ok(tone_result(&[(8, None), (9, Some(-40.0))])));
+    assert!(!check.passed);
+    assert_eq!(check.return_peak_db, Some(-40.0));
+
+    let check = chain.evaluate(-18.0, 6.0, Err("Engine is busy".to_owned()));
+    assert!(!check.passed);
+    assert!(check.diagnosis.contains("Engine is busy"));
+}
+
+#[derive(Parser)]
+struct TestOpts {
+    #[clap(flatten)]
+    tasks: TaskOpts,
+}
+
+#[test]
+fn test_latency_profile_packet_limits() {
+    let opts = TestOpts::parse_from(["test",
+                                     "--max-packet-age-ms=250",
+                                     "--max-packet-audio-frames=4",
+                                     "--low-latency-max-packet-age-ms=20",
+                                     "--low-latency-max-packet-audio-frames=0"]).tasks;
+
+    assert_eq!(TaskLatencyProfile::default(), TaskLatencyProfile::Stable);
+    assert_eq!(TaskLatencyProfile::Stable.max_packet_age_ms(&opts), 250);
+    assert_eq!(TaskLatencyProfile::Stable.max_packet_audio_frames(&opts), 4);
+    assert_eq!(TaskLatencyProfile::LowLatency.max_packet_age_ms(&opts), 20);
+    // a packet is never held back waiting for audio that would not fit
+    assert_eq!(TaskLatencyProfile::LowLatency.max_packet_audio_frames(&opts), 1);
+
+    // the low latency limits never exceed the global ones
+    let opts = TestOpts::parse_from(["test", "--max-packet-age-ms=10", "--low-latency-max-packet-age-ms=20"]).tasks;
+    assert_eq!(TaskLatencyProfile::LowLatency.max_packet_age_ms(&opts), 10);
+
+    let profile = serde_json::from_str::<TaskLatencyProfile>(r#""low_latency""#).unwrap();
+    assert_eq!(profile, TaskLatencyProfile::LowLatency);
+}
+
+#[test]
+fn test_stream_codec_parses_and_validates_opus_settings() {
+    assert_eq!(TaskStreamCodec::default(), TaskStreamCodec::Flac);
+    assert_eq!(serde_json::from_str::<TaskStreamCodec>(r#"{"codec": "flac"}"#).unwrap(),
+               TaskStreamCodec::Flac);
+
+    let opus = serde_json::from_str::<TaskStreamCodec>(r#"{"codec": "opus", "bitrate": 96000, "frame_size": 960}"#);
+    let opus = opus.expect("opus codec parses");
+    assert_eq!(opus,
+               TaskStreamCodec::Opus { bitrate:    96000,
+                                       frame_size: 960, });
+    assert!(opus.validate().is_ok());
+
+    let odd_frames = TaskStreamCodec::Opus { bitrate:    96000,
+                                             frame_size: 1000, };
+    assert!(odd_frames.validate().is_err(), "Opus frames are 2.5 to 60 ms long");
+
+    let too_fast = TaskStreamCodec::Opus { bitrate:    1_000_000,
+                                           frame_size: 960, };
+    assert!(too_fast.validate().is_err());
+}
+
+#[test]
+fn test_stream_continuity_across_loop_wraps() {
+    let mut continuity = StreamContinuity::default();
+
+    assert_eq!(continuity.push(0, 4096, 1.0), StreamStep::Start);
+    assert_eq!(continuity.push(4096, 4096, 1.1), StreamStep::Continuous);
+    // the loop wrapped, the timeline went back but the stream kept counting
+    assert_eq!(continuity.push(8192, 4096, 0.5), StreamStep::LoopWrap);
+    assert_eq!(continuity.push(12288, 4096, 0.6), StreamStep::Continuous);
+    assert_eq!(continuity.passes, 1);
+
+    assert_eq!(continuity.push(20480, 4096, 0.8), StreamStep::Gap { missing: 4096 });
+    assert_eq!(continuity.gaps, 1);
+
+    // audio delivered twice does not move the stream back
+    assert_eq!(continuity.push(20480, 4096, 0.8), StreamStep::Repeated);
+    assert_eq!(continuity.push(24576, 4096, 0.9), StreamStep::Continuous);
+}
+
+#[test]
+fn test_stream_continuity_after_seek_is_not_a_loop_wrap() {
+    let mut continuity = StreamContinuity::default();
+
+    assert_eq!(continuity.push(0, 4096, 10.0), StreamStep::Start);
+    continuity.seek();
+
+    // the play went back to the start, the stream kept counting
+    assert_eq!(continuity.push(4096, 4096, 0.0), StreamStep::Continuous);
+    assert_eq!(continuity.passes, 0);
+    assert_eq!(continuity.push(8192, 4096, 0.1), StreamStep::Continuous);
+}
+
+fn playlist(segments: &[(f64, f64)]) -> TaskPlaylist {
+    TaskPlaylist { segments: segments.iter()
+                                     .map(|(start, length)| TimeSegment { start:  *start,
+                                                                          length: *length, })
+                                     .collect(), }
+}
+
+#[test]
+fn test_playlist_validation() {
+    assert!(playlist(&[]).validate().is_ok());
+    assert!(playlist(&[(0.0, 10.0), (30.0, 5.0), (0.0, 10.0)]).validate().is_ok());
+    assert!(playlist(&[(-1.0, 10.0)]).validate().is_err());
+    assert!(playlist(&[(0.0, 0.0)]).validate().is_err());
+    assert!(playlist(&[(0.0, f64::NAN)]).validate().is_err());
+}
+
+#[test]
+fn test_playlist_segment_index_follows_the_order() {
+    // the first segment plays again at the end
+    let playlist = playlist(&[(0.0, 10.0), (30.0, 5.0), (0.0, 10.0)]);
+
+    assert_eq!(playlist.segment_index_at(None, 2.0), Some(0));
+    assert_eq!(playlist.segment_index_at(Some(0), 31.0), Some(1));
+    assert_eq!(playlist.segment_index_at(Some(1), 1.0), Some(2));
+    assert_eq!(playlist.segment_index_at(Some(2), 9.0), Some(2));
+    // a looping play starts over after the last segment
+    assert_eq!(playlist.segment_index_at(Some(2), 32.0), Some(1));
+    assert_eq!(playlist.segment_index_at(Some(1), 20.0), None);
+}
+
+fn punch_recording(start: f64, length: f64) -> TaskRecording {
+    let punch = TaskPunchRegion { segment: { TimeSegment { start, length } },
+                                  tracks:  { HashSet::new() }, };
+
+    TaskRecording { armed:       { true },
+                    loop_record: { false },
+                    punch:       { Some(punch) }, }
+}
+
+#[test]
+fn test_recording_punch_region() {
+    let recording: TaskRecording = serde_json::from_str(r#"{"armed": true}"#).unwrap();
+    assert_eq!(recording.punch, None);
+    assert!(recording.validate().is_ok());
+
+    let json = r#"{"armed": true, "punch": {"segment": {"start": 4.0, "length": 2.0}, "tracks": ["vox"]}}"#;
+    let recording: TaskRecording = serde_json::from_str(json).unwrap();
+    assert_eq!(recording.punch.as_ref().map(|punch| punch.tracks.clone()),
+               Some(HashSet::from([TrackNodeId::new("vox".to_string())])));
+    assert!(recording.validate().is_ok());
+
+    assert!(punch_recording(4.0, 2.0).validate().is_ok());
+    assert!(punch_recording(-1.0, 2.0).validate().is_err());
+    assert!(punch_recording(4.0, 0.0).validate().is_err());
+    assert!(punch_recording(4.0, f64::INFINITY).validate().is_err());
+}
+
+fn envelope(target: EnvelopeTarget, points: &[(f64, f64)]) -> TaskEnvelope {
+    TaskEnvelope { target: { target },
+                   points: {
+                       points.iter()
+                             .map(|(time, value)| EnvelopePoint { time:  { *time },
+                                                                  value: { *value },
+                                                                  shape: { EnvelopeShape::Linear }, })
+                             .collect()
+                   }, }
+}
+
+#[test]
+fn test_envelope_validation() {
+    let connection_id = NodeConnectionId::new("kick_to_mix".to_string());
+    let volume = EnvelopeTarget::ConnectionVolume { connection_id: connection_id.clone(), };
+    let pan = EnvelopeTarget::ConnectionPan { connection_id: connection_id.clone(), };
+
+    let envelopes = |envelopes: Vec<TaskEnvelope>| TaskEnvelopes { envelopes };
+
+    assert!(TaskEnvelopes::default().validate().is_ok());
+    assert!(envelopes(vec![envelope(volume.clone(), &[(0.0, 1.0), (2.0, 0.5), (2.0, 2.0)]),
+                           envelope(pan.clone(), &[(1.0, -1.0), (3.0, 1.0)])]).validate()
+                                                                              .is_ok());
+    // one envelope per target
+    assert!(envelopes(vec![envelope(volume.clone(), &[(0.0, 1.0)]),
+                           envelope(volume.clone(), &[(1.0, 1.0)])]).validate()
+                                                                    .is_err());
+    assert!(envelopes(vec![envelope(volume.clone(), &[])]).validate().is_err());
+    assert!(envelopes(vec![envelope(volume.clone(), &[(2.0, 1.0), (1.0, 1.0)])]).validate()
+                                                                                .is_err());
+    assert!(envelopes(vec![envelope(volume, &[(0.0, -0.1)])]).validate().is_err());
+    assert!(envelopes(vec![envelope(pan, &[(0.0, 1.5)])]).validate().is_err());
+}
+
+#[test]
+fn test_envelope_target_json() {
+    let json = r#"{"envelopes": [{
+                     "target": {"type": "dynamic_instance_parameter", "dynamic_id": "verb", "parameter": "Mix"},
+                     "points": [{"time": 0.0, "value": 0.2}, {"time": 4.0, "value": 0.8, "shape": "square"}]
+                   }]}"#;
+    let envelopes: TaskEnvelopes = serde_json::from_str(json).unwrap();
+    let envelope = &envelopes.envelopes[0];
+
+    assert_eq!(envelope.target,
+               EnvelopeTarget::DynamicInstanceParameter { dynamic_id: DynamicInstanceNodeId::new("verb".to_string()),
+                                                          parameter:  "Mix".to_string(), });
+    assert_eq!(envelope.points[0].shape, EnvelopeShape::Linear);
+    assert_eq!(envelope.points[1].shape, EnvelopeShape::Square);
+    assert!(envelopes.validate().is_ok());
+}
+
+#[test]
+fn test_media_fades_fit_within_media_segments() {
+    let verse = TrackMediaId::new("verse".to_string());
+    let segment = |start, length| TimeSegment { start, length };
+    // the verse plays 10 seconds of its file from 2 seconds in, at 30 seconds on the timeline
+    let segments = |media_id: &TrackMediaId| (media_id == &verse).then(|| (segment(2.0, 10.0), segment(30.0, 10.0)));
+
+    let fades = |fades: MediaFades| TaskMediaFades { media: HashMap::from([(verse.clone(), fades)]), };
+    let faded = |fade_in, fade_out, crossfade| {
+        fades(MediaFades { fade_in:   { fade_in },
+                           fade_out:  { fade_out },
+                           crossfade: { crossfade },
+                           shape:     { FadeShape::SCurve }, }).validate_segments(segments)
+    };
+
+    assert!(TaskMediaFades::default().validate_segments(segments).is_ok());
+    assert!(faded(4.0, 6.0, 0.0).is_ok());
+    assert!(faded(0.0, 0.0, 2.0).is_ok(),
+            "crossfades use the media before the segment");
+
+    assert!(faded(4.0, 6.5, 0.0).is_err());
+    assert!(faded(-1.0, 0.0, 0.0).is_err());
+    assert!(faded(f64::NAN, 0.0, 0.0).is_err());
+    assert!(faded(0.0, 0.0, 2.5).is_err(), "there is no handle for the crossfade");
+
+    let chorus =
+        TaskMediaFades { media: HashMap::from([(TrackMediaId::new("chorus".to_string()), Default::default())]), };
+    assert!(chorus.validate_segments(segments).is_err());
+}
+
+#[test]
+fn test_media_rates_json_and_validation() {
+    let verse = TrackMediaId::new("verse".to_string());
+    let has_media = |media_id: &TrackMediaId| media_id == &verse;
+
+    let rates = serde_json::from_str::<TaskMediaRates>(r#"{"media": {"verse": {"rate": 0.5}}}"#).unwrap();
+    assert_eq!(rates.media.get(&verse),
+               Some(&MediaRate { rate:    0.5,
+                                 stretch: StretchMode::Balanced, }));
+    assert!(rates.validate_media(has_media).is_ok());
+
+    let rated = |rate| TaskMediaRates { media: HashMap::from([(verse.clone(),
+                                                               MediaRate { rate,
+                                                                           ..Default::default() })]), };
+    assert!(rated(4.0).validate_media(has_media).is_ok());
+    assert!(rated(4.5).validate_media(has_media).is_err());
+    assert!(rated(0.0).validate_media(has_media).is_err());
+    assert!(rated(f64::NAN).validate_media(has_media).is_err());
+
+    let chorus =
+        TaskMediaRates { media: HashMap::from([(TrackMediaId::new("chorus".to_string()), MediaRate::default())]), };
+    assert!(chorus.validate_media(has_media).is_err());
+}
+
+#[test]
+fn test_monitor_gain_and_validation() {
+    let approx = |a: f64, b: f64| (a - b).abs() < 1e-9;
+    let monitor = TaskMonitor { level_db: -6.0,
+                                ..Default::default() };
+
+    assert!(approx(TaskMonitor::default().gain(), 1.0));
+    assert!(approx(monitor.gain(), 10f64.powf(-6.0 / 20.0)));
+    assert!(approx(TaskMonitor { dim: true,
+                                 ..monitor.clone() }.gain(),
+                   10f64.powf(-26.0 / 20.0)));
+    assert_eq!(TaskMonitor { mute: true,
+                             ..monitor.clone() }.gain(),
+               0.0);
+
+    let mixer = MixerNodeId::new("mixer".to_string());
+    let has_mixer = |mixer_id: &MixerNodeId| mixer_id == &mixer;
+
+    assert!(TaskMonitor { source: Some(mixer.clone()),
+                          ..monitor.clone() }.validate_mixers(has_mixer)
+                                             .is_ok());
+    assert!(TaskMonitor { level_db: 13.0,
+                          ..Default::default() }.validate_mixers(has_mixer)
+                                                .is_err());
+    assert!(TaskMonitor { dim_db: 6.0,
+                          ..Default::default() }.validate_mixers(has_mixer)
+                                                .is_err());
+    assert!(TaskMonitor { source: Some(MixerNodeId::new("other".to_string())),
+                          ..Default::default() }.validate_mixers(has_mixer)
+                                                .is_err());
+}
+
+#[test]
+fn test_click_json_and_validation() {
+    let mixer = MixerNodeId::new("mixer".to_string());
+    let has_mixer = |mixer_id: &MixerNodeId| mixer_id == &mixer;
+
+    let click = serde_json::from_str::<TaskClick>(r#"{"mixer_id": "mixer", "count_in_bars": 2}"#).unwrap();
+    assert_eq!(click.level_db, -12.0);
+    assert!(!click.through_play);
+    assert_eq!(click.tempo, None);
+    assert!(click.validate_mixers(has_mixer).is_ok());
+
+    let tempo = ClickTempo { bpm:         120.0,
+                             numerator:   7,
+                             denominator: 8, };
+    assert!(TaskClick { tempo: Some(tempo),
+                        ..click.clone() }.validate_mixers(has_mixer)
+                                         .is_ok());
+    assert!(TaskClick { tempo: Some(ClickTempo { denominator: 6,
+                                                 ..tempo }),
+                        ..click.clone() }.validate_mixers(has_mixer)
+                                         .is_err());
+    assert!(TaskClick { tempo: Some(ClickTempo { bpm: 0.0, ..tempo }),
+                        ..click.clone() }.validate_mixers(has_mixer)
+                                         .is_err());
+    assert!(TaskClick { count_in_bars: 0,
+                        ..click.clone() }.validate_mixers(has_mixer)
+                                         .is_err());
+    assert!(TaskClick { count_in_bars: 0,
+                        through_play: true,
+                        ..click.clone() }.validate_mixers(has_mixer)
+                                         .is_ok());
+    assert!(TaskClick { count_in_bars: 17,
+                        ..click.clone() }.validate_mixers(has_mixer)
+                                         .is_err());
+    assert!(TaskClick { level_db: 3.0,
+                        ..click.clone() }.validate_mixers(has_mixer)
+                                         .is_err());
+    assert!(TaskClick { mixer_id: MixerNodeId::new("other".to_string()),
+                        ..click }.validate_mixers(has_mixer)
+                                 .is_err());
+}
+
+#[test]
+fn test_null_test_compares_renders() {
+    let sine = |gain: f32| {
+        let channel = (0..48_000).map(|i| gain * (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / 48_000.0).sin())
+                                 .collect::<Vec<_>>();
+        vec![channel.clone(), channel]
+    };
+    let half_db = 20.0 * 0.5f64.log10();
+
+    let identical = compare_renders(&sine(0.5), &sine(0.5), 48_000);
+    assert_eq!(identical.length_samples, 48_000);
+    assert!(identical.null_depth_db < -150.0);
+    assert!(identical.gain_difference_db.abs() < 1e-6);
+
+    let quieter = compare_renders(&sine(0.5), &sine(0.25), 48_000);
+    assert!((quieter.gain_difference_db - half_db).abs() < 0.01);
+    assert!((quieter.null_depth_db - half_db).abs() < 0.01,
+            "the difference is a sine at half the level");
+
+    let band = quieter.bands.iter().find(|band| band.center_hz == 1000.0).unwrap();
+    assert!((band.gain_difference_db - half_db).abs() < 0.01);
+}
+
+fn track_group(tracks: &[&str], gain_db: f64) -> TrackGroup {
+    TrackGroup { tracks:  { tracks.iter().map(|track| TrackNodeId::new(track.to_string())).collect() },
+                 gain_db: { gain_db }, }
+}
+
+#[test]
+fn test_track_group_gains_add_up() {
+    let drums = ("drums".to_string(), track_group(&["kick", "snare"], -6.0));
+    let all = ("all".to_string(), track_group(&["kick", "vocals"], -6.0));
+    let groups = TaskTrackGroups { groups: HashMap::from([drums, all]), };
+
+    let gain = |track: &str| groups.track_gain(&TrackNodeId::new(track.to_string()));
+
+    assert!((gain("kick") - 0.251).abs() < 0.001);
+    assert!((gain("snare") - 0.501).abs() < 0.001);
+    assert!((gain("vocals") - 0.501).abs() < 0.001);
+    assert_eq!(gain("bass"), 1.0);
+    assert!(groups.validate().is_ok());
+}
+
+#[test]
+fn test_track_group_scales_the_client_fader() {
+    let groups = TaskTrackGroups { groups: HashMap::from([("drums".to_string(), track_group(&["kick"], 20.0))]), };
+
+    let kick = OutputPadId::TrackOutput(TrackNodeId::new("kick".to_string()));
+    let bus = OutputPadId::MixerOutput(MixerNodeId::new("bus".to_string()));
+    let fader = ConnectionValues { volume: Some(0.5),
+                                   pan:    Some(-0.25), };
+
+    let values = groups.connection_values(&kick, Some(&fader));
+    assert!((values.volume.unwrap() - 5.0).abs() < 1e-9);
+    assert_eq!(values.pan, Some(-0.25));
+
+    let values = groups.connection_values(&kick, None);
+    assert!((values.volume.unwrap() - 10.0).abs() < 1e-9);
+    assert_eq!(values.pan, None);
+
+    let values = groups.connection_values(&bus, Some(&fader));
+    assert_eq!(values.volume, Some(0.5));
+}
+
+#[test]
+fn test_track_group_validation() {
+    let empty = TaskTrackGroups { groups: HashMap::from([("empty".to_string(), track_group(&[], 0.0))]), };
+    assert!(empty.validate().is_err());
+
+    let loud = TaskTrackGroups { groups: HashMap::from([("loud".to_string(), track_group(&["kick"], 48.0))]), };
+    assert!(loud.validate().is_err());
+
+    let invalid = TaskTrackGroups { groups: HashMap::from([("nan".to_string(), track_group(&["kick"], f64::NAN))]), };
+    assert!(invalid.validate().is_err());
+}
+
+fn recorded_serials(recording: &PlayRecording, since: Timestamp) -> anyhow::Result<Vec<u64>> {
+    Ok(read_segments(&recording.segments_since(since), since)?.into_iter()
+                                                              .map(|(serial, _)| serial)
+                                                              .collect())
+}
+
+#[test]
+fn test_play_recording_keeps_segments_within_the_recording_window() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let start = Utc.with_ymd_and_hms(2022, 10, 23, 12, 0, 0).unwrap();
+    let at = |millis: i64| start + chrono::Duration::milliseconds(millis);
+
+    let mut recording = PlayRecording::new(dir.path().join("play"), chrono::Duration::seconds(10));
+    for serial in 0..40u64 {
+        recording.append(serial, at(serial as i64 * 1000), &[serial as u8; 3])?;
+    }
+
+    assert_eq!(recording.segments_since(at(0)).len(), 4);
+    assert_eq!(recorded_serials(&recording, at(34_500))?,
+               (35..40).collect::<Vec<_>>(),
+               "rewinding reads from the middle of a segment");
+
+    let packets = read_segments(&recording.segments_since(at(39_000)), at(39_000))?;
+    assert_eq!(packets, vec![(39, vec![39; 3])]);
+
+    recording.trim(at(15_000))?;
+    assert_eq!(recorded_serials(&recording, at(0))?,
+               (10..40).collect::<Vec<_>>(),
+               "only whole segments are dropped");
+
+    recording.trim(at(60_000))?;
+    assert!(recording.is_empty());
+
+    recording.append(40, at(61_000), &[40])?;
+    assert_eq!(recorded_serials(&recording, at(0))?,
+               vec![40],
+               "recording continues after everything was trimmed");
+
+    Ok(())
+}
+
+#[test]
+fn test_pad_loudness_reads_silent_windows_as_none() {
+    let loudness: PadLoudness =
+        serde_json::from_str(r#"{"momentary_lufs": -23.5, "short_term_lufs": null}"#).expect("valid loudness");
+
+    assert_eq!(loudness,
+               PadLoudness { momentary_lufs:  { Some(-23.5) },
+                             short_term_lufs: { None },
+                             true_peak_db:    { None }, });
+    assert_eq!(serde_json::from_str::<PadLoudness>("{}").expect("valid loudness"),
+               PadLoudness::default());
+}
+
+#[test]
+fn test_spectrum_reports_are_off_unless_bands_are_set() {
+    assert_eq!(TestOpts::parse_from(["test"]).tasks.spectrum(), None);
+
+    let opts = TestOpts::parse_from(["test", "--spectrum-bands=32", "--spectrum-rate-hz=0"]).tasks;
+    assert_eq!(opts.spectrum(), None);
+
+    let opts = TestOpts::parse_from(["test", "--spectrum-bands=32", "--spectrum-rate-hz=20"]).tasks;
+    assert_eq!(opts.spectrum(),
+               Some(EngineSpectrumSettings { bands:   { 32 },
+                                             rate_hz: { 20.0 }, }));
+}
+
+#[test]
+fn test_meter_capture_holds_peaks_between_readings() -> anyhow::Result<()> {
+    let pad_id = NodePadId::from(OutputPadId::MixerOutput(MixerNodeId::new("master".to_string())));
+    let peaks = |volume: Vec<f64>| PadMetering { volume };
+    let loudness = PadLoudness { momentary_lufs:  { Some(-14.0) },
+                                 short_term_lufs: { None },
+                                 true_peak_db:    { Some(-1.0) }, };
+
+    let mut writer = MeterCaptureWriter::new(vec![], 100, true)?;
+    writer.push_peaks(0, &pad_id, &peaks(vec![0.5, 0.25]))?;
+    writer.push_peaks(50, &pad_id, &peaks(vec![0.75, 0.125]))?;
+    writer.push_peaks(120, &pad_id, &peaks(vec![0.25, 0.25]))?;
+    writer.push_loudness(150, &pad_id, &loudness)?;
+
+    let readings = read_meter_capture(&writer.finish()?)?;
+    let name = pad_id.to_string();
+
+    assert_eq!(readings.len(), 3);
+    assert_eq!(readings[0],
+               CapturedMeter::Peaks { at:     { 0 },
+                                      pad_id: { name.clone() },
+                                      peaks:  { vec![0.5, 0.25] }, });
+    assert_eq!(readings[1],
+               CapturedMeter::Peaks { at:     { 120 },
+                                      pad_id: { name.clone() },
+                                      peaks:  { vec![0.75, 0.25] }, },
+               "the peak of the reading in between is held");
+
+    match &readings[2] {
+        CapturedMeter::Loudness { at,
+                                  momentary_lufs,
+                                  short_term_lufs,
+                                  .. } => {
+            assert_eq!((*at, *momentary_lufs),
+                       (150, -14.0),
+                       "held readings are written when the capture ends");
+            assert!(short_term_lufs.is_nan());
+        }
+        other => panic!("expected loudness, got {other:?}"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_render_format_validation() {
+    let formats = [RenderFormat::Wav { bit_depth: 24 },
+                   RenderFormat::Flac { bit_depth: 16 },
+                   RenderFormat::Mp3 { bitrate_kbps: 320 }];
+
+    assert!(validate_render_formats(&formats).is_ok());
+    assert!(validate_render_formats(&[]).is_ok());
+    assert!(validate_render_formats(&[RenderFormat::Wav { bit_depth: 8 }]).is_err());
+    assert!(validate_render_formats(&[RenderFormat::Flac { bit_depth: 32 }]).is_err());
+    assert!(validate_render_formats(&[RenderFormat::Mp3 { bitrate_kbps: 512 }]).is_err());
+    assert!(validate_render_formats(&[formats[0], formats[0]]).is_err());
+
+    let format: RenderFormat = serde_json::from_str(r#"{"format": "mp3", "bitrate_kbps": 192}"#).unwrap();
+    assert_eq!(format, RenderFormat::Mp3 { bitrate_kbps: 192 });
+    assert_eq!(format.key(), "mp3-192");
+}
+
+const LOUDNORM_OUTPUT: &str = r#"[Parsed_loudnorm_0 @ 0x5581] 
+{
+	"input_i" : "-23.71",
+	"input_tp" : "-4.20",
+	"input_lra" : "6.10",
+	"input_thresh" : "-34.02",
+	"output_i" : "-14.02",
+	"output_tp" : "-1.00",
+	"output_lra" : "5.90",
+	"output_thresh" : "-24.31",
+	"normalization_type" : "linear",
+	"target_offset" : "0.02"
+}
+"#;
+
+#[test]
+fn test_render_normalization_reads_loudnorm_reports() -> anyhow::Result<()> {
+    let report = parse_loudnorm_report(LOUDNORM_OUTPUT)?;
+    assert_eq!(report.input_i, -23.71);
+    assert_eq!(report.output_tp, -1.0);
+    assert_eq!(report.target_offset, 0.02);
+
+    assert!(parse_loudnorm_report("Press [q] to stop").is_err());
+    assert!(parse_loudnorm_report(r#"{"input_i" : "-23.71"}"#).is_err());
+
+    let normalization: TaskRenderNormalization = serde_json::from_str("{}")?;
+    assert_eq!(normalization.target_lufs, -14.0);
+    assert!(normalization.validate().is_ok());
+
+    assert_eq!(loudnorm_filter(&normalization, None),
+               "loudnorm=I=-14:TP=-1:LRA=11:print_format=json");
+    assert!(loudnorm_filter(&normalization, Some(&report)).contains(":measured_I=-23.71:measured_TP=-4.2:"));
+
+    let too_loud = TaskRenderNormalization { target_lufs:  { -2.0 },
+                                             true_peak_db: { -1.0 }, };
+    assert!(too_loud.validate().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_recall_sheet_values_and_html() {
+    let parameters = json!({ "gain": [2.5, 3], "eq_on": true, "name": "<lead>", "hpf": null });
+
+    assert_eq!(recall_values(&parameters),
+               vec![("eq_on".to_owned(), "true".to_owned()),
+                    ("gain".to_owned(), "2.5 / 3".to_owned()),
+                    ("hpf".to_owned(), "-".to_owned()),
+                    ("name".to_owned(), "<lead>".to_owned())]);
+    assert!(recall_values(&json!(null)).is_empty());
+
+    let time = Utc.with_ymd_and_hms(2022, 10, 23, 12, 0, 0).unwrap();
+    let instance_id = FixedInstanceId::new("distopik".to_owned(), "dual1084".to_owned(), "1".to_owned());
+    let instance = RecallInstance { fixed_id:    FixedInstanceNodeId::new("eq".to_owned()),
+                                    model_id:    instance_id.model_id(),
+                                    instance_id: instance_id,
+                                    model:       None,
+                                    parameters:  parameters, };
+    let sheet = TaskRecallSheet { task_id:       AppTaskId::new(AppId::test(), TaskId::new("recall".to_owned())),
+                                  revision:      3,
+                                  generated_at:  time,
+                                  reserved_from: time,
+                                  reserved_to:   time,
+                                  instances:     vec![instance],
+                                  media_id:      None,
+                                  html_media_id: None, };
+
+    let html = sheet.to_html();
+    assert!(html.contains("<td>name</td><td>&lt;lead&gt;</td>"));
+    assert!(html.contains("<td>gain</td><td>2.5 / 3</td>"));
+    assert!(!html.contains("<lead>"));
+}
+
+#[test]
+fn test_json_hash_ignores_key_order() {
+    let mut one = serde_json::Map::new();
+    one.insert("b".to_owned(), json!([true, null]));
+    one.insert("a".to_owned(), json!(1));
+
+    let mut other = serde_json::Map::new();
+    other.insert("a".to_owned(), json!(1));
+    other.insert("b".to_owned(), json!([true, null]));
+
+    // engines hash the same canonical JSON, `{"a":1,"b":[true,null]}`
+    assert_eq!(json_hash(&Value::Object(one)), "595cf28929e773ea");
+    assert_eq!(json_hash(&Value::Object(other)), "595cf28929e773ea");
+    assert_ne!(json_hash(&json!({ "a": 2, "b": [true, null] })), "595cf28929e773ea");
+}
+
+#[test]
+fn test_engines_with_saturated_cpu_or_new_xruns_are_overloaded() {
+    let resources = |cpu_usage: Option<f64>, xruns: u64| EngineResources { cpu_usage:  { cpu_usage },
+                                                                           xruns:      { xruns },
+                                                                           block_size: { Some(256) },
+                                                                           tracks:     { HashMap::new() }, };
+
+    let previous = resources(Some(40.0), 3);
+
+    assert!(!resources(Some(50.0), 3).is_overloaded(Some(&previous), 85.0));
+    assert!(!resources(None, 3).is_overloaded(Some(&previous), 85.0));
+    assert!(resources(Some(90.0), 3).is_overloaded(Some(&previous), 85.0));
+    assert!(resources(Some(50.0), 4).is_overloaded(Some(&previous), 85.0));
+    // xruns from before the first report are not new
+    assert!(!resources(Some(50.0), 4).is_overloaded(None, 85.0));
+}
+
+#[test]
+fn test_deleting_a_task_requires_its_latest_revision() {
+    let delete =
+        |revision: u64| DeleteTask { task_id:  { AppTaskId::new(AppId::test(), TaskId::new("delete".to_owned())) },
+                                     revision: { revision },
+                                     security: { DomainSecurity::Cloud }, };
+
+    assert!(delete(3).check_revision(3).is_ok());
+    assert!(delete(4).check_revision(3).is_ok());
+    assert!(matches!(delete(2).check_revision(3),
+                     Err(DomainError::TaskModificationRevisionOutOfDate { revision: 3, .. })));
+}
+
+#[test]
+fn test_watermark_payload_names_the_client_of_the_play() {
+    let watermark = TaskWatermark { mode:     { WatermarkMode::Inaudible },
+                                    level_db: { None }, };
+    let alice = ClientId::new("alice".to_owned());
+    let bob = ClientId::new("bob".to_owned());
+    let key = DomainSecurity::SecureKey(SecureKey::new("key".to_owned()));
+
+    let for_alice = watermark.for_play(Some(&alice), &key).unwrap();
+    let for_bob = watermark.for_play(Some(&bob), &key).unwrap();
+
+    assert_eq!(for_alice.payload, watermark_payload("client:alice"));
+    assert_ne!(for_alice.payload, for_bob.payload);
+    assert_eq!(for_alice.level_db, -66.0);
+    // the same client gets the same payload on every play
+    assert_eq!(watermark.for_play(Some(&alice), &DomainSecurity::Cloud),
+               Some(for_alice));
+    // without a client the requester is named
+    assert_eq!(watermark.for_play(None, &key).unwrap().payload,
+               watermark_payload("key:key"));
+}
+
+#[test]
+fn test_watermark_off_by_default_and_level_validated() {
+    let alice = ClientId::new("alice".to_owned());
+
+    assert_eq!(TaskWatermark::default().for_play(Some(&alice), &DomainSecurity::Cloud),
+               None);
+
+    let audible = TaskWatermark { mode:     { WatermarkMode::Audible },
+                                  level_db: { Some(-20.0) }, };
+
+    assert!(audible.validate().is_ok());
+    assert_eq!(audible.for_play(None, &DomainSecurity::Cloud).unwrap().level_db, -20.0);
+
+    for level_db in [6.0, -120.0] {
+        let watermark = TaskWatermark { level_db: { Some(level_db) },
+                                        ..audible };
+        assert!(watermark.validate().is_err());
+    }
+}
+
+#[test]
+fn test_loudness_target_is_optional_and_validated() {
+    let target = |target_lufs: Option<f64>| TaskLoudnessTarget { target_lufs: { target_lufs }, };
+
+    assert_eq!(serde_json::from_value::<TaskLoudnessTarget>(json!({})).unwrap(),
+               target(None));
+    assert!(target(None).validate().is_ok());
+    assert!(target(Some(-23.0)).validate().is_ok());
+    assert!(target(Some(3.0)).validate().is_err());
+    assert!(target(Some(-90.0)).validate().is_err());
+}
+
+fn elements(fixed: &[&str], mixers: &[&str], connections: &[&str]) -> TaskSpecElements {
+    let ids = |ids: &[&str]| ids.iter().map(ToString::to_string).collect();
+
+    TaskSpecElements { fixed:       { ids(fixed) },
+                       mixers:      { ids(mixers) },
+                       connections: { ids(connections) }, }
+}
+
+#[test]
+fn test_safe_mode_disables_only_the_failed_elements() {
+    let all = elements(&["eq", "comp"], &["master"], &["c1", "c10"]);
+    let changed = elements(&["comp"], &[], &["c10"]);
+
+    // the elements the engine error names, as whole IDs
+    let failed = TaskSpecElements::failed(&all, changed.clone(), "Connection c10 has no source pad");
+    assert_eq!(failed, elements(&[], &[], &["c10"]));
+
+    // otherwise the elements changed since the spec the engine last accepted
+    let failed = TaskSpecElements::failed(&all, changed, "Engine timed out");
+    assert_eq!(failed, elements(&["comp"], &[], &["c10"]));
+
+    // and everything only when nothing narrows it down
+    let failed = TaskSpecElements::failed(&all, TaskSpecElements::default(), "Engine timed out");
+    assert_eq!(failed, all);
+
+    let mut safe_mode = SafeModeState::new("Engine timed out".to_owned(), elements(&["comp"], &[], &[]));
+    assert_eq!(safe_mode.rejected("Fixed instance eq not found".to_owned(),
+                                  TaskSpecElements::failed(&all, TaskSpecElements::default(), "eq not found")),
+               elements(&["eq"], &[], &[]));
+    assert_eq!(safe_mode.disabled(), &elements(&["eq", "comp"], &[], &[]));
+}
+
+#[test]
+fn test_safe_mode_filters_modifications() {
+    let mut safe_mode = SafeModeState::new("Engine timed out".to_owned(), elements(&["comp"], &[], &["c1"]));
+
+    // a modification adding a mixer and removing the disabled connection
+    let present = elements(&["eq", "comp"], &["master", "bus"], &[]);
+    safe_mode.modified(elements(&["comp"], &["bus"], &[]), &present);
+
+    assert_eq!(safe_mode.disabled(), &elements(&["comp"], &[], &[]));
+    assert_eq!(safe_mode.pending(), &elements(&[], &["bus"], &[]));
+
+    // the engine rejects the new mixer, which is disabled without touching the rest of the spec
+    let disabled = safe_mode.rejected("Mixer failed".to_owned(), present.clone());
+    assert_eq!(disabled, elements(&[], &["bus"], &[]));
+    assert_eq!(safe_mode.disabled(), &elements(&["comp"], &["bus"], &[]));
+    assert!(safe_mode.pending().is_empty());
+
+    // re-enabled elements are accepted with the next spec
+    assert_eq!(safe_mode.enable(&elements(&[], &["bus", "master"], &[])),
+               elements(&[], &["bus"], &[]));
+    assert_eq!(safe_mode.accepted(), elements(&[], &["bus"], &[]));
+    assert_eq!(safe_mode.disabled(), &elements(&["comp"], &[], &[]));
+}
+
+#[test]
+fn test_pause_survives_the_engine_re_reporting_the_play() {
+    let (play, other) = (PlayId::new(1), PlayId::new(2));
+    let mut pause = PlayPause::default();
+
+    pause.set(&play, true);
+    pause.actual_changed(Some(&play));
+    assert!(pause.is_paused(&play));
+    assert!(!pause.is_paused(&other));
+
+    pause.actual_changed(Some(&other));
+    assert!(!pause.is_paused(&play), "another play ends the pause");
+}
+
+#[test]
+fn test_pause_is_cleared_by_a_new_desired_play() {
+    let play = PlayId::new(1);
+    let mut pause = PlayPause::default();
+
+    pause.set(&play, true);
+    pause.desired_changed();
+    assert!(!pause.is_paused(&play));
+
+    pause.set(&play, true);
+    pause.set(&play, false);
+    assert!(!pause.is_paused(&play), "resumed");
+}
+
+#[test]
+fn test_pause_is_cleared_by_stop() {
+    let play = PlayId::new(1);
+    let mut engine = TaskEngine::new(AppTaskId::new(AppId::test(), TaskId::new("pause".to_owned())));
+
+    engine.set_paused(&play, true);
+    assert!(engine.is_paused(&play));
+
+    engine.set_actual_stopped();
+    assert!(!engine.is_paused(&play));
+
+    let mut pause = PlayPause::default();
+    pause.set(&play, true);
+    pause.actual_changed(None);
+    assert!(!pause.is_paused(&play));
+}
+
+#[test]
+fn test_packet_serials_keep_increasing_across_plays() {
+    let (first, second) = (PlayId::new(1), PlayId::new(2));
+    let mut collected = StreamingPacket::default();
+    let mut next_serial = 0;
+    let mut cache = HashMap::new();
+
+    for play_id in [first, first, second, second] {
+        let packet = TaskActor::seal_packet(&mut collected, Some(play_id), &mut next_serial);
+        TasksSupervisor::cache_packet(&mut cache, packet);
+    }
+
+    let serials = |play_id: &PlayId| {
+        TasksSupervisor::cached_packets(&cache[play_id], &[0, 1, 2, 3]).into_iter()
+                                                                       .map(|packet| packet.serial)
+                                                                       .collect::<Vec<_>>()
+    };
+
+    assert_eq!(serials(&first), vec![0, 1]);
+    assert_eq!(serials(&second),
+               vec![2, 3],
+               "the second play continues the serials of the first");
+    assert_eq!(next_serial, 4);
+}
@@ -0,0 +1,99 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use audiocloud_api::common::change::ModifyTaskSpec;
+use audiocloud_api::common::task::{ConnectionValues, TaskSpec};
+use audiocloud_api::newtypes::{NodeConnectionId, TrackNodeId};
+use audiocloud_api::OutputPadId;
+
+const MIN_GROUP_GAIN_DB: f64 = -144.0;
+const MAX_GROUP_GAIN_DB: f64 = 24.0;
+
+/// Groups of tracks with linked levels, like VCA faders: the gain of a group scales the level of every connection
+/// leaving its tracks on top of the level set on the connection itself. Gains of a track in several groups add up
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskTrackGroups {
+    #[serde(default)]
+    pub groups: HashMap<String, TrackGroup>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TrackGroup {
+    #[schema(value_type = Vec<String>)]
+    pub tracks:  HashSet<TrackNodeId>,
+    /// Gain of the group in dB, 0 leaves the levels of its tracks as they are
+    #[serde(default)]
+    pub gain_db: f64,
+}
+
+impl TaskTrackGroups {
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Check that every group has tracks and a gain between -144dB and +24dB
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, group) in &self.groups {
+            if group.tracks.is_empty() {
+                return Err(format!("Group {name} has no tracks"));
+            }
+            if !group.gain_db.is_finite() || group.gain_db < MIN_GROUP_GAIN_DB || group.gain_db > MAX_GROUP_GAIN_DB {
+                let gain_db = group.gain_db;
+                return Err(format!("Group {name} has gain {gain_db}dB, outside of the supported range"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Linear gain the groups of a track apply to it
+    pub fn track_gain(&self, track_id: &TrackNodeId) -> f64 {
+        let gain_db = self.groups
+                          .values()
+                          .filter(|group| group.tracks.contains(track_id))
+                          .map(|group| group.gain_db)
+                          .sum::<f64>();
+
+        db_to_gain(gain_db)
+    }
+
+    /// Values of a connection leaving `from`, with the level set on it by the client scaled by the group gain
+    pub fn connection_values(&self, from: &OutputPadId, fader: Option<&ConnectionValues>) -> ConnectionValues {
+        let gain = match from {
+            OutputPadId::TrackOutput(track_id) => self.track_gain(track_id),
+            _ => 1.0,
+        };
+
+        let volume = fader.and_then(|fader| fader.volume).unwrap_or(1.0);
+
+        ConnectionValues { volume: { Some(volume * gain) },
+                           pan:    { fader.and_then(|fader| fader.pan) }, }
+    }
+
+    /// Connection value updates that bring the engine to the levels of the groups and the faders of the client
+    ///
+    /// Covers every connection leaving a track, so tracks removed from a group go back to their own level, and every
+    /// other connection the client set values on, since engines forget them when they rebuild the project.
+    pub fn connection_levels(&self,
+                             spec: &TaskSpec,
+                             faders: &HashMap<NodeConnectionId, ConnectionValues>)
+                             -> Vec<ModifyTaskSpec> {
+        spec.connections
+            .iter()
+            .filter(|(id, connection)| {
+                matches!(connection.from, OutputPadId::TrackOutput(_)) || faders.contains_key(id)
+            })
+            .map(|(id, connection)| {
+                let values = self.connection_values(&connection.from, faders.get(id));
+                ModifyTaskSpec::SetConnectionParameterValues { connection_id: { id.clone() },
+                                                               values:        { values }, }
+            })
+            .collect()
+    }
+}
+
+pub fn db_to_gain(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use audiocloud_api::ClientId;
+
+use crate::DomainSecurity;
+
+/// Watermark mixed into the streams of a task's plays, so a leaked recording can be traced back to the client that
+/// requested the play
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskWatermark {
+    #[serde(default)]
+    pub mode:     WatermarkMode,
+    /// Level of the watermark in dBFS, -30 for audible and -66 for inaudible watermarks if not set
+    #[serde(default)]
+    pub level_db: Option<f64>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WatermarkMode {
+    #[default]
+    Off,
+    /// Short tone bursts at a fixed interval, encoding nothing but clearly marking the stream as a preview
+    Audible,
+    /// Low level spread spectrum noise carrying the 64 bit payload, recoverable by correlation
+    Inaudible,
+}
+
+/// Watermark the engine mixes into the stream of one play
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlayWatermark {
+    pub mode:     WatermarkMode,
+    pub level_db: f64,
+    /// Identifies the client the play streams to, see [`watermark_payload`]
+    pub payload:  u64,
+}
+
+impl TaskWatermark {
+    pub fn validate(&self) -> Result<(), String> {
+        match self.level_db {
+            Some(level_db) if !(-96.0..=0.0).contains(&level_db) => {
+                Err(format!("Watermark level must be between -96 and 0 dBFS, not {level_db}"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Watermark of a play requested by `security`, for `client_id` if the request named the client it streams to
+    pub fn for_play(&self, client_id: Option<&ClientId>, security: &DomainSecurity) -> Option<PlayWatermark> {
+        let level_db = match self.mode {
+            WatermarkMode::Off => return None,
+            WatermarkMode::Audible => self.level_db.unwrap_or(-30.0),
+            WatermarkMode::Inaudible => self.level_db.unwrap_or(-66.0),
+        };
+
+        let client = match (client_id, security) {
+            (Some(client_id), _) => format!("client:{client_id}"),
+            (None, DomainSecurity::Token(token)) => format!("sub:{}", token.subject),
+            (None, DomainSecurity::SecureKey(secure_key)) => format!("key:{secure_key}"),
+            (None, DomainSecurity::Cloud) => "cloud".to_owned(),
+        };
+
+        Some(PlayWatermark { mode:     { self.mode },
+                             level_db: { level_db },
+                             payload:  { watermark_payload(&client) }, })
+    }
+}
+
+/// 64 bit FNV-1a hash of the client a play streams to, what an inaudible watermark carries
+///
+/// Operators look up the payload a leaked recording carries by hashing the clients that requested plays of the task.
+pub fn watermark_payload(client: &str) -> u64 {
+    client.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+                      (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+                  })
+}
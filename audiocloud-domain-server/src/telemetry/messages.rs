@@ -0,0 +1,25 @@
+use actix::Message;
+use serde::Deserialize;
+
+use audiocloud_api::{FixedInstanceId, Timestamp};
+
+use crate::telemetry::InstanceReportSeries;
+use crate::DomainResult;
+
+/// Time range of instance reports, both ends included
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct InstanceReportsQuery {
+    pub from:       Option<Timestamp>,
+    pub to:         Option<Timestamp>,
+    /// Only this report, instead of all of them
+    pub report_id:  Option<String>,
+    /// Neighbouring buckets are merged until each series has at most this many points
+    pub max_points: Option<usize>,
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "DomainResult<Vec<InstanceReportSeries>>")]
+pub struct GetInstanceReports {
+    pub instance_id: FixedInstanceId,
+    pub query:       InstanceReportsQuery,
+}
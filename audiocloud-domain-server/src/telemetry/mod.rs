@@ -0,0 +1,72 @@
+use actix::{Actor, Addr};
+use anyhow::anyhow;
+use chrono::Duration;
+use clap::Args;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use tracing::*;
+use utoipa::ToSchema;
+
+pub use messages::*;
+pub use series::{ReportBucket, ReportSeries, ReportSeriesConfig};
+use supervisor::TelemetrySupervisor;
+
+pub mod messages;
+mod series;
+mod supervisor;
+
+#[cfg(test)]
+mod tests;
+
+static TELEMETRY_SUPERVISOR: OnceCell<Addr<TelemetrySupervisor>> = OnceCell::new();
+
+/// Values of one channel of an instance report over time
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct InstanceReportSeries {
+    pub report_id: String,
+    pub channel:   usize,
+    pub points:    Vec<ReportBucket>,
+}
+
+#[derive(Args, Clone, Copy, Debug)]
+pub struct TelemetryOpts {
+    /// Instance reports are kept at this resolution for the fine retention, in milliseconds
+    #[clap(long, env, default_value = "1000")]
+    pub report_fine_resolution_ms: u64,
+
+    /// How long instance reports are kept at the fine resolution, in seconds
+    #[clap(long, env, default_value = "3600")]
+    pub report_fine_retention_seconds: u64,
+
+    /// Instance reports are kept at this resolution for the coarse retention, in seconds
+    #[clap(long, env, default_value = "60")]
+    pub report_coarse_resolution_seconds: u64,
+
+    /// How long instance reports are kept at the coarse resolution, in seconds
+    #[clap(long, env, default_value = "86400")]
+    pub report_coarse_retention_seconds: u64,
+}
+
+impl TelemetryOpts {
+    pub fn series_config(&self) -> ReportSeriesConfig {
+        ReportSeriesConfig { fine_resolution:   { Duration::milliseconds(self.report_fine_resolution_ms as i64) },
+                             fine_retention:    { Duration::seconds(self.report_fine_retention_seconds as i64) },
+                             coarse_resolution: { Duration::seconds(self.report_coarse_resolution_seconds as i64) },
+                             coarse_retention:  { Duration::seconds(self.report_coarse_retention_seconds as i64) }, }
+    }
+}
+
+#[instrument(skip_all, err)]
+pub fn init(opts: TelemetryOpts) -> anyhow::Result<()> {
+    let supervisor = TelemetrySupervisor::new(opts);
+
+    TELEMETRY_SUPERVISOR.set(supervisor.start())
+                        .map_err(|_| anyhow!("Telemetry supervisor already initialized"))?;
+
+    Ok(())
+}
+
+pub fn get_telemetry_supervisor() -> &'static Addr<TelemetrySupervisor> {
+    TELEMETRY_SUPERVISOR.get()
+                        .expect("Telemetry supervisor not initialized")
+}
@@ -0,0 +1,197 @@
+use std::collections::VecDeque;
+
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use audiocloud_api::Timestamp;
+
+/// Values of a report within one bucket of time
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ReportBucket {
+    /// Start of the bucket
+    #[schema(value_type = String)]
+    pub at:    Timestamp,
+    pub min:   f64,
+    pub max:   f64,
+    pub mean:  f64,
+    /// Number of reported values in the bucket
+    pub count: u64,
+}
+
+impl ReportBucket {
+    fn new(at: Timestamp, value: f64) -> Self {
+        Self { at:    { at },
+               min:   { value },
+               max:   { value },
+               mean:  { value },
+               count: { 1 }, }
+    }
+
+    fn merge(&mut self, other: &ReportBucket) {
+        let count = self.count + other.count;
+
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.mean = (self.mean * self.count as f64 + other.mean * other.count as f64) / count as f64;
+        self.count = count;
+    }
+}
+
+/// Buckets of one resolution, the oldest dropped once they are older than the retention
+#[derive(Clone, Debug)]
+struct ReportTier {
+    resolution: Duration,
+    retention:  Duration,
+    buckets:    VecDeque<ReportBucket>,
+}
+
+impl ReportTier {
+    fn new(resolution: Duration, retention: Duration) -> Self {
+        Self { resolution: { resolution.max(Duration::milliseconds(1)) },
+               retention:  { retention },
+               buckets:    { VecDeque::new() }, }
+    }
+
+    fn bucket_start(&self, at: Timestamp) -> Timestamp {
+        let millis = at.timestamp_millis().rem_euclid(self.resolution.num_milliseconds());
+        let nanos = at.timestamp_subsec_nanos() % 1_000_000;
+
+        at - Duration::milliseconds(millis) - Duration::nanoseconds(nanos as i64)
+    }
+
+    fn record(&mut self, at: Timestamp, value: f64) {
+        let bucket = ReportBucket::new(self.bucket_start(at), value);
+
+        match self.buckets.back_mut() {
+            // reports arrive in order, a late one is counted in the latest bucket
+            Some(last) if last.at >= bucket.at => last.merge(&bucket),
+            _ => self.buckets.push_back(bucket),
+        }
+    }
+
+    fn expire(&mut self, now: Timestamp) {
+        let cutoff = now - self.retention;
+        while matches!(self.buckets.front(), Some(bucket) if bucket.at + self.resolution <= cutoff) {
+            self.buckets.pop_front();
+        }
+    }
+
+    fn covers(&self, from: Timestamp) -> bool {
+        matches!(self.buckets.front(), Some(bucket) if bucket.at <= from)
+    }
+}
+
+/// Time series of one report channel of an instance, kept at a fine resolution for a short while and at a coarse
+/// resolution for longer
+#[derive(Clone, Debug)]
+pub struct ReportSeries {
+    fine:   ReportTier,
+    coarse: ReportTier,
+}
+
+/// Resolutions and retentions of report series
+#[derive(Clone, Copy, Debug)]
+pub struct ReportSeriesConfig {
+    pub fine_resolution:   Duration,
+    pub fine_retention:    Duration,
+    pub coarse_resolution: Duration,
+    pub coarse_retention:  Duration,
+}
+
+impl ReportSeries {
+    pub fn new(config: &ReportSeriesConfig) -> Self {
+        Self { fine:   { ReportTier::new(config.fine_resolution, config.fine_retention) },
+               coarse: { ReportTier::new(config.coarse_resolution, config.coarse_retention) }, }
+    }
+
+    pub fn record(&mut self, at: Timestamp, value: f64) {
+        self.fine.record(at, value);
+        self.coarse.record(at, value);
+    }
+
+    pub fn expire(&mut self, now: Timestamp) {
+        self.fine.expire(now);
+        self.coarse.expire(now);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fine.buckets.is_empty() && self.coarse.buckets.is_empty()
+    }
+
+    /// Buckets starting between `from` and `to`, from the fine tier if it reaches back far enough. When there are more
+    /// than `max_points`, neighbouring buckets are merged until there are not.
+    pub fn query(&self, from: Option<Timestamp>, to: Option<Timestamp>, max_points: usize) -> Vec<ReportBucket> {
+        let tier = match from {
+            Some(from) if self.fine.covers(from) => &self.fine,
+            _ => &self.coarse,
+        };
+
+        let buckets = tier.buckets
+                          .iter()
+                          .filter(|bucket| from.map(|from| bucket.at >= from).unwrap_or(true))
+                          .filter(|bucket| to.map(|to| bucket.at <= to).unwrap_or(true))
+                          .copied()
+                          .collect::<Vec<_>>();
+
+        downsample(buckets, max_points)
+    }
+}
+
+/// Merge runs of neighbouring buckets so that at most `max_points` remain
+pub fn downsample(buckets: Vec<ReportBucket>, max_points: usize) -> Vec<ReportBucket> {
+    let max_points = max_points.max(1);
+    if buckets.len() <= max_points {
+        return buckets;
+    }
+
+    let per_point = (buckets.len() + max_points - 1) / max_points;
+
+    buckets.chunks(per_point)
+           .map(|chunk| {
+               let mut merged = chunk[0];
+               for bucket in &chunk[1..] {
+                   merged.merge(bucket);
+               }
+               merged
+           })
+           .collect()
+}
+
+/// Numeric values of instance reports by report ID and channel
+///
+/// Reports are an object of report IDs with either a single value or an array with a value per channel. Booleans
+/// count as 0 and 1, anything else is not a value that can be plotted and is skipped.
+pub fn flatten_reports(reports: &Value) -> Vec<(String, usize, f64)> {
+    let mut rv = vec![];
+
+    if let Value::Object(reports) = reports {
+        for (report_id, value) in reports {
+            match value {
+                Value::Array(channels) => {
+                    for (channel, value) in channels.iter().enumerate() {
+                        if let Some(value) = report_value(value) {
+                            rv.push((report_id.clone(), channel, value));
+                        }
+                    }
+                }
+                value => {
+                    if let Some(value) = report_value(value) {
+                        rv.push((report_id.clone(), 0, value));
+                    }
+                }
+            }
+        }
+    }
+
+    rv
+}
+
+fn report_value(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::Bool(value) => Some(if *value { 1.0 } else { 0.0 }),
+        _ => None,
+    }
+}
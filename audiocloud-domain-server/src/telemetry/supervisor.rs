@@ -0,0 +1,99 @@
+#![allow(unused_variables)]
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use actix::{Actor, AsyncContext, Context, Handler};
+use actix_broker::BrokerSubscribe;
+
+use audiocloud_api::{now, FixedInstanceId};
+
+use crate::fixed_instances::NotifyFixedInstanceReports;
+use crate::telemetry::series::flatten_reports;
+use crate::telemetry::{GetInstanceReports, InstanceReportSeries, ReportSeries, ReportSeriesConfig, TelemetryOpts};
+use crate::DomainResult;
+
+/// How often buckets older than their retention are dropped
+const EXPIRE_INTERVAL: Duration = Duration::from_secs(60);
+
+const DEFAULT_MAX_POINTS: usize = 1_000;
+
+pub struct TelemetrySupervisor {
+    config:  ReportSeriesConfig,
+    reports: HashMap<FixedInstanceId, HashMap<(String, usize), ReportSeries>>,
+}
+
+impl TelemetrySupervisor {
+    pub fn new(opts: TelemetryOpts) -> Self {
+        Self { config:  { opts.series_config() },
+               reports: { HashMap::new() }, }
+    }
+
+    fn expire(&mut self, ctx: &mut Context<Self>) {
+        let now = now();
+
+        for series in self.reports.values_mut() {
+            series.retain(|_, series| {
+                      series.expire(now);
+                      !series.is_empty()
+                  });
+        }
+
+        self.reports.retain(|_, series| !series.is_empty());
+    }
+}
+
+impl Actor for TelemetrySupervisor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.subscribe_system_async::<NotifyFixedInstanceReports>(ctx);
+
+        ctx.run_interval(EXPIRE_INTERVAL, Self::expire);
+    }
+}
+
+impl Handler<NotifyFixedInstanceReports> for TelemetrySupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyFixedInstanceReports, ctx: &mut Self::Context) -> Self::Result {
+        let at = now();
+        let config = self.config;
+        let reports = self.reports.entry(msg.instance_id).or_default();
+
+        for (report_id, channel, value) in flatten_reports(&msg.reports) {
+            reports.entry((report_id, channel))
+                   .or_insert_with(|| ReportSeries::new(&config))
+                   .record(at, value);
+        }
+    }
+}
+
+impl Handler<GetInstanceReports> for TelemetrySupervisor {
+    type Result = DomainResult<Vec<InstanceReportSeries>>;
+
+    fn handle(&mut self, msg: GetInstanceReports, ctx: &mut Self::Context) -> Self::Result {
+        let query = msg.query;
+        let max_points = query.max_points.unwrap_or(DEFAULT_MAX_POINTS);
+
+        let series = match self.reports.get(&msg.instance_id) {
+            Some(series) => series,
+            None => return Ok(vec![]),
+        };
+
+        let mut rv = vec![];
+        for ((report_id, channel), series) in series {
+            if matches!(&query.report_id, Some(only) if only != report_id) {
+                continue;
+            }
+
+            rv.push(InstanceReportSeries { report_id: { report_id.clone() },
+                                           channel:   { *channel },
+                                           points:    { series.query(query.from, query.to, max_points) }, });
+        }
+
+        rv.sort_by(|a, b| (&a.report_id, a.channel).cmp(&(&b.report_id, b.channel)));
+
+        Ok(rv)
+    }
+}
@@ -0,0 +1,83 @@
+use chrono::{Duration, TimeZone, Utc};
+use serde_json::json;
+
+use audiocloud_api::Timestamp;
+
+use crate::telemetry::series::flatten_reports;
+use crate::telemetry::{ReportSeries, ReportSeriesConfig};
+
+fn config() -> ReportSeriesConfig {
+    ReportSeriesConfig { fine_resolution:   { Duration::seconds(1) },
+                         fine_retention:    { Duration::seconds(60) },
+                         coarse_resolution: { Duration::seconds(10) },
+                         coarse_retention:  { Duration::seconds(600) }, }
+}
+
+fn at(millis: i64) -> Timestamp {
+    Utc.timestamp_millis_opt(1_660_000_000_000 + millis).unwrap()
+}
+
+#[test]
+fn test_reports_are_bucketed_with_min_max_and_mean() {
+    let mut series = ReportSeries::new(&config());
+    series.record(at(0), -3.0);
+    series.record(at(400), -1.0);
+    series.record(at(900), -2.0);
+    series.record(at(1_100), -6.0);
+
+    let points = series.query(Some(at(0)), None, 100);
+    assert_eq!(points.len(), 2);
+    assert_eq!(points[0].at, at(0));
+    assert_eq!((points[0].min, points[0].max, points[0].count), (-3.0, -1.0, 3));
+    assert!((points[0].mean + 2.0).abs() < 1e-9);
+    assert_eq!((points[1].at, points[1].mean), (at(1_000), -6.0));
+}
+
+#[test]
+fn test_old_ranges_are_served_from_the_coarse_tier() {
+    let mut series = ReportSeries::new(&config());
+    for second in 0..300 {
+        series.record(at(second * 1_000), second as f64);
+    }
+
+    series.expire(at(300_000));
+
+    // the last minute is still at a resolution of a second
+    assert_eq!(series.query(Some(at(250_000)), None, 1_000).len(), 50);
+
+    // before that only the ten second buckets remain
+    let points = series.query(Some(at(0)), Some(at(99_000)), 1_000);
+    assert_eq!(points.len(), 10);
+    assert_eq!((points[0].min, points[0].max, points[0].mean), (0.0, 9.0, 4.5));
+}
+
+#[test]
+fn test_queries_are_downsampled_to_max_points() {
+    let mut series = ReportSeries::new(&config());
+    for second in 0..60 {
+        series.record(at(second * 1_000), second as f64);
+    }
+
+    let points = series.query(Some(at(0)), None, 6);
+    assert_eq!(points.len(), 6);
+    assert_eq!((points[0].min, points[0].max, points[0].count), (0.0, 9.0, 10));
+    assert_eq!(points[5].max, 59.0);
+}
+
+#[test]
+fn test_flatten_reports_by_channel() {
+    let mut values = flatten_reports(&json!({
+                                         "gain_reduction": [-3.5, -4.0],
+                                         "overload": true,
+                                         "level": -12,
+                                         "model": "1084",
+                                     }));
+
+    values.sort_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)));
+
+    assert_eq!(values,
+               vec![("gain_reduction".to_owned(), 0, -3.5),
+                    ("gain_reduction".to_owned(), 1, -4.0),
+                    ("level".to_owned(), 0, -12.0),
+                    ("overload".to_owned(), 0, 1.0)]);
+}
@@ -1 +1,3 @@
 mod actix;
+mod rate_limit;
+mod security;
@@ -0,0 +1,79 @@
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+
+use crate::rate_limit::{RateLimitConfig, RateLimiter, RateLimits};
+
+#[test]
+fn test_clients_over_their_burst_are_rejected() {
+    let limiter = RateLimiter::new(2.0, 3.0, Duration::from_secs(60));
+    let now = Instant::now();
+
+    for _ in 0..3 {
+        assert_eq!(limiter.check_at("ip:10.0.0.1", now), Ok(()));
+    }
+
+    assert_eq!(limiter.check_at("ip:10.0.0.1", now), Err(Duration::from_millis(500)));
+    assert_eq!(limiter.check_at("ip:10.0.0.2", now), Ok(()));
+}
+
+#[test]
+fn test_buckets_refill_at_the_sustained_rate() {
+    let limiter = RateLimiter::new(2.0, 3.0, Duration::from_secs(60));
+    let now = Instant::now();
+
+    for _ in 0..3 {
+        assert_eq!(limiter.check_at("sub:user", now), Ok(()));
+    }
+
+    assert!(limiter.check_at("sub:user", now + Duration::from_millis(250)).is_err());
+    assert_eq!(limiter.check_at("sub:user", now + Duration::from_millis(500)), Ok(()));
+    assert!(limiter.check_at("sub:user", now + Duration::from_millis(500)).is_err());
+
+    // never more than the burst, however long the client was away
+    let later = now + Duration::from_secs(30);
+    for _ in 0..3 {
+        assert_eq!(limiter.check_at("sub:user", later), Ok(()));
+    }
+    assert!(limiter.check_at("sub:user", later).is_err());
+}
+
+#[test]
+fn test_idle_clients_are_forgotten() {
+    let limiter = RateLimiter::new(1.0, 1.0, Duration::from_secs(10));
+    let now = Instant::now();
+
+    assert_eq!(limiter.check_at("ip:10.0.0.1", now), Ok(()));
+    assert_eq!(limiter.check_at("ip:10.0.0.2", now + Duration::from_secs(8)), Ok(()));
+    assert_eq!(limiter.client_count(), 2);
+
+    assert_eq!(limiter.check_at("ip:10.0.0.3", now + Duration::from_secs(15)), Ok(()));
+    assert_eq!(limiter.client_count(), 2);
+}
+
+#[test]
+fn test_reconfiguring_applies_to_known_clients() {
+    let limiter = RateLimiter::new(1.0, 1.0, Duration::from_secs(60));
+    let now = Instant::now();
+
+    assert_eq!(limiter.check_at("ip:10.0.0.1", now), Ok(()));
+    assert!(limiter.check_at("ip:10.0.0.1", now).is_err());
+
+    limiter.reconfigure(0.0, 1.0, Duration::from_secs(60));
+    assert_eq!(limiter.check_at("ip:10.0.0.1", now), Ok(()));
+}
+
+#[test]
+fn test_rate_limits_are_read_from_the_domain_config() -> anyhow::Result<()> {
+    let config = json!({
+        "fixed_instances": {},
+        "rate_limits": { "rest": { "per_second": 5.0, "burst": 10.0 } }
+    });
+    let config = serde_json::from_value::<RateLimitConfig>(config)?;
+
+    assert_eq!(config.rate_limits.rest.per_second, 5.0);
+    assert_eq!(config.rate_limits.socket, RateLimits::default().socket);
+    assert_eq!(config.rate_limits.idle_seconds, 300);
+
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+
+use maplit::hashmap;
+
+use audiocloud_api::{AppId, AppTaskId, SecureKey, TaskId};
+
+use crate::{rekey_scopes, DomainSecurity, SecureKeyScope, TokenSecurity};
+
+#[test]
+fn test_keys_and_tokens_without_a_scope_can_only_listen() {
+    let task_id = AppTaskId::new(AppId::test(), TaskId::new("scoped".to_owned()));
+    let owner = SecureKey::new("owner".to_owned());
+    let guest = SecureKey::new("guest".to_owned());
+    let key_scopes = hashmap! { owner.clone() => SecureKeyScope::Full };
+
+    let scope = |security: DomainSecurity| security.scope_on_task(&task_id, &key_scopes);
+
+    assert_eq!(scope(DomainSecurity::Cloud), SecureKeyScope::Full);
+    assert_eq!(scope(DomainSecurity::SecureKey(owner)), SecureKeyScope::Full);
+    assert_eq!(scope(DomainSecurity::SecureKey(guest)), SecureKeyScope::Listen);
+
+    let token = |scopes: HashMap<AppTaskId, SecureKeyScope>| {
+        DomainSecurity::Token(TokenSecurity { subject: { "user".to_owned() },
+                                              tasks:   { HashMap::new() },
+                                              scopes:  { scopes }, })
+    };
+
+    assert_eq!(scope(token(hashmap! { task_id.clone() => SecureKeyScope::Transport })),
+               SecureKeyScope::Transport);
+    assert_eq!(scope(token(HashMap::new())), SecureKeyScope::Listen);
+}
+
+#[test]
+fn test_rotated_keys_keep_their_scope() {
+    let listener = SecureKey::new("listener".to_owned());
+    let rotated = SecureKey::new("rotated".to_owned());
+    let revoked = SecureKey::new("revoked".to_owned());
+    let scopes = hashmap! { listener.clone() => SecureKeyScope::Listen, revoked.clone() => SecureKeyScope::Transport };
+
+    // the listener key is rotated and the other one revoked, leaving only the new key
+    let rekeyed = rekey_scopes(&scopes, [&rotated], Some((&listener, &rotated)));
+
+    assert_eq!(rekeyed, hashmap! { rotated => SecureKeyScope::Listen });
+    assert!(rekey_scopes(&scopes, [&listener], None).get(&revoked).is_none());
+}
@@ -0,0 +1,52 @@
+use clap::Parser;
+
+use audiocloud_driver::sweep;
+
+#[derive(Parser)]
+struct Opts {
+    #[clap(flatten)]
+    sweep: sweep::SweepOpts,
+
+    /// Print the report as JSON instead of a summary
+    #[clap(long, env)]
+    json: bool,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    // sweeps every parameter of a model across its range on an instance served by a running driver, to validate new
+    // driver implementations the same way the domain will drive them
+
+    let _ = dotenv::dotenv();
+
+    tracing_subscriber::fmt::init();
+
+    let opts = Opts::parse();
+
+    let report = sweep::run(&opts.sweep).await?;
+
+    if opts.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        for anomaly in &report.anomalies {
+            println!("ANOMALY {:<24} {:<12} {}",
+                     anomaly.parameter,
+                     anomaly.value.to_string(),
+                     anomaly.message);
+        }
+
+        let status = if report.passed() { "PASS" } else { "FAIL" };
+        println!("{status} {}: {} parameters, {} values, {} anomalies in {} ms",
+                 report.instance_id,
+                 report.parameters,
+                 report.steps,
+                 report.anomalies.len(),
+                 report.elapsed_ms);
+    }
+
+    if !report.passed() {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
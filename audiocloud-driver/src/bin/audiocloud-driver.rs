@@ -23,6 +23,10 @@ struct DriverOpts {
 
     #[clap(long, env, default_value = "7400")]
     port: u16,
+
+    /// File to keep the last parameter values written to each instance in, to reapply them after a restart
+    #[clap(long, env)]
+    values_file: Option<PathBuf>,
 }
 
 #[actix_web::main]
@@ -40,7 +44,7 @@ async fn main() -> anyhow::Result<()> {
 
     let instances = serde_yaml::from_reader::<_, ConfigFile>(fs::File::open(opts.config_file)?)?;
 
-    supervisor::init(opts.nats, instances).await?;
+    supervisor::init(opts.nats, instances, opts.values_file).await?;
 
     info!(bind = opts.bind,
           port = opts.port,
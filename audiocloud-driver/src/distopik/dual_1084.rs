@@ -4,6 +4,7 @@ use std::os::unix::prelude::*;
 use std::time::Duration;
 
 use actix::{Actor, Context, Handler, Recipient};
+use actix_broker::BrokerIssue;
 
 use nix::{ioctl_none, ioctl_write_ptr};
 use serde::{Deserialize, Serialize};
@@ -20,7 +21,7 @@ use audiocloud_models::distopik::{
 };
 
 use crate::utils::*;
-use crate::{Command, InstanceConfig};
+use crate::{Command, InstanceConfig, NotifyInstanceValues};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Config {
@@ -394,7 +395,7 @@ impl Handler<Command> for Dual1084 {
                 // self.write_io_expanders();
                 Dual1084::set_io_expanders(&self);
 
-                // self.issue_system_async(self.values.clone());
+                self.notify_values();
 
                 Ok(())
             }
@@ -404,6 +405,21 @@ impl Handler<Command> for Dual1084 {
 }
 
 impl Dual1084 {
+    /// The driver knows every parameter it set, including those never written since it started
+    fn notify_values(&mut self) {
+        match serde_json::to_value(&self.values) {
+            Ok(parameters) => {
+                let mut values = NotifyInstanceValues::new(self.id.clone());
+                values.parameters = parameters;
+
+                self.issue_system_async(values);
+            }
+            Err(error) => {
+                error!(%error, "Failed to encode Dual1084 values");
+            }
+        }
+    }
+
     pub fn set_io_expanders(&self) {
         let mut spi_data: [u32; 9] = [0; 9];
         const io_boards: [u16; 4] = [3, 1, 5, 7];
@@ -15,7 +15,9 @@ pub mod nats;
 pub mod netio;
 pub mod rest_api;
 pub mod supervisor;
+pub mod sweep;
 pub mod utils;
+pub mod values;
 
 pub type ConfigFile = HashMap<FixedInstanceId, DriverConfig>;
 
@@ -52,6 +54,13 @@ pub struct GetValues {
     pub instance_id: FixedInstanceId,
 }
 
+/// Values of every configured instance, as far as they are known
+#[derive(Message)]
+#[rtype(result = "Vec<NotifyInstanceValues>")]
+pub struct GetAllValues;
+
+/// Parameters and reports of an instance; drivers send them after writing to or reading from the hardware, and
+/// either may be partial or null to leave the cached values of the other alone
 #[derive(Serialize, Deserialize, Clone, Debug, Message)]
 #[rtype(result = "()")]
 pub struct NotifyInstanceValues {
@@ -17,7 +17,7 @@ use audiocloud_api::newtypes::FixedInstanceId;
 
 use crate::info;
 use crate::supervisor::get_driver_supervisor;
-use crate::{Command, Event};
+use crate::{Command, Event, GetValues};
 
 #[derive(Args, Clone, Debug)]
 pub struct NatsOpts {
@@ -38,7 +38,12 @@ pub async fn init(opts: NatsOpts, instances: HashSet<FixedInstanceId>) -> anyhow
         let subscription = connection.subscribe(&format!("ac.inst.{manufacturer}.{model}.{instance}.cmds"))
                                      .await?;
 
-        spawn(handle_commands(subscription, instance_id));
+        spawn(handle_commands(subscription, instance_id.clone()));
+
+        let subscription = connection.subscribe(&format!("ac.inst.{manufacturer}.{model}.{instance}.values"))
+                                     .await?;
+
+        spawn(handle_values_requests(subscription, instance_id));
     }
 
     NATS.set(connection)
@@ -89,6 +94,32 @@ async fn handle_commands(subscription: nats_aflowt::Subscription, instance_id: F
     error!("Leaving command receive loop")
 }
 
+/// Answer requests for the cached values of an instance, which the command subject has no command for
+#[instrument(skip_all, fields(%instance_id))]
+async fn handle_values_requests(subscription: nats_aflowt::Subscription, instance_id: FixedInstanceId) {
+    while let Some(msg) = subscription.next().await {
+        let request = GetValues { instance_id: instance_id.clone(), };
+
+        match get_driver_supervisor().send(request).await {
+            Ok(response) => {
+                let response = match response {
+                    Ok(values) => SerializableResult::Ok(values),
+                    Err(err) => SerializableResult::Error(err),
+                };
+
+                if let Ok(encoded) = Json.serialize(&response) {
+                    let _ = msg.respond(encoded).await;
+                }
+            }
+            Err(err) => {
+                error!(%err, "Error from supervisor");
+            }
+        }
+    }
+
+    error!("Leaving values request receive loop")
+}
+
 #[derive(Default)]
 pub struct NatsService;
 
@@ -6,10 +6,11 @@ use audiocloud_api::instance_driver::InstanceDriverCommand;
 use audiocloud_api::newtypes::FixedInstanceId;
 
 use crate::supervisor::get_driver_supervisor;
-use crate::{Command, GetInstances, GetValues};
+use crate::{Command, GetAllValues, GetInstances, GetValues};
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
-    cfg.service(get_parameters)
+    cfg.service(get_all_values)
+       .service(get_parameters)
        .service(get_instances)
        .service(set_parameters)
        .service(set_parameter)
@@ -30,6 +31,17 @@ async fn get_instances() -> impl Responder {
     Ok::<_, Error>(web::Json(rv))
 }
 
+#[get("/values")]
+async fn get_all_values() -> impl Responder {
+    let command = GetAllValues;
+
+    let rv = get_driver_supervisor().send(command)
+                                    .await
+                                    .map_err(ErrorInternalServerError)?;
+
+    Ok::<_, Error>(web::Json(rv))
+}
+
 #[get("/{manufacturer}/{name}/{instance}")]
 async fn get_parameters(path: web::Path<(String, String, String)>) -> impl Responder {
     let instance_id = get_instance_id(path.into_inner());
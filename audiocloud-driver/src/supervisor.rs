@@ -1,16 +1,24 @@
 use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 
 use actix::fut::LocalBoxActorFuture;
-use actix::{fut, Actor, ActorFutureExt, Addr, Context, Handler, Recipient, Response, WrapFuture};
-use actix_broker::BrokerSubscribe;
+use actix::{
+    fut, Actor, ActorFutureExt, Addr, AsyncContext, Context, ContextFutureSpawner, Handler, Recipient, Response,
+    WrapFuture,
+};
+use actix_broker::{BrokerIssue, BrokerSubscribe};
 use once_cell::sync::OnceCell;
+use serde_json::Value;
 use tracing::*;
 
-use audiocloud_api::instance_driver::InstanceDriverError;
+use audiocloud_api::instance_driver::{InstanceDriverCommand, InstanceDriverError, InstanceDriverEvent};
 use audiocloud_api::newtypes::FixedInstanceId;
 
 use crate::nats::NatsOpts;
-use crate::{nats, Command, ConfigFile, GetInstances, GetValues, InstanceConfig, NotifyInstanceValues};
+use crate::values::{merge_parameters, merge_reports, ValueStore};
+use crate::{
+    nats, Command, ConfigFile, Event, GetAllValues, GetInstances, GetValues, InstanceConfig, NotifyInstanceValues,
+};
 
 static SUPERVISOR_ADDR: OnceCell<Addr<DriverSupervisor>> = OnceCell::new();
 
@@ -23,8 +31,8 @@ pub fn get_driver_supervisor() -> Addr<DriverSupervisor> {
     }
 }
 
-pub async fn init(nats_opts: NatsOpts, config: ConfigFile) -> anyhow::Result<()> {
-    let supervisor = DriverSupervisor::new(nats_opts, config).await?;
+pub async fn init(nats_opts: NatsOpts, config: ConfigFile, values_file: Option<PathBuf>) -> anyhow::Result<()> {
+    let supervisor = DriverSupervisor::new(nats_opts, config, values_file).await?;
 
     SUPERVISOR_ADDR.set(supervisor.start())
                    .expect("Driver supervisor already initialized");
@@ -39,6 +47,7 @@ pub async fn init(nats_opts: NatsOpts, config: ConfigFile) -> anyhow::Result<()>
 pub struct DriverSupervisor {
     instances: HashMap<FixedInstanceId, Recipient<Command>>,
     values:    HashMap<FixedInstanceId, NotifyInstanceValues>,
+    store:     ValueStore,
 }
 
 impl Handler<Command> for DriverSupervisor {
@@ -46,11 +55,22 @@ impl Handler<Command> for DriverSupervisor {
 
     fn handle(&mut self, msg: Command, _ctx: &mut Context<Self>) -> Self::Result {
         let instance_id = msg.instance_id.clone();
+        let parameters = match &msg.command {
+            InstanceDriverCommand::SetParameters(parameters) => Some(parameters.clone()),
+            _ => None,
+        };
+
         if let Some(instance) = self.instances.get(&instance_id) {
             instance.send(msg)
                     .into_actor(self)
-                    .map(move |res, _, _| match res {
+                    .map(move |res, actor, _| match res {
                         Err(_) => Err(InstanceDriverError::InstanceNotFound(instance_id)),
+                        Ok(Ok(())) => {
+                            if let Some(parameters) = parameters {
+                                actor.parameters_written(&instance_id, &parameters);
+                            }
+                            Ok(())
+                        }
                         Ok(res) => res,
                     })
                     .boxed_local()
@@ -73,7 +93,36 @@ impl Handler<NotifyInstanceValues> for DriverSupervisor {
     type Result = ();
 
     fn handle(&mut self, msg: NotifyInstanceValues, _ctx: &mut Self::Context) -> Self::Result {
-        self.values.insert(msg.instance_id.clone(), msg);
+        if !self.instances.contains_key(&msg.instance_id) {
+            return;
+        }
+
+        if !self.values.contains_key(&msg.instance_id) {
+            let values = self.instance_values(&msg.instance_id);
+            self.values.insert(msg.instance_id.clone(), values);
+        }
+
+        let values = self.values.get_mut(&msg.instance_id).expect("values cached");
+
+        if !msg.parameters.is_null() {
+            merge_parameters(&mut values.parameters, &msg.parameters);
+        }
+
+        merge_reports(&mut values.reports, &msg.reports);
+    }
+}
+
+impl Handler<Event> for DriverSupervisor {
+    type Result = ();
+
+    fn handle(&mut self, msg: Event, ctx: &mut Self::Context) -> Self::Result {
+        // drivers emit reports as events for the domain, the cache keeps them for GetValues
+        if let InstanceDriverEvent::Reports { reports } = msg.event {
+            let mut values = NotifyInstanceValues::new(msg.instance_id);
+            values.reports = reports;
+
+            ctx.notify(values);
+        }
     }
 }
 
@@ -81,15 +130,27 @@ impl Handler<GetValues> for DriverSupervisor {
     type Result = Result<NotifyInstanceValues, InstanceDriverError>;
 
     fn handle(&mut self, msg: GetValues, _ctx: &mut Self::Context) -> Self::Result {
-        match self.values.get(&msg.instance_id) {
-            None => Err(InstanceDriverError::InstanceNotFound(msg.instance_id.clone())),
-            Some(values) => Ok(values.clone()),
+        if !self.instances.contains_key(&msg.instance_id) {
+            return Err(InstanceDriverError::InstanceNotFound(msg.instance_id));
         }
+
+        Ok(self.instance_values(&msg.instance_id))
+    }
+}
+
+impl Handler<GetAllValues> for DriverSupervisor {
+    type Result = Response<Vec<NotifyInstanceValues>>;
+
+    fn handle(&mut self, _msg: GetAllValues, _ctx: &mut Self::Context) -> Self::Result {
+        Response::reply(self.instances
+                            .keys()
+                            .map(|instance_id| self.instance_values(instance_id))
+                            .collect())
     }
 }
 
 impl DriverSupervisor {
-    pub async fn new(nats_opts: NatsOpts, config: ConfigFile) -> anyhow::Result<Self> {
+    pub async fn new(nats_opts: NatsOpts, config: ConfigFile, values_file: Option<PathBuf>) -> anyhow::Result<Self> {
         let mut instances = HashMap::new();
 
         for (id, config) in config {
@@ -97,11 +158,80 @@ impl DriverSupervisor {
             instances.insert(id, instance);
         }
 
+        let store = ValueStore::load(values_file)?;
+        let values = store.iter()
+                          .filter(|(id, _)| instances.contains_key(id))
+                          .map(|(id, parameters)| {
+                              let mut values = NotifyInstanceValues::new(id.clone());
+                              values.parameters = parameters.clone();
+                              (id.clone(), values)
+                          })
+                          .collect();
+
         let instance_ids = instances.keys().cloned().collect::<HashSet<_>>();
         nats::init(nats_opts, instance_ids).await?;
 
         Ok(Self { instances,
-                  values: Default::default() })
+                  values,
+                  store })
+    }
+
+    /// Write the stored parameters back to the hardware, which lost or never had them after a restart
+    fn reapply_stored_values(&mut self, ctx: &mut Context<Self>) {
+        for (instance_id, instance) in &self.instances {
+            if let Some(parameters) = self.store.get(instance_id) {
+                let instance_id = instance_id.clone();
+                let command = Command { instance_id: instance_id.clone(),
+                                        command:     InstanceDriverCommand::SetParameters(parameters.clone()), };
+
+                instance.send(command)
+                        .into_actor(self)
+                        .map(move |res, actor, _| match res {
+                            Ok(Ok(())) => {
+                                info!(%instance_id, "Reapplied stored parameter values");
+                                actor.notify_values(&instance_id);
+                            }
+                            Ok(Err(error)) => warn!(%instance_id, ?error, "Failed to reapply stored parameter values"),
+                            Err(error) => warn!(%instance_id, %error, "Instance not reachable to reapply values"),
+                        })
+                        .spawn(ctx);
+            }
+        }
+    }
+
+    fn parameters_written(&mut self, instance_id: &FixedInstanceId, parameters: &Value) {
+        let stored = self.store.update(instance_id, parameters).clone();
+        if let Err(error) = self.store.save() {
+            warn!(%instance_id, %error, "Failed to store parameter values");
+        }
+
+        self.values
+            .entry(instance_id.clone())
+            .or_insert_with(|| NotifyInstanceValues::new(instance_id.clone()))
+            .parameters = stored;
+
+        self.notify_values(instance_id);
+    }
+
+    /// Cached values of an instance, or the parameters last written to it if it has not sent any values yet
+    fn instance_values(&self, instance_id: &FixedInstanceId) -> NotifyInstanceValues {
+        match self.values.get(instance_id) {
+            Some(values) => values.clone(),
+            None => {
+                let mut values = NotifyInstanceValues::new(instance_id.clone());
+                if let Some(parameters) = self.store.get(instance_id) {
+                    values.parameters = parameters.clone();
+                }
+                values
+            }
+        }
+    }
+
+    /// Broadcast everything known about the values of an instance
+    fn notify_values(&mut self, instance_id: &FixedInstanceId) {
+        if let Some(values) = self.values.get(instance_id) {
+            self.issue_system_async(values.clone());
+        }
     }
 }
 
@@ -111,5 +241,7 @@ impl Actor for DriverSupervisor {
     fn started(&mut self, ctx: &mut Self::Context) {
         warn!("Restarting instance_driver supervisor");
         self.subscribe_system_async::<NotifyInstanceValues>(ctx);
+        self.subscribe_system_async::<Event>(ctx);
+        self.reapply_stored_values(ctx);
     }
 }
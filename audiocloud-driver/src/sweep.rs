@@ -0,0 +1,333 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use clap::Args;
+use nats_aflowt::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::time::{sleep, timeout};
+use tracing::*;
+
+use audiocloud_api::api::codec::{Codec, Json};
+use audiocloud_api::common::error::SerializableResult;
+use audiocloud_api::instance_driver::{InstanceDriverCommand, InstanceDriverError};
+use audiocloud_api::newtypes::FixedInstanceId;
+use audiocloud_api::{Model, ModelElementScope, ModelValue, ModelValueOption};
+
+use crate::NotifyInstanceValues;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Args, Clone, Debug)]
+pub struct SweepOpts {
+    /// NATS URL the driver is connected to
+    #[clap(long, env, default_value = "nats://localhost:4222")]
+    pub nats_url: String,
+
+    /// Instance to sweep, as `manufacturer:name:instance`
+    #[clap(long, env)]
+    pub instance_id: String,
+
+    /// YAML file of the model of the instance, as the domain reads them from its models folder
+    #[clap(long, env)]
+    pub model: PathBuf,
+
+    /// Milliseconds to wait after writing each value before reading the values of the instance back
+    #[clap(long, env, default_value = "250")]
+    pub dwell_ms: u64,
+
+    /// Number of evenly spaced values to write across each numeric range of a parameter, ends included
+    #[clap(long, env, default_value = "5")]
+    pub steps: usize,
+
+    /// Largest difference between a numeric value written and the one read back that is not an anomaly
+    #[clap(long, env, default_value = "0.01")]
+    pub tolerance: f64,
+
+    /// Milliseconds the driver has to reply to a command or a values request
+    #[clap(long, env, default_value = "1000")]
+    pub reply_timeout_ms: u64,
+
+    /// Only sweep parameters whose name contains this string
+    #[clap(long, env)]
+    pub only: Option<String>,
+}
+
+/// Something the driver did not do as the model says it should
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SweepAnomaly {
+    pub parameter: String,
+    pub value:     Value,
+    pub message:   String,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SweepReport {
+    pub instance_id: String,
+    pub parameters:  usize,
+    pub steps:       usize,
+    pub anomalies:   Vec<SweepAnomaly>,
+    pub elapsed_ms:  u64,
+}
+
+impl SweepReport {
+    pub fn passed(&self) -> bool {
+        self.anomalies.is_empty()
+    }
+}
+
+/// The driver being tested, through the same NATS subjects the domain uses for the instance
+struct DriverUnderTest {
+    connection:      Connection,
+    command_subject: String,
+    values_subject:  String,
+    reply_timeout:   Duration,
+}
+
+impl DriverUnderTest {
+    async fn connect(opts: &SweepOpts, instance_id: &FixedInstanceId) -> anyhow::Result<Self> {
+        let connection = nats_aflowt::connect(opts.nats_url.as_str()).await?;
+        let subject = format!("ac.inst.{}.{}.{}",
+                              instance_id.manufacturer, instance_id.name, instance_id.instance);
+
+        Ok(Self { connection:      { connection },
+                  command_subject: { format!("{subject}.cmds") },
+                  values_subject:  { format!("{subject}.values") },
+                  reply_timeout:   { Duration::from_millis(opts.reply_timeout_ms) }, })
+    }
+
+    async fn request(&self, subject: &str, payload: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        let reply = match timeout(self.reply_timeout, self.connection.request(subject, &payload)).await {
+            Ok(reply) => reply?,
+            Err(_) => return Err(anyhow!("No reply within {:?}", self.reply_timeout)),
+        };
+
+        Ok(reply.data)
+    }
+
+    async fn command(&self, cmd: InstanceDriverCommand) -> anyhow::Result<SerializableResult<(), InstanceDriverError>> {
+        let reply = self.request(&self.command_subject, Json.serialize(&cmd)?).await?;
+
+        Json.deserialize(&reply)
+            .map_err(|error| anyhow!("Reply does not decode as SerializableResult<(), InstanceDriverError>: {error}"))
+    }
+
+    async fn values(&self) -> anyhow::Result<NotifyInstanceValues> {
+        let reply = self.request(&self.values_subject, vec![]).await?;
+
+        match Json.deserialize::<SerializableResult<NotifyInstanceValues, InstanceDriverError>>(&reply) {
+            Ok(SerializableResult::Ok(values)) => Ok(values),
+            Ok(SerializableResult::Error(error)) => Err(anyhow!("Driver failed to return values: {error}")),
+            Err(error) => Err(anyhow!("Values reply does not decode: {error}")),
+        }
+    }
+}
+
+/// Write every value of every parameter of the model to the instance in turn, reading the values back after each to
+/// check the parameter took the value and the reports are within the ranges of the model
+///
+/// Each parameter is set back to what the driver reported for it before the sweep once its values are done.
+#[instrument(skip_all, err)]
+pub async fn run(opts: &SweepOpts) -> anyhow::Result<SweepReport> {
+    let instance_id =
+        FixedInstanceId::from_str(&opts.instance_id).map_err(|error| anyhow!("Invalid instance ID: {error}"))?;
+    let model = serde_yaml::from_str::<Model>(&std::fs::read_to_string(&opts.model)?)?;
+    let driver = DriverUnderTest::connect(opts, &instance_id).await?;
+    let dwell = Duration::from_millis(opts.dwell_ms);
+    let started = Instant::now();
+
+    let initial = driver.values().await?;
+    let mut report = SweepReport { instance_id: opts.instance_id.clone(),
+                                   ..Default::default() };
+
+    let mut parameters = model.parameters.iter().collect::<Vec<_>>();
+    parameters.sort_by_key(|(id, _)| id.to_string());
+
+    for (id, parameter) in parameters {
+        let name = id.to_string();
+        if matches!(&opts.only, Some(only) if !name.contains(only.as_str())) {
+            continue;
+        }
+
+        let channels = scope_channels(&model, &parameter.scope);
+        report.parameters += 1;
+
+        info!(parameter = %name, channels, "Sweeping");
+
+        for value in sweep_values(&parameter.values, opts.steps) {
+            report.steps += 1;
+
+            let mut anomaly = |message: String| {
+                warn!(parameter = %name, %value, %message, "Anomaly");
+                report.anomalies.push(SweepAnomaly { parameter: { name.clone() },
+                                                     value:     { value.clone() },
+                                                     message:   { message }, });
+            };
+
+            let written = Value::Array(vec![value.clone(); channels]);
+            let cmd = InstanceDriverCommand::SetParameters(parameter_values(&name, written.clone()));
+
+            match driver.command(cmd).await {
+                Ok(SerializableResult::Ok(())) => {}
+                Ok(SerializableResult::Error(error)) => {
+                    anomaly(format!("Driver refused the value: {error}"));
+                    continue;
+                }
+                Err(error) => {
+                    anomaly(format!("{error:#}"));
+                    continue;
+                }
+            }
+
+            sleep(dwell).await;
+
+            let values = match driver.values().await {
+                Ok(values) => values,
+                Err(error) => {
+                    anomaly(format!("{error:#}"));
+                    continue;
+                }
+            };
+
+            if let Err(message) = check_parameter(&written, values.parameters.get(&name), opts.tolerance) {
+                anomaly(message);
+            }
+
+            for (report_id, model_report) in &model.reports {
+                if let Err(message) = check_report(values.reports.get(report_id.to_string()), &model_report.values) {
+                    anomaly(format!("Report {report_id}: {message}"));
+                }
+            }
+        }
+
+        if let Some(value) = initial.parameters.get(&name).filter(|value| !value.is_null()) {
+            let cmd = InstanceDriverCommand::SetParameters(parameter_values(&name, value.clone()));
+            if !matches!(driver.command(cmd).await, Ok(SerializableResult::Ok(()))) {
+                warn!(parameter = %name, "Failed to set the parameter back to its value from before the sweep");
+            }
+        }
+    }
+
+    report.elapsed_ms = started.elapsed().as_millis() as u64;
+
+    Ok(report)
+}
+
+fn parameter_values(name: &str, values: Value) -> Value {
+    let mut parameters = serde_json::Map::new();
+    parameters.insert(name.to_owned(), values);
+
+    Value::Object(parameters)
+}
+
+/// Number of values a parameter or report has, one per channel
+fn scope_channels(model: &Model, scope: &ModelElementScope) -> usize {
+    match scope {
+        ModelElementScope::Global => 1,
+        ModelElementScope::AllInputs => model.inputs.len(),
+        ModelElementScope::AllOutputs => model.outputs.len(),
+        ModelElementScope::Size(size) => *size,
+    }
+}
+
+/// Values to write for the options of a parameter: every single value, and `steps` evenly spaced values across each
+/// numeric range with both ends included
+pub fn sweep_values(options: &[ModelValueOption], steps: usize) -> Vec<Value> {
+    let mut values = vec![];
+
+    for option in options {
+        match option {
+            ModelValueOption::Single(value) => values.push(model_value(value)),
+            ModelValueOption::Range(ModelValue::Number(start), ModelValue::Number(end)) => {
+                let steps = steps.max(2);
+                for i in 0..steps {
+                    values.push(json!(start + (end - start) * i as f64 / (steps - 1) as f64));
+                }
+            }
+            ModelValueOption::Range(start, end) => {
+                values.push(model_value(start));
+                values.push(model_value(end));
+            }
+        }
+    }
+
+    values.dedup();
+    values
+}
+
+fn model_value(value: &ModelValue) -> Value {
+    match value {
+        ModelValue::String(value) => Value::String(value.clone()),
+        ModelValue::Number(value) => json!(value),
+        ModelValue::Bool(value) => Value::Bool(*value),
+    }
+}
+
+/// Compare the values written to a parameter with the ones the driver reports for it, per channel
+///
+/// Drivers report channels as an array, or as an object with a key per channel in order such as `left` and `right`.
+pub fn check_parameter(written: &Value, reported: Option<&Value>, tolerance: f64) -> Result<(), String> {
+    let reported = match reported {
+        Some(Value::Array(values)) => values.clone(),
+        Some(Value::Object(values)) => values.values().cloned().collect(),
+        Some(Value::Null) | None => return Err("Parameter not reported back".to_owned()),
+        Some(value) => vec![value.clone()],
+    };
+
+    let written = match written {
+        Value::Array(values) => values.clone(),
+        value => vec![value.clone()],
+    };
+
+    if reported.len() < written.len() {
+        return Err(format!("Reported {} channels, {} written",
+                           reported.len(),
+                           written.len()));
+    }
+
+    for (channel, (written, reported)) in written.iter().zip(&reported).enumerate() {
+        let matches = match (written.as_f64(), reported.as_f64()) {
+            (Some(written), Some(reported)) => (written - reported).abs() <= tolerance,
+            _ => written == reported,
+        };
+
+        if !matches {
+            return Err(format!("Channel {channel} reported {reported}, {written} written"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Check the values of a report fall within the options of the model, a report or channel not read yet (null) is fine
+pub fn check_report(reported: Option<&Value>, options: &[ModelValueOption]) -> Result<(), String> {
+    let values = match reported {
+        Some(Value::Array(values)) => values.clone(),
+        Some(Value::Object(values)) => values.values().cloned().collect(),
+        Some(Value::Null) | None => return Ok(()),
+        Some(value) => vec![value.clone()],
+    };
+
+    if options.is_empty() {
+        return Ok(());
+    }
+
+    for (channel, value) in values.iter().enumerate().filter(|(_, value)| !value.is_null()) {
+        let within = options.iter().any(|option| match (option, value.as_f64()) {
+                                       (ModelValueOption::Range(ModelValue::Number(start),
+                                                                ModelValue::Number(end)),
+                                        Some(number)) => start.min(*end) <= number && number <= start.max(*end),
+                                       (ModelValueOption::Single(single), _) => &model_value(single) == value,
+                                       _ => false,
+                                   });
+
+        if !within {
+            return Err(format!("Channel {channel} reported {value}, outside of the model"));
+        }
+    }
+
+    Ok(())
+}
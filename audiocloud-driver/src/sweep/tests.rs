@@ -0,0 +1,36 @@
+use serde_json::json;
+
+use audiocloud_api::{ModelValue, ModelValueOption};
+
+use crate::sweep::{check_parameter, check_report, sweep_values};
+
+#[test]
+fn test_sweep_values_cover_ranges_and_singles() {
+    let options = [ModelValueOption::Single(ModelValue::Bool(false)),
+                   ModelValueOption::Range(ModelValue::Number(-10.0), ModelValue::Number(10.0)),
+                   ModelValueOption::Single(ModelValue::Number(10.0))];
+
+    assert_eq!(sweep_values(&options, 5),
+               vec![json!(false),
+                    json!(-10.0),
+                    json!(-5.0),
+                    json!(0.0),
+                    json!(5.0),
+                    json!(10.0)]);
+}
+
+#[test]
+fn test_values_read_back_are_checked_per_channel() {
+    let written = json!([2.0, 2.0]);
+
+    assert!(check_parameter(&written, Some(&json!([2.001, 2.0])), 0.01).is_ok());
+    assert!(check_parameter(&written, Some(&json!({"left": 2.0, "right": 2.0})), 0.01).is_ok());
+    assert!(check_parameter(&written, Some(&json!([2.0, 3.0])), 0.01).is_err());
+    assert!(check_parameter(&written, Some(&json!([2.0])), 0.01).is_err());
+    assert!(check_parameter(&written, None, 0.01).is_err());
+
+    let meter = [ModelValueOption::Range(ModelValue::Number(-60.0), ModelValue::Number(0.0))];
+
+    assert!(check_report(Some(&json!([-12.0, null])), &meter).is_ok());
+    assert!(check_report(Some(&json!([-12.0, 6.0])), &meter).is_err());
+}
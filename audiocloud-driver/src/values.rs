@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use serde_json::Value;
+use tracing::*;
+
+use audiocloud_api::newtypes::FixedInstanceId;
+
+#[cfg(test)]
+mod tests;
+
+/// Last parameter values written to each instance, kept in a file so they can be reapplied after the driver restarts
+///
+/// Hardware state is otherwise unknown after a restart, as drivers only keep what they wrote in memory.
+#[derive(Debug, Default)]
+pub struct ValueStore {
+    path:       Option<PathBuf>,
+    parameters: HashMap<FixedInstanceId, Value>,
+}
+
+impl ValueStore {
+    /// Load the values stored at `path`, a missing file has none. Without a path values are only kept in memory.
+    pub fn load(path: Option<PathBuf>) -> anyhow::Result<Self> {
+        let parameters = match &path {
+            None => HashMap::new(),
+            Some(path) => match fs::read(path) {
+                Ok(contents) => serde_json::from_slice(&contents)?,
+                Err(error) if error.kind() == ErrorKind::NotFound => {
+                    info!(path = %path.display(), "No stored parameter values yet");
+                    HashMap::new()
+                }
+                Err(error) => return Err(error.into()),
+            },
+        };
+
+        Ok(Self { path, parameters })
+    }
+
+    pub fn get(&self, instance_id: &FixedInstanceId) -> Option<&Value> {
+        self.parameters.get(instance_id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&FixedInstanceId, &Value)> {
+        self.parameters.iter()
+    }
+
+    /// Merge parameters written to an instance into the stored ones and return all of them
+    pub fn update(&mut self, instance_id: &FixedInstanceId, parameters: &Value) -> &Value {
+        let stored = self.parameters
+                         .entry(instance_id.clone())
+                         .or_insert_with(|| Value::Object(Default::default()));
+
+        merge_parameters(stored, parameters);
+
+        stored
+    }
+
+    /// Write the values to the file, through a temporary file so a crash never leaves half of them behind
+    pub fn save(&self) -> anyhow::Result<()> {
+        if let Some(path) = &self.path {
+            let temp = path.with_extension("tmp");
+            fs::write(&temp, serde_json::to_vec_pretty(&self.parameters)?)?;
+            fs::rename(&temp, path)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parameters are written partially, each top level parameter replaces the stored one along with all its channels
+pub fn merge_parameters(stored: &mut Value, update: &Value) {
+    match (stored, update) {
+        (Value::Object(stored), Value::Object(update)) => {
+            for (parameter, value) in update {
+                stored.insert(parameter.clone(), value.clone());
+            }
+        }
+        (stored, update) => *stored = update.clone(),
+    }
+}
+
+/// Reports are partial as well, and a report a driver could not read this time (null) keeps the last known value
+pub fn merge_reports(stored: &mut Value, update: &Value) {
+    match (stored, update) {
+        (_, Value::Null) => {}
+        (Value::Object(stored), Value::Object(update)) => {
+            for (report, value) in update {
+                if !value.is_null() {
+                    stored.insert(report.clone(), value.clone());
+                }
+            }
+        }
+        (stored, update) => *stored = update.clone(),
+    }
+}
@@ -0,0 +1,56 @@
+use std::env;
+
+use serde_json::json;
+
+use audiocloud_api::newtypes::FixedInstanceId;
+
+use crate::values::{merge_parameters, merge_reports, ValueStore};
+
+fn dual_1084() -> FixedInstanceId {
+    FixedInstanceId::new("distopik".to_owned(), "dual1084".to_owned(), "1".to_owned())
+}
+
+#[test]
+fn test_partial_parameter_writes_are_merged() {
+    let mut stored = json!({"low_gain": {"left": 1.0, "right": 1.0}, "eql_toggle": {"left": false, "right": false}});
+
+    merge_parameters(&mut stored, &json!({"low_gain": {"left": 2.0, "right": 3.0}}));
+
+    assert_eq!(stored,
+               json!({"low_gain": {"left": 2.0, "right": 3.0}, "eql_toggle": {"left": false, "right": false}}));
+}
+
+#[test]
+fn test_unread_reports_keep_the_last_known_value() {
+    let mut stored = serde_json::Value::Null;
+
+    merge_reports(&mut stored,
+                  &json!({"power": [true, false, false, false], "current": [0.5, 0.0, 0.0, 0.0]}));
+    merge_reports(&mut stored,
+                  &json!({"power": [true, true, false, false], "current": null}));
+    merge_reports(&mut stored, &serde_json::Value::Null);
+
+    assert_eq!(stored,
+               json!({"power": [true, true, false, false], "current": [0.5, 0.0, 0.0, 0.0]}));
+}
+
+#[test]
+fn test_values_survive_a_restart() -> anyhow::Result<()> {
+    let path = env::temp_dir().join(format!("audiocloud-driver-values-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let mut store = ValueStore::load(Some(path.clone()))?;
+    assert!(store.get(&dual_1084()).is_none());
+
+    store.update(&dual_1084(), &json!({"low_gain": {"left": 2.0, "right": 3.0}}));
+    store.update(&dual_1084(), &json!({"high_gain": {"left": -1.0, "right": 0.0}}));
+    store.save()?;
+
+    let restarted = ValueStore::load(Some(path.clone()))?;
+    assert_eq!(restarted.get(&dual_1084()),
+               Some(&json!({"low_gain": {"left": 2.0, "right": 3.0}, "high_gain": {"left": -1.0, "right": 0.0}})));
+
+    std::fs::remove_file(&path)?;
+
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use std::ffi::c_void;
+use std::ptr::slice_from_raw_parts;
+
+use anyhow::anyhow;
+use bytes::Bytes;
+use libflac_sys::{
+    FLAC__StreamEncoder, FLAC__StreamEncoderWriteStatus, FLAC__byte, FLAC__stream_encoder_delete,
+    FLAC__stream_encoder_finish, FLAC__stream_encoder_init_stream, FLAC__stream_encoder_new,
+    FLAC__stream_encoder_process, FLAC__stream_encoder_set_bits_per_sample, FLAC__stream_encoder_set_channels,
+    FLAC__stream_encoder_set_sample_rate, FLAC__stream_encoder_set_streamable_subset,
+};
+use tracing::*;
+
+use audiocloud_api::audio_engine::CompressedAudio;
+use audiocloud_api::common::media::PlayId;
+
+/// Streaming FLAC encoder producing `CompressedAudio` chunks, the same framing the REAPER plugin uses
+pub struct FlacEncoder {
+    encoder:         *mut FLAC__StreamEncoder,
+    internals:       Box<SharedInternals>,
+    tmp_buffer:      Vec<Vec<i32>>,
+    bits_per_sample: usize,
+    play_id:         PlayId,
+    stream_pos:      u64,
+}
+
+impl Drop for FlacEncoder {
+    fn drop(&mut self) {
+        unsafe { FLAC__stream_encoder_delete(self.encoder) };
+    }
+}
+
+impl FlacEncoder {
+    #[instrument(skip_all, err)]
+    pub fn new(play_id: PlayId, sample_rate: usize, channels: usize, bits_per_sample: usize) -> anyhow::Result<Self> {
+        debug!(sample_rate, channels, bits_per_sample, "enter");
+
+        if bits_per_sample > 16 {
+            return Err(anyhow!("The reference encoder only supports 16-bit encoding"));
+        }
+
+        unsafe {
+            let encoder = FLAC__stream_encoder_new();
+            if encoder.is_null() {
+                return Err(anyhow!("FLAC__stream_encoder_new failed"));
+            }
+
+            let mut internals = Box::new(SharedInternals { buffer: vec![] });
+
+            if FLAC__stream_encoder_set_channels(encoder, channels as u32) != 1
+               || FLAC__stream_encoder_set_bits_per_sample(encoder, bits_per_sample as u32) != 1
+               || FLAC__stream_encoder_set_streamable_subset(encoder, 1) != 1
+               || FLAC__stream_encoder_set_sample_rate(encoder, sample_rate as u32) != 1
+               || FLAC__stream_encoder_init_stream(encoder,
+                                                   Some(write),
+                                                   None,
+                                                   None,
+                                                   None,
+                                                   internals.as_mut() as *mut SharedInternals as *mut c_void)
+                  != 0
+            {
+                FLAC__stream_encoder_delete(encoder);
+                return Err(anyhow!("Failed to initialize FLAC encoder"));
+            }
+
+            Ok(Self { encoder:         { encoder },
+                      internals:       { internals },
+                      tmp_buffer:      { (0..channels).map(|_| Vec::new()).collect() },
+                      bits_per_sample: { bits_per_sample },
+                      play_id:         { play_id },
+                      stream_pos:      { 0 }, })
+        }
+    }
+
+    /// Encode a block of planar samples whose first frame is at `timeline_pos` seconds
+    pub fn process(&mut self, channels: &[Vec<f64>], timeline_pos: f64) -> anyhow::Result<Option<CompressedAudio>> {
+        let converter = match self.bits_per_sample {
+            16 => |s: f64| dasp::sample::conv::f64::to_i16(s.clamp(-1.0, 1.0)) as i32,
+            i => {
+                return Err(anyhow!("Only 16 bits_per_sample supported, not {i}"));
+            }
+        };
+
+        let mut pointers = vec![];
+        for (input, output) in channels.iter().zip(self.tmp_buffer.iter_mut()) {
+            output.clear();
+            output.extend(input.iter().copied().map(converter));
+            pointers.push(output.as_ptr());
+        }
+
+        let len = self.tmp_buffer.iter().map(Vec::len).min().unwrap_or_default();
+        if len == 0 {
+            return Ok(None);
+        }
+
+        unsafe {
+            if FLAC__stream_encoder_process(self.encoder, pointers.as_ptr(), len as u32) != 1 {
+                return Err(anyhow!("FLAC__stream_encoder_process failed"));
+            }
+        }
+
+        Ok(Some(self.take(timeline_pos, len, false)))
+    }
+
+    pub fn finish(&mut self, timeline_pos: f64) -> anyhow::Result<CompressedAudio> {
+        unsafe {
+            if FLAC__stream_encoder_finish(self.encoder) != 1 {
+                return Err(anyhow!("FLAC__stream_encoder_finish failed"));
+            }
+        }
+
+        Ok(self.take(timeline_pos, 0, true))
+    }
+
+    fn take(&mut self, timeline_pos: f64, num_samples: usize, last: bool) -> CompressedAudio {
+        let audio = CompressedAudio { play_id:      { self.play_id },
+                                      timeline_pos: { timeline_pos },
+                                      stream_pos:   { self.stream_pos },
+                                      buffer:       { Bytes::from(std::mem::take(&mut self.internals.buffer)) },
+                                      num_samples:  { num_samples as _ },
+                                      last:         { last }, };
+
+        self.stream_pos += num_samples as u64;
+
+        audio
+    }
+}
+
+unsafe extern "C" fn write(_encoder: *const FLAC__StreamEncoder,
+                           buffer: *const FLAC__byte,
+                           bytes: usize,
+                           _samples: u32,
+                           _current_frame: u32,
+                           client_data: *mut c_void)
+                           -> FLAC__StreamEncoderWriteStatus {
+    let internals = &mut *(client_data as *mut SharedInternals);
+    internals.buffer
+             .extend_from_slice(&*slice_from_raw_parts(buffer as *const u8, bytes));
+
+    0
+}
+
+struct SharedInternals {
+    buffer: Vec<u8>,
+}
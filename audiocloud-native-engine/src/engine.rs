@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use flume::{Receiver, RecvTimeoutError, Sender};
+use tracing::*;
+
+use audiocloud_api::audio_engine::command::EngineCommand;
+use audiocloud_api::audio_engine::event::EngineEvent;
+use audiocloud_api::newtypes::AppTaskId;
+
+use crate::media::MediaCache;
+use crate::output::LocalOutput;
+use crate::render::RenderOpts;
+use crate::task::NativeTask;
+
+pub type EngineCommandWithResultSender = (EngineCommand, Sender<anyhow::Result<()>>);
+
+pub struct NativeEngine {
+    shared_media_root: PathBuf,
+    sample_rate:       usize,
+    block_size:        usize,
+    media:             MediaCache,
+    tasks:             HashMap<AppTaskId, NativeTask>,
+    output:            Option<LocalOutput>,
+    render_opts:       RenderOpts,
+    rx_cmd:            Receiver<EngineCommandWithResultSender>,
+    tx_evt:            Sender<EngineEvent>,
+}
+
+impl NativeEngine {
+    pub fn new(shared_media_root: PathBuf,
+               sample_rate: usize,
+               block_size: usize,
+               output: Option<LocalOutput>,
+               render_opts: RenderOpts,
+               rx_cmd: Receiver<EngineCommandWithResultSender>,
+               tx_evt: Sender<EngineEvent>)
+               -> Self {
+        Self { shared_media_root: { shared_media_root },
+               sample_rate:       { sample_rate },
+               block_size:        { block_size },
+               media:             { MediaCache::new(sample_rate) },
+               tasks:             { HashMap::new() },
+               output:            { output },
+               render_opts:       { render_opts },
+               rx_cmd:            { rx_cmd },
+               tx_evt:            { tx_evt }, }
+    }
+
+    /// Process a block every `block_size` frames of wall clock time, handling commands in between
+    pub fn run(mut self) {
+        let period = Duration::from_secs_f64(self.block_size as f64 / self.sample_rate as f64);
+        let mut next_block = Instant::now() + period;
+
+        loop {
+            match self.rx_cmd.recv_deadline(next_block) {
+                Ok((cmd, sender)) => {
+                    if let Err(err) = sender.send(self.dispatch_cmd(cmd)) {
+                        warn!(%err, "failed to send response to command");
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    self.process_block();
+                    next_block += period;
+
+                    // if we fell behind by more than a block, skip ahead instead of bursting to catch up
+                    let now = Instant::now();
+                    if next_block + period < now {
+                        warn!(behind_ms = (now - next_block).as_millis() as u64,
+                              "processing fell behind");
+                        next_block = now + period;
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    warn!("command channel closed, stopping");
+                    return;
+                }
+            }
+        }
+    }
+
+    fn process_block(&mut self) {
+        let mut monitor = vec![vec![0.0f64; self.block_size]; 2];
+
+        for task in self.tasks.values_mut() {
+            if let Err(err) = task.process(self.block_size, &mut monitor) {
+                warn!(%err, task_id = %task.id(), "failed to process block");
+            }
+
+            while let Some(event) = task.events.pop_front() {
+                debug!(?event, "emitting");
+                let _ = self.tx_evt.try_send(event);
+            }
+        }
+
+        if let Some(output) = self.output.as_mut() {
+            output.write(&monitor);
+        }
+    }
+
+    #[instrument(skip_all, err)]
+    fn dispatch_cmd(&mut self, cmd: EngineCommand) -> anyhow::Result<()> {
+        use EngineCommand::*;
+
+        debug!(?cmd, "entered");
+
+        match cmd {
+            SetSpec { task_id,
+                      spec,
+                      media_ready,
+                      .. } => {
+                if let Some(task) = self.tasks.get_mut(&task_id) {
+                    task.set_spec(spec, media_ready, &mut self.media)?;
+                } else {
+                    let mut task = NativeTask::new(task_id.clone(), self.shared_media_root.clone(), self.sample_rate);
+                    task.set_spec(spec, media_ready, &mut self.media)?;
+                    self.tasks.insert(task_id, task);
+                }
+            }
+            Media { task_id, media_ready } => {
+                if let Some(task) = self.tasks.get_mut(&task_id) {
+                    task.on_media_updated(media_ready, &mut self.media)?;
+                }
+            }
+            ModifySpec { task_id,
+                         transaction,
+                         media_ready,
+                         .. } => {
+                self.task_mut(&task_id)?
+                    .modify_spec(transaction, media_ready, &mut self.media)?;
+            }
+            SetDynamicParameterValues { task_id, .. } => {
+                // the native engine has no dynamic instances, so there is nothing to set
+                self.task_mut(&task_id)?;
+            }
+            Render { task_id, render } => {
+                let block_size = self.block_size;
+                let render_opts = self.render_opts.clone();
+                self.task_mut(&task_id)?.render(render, block_size, &render_opts)?;
+            }
+            Play { task_id, play } => {
+                self.task_mut(&task_id)?.play(play)?;
+            }
+            UpdatePlay { task_id, update } => {
+                self.task_mut(&task_id)?.update_play(update)?;
+            }
+            CancelRender { task_id, render_id } => {
+                self.task_mut(&task_id)?.cancel_render(render_id)?;
+            }
+            StopPlay { task_id, play_id } => {
+                self.task_mut(&task_id)?.stop_play(play_id)?;
+            }
+            Instances { task_id, instances } => {
+                if !instances.is_empty() {
+                    warn!(%task_id, "the native engine has no fixed instances, ignoring instance routing");
+                }
+            }
+            Close { task_id } => {
+                if let Some(mut task) = self.tasks.remove(&task_id) {
+                    task.close();
+                    while let Some(event) = task.events.pop_front() {
+                        let _ = self.tx_evt.try_send(event);
+                    }
+                } else {
+                    return Err(anyhow!("Task not found"));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn task_mut(&mut self, task_id: &AppTaskId) -> anyhow::Result<&mut NativeTask> {
+        self.tasks
+            .get_mut(task_id)
+            .ok_or_else(|| anyhow!("Task {task_id} not found"))
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use anyhow::anyhow;
+
+use audiocloud_api::common::task::{NodeConnection, TaskSpec, TimeSegment};
+use audiocloud_api::newtypes::{MixerNodeId, NodeConnectionId, TrackMediaId, TrackNodeId};
+use audiocloud_api::{ChannelMask, InputPadId, NodePadId, OutputPadId, PadMetering};
+
+use crate::media::DecodedMedia;
+
+#[cfg(test)]
+mod tests;
+
+pub type Buffers = Vec<Vec<f64>>;
+
+/// Gain and pan of a connection, changed at runtime without rebuilding the graph
+#[derive(Copy, Clone, Debug)]
+pub struct ConnectionGain {
+    pub volume: f64,
+    pub pan:    f64,
+}
+
+impl Default for ConnectionGain {
+    fn default() -> Self {
+        Self { volume: { 1.0 },
+               pan:    { 0.0 }, }
+    }
+}
+
+#[derive(Clone)]
+struct GraphMediaItem {
+    media:          Arc<DecodedMedia>,
+    timeline_start: usize,
+    timeline_end:   usize,
+    media_start:    usize,
+}
+
+#[derive(Clone)]
+struct GraphTrack {
+    id:       TrackNodeId,
+    channels: usize,
+    items:    Vec<GraphMediaItem>,
+}
+
+#[derive(Clone)]
+struct GraphMixer {
+    id:              MixerNodeId,
+    input_channels:  usize,
+    output_channels: usize,
+}
+
+/// Connection from the output of a track or mixer to the input of a mixer, with the channels it maps
+#[derive(Clone)]
+struct GraphConnection {
+    from:          NodePadId,
+    to:            MixerNodeId,
+    from_channels: Vec<usize>,
+    to_channels:   Vec<usize>,
+}
+
+impl GraphConnection {
+    fn new(connection: &NodeConnection) -> anyhow::Result<Self> {
+        let to = match (&connection.from, &connection.to) {
+            (OutputPadId::TrackOutput(_) | OutputPadId::MixerOutput(_), InputPadId::MixerInput(mixer_id)) => {
+                mixer_id.clone()
+            }
+            (from, to) => return Err(anyhow!("The native engine can not connect {from} to {to}")),
+        };
+
+        Ok(Self { from:          { NodePadId::from(connection.from.clone()) },
+                  to:            { to },
+                  from_channels: { mask_channels(connection.from_channels) },
+                  to_channels:   { mask_channels(connection.to_channels) }, })
+    }
+}
+
+/// Tracks feeding mixers, evaluated block by block
+///
+/// Mixers are kept in dependency order, so each mixer is evaluated after everything connected to its input.
+#[derive(Clone)]
+pub struct Graph {
+    tracks:      Vec<GraphTrack>,
+    mixers:      Vec<GraphMixer>,
+    connections: HashMap<NodeConnectionId, GraphConnection>,
+}
+
+impl Graph {
+    pub fn new(spec: &TaskSpec,
+               media: &HashMap<(TrackNodeId, TrackMediaId), Arc<DecodedMedia>>,
+               sample_rate: usize)
+               -> anyhow::Result<Self> {
+        if !spec.fixed.is_empty() {
+            return Err(anyhow!("The native engine does not support fixed instances"));
+        }
+
+        if !spec.dynamic.is_empty() {
+            return Err(anyhow!("The native engine does not support dynamic instances"));
+        }
+
+        let to_frames = |seconds: f64| (seconds.max(0.0) * sample_rate as f64).round() as usize;
+
+        let tracks = spec.tracks
+                         .iter()
+                         .map(|(track_id, track)| {
+                             let items = track.media
+                                              .iter()
+                                              .filter_map(|(media_id, item)| {
+                                                  media.get(&(track_id.clone(), media_id.clone())).map(|media| {
+                                                      let TimeSegment { start, length } = item.timeline_segment;
+                                                      GraphMediaItem { media:          { media.clone() },
+                                                                       timeline_start: { to_frames(start) },
+                                                                       timeline_end:   { to_frames(start + length) },
+                                                                       media_start:    {
+                                                                           to_frames(item.media_segment.start)
+                                                                       }, }
+                                                  })
+                                              })
+                                              .collect();
+
+                             GraphTrack { id:       { track_id.clone() },
+                                          channels: { track.channels.num_channels() },
+                                          items:    { items }, }
+                         })
+                         .collect();
+
+        let connections =
+            spec.connections
+                .iter()
+                .map(|(connection_id, connection)| Ok((connection_id.clone(), GraphConnection::new(connection)?)))
+                .collect::<anyhow::Result<HashMap<_, _>>>()?;
+
+        let mixers = spec.mixers
+                         .iter()
+                         .map(|(mixer_id, mixer)| GraphMixer { id:              { mixer_id.clone() },
+                                                               input_channels:  { mixer.input_channels },
+                                                               output_channels: { mixer.output_channels }, })
+                         .collect();
+
+        Ok(Self { tracks:      { tracks },
+                  mixers:      { sort_mixers(mixers, &connections)? },
+                  connections: { connections }, })
+    }
+
+    pub fn has_mixer(&self, mixer_id: &MixerNodeId) -> bool {
+        self.mixers.iter().any(|mixer| &mixer.id == mixer_id)
+    }
+
+    pub fn mixer_output_channels(&self, mixer_id: &MixerNodeId) -> Option<usize> {
+        self.mixers
+            .iter()
+            .find(|mixer| &mixer.id == mixer_id)
+            .map(|mixer| mixer.output_channels)
+    }
+
+    /// Evaluate `len` frames starting at timeline frame `position`, returning the output of every pad
+    pub fn process(&self,
+                   position: usize,
+                   len: usize,
+                   gains: &HashMap<NodeConnectionId, ConnectionGain>)
+                   -> HashMap<NodePadId, Buffers> {
+        let mut pads = HashMap::new();
+
+        for track in &self.tracks {
+            let mut buffers = vec![vec![0.0; len]; track.channels];
+
+            for item in &track.items {
+                let start = item.timeline_start.max(position);
+                let end = item.timeline_end.min(position + len);
+                let media_channels = item.media.channels.len();
+
+                for frame in start..end {
+                    let media_frame = item.media_start + frame - item.timeline_start;
+                    if media_frame >= item.media.num_frames() {
+                        break;
+                    }
+
+                    for (channel, buffer) in buffers.iter_mut().enumerate() {
+                        // mono media plays on every channel of the track
+                        let source = if media_channels == 1 { 0 } else { channel };
+                        buffer[frame - position] += item.media.sample(source, media_frame) as f64;
+                    }
+                }
+            }
+
+            pads.insert(NodePadId::TrackOutput(track.id.clone()), buffers);
+        }
+
+        for mixer in &self.mixers {
+            let mut input = vec![vec![0.0; len]; mixer.input_channels];
+            for (connection_id, connection) in self.connections.iter().filter(|(_, conn)| conn.to == mixer.id) {
+                if let Some(source) = pads.get(&connection.from) {
+                    let gain = gains.get(connection_id).copied().unwrap_or_default();
+                    mix_connection(source, &mut input, connection, gain);
+                }
+            }
+
+            let mut output = vec![vec![0.0; len]; mixer.output_channels];
+            for (output, input) in output.iter_mut().zip(input.iter()) {
+                output.copy_from_slice(input);
+            }
+
+            pads.insert(NodePadId::MixerInput(mixer.id.clone()), input);
+            pads.insert(NodePadId::MixerOutput(mixer.id.clone()), output);
+        }
+
+        pads
+    }
+}
+
+/// Per channel peak of every pad
+pub fn peak_meters(pads: &HashMap<NodePadId, Buffers>) -> HashMap<NodePadId, PadMetering> {
+    pads.iter()
+        .map(|(pad_id, buffers)| {
+            let volume = buffers.iter()
+                                .map(|buffer| buffer.iter().fold(0.0f64, |peak, sample| peak.max(sample.abs())))
+                                .collect();
+
+            (pad_id.clone(), PadMetering { volume })
+        })
+        .collect()
+}
+
+fn mask_channels(mask: ChannelMask) -> Vec<usize> {
+    match mask {
+        ChannelMask::Mono(start) => vec![start],
+        ChannelMask::Stereo(start) => vec![start, start + 1],
+    }
+}
+
+fn mix_connection(source: &Buffers, dest: &mut Buffers, connection: &GraphConnection, gain: ConnectionGain) {
+    for (from, to, channel_gain) in connection_gains(&connection.from_channels, &connection.to_channels, gain.pan) {
+        if let (Some(source), Some(dest)) = (source.get(from), dest.get_mut(to)) {
+            let channel_gain = channel_gain * gain.volume;
+            for (dest, source) in dest.iter_mut().zip(source.iter()) {
+                *dest += source * channel_gain;
+            }
+        }
+    }
+}
+
+/// Gain from each source channel to each destination channel of a connection panned by `pan`, -1 to 1
+///
+/// Equal power pan when going from mono to stereo, balance when going from stereo to stereo.
+fn connection_gains(from: &[usize], to: &[usize], pan: f64) -> Vec<(usize, usize, f64)> {
+    let pan = pan.clamp(-1.0, 1.0);
+
+    match (from, to) {
+        ([from], [to]) => vec![(*from, *to, 1.0)],
+        ([from], [left, right]) => {
+            let angle = (pan + 1.0) * std::f64::consts::FRAC_PI_4;
+            vec![(*from, *left, angle.cos()), (*from, *right, angle.sin())]
+        }
+        ([left, right], [to]) => vec![(*left, *to, 0.5), (*right, *to, 0.5)],
+        ([from_left, from_right], [to_left, to_right]) => {
+            vec![(*from_left, *to_left, (1.0 - pan).min(1.0)),
+                 (*from_right, *to_right, (1.0 + pan).min(1.0))]
+        }
+        _ => vec![],
+    }
+}
+
+/// Order mixers so that each comes after every mixer connected to its input
+fn sort_mixers(mut pending: Vec<GraphMixer>,
+               connections: &HashMap<NodeConnectionId, GraphConnection>)
+               -> anyhow::Result<Vec<GraphMixer>> {
+    let mut sorted = vec![];
+    let mut done = HashSet::new();
+
+    while !pending.is_empty() {
+        let ready = pending.iter().position(|mixer| {
+                                      connections.values()
+                                                 .filter(|connection| connection.to == mixer.id)
+                                                 .all(|connection| match &connection.from {
+                                                     NodePadId::MixerOutput(source) => done.contains(source),
+                                                     _ => true,
+                                                 })
+                                  });
+
+        match ready {
+            Some(index) => {
+                let mixer = pending.remove(index);
+                done.insert(mixer.id.clone());
+                sorted.push(mixer);
+            }
+            None => return Err(anyhow!("Mixer connections contain a cycle")),
+        }
+    }
+
+    Ok(sorted)
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::f64::consts::FRAC_1_SQRT_2;
+use std::sync::Arc;
+
+use audiocloud_api::newtypes::{MixerNodeId, NodeConnectionId, TrackNodeId};
+use audiocloud_api::NodePadId;
+
+use crate::graph::{
+    connection_gains, sort_mixers, ConnectionGain, Graph, GraphConnection, GraphMediaItem, GraphMixer, GraphTrack,
+};
+use crate::media::DecodedMedia;
+
+/// Mono track playing `len` frames of a constant level from the start of the timeline
+fn track(id: &str, level: f32, len: usize) -> GraphTrack {
+    let item = GraphMediaItem { media:          { Arc::new(DecodedMedia { channels: vec![vec![level; len]], }) },
+                                timeline_start: { 0 },
+                                timeline_end:   { len },
+                                media_start:    { 0 }, };
+
+    GraphTrack { id:       { TrackNodeId::new(id.to_string()) },
+                 channels: { 1 },
+                 items:    { vec![item] }, }
+}
+
+fn mixer(id: &str) -> GraphMixer {
+    GraphMixer { id:              { MixerNodeId::new(id.to_string()) },
+                 input_channels:  { 2 },
+                 output_channels: { 2 }, }
+}
+
+fn connection(from: NodePadId, to: &str, from_channels: &[usize], to_channels: &[usize]) -> GraphConnection {
+    GraphConnection { from:          { from },
+                      to:            { MixerNodeId::new(to.to_string()) },
+                      from_channels: { from_channels.to_vec() },
+                      to_channels:   { to_channels.to_vec() }, }
+}
+
+fn track_output(id: &str) -> NodePadId {
+    NodePadId::TrackOutput(TrackNodeId::new(id.to_string()))
+}
+
+fn mixer_output(id: &str) -> NodePadId {
+    NodePadId::MixerOutput(MixerNodeId::new(id.to_string()))
+}
+
+fn connections(list: Vec<(&str, GraphConnection)>) -> HashMap<NodeConnectionId, GraphConnection> {
+    list.into_iter()
+        .map(|(id, connection)| (NodeConnectionId::new(id.to_string()), connection))
+        .collect()
+}
+
+fn assert_gains(actual: Vec<(usize, usize, f64)>, expected: &[(usize, usize, f64)]) {
+    assert_eq!(actual.len(), expected.len());
+    for ((from, to, gain), (expected_from, expected_to, expected_gain)) in actual.into_iter().zip(expected) {
+        assert_eq!((from, to), (*expected_from, *expected_to));
+        assert!((gain - expected_gain).abs() < 1e-12,
+                "gain {gain}, expected {expected_gain}");
+    }
+}
+
+#[test]
+fn test_tracks_sum_into_the_mixer_they_are_connected_to() {
+    let graph = Graph { tracks:      { vec![track("kick", 0.25, 8), track("bass", 0.5, 8)] },
+                        mixers:      { vec![mixer("mix")] },
+                        connections: {
+                            connections(vec![("kick_to_mix", connection(track_output("kick"), "mix", &[0], &[0])),
+                                             ("bass_to_mix", connection(track_output("bass"), "mix", &[0], &[0])),
+                                             ("bass_to_mix_right",
+                                              connection(track_output("bass"), "mix", &[0], &[1]))])
+                        }, };
+
+    let gains = HashMap::from([(NodeConnectionId::new("bass_to_mix".to_string()),
+                                ConnectionGain { volume: { 0.5 },
+                                                 pan:    { 0.0 }, })]);
+
+    let pads = graph.process(0, 8, &gains);
+    let output = &pads[&mixer_output("mix")];
+
+    assert_eq!(output[0], vec![0.5; 8], "kick at unity and bass at half volume");
+    assert_eq!(output[1], vec![0.5; 8], "bass alone, at unity");
+    assert_eq!(pads[&track_output("kick")], vec![vec![0.25; 8]]);
+
+    let pads = graph.process(6, 4, &HashMap::new());
+    assert_eq!(pads[&mixer_output("mix")][0],
+               vec![0.75, 0.75, 0.0, 0.0],
+               "silence past the end of the media");
+}
+
+#[test]
+fn test_mono_to_stereo_pans_with_equal_power() {
+    assert_gains(connection_gains(&[0], &[0, 1], -1.0), &[(0, 0, 1.0), (0, 1, 0.0)]);
+    assert_gains(connection_gains(&[0], &[0, 1], 0.0),
+                 &[(0, 0, FRAC_1_SQRT_2), (0, 1, FRAC_1_SQRT_2)]);
+    assert_gains(connection_gains(&[0], &[0, 1], 1.0), &[(0, 0, 0.0), (0, 1, 1.0)]);
+    // pan is clamped to the right
+    assert_gains(connection_gains(&[0], &[0, 1], 3.0), &[(0, 0, 0.0), (0, 1, 1.0)]);
+
+    for pan in [-0.75, -0.3, 0.2, 0.6] {
+        let power = connection_gains(&[0], &[0, 1], pan).iter()
+                                                        .map(|(_, _, gain)| gain * gain)
+                                                        .sum::<f64>();
+        assert!((power - 1.0).abs() < 1e-12, "power {power} at pan {pan}");
+    }
+}
+
+#[test]
+fn test_stereo_to_stereo_pans_as_a_balance() {
+    assert_gains(connection_gains(&[0, 1], &[0, 1], -1.0), &[(0, 0, 1.0), (1, 1, 0.0)]);
+    assert_gains(connection_gains(&[0, 1], &[0, 1], 0.0), &[(0, 0, 1.0), (1, 1, 1.0)]);
+    assert_gains(connection_gains(&[0, 1], &[0, 1], 0.5), &[(0, 0, 0.5), (1, 1, 1.0)]);
+    assert_gains(connection_gains(&[0, 1], &[2, 3], 1.0), &[(0, 2, 0.0), (1, 3, 1.0)]);
+    assert_gains(connection_gains(&[0, 1], &[0], 0.0), &[(0, 0, 0.5), (1, 0, 0.5)]);
+}
+
+#[test]
+fn test_mixers_are_ordered_after_the_mixers_feeding_them() -> anyhow::Result<()> {
+    let chain = connections(vec![("drums_to_bus", connection(mixer_output("drums"), "bus", &[0, 1], &[0, 1])),
+                                 ("bus_to_master", connection(mixer_output("bus"), "master", &[0, 1], &[0, 1])),
+                                 ("kick_to_drums", connection(track_output("kick"), "drums", &[0], &[0]))]);
+
+    let sorted = sort_mixers(vec![mixer("master"), mixer("bus"), mixer("drums")], &chain)?;
+    let sorted = sorted.iter().map(|mixer| mixer.id.to_string()).collect::<Vec<_>>();
+    assert_eq!(sorted, vec!["drums", "bus", "master"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_mixer_cycle_is_rejected() {
+    let cycle = connections(vec![("a_to_b", connection(mixer_output("a"), "b", &[0, 1], &[0, 1])),
+                                 ("b_to_c", connection(mixer_output("b"), "c", &[0, 1], &[0, 1])),
+                                 ("c_to_a", connection(mixer_output("c"), "a", &[0, 1], &[0, 1]))]);
+
+    let error = sort_mixers(vec![mixer("a"), mixer("b"), mixer("c")], &cycle).err()
+                                                                             .expect("cycle rejected");
+    assert!(error.to_string().contains("cycle"));
+
+    let feedback = connections(vec![("a_to_a", connection(mixer_output("a"), "a", &[0, 1], &[0, 1]))]);
+    assert!(sort_mixers(vec![mixer("a")], &feedback).is_err(),
+            "mixer feeding itself");
+}
@@ -0,0 +1,181 @@
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use clap::Parser;
+use tracing::*;
+
+use audiocloud_api::audio_engine::event::EngineEvent;
+use audiocloud_api::{Codec, MsgPack};
+
+use crate::engine::NativeEngine;
+use crate::render::RenderOpts;
+
+mod encoder;
+mod engine;
+mod graph;
+mod media;
+mod output;
+mod render;
+mod task;
+
+#[derive(Parser, Clone, Debug)]
+pub struct Opts {
+    /// NATS URL to connect to
+    #[clap(long, env, default_value = "nats://localhost:4222")]
+    pub nats_url: String,
+
+    /// Subject on which the domain sends commands to this engine
+    #[clap(long, env)]
+    pub nats_cmd_topic: String,
+
+    /// Subject on which this engine publishes events to the domain
+    #[clap(long, env)]
+    pub nats_evt_topic: String,
+
+    /// Root of the media shared with the domain, paths in media_ready are relative to it
+    #[clap(long, env)]
+    pub shared_media_root: PathBuf,
+
+    /// Internal sample rate, media is resampled to it when loaded
+    #[clap(long, env, default_value = "48000")]
+    pub sample_rate: usize,
+
+    /// Frames processed per block, also the granularity of Playing events
+    #[clap(long, env, default_value = "4096")]
+    pub block_size: usize,
+
+    /// Also play the active mixer to a local audio device, use "default" for the system default output
+    #[clap(long, env)]
+    pub output_device: Option<String>,
+
+    /// FFmpeg binary used to encode renders
+    #[clap(long, env, default_value = "ffmpeg")]
+    pub ffmpeg_path: PathBuf,
+
+    /// FFmpeg audio codec renders are encoded with
+    #[clap(long, env, default_value = "flac")]
+    pub render_codec: String,
+
+    /// File extension of rendered files, selects the FFmpeg container
+    #[clap(long, env, default_value = "flac")]
+    pub render_extension: String,
+
+    /// Directory renders are written to, defaults to `renders` in the shared media root
+    #[clap(long, env)]
+    pub render_root: Option<PathBuf>,
+}
+
+fn main() -> anyhow::Result<()> {
+    let _ = dotenv::dotenv();
+
+    tracing_subscriber::fmt().with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+                             .init();
+
+    let opts = Opts::parse();
+    let shared_media_root = opts.shared_media_root.canonicalize()?;
+
+    info!(?shared_media_root, "Shared media located at");
+
+    let (tx_cmd, rx_cmd) = flume::unbounded();
+    let (tx_evt, rx_evt) = flume::unbounded::<EngineEvent>();
+
+    debug!("Connecting to NATS");
+    let connection = nats::connect(&opts.nats_url)?;
+
+    debug!(topic = %opts.nats_cmd_topic, "Subscribing to commands");
+    let subscription = connection.subscribe(&opts.nats_cmd_topic)?;
+
+    thread::spawn(move || {
+        while let Some(msg) = subscription.next() {
+            match MsgPack.deserialize(&msg.data[..]) {
+                Ok(cmd) => {
+                    let (tx, rx) = flume::bounded::<anyhow::Result<()>>(1);
+                    if tx_cmd.send((cmd, tx)).is_err() {
+                        break;
+                    }
+
+                    thread::spawn(move || {
+                        let result = match rx.recv_timeout(Duration::from_millis(500)) {
+                            Err(_) => Err("Request timed out".to_string()),
+                            Ok(Err(err)) => Err(err.to_string()),
+                            Ok(Ok(result)) => Ok(result),
+                        };
+
+                        match MsgPack.serialize(&result) {
+                            Ok(result) => {
+                                if let Err(err) = msg.respond(result) {
+                                    warn!(%err, "failed to send response");
+                                }
+                            }
+                            Err(err) => warn!(%err, "failed to serialize response"),
+                        }
+                    });
+                }
+                Err(err) => warn!(%err, "failed to decode command"),
+            }
+        }
+    });
+
+    // extension commands (hardware track inputs and friends) need REAPER, refuse them so the domain knows right away
+    let ext_topic = format!("{}.ext", opts.nats_cmd_topic);
+    debug!(topic = %ext_topic, "Subscribing to extension commands");
+    let ext_subscription = connection.subscribe(&ext_topic)?;
+
+    thread::spawn(move || {
+        while let Some(msg) = ext_subscription.next() {
+            let result: Result<(), String> =
+                Err("Extension commands are not supported by the native engine".to_string());
+            match MsgPack.serialize(&result) {
+                Ok(result) => {
+                    if let Err(err) = msg.respond(result) {
+                        warn!(%err, "failed to send response");
+                    }
+                }
+                Err(err) => warn!(%err, "failed to serialize response"),
+            }
+        }
+    });
+
+    thread::spawn({
+        let connection = connection.clone();
+        let topic = opts.nats_evt_topic.clone();
+        move || {
+            while let Ok(evt) = rx_evt.recv() {
+                if let Ok(encoded) = MsgPack.serialize(&evt) {
+                    if let Err(err) = connection.publish(&topic, encoded) {
+                        warn!(%err, "failed to publish event");
+                    }
+                }
+            }
+        }
+    });
+
+    let output = match &opts.output_device {
+        Some(device) => Some(output::LocalOutput::open(device, opts.sample_rate)?),
+        None => None,
+    };
+
+    let render_opts = RenderOpts { ffmpeg_path: { opts.ffmpeg_path.clone() },
+                                   codec:       { opts.render_codec.clone() },
+                                   extension:   { opts.render_extension.clone() },
+                                   root:        {
+                                       opts.render_root
+                                           .clone()
+                                           .unwrap_or_else(|| shared_media_root.join("renders"))
+                                   }, };
+
+    info!(sample_rate = opts.sample_rate,
+          block_size = opts.block_size,
+          "init complete");
+
+    NativeEngine::new(shared_media_root,
+                      opts.sample_rate,
+                      opts.block_size,
+                      output,
+                      render_opts,
+                      rx_cmd,
+                      tx_evt).run();
+
+    Ok(())
+}
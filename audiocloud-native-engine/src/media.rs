@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use rubato::{InterpolationParameters, InterpolationType, Resampler, SincFixedIn, WindowFunction};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use tracing::*;
+
+#[cfg(test)]
+mod tests;
+
+/// Media fully decoded to planar samples at the engine sample rate
+#[derive(Debug)]
+pub struct DecodedMedia {
+    pub channels: Vec<Vec<f32>>,
+}
+
+impl DecodedMedia {
+    pub fn num_frames(&self) -> usize {
+        self.channels.first().map(Vec::len).unwrap_or_default()
+    }
+
+    /// Sample at `frame`, silence outside of the media or for channels the media does not have
+    pub fn sample(&self, channel: usize, frame: usize) -> f32 {
+        self.channels
+            .get(channel)
+            .and_then(|channel| channel.get(frame))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// Decoded media shared between tasks, keyed by canonical path
+pub struct MediaCache {
+    sample_rate: usize,
+    media:       HashMap<PathBuf, Arc<DecodedMedia>>,
+}
+
+impl MediaCache {
+    pub fn new(sample_rate: usize) -> Self {
+        Self { sample_rate: { sample_rate },
+               media:       { HashMap::new() }, }
+    }
+
+    pub fn load(&mut self, path: &Path) -> anyhow::Result<Arc<DecodedMedia>> {
+        let path = path.canonicalize()?;
+
+        if let Some(media) = self.media.get(&path) {
+            return Ok(media.clone());
+        }
+
+        let media = Arc::new(decode(&path, self.sample_rate)?);
+        self.media.insert(path, media.clone());
+
+        Ok(media)
+    }
+
+    /// Drop media no task references anymore
+    pub fn purge(&mut self) {
+        self.media.retain(|_, media| Arc::strong_count(media) > 1);
+    }
+}
+
+#[instrument(skip(sample_rate), err)]
+fn decode(path: &Path, sample_rate: usize) -> anyhow::Result<DecodedMedia> {
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let source = MediaSourceStream::new(Box::new(File::open(path)?), Default::default());
+    let probed =
+        symphonia::default::get_probe().format(&hint, source, &FormatOptions::default(), &MetadataOptions::default())?;
+    let mut format = probed.format;
+
+    let track = format.tracks()
+                      .iter()
+                      .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+                      .ok_or_else(|| anyhow!("No decodable audio track"))?;
+
+    let track_id = track.id;
+    let source_rate = track.codec_params
+                           .sample_rate
+                           .ok_or_else(|| anyhow!("Unknown sample rate"))? as usize;
+    let mut decoder = symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+    let mut channels: Vec<Vec<f32>> = vec![];
+
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err.into()),
+        };
+
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(SymphoniaError::DecodeError(err)) => {
+                warn!(%err, "skipping undecodable packet");
+                continue;
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let spec = *decoded.spec();
+        let num_channels = spec.channels.count();
+        let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        buffer.copy_interleaved_ref(decoded);
+
+        channels.resize_with(num_channels, Vec::new);
+        for frame in buffer.samples().chunks(num_channels) {
+            for (channel, sample) in channels.iter_mut().zip(frame) {
+                channel.push(*sample);
+            }
+        }
+    }
+
+    if source_rate != sample_rate {
+        debug!(source_rate, sample_rate, "resampling");
+        channels = resample(&channels, source_rate, sample_rate)?;
+    }
+
+    Ok(DecodedMedia { channels })
+}
+
+/// Frames of input the resampler takes at a time
+const RESAMPLE_CHUNK: usize = 4096;
+
+/// Band-limited sinc resampling of planar channels
+///
+/// Media is decoded once and both plays and offline renders mix straight from it, so it has to be clean enough for
+/// the rendered file and not alias. The output is aligned with the input, without the latency of the filter.
+pub fn resample(input: &[Vec<f32>], from: usize, to: usize) -> anyhow::Result<Vec<Vec<f32>>> {
+    let num_frames = match input.first() {
+        Some(channel) => channel.len(),
+        None => return Ok(vec![]),
+    };
+
+    let ratio = to as f64 / from as f64;
+    let expected = (num_frames as f64 * ratio).round() as usize;
+    let params = InterpolationParameters { sinc_len:            { 256 },
+                                           f_cutoff:            { 0.95 },
+                                           interpolation:       { InterpolationType::Cubic },
+                                           oversampling_factor: { 128 },
+                                           window:              { WindowFunction::BlackmanHarris2 }, };
+
+    let mut resampler = SincFixedIn::<f32>::new(ratio, 1.0, params, RESAMPLE_CHUNK, input.len())?;
+    let delay = resampler.output_delay();
+    let mut output = vec![Vec::with_capacity(expected + delay); input.len()];
+    let mut position = 0;
+
+    // the input is padded with silence until the tail delayed by the filter is out as well
+    while output[0].len() < expected + delay {
+        let needed = resampler.input_frames_next();
+        let chunk = input.iter()
+                         .map(|channel| {
+                             let start = position.min(channel.len());
+                             let end = (position + needed).min(channel.len());
+                             let mut chunk = channel[start..end].to_vec();
+                             chunk.resize(needed, 0.0);
+                             chunk
+                         })
+                         .collect::<Vec<_>>();
+
+        position += needed;
+
+        for (channel, resampled) in output.iter_mut().zip(resampler.process(&chunk, None)?) {
+            channel.extend(resampled);
+        }
+    }
+
+    for channel in &mut output {
+        channel.drain(..delay);
+        channel.truncate(expected);
+    }
+
+    Ok(output)
+}
@@ -0,0 +1,58 @@
+use std::f32::consts::TAU;
+
+use crate::media::{resample, DecodedMedia};
+
+fn sine(frequency: f32, sample_rate: usize, len: usize) -> Vec<f32> {
+    (0..len).map(|frame| (TAU * frequency * frame as f32 / sample_rate as f32).sin() * 0.5)
+            .collect()
+}
+
+#[test]
+fn test_resampled_media_has_the_length_of_the_target_rate() -> anyhow::Result<()> {
+    let input = vec![sine(1000.0, 44_100, 44_100), sine(500.0, 44_100, 44_100)];
+
+    let output = resample(&input, 44_100, 48_000)?;
+    assert_eq!(output.len(), 2);
+    assert!(output.iter().all(|channel| channel.len() == 48_000));
+
+    let output = resample(&input, 44_100, 22_050)?;
+    assert!(output.iter().all(|channel| channel.len() == 22_050));
+
+    assert!(resample(&[], 44_100, 48_000)?.is_empty(), "no channels");
+
+    Ok(())
+}
+
+#[test]
+fn test_resampling_round_trips_in_time_and_level() -> anyhow::Result<()> {
+    let input = vec![sine(1000.0, 48_000, 48_000)];
+
+    let down = resample(&input, 48_000, 44_100)?;
+    let expected = sine(1000.0, 44_100, 44_100);
+    let up = resample(&down, 44_100, 48_000)?;
+
+    assert_eq!(up[0].len(), input[0].len());
+
+    // the edges ring as the filter runs into the silence around the media, the rest matches in time and level
+    for (channel, expected) in [(&down[0], &expected), (&up[0], &input[0])] {
+        let edge = 1024;
+        let error = channel[edge..channel.len() - edge].iter()
+                                                       .zip(&expected[edge..expected.len() - edge])
+                                                       .map(|(sample, expected)| (sample - expected).abs())
+                                                       .fold(0.0f32, f32::max);
+
+        assert!(error < 0.01, "largest error {error}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_decoded_media_is_silent_outside_of_its_frames_and_channels() {
+    let media = DecodedMedia { channels: vec![vec![0.25, 0.5]], };
+
+    assert_eq!(media.num_frames(), 2);
+    assert_eq!(media.sample(0, 1), 0.5);
+    assert_eq!(media.sample(0, 2), 0.0);
+    assert_eq!(media.sample(1, 0), 0.0);
+}
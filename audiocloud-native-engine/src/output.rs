@@ -0,0 +1,83 @@
+use std::collections::VecDeque;
+
+use anyhow::anyhow;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{SampleRate, Stream, StreamConfig};
+use flume::{Receiver, Sender};
+use tracing::*;
+
+/// Blocks queued towards the device before new ones are dropped, bounds the added monitoring latency
+const MAX_QUEUED_BLOCKS: usize = 4;
+
+/// A local audio device playing what the engine monitors, for domains that also listen on site
+pub struct LocalOutput {
+    _stream: Stream,
+    tx:      Sender<Vec<f32>>,
+}
+
+impl LocalOutput {
+    #[instrument(skip_all, err, fields(device = %device))]
+    pub fn open(device: &str, sample_rate: usize) -> anyhow::Result<Self> {
+        let host = cpal::default_host();
+        let device = if device == "default" {
+                         host.default_output_device()
+                     } else {
+                         host.output_devices()?
+                             .find(|candidate| candidate.name().map(|name| name == device).unwrap_or(false))
+                     }.ok_or_else(|| anyhow!("Output device {device} not found"))?;
+
+        let config = StreamConfig { channels:    { 2 },
+                                    sample_rate: { SampleRate(sample_rate as u32) },
+                                    buffer_size: { cpal::BufferSize::Default }, };
+
+        let (tx, rx) = flume::bounded(MAX_QUEUED_BLOCKS);
+        let mut feed = Feed { rx:      { rx },
+                              pending: { VecDeque::new() }, };
+
+        let stream = device.build_output_stream(&config,
+                                                move |data: &mut [f32], _| feed.fill(data),
+                                                |err| warn!(%err, "output stream error"))?;
+        stream.play()?;
+
+        info!(name = ?device.name(), sample_rate, "Local output opened");
+
+        Ok(Self { _stream: { stream },
+                  tx:      { tx }, })
+    }
+
+    /// Queue a stereo block, dropping it if the device is not keeping up
+    pub fn write(&mut self, channels: &[Vec<f64>]) {
+        let len = channels.iter().map(Vec::len).min().unwrap_or_default();
+        let mut interleaved = Vec::with_capacity(len * 2);
+
+        for frame in 0..len {
+            for channel in channels.iter().take(2) {
+                interleaved.push(channel[frame].clamp(-1.0, 1.0) as f32);
+            }
+        }
+
+        if self.tx.try_send(interleaved).is_err() {
+            trace!("output queue full, dropping block");
+        }
+    }
+}
+
+struct Feed {
+    rx:      Receiver<Vec<f32>>,
+    pending: VecDeque<f32>,
+}
+
+impl Feed {
+    fn fill(&mut self, data: &mut [f32]) {
+        while self.pending.len() < data.len() {
+            match self.rx.try_recv() {
+                Ok(block) => self.pending.extend(block),
+                Err(_) => break,
+            }
+        }
+
+        for sample in data.iter_mut() {
+            *sample = self.pending.pop_front().unwrap_or_default();
+        }
+    }
+}
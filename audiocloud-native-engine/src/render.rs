@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use anyhow::anyhow;
+use flume::{Receiver, Sender, TryRecvError};
+use tracing::*;
+
+use audiocloud_api::common::media::{RenderId, RequestRender};
+use audiocloud_api::newtypes::NodeConnectionId;
+use audiocloud_api::NodePadId;
+
+use crate::graph::{ConnectionGain, Graph};
+
+/// How renders are encoded, shared by all tasks of the engine
+#[derive(Clone, Debug)]
+pub struct RenderOpts {
+    pub ffmpeg_path: PathBuf,
+    pub codec:       String,
+    pub extension:   String,
+    pub root:        PathBuf,
+}
+
+pub enum RenderProgress {
+    Completion(f64),
+    Finished(String),
+    Failed(String),
+}
+
+/// An offline bounce of one mixer, mixed as fast as possible on its own thread and encoded by FFmpeg
+///
+/// The render works on a snapshot of the graph, so spec changes while rendering only apply to the next render.
+pub struct BatchRender {
+    pub render_id: RenderId,
+    cancel:        Arc<AtomicBool>,
+    rx_progress:   Receiver<RenderProgress>,
+}
+
+impl BatchRender {
+    pub fn start(render: RequestRender,
+                 graph: Graph,
+                 gains: HashMap<NodeConnectionId, ConnectionGain>,
+                 path: PathBuf,
+                 sample_rate: usize,
+                 block_size: usize,
+                 opts: &RenderOpts)
+                 -> anyhow::Result<Self> {
+        let channels = graph.mixer_output_channels(&render.mixer_id)
+                            .ok_or_else(|| anyhow!("Mixer {} not found", render.mixer_id))?;
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let child = Command::new(&opts.ffmpeg_path).args(["-hide_banner", "-loglevel", "error", "-y", "-f", "f32le"])
+                                                   .args(["-ar", &sample_rate.to_string()])
+                                                   .args(["-ac", &channels.to_string()])
+                                                   .args(["-i", "-", "-c:a", &opts.codec])
+                                                   .arg(&path)
+                                                   .stdin(Stdio::piped())
+                                                   .stdout(Stdio::null())
+                                                   .stderr(Stdio::piped())
+                                                   .spawn()
+                                                   .map_err(|err| anyhow!("Failed to start FFmpeg: {err}"))?;
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (tx_progress, rx_progress) = flume::unbounded();
+
+        let job = RenderJob { render:      { render.clone() },
+                              graph:       { graph },
+                              gains:       { gains },
+                              path:        { path },
+                              channels:    { channels },
+                              sample_rate: { sample_rate },
+                              block_size:  { block_size },
+                              cancel:      { cancel.clone() },
+                              tx_progress: { tx_progress }, };
+
+        thread::spawn(move || job.run(child));
+
+        Ok(Self { render_id:   { render.render_id },
+                  cancel:      { cancel },
+                  rx_progress: { rx_progress }, })
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::SeqCst);
+    }
+
+    /// Latest progress of the render, if any was reported since the last poll
+    pub fn poll(&self) -> Option<RenderProgress> {
+        let mut latest = None;
+
+        loop {
+            match self.rx_progress.try_recv() {
+                Ok(progress @ (RenderProgress::Finished(_) | RenderProgress::Failed(_))) => return Some(progress),
+                Ok(progress) => latest = Some(progress),
+                Err(TryRecvError::Empty) => return latest,
+                Err(TryRecvError::Disconnected) => {
+                    return Some(RenderProgress::Failed("Render thread exited unexpectedly".to_owned()))
+                }
+            }
+        }
+    }
+}
+
+struct RenderJob {
+    render:      RequestRender,
+    graph:       Graph,
+    gains:       HashMap<NodeConnectionId, ConnectionGain>,
+    path:        PathBuf,
+    channels:    usize,
+    sample_rate: usize,
+    block_size:  usize,
+    cancel:      Arc<AtomicBool>,
+    tx_progress: Sender<RenderProgress>,
+}
+
+impl RenderJob {
+    #[instrument(skip_all, fields(render_id = %self.render.render_id))]
+    fn run(self, mut child: Child) {
+        let result = match self.mix(&mut child) {
+            Ok(()) => Self::finish(child),
+            Err(err) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                Err(err)
+            }
+        };
+
+        let progress = match result {
+            Ok(()) => {
+                debug!(path = ?self.path, "render finished");
+                RenderProgress::Finished(self.path.to_string_lossy().to_string())
+            }
+            Err(err) => {
+                warn!(%err, "render failed");
+                let _ = std::fs::remove_file(&self.path);
+                RenderProgress::Failed(err.to_string())
+            }
+        };
+
+        let _ = self.tx_progress.send(progress);
+    }
+
+    fn mix(&self, child: &mut Child) -> anyhow::Result<()> {
+        let start = (self.render.segment.start.max(0.0) * self.sample_rate as f64).round() as usize;
+        let end = (self.render.segment.end().max(0.0) * self.sample_rate as f64).round() as usize;
+        let output_pad = NodePadId::MixerOutput(self.render.mixer_id.clone());
+
+        let mut stdin = child.stdin
+                             .take()
+                             .ok_or_else(|| anyhow!("FFmpeg stdin not available"))?;
+        let mut position = start;
+        let mut interleaved = Vec::with_capacity(self.block_size * self.channels * 4);
+
+        while position < end {
+            if self.cancel.load(Ordering::SeqCst) {
+                return Err(anyhow!("Rendering cancelled"));
+            }
+
+            let len = self.block_size.min(end - position);
+            let pads = self.graph.process(position, len, &self.gains);
+
+            interleaved.clear();
+            for frame in 0..len {
+                for channel in 0..self.channels {
+                    let sample = pads.get(&output_pad)
+                                     .and_then(|buffers| buffers.get(channel))
+                                     .map(|buffer| buffer[frame])
+                                     .unwrap_or_default() as f32;
+                    interleaved.extend_from_slice(&sample.to_le_bytes());
+                }
+            }
+
+            stdin.write_all(&interleaved)
+                 .map_err(|err| anyhow!("Failed to write to FFmpeg: {err}"))?;
+
+            position += len;
+
+            let _ = self.tx_progress
+                        .send(RenderProgress::Completion((position - start) as f64 / (end - start) as f64));
+        }
+
+        Ok(())
+    }
+
+    fn finish(mut child: Child) -> anyhow::Result<()> {
+        let status = child.wait()?;
+        if status.success() {
+            return Ok(());
+        }
+
+        let mut stderr = String::new();
+        if let Some(mut pipe) = child.stderr.take() {
+            let _ = pipe.read_to_string(&mut stderr);
+        }
+
+        Err(anyhow!("FFmpeg exited with {status}: {}", stderr.trim()))
+    }
+}
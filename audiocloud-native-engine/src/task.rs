@@ -0,0 +1,403 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use tracing::*;
+
+use audiocloud_api::audio_engine::event::EngineEvent;
+use audiocloud_api::common::change::{ModifyTaskSpec, UpdateTaskPlay};
+use audiocloud_api::common::media::{PlayId, RenderId, RequestPlay, RequestRender};
+use audiocloud_api::common::task::TaskSpec;
+use audiocloud_api::newtypes::{AppMediaObjectId, AppTaskId, NodeConnectionId};
+use audiocloud_api::NodePadId;
+
+use crate::encoder::FlacEncoder;
+use crate::graph::{peak_meters, ConnectionGain, Graph};
+use crate::media::MediaCache;
+use crate::render::{BatchRender, RenderOpts, RenderProgress};
+
+/// Channels streamed to the client, mono mixers are duplicated to both sides
+const STREAM_CHANNELS: usize = 2;
+
+struct NativePlay {
+    play:     RequestPlay,
+    position: usize,
+    /// Number of times a looping play wrapped back to the start of the segment
+    passes:   u64,
+    encoder:  FlacEncoder,
+}
+
+pub struct NativeTask {
+    id:                AppTaskId,
+    shared_media_root: PathBuf,
+    sample_rate:       usize,
+    spec:              TaskSpec,
+    media_ready:       HashMap<AppMediaObjectId, String>,
+    graph:             Graph,
+    gains:             HashMap<NodeConnectionId, ConnectionGain>,
+    play:              Option<NativePlay>,
+    render:            Option<BatchRender>,
+    pub events:        VecDeque<EngineEvent>,
+}
+
+impl NativeTask {
+    pub fn new(id: AppTaskId, shared_media_root: PathBuf, sample_rate: usize) -> Self {
+        let spec = TaskSpec::default();
+        let graph = Graph::new(&spec, &HashMap::new(), sample_rate).expect("empty graph");
+
+        Self { id:                { id },
+               shared_media_root: { shared_media_root },
+               sample_rate:       { sample_rate },
+               spec:              { spec },
+               media_ready:       { HashMap::new() },
+               graph:             { graph },
+               gains:             { HashMap::new() },
+               play:              { None },
+               render:            { None },
+               events:            { VecDeque::new() }, }
+    }
+
+    pub fn id(&self) -> &AppTaskId {
+        &self.id
+    }
+
+    #[instrument(skip_all, err, fields(id = %self.id))]
+    pub fn set_spec(&mut self,
+                    spec: TaskSpec,
+                    media_ready: HashMap<AppMediaObjectId, String>,
+                    cache: &mut MediaCache)
+                    -> anyhow::Result<()> {
+        if self.spec == spec && self.media_ready == media_ready {
+            debug!("incoming spec is the same, not changing anything");
+            return Ok(());
+        }
+
+        self.rebuild(spec, media_ready, cache)?;
+        self.gains.clear();
+
+        Ok(())
+    }
+
+    #[instrument(skip_all, err, fields(id = %self.id))]
+    pub fn modify_spec(&mut self,
+                       transaction: Vec<ModifyTaskSpec>,
+                       media_ready: HashMap<AppMediaObjectId, String>,
+                       cache: &mut MediaCache)
+                       -> anyhow::Result<()> {
+        let mut spec = self.spec.clone();
+        let mut gains = self.gains.clone();
+
+        for item in transaction {
+            if let ModifyTaskSpec::SetConnectionParameterValues { connection_id, values } = &item {
+                let gain = gains.entry(connection_id.clone()).or_default();
+                if let Some(volume) = values.volume {
+                    gain.volume = volume;
+                }
+                if let Some(pan) = values.pan {
+                    gain.pan = pan;
+                }
+            }
+
+            spec.modify(item).map_err(|error| anyhow!("{error}"))?;
+        }
+
+        // the transaction is only applied if the resulting graph is valid, otherwise the previous one keeps playing
+        self.rebuild(spec, media_ready, cache)?;
+        self.gains = gains;
+        self.gains.retain(|id, _| self.spec.connections.contains_key(id));
+
+        Ok(())
+    }
+
+    pub fn on_media_updated(&mut self,
+                            media_ready: HashMap<AppMediaObjectId, String>,
+                            cache: &mut MediaCache)
+                            -> anyhow::Result<()> {
+        let mut merged = self.media_ready.clone();
+        merged.extend(media_ready);
+
+        self.rebuild(self.spec.clone(), merged, cache)
+    }
+
+    fn rebuild(&mut self,
+               spec: TaskSpec,
+               media_ready: HashMap<AppMediaObjectId, String>,
+               cache: &mut MediaCache)
+               -> anyhow::Result<()> {
+        let mut media = HashMap::new();
+
+        for (track_id, track) in &spec.tracks {
+            for (media_id, item) in &track.media {
+                let object_id = item.object_id.clone().for_app(self.id.app_id.clone());
+
+                // media that is not ready yet is silent until a Media command tells us where it is
+                if let Some(path) = media_ready.get(&object_id) {
+                    match cache.load(&self.shared_media_root.join(path)) {
+                        Ok(decoded) => {
+                            media.insert((track_id.clone(), media_id.clone()), decoded);
+                        }
+                        Err(err) => warn!(%err, %object_id, "failed to load media"),
+                    }
+                }
+            }
+        }
+
+        let graph = Graph::new(&spec, &media, self.sample_rate)?;
+
+        if matches!(&self.play, Some(play) if !graph.has_mixer(&play.play.mixer_id)) {
+            self.stop("Playing mixer was removed from the spec");
+        }
+
+        self.spec = spec;
+        self.media_ready = media_ready;
+        self.graph = graph;
+
+        cache.purge();
+
+        Ok(())
+    }
+
+    pub fn play(&mut self, play: RequestPlay) -> anyhow::Result<()> {
+        let play_sample_rate: usize = play.sample_rate.into();
+        if play_sample_rate != self.sample_rate {
+            return Err(anyhow!("The native engine runs at {} Hz and can not stream at {play_sample_rate} Hz",
+                               self.sample_rate));
+        }
+
+        if !self.graph.has_mixer(&play.mixer_id) {
+            return Err(anyhow!("Mixer {} not found", play.mixer_id));
+        }
+
+        if self.render.is_some() {
+            return Err(anyhow!("Can not play while rendering"));
+        }
+
+        if self.play.is_some() {
+            self.finish_play();
+        }
+
+        let encoder = FlacEncoder::new(play.play_id, self.sample_rate, STREAM_CHANNELS, play.bit_depth.into())?;
+
+        self.play = Some(NativePlay { position: { self.to_frames(play.start_at) },
+                                      passes:   { 0 },
+                                      play:     { play },
+                                      encoder:  { encoder }, });
+
+        Ok(())
+    }
+
+    pub fn update_play(&mut self, update: UpdateTaskPlay) -> anyhow::Result<()> {
+        let position = update.start_at.map(|start_at| self.to_frames(start_at));
+        let play = self.play.as_mut().ok_or_else(|| anyhow!("Not playing"))?;
+
+        if play.play.play_id != update.play_id {
+            return Err(anyhow!("Not playing {}", update.play_id));
+        }
+
+        if let Some(mixer_id) = update.mixer_id {
+            if !self.graph.has_mixer(&mixer_id) {
+                return Err(anyhow!("Mixer {mixer_id} not found"));
+            }
+            play.play.mixer_id = mixer_id;
+        }
+
+        if let Some(segment) = update.segment {
+            play.play.segment = segment;
+        }
+
+        if let Some(looping) = update.looping {
+            play.play.looping = looping;
+        }
+
+        if let Some(position) = position {
+            play.position = position;
+        }
+
+        Ok(())
+    }
+
+    pub fn stop_play(&mut self, play_id: PlayId) -> anyhow::Result<()> {
+        match &self.play {
+            Some(play) if play.play.play_id == play_id => {
+                self.finish_play();
+                Ok(())
+            }
+            _ => Err(anyhow!("Not playing {play_id}")),
+        }
+    }
+
+    pub fn render(&mut self, render: RequestRender, block_size: usize, opts: &RenderOpts) -> anyhow::Result<()> {
+        if self.play.is_some() {
+            return Err(anyhow!("Can not render while playing"));
+        }
+
+        if let Some(existing) = &self.render {
+            return Err(anyhow!("Already rendering {}", existing.render_id));
+        }
+
+        let path = opts.root
+                       .join(self.id.app_id.to_string())
+                       .join(format!("{}.{}", render.render_id, opts.extension));
+
+        self.render = Some(BatchRender::start(render,
+                                              self.graph.clone(),
+                                              self.gains.clone(),
+                                              path,
+                                              self.sample_rate,
+                                              block_size,
+                                              opts)?);
+
+        Ok(())
+    }
+
+    pub fn cancel_render(&mut self, render_id: RenderId) -> anyhow::Result<()> {
+        match &self.render {
+            Some(render) if render.render_id == render_id => {
+                // the render thread reports the cancellation as a failure, which ends the render on the next block
+                render.cancel();
+                Ok(())
+            }
+            _ => Err(anyhow!("Not rendering {render_id}")),
+        }
+    }
+
+    pub fn close(&mut self) {
+        if self.play.is_some() {
+            self.finish_play();
+        }
+
+        if let Some(render) = self.render.take() {
+            render.cancel();
+            self.events
+                .push_back(EngineEvent::RenderingFailed { task_id:   { self.id.clone() },
+                                                          render_id: { render.render_id },
+                                                          error:     { "Task closed while rendering".to_owned() }, });
+        }
+    }
+
+    /// Advance a task by one block, reporting render progress and mixing playing audio into `monitor`
+    pub fn process(&mut self, block_size: usize, monitor: &mut [Vec<f64>]) -> anyhow::Result<()> {
+        self.poll_render();
+
+        let play = match self.play.as_mut() {
+            Some(play) => play,
+            None => return Ok(()),
+        };
+
+        let segment_start = (play.play.segment.start * self.sample_rate as f64).round() as usize;
+        let segment_end = (play.play.segment.end() * self.sample_rate as f64).round() as usize;
+        let mut remaining = block_size;
+
+        // a looping play wraps inside the block, filling the rest of it from the start of the segment without a gap
+        while remaining > 0 {
+            let len = remaining.min(segment_end.saturating_sub(play.position));
+
+            if len > 0 {
+                let pads = self.graph.process(play.position, len, &self.gains);
+                let mixer = pads.get(&NodePadId::MixerOutput(play.play.mixer_id.clone()));
+                let stereo =
+                    (0..STREAM_CHANNELS).map(|channel| {
+                                            mixer.and_then(|mixer| mixer.get(channel).or_else(|| mixer.first()))
+                                                 .cloned()
+                                                 .unwrap_or_else(|| vec![0.0; len])
+                                        })
+                                        .collect::<Vec<_>>();
+
+                let offset = block_size - remaining;
+                for (monitor, channel) in monitor.iter_mut().zip(stereo.iter()) {
+                    for (monitor, sample) in monitor[offset..].iter_mut().zip(channel.iter()) {
+                        *monitor += sample;
+                    }
+                }
+
+                let timeline_pos = play.position as f64 / self.sample_rate as f64;
+                if let Some(audio) = play.encoder.process(&stereo, timeline_pos)? {
+                    self.events
+                        .push_back(EngineEvent::Playing { task_id:         { self.id.clone() },
+                                                          play_id:         { play.play.play_id },
+                                                          audio:           { audio },
+                                                          peak_metering:   { peak_meters(&pads) },
+                                                          dynamic_reports: { Default::default() }, });
+                }
+
+                play.position += len;
+                remaining -= len;
+            }
+
+            if play.position < segment_end {
+                break;
+            }
+
+            if !play.play.looping || segment_start >= segment_end {
+                debug!(play_id = %play.play.play_id, "reached end of play");
+                self.finish_play();
+                break;
+            }
+
+            play.position = segment_start;
+            play.passes += 1;
+            trace!(play_id = %play.play.play_id, passes = play.passes, "wrapped loop");
+        }
+
+        Ok(())
+    }
+
+    fn poll_render(&mut self) {
+        let render_id = match &self.render {
+            Some(render) => render.render_id,
+            None => return,
+        };
+
+        let event = match self.render.as_ref().and_then(BatchRender::poll) {
+            Some(RenderProgress::Completion(completion)) => EngineEvent::Rendering { task_id:    { self.id.clone() },
+                                                                                     render_id:  { render_id },
+                                                                                     completion: { completion }, },
+            Some(RenderProgress::Finished(path)) => {
+                self.render = None;
+                EngineEvent::RenderingFinished { task_id:   { self.id.clone() },
+                                                 render_id: { render_id },
+                                                 path:      { path }, }
+            }
+            Some(RenderProgress::Failed(error)) => {
+                self.render = None;
+                EngineEvent::RenderingFailed { task_id:   { self.id.clone() },
+                                               render_id: { render_id },
+                                               error:     { error }, }
+            }
+            None => return,
+        };
+
+        self.events.push_back(event);
+    }
+
+    fn finish_play(&mut self) {
+        if let Some(mut play) = self.play.take() {
+            let timeline_pos = play.position as f64 / self.sample_rate as f64;
+            match play.encoder.finish(timeline_pos) {
+                Ok(audio) => self.events
+                                 .push_back(EngineEvent::Playing { task_id:         { self.id.clone() },
+                                                                   play_id:         { play.play.play_id },
+                                                                   audio:           { audio },
+                                                                   peak_metering:   { Default::default() },
+                                                                   dynamic_reports: { Default::default() }, }),
+                Err(err) => warn!(%err, "failed to flush encoder"),
+            }
+
+            self.events
+                .push_back(EngineEvent::Stopped { task_id: { self.id.clone() }, });
+        }
+    }
+
+    fn stop(&mut self, error: &str) {
+        if let Some(play) = self.play.take() {
+            self.events
+                .push_back(EngineEvent::PlayingFailed { task_id: { self.id.clone() },
+                                                        play_id: { play.play.play_id },
+                                                        error:   { error.to_owned() }, });
+        }
+    }
+
+    fn to_frames(&self, seconds: f64) -> usize {
+        (seconds.max(0.0) * self.sample_rate as f64).round() as usize
+    }
+}
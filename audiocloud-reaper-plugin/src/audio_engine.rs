@@ -21,24 +21,49 @@ use audiocloud_api::audio_engine::event::EngineEvent;
 use audiocloud_api::audio_engine::CompressedAudio;
 use audiocloud_api::cloud::domains::FixedInstanceRouting;
 use audiocloud_api::common::media::{PlayId, RenderId, RequestPlay};
-use audiocloud_api::common::task::{NodeConnection, TaskSpec};
-use audiocloud_api::newtypes::{AppMediaObjectId, AppTaskId, FixedInstanceId, NodeConnectionId};
+use audiocloud_api::common::task::{InstanceReports, NodeConnection, TaskSpec};
+use audiocloud_api::newtypes::{AppMediaObjectId, AppTaskId, DynamicInstanceNodeId, FixedInstanceId, NodeConnectionId};
 use audiocloud_api::{ChannelMask, NodePadId, PadMetering};
 use project::EngineProject;
 
+use crate::audio_engine::clock::ClockMonitor;
 use crate::audio_engine::project::EngineProjectTemplateSnapshot;
-use crate::events::EngineCommandWithResultSender;
+use crate::audio_engine::resources::ResourceMonitor;
+use crate::audio_engine::session_state::{spec_hash, SavedSession, SessionState};
+use crate::audio_engine::test_tone::TestToneRun;
+use crate::events::{
+    EngineCommandWithResultSender, EngineExtCommand, EngineExtCommandWithResultSender, EngineExtEvent, Envelope,
+    EnvelopePoint, EnvelopeTarget, LatencyProfile, SpectrumSettings, StreamCodec,
+};
+use crate::loudness::LoudnessReading;
+use crate::spectrum::SpectrumReport;
+use crate::watermark::PlayWatermark;
 
+mod click;
+mod clock;
+mod dynamic_instance;
 mod fixed_instance;
 mod media_item;
 mod media_track;
+mod midi;
 mod mixer;
 mod project;
+mod render_conversion;
+mod resources;
 mod rest_api;
+mod session_state;
+mod sync_output;
+mod test_tone;
 
 pub struct PluginRegistry {
-    pub tx_engine: Sender<ReaperEngineCommand>,
-    pub plugins:   HashMap<AppTaskId, Sender<StreamingPluginCommand>>,
+    pub tx_engine:        Sender<ReaperEngineCommand>,
+    pub plugins:          HashMap<AppTaskId, Sender<StreamingPluginCommand>>,
+    pub loudness_targets: HashMap<AppTaskId, f64>,
+    pub latency_profiles: HashMap<AppTaskId, LatencyProfile>,
+    pub stream_codecs:    HashMap<AppTaskId, StreamCodec>,
+    pub spectrums:        HashMap<AppTaskId, SpectrumSettings>,
+    /// Watermark of the next play of each session, used only by the play it was set for
+    pub watermarks:       HashMap<AppTaskId, (PlayId, PlayWatermark)>,
 }
 
 impl PluginRegistry {
@@ -69,8 +94,29 @@ impl PluginRegistry {
                          .get(app_session_id)
                          .ok_or_else(|| anyhow!("No plugin for session {app_session_id}"))?;
 
+        let loudness_target = lock.loudness_targets
+                                  .get(app_session_id)
+                                  .copied()
+                                  .or_else(default_loudness_target);
+
+        let latency_profile = lock.latency_profiles.get(app_session_id).copied().unwrap_or_default();
+
+        let stream_codec = lock.stream_codecs.get(app_session_id).copied().unwrap_or_default();
+
+        let spectrum = lock.spectrums.get(app_session_id).copied();
+
+        let watermark = lock.watermarks
+                            .get(app_session_id)
+                            .filter(|(play_id, _)| play_id == &play.play_id)
+                            .map(|(_, watermark)| *watermark);
+
         let _ = plugin.try_send(StreamingPluginCommand::Play { context: ProjectContext::CurrentProject,
-                                                               play });
+                                                               play,
+                                                               loudness_target,
+                                                               latency_profile,
+                                                               stream_codec,
+                                                               spectrum,
+                                                               watermark });
 
         Ok(())
     }
@@ -90,6 +136,147 @@ impl PluginRegistry {
         Ok(())
     }
 
+    /// Drop what the play streamed from before the transport moved, so the next packet starts at the new position
+    pub fn seek(app_session_id: &AppTaskId, play_id: PlayId) -> anyhow::Result<()> {
+        let lock = PLUGIN_REGISTRY.get()
+                                  .ok_or_else(|| anyhow!("failed to obtain plugin registry: not initialized?"))?
+                                  .lock()
+                                  .map_err(|_| anyhow!("failed to lock plugin registry"))?;
+
+        let plugin = lock.plugins
+                         .get(app_session_id)
+                         .ok_or_else(|| anyhow!("No plugin for session {app_session_id}"))?;
+
+        let _ = plugin.try_send(StreamingPluginCommand::Seek { play_id });
+
+        Ok(())
+    }
+
+    pub fn pause(app_session_id: &AppTaskId, play_id: PlayId, paused: bool) -> anyhow::Result<()> {
+        let lock = PLUGIN_REGISTRY.get()
+                                  .ok_or_else(|| anyhow!("failed to obtain plugin registry: not initialized?"))?
+                                  .lock()
+                                  .map_err(|_| anyhow!("failed to lock plugin registry"))?;
+
+        let plugin = lock.plugins
+                         .get(app_session_id)
+                         .ok_or_else(|| anyhow!("No plugin for session {app_session_id}"))?;
+
+        let _ = plugin.try_send(StreamingPluginCommand::Pause { play_id, paused });
+
+        Ok(())
+    }
+
+    /// Change the bitrate of the play the session is streaming, if it is still streaming it
+    pub fn set_stream_bitrate(app_session_id: &AppTaskId, play_id: PlayId, bitrate: u32) -> anyhow::Result<()> {
+        let lock = PLUGIN_REGISTRY.get()
+                                  .ok_or_else(|| anyhow!("failed to obtain plugin registry: not initialized?"))?
+                                  .lock()
+                                  .map_err(|_| anyhow!("failed to lock plugin registry"))?;
+
+        let plugin = lock.plugins
+                         .get(app_session_id)
+                         .ok_or_else(|| anyhow!("No plugin for session {app_session_id}"))?;
+
+        let _ = plugin.try_send(StreamingPluginCommand::SetBitrate { play_id, bitrate });
+
+        Ok(())
+    }
+
+    /// Set the gain of the monitor controller of the session
+    pub fn set_monitor_gain(app_session_id: &AppTaskId, gain: f64) -> anyhow::Result<()> {
+        let lock = PLUGIN_REGISTRY.get()
+                                  .ok_or_else(|| anyhow!("failed to obtain plugin registry: not initialized?"))?
+                                  .lock()
+                                  .map_err(|_| anyhow!("failed to lock plugin registry"))?;
+
+        let plugin = lock.plugins
+                         .get(app_session_id)
+                         .ok_or_else(|| anyhow!("No plugin for session {app_session_id}"))?;
+
+        let _ = plugin.try_send(StreamingPluginCommand::SetMonitorGain { gain });
+
+        Ok(())
+    }
+
+    /// Set the monitoring loudness target of a session, applied from the next play onwards
+    pub fn set_loudness_target(app_session_id: &AppTaskId, target_lufs: Option<f64>) -> anyhow::Result<()> {
+        let mut lock = PLUGIN_REGISTRY.get()
+                                      .ok_or_else(|| anyhow!("failed to obtain plugin registry: not initialized?"))?
+                                      .lock()
+                                      .map_err(|_| anyhow!("failed to lock plugin registry"))?;
+
+        match target_lufs {
+            Some(target_lufs) => lock.loudness_targets.insert(app_session_id.clone(), target_lufs),
+            None => lock.loudness_targets.remove(app_session_id),
+        };
+
+        Ok(())
+    }
+
+    /// Set the latency profile of a session, applied from the next play onwards
+    pub fn set_latency_profile(app_session_id: &AppTaskId, profile: LatencyProfile) -> anyhow::Result<()> {
+        let mut lock = PLUGIN_REGISTRY.get()
+                                      .ok_or_else(|| anyhow!("failed to obtain plugin registry: not initialized?"))?
+                                      .lock()
+                                      .map_err(|_| anyhow!("failed to lock plugin registry"))?;
+
+        match profile {
+            LatencyProfile::Stable => lock.latency_profiles.remove(app_session_id),
+            profile => lock.latency_profiles.insert(app_session_id.clone(), profile),
+        };
+
+        Ok(())
+    }
+
+    /// Set the codec streamed audio of a session is compressed with, applied from the next play onwards
+    pub fn set_stream_codec(app_session_id: &AppTaskId, codec: StreamCodec) -> anyhow::Result<()> {
+        let mut lock = PLUGIN_REGISTRY.get()
+                                      .ok_or_else(|| anyhow!("failed to obtain plugin registry: not initialized?"))?
+                                      .lock()
+                                      .map_err(|_| anyhow!("failed to lock plugin registry"))?;
+
+        match codec {
+            StreamCodec::Flac => lock.stream_codecs.remove(app_session_id),
+            codec => lock.stream_codecs.insert(app_session_id.clone(), codec),
+        };
+
+        Ok(())
+    }
+
+    /// Set the spectrum reports of a session, applied from the next play onwards
+    pub fn set_spectrum(app_session_id: &AppTaskId, spectrum: Option<SpectrumSettings>) -> anyhow::Result<()> {
+        let mut lock = PLUGIN_REGISTRY.get()
+                                      .ok_or_else(|| anyhow!("failed to obtain plugin registry: not initialized?"))?
+                                      .lock()
+                                      .map_err(|_| anyhow!("failed to lock plugin registry"))?;
+
+        match spectrum {
+            Some(spectrum) => lock.spectrums.insert(app_session_id.clone(), spectrum),
+            None => lock.spectrums.remove(app_session_id),
+        };
+
+        Ok(())
+    }
+
+    /// Set the watermark of the stream of a play of a session, replacing the one of the previous play
+    pub fn set_watermark(app_session_id: &AppTaskId,
+                         play_id: PlayId,
+                         watermark: Option<PlayWatermark>)
+                         -> anyhow::Result<()> {
+        let mut lock = PLUGIN_REGISTRY.get()
+                                      .ok_or_else(|| anyhow!("failed to obtain plugin registry: not initialized?"))?
+                                      .lock()
+                                      .map_err(|_| anyhow!("failed to lock plugin registry"))?;
+
+        match watermark {
+            Some(watermark) => lock.watermarks.insert(app_session_id.clone(), (play_id, watermark)),
+            None => lock.watermarks.remove(app_session_id),
+        };
+
+        Ok(())
+    }
+
     pub fn has(app_session_id: &AppTaskId) -> anyhow::Result<bool> {
         let lock = PLUGIN_REGISTRY.get()
                                   .ok_or_else(|| anyhow!("failed to obtain plugin registry: not initialized?"))?
@@ -101,7 +288,12 @@ impl PluginRegistry {
 
     pub(crate) fn init(tx_engine: Sender<ReaperEngineCommand>) {
         PLUGIN_REGISTRY.set(Mutex::new(PluginRegistry { tx_engine,
-                                                        plugins: HashMap::new() }))
+                                                        plugins: HashMap::new(),
+                                                        loudness_targets: HashMap::new(),
+                                                        latency_profiles: HashMap::new(),
+                                                        stream_codecs: HashMap::new(),
+                                                        spectrums: HashMap::new(),
+                                                        watermarks: HashMap::new() }))
                        .map_err(|_| anyhow!("Plugin registry already initialized"))
                        .expect("init Plugin Registry");
     }
@@ -109,12 +301,21 @@ impl PluginRegistry {
 
 static PLUGIN_REGISTRY: OnceCell<Mutex<PluginRegistry>> = OnceCell::new();
 
+/// Loudness target for sessions without an explicit one, from the `MONITORING_TARGET_LUFS` env var
+fn default_loudness_target() -> Option<f64> {
+    std::env::var("MONITORING_TARGET_LUFS").ok()
+                                           .and_then(|target| target.parse().ok())
+}
+
 #[derive(Debug)]
 pub enum ReaperEngineCommand {
     PlayReady(AppTaskId, PlayId),
     PlayError(AppTaskId, String),
     Audio(AppTaskId, PlayId, CompressedAudio),
+    Loudness(AppTaskId, PlayId, LoudnessReading),
+    Spectrum(AppTaskId, PlayId, SpectrumReport),
     Request(EngineCommandWithResultSender),
+    Ext(EngineExtCommandWithResultSender),
     GetStatus(Sender<anyhow::Result<HashMap<AppTaskId, EngineStatus>>>),
 }
 
@@ -125,17 +326,41 @@ pub struct EngineStatus {
     pub is_transport_playing: bool,
     pub position:             f64,
     pub plugin_ready:         bool,
+    pub track_count:          usize,
 }
 
 #[derive(Debug)]
 pub enum StreamingPluginCommand {
     Play {
-        context: ProjectContext,
-        play:    RequestPlay,
+        context:         ProjectContext,
+        play:            RequestPlay,
+        loudness_target: Option<f64>,
+        latency_profile: LatencyProfile,
+        stream_codec:    StreamCodec,
+        spectrum:        Option<SpectrumSettings>,
+        watermark:       Option<PlayWatermark>,
     },
     Flush {
         play_id: PlayId,
     },
+    /// The transport moved while playing, keep streaming the play from the new position
+    Seek {
+        play_id: PlayId,
+    },
+    /// Stop or continue encoding the play without finishing it
+    Pause {
+        play_id: PlayId,
+        paused:  bool,
+    },
+    /// Stream the play at another bitrate from the next frame on
+    SetBitrate {
+        play_id: PlayId,
+        bitrate: u32,
+    },
+    /// Scale the streamed audio by the gain of the monitor controller, for this and later plays
+    SetMonitorGain {
+        gain: f64,
+    },
 }
 
 #[derive(Debug)]
@@ -144,6 +369,13 @@ pub struct ReaperEngine {
     sessions:          HashMap<AppTaskId, EngineProject>,
     rx_cmd:            Receiver<ReaperEngineCommand>,
     tx_evt:            Sender<EngineEvent>,
+    tx_ext_evt:        Sender<EngineExtEvent>,
+    clock:             ClockMonitor,
+    resources:         ResourceMonitor,
+    test_tone:         Option<TestToneRun>,
+    session_state:     SessionState,
+    /// Sessions saved by the previous run, reloaded once REAPER runs the engine
+    restore:           Option<HashMap<AppTaskId, SavedSession>>,
 }
 
 impl Drop for ReaperEngine {
@@ -168,14 +400,24 @@ impl ReaperEngine {
     pub fn new(shared_media_root: PathBuf,
                tx_cmd: Sender<ReaperEngineCommand>,
                rx_cmd: Receiver<ReaperEngineCommand>,
-               tx_evt: Sender<EngineEvent>)
+               tx_evt: Sender<EngineEvent>,
+               tx_ext_evt: Sender<EngineExtEvent>)
                -> ReaperEngine {
         thread::spawn(move || rest_api::run(tx_cmd));
 
+        let mut session_state = SessionState::load();
+        let restore = Some(session_state.take_sessions());
+
         ReaperEngine { sessions: HashMap::new(),
                        shared_media_root,
                        rx_cmd,
-                       tx_evt }
+                       tx_evt,
+                       tx_ext_evt,
+                       clock: ClockMonitor::new(),
+                       resources: ResourceMonitor::new(),
+                       test_tone: None,
+                       session_state,
+                       restore }
     }
 
     #[instrument(skip_all, err)]
@@ -184,21 +426,30 @@ impl ReaperEngine {
 
         debug!(?cmd, "entered");
 
+        if let Some(session) = self.sessions.get_mut(command_task_id(&cmd)) {
+            session.record_command(command_summary(&cmd));
+        }
+
         match cmd {
             SetSpec { task_id: session_id,
                       spec,
                       instances,
                       media_ready, } => {
                 if let Some(project) = self.sessions.get_mut(&session_id) {
-                    project.set_spec(spec, instances, media_ready)?;
+                    project.set_spec(spec.clone(), instances.clone(), media_ready.clone())?;
                 } else {
-                    self.create_session(session_id, spec, instances, media_ready)?;
+                    self.create_session(session_id.clone(), spec.clone(), instances.clone(), media_ready.clone())?;
                 }
+
+                // saved once applied, a spec that crashes the engine is not reloaded
+                self.session_state
+                    .set_spec(&session_id, &spec, &instances, &media_ready);
             }
             Media { task_id: session_id,
                     media_ready: ready, } => {
                 if let Some(session) = self.sessions.get_mut(&session_id) {
                     session.on_media_updated(&ready)?;
+                    self.session_state.set_media(&session_id, &ready);
                 }
             }
             ModifySpec { task_id: session_id,
@@ -206,14 +457,18 @@ impl ReaperEngine {
                          instances,
                          media_ready, } => {
                 if let Some(session) = self.sessions.get_mut(&session_id) {
-                    session.modify_spec(transaction, instances, media_ready)?;
+                    session.modify_spec(transaction, instances.clone(), media_ready.clone())?;
+                    self.session_state.set_instances(&session_id, &instances);
+                    self.session_state.set_media(&session_id, &media_ready);
                 } else {
                     return Err(anyhow!("Session not found"));
                 }
             }
-            SetDynamicParameterValues { task_id: session_id, .. } => {
-                if let Some(_) = self.sessions.get_mut(&session_id) {
-                    // TODO: implement dynamic parameters
+            SetDynamicParameterValues { task_id: session_id,
+                                        dynamic_id,
+                                        values, } => {
+                if let Some(session) = self.sessions.get_mut(&session_id) {
+                    session.set_dynamic_parameters(&dynamic_id, values)?;
                 } else {
                     return Err(anyhow!("Session not found"));
                 }
@@ -262,11 +517,13 @@ impl ReaperEngine {
                         instances, } => {
                 if let Some(session) = self.sessions.get_mut(&session_id) {
                     session.on_instances_updated(&instances)?;
+                    self.session_state.set_instances(&session_id, &instances);
                 }
             }
             Close { task_id: session_id } => {
                 if let Some(session) = self.sessions.remove(&session_id) {
                     drop(session);
+                    self.session_state.remove(&session_id);
                 } else {
                     return Err(anyhow!("Session not found"));
                 }
@@ -275,6 +532,222 @@ impl ReaperEngine {
         Ok(())
     }
 
+    #[instrument(skip_all, err)]
+    fn dispatch_ext_cmd(&mut self, cmd: EngineExtCommand) -> anyhow::Result<()> {
+        debug!(?cmd, "entered");
+
+        if let Some(session) = cmd.task_id().and_then(|task_id| self.sessions.get_mut(task_id)) {
+            session.record_command(cmd.summary());
+        }
+
+        match cmd {
+            EngineExtCommand::SetTrackInputs { task_id: session_id,
+                                               inputs, } => {
+                if let Some(session) = self.sessions.get_mut(&session_id) {
+                    session.set_track_inputs(inputs)?;
+                } else {
+                    return Err(anyhow!("Session not found"));
+                }
+            }
+            EngineExtCommand::SetRecording { task_id: session_id,
+                                             armed,
+                                             loop_record,
+                                             punch, } => {
+                if let Some(session) = self.sessions.get_mut(&session_id) {
+                    session.set_recording(armed, loop_record, punch)?;
+                } else {
+                    return Err(anyhow!("Session not found"));
+                }
+            }
+            EngineExtCommand::SetEnvelopes { task_id: session_id,
+                                             envelopes, } => {
+                if let Some(session) = self.sessions.get_mut(&session_id) {
+                    session.set_envelopes(envelopes)?;
+                } else {
+                    return Err(anyhow!("Session not found"));
+                }
+            }
+            EngineExtCommand::SetMediaFades { task_id: session_id,
+                                              fades, } => {
+                if let Some(session) = self.sessions.get_mut(&session_id) {
+                    session.set_media_fades(fades)?;
+                } else {
+                    return Err(anyhow!("Session not found"));
+                }
+            }
+            EngineExtCommand::SetMediaRates { task_id: session_id,
+                                              rates, } => {
+                if let Some(session) = self.sessions.get_mut(&session_id) {
+                    session.set_media_rates(rates)?;
+                } else {
+                    return Err(anyhow!("Session not found"));
+                }
+            }
+            EngineExtCommand::SetMonitor { task_id: session_id,
+                                           gain,
+                                           source, } => {
+                if let Some(session) = self.sessions.get_mut(&session_id) {
+                    session.set_monitor(gain, source)?;
+                } else {
+                    return Err(anyhow!("Session not found"));
+                }
+            }
+            EngineExtCommand::SetClick { task_id: session_id,
+                                         click, } => {
+                if let Some(session) = self.sessions.get_mut(&session_id) {
+                    session.set_click(click);
+                } else {
+                    return Err(anyhow!("Session not found"));
+                }
+            }
+            EngineExtCommand::SetLeadIn { task_id: session_id,
+                                          lead_in, } => {
+                if let Some(session) = self.sessions.get_mut(&session_id) {
+                    session.set_lead_in(lead_in);
+                } else {
+                    return Err(anyhow!("Session not found"));
+                }
+            }
+            EngineExtCommand::SetPlaylist { task_id: session_id,
+                                            playlist, } => {
+                if let Some(session) = self.sessions.get_mut(&session_id) {
+                    session.set_playlist(playlist);
+                } else {
+                    return Err(anyhow!("Session not found"));
+                }
+            }
+            EngineExtCommand::SetTempoMap { task_id: session_id,
+                                            tempo_map, } => {
+                if let Some(session) = self.sessions.get_mut(&session_id) {
+                    session.set_tempo_map(tempo_map)?;
+                } else {
+                    return Err(anyhow!("Session not found"));
+                }
+            }
+            EngineExtCommand::PausePlay { task_id: session_id,
+                                          play_id, } => {
+                if let Some(session) = self.sessions.get_mut(&session_id) {
+                    session.pause_play(play_id)?;
+                } else {
+                    return Err(anyhow!("Session not found"));
+                }
+            }
+            EngineExtCommand::ResumePlay { task_id: session_id,
+                                           play_id, } => {
+                if let Some(session) = self.sessions.get_mut(&session_id) {
+                    session.resume_play(play_id)?;
+                } else {
+                    return Err(anyhow!("Session not found"));
+                }
+            }
+            EngineExtCommand::SetLatencyProfile { task_id: session_id,
+                                                  profile, } => {
+                if !self.sessions.contains_key(&session_id) {
+                    return Err(anyhow!("Session not found"));
+                }
+
+                PluginRegistry::set_latency_profile(&session_id, profile)?;
+            }
+            EngineExtCommand::SetLoudnessTarget { task_id: session_id,
+                                                  target_lufs, } => {
+                if !self.sessions.contains_key(&session_id) {
+                    return Err(anyhow!("Session not found"));
+                }
+
+                if matches!(target_lufs, Some(target_lufs) if !(-70.0..=0.0).contains(&target_lufs)) {
+                    return Err(anyhow!("Loudness target must be between -70 and 0 LUFS, not {target_lufs:?}"));
+                }
+
+                PluginRegistry::set_loudness_target(&session_id, target_lufs)?;
+            }
+            EngineExtCommand::SetStreamCodec { task_id: session_id,
+                                               codec, } => {
+                if !self.sessions.contains_key(&session_id) {
+                    return Err(anyhow!("Session not found"));
+                }
+
+                codec.validate()?;
+
+                PluginRegistry::set_stream_codec(&session_id, codec)?;
+            }
+            EngineExtCommand::SetStreamQuality { task_id: session_id,
+                                                 play_id,
+                                                 bitrate, } => {
+                if !self.sessions.contains_key(&session_id) {
+                    return Err(anyhow!("Session not found"));
+                }
+
+                if !(6_000..=510_000).contains(&bitrate) {
+                    return Err(anyhow!("Opus bitrate must be between 6000 and 510000 bits per second, not {bitrate}"));
+                }
+
+                PluginRegistry::set_stream_bitrate(&session_id, play_id, bitrate)?;
+            }
+            EngineExtCommand::SetSpectrum { task_id: session_id,
+                                            spectrum, } => {
+                if !self.sessions.contains_key(&session_id) {
+                    return Err(anyhow!("Session not found"));
+                }
+
+                if let Some(spectrum) = &spectrum {
+                    spectrum.validate()?;
+                }
+
+                PluginRegistry::set_spectrum(&session_id, spectrum)?;
+            }
+            EngineExtCommand::SetWatermark { task_id: session_id,
+                                             play_id,
+                                             watermark, } => {
+                if !self.sessions.contains_key(&session_id) {
+                    return Err(anyhow!("Session not found"));
+                }
+
+                if let Some(watermark) = &watermark {
+                    watermark.validate()?;
+                }
+
+                PluginRegistry::set_watermark(&session_id, play_id, watermark)?;
+            }
+            EngineExtCommand::StartTestTone { test_id, tone } => {
+                if let Some(running) = &self.test_tone {
+                    return Err(anyhow!("Test tone {} is still running", running.test_id()));
+                }
+
+                for (session_id, status) in self.get_status()? {
+                    if status.is_playing.is_some() || status.is_rendering.is_some() {
+                        return Err(anyhow!("Engine is busy with session {session_id}"));
+                    }
+                }
+
+                self.test_tone = Some(TestToneRun::start(test_id, tone)?);
+            }
+            EngineExtCommand::SetRenderFormats { task_id: session_id,
+                                                 render_id,
+                                                 formats, } => {
+                if let Some(session) = self.sessions.get_mut(&session_id) {
+                    session.set_render_formats(render_id, formats);
+                } else {
+                    return Err(anyhow!("Session not found"));
+                }
+            }
+            EngineExtCommand::CaptureDiagnostics { task_id: session_id,
+                                                   error, } => {
+                let session = self.sessions
+                                  .get(&session_id)
+                                  .ok_or_else(|| anyhow!("Session not found"))?;
+
+                let path = session.capture_diagnostics(error.clone())?;
+
+                let _ = self.tx_ext_evt
+                            .try_send(EngineExtEvent::DiagnosticsCaptured { task_id: session_id,
+                                                                            error,
+                                                                            path });
+            }
+        }
+
+        Ok(())
+    }
+
     fn create_session(&mut self,
                       session_id: AppTaskId,
                       spec: TaskSpec,
@@ -297,14 +770,42 @@ impl ReaperEngine {
         Ok(())
     }
 
+    /// Reload the sessions saved by the previous run and report that the engine started afresh, with the hashes of the
+    /// specs of the sessions it reloaded
+    #[instrument(skip_all)]
+    fn restore_sessions(&mut self, saved: HashMap<AppTaskId, SavedSession>) {
+        let mut sessions = HashMap::new();
+
+        for (session_id, session) in saved {
+            let hash = spec_hash(&session.spec);
+            let result = self.create_session(session_id.clone(),
+                                             session.spec.clone(),
+                                             session.instances.clone(),
+                                             session.media.clone());
+
+            match result {
+                Ok(()) => {
+                    self.session_state
+                        .set_spec(&session_id, &session.spec, &session.instances, &session.media);
+                    sessions.insert(session_id, hash);
+                }
+                Err(error) => warn!(%error, %session_id, "Failed to reload session"),
+            }
+        }
+
+        info!(sessions = sessions.len(), "Engine started");
+
+        let _ = self.tx_ext_evt.try_send(EngineExtEvent::Started { sessions });
+    }
+
     #[instrument(skip_all, err)]
     pub fn send_playing_audio_event(&mut self,
                                     session_id: AppTaskId,
                                     play_id: PlayId,
                                     audio: CompressedAudio,
-                                    peak_metering: HashMap<NodePadId, PadMetering>)
+                                    peak_metering: HashMap<NodePadId, PadMetering>,
+                                    dynamic_reports: HashMap<DynamicInstanceNodeId, InstanceReports>)
                                     -> anyhow::Result<()> {
-        let dynamic_reports = Default::default();
         let event = EngineEvent::Playing { task_id: session_id,
                                            play_id,
                                            audio,
@@ -321,21 +822,47 @@ impl ReaperEngine {
 impl ControlSurface for ReaperEngine {
     #[instrument(skip(self))]
     fn run(&mut self) {
+        if let Some(saved) = self.restore.take() {
+            self.restore_sessions(saved);
+        }
+
         while let Ok(cmd) = self.rx_cmd.try_recv() {
             match cmd {
                 ReaperEngineCommand::Audio(session_id, play_id, audio) => {
                     if let Some(session) = self.sessions.get(&session_id) {
                         let peaks = session.get_peak_meters();
-                        let _ = self.send_playing_audio_event(session_id, play_id, audio, peaks);
+                        let reports = session.get_dynamic_reports();
+                        let _ = self.send_playing_audio_event(session_id, play_id, audio, peaks, reports);
                     } else {
                         warn!(%session_id, "Session not found");
                     }
                 }
+                ReaperEngineCommand::Loudness(session_id, play_id, reading) => {
+                    if let Some(pad_id) = self.sessions.get(&session_id).and_then(EngineProject::streamed_pad) {
+                        let loudness = HashMap::from([(pad_id, reading)]);
+                        let _ = self.tx_ext_evt.try_send(EngineExtEvent::Loudness { task_id: session_id,
+                                                                                    play_id,
+                                                                                    loudness });
+                    }
+                }
+                ReaperEngineCommand::Spectrum(session_id, play_id, report) => {
+                    if let Some(pad_id) = self.sessions.get(&session_id).and_then(EngineProject::streamed_pad) {
+                        let spectrum = HashMap::from([(pad_id, report)]);
+                        let _ = self.tx_ext_evt.try_send(EngineExtEvent::Spectrum { task_id: session_id,
+                                                                                    play_id,
+                                                                                    spectrum });
+                    }
+                }
                 ReaperEngineCommand::Request((cmd, sender)) => {
                     if let Err(err) = sender.send(self.dispatch_cmd(cmd)) {
                         warn!(%err, "failed to send response to command");
                     }
                 }
+                ReaperEngineCommand::Ext((cmd, sender)) => {
+                    if let Err(err) = sender.send(self.dispatch_ext_cmd(cmd)) {
+                        warn!(%err, "failed to send response to extension command");
+                    }
+                }
                 ReaperEngineCommand::PlayReady(session_id, play_id) => {
                     if let Some(session) = self.sessions.get_mut(&session_id) {
                         session.play_ready(play_id);
@@ -359,10 +886,80 @@ impl ControlSurface for ReaperEngine {
                 debug!(?event, "emitting");
                 let _ = self.tx_evt.try_send(event);
             }
+            while let Some(event) = session.ext_events.pop_front() {
+                debug!(?event, "emitting extension event");
+                let _ = self.tx_ext_evt.try_send(event);
+            }
+        }
+
+        if let Some(result) = self.test_tone.as_mut().and_then(TestToneRun::run) {
+            if let Some(run) = self.test_tone.take() {
+                debug!(?result, "emitting test tone result");
+                let _ = self.tx_ext_evt
+                            .try_send(EngineExtEvent::TestToneMeasured { test_id: run.test_id().to_owned(),
+                                                                         result });
+            }
+        }
+
+        if let Some(status) = self.clock.run() {
+            debug!(?status, "emitting clock status");
+            let _ = self.tx_ext_evt.try_send(EngineExtEvent::ClockStatus { status });
+        }
+
+        let sessions = &self.sessions;
+        let tracks = || {
+            sessions.iter()
+                    .map(|(id, session)| (id.clone(), session.track_count()))
+                    .collect()
+        };
+
+        if let Some(resources) = self.resources.run(self.clock.underruns(), tracks) {
+            debug!(?resources, "emitting resource usage");
+            let _ = self.tx_ext_evt.try_send(EngineExtEvent::Resources { resources });
         }
     }
 }
 
+fn command_task_id(cmd: &EngineCommand) -> &AppTaskId {
+    use audiocloud_api::audio_engine::command::EngineCommand::*;
+
+    match cmd {
+        SetSpec { task_id, .. }
+        | Media { task_id, .. }
+        | ModifySpec { task_id, .. }
+        | SetDynamicParameterValues { task_id, .. }
+        | Render { task_id, .. }
+        | Play { task_id, .. }
+        | UpdatePlay { task_id, .. }
+        | CancelRender { task_id, .. }
+        | StopPlay { task_id, .. }
+        | Instances { task_id, .. }
+        | Close { task_id } => task_id,
+    }
+}
+
+/// Variant name and ids of the command, diagnostic bundles are downloadable by the app so its payload (spec, media
+/// download URLs, ...) is left out
+fn command_summary(cmd: &EngineCommand) -> String {
+    use audiocloud_api::audio_engine::command::EngineCommand::*;
+
+    match cmd {
+        SetSpec { task_id, .. } => format!("SetSpec({task_id})"),
+        Media { task_id, .. } => format!("Media({task_id})"),
+        ModifySpec { task_id, .. } => format!("ModifySpec({task_id})"),
+        SetDynamicParameterValues { task_id, dynamic_id, .. } => {
+            format!("SetDynamicParameterValues({task_id}, {dynamic_id})")
+        }
+        Render { task_id, render } => format!("Render({task_id}, {})", render.render_id),
+        Play { task_id, play } => format!("Play({task_id}, {})", play.play_id),
+        UpdatePlay { task_id, update } => format!("UpdatePlay({task_id}, {})", update.play_id),
+        CancelRender { task_id, render_id } => format!("CancelRender({task_id}, {render_id})"),
+        StopPlay { task_id, play_id } => format!("StopPlay({task_id}, {play_id})"),
+        Instances { task_id, .. } => format!("Instances({task_id})"),
+        Close { task_id } => format!("Close({task_id})"),
+    }
+}
+
 pub fn beautify_chunk(chunk: String) -> String {
     let mut tab = 0;
 
@@ -418,6 +1015,21 @@ impl<'a> ConnectionTemplate<'a> {
             ChannelMask::Stereo(start) => start as i32,
         }
     }
+
+    fn envelope_chunk_name(&self, envelope: &Envelope) -> &'static str {
+        match envelope.target {
+            EnvelopeTarget::ConnectionPan { .. } => "AUXPANENV",
+            _ => "AUXVOLENV",
+        }
+    }
+
+    /// REAPER keeps pan envelope points with left positive, the opposite of the pan of the connection
+    fn envelope_point_value(&self, envelope: &Envelope, point: &EnvelopePoint) -> f64 {
+        match envelope.target {
+            EnvelopeTarget::ConnectionPan { .. } => -point.value,
+            _ => point.value,
+        }
+    }
 }
 
 pub(crate) fn get_track_uuid(track: MediaTrack) -> Uuid {
@@ -429,6 +1041,11 @@ pub(crate) fn get_track_uuid(track: MediaTrack) -> Uuid {
 }
 
 fn append_track(pad_id: &NodePadId, context: ProjectContext) -> anyhow::Result<(MediaTrack, Uuid)> {
+    append_named_track(&pad_id.to_string(), context)
+}
+
+/// Append a track that is not a pad of the task, such as a generated click track
+fn append_named_track(name: &str, context: ProjectContext) -> anyhow::Result<(MediaTrack, Uuid)> {
     let reaper = Reaper::get();
 
     let index = reaper.count_tracks(context);
@@ -438,10 +1055,8 @@ fn append_track(pad_id: &NodePadId, context: ProjectContext) -> anyhow::Result<(
     let track = reaper.get_track(context, index)
                       .ok_or_else(|| anyhow!("failed to get track we just created"))?;
 
-    let pad_id = pad_id.to_string();
-
     unsafe {
-        reaper.get_set_media_track_info_set_name(track, pad_id.as_str());
+        reaper.get_set_media_track_info_set_name(track, name);
     }
 
     let track_id = get_track_uuid(track);
@@ -0,0 +1,188 @@
+use std::f64::consts::PI;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::ptr::null_mut;
+
+use askama::Template;
+use reaper_medium::{MediaTrack, ProjectContext, ReaProject, Reaper};
+use tracing::*;
+use uuid::Uuid;
+
+use audiocloud_api::newtypes::MixerNodeId;
+
+use crate::audio_engine::test_tone::{write_mono_wav, TONE_SAMPLE_RATE};
+use crate::audio_engine::{append_named_track, beautify_chunk, delete_track, set_track_chunk};
+use crate::events::Click;
+
+/// Name of the generated click track, the input of the mixer of the click receives from the track by this name
+pub const CLICK_TRACK_NAME: &str = "audiocloud click";
+
+/// Length of one click
+const CLICK_LENGTH_SAMPLES: usize = (TONE_SAMPLE_RATE / 50) as usize;
+
+/// Beats other than the first of a bar are this much quieter
+const UNACCENTED_DB: f64 = -6.0;
+
+#[derive(Template)]
+#[template(path = "audio_engine/click_track.txt")]
+struct ClickTrackTemplate {
+    track_id: Uuid,
+    start:    f64,
+    length:   f64,
+    path:     String,
+}
+
+/// A beat of the click, `accent` on the first beat of a bar
+#[derive(Debug, Clone, Copy)]
+struct ClickHit {
+    time:   f64,
+    accent: bool,
+}
+
+/// Click of the play going on, a track playing the generated clicks that only the input of its mixer receives
+#[derive(Debug)]
+pub struct ClickTrack {
+    pub mixer_id: MixerNodeId,
+    track:        MediaTrack,
+    path:         PathBuf,
+}
+
+impl ClickTrack {
+    /// Generate the clicks from `start` to `end` into `path` and append a track playing them, the project must have
+    /// the focus
+    #[instrument(skip_all, err)]
+    pub fn new(project: ReaProject, click: &Click, start: f64, end: f64, path: PathBuf) -> anyhow::Result<Self> {
+        let context = ProjectContext::Proj(project);
+        let item_start = start.max(0.0);
+        let hits = click_hits(project, click, start, end);
+
+        write_click_wav(&path, click, &hits, item_start, end)?;
+
+        let (track, track_id) = append_named_track(CLICK_TRACK_NAME, context)?;
+        let chunk = ClickTrackTemplate { track_id: { track_id },
+                                         start:    { item_start },
+                                         length:   { end - item_start },
+                                         path:     { path.to_string_lossy().to_string() }, }.render()?;
+
+        set_track_chunk(context, track, &beautify_chunk(chunk))?;
+
+        debug!(mixer_id = %click.mixer_id, start, end, hits = hits.len(), "Generated click track");
+
+        Ok(Self { mixer_id: { click.mixer_id.clone() },
+                  track:    { track },
+                  path:     { path }, })
+    }
+
+    pub fn delete(&self, context: ProjectContext) {
+        delete_track(context, self.track);
+        // the clicks are generated for every play, nothing else refers to them
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Where the count-in of a click starts so that it ends at `pre_roll_start` after its bars, before the start of the
+/// project if the play starts too early for the whole count-in
+pub fn count_in_start(project: ReaProject, click: &Click, pre_roll_start: f64) -> f64 {
+    match click.tempo {
+        Some(tempo) => pre_roll_start - (click.count_in_bars * tempo.numerator) as f64 * tempo.beat_length(),
+        None => bars_before(project, pre_roll_start, click.count_in_bars),
+    }
+}
+
+/// Time of the same position within the bar `bars` bars earlier, at the tempo and time signature of the project
+pub fn bars_before(project: ReaProject, time: f64, bars: u32) -> f64 {
+    let project = project.as_ptr();
+
+    unsafe {
+        let low = Reaper::get().low();
+        let mut measure = 0;
+        let beat = low.TimeMap2_timeToBeats(project, time, &mut measure, null_mut(), null_mut(), null_mut());
+        let measure = measure - bars as i32;
+
+        low.TimeMap2_beatsToTime(project, beat, &measure)
+    }
+}
+
+/// Beats from `start` to `end`, anchored on `start` at a fixed tempo and on the bars of the project otherwise
+fn click_hits(project: ReaProject, click: &Click, start: f64, end: f64) -> Vec<ClickHit> {
+    match click.tempo {
+        Some(tempo) => (0u64..).map(|beat| ClickHit { time:   { start + beat as f64 * tempo.beat_length() },
+                                                      accent: { beat % tempo.numerator as u64 == 0 }, })
+                               .take_while(|hit| hit.time < end)
+                               .filter(|hit| hit.time >= 0.0)
+                               .collect(),
+        None => tempo_map_hits(project, start.max(0.0), end),
+    }
+}
+
+fn tempo_map_hits(project: ReaProject, start: f64, end: f64) -> Vec<ClickHit> {
+    let project = project.as_ptr();
+    let mut hits = vec![];
+
+    unsafe {
+        let low = Reaper::get().low();
+        let mut measure = 0;
+        low.TimeMap2_timeToBeats(project, start, &mut measure, null_mut(), null_mut(), null_mut());
+
+        loop {
+            let (mut qn_start, mut qn_end, mut tempo) = (0.0, 0.0, 0.0);
+            let (mut numerator, mut denominator) = (0, 0);
+            low.TimeMap_GetMeasureInfo(project,
+                                       measure,
+                                       &mut qn_start,
+                                       &mut qn_end,
+                                       &mut numerator,
+                                       &mut denominator,
+                                       &mut tempo);
+
+            let beat_qn = 4.0 / denominator.max(1) as f64;
+            for beat in 0..numerator.max(1) {
+                let time = low.TimeMap2_QNToTime(project, qn_start + beat as f64 * beat_qn);
+                if time >= end {
+                    return hits;
+                }
+                if time >= start {
+                    hits.push(ClickHit { time:   { time },
+                                         accent: { beat == 0 }, });
+                }
+            }
+
+            measure += 1;
+        }
+    }
+}
+
+/// Short decaying sine bursts, higher on the accented beats, the file starts at `item_start` on the timeline
+fn write_click_wav(path: &Path, click: &Click, hits: &[ClickHit], item_start: f64, end: f64) -> anyhow::Result<()> {
+    let sample_rate = TONE_SAMPLE_RATE as f64;
+    let num_samples = ((end - item_start).max(0.0) * sample_rate) as usize;
+    let accent_gain = 10f64.powf(click.level_db / 20.0);
+    let gain = 10f64.powf((click.level_db + UNACCENTED_DB) / 20.0);
+
+    let offsets = hits.iter()
+                      .map(|hit| (((hit.time - item_start) * sample_rate).round().max(0.0) as usize, hit.accent))
+                      .collect::<Vec<_>>();
+
+    let mut next = 0;
+
+    write_mono_wav(path, num_samples, |i| {
+        while next < offsets.len() && offsets[next].0 <= i {
+            next += 1;
+        }
+
+        let (offset, accent) = match next.checked_sub(1) {
+            Some(current) => offsets[current],
+            None => return 0.0,
+        };
+
+        let elapsed = i - offset;
+        if elapsed >= CLICK_LENGTH_SAMPLES {
+            return 0.0;
+        }
+
+        let (frequency, gain) = if accent { (1500.0, accent_gain) } else { (1000.0, gain) };
+        let decay = 1.0 - elapsed as f64 / CLICK_LENGTH_SAMPLES as f64;
+
+        (2.0 * PI * frequency * elapsed as f64 / sample_rate).sin() * gain * decay * decay
+    })
+}
@@ -0,0 +1,135 @@
+use std::env;
+use std::ffi::CStr;
+use std::time::{Duration, Instant};
+
+use cstr::cstr;
+use reaper_medium::Reaper;
+
+use crate::events::ClockStatus;
+
+/// How often the audio device is checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The status is reported at least this often, even when it does not change
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// An audio thread underrun within this long counts as a clock problem
+const UNDERRUN_WINDOW: Duration = Duration::from_secs(5);
+
+/// Watches the audio device REAPER runs on for signs that the converters lost their clock
+///
+/// ASIO and Core Audio do not tell the host whether the interface is locked to an external clock. Interfaces that
+/// lose it either stop calling back, fall back to their internal clock at another sample rate, or drop buffers, so
+/// those are what is checked. The clock source itself is only known when configured with `CLOCK_SOURCE`, and the
+/// sample rate the studio runs at with `CLOCK_EXPECTED_SAMPLE_RATE`.
+#[derive(Debug)]
+pub struct ClockMonitor {
+    source:               Option<String>,
+    expected_sample_rate: Option<f64>,
+    last_check:           Option<Instant>,
+    last_report:          Option<(Instant, ClockStatus)>,
+    last_underrun_time:   u32,
+    last_underrun_at:     Option<Instant>,
+    underruns:            u64,
+}
+
+impl ClockMonitor {
+    pub fn new() -> Self {
+        let expected_sample_rate = env::var("CLOCK_EXPECTED_SAMPLE_RATE").ok()
+                                                                         .and_then(|rate| rate.parse().ok());
+
+        Self { source:               { env::var("CLOCK_SOURCE").ok() },
+               expected_sample_rate: { expected_sample_rate },
+               last_check:           { None },
+               last_report:          { None },
+               last_underrun_time:   { 0 },
+               last_underrun_at:     { None },
+               underruns:            { 0 }, }
+    }
+
+    /// Check the audio device when it is time to, returning the status when it should be reported
+    pub fn run(&mut self) -> Option<ClockStatus> {
+        let now = Instant::now();
+        if matches!(self.last_check, Some(last_check) if now - last_check < CHECK_INTERVAL) {
+            return None;
+        }
+
+        self.last_check = Some(now);
+
+        let status = self.check(now);
+        let report = match &self.last_report {
+            Some((reported_at, reported)) => reported != &status || now - *reported_at >= REPORT_INTERVAL,
+            None => true,
+        };
+
+        if report {
+            self.last_report = Some((now, status.clone()));
+            Some(status)
+        } else {
+            None
+        }
+    }
+
+    /// Audio thread underruns seen since the engine started
+    pub fn underruns(&self) -> u64 {
+        self.underruns
+    }
+
+    fn check(&mut self, now: Instant) -> ClockStatus {
+        let reaper = Reaper::get();
+
+        let (running, audio_underrun_time) = unsafe {
+            let mut audio_underrun_time = 0;
+            reaper.low()
+                  .GetUnderrunTime(&mut audio_underrun_time, std::ptr::null_mut(), std::ptr::null_mut());
+
+            (reaper.low().Audio_IsRunning() != 0, audio_underrun_time)
+        };
+
+        // REAPER keeps the time of the last underrun only, every change is a new one
+        if audio_underrun_time != 0 && audio_underrun_time != self.last_underrun_time {
+            self.last_underrun_time = audio_underrun_time;
+            self.last_underrun_at = Some(now);
+            self.underruns += 1;
+        }
+
+        let sample_rate = get_audio_device_info(cstr!("SRATE")).and_then(|rate| rate.parse::<f64>().ok());
+        let device = get_audio_device_info(cstr!("IDENT_OUT")).or_else(|| get_audio_device_info(cstr!("MODE")));
+
+        let rate_matches = match (sample_rate, self.expected_sample_rate) {
+            (Some(sample_rate), Some(expected)) => (sample_rate - expected).abs() < 1.0,
+            (None, Some(_)) => false,
+            _ => true,
+        };
+
+        let recent_underrun = matches!(self.last_underrun_at, Some(at) if now - at < UNDERRUN_WINDOW);
+
+        ClockStatus { source:               { self.source.clone() },
+                      device:               { device },
+                      sample_rate:          { sample_rate },
+                      expected_sample_rate: { self.expected_sample_rate },
+                      running:              { running },
+                      underruns:            { self.underruns },
+                      locked:               { running && rate_matches && !recent_underrun }, }
+    }
+}
+
+pub fn get_audio_device_info(attribute: &CStr) -> Option<String> {
+    let reaper = Reaper::get();
+    let mut buffer = [0i8; 512];
+
+    unsafe {
+        if !reaper.low()
+                  .GetAudioDeviceInfo(attribute.as_ptr(), buffer.as_mut_ptr(), buffer.len() as i32)
+        {
+            return None;
+        }
+
+        let value = CStr::from_ptr(buffer.as_ptr()).to_string_lossy().to_string();
+        if value.is_empty() {
+            None
+        } else {
+            Some(value)
+        }
+    }
+}
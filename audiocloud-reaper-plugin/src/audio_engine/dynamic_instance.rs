@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+
+use anyhow::anyhow;
+use askama::Template;
+use reaper_medium::{MediaTrack, ProjectContext, Reaper};
+use serde_json::Value;
+use tracing::*;
+use uuid::Uuid;
+
+use audiocloud_api::common::task::{DynamicInstanceNode, InstanceParameters, InstanceReports, NodePadId};
+use audiocloud_api::newtypes::DynamicInstanceNodeId;
+use audiocloud_api::{InputPadId, OutputPadId, PadMetering};
+
+use crate::audio_engine::project::{get_track_peak_meters, EngineProject, EngineProjectTemplateSnapshot};
+use crate::audio_engine::{append_track, beautify_chunk, delete_track, set_track_chunk, ConnectionTemplate};
+use crate::events::Envelope;
+
+/// A software processor, inserted as FX on the output track of the instance
+///
+/// The input track takes the connections to the instance and sends them on to the output track. Parameters are
+/// normalized FX parameter values by FX parameter name, they are applied again whenever the output track chunk is
+/// replaced, since replacing it drops the FX. The same goes for the envelopes of the FX parameters.
+#[derive(Debug)]
+pub struct EngineDynamicInstance {
+    dynamic_id:    DynamicInstanceNodeId,
+    input_pad_id:  InputPadId,
+    output_pad_id: OutputPadId,
+    input_id:      Uuid,
+    output_id:     Uuid,
+    input_track:   MediaTrack,
+    output_track:  MediaTrack,
+    spec:          DynamicInstanceNode,
+    envelopes:     HashMap<String, Envelope>,
+}
+
+impl EngineDynamicInstance {
+    #[instrument(skip_all, err)]
+    pub fn new(project: &EngineProject,
+               dynamic_id: DynamicInstanceNodeId,
+               spec: DynamicInstanceNode)
+               -> anyhow::Result<Self> {
+        let input_pad_id = InputPadId::DynamicInstanceInput(dynamic_id.clone());
+        let output_pad_id = OutputPadId::DynamicInstanceOutput(dynamic_id.clone());
+
+        project.focus()?;
+
+        let (input_track, input_id) = append_track(&input_pad_id.clone().into(), project.context())?;
+        let (output_track, output_id) = append_track(&output_pad_id.clone().into(), project.context())?;
+        let envelopes = project.dynamic_instance_envelopes(&dynamic_id);
+
+        let rv = Self { dynamic_id:    { dynamic_id },
+                        input_pad_id:  { input_pad_id },
+                        output_pad_id: { output_pad_id },
+                        input_id:      { input_id },
+                        output_id:     { output_id },
+                        input_track:   { input_track },
+                        output_track:  { output_track },
+                        spec:          { spec },
+                        envelopes:     { envelopes }, };
+
+        rv.insert_fx()?;
+
+        Ok(rv)
+    }
+
+    pub(crate) fn delete(&self, context: ProjectContext) {
+        delete_track(context, self.input_track);
+        delete_track(context, self.output_track);
+    }
+
+    pub fn get_input_track(&self) -> MediaTrack {
+        self.input_track
+    }
+
+    pub fn get_input_state_chunk(&self, project: &EngineProjectTemplateSnapshot) -> anyhow::Result<String> {
+        Ok(beautify_chunk(DynamicInputTemplate { project,
+                                                 instance: self }.render()?))
+    }
+
+    pub fn get_output_state_chunk(&self, project: &EngineProjectTemplateSnapshot) -> anyhow::Result<String> {
+        Ok(beautify_chunk(DynamicOutputTemplate { project,
+                                                  instance: self }.render()?))
+    }
+
+    pub fn update_input_state_chunk(&self, project: &EngineProjectTemplateSnapshot) -> anyhow::Result<()> {
+        set_track_chunk(project.context(),
+                        self.input_track,
+                        &self.get_input_state_chunk(project)?)
+    }
+
+    pub fn update_output_state_chunk(&self, project: &EngineProjectTemplateSnapshot) -> anyhow::Result<()> {
+        set_track_chunk(project.context(),
+                        self.output_track,
+                        &self.get_output_state_chunk(project)?)?;
+
+        self.insert_fx()
+    }
+
+    #[instrument(skip_all, err, fields(id = %self.dynamic_id))]
+    pub fn update_state_chunk(&self, project: &EngineProjectTemplateSnapshot) -> anyhow::Result<()> {
+        self.update_input_state_chunk(project)?;
+        self.update_output_state_chunk(project)?;
+
+        Ok(())
+    }
+
+    /// Merge parameter values into the ones the instance has, and apply them to the FX
+    pub fn set_parameters(&mut self, values: InstanceParameters) -> anyhow::Result<()> {
+        match (&mut self.spec.parameters, values) {
+            (Value::Object(current), Value::Object(values)) => current.extend(values),
+            (current, values) => *current = values,
+        }
+
+        self.apply_parameters()
+    }
+
+    /// Replace the envelopes of the FX parameters by parameter name, parameters without one keep their values
+    pub fn set_envelopes(&mut self, envelopes: HashMap<String, Envelope>) {
+        if self.envelopes != envelopes {
+            self.envelopes = envelopes;
+            self.apply_envelopes();
+        }
+    }
+
+    /// Normalized values of all parameters of the FX, by name
+    pub fn get_reports(&self) -> InstanceReports {
+        let track = self.output_track.as_ptr();
+        let low = Reaper::get().low();
+
+        let mut reports = serde_json::Map::new();
+        for (index, name) in get_fx_param_names(self.output_track) {
+            let value = unsafe { low.TrackFX_GetParamNormalized(track, 0, index) };
+            reports.insert(name, Value::from(value));
+        }
+
+        Value::Object(reports)
+    }
+
+    pub fn fill_peak_meters(&self, peaks: &mut HashMap<NodePadId, PadMetering>) {
+        peaks.insert(self.input_pad_id.clone().into(),
+                     get_track_peak_meters(self.input_track, 2));
+
+        peaks.insert(self.output_pad_id.clone().into(),
+                     get_track_peak_meters(self.output_track, 2));
+    }
+
+    /// Add the FX of the model to the output track unless it is there already, and apply the parameters and envelopes
+    /// to it
+    fn insert_fx(&self) -> anyhow::Result<()> {
+        let fx_name = CString::new(self.spec.model_id.name.as_str())?;
+
+        let index = unsafe {
+            Reaper::get().low()
+                         .TrackFX_AddByName(self.output_track.as_ptr(), fx_name.as_ptr(), false, -1)
+        };
+
+        if index < 0 {
+            return Err(anyhow!("FX {} of dynamic instance {} not found",
+                               self.spec.model_id,
+                               self.dynamic_id));
+        }
+
+        self.apply_parameters()?;
+        self.apply_envelopes();
+
+        Ok(())
+    }
+
+    fn apply_parameters(&self) -> anyhow::Result<()> {
+        let parameters = match &self.spec.parameters {
+            Value::Object(parameters) => parameters,
+            Value::Null => return Ok(()),
+            _ => return Err(anyhow!("Parameters of dynamic instance {} are not an object", self.dynamic_id)),
+        };
+
+        let indices = get_fx_param_names(self.output_track).into_iter()
+                                                           .map(|(index, name)| (name, index))
+                                                           .collect::<HashMap<_, _>>();
+
+        for (name, value) in parameters {
+            let (index, value) = match (indices.get(name), normalized_value(value)) {
+                (Some(index), Some(value)) => (*index, value),
+                (None, _) => {
+                    warn!(id = %self.dynamic_id, %name, "FX parameter not found");
+                    continue;
+                }
+                (_, None) => {
+                    warn!(id = %self.dynamic_id, %name, %value, "FX parameter value is not a number");
+                    continue;
+                }
+            };
+
+            unsafe {
+                Reaper::get().low()
+                             .TrackFX_SetParamNormalized(self.output_track.as_ptr(), 0, index, value);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the points of the envelopes, clearing envelopes of parameters that no longer have one
+    fn apply_envelopes(&self) {
+        let track = self.output_track.as_ptr();
+        let low = Reaper::get().low();
+
+        let names = get_fx_param_names(self.output_track);
+        for name in self.envelopes.keys() {
+            if !names.iter().any(|(_, param_name)| param_name == name) {
+                warn!(id = %self.dynamic_id, %name, "FX parameter of envelope not found");
+            }
+        }
+
+        for (index, name) in names {
+            let envelope = self.envelopes.get(&name);
+
+            unsafe {
+                let reaper_envelope = low.GetFXEnvelope(track, 0, index, envelope.is_some());
+                if reaper_envelope.is_null() {
+                    continue;
+                }
+
+                low.DeleteEnvelopePointRange(reaper_envelope, f64::MIN, f64::MAX);
+
+                if let Some(envelope) = envelope {
+                    let mut no_sort = true;
+                    for point in &envelope.points {
+                        low.InsertEnvelopePoint(reaper_envelope,
+                                                point.time,
+                                                point.value,
+                                                point.shape.reaper_shape(),
+                                                0.0,
+                                                false,
+                                                &mut no_sort);
+                    }
+
+                    low.Envelope_SortPoints(reaper_envelope);
+                }
+            }
+        }
+    }
+}
+
+/// Index and name of each parameter of the first FX on the track
+fn get_fx_param_names(track: MediaTrack) -> Vec<(i32, String)> {
+    let low = Reaper::get().low();
+    let mut rv = vec![];
+
+    unsafe {
+        for index in 0..low.TrackFX_GetNumParams(track.as_ptr(), 0) {
+            let mut buffer = [0i8; 256];
+            if low.TrackFX_GetParamName(track.as_ptr(), 0, index, buffer.as_mut_ptr(), buffer.len() as i32) {
+                rv.push((index, CStr::from_ptr(buffer.as_ptr()).to_string_lossy().to_string()));
+            }
+        }
+    }
+
+    rv
+}
+
+/// FX parameters are not per channel, of per channel values the first one is applied
+fn normalized_value(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64().map(|value| value.clamp(0.0, 1.0)),
+        Value::Bool(value) => Some(if *value { 1.0 } else { 0.0 }),
+        Value::Array(values) => values.first().and_then(normalized_value),
+        _ => None,
+    }
+}
+
+#[derive(Template)]
+#[template(path = "audio_engine/dynamic_input.txt")]
+struct DynamicInputTemplate<'a> {
+    project:  &'a EngineProjectTemplateSnapshot,
+    instance: &'a EngineDynamicInstance,
+}
+
+#[derive(Template)]
+#[template(path = "audio_engine/dynamic_output.txt")]
+struct DynamicOutputTemplate<'a> {
+    project:  &'a EngineProjectTemplateSnapshot,
+    instance: &'a EngineDynamicInstance,
+}
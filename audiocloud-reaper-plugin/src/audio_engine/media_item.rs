@@ -10,9 +10,14 @@ use tracing::*;
 use uuid::Uuid;
 
 use crate::audio_engine::media_track::EngineMediaTrack;
+use crate::audio_engine::midi::MidiClip;
 use crate::audio_engine::project::EngineProjectTemplateSnapshot;
+use crate::events::{TrackMediaFades, TrackMediaRate};
 use audiocloud_api::newtypes::{AppId, AppMediaObjectId, TrackMediaId};
 
+/// Media ending less than this many seconds from where other media starts is crossfaded with it
+const ADJACENT_MEDIA_TOLERANCE: f64 = 0.001;
+
 #[derive(Debug)]
 pub struct EngineMediaItem {
     media_id:  TrackMediaId,
@@ -24,6 +29,7 @@ pub struct EngineMediaItem {
     take:      MediaItemTake,
     spec:      TrackMedia,
     path:      Option<String>,
+    midi:      Option<MidiClip>,
 }
 
 impl EngineMediaItem {
@@ -46,6 +52,8 @@ impl EngineMediaItem {
 
         debug!(?path, "path");
 
+        let midi = load_midi_clip(&path);
+
         let reaper = Reaper::get();
 
         let item = unsafe { reaper.add_media_item_to_track(track)? };
@@ -67,7 +75,8 @@ impl EngineMediaItem {
                   item,
                   take,
                   spec,
-                  path })
+                  path,
+                  midi })
     }
 
     #[instrument(skip_all, err)]
@@ -89,6 +98,7 @@ impl EngineMediaItem {
             if let Some(path) = available.get(&self.object_id) {
                 let new_path = Some(root_dir.join(path).to_str().unwrap().to_string());
                 if &new_path != &self.path {
+                    self.midi = load_midi_clip(&new_path);
                     self.path = new_path;
                     debug!("our path is replaced, queue to sync");
                     return true;
@@ -104,6 +114,75 @@ impl EngineMediaItem {
     pub fn update(&mut self, update: UpdateTaskTrackMedia) {
         self.spec.update(update.clone());
     }
+
+    pub fn item(&self) -> MediaItem {
+        self.item
+    }
+}
+
+/// MIDI files are inlined into the project as MIDI items, anything else is played from the file
+fn load_midi_clip(path: &Option<String>) -> Option<MidiClip> {
+    let path = path.as_ref()?;
+
+    match MidiClip::load(path) {
+        Ok(midi) => midi,
+        Err(error) => {
+            warn!(%error, %path, "Could not read MIDI file");
+            None
+        }
+    }
+}
+
+/// A take REAPER recorded, `offset` is where in the file it starts
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedTake {
+    pub path:   PathBuf,
+    pub offset: f64,
+}
+
+/// Takes of a recorded media item, in pass order when loop recording added one take per pass, or only the active take
+pub fn get_media_item_recorded_takes(media_item: MediaItem, all_takes: bool) -> Vec<RecordedTake> {
+    let reaper = Reaper::get();
+
+    unsafe {
+        let item = media_item.as_ptr();
+        let takes = if all_takes {
+            let count = reaper.low().CountTakes(item);
+            (0..count).map(|index| reaper.low().GetTake(item, index)).collect()
+        } else {
+            vec![reaper.low().GetActiveTake(item)]
+        };
+
+        takes.into_iter().filter_map(|take| get_recorded_take(take)).collect()
+    }
+}
+
+unsafe fn get_recorded_take(take: *mut reaper_low::raw::MediaItem_Take) -> Option<RecordedTake> {
+    let reaper = Reaper::get();
+    let mut buffer = [0i8; 4096];
+
+    if take.is_null() {
+        return None;
+    }
+
+    let source = reaper.low().GetMediaItemTake_Source(take);
+    if source.is_null() {
+        return None;
+    }
+
+    reaper.low()
+          .GetMediaSourceFileName(source, buffer.as_mut_ptr(), buffer.len() as i32);
+
+    let path = CStr::from_ptr(buffer.as_ptr()).to_string_lossy().to_string();
+    if path.is_empty() {
+        return None;
+    }
+
+    let offset = reaper.low()
+                       .GetMediaItemTakeInfo_Value(take, cstr!("D_STARTOFFS").as_ptr());
+
+    Some(RecordedTake { path: PathBuf::from(path),
+                        offset })
 }
 
 #[instrument(skip_all, err)]
@@ -159,4 +238,78 @@ impl<'a> EngineMediaItemTemplate<'a> {
                -> Self {
         Self { media, track, project }
     }
+
+    fn fades(&self) -> TrackMediaFades {
+        self.project.media_fades(&self.media.media_id)
+    }
+
+    fn rate(&self) -> TrackMediaRate {
+        self.project.media_rate(&self.media.media_id)
+    }
+
+    /// Length of the media on the timeline at its rate, the timeline segment is as long as the media at a rate of 1
+    pub fn timeline_length(&self) -> f64 {
+        self.media.spec.timeline_segment.length / self.rate().rate
+    }
+
+    /// Length of the crossfade into the item, no longer than the media before its media segment at the rate
+    fn crossfade(&self) -> f64 {
+        let spec = &self.media.spec;
+        let handle = (spec.media_segment.start / self.rate().rate).min(spec.timeline_segment.start)
+                                                                  .max(0.0);
+
+        self.fades().crossfade.clamp(0.0, handle)
+    }
+
+    fn position(&self) -> f64 {
+        self.media.spec.timeline_segment.start - self.crossfade()
+    }
+
+    fn length(&self) -> f64 {
+        self.timeline_length() + self.crossfade()
+    }
+
+    fn source_start(&self) -> f64 {
+        self.media.spec.media_segment.start - self.crossfade() * self.rate().rate
+    }
+
+    fn source_length(&self) -> f64 {
+        self.media.spec.media_segment.length + self.crossfade() * self.rate().rate
+    }
+
+    fn play_rate(&self) -> f64 {
+        self.rate().rate
+    }
+
+    fn preserve_pitch(&self) -> i32 {
+        self.rate().stretch.preserves_pitch() as i32
+    }
+
+    fn pitch_mode(&self) -> i32 {
+        self.rate().stretch.reaper_pitch_mode()
+    }
+
+    fn fade_in(&self) -> f64 {
+        match self.crossfade() {
+            crossfade if crossfade > 0.0 => crossfade,
+            _ => self.fades().fade_in,
+        }
+    }
+
+    /// The item fades out over its own fade out, or over the crossfade of media starting where it ends if longer
+    fn fade_out(&self) -> f64 {
+        let spec = &self.media.spec;
+        let end = spec.timeline_segment.start + self.timeline_length();
+
+        self.track
+            .media_items()
+            .filter(|other| other.media_id != self.media.media_id)
+            .filter(|other| (other.spec.timeline_segment.start - end).abs() < ADJACENT_MEDIA_TOLERANCE)
+            .map(|other| EngineMediaItemTemplate::new(other, self.track, self.project).crossfade())
+            .fold(self.fades().fade_out, f64::max)
+    }
+
+    fn fade_shape(&self) -> i32 {
+        self.fades().shape.reaper_shape()
+    }
 }
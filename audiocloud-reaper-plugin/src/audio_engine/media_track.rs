@@ -2,7 +2,8 @@ use std::collections::HashMap;
 use std::path::PathBuf;
 
 use askama::Template;
-use reaper_medium::{MediaTrack, ProjectContext};
+use cstr::cstr;
+use reaper_medium::{MediaItem, MediaTrack, ProjectContext, Reaper};
 use tracing::*;
 use uuid::Uuid;
 
@@ -11,9 +12,12 @@ use audiocloud_api::newtypes::{AppId, AppMediaObjectId, TrackMediaId, TrackNodeI
 use audiocloud_api::{NodePadId, OutputPadId, PadMetering};
 
 use crate::audio_engine;
-use crate::audio_engine::media_item::{EngineMediaItem, EngineMediaItemTemplate};
+use crate::audio_engine::media_item::{
+    get_media_item_recorded_takes, EngineMediaItem, EngineMediaItemTemplate, RecordedTake,
+};
 use crate::audio_engine::project::{get_track_peak_meters, EngineProject, EngineProjectTemplateSnapshot};
 use crate::audio_engine::{append_track, delete_track, set_track_chunk};
+use crate::events::TrackHardwareInput;
 
 #[derive(Debug)]
 pub struct EngineMediaTrack {
@@ -24,6 +28,8 @@ pub struct EngineMediaTrack {
     track:         MediaTrack,
     media:         HashMap<TrackMediaId, EngineMediaItem>,
     spec:          TrackNode,
+    input:         Option<TrackHardwareInput>,
+    recording:     bool,
     root_dir:      PathBuf,
 }
 
@@ -57,6 +63,8 @@ impl EngineMediaTrack {
                         track:         { track },
                         media:         { media },
                         spec:          { spec },
+                        input:         { None },
+                        recording:     { false },
                         root_dir:      { root_dir }, };
 
         Ok(rv)
@@ -71,6 +79,20 @@ impl EngineMediaTrack {
         &self.output_pad_id
     }
 
+    pub fn media_items(&self) -> impl Iterator<Item = &EngineMediaItem> {
+        self.media.values()
+    }
+
+    /// Lengths of the media items on the timeline at their rates, by track media
+    pub fn media_lengths(&self, project: &EngineProjectTemplateSnapshot) -> HashMap<TrackMediaId, f64> {
+        self.media
+            .iter()
+            .map(|(media_id, media)| {
+                (media_id.clone(), EngineMediaItemTemplate::new(media, self, project).timeline_length())
+            })
+            .collect()
+    }
+
     pub fn get_state_chunk(&self, project: &EngineProjectTemplateSnapshot) -> anyhow::Result<String> {
         Ok(audio_engine::beautify_chunk(EngineMediaTrackTemplate { project, track: self }.render()?))
     }
@@ -86,6 +108,70 @@ impl EngineMediaTrack {
         rv
     }
 
+    /// Returns true if the input changed and the state chunk needs updating
+    pub fn set_input(&mut self, input: Option<TrackHardwareInput>) -> bool {
+        if self.input == input {
+            false
+        } else {
+            self.input = input;
+            true
+        }
+    }
+
+    /// Returns true if the record mode changed and the state chunk needs updating
+    pub fn set_recording(&mut self, recording: bool) -> bool {
+        if self.recording == recording {
+            false
+        } else {
+            self.recording = recording;
+            self.input.is_some()
+        }
+    }
+
+    /// Records its input on the following plays
+    pub fn is_armed(&self) -> bool {
+        self.recording && self.input.is_some()
+    }
+
+    /// Temporarily switch an armed track between recording its input and only monitoring it, without a chunk update,
+    /// tracks that are not armed keep only monitoring
+    pub fn set_record_mode(&self, recording: bool) {
+        if self.input.is_some() {
+            unsafe {
+                Reaper::get().low().SetMediaTrackInfo_Value(self.track.as_ptr(),
+                                                            cstr!("I_RECMODE").as_ptr(),
+                                                            reaper_rec_mode(recording && self.recording) as f64);
+            }
+        }
+    }
+
+    /// Remove the media items REAPER recorded on the track and return their takes, all of them for loop recordings
+    #[instrument(skip_all, err, fields(id = %self.id))]
+    pub fn take_recorded_items(&mut self, loop_record: bool) -> anyhow::Result<Vec<RecordedTake>> {
+        let reaper = Reaper::get();
+        let mut recorded = vec![];
+
+        let count = unsafe { reaper.low().CountTrackMediaItems(self.track.as_ptr()) };
+        for index in (0..count).rev() {
+            let item = match MediaItem::new(unsafe { reaper.low().GetTrackMediaItem(self.track.as_ptr(), index) }) {
+                Some(item) => item,
+                None => continue,
+            };
+
+            if self.media.values().any(|media| media.item() == item) {
+                continue;
+            }
+
+            recorded.extend(get_media_item_recorded_takes(item, loop_record));
+
+            unsafe {
+                reaper.delete_track_media_item(self.track, item)?;
+            }
+        }
+
+        Ok(recorded)
+    }
+
     #[instrument(skip_all, err)]
     pub fn set_media_values(&mut self,
                             media_id: TrackMediaId,
@@ -147,3 +233,28 @@ struct EngineMediaTrackTemplate<'a> {
     project: &'a EngineProjectTemplateSnapshot,
     track:   &'a EngineMediaTrack,
 }
+
+impl<'a> EngineMediaTrackTemplate<'a> {
+    fn reaper_rec_input(&self) -> Option<i32> {
+        self.track.input.map(|input| {
+                            (match self.track.spec.channels.num_channels() {
+                                1 => input.channel,
+                                2 => input.channel | 1024,
+                                x => input.channel | (x << 4),
+                            }) as i32
+                        })
+    }
+
+    fn reaper_rec_mode(&self) -> i32 {
+        reaper_rec_mode(self.track.recording)
+    }
+}
+
+/// REAPER record mode of a track with a hardware input: record the input, or only monitor it
+fn reaper_rec_mode(recording: bool) -> i32 {
+    if recording {
+        0
+    } else {
+        2
+    }
+}
@@ -0,0 +1,181 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, ensure};
+
+/// Notes and controllers of a standard MIDI file, merged into one list the way an in-project REAPER MIDI item holds
+/// them
+#[derive(Debug, Clone, PartialEq)]
+pub struct MidiClip {
+    pub ticks_per_quarter: u16,
+    pub events:            Vec<MidiClipEvent>,
+}
+
+/// A channel voice message, `delta` ticks after the event before it
+#[derive(Debug, Clone, PartialEq)]
+pub struct MidiClipEvent {
+    pub delta: u64,
+    pub bytes: Vec<u8>,
+}
+
+impl MidiClipEvent {
+    /// Bytes as they are written in a project file, lower case hex separated by spaces
+    pub fn hex(&self) -> String {
+        self.bytes
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl MidiClip {
+    /// Load a MIDI clip from a media file, `None` if the file is not a standard MIDI file
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Option<Self>> {
+        let data = fs::read(path)?;
+        if !data.starts_with(b"MThd") {
+            return Ok(None);
+        }
+
+        Ok(Some(Self::parse(&data)?))
+    }
+
+    pub fn parse(data: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader { data, pos: 0 };
+
+        ensure!(reader.take(4)? == b"MThd", "Not a standard MIDI file");
+        let header_length = reader.u32()? as usize;
+        ensure!(header_length >= 6, "MIDI header is too short");
+
+        let _format = reader.u16()?;
+        let tracks = reader.u16()?;
+        let division = reader.u16()?;
+        reader.take(header_length - 6)?;
+
+        ensure!(division & 0x8000 == 0 && division > 0,
+                "Only MIDI files timed in ticks per quarter note are supported");
+
+        let mut timed = vec![];
+        for _ in 0..tracks {
+            let id = reader.take(4)?;
+            let length = reader.u32()? as usize;
+            let chunk = reader.take(length)?;
+
+            if id == b"MTrk" {
+                read_track(chunk, &mut timed)?;
+            }
+        }
+
+        // stable, so events of the same tick keep their order within a track
+        timed.sort_by_key(|(tick, _)| *tick);
+
+        let mut last = 0;
+        let events = timed.into_iter()
+                          .map(|(tick, bytes)| {
+                              let delta = tick - last;
+                              last = tick;
+                              MidiClipEvent { delta, bytes }
+                          })
+                          .collect();
+
+        Ok(Self { ticks_per_quarter: division,
+                  events })
+    }
+}
+
+/// Channel voice messages of a track at their absolute ticks, meta and system exclusive events are skipped
+fn read_track(data: &[u8], events: &mut Vec<(u64, Vec<u8>)>) -> anyhow::Result<()> {
+    let mut reader = Reader { data, pos: 0 };
+    let mut running_status = None;
+    let mut tick = 0u64;
+
+    while !reader.is_empty() {
+        tick += reader.vlq()? as u64;
+
+        let status = match reader.peek()? {
+            byte if byte & 0x80 != 0 => {
+                reader.pos += 1;
+                byte
+            }
+            _ => running_status.ok_or_else(|| anyhow!("Data byte without a status"))?,
+        };
+
+        match status {
+            0xFF => {
+                let kind = reader.take(1)?[0];
+                let length = reader.vlq()? as usize;
+                reader.take(length)?;
+
+                if kind == 0x2F {
+                    break;
+                }
+            }
+            0xF0 | 0xF7 => {
+                let length = reader.vlq()? as usize;
+                reader.take(length)?;
+            }
+            0x80..=0xEF => {
+                let length = if matches!(status & 0xF0, 0xC0 | 0xD0) { 1 } else { 2 };
+                let mut bytes = vec![status];
+                bytes.extend_from_slice(reader.take(length)?);
+
+                events.push((tick, bytes));
+                running_status = Some(status);
+            }
+            _ => bail!("Unexpected MIDI status {status:#04x}"),
+        }
+    }
+
+    Ok(())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos:  usize,
+}
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn peek(&self) -> anyhow::Result<u8> {
+        self.data
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| anyhow!("MIDI data ends unexpectedly"))
+    }
+
+    fn take(&mut self, count: usize) -> anyhow::Result<&'a [u8]> {
+        let end = self.pos + count;
+        ensure!(end <= self.data.len(), "MIDI data ends unexpectedly");
+
+        let rv = &self.data[self.pos..end];
+        self.pos = end;
+
+        Ok(rv)
+    }
+
+    fn u16(&mut self) -> anyhow::Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn vlq(&mut self) -> anyhow::Result<u32> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let byte = self.take(1)?[0];
+            value = (value << 7) | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        bail!("MIDI variable length quantity is too long")
+    }
+}
@@ -27,6 +27,7 @@ pub struct AudioMixer {
     input_track:   MediaTrack,
     output_track:  MediaTrack,
     spec:          MixerNode,
+    master_send:   bool,
 }
 
 impl AudioMixer {
@@ -54,7 +55,8 @@ impl AudioMixer {
                   output_id:     { output_id },
                   input_track:   { input_track },
                   output_track:  { output_track },
-                  spec:          { spec }, })
+                  spec:          { spec },
+                  master_send:   { false }, })
     }
 
     pub fn get_input_track(&self) -> MediaTrack {
@@ -90,7 +92,13 @@ impl AudioMixer {
                      get_track_peak_meters(self.output_track, self.spec.output_channels));
     }
 
+    /// Output pad of the mixer, if it is sent to the master and so streamed
+    pub fn streamed_pad(&self) -> Option<NodePadId> {
+        Some(self.output_pad_id.clone().into()).filter(|_| self.master_send)
+    }
+
     pub fn set_master_send(&mut self, master_send: bool) {
+        self.master_send = master_send;
         set_track_master_send(self.output_track, master_send);
     }
 
@@ -1,7 +1,7 @@
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::ffi::{CStr, CString};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::ptr::null_mut;
 
 use anyhow::anyhow;
@@ -15,8 +15,10 @@ use reaper_medium::{
     ProjectRef, ReaProject, Reaper, ReaperPanValue, ReaperVolumeValue, SetEditCurPosOptions, TimeRangeType,
     TrackAttributeKey, TrackSendCategory, TrackSendRef,
 };
+use serde::Serialize;
 use tempdir::TempDir;
 use tracing::*;
+use uuid::Uuid;
 
 use audiocloud_api::audio_engine::event::EngineEvent;
 use audiocloud_api::cloud::domains::FixedInstanceRouting;
@@ -24,23 +26,46 @@ use audiocloud_api::common::change::{ModifyTaskSpec, UpdateTaskPlay};
 use audiocloud_api::common::media::{PlayId, RenderId, RequestPlay, RequestRender};
 
 use audiocloud_api::common::task::{
-    ConnectionValues, FixedInstanceNode, MixerNode, NodeConnection, TaskSpec, TimeSegment, TrackNode,
+    ConnectionValues, DynamicInstanceNode, FixedInstanceNode, InstanceParameters, InstanceReports, MixerNode,
+    NodeConnection, TaskSpec, TimeSegment, TrackNode,
 };
 use audiocloud_api::common::time::Timestamped;
 use audiocloud_api::newtypes::{
-    AppMediaObjectId, AppTaskId, FixedInstanceId, FixedInstanceNodeId, MixerNodeId, NodeConnectionId, TrackNodeId,
+    AppMediaObjectId, AppTaskId, DynamicInstanceNodeId, FixedInstanceId, FixedInstanceNodeId, MixerNodeId,
+    NodeConnectionId, TrackMediaId, TrackNodeId,
 };
 use audiocloud_api::{InputPadId, NodePadId, OutputPadId, PadMetering};
 
+use crate::audio_engine::click::{bars_before, count_in_start, ClickTrack, CLICK_TRACK_NAME};
+use crate::audio_engine::dynamic_instance::EngineDynamicInstance;
 use crate::audio_engine::fixed_instance::EngineFixedInstance;
 use crate::audio_engine::media_track::EngineMediaTrack;
 use crate::audio_engine::mixer::AudioMixer;
+use crate::audio_engine::render_conversion::RenderConversion;
+use crate::audio_engine::sync_output::SyncOutput;
 use crate::audio_engine::{EngineStatus, PluginRegistry};
+use crate::events::{
+    Click, EngineExtEvent, Envelope, Envelopes, LeadIn, MediaFades, MediaRates, Playlist, PunchRegion, RenderFormat,
+    TempoMap, TrackHardwareInput, TrackMediaFades, TrackMediaRate,
+};
+
+/// Commands kept per session for diagnostic bundles
+const MAX_RECENT_COMMANDS: usize = 32;
+
+#[derive(Serialize)]
+struct DiagnosticsBundle {
+    error:    String,
+    status:   EngineStatus,
+    commands: Vec<String>,
+    project:  String,
+}
 
 #[derive(Debug, Clone)]
 pub enum ProjectPlayState {
     PreparingToPlay(RequestPlay),
     Playing(RequestPlay),
+    /// Transport paused, the streaming plugin and instances stay as they are for the play to resume
+    Paused(RequestPlay),
     Rendering(RequestRender),
     Stopped,
 }
@@ -50,7 +75,31 @@ pub struct EngineProject {
     id:                    AppTaskId,
     project:               ReaProject,
     tracks:                HashMap<TrackNodeId, EngineMediaTrack>,
+    track_inputs:          HashMap<TrackNodeId, TrackHardwareInput>,
+    recording:             bool,
+    recording_takes:       bool,
+    loop_record:           bool,
+    /// Region recordings punch in and out of, instead of the segment of the play
+    punch:                 Option<PunchRegion>,
+    lead_in:               LeadIn,
+    count_in_until:        Option<f64>,
+    /// Click of the next play, generated once it starts
+    click:                 Option<Click>,
+    click_track:           Option<ClickTrack>,
+    playlist:              Playlist,
+    /// Segment of the playlist the current play is in, `None` while not playing a playlist
+    playlist_index:        Option<usize>,
+    /// Envelopes of connections and dynamic instance parameters, written into the track chunks and FX
+    envelopes:             Envelopes,
+    media_fades:           MediaFades,
+    media_rates:           MediaRates,
+    /// Mixer of the current play, sent to the master unless the monitor controller selects another source
+    play_mixer_id:         Option<MixerNodeId>,
+    monitor_source:        Option<MixerNodeId>,
+    monitor_gain:          f64,
+    sync_output:           SyncOutput,
     fixed_instances:       HashMap<FixedInstanceNodeId, EngineFixedInstance>,
+    dynamic_instances:     HashMap<DynamicInstanceNodeId, EngineDynamicInstance>,
     mixers:                HashMap<MixerNodeId, AudioMixer>,
     spec:                  TaskSpec,
     local_media_root:      PathBuf,
@@ -60,12 +109,23 @@ pub struct EngineProject {
     pub session_path:      PathBuf,
     pub reaper_play_state: Timestamped<PlayState>,
     pub events:            VecDeque<EngineEvent>,
+    pub ext_events:        VecDeque<EngineExtEvent>,
+    /// Most recent commands for the session, oldest first, written to diagnostic bundles
+    recent_commands:       VecDeque<String>,
+    /// Formats the file of the render is converted to once it finished
+    render_formats:        Option<(RenderId, Vec<RenderFormat>)>,
+    render_conversions:    Vec<RenderConversion>,
 }
 
 #[derive(Debug, Clone)]
 pub struct EngineProjectTemplateSnapshot {
-    context:     ProjectContext,
-    connections: HashMap<NodeConnectionId, NodeConnection>,
+    context:        ProjectContext,
+    connections:    HashMap<NodeConnectionId, NodeConnection>,
+    envelopes:      Envelopes,
+    media_fades:    MediaFades,
+    media_rates:    MediaRates,
+    /// Mixer receiving the click track of the play going on
+    click_mixer_id: Option<MixerNodeId>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -94,13 +154,16 @@ impl EngineProjectTemplateSnapshot {
     }
 
     fn get_track_index_for_pad(&self, pad_id: &NodePadId) -> Option<usize> {
+        self.get_track_index_by_name(&pad_id.to_string())
+    }
+
+    fn get_track_index_by_name(&self, track_name: &str) -> Option<usize> {
         let reaper = Reaper::get();
         let mut index = 0;
-        let track_name = pad_id.to_string();
 
         while let Some(track) = reaper.get_track(self.context, index) {
             let matches =
-                unsafe { reaper.get_set_media_track_info_get_name(track, |name| name.to_str() == track_name.as_str()) };
+                unsafe { reaper.get_set_media_track_info_get_name(track, |name| name.to_str() == track_name) };
 
             if matches.unwrap_or(false) {
                 return Some(index as usize);
@@ -116,6 +179,27 @@ impl EngineProjectTemplateSnapshot {
         self.get_track_index_for_pad(id)
     }
 
+    pub fn connection_envelopes(&self, connection_id: &NodeConnectionId) -> Vec<&Envelope> {
+        self.envelopes.connection_envelopes(connection_id)
+    }
+
+    pub fn media_fades(&self, media_id: &TrackMediaId) -> TrackMediaFades {
+        self.media_fades.media.get(media_id).copied().unwrap_or_default()
+    }
+
+    pub fn media_rate(&self, media_id: &TrackMediaId) -> TrackMediaRate {
+        self.media_rates.media.get(media_id).copied().unwrap_or_default()
+    }
+
+    /// Index of the click track, if the mixer receives it
+    pub fn click_track_index(&self, mixer_id: &MixerNodeId) -> Option<usize> {
+        if self.click_mixer_id.as_ref() != Some(mixer_id) {
+            return None;
+        }
+
+        self.get_track_index_by_name(CLICK_TRACK_NAME)
+    }
+
     pub fn fixed_input_track_index(&self, fixed_id: &FixedInstanceNodeId) -> Option<usize> {
         self.track_index(&NodePadId::FixedInstanceInput(fixed_id.clone()))
     }
@@ -124,6 +208,10 @@ impl EngineProjectTemplateSnapshot {
         self.track_index(&NodePadId::MixerInput(mixer_id.clone()))
     }
 
+    pub fn dynamic_input_track_index(&self, dynamic_id: &DynamicInstanceNodeId) -> Option<usize> {
+        self.track_index(&NodePadId::DynamicInstanceInput(dynamic_id.clone()))
+    }
+
     pub fn flows_to<'a>(&'a self,
                         flow: &'a InputPadId)
                         -> impl Iterator<Item = (&NodeConnectionId, &NodeConnection)> + 'a {
@@ -134,12 +222,15 @@ impl EngineProjectTemplateSnapshot {
 // NOTE: requires SWS extensions
 lazy_static! {
     static ref CMD_REC_MODE_SET_TIME_RANGE_AUTO_PUNCH: CommandId = CommandId::new(40076);
+    static ref CMD_TOGGLE_LOOP_POINTS_LINKED_TO_TIME_SELECTION: CommandId = CommandId::new(40621);
     static ref CMD_CREATE_PROJECT_TAB: CommandId = CommandId::new(40859);
     static ref CMD_CLOSE_CURRENT_PROJECT_TAB: CommandId = CommandId::new(40860);
     static ref CMD_SWITCH_TO_NEXT_PROJECT_TAB: CommandId = CommandId::new(40861);
     static ref CMD_TRANSPORT_RECORD: CommandId = CommandId::new(1013);
     static ref CMD_TRANSPORT_STOP_AND_SAVE_MEDIA: CommandId = CommandId::new(40667);
     static ref CMD_TRANSPORT_STOP_AND_DELETE_MEDIA: CommandId = CommandId::new(40668);
+    static ref CMD_METRONOME_ENABLE: CommandId = CommandId::new(41745);
+    static ref CMD_METRONOME_DISABLE: CommandId = CommandId::new(41746);
 }
 
 #[derive(Template)]
@@ -170,33 +261,56 @@ impl EngineProject {
                                           session_id: &id,
                                           media_root: local_media_root.to_string_lossy().to_string(), }.render()?)?;
 
-        reaper.main_on_command_ex(*CMD_CREATE_PROJECT_TAB, 0, CurrentProject);
-
-        unsafe {
-            let path_as_cstr = CString::new(format!("noprompt:{}", session_path.to_string_lossy()))?;
-            reaper.low().Main_openProject(path_as_cstr.as_ptr());
-        }
-
-        let project = reaper.enum_projects(ProjectRef::Current, 0)
-                            .ok_or_else(|| anyhow!("No current project even though we just opened one"))?
-                            .project;
+        let project = open_project_tab(&session_path)?;
 
         let context = ProjectContext::Proj(project);
 
         reaper.main_on_command_ex(*CMD_REC_MODE_SET_TIME_RANGE_AUTO_PUNCH, 0, context);
 
+        // the time selection is the punch region while the loop points stay on the segment of the play, REAPER must
+        // not move one with the other
+        let loop_points_linked = unsafe {
+            reaper.low()
+                  .GetToggleCommandStateEx(0, CMD_TOGGLE_LOOP_POINTS_LINKED_TO_TIME_SELECTION.get() as i32)
+        };
+        if loop_points_linked == 1 {
+            reaper.main_on_command_ex(*CMD_TOGGLE_LOOP_POINTS_LINKED_TO_TIME_SELECTION, 0, context);
+        }
+
         let tracks = Default::default();
+        let track_inputs = Default::default();
         let fixed_instances = Default::default();
+        let dynamic_instances = Default::default();
         let mixers = Default::default();
         let spec = Default::default();
         let play_state = ProjectPlayState::Stopped.into();
         let reaper_play_state = Timestamped::from(Reaper::get().get_play_state_ex(context));
         let events = VecDeque::new();
+        let ext_events = VecDeque::new();
 
         let mut rv = Self { id,
                             project,
                             tracks,
+                            track_inputs,
+                            recording: false,
+                            recording_takes: false,
+                            loop_record: false,
+                            punch: None,
+                            lead_in: LeadIn::default(),
+                            count_in_until: None,
+                            click: None,
+                            click_track: None,
+                            playlist: Playlist::default(),
+                            playlist_index: None,
+                            envelopes: Envelopes::default(),
+                            media_fades: MediaFades::default(),
+                            media_rates: MediaRates::default(),
+                            play_mixer_id: None,
+                            monitor_source: None,
+                            monitor_gain: 1.0,
+                            sync_output: SyncOutput::new(),
                             fixed_instances,
+                            dynamic_instances,
                             mixers,
                             spec,
                             local_media_root,
@@ -205,7 +319,11 @@ impl EngineProject {
                             session_path,
                             play_state,
                             reaper_play_state,
-                            events };
+                            events,
+                            ext_events,
+                            recent_commands: VecDeque::new(),
+                            render_formats: None,
+                            render_conversions: vec![] };
 
         rv.set_spec(session_spec, instances, media)?;
 
@@ -213,15 +331,29 @@ impl EngineProject {
     }
 
     pub fn template_snapshot(&self) -> EngineProjectTemplateSnapshot {
-        EngineProjectTemplateSnapshot { context:     self.context(),
-                                        connections: self.spec.connections.clone(), }
+        EngineProjectTemplateSnapshot { context:        self.context(),
+                                        connections:    self.spec.connections.clone(),
+                                        envelopes:      self.envelopes.clone(),
+                                        media_fades:    self.media_fades.clone(),
+                                        media_rates:    self.media_rates.clone(),
+                                        click_mixer_id: self.click_track.as_ref().map(|click| click.mixer_id.clone()), }
     }
 
     pub fn play_ready(&mut self, play_id: PlayId) {
         if let ProjectPlayState::PreparingToPlay(play) = self.play_state.value() {
             if play.play_id == play_id {
                 self.play_state = ProjectPlayState::Playing(play.clone()).into();
-                Reaper::get().on_play_button_ex(self.context());
+
+                // the time range auto punch record mode keeps the takes within the punch region or played segment
+                self.recording_takes = self.tracks.values().any(EngineMediaTrack::is_armed);
+                if self.recording_takes {
+                    Reaper::get().main_on_command_ex(*CMD_TRANSPORT_RECORD, 0, self.context());
+                } else {
+                    Reaper::get().on_play_button_ex(self.context());
+                }
+
+                let position = Reaper::get().get_cursor_position_ex(self.context()).get();
+                self.sync_output.start(position, self.recording_takes);
             }
         }
     }
@@ -248,8 +380,21 @@ impl EngineProject {
             }
             ProjectPlayState::Playing(play) => {
                 debug!(cur_pos, end = play.segment.end(), "playing...");
-                if !new_play_state.is_playing && self.reaper_play_state.value().is_playing {
+                if matches!(self.count_in_until, Some(until) if cur_pos >= until) {
+                    self.end_count_in();
+                }
+                if let Some(index) = self.playlist_index {
+                    self.advance_playlist(index, play.looping, cur_pos);
+                }
+                if self.recording_takes && !play.looping && cur_pos >= play.segment.end() {
+                    debug!(play_id = %play.play_id, "reached end of recording");
+                    self.finish_recording_takes(play.segment, play.looping);
+                    self.clean_up_end_of_play(play.play_id);
+                } else if !new_play_state.is_playing && self.reaper_play_state.value().is_playing {
                     debug!(play_id = %play.play_id, "reached end of play");
+                    if self.recording_takes {
+                        self.finish_recording_takes(play.segment, play.looping);
+                    }
                     self.clean_up_end_of_play(play.play_id);
                 }
             }
@@ -263,6 +408,12 @@ impl EngineProject {
             _ => {}
         }
 
+        if new_play_state.is_playing {
+            self.sync_output.run(cur_pos);
+        }
+
+        self.poll_render_conversions();
+
         self.reaper_play_state = Timestamped::from(new_play_state);
 
         Ok(())
@@ -273,9 +424,12 @@ impl EngineProject {
         let context = self.context();
 
         reaper.main_on_command_ex(*CMD_TRANSPORT_STOP_AND_SAVE_MEDIA, 0, context);
+        self.sync_output.stop();
+        self.set_tracks_record_mode(self.recording);
 
         if let Some(mixer) = self.mixers.get_mut(&mixer_id) {
             if let Some(path) = mixer.clear_render() {
+                self.start_render_conversion(&render_id, &path);
                 self.events
                     .push_back(EngineEvent::RenderingFinished { task_id: self.id.clone(),
                                                                 render_id,
@@ -292,8 +446,161 @@ impl EngineProject {
         self.play_state = ProjectPlayState::Stopped.into();
     }
 
+    pub fn set_render_formats(&mut self, render_id: RenderId, formats: Vec<RenderFormat>) {
+        self.render_formats = Some((render_id, formats));
+    }
+
+    fn start_render_conversion(&mut self, render_id: &RenderId, path: &str) {
+        let formats = match self.render_formats.take() {
+            Some((formats_render_id, formats)) if &formats_render_id == render_id && !formats.is_empty() => formats,
+            _ => return,
+        };
+
+        self.render_conversions.push(RenderConversion::start(self.id.clone(),
+                                                             render_id.clone(),
+                                                             PathBuf::from(path),
+                                                             formats,
+                                                             self.shared_media_root.clone()));
+    }
+
+    fn poll_render_conversions(&mut self) {
+        let mut pending = vec![];
+        for conversion in self.render_conversions.drain(..) {
+            match conversion.poll() {
+                Some(event) => self.ext_events.push_back(event),
+                None => pending.push(conversion),
+            }
+        }
+
+        self.render_conversions = pending;
+    }
+
+    /// Stop recording and turn what the tracks with hardware inputs recorded into takes on the shared media root
+    fn finish_recording_takes(&mut self, segment: TimeSegment, looping: bool) {
+        let reaper = Reaper::get();
+
+        reaper.main_on_command_ex(*CMD_TRANSPORT_STOP_AND_SAVE_MEDIA, 0, self.context());
+        self.recording_takes = false;
+
+        let loop_record = self.loop_record && looping;
+        // REAPER keeps only what was recorded within the punch region, of every pass of a loop recording
+        let segment = self.punch.as_ref().map(|punch| punch.segment).unwrap_or(segment);
+
+        let mut recorded = vec![];
+        for (track_id, track) in self.tracks.iter_mut() {
+            match track.take_recorded_items(loop_record) {
+                Ok(takes) => recorded.push((track_id.clone(), takes)),
+                Err(err) => warn!(%err, %track_id, "failed to collect recorded items"),
+            }
+        }
+
+        // the passes of a loop recording share one file, which is moved only once
+        let mut stored = HashMap::<PathBuf, String>::new();
+
+        for (track_id, takes) in recorded {
+            for (index, take) in takes.into_iter().enumerate() {
+                let path = match stored.get(&take.path) {
+                    Some(path) => path.clone(),
+                    None => match self.store_media("takes", &take.path) {
+                        Ok(path) => {
+                            stored.insert(take.path.clone(), path.clone());
+                            path
+                        }
+                        Err(err) => {
+                            warn!(%err, recorded_path = ?take.path, "failed to store recorded take");
+                            continue;
+                        }
+                    },
+                };
+
+                let (pass, media_segment) = if loop_record {
+                    (Some(index + 1),
+                     Some(TimeSegment { start:  take.offset,
+                                        length: segment.length, }))
+                } else {
+                    (None, None)
+                };
+
+                self.ext_events
+                    .push_back(EngineExtEvent::TakeRecorded { task_id: self.id.clone(),
+                                                              track_id: track_id.clone(),
+                                                              segment,
+                                                              path,
+                                                              pass,
+                                                              media_segment });
+            }
+        }
+    }
+
+    /// Move a recorded file to `folder` of the shared media root, returning its path relative to the root
+    fn store_media(&self, folder: &str, recorded_path: &PathBuf) -> anyhow::Result<String> {
+        let extension = recorded_path.extension()
+                                     .map(|ext| ext.to_string_lossy().to_string())
+                                     .unwrap_or_else(|| "wav".to_owned());
+
+        let relative = PathBuf::from(folder).join(self.id.app_id.to_string())
+                                            .join(format!("{}.{extension}", Uuid::new_v4()));
+        let destination = self.shared_media_root.join(&relative);
+
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // the temporary folder of the session may be on a different file system than the shared media
+        if fs::rename(recorded_path, &destination).is_err() {
+            fs::copy(recorded_path, &destination)?;
+            fs::remove_file(recorded_path)?;
+        }
+
+        Ok(relative.to_string_lossy().to_string())
+    }
+
+    pub fn record_command(&mut self, command: String) {
+        if self.recent_commands.len() == MAX_RECENT_COMMANDS {
+            self.recent_commands.pop_front();
+        }
+
+        self.recent_commands.push_back(command);
+    }
+
+    /// Write the error, status, most recent commands and project file of the session to the shared media root,
+    /// returning the path of the bundle relative to the root
+    pub fn capture_diagnostics(&self, error: String) -> anyhow::Result<String> {
+        // the project file on disk is as the session was created, until REAPER saves it
+        unsafe {
+            Reaper::get().low().Main_SaveProject(self.project.as_ptr(), false);
+        }
+
+        let bundle = DiagnosticsBundle { error:    { error },
+                                         status:   { self.get_status()? },
+                                         commands: { self.recent_commands.iter().cloned().collect() },
+                                         project:  { fs::read_to_string(&self.session_path)? }, };
+
+        let relative = PathBuf::from("diagnostics").join(self.id.app_id.to_string())
+                                                   .join(format!("{}.json", Uuid::new_v4()));
+        let destination = self.shared_media_root.join(&relative);
+
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(&destination, serde_json::to_vec_pretty(&bundle)?)?;
+
+        Ok(relative.to_string_lossy().to_string())
+    }
+
+    fn set_tracks_record_mode(&self, recording: bool) {
+        for track in self.tracks.values() {
+            track.set_record_mode(recording);
+        }
+    }
+
     fn clean_up_end_of_play(&mut self, play_id: PlayId) {
         self.clear_mixer_master_sends();
+        self.end_count_in();
+        self.end_click();
+        self.sync_output.stop();
+        self.playlist_index = None;
         // a plugin flush is not critical, so we are fine with discarding the error
         let _ = PluginRegistry::flush(&self.id, play_id);
 
@@ -323,46 +630,87 @@ impl EngineProject {
             instance.fill_peak_meters(&mut peaks);
         }
 
+        for instance in self.dynamic_instances.values() {
+            instance.fill_peak_meters(&mut peaks);
+        }
+
         peaks
     }
 
+    /// Reports of the dynamic instances, the values their FX parameters have right now
+    pub fn get_dynamic_reports(&self) -> HashMap<DynamicInstanceNodeId, InstanceReports> {
+        self.dynamic_instances
+            .iter()
+            .map(|(dynamic_id, instance)| (dynamic_id.clone(), instance.get_reports()))
+            .collect()
+    }
+
+    /// Output pad of the mixer streamed by the current play, the pad the streaming plugin meters the loudness of
+    pub fn streamed_pad(&self) -> Option<NodePadId> {
+        self.mixers.values().find_map(AudioMixer::streamed_pad)
+    }
+
     #[instrument(skip_all, err)]
     pub fn focus(&self) -> anyhow::Result<()> {
-        let reaper = Reaper::get();
-        let mut first = None;
-        loop {
-            if let Some(enumerated) = reaper.enum_projects(ProjectRef::Current, 0) {
-                match first {
-                    None => first = Some(enumerated.project),
-                    Some(x) => {
-                        if x == enumerated.project {
-                            return Err(anyhow!("Project not found"));
-                        }
+        focus_project(self.project)
+    }
+}
+
+/// Open the project file at `path` in a new project tab, which becomes the current project
+pub(crate) fn open_project_tab(path: &Path) -> anyhow::Result<ReaProject> {
+    let reaper = Reaper::get();
+
+    reaper.main_on_command_ex(*CMD_CREATE_PROJECT_TAB, 0, CurrentProject);
+
+    unsafe {
+        let path_as_cstr = CString::new(format!("noprompt:{}", path.to_string_lossy()))?;
+        reaper.low().Main_openProject(path_as_cstr.as_ptr());
+    }
+
+    Ok(reaper.enum_projects(ProjectRef::Current, 0)
+             .ok_or_else(|| anyhow!("No current project even though we just opened one"))?
+             .project)
+}
+
+pub(crate) fn close_project_tab(project: ReaProject) {
+    if focus_project(project).is_ok() {
+        Reaper::get().main_on_command_ex(*CMD_CLOSE_CURRENT_PROJECT_TAB, 0, ProjectContext::Proj(project));
+    } else {
+        warn!("Project could not be focused for closing");
+    }
+}
+
+/// Switch project tabs until `project` is the current one
+pub(crate) fn focus_project(project: ReaProject) -> anyhow::Result<()> {
+    let reaper = Reaper::get();
+    let mut first = None;
+    loop {
+        if let Some(enumerated) = reaper.enum_projects(ProjectRef::Current, 0) {
+            match first {
+                None => first = Some(enumerated.project),
+                Some(x) => {
+                    if x == enumerated.project {
+                        return Err(anyhow!("Project not found"));
                     }
                 }
+            }
 
-                if enumerated.project != self.project {
-                    reaper.main_on_command_ex(*CMD_SWITCH_TO_NEXT_PROJECT_TAB, 0, CurrentProject);
-                } else {
-                    break;
-                }
+            if enumerated.project != project {
+                reaper.main_on_command_ex(*CMD_SWITCH_TO_NEXT_PROJECT_TAB, 0, CurrentProject);
+            } else {
+                break;
             }
         }
-
-        Ok(())
     }
+
+    Ok(())
 }
 
 impl Drop for EngineProject {
     fn drop(&mut self) {
         if self.project.as_ptr() != null_mut() {
-            let reaper = Reaper::get();
-            if let Ok(_) = self.focus() {
-                debug!(id = %self.id, "Closing project");
-                reaper.main_on_command_ex(*CMD_CLOSE_CURRENT_PROJECT_TAB, 0, self.context());
-            } else {
-                warn!("Project could not be focused for closing");
-            }
+            debug!(id = %self.id, "Closing project");
+            close_project_tab(self.project);
         }
     }
 }
@@ -373,8 +721,11 @@ impl EngineProject {
                      spec: TrackNode,
                      media: &HashMap<AppMediaObjectId, String>)
                      -> anyhow::Result<()> {
-        self.tracks.insert(id.clone(),
-                           EngineMediaTrack::new(self, self.id.app_id.clone(), id.clone(), spec, media)?);
+        let mut track = EngineMediaTrack::new(self, self.id.app_id.clone(), id.clone(), spec, media)?;
+        track.set_input(self.track_inputs.get(&id).copied());
+        track.set_recording(arms_track(self.recording, self.punch.as_ref(), &id));
+
+        self.tracks.insert(id, track);
 
         Ok(())
     }
@@ -406,6 +757,32 @@ impl EngineProject {
         Ok(())
     }
 
+    pub fn add_dynamic_instance(&mut self,
+                                dynamic_id: DynamicInstanceNodeId,
+                                spec: DynamicInstanceNode)
+                                -> anyhow::Result<()> {
+        self.dynamic_instances
+            .insert(dynamic_id.clone(), EngineDynamicInstance::new(self, dynamic_id, spec)?);
+
+        Ok(())
+    }
+
+    pub fn delete_dynamic_instance(&mut self, dynamic_id: &DynamicInstanceNodeId) {
+        if let Some(dynamic) = self.dynamic_instances.remove(dynamic_id) {
+            dynamic.delete(self.context());
+        }
+    }
+
+    pub fn set_dynamic_parameters(&mut self,
+                                  dynamic_id: &DynamicInstanceNodeId,
+                                  values: InstanceParameters)
+                                  -> anyhow::Result<()> {
+        self.dynamic_instances
+            .get_mut(dynamic_id)
+            .ok_or_else(|| anyhow!("Dynamic instance {dynamic_id} not found"))?
+            .set_parameters(values)
+    }
+
     pub fn add_mixer(&mut self, mixer_id: MixerNodeId, spec: MixerNode) -> anyhow::Result<()> {
         self.mixers
             .insert(mixer_id.clone(), AudioMixer::new(self, mixer_id.clone(), spec)?);
@@ -423,17 +800,24 @@ impl EngineProject {
         Ok(EngineStatus { plugin_ready:         PluginRegistry::has(&self.id)?,
                           is_transport_playing: self.reaper_play_state.value().is_playing
                                                 || self.reaper_play_state.value().is_recording,
-                          is_playing:           if let ProjectPlayState::Playing(play) = self.play_state.value() {
-                              Some(play.play_id.clone())
-                          } else {
-                              None
+                          is_playing:           match self.play_state.value() {
+                              ProjectPlayState::Playing(play) | ProjectPlayState::Paused(play) => {
+                                  Some(play.play_id.clone())
+                              }
+                              _ => None,
                           },
                           is_rendering:         if let ProjectPlayState::Rendering(render) = self.play_state.value() {
                               Some(render.render_id.clone())
                           } else {
                               None
                           },
-                          position:             Reaper::get().get_play_position_ex(self.context()).get(), })
+                          position:             Reaper::get().get_play_position_ex(self.context()).get(),
+                          track_count:          self.track_count(), })
+    }
+
+    /// Tracks in the REAPER project, including the ones of mixers, instances and the click
+    pub fn track_count(&self) -> usize {
+        Reaper::get().count_tracks(self.context()) as usize
     }
 
     pub fn render(&mut self, render: RequestRender) -> anyhow::Result<()> {
@@ -444,15 +828,20 @@ impl EngineProject {
         self.set_time_range_markers(render.segment);
         self.clear_all_project_markers();
         self.set_looping(false);
-        self.set_play_position((render.segment.start - 0.125).max(0.0), false);
+        let start_at = (render.segment.start - 0.125).max(0.0);
+        self.set_play_position(start_at, false);
 
         if let Some(mixer) = self.mixers.get_mut(&render.mixer_id) {
             mixer.prepare_render(&render);
         }
 
+        // renders use the record transport, the hardware inputs must not record along
+        self.set_tracks_record_mode(false);
+
         self.play_state = ProjectPlayState::Rendering(render).into();
 
         reaper.main_on_command_ex(*CMD_TRANSPORT_RECORD, 0, self.context());
+        self.sync_output.start(start_at, false);
 
         Ok(())
     }
@@ -462,15 +851,31 @@ impl EngineProject {
 
         self.stop()?;
 
-        for (mixer_id, mixer) in &mut self.mixers {
-            mixer.set_master_send(mixer_id == &play.mixer_id);
-        }
+        self.play_mixer_id = Some(play.mixer_id.clone());
+        self.set_mixer_master_sends();
 
         self.clear_all_project_markers();
-        self.set_time_range_markers(play.segment);
-        self.set_play_position(play.start_at, false);
-        self.set_looping(play.looping);
 
+        // a playlist starts with its first segment, moving on to the next segment is up to us and not REAPER repeat
+        if let Some(first) = self.playlist.segments.first().copied() {
+            self.set_play_range_markers(first);
+            let end = self.playlist
+                          .segments
+                          .iter()
+                          .map(|segment| segment.end())
+                          .fold(first.end(), f64::max);
+            self.set_play_position(self.start_lead_in(first.start, end), false);
+            self.set_looping(false);
+            self.playlist_index = Some(0);
+        } else {
+            self.set_play_range_markers(play.segment);
+            self.set_play_position(self.start_lead_in(play.start_at, play.segment.end()), false);
+            self.set_looping(play.looping);
+            self.playlist_index = None;
+        }
+
+        // the plugin may have been loaded after the monitor controller was last set
+        PluginRegistry::set_monitor_gain(&self.id, self.monitor_gain)?;
         PluginRegistry::play(&self.id, play.clone(), self.context())?;
 
         self.play_state = ProjectPlayState::PreparingToPlay(play).into();
@@ -482,13 +887,12 @@ impl EngineProject {
         let reaper = Reaper::get();
 
         if let Some(new_mixer_id) = update.mixer_id {
-            for (mixer_id, mixer) in self.mixers.iter_mut() {
-                mixer.set_master_send(mixer_id == &new_mixer_id);
-            }
+            self.play_mixer_id = Some(new_mixer_id);
+            self.set_mixer_master_sends();
         }
 
         if let Some(segment) = update.segment {
-            self.set_time_range_markers(segment);
+            self.set_play_range_markers(segment);
         }
 
         if let Some(looping) = update.looping {
@@ -497,13 +901,105 @@ impl EngineProject {
 
         if let Some(start_at) = update.start_at {
             self.set_play_position(start_at, true);
+
+            // the play keeps going from the new position, only what was buffered from the old one is dropped
+            match self.play_state.value() {
+                ProjectPlayState::Playing(play) => {
+                    PluginRegistry::seek(&self.id, play.play_id)?;
+                    self.sync_output.start(start_at, self.recording_takes);
+                }
+                // machines stay stopped while paused, they are located again when the play resumes
+                ProjectPlayState::Paused(play) => PluginRegistry::seek(&self.id, play.play_id)?,
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn pause_play(&mut self, play_id: PlayId) -> anyhow::Result<()> {
+        let play = match self.play_state.value() {
+            ProjectPlayState::Playing(play) if play.play_id == play_id => play.clone(),
+            ProjectPlayState::Paused(play) if play.play_id == play_id => return Ok(()),
+            _ => return Err(anyhow!("Play {play_id} is not playing")),
+        };
+
+        // takes are only saved when the recording stops
+        if self.recording_takes {
+            return Err(anyhow!("Can not pause while recording takes"));
         }
 
+        Reaper::get().on_pause_button_ex(self.context());
+        PluginRegistry::pause(&self.id, play_id, true)?;
+
+        self.sync_output.stop();
+        self.play_state = ProjectPlayState::Paused(play).into();
+
+        Ok(())
+    }
+
+    pub fn resume_play(&mut self, play_id: PlayId) -> anyhow::Result<()> {
+        let play = match self.play_state.value() {
+            ProjectPlayState::Paused(play) if play.play_id == play_id => play.clone(),
+            ProjectPlayState::Playing(play) if play.play_id == play_id => return Ok(()),
+            _ => return Err(anyhow!("Play {play_id} is not paused")),
+        };
+
+        PluginRegistry::pause(&self.id, play_id, false)?;
+        Reaper::get().on_play_button_ex(self.context());
+
+        let position = Reaper::get().get_play_position_ex(self.context()).get();
+        self.sync_output.start(position, false);
+        self.play_state = ProjectPlayState::Playing(play).into();
+
         Ok(())
     }
 
     pub fn stop_render(&mut self, render_id: RenderId) -> anyhow::Result<()> {
-        self.stop()
+        match self.play_state.value() {
+            ProjectPlayState::Rendering(render) if render.render_id == render_id => {
+                let render = render.clone();
+                self.cancel_render(render);
+                Ok(())
+            }
+            _ => self.stop(),
+        }
+    }
+
+    /// Stop rendering but keep what was rendered so far, moved to the shared media root for the app to decide if it
+    /// is of any use
+    fn cancel_render(&mut self, render: RequestRender) {
+        let reaper = Reaper::get();
+        let context = self.context();
+
+        let position = reaper.get_play_position_ex(context).get();
+        let rendered_length = (position - render.segment.start).clamp(0.0, render.segment.length);
+
+        reaper.main_on_command_ex(*CMD_TRANSPORT_STOP_AND_SAVE_MEDIA, 0, context);
+
+        let partial_path = match self.mixers.get_mut(&render.mixer_id).and_then(AudioMixer::clear_render) {
+            Some(path) => match self.store_media("renders", &PathBuf::from(path)) {
+                Ok(path) => Some(path),
+                Err(error) => {
+                    warn!(%error, render_id = %render.render_id, "Failed to keep partially rendered file");
+                    None
+                }
+            },
+            None => None,
+        };
+
+        self.ext_events
+            .push_back(EngineExtEvent::RenderingCancelled { task_id:         { self.id.clone() },
+                                                            render_id:       { render.render_id },
+                                                            partial_path:    { partial_path },
+                                                            rendered_length: { rendered_length }, });
+
+        self.set_tracks_record_mode(self.recording);
+        self.end_count_in();
+        self.end_click();
+        self.sync_output.stop();
+        self.playlist_index = None;
+        self.play_state = ProjectPlayState::Stopped.into();
     }
 
     pub fn stop_play(&mut self, play_id: PlayId) -> anyhow::Result<()> {
@@ -526,12 +1022,22 @@ impl EngineProject {
                     .push_back(EngineEvent::RenderingFailed { task_id:   self.id.clone(),
                                                               render_id: render.render_id,
                                                               error:     format!("Rendering stopped prematurely"), });
+
+                self.set_tracks_record_mode(self.recording);
+            }
+            ProjectPlayState::Playing(play) if self.recording_takes => {
+                let (segment, looping) = (play.segment, play.looping);
+                self.finish_recording_takes(segment, looping);
             }
             _ => {
                 reaper.on_stop_button_ex(context);
             }
         }
 
+        self.end_count_in();
+        self.end_click();
+        self.sync_output.stop();
+        self.playlist_index = None;
         self.play_state = ProjectPlayState::Stopped.into();
 
         Ok(())
@@ -561,7 +1067,9 @@ impl EngineProject {
             self.add_fixed_instance(fixed_id, fixed_spec, &instances)?;
         }
 
-        // skip dynamic instances, not supported yet
+        for (dynamic_id, dynamic_spec) in spec.dynamic.clone() {
+            self.add_dynamic_instance(dynamic_id, dynamic_spec)?;
+        }
 
         for (mixer_id, mixer_spec) in spec.mixers.clone() {
             self.add_mixer(mixer_id, mixer_spec)?;
@@ -571,6 +1079,10 @@ impl EngineProject {
 
         self.update_all_state_chunks()?;
 
+        if !self.media_rates.media.is_empty() {
+            self.report_media_lengths();
+        }
+
         Ok(())
     }
 
@@ -585,6 +1097,10 @@ impl EngineProject {
             instance.update_state_chunk(&snapshot)?;
         }
 
+        for instance in self.dynamic_instances.values() {
+            instance.update_state_chunk(&snapshot)?;
+        }
+
         for mixer in self.mixers.values() {
             mixer.update_state_chunk(&snapshot)?;
         }
@@ -611,6 +1127,10 @@ impl EngineProject {
             self.update_track_chunk(&chunk_id.pad_id, chunk_id.include_inserts)?;
         }
 
+        if !self.media_rates.media.is_empty() {
+            self.report_media_lengths();
+        }
+
         Ok(())
     }
 
@@ -633,11 +1153,18 @@ impl EngineProject {
                                                             .get(fixed_id)
                                                             .ok_or_else(|| anyhow!("Fixed {fixed_id} not found"))?
                                                             .get_return_state_chunk(&snapshot)?,
-            NodePadId::DynamicInstanceInput(_) => {
-                return Err(anyhow!("Dynamic instances not supported yet"));
+            NodePadId::DynamicInstanceInput(dynamic_id) => {
+                self.dynamic_instances
+                    .get(dynamic_id)
+                    .ok_or_else(|| anyhow!("Dynamic {dynamic_id} not found"))?
+                    .get_input_state_chunk(&snapshot)?
             }
-            NodePadId::DynamicInstanceOutput(_) => {
-                return Err(anyhow!("Dynamic instances not supported yet"));
+            NodePadId::DynamicInstanceOutput(dynamic_id) => {
+                // replacing the chunk drops the FX, the instance inserts it again
+                return self.dynamic_instances
+                           .get(dynamic_id)
+                           .ok_or_else(|| anyhow!("Dynamic {dynamic_id} not found"))?
+                           .update_output_state_chunk(&snapshot);
             }
             NodePadId::TrackOutput(track_id) => self.tracks
                                                     .get(track_id)
@@ -707,8 +1234,11 @@ impl EngineProject {
                 dirty.insert(ReaperChunkId::from(InputPadId::FixedInstanceInput(fixed_id.clone())));
                 dirty.insert(ReaperChunkId::from(OutputPadId::FixedInstanceOutput(fixed_id.clone())));
             }
-            ModifyTaskSpec::AddDynamicInstance { .. } => {
-                // not supported, silently ignore
+            ModifyTaskSpec::AddDynamicInstance { dynamic_id, spec } => {
+                self.add_dynamic_instance(dynamic_id.clone(), spec)?;
+
+                dirty.insert(ReaperChunkId::from(InputPadId::DynamicInstanceInput(dynamic_id.clone())));
+                dirty.insert(ReaperChunkId::from(OutputPadId::DynamicInstanceOutput(dynamic_id.clone())));
             }
             ModifyTaskSpec::AddMixer { mixer_id, spec: mixer } => {
                 self.add_mixer(mixer_id.clone(), mixer)?;
@@ -722,7 +1252,9 @@ impl EngineProject {
             ModifyTaskSpec::DeleteFixedInstance { fixed_id } => {
                 self.delete_fixed_instance(fixed_id)?;
             }
-            ModifyTaskSpec::DeleteDynamicInstance { .. } => {}
+            ModifyTaskSpec::DeleteDynamicInstance { dynamic_id } => {
+                self.delete_dynamic_instance(&dynamic_id);
+            }
             ModifyTaskSpec::DeleteConnection { connection_id } => {
                 if let Some(connection) = self.spec.connections.remove(&connection_id) {
                     dirty.insert(ReaperChunkId::from(connection.to.clone()));
@@ -739,7 +1271,9 @@ impl EngineProject {
                 }
             }
             ModifyTaskSpec::SetFixedInstanceParameterValues { .. } => {}
-            ModifyTaskSpec::SetDynamicInstanceParameterValues { .. } => {}
+            ModifyTaskSpec::SetDynamicInstanceParameterValues { dynamic_id, values } => {
+                self.set_dynamic_parameters(&dynamic_id, values)?;
+            }
         }
         Ok(())
     }
@@ -755,7 +1289,9 @@ impl EngineProject {
 
         self.tracks.clear();
         self.fixed_instances.clear();
+        self.dynamic_instances.clear();
         self.mixers.clear();
+        self.click_track = None;
     }
 
     pub fn on_media_updated(&mut self, available: &HashMap<AppMediaObjectId, String>) -> anyhow::Result<()> {
@@ -769,6 +1305,296 @@ impl EngineProject {
         Ok(())
     }
 
+    /// Arm tracks to record and monitor their hardware inputs, inputs of tracks added later are applied on creation
+    pub fn set_track_inputs(&mut self, inputs: HashMap<TrackNodeId, TrackHardwareInput>) -> anyhow::Result<()> {
+        let snapshot = self.template_snapshot();
+
+        for (track_id, track) in self.tracks.iter_mut() {
+            if track.set_input(inputs.get(track_id).copied()) {
+                track.update_state_chunk(&snapshot)?;
+            }
+        }
+
+        self.track_inputs = inputs;
+
+        Ok(())
+    }
+
+    /// Arm or disarm recording takes on the tracks with hardware inputs, or on the tracks of the punch region, from
+    /// the next play onwards
+    pub fn set_recording(&mut self, armed: bool, loop_record: bool, punch: Option<PunchRegion>) -> anyhow::Result<()> {
+        let snapshot = self.template_snapshot();
+
+        for (track_id, track) in self.tracks.iter_mut() {
+            if track.set_recording(arms_track(armed, punch.as_ref(), track_id)) {
+                track.update_state_chunk(&snapshot)?;
+            }
+        }
+
+        self.recording = armed;
+        self.loop_record = loop_record;
+        self.punch = punch;
+
+        Ok(())
+    }
+
+    /// Lead into the following plays with a pre-roll and a count-in, the takes recorded stay within the segment
+    pub fn set_lead_in(&mut self, lead_in: LeadIn) {
+        self.lead_in = lead_in;
+    }
+
+    /// Generate a click track for the next play, counting in instead of the metronome
+    pub fn set_click(&mut self, click: Click) {
+        self.click = Some(click);
+    }
+
+    /// Segments the following plays step through, the play going on keeps the segments it started with
+    pub fn set_playlist(&mut self, playlist: Playlist) {
+        self.playlist = playlist;
+    }
+
+    /// Replace the envelopes, rewriting the receives of connections whose envelopes changed and the FX envelopes of
+    /// the dynamic instances
+    pub fn set_envelopes(&mut self, envelopes: Envelopes) -> anyhow::Result<()> {
+        let mut dirty = HashSet::new();
+        for (connection_id, connection) in &self.spec.connections {
+            if self.envelopes.connection_envelopes(connection_id) != envelopes.connection_envelopes(connection_id) {
+                dirty.insert(ReaperChunkId::from(connection.to.clone()));
+            }
+        }
+
+        self.envelopes = envelopes;
+
+        for chunk_id in dirty {
+            self.update_track_chunk(&chunk_id.pad_id, chunk_id.include_inserts)?;
+        }
+
+        for (dynamic_id, dynamic) in self.dynamic_instances.iter_mut() {
+            dynamic.set_envelopes(self.envelopes.dynamic_instance_envelopes(dynamic_id));
+        }
+
+        Ok(())
+    }
+
+    /// Replace the fades of the media items, rewriting the tracks with media whose fades changed
+    pub fn set_media_fades(&mut self, fades: MediaFades) -> anyhow::Result<()> {
+        let dirty = self.spec
+                        .tracks
+                        .iter()
+                        .filter(|(_, track)| {
+                            track.media
+                                 .keys()
+                                 .any(|media_id| self.media_fades.media.get(media_id) != fades.media.get(media_id))
+                        })
+                        .map(|(track_id, _)| track_id.clone())
+                        .collect::<Vec<_>>();
+
+        self.media_fades = fades;
+
+        let snapshot = self.template_snapshot();
+        for track_id in dirty {
+            if let Some(track) = self.tracks.get(&track_id) {
+                track.update_state_chunk(&snapshot)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replace the playback rates of the media items, rewriting the tracks with media whose rate changed and reporting
+    /// the lengths the media takes on the timeline
+    pub fn set_media_rates(&mut self, rates: MediaRates) -> anyhow::Result<()> {
+        let dirty = self.spec
+                        .tracks
+                        .iter()
+                        .filter(|(_, track)| {
+                            track.media
+                                 .keys()
+                                 .any(|media_id| self.media_rates.media.get(media_id) != rates.media.get(media_id))
+                        })
+                        .map(|(track_id, _)| track_id.clone())
+                        .collect::<Vec<_>>();
+
+        self.media_rates = rates;
+
+        let snapshot = self.template_snapshot();
+        for track_id in dirty {
+            if let Some(track) = self.tracks.get(&track_id) {
+                track.update_state_chunk(&snapshot)?;
+            }
+        }
+
+        self.report_media_lengths();
+
+        Ok(())
+    }
+
+    fn report_media_lengths(&mut self) {
+        let snapshot = self.template_snapshot();
+        let lengths = self.tracks
+                          .values()
+                          .flat_map(|track| track.media_lengths(&snapshot))
+                          .collect();
+
+        self.ext_events
+            .push_back(EngineExtEvent::MediaLengths { task_id: self.id.clone(),
+                                                      lengths });
+    }
+
+    /// Set the gain of the streamed audio and the mixer monitored while playing
+    pub fn set_monitor(&mut self, gain: f64, source: Option<MixerNodeId>) -> anyhow::Result<()> {
+        self.monitor_gain = gain;
+        self.monitor_source = source;
+
+        // without a plugin there is nothing streaming, the gain is sent along with the next play
+        if PluginRegistry::has(&self.id)? {
+            PluginRegistry::set_monitor_gain(&self.id, gain)?;
+        }
+
+        if self.play_mixer_id.is_some() {
+            self.set_mixer_master_sends();
+        }
+
+        Ok(())
+    }
+
+    pub fn dynamic_instance_envelopes(&self, dynamic_id: &DynamicInstanceNodeId) -> HashMap<String, Envelope> {
+        self.envelopes.dynamic_instance_envelopes(dynamic_id)
+    }
+
+    /// Move on to the next segment of the playlist once the audio REAPER buffered ahead reached the end of the
+    /// current one, so the next segment follows without a gap. The last segment ends the play unless it loops
+    fn advance_playlist(&mut self, index: usize, looping: bool, cur_pos: f64) {
+        let reaper = Reaper::get();
+        let context = self.context();
+
+        let segment = match self.playlist.segments.get(index) {
+            Some(segment) => *segment,
+            None => return,
+        };
+
+        let buffered_pos = reaper.get_play_position_2_ex(context).get();
+        if buffered_pos < segment.end() {
+            return;
+        }
+
+        let next = match self.playlist.segments.get(index + 1) {
+            Some(next) => Some((index + 1, *next)),
+            None if looping => self.playlist.segments.first().map(|first| (0, *first)),
+            None => None,
+        };
+
+        match next {
+            Some((next_index, next)) => {
+                debug!(index = next_index, start = next.start, "next playlist segment");
+                self.set_play_range_markers(next);
+                self.set_play_position(next.start, true);
+                self.playlist_index = Some(next_index);
+            }
+            None if cur_pos >= segment.end() => {
+                // stopping the transport ends the play on the next run
+                debug!("reached end of playlist");
+                reaper.on_stop_button_ex(context);
+            }
+            None => {}
+        }
+    }
+
+    /// Replace the tempo and time signature markers of the project with the ones of the tempo map
+    pub fn set_tempo_map(&mut self, tempo_map: TempoMap) -> anyhow::Result<()> {
+        let reaper = Reaper::get();
+        let project = self.project.as_ptr();
+
+        unsafe {
+            for index in (0..reaper.low().CountTempoTimeSigMarkers(project)).rev() {
+                reaper.low().DeleteTempoTimeSigMarker(project, index);
+            }
+
+            for change in &tempo_map.changes {
+                if !reaper.low().SetTempoTimeSigMarker(project,
+                                                       -1,
+                                                       change.time,
+                                                       -1,
+                                                       -1,
+                                                       change.bpm,
+                                                       change.numerator as i32,
+                                                       change.denominator as i32,
+                                                       false)
+                {
+                    return Err(anyhow!("Failed to add tempo change at {}", change.time));
+                }
+            }
+        }
+
+        reaper.low().UpdateTimeline();
+
+        Ok(())
+    }
+
+    /// Position to start playing from so that the lead-in ends at `start_at`, turning on the metronome for the count-in
+    /// unless the play has a click, which may keep clicking until `end`
+    fn start_lead_in(&mut self, start_at: f64, end: f64) -> f64 {
+        let pre_roll_start = (start_at - self.lead_in.pre_roll.max(0.0)).max(0.0);
+        if let Some(click) = self.click.take() {
+            return self.start_click(click, pre_roll_start, end);
+        }
+
+        if self.lead_in.count_in_bars == 0 {
+            return pre_roll_start;
+        }
+
+        // count whole bars back at the tempo and time signature of the project
+        let count_in_start = bars_before(self.project, pre_roll_start, self.lead_in.count_in_bars);
+
+        Reaper::get().main_on_command_ex(*CMD_METRONOME_ENABLE, 0, self.context());
+        self.count_in_until = Some(pre_roll_start);
+
+        count_in_start.max(0.0)
+    }
+
+    fn end_count_in(&mut self) {
+        if self.count_in_until.take().is_some() {
+            Reaper::get().main_on_command_ex(*CMD_METRONOME_DISABLE, 0, self.context());
+        }
+    }
+
+    /// Generate the click track of a play and send it to the mixer of the click, returning where the count-in starts
+    fn start_click(&mut self, click: Click, pre_roll_start: f64, end: f64) -> f64 {
+        let count_in_start = count_in_start(self.project, &click, pre_roll_start);
+        let click_end = if click.through_play {
+            end.max(pre_roll_start)
+        } else {
+            pre_roll_start
+        };
+        let path = self.temp_dir.path().join(format!("click-{}.wav", Uuid::new_v4()));
+
+        let click_track = self.focus()
+                              .and_then(|_| ClickTrack::new(self.project, &click, count_in_start, click_end, path));
+
+        match click_track {
+            Ok(click_track) => {
+                self.click_track = Some(click_track);
+            }
+            Err(error) => {
+                warn!(%error, "Failed to generate click track, playing without it");
+                return pre_roll_start;
+            }
+        }
+
+        if let Err(error) = self.update_track_chunk(&NodePadId::MixerInput(click.mixer_id.clone()), false) {
+            warn!(%error, mixer_id = %click.mixer_id, "Failed to send click track to mixer");
+        }
+
+        count_in_start.max(0.0)
+    }
+
+    /// Remove the click track of the play, REAPER drops the receive of its mixer with it
+    fn end_click(&mut self) {
+        if let Some(click_track) = self.click_track.take() {
+            click_track.delete(self.context());
+        }
+    }
+
     pub fn on_instances_updated(&mut self,
                                 instances: &HashMap<FixedInstanceId, FixedInstanceRouting>)
                                 -> anyhow::Result<()> {
@@ -810,6 +1636,9 @@ impl EngineProject {
                 InputPadId::FixedInstanceInput(fixed_id) => self.fixed_instances
                                                                 .get(fixed_id)
                                                                 .map(|fixed_instance| fixed_instance.get_input_track()),
+                InputPadId::DynamicInstanceInput(dynamic_id) => self.dynamic_instances
+                                                                    .get(dynamic_id)
+                                                                    .map(EngineDynamicInstance::get_input_track),
                 other => return Err(anyhow!("Unsupported target {other}")),
             }.ok_or_else(|| anyhow!("Connection target {target} not found"))?;
 
@@ -838,14 +1667,48 @@ impl EngineProject {
     }
 
     fn clear_mixer_master_sends(&mut self) {
+        self.play_mixer_id = None;
+
         for (mixer_id, mixer) in &mut self.mixers {
             mixer.set_master_send(false);
         }
     }
 
+    /// Send the monitored mixer to the master, the source of the monitor controller if it is a mixer of the task and
+    /// the mixer of the play otherwise
+    fn set_mixer_master_sends(&mut self) {
+        let source = self.monitor_source
+                         .as_ref()
+                         .filter(|source| self.mixers.contains_key(*source));
+        let monitored = self.play_mixer_id
+                            .as_ref()
+                            .map(|play_mixer_id| source.unwrap_or(play_mixer_id).clone());
+
+        for (mixer_id, mixer) in &mut self.mixers {
+            mixer.set_master_send(monitored.as_ref() == Some(mixer_id));
+        }
+    }
+
     fn set_time_range_markers(&mut self, segment: TimeSegment) {
+        self.set_time_range(TimeRangeType::LoopPoints, segment);
+        self.set_time_range(TimeRangeType::TimeSelection, segment);
+    }
+
+    /// Loop over the segment of a play, recordings punch in and out of the punch region instead if there is one
+    fn set_play_range_markers(&mut self, segment: TimeSegment) {
+        let punch = self.punch
+                        .as_ref()
+                        .filter(|_| self.recording)
+                        .map(|punch| punch.segment)
+                        .unwrap_or(segment);
+
+        self.set_time_range(TimeRangeType::LoopPoints, segment);
+        self.set_time_range(TimeRangeType::TimeSelection, punch);
+    }
+
+    fn set_time_range(&mut self, range_type: TimeRangeType, segment: TimeSegment) {
         Reaper::get().get_set_loop_time_range_2_set(self.context(),
-                                                    TimeRangeType::TimeSelection,
+                                                    range_type,
                                                     PositionInSeconds::new(segment.start),
                                                     PositionInSeconds::new(segment.end()),
                                                     AutoSeekBehavior::DenyAutoSeek);
@@ -917,3 +1780,11 @@ pub fn get_track_peak_meters(track: MediaTrack, channels: usize) -> PadMetering
 
     rv
 }
+
+/// Whether a track records while `armed`, the tracks of a punch region that names none are all of them
+fn arms_track(armed: bool, punch: Option<&PunchRegion>, track_id: &TrackNodeId) -> bool {
+    match punch {
+        Some(punch) if !punch.tracks.is_empty() => armed && punch.tracks.contains(track_id),
+        _ => armed,
+    }
+}
@@ -0,0 +1,114 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+
+use anyhow::anyhow;
+use flume::Receiver;
+use tracing::*;
+
+use audiocloud_api::common::media::RenderId;
+use audiocloud_api::newtypes::AppTaskId;
+
+use crate::events::{EngineExtEvent, RenderFormat, RenderOutput};
+
+/// Converts the file of a finished render to the formats requested for it with ffmpeg, off the main thread
+///
+/// The ffmpeg binary is `FFMPEG_PATH`, or `ffmpeg` on the path. Outputs are written to `renders/<app id>` of the
+/// shared media root.
+pub struct RenderConversion {
+    rx_event: Receiver<EngineExtEvent>,
+}
+
+impl RenderConversion {
+    pub fn start(task_id: AppTaskId,
+                 render_id: RenderId,
+                 source: PathBuf,
+                 formats: Vec<RenderFormat>,
+                 shared_media_root: PathBuf)
+                 -> Self {
+        let (tx_event, rx_event) = flume::bounded(1);
+
+        thread::spawn(move || {
+            let outputs =
+                formats.into_iter()
+                       .map(|format| convert_output(&task_id, &render_id, &source, format, &shared_media_root))
+                       .collect();
+
+            let _ = tx_event.send(EngineExtEvent::RenderOutputs { task_id,
+                                                                  render_id,
+                                                                  outputs });
+        });
+
+        Self { rx_event }
+    }
+
+    /// The outputs once all of them were converted
+    pub fn poll(&self) -> Option<EngineExtEvent> {
+        self.rx_event.try_recv().ok()
+    }
+}
+
+fn convert_output(task_id: &AppTaskId,
+                  render_id: &RenderId,
+                  source: &Path,
+                  format: RenderFormat,
+                  shared_media_root: &Path)
+                  -> RenderOutput {
+    let relative = PathBuf::from("renders").join(task_id.app_id.to_string())
+                                           .join(format!("{render_id}-{}.{}", format.key(), format.extension()));
+
+    let result = match convert(source, format, &shared_media_root.join(&relative)) {
+        Ok(()) => Ok(relative.to_string_lossy().to_string()),
+        Err(error) => {
+            warn!(%error, %render_id, format = format.key(), "Render conversion failed");
+            Err(error.to_string())
+        }
+    };
+
+    RenderOutput { format, result }
+}
+
+fn convert(source: &Path, format: RenderFormat, destination: &Path) -> anyhow::Result<()> {
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let ffmpeg = env::var("FFMPEG_PATH").unwrap_or_else(|_| "ffmpeg".to_owned());
+
+    let output = Command::new(ffmpeg).args(["-nostdin", "-y", "-loglevel", "error", "-i"])
+                                     .arg(source)
+                                     .args(codec_args(format)?)
+                                     .arg(destination)
+                                     .output()?;
+
+    if !output.status.success() {
+        return Err(anyhow!("ffmpeg exited with {}: {}",
+                           output.status,
+                           String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    Ok(())
+}
+
+fn codec_args(format: RenderFormat) -> anyhow::Result<Vec<String>> {
+    let args = match format {
+        RenderFormat::Wav { bit_depth: 16 } => vec!["-c:a", "pcm_s16le"],
+        RenderFormat::Wav { bit_depth: 24 } => vec!["-c:a", "pcm_s24le"],
+        RenderFormat::Wav { bit_depth: 32 } => vec!["-c:a", "pcm_f32le"],
+        RenderFormat::Flac { bit_depth: 16 } => vec!["-c:a", "flac", "-sample_fmt", "s16"],
+        RenderFormat::Flac { bit_depth: 24 } => {
+            vec!["-c:a", "flac", "-sample_fmt", "s32", "-bits_per_raw_sample", "24"]
+        }
+        RenderFormat::Mp3 { bitrate_kbps } if (32..=320).contains(&bitrate_kbps) => {
+            return Ok(vec!["-c:a".to_owned(),
+                           "libmp3lame".to_owned(),
+                           "-b:a".to_owned(),
+                           format!("{bitrate_kbps}k")]);
+        }
+        other => return Err(anyhow!("Render format {} is not supported", other.key())),
+    };
+
+    Ok(args.into_iter().map(str::to_owned).collect())
+}
@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use cpu_time::ProcessTime;
+use cstr::cstr;
+
+use audiocloud_api::newtypes::AppTaskId;
+
+use crate::audio_engine::clock::get_audio_device_info;
+use crate::events::EngineResources;
+
+/// How often resource usage is reported, CPU usage is averaged over this long
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Measures how loaded the engine is, for the domain to keep new tasks off engines that are about to drop audio
+///
+/// REAPER does not expose the load of its audio thread, so the CPU time of the whole process stands in for it.
+#[derive(Debug)]
+pub struct ResourceMonitor {
+    last_sample: Option<(Instant, ProcessTime)>,
+    cores:       f64,
+}
+
+impl ResourceMonitor {
+    pub fn new() -> Self {
+        let cores = thread::available_parallelism().map(|cores| cores.get()).unwrap_or(1);
+
+        Self { last_sample: { None },
+               cores:       { cores as f64 }, }
+    }
+
+    /// Resource usage when it is time to report it, with the xruns the clock monitor counted and the track count of
+    /// each session
+    pub fn run(&mut self, xruns: u64, tracks: impl FnOnce() -> HashMap<AppTaskId, usize>) -> Option<EngineResources> {
+        let now = Instant::now();
+        if matches!(self.last_sample, Some((sampled_at, _)) if now - sampled_at < REPORT_INTERVAL) {
+            return None;
+        }
+
+        let cpu_time = ProcessTime::now();
+        let cpu_usage = self.last_sample.map(|(sampled_at, last_cpu_time)| {
+                                            let used = cpu_time.duration_since(last_cpu_time).as_secs_f64();
+                                            used / (now - sampled_at).as_secs_f64() / self.cores * 100.0
+                                        });
+
+        self.last_sample = Some((now, cpu_time));
+
+        let block_size = get_audio_device_info(cstr!("BSIZE")).and_then(|size| size.parse().ok());
+
+        Some(EngineResources { cpu_usage:  { cpu_usage },
+                               xruns:      { xruns },
+                               block_size: { block_size },
+                               tracks:     { tracks() }, })
+    }
+}
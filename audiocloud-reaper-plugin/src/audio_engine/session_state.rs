@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::*;
+
+use audiocloud_api::api::codec::{Codec, MsgPack};
+use audiocloud_api::cloud::domains::FixedInstanceRouting;
+use audiocloud_api::common::task::TaskSpec;
+use audiocloud_api::newtypes::{AppMediaObjectId, AppTaskId, FixedInstanceId};
+
+/// Sessions the engine has open, written to disk whenever their spec, instances or media change so that the engine
+/// reloads them when it starts after a crash
+///
+/// Kept at `SESSION_STATE_PATH`, or in the temporary folder of the system when it is not set.
+#[derive(Debug)]
+pub struct SessionState {
+    path:     PathBuf,
+    sessions: HashMap<AppTaskId, SavedSession>,
+}
+
+/// What the last `SetSpec` of a session had, with the instances and media updated since
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedSession {
+    pub spec:      TaskSpec,
+    pub instances: HashMap<FixedInstanceId, FixedInstanceRouting>,
+    pub media:     HashMap<AppMediaObjectId, String>,
+}
+
+impl SessionState {
+    /// Read the sessions saved by the previous run of the engine, none if there was nothing to read
+    pub fn load() -> Self {
+        let path =
+            env::var("SESSION_STATE_PATH").map(PathBuf::from)
+                                          .unwrap_or_else(|_| env::temp_dir().join("audiocloud-sessions.msgpack"));
+
+        let sessions = match fs::read(&path) {
+            Ok(data) => MsgPack.deserialize(&data[..]).unwrap_or_else(|error| {
+                                                          warn!(%error, ?path, "Failed to read saved sessions");
+                                                          HashMap::new()
+                                                      }),
+            Err(_) => HashMap::new(),
+        };
+
+        Self { path:     { path },
+               sessions: { sessions }, }
+    }
+
+    /// Sessions to reload, leaving none saved until they are created again so that a session crashing the engine as
+    /// it reloads is not reloaded once more
+    pub fn take_sessions(&mut self) -> HashMap<AppTaskId, SavedSession> {
+        let sessions = std::mem::take(&mut self.sessions);
+        self.save();
+
+        sessions
+    }
+
+    pub fn set_spec(&mut self,
+                    task_id: &AppTaskId,
+                    spec: &TaskSpec,
+                    instances: &HashMap<FixedInstanceId, FixedInstanceRouting>,
+                    media: &HashMap<AppMediaObjectId, String>) {
+        self.sessions.insert(task_id.clone(),
+                             SavedSession { spec:      { spec.clone() },
+                                            instances: { instances.clone() },
+                                            media:     { media.clone() }, });
+        self.save();
+    }
+
+    pub fn set_instances(&mut self, task_id: &AppTaskId, instances: &HashMap<FixedInstanceId, FixedInstanceRouting>) {
+        if let Some(session) = self.sessions.get_mut(task_id) {
+            session.instances = instances.clone();
+            self.save();
+        }
+    }
+
+    pub fn set_media(&mut self, task_id: &AppTaskId, media: &HashMap<AppMediaObjectId, String>) {
+        if let Some(session) = self.sessions.get_mut(task_id) {
+            session.media
+                   .extend(media.iter().map(|(id, path)| (id.clone(), path.clone())));
+            self.save();
+        }
+    }
+
+    pub fn remove(&mut self, task_id: &AppTaskId) {
+        if self.sessions.remove(task_id).is_some() {
+            self.save();
+        }
+    }
+
+    fn save(&self) {
+        let result = MsgPack.serialize(&self.sessions)
+                            .map_err(|error| anyhow::anyhow!("{error}"))
+                            .and_then(|data| {
+                                // written next to the state and renamed, so a crash never leaves half of it behind
+                                let temp_path = self.path.with_extension("tmp");
+                                fs::write(&temp_path, data)?;
+                                fs::rename(&temp_path, &self.path)?;
+                                Ok(())
+                            });
+
+        if let Err(error) = result {
+            warn!(%error, path = ?self.path, "Failed to save sessions");
+        }
+    }
+}
+
+/// Hash of a spec, the 64 bit FNV-1a hash of its JSON with the keys of all objects sorted, in hex. Mirrors
+/// `spec_hash` of the domain server, which compares it with the spec it set last
+pub fn spec_hash(spec: &TaskSpec) -> String {
+    let mut json = String::new();
+    write_canonical_json(&serde_json::to_value(spec).unwrap_or_default(), &mut json);
+
+    let hash = json.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+                               (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+                           });
+
+    format!("{hash:016x}")
+}
+
+fn write_canonical_json(value: &Value, json: &mut String) {
+    match value {
+        Value::Object(object) => {
+            let mut keys = object.keys().collect::<Vec<_>>();
+            keys.sort();
+
+            json.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                json.push_str(&Value::String(key.clone()).to_string());
+                json.push(':');
+                write_canonical_json(&object[key], json);
+            }
+            json.push('}');
+        }
+        Value::Array(items) => {
+            json.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                write_canonical_json(item, json);
+            }
+            json.push(']');
+        }
+        other => json.push_str(&other.to_string()),
+    }
+}
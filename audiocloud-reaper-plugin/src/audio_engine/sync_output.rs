@@ -0,0 +1,308 @@
+use std::env;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use once_cell::sync::Lazy;
+use reaper_medium::Reaper;
+use tracing::*;
+
+#[cfg(test)]
+mod tests;
+
+static SYNC_OUTPUT_CONFIG: Lazy<Option<SyncOutputConfig>> = Lazy::new(SyncOutputConfig::from_env);
+
+/// Quarter frames sent at most per engine tick, a longer gap is a jump in the timeline and is sent as a full frame
+const MAX_QUARTER_FRAMES_PER_TICK: u64 = 16;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MtcFrameRate {
+    Fps24,
+    Fps25,
+    Fps2997DropFrame,
+    Fps30,
+}
+
+impl FromStr for MtcFrameRate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "24" => Ok(Self::Fps24),
+            "25" => Ok(Self::Fps25),
+            "29.97df" => Ok(Self::Fps2997DropFrame),
+            "30" => Ok(Self::Fps30),
+            other => Err(anyhow!("Unknown frame rate {other}, expected 24, 25, 29.97df or 30")),
+        }
+    }
+}
+
+impl MtcFrameRate {
+    fn frames_per_second(self) -> f64 {
+        match self {
+            Self::Fps24 => 24.0,
+            Self::Fps25 => 25.0,
+            Self::Fps2997DropFrame => 30000.0 / 1001.0,
+            Self::Fps30 => 30.0,
+        }
+    }
+
+    /// Frames counted per timecode second
+    fn nominal(self) -> u64 {
+        match self {
+            Self::Fps24 => 24,
+            Self::Fps25 => 25,
+            Self::Fps2997DropFrame | Self::Fps30 => 30,
+        }
+    }
+
+    /// Rate code of the MTC and MMC hour byte
+    fn code(self) -> u8 {
+        match self {
+            Self::Fps24 => 0,
+            Self::Fps25 => 1,
+            Self::Fps2997DropFrame => 2,
+            Self::Fps30 => 3,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct SyncOutputConfig {
+    device:     i32,
+    mtc:        bool,
+    mmc:        bool,
+    mmc_device: u8,
+    frame_rate: MtcFrameRate,
+    /// Timecode of the start of the task timeline, in seconds
+    offset:     f64,
+}
+
+impl SyncOutputConfig {
+    /// Sync output is off unless `SYNC_OUTPUT_DEVICE` names the index of a REAPER MIDI output device
+    fn from_env() -> Option<Self> {
+        let device = env::var("SYNC_OUTPUT_DEVICE").ok()?;
+        let device = match device.parse() {
+            Ok(device) => device,
+            Err(error) => {
+                warn!(%error, "Invalid SYNC_OUTPUT_DEVICE, disabling sync output");
+                return None;
+            }
+        };
+
+        let (mtc, mmc) = match env::var("SYNC_OUTPUT").as_deref() {
+            Ok("mtc") => (true, false),
+            Ok("mmc") => (false, true),
+            Ok("both") | Err(_) => (true, true),
+            Ok(other) => {
+                warn!(other,
+                      "Invalid SYNC_OUTPUT, expected mtc, mmc or both, disabling sync output");
+                return None;
+            }
+        };
+
+        let mmc_device = env::var("SYNC_MMC_DEVICE_ID").ok()
+                                                       .and_then(|id| id.parse().ok())
+                                                       .unwrap_or(0x7F)
+                                                       .min(0x7F);
+
+        let frame_rate = env::var("SYNC_FRAME_RATE").ok()
+                                                    .map(|rate| {
+                                                        rate.parse().unwrap_or_else(|error| {
+                                                                        warn!(%error, "Invalid SYNC_FRAME_RATE");
+                                                                        MtcFrameRate::Fps25
+                                                                    })
+                                                    })
+                                                    .unwrap_or(MtcFrameRate::Fps25);
+
+        let offset = env::var("SYNC_TIMECODE_OFFSET").ok()
+                                                     .and_then(|offset| offset.parse().ok())
+                                                     .unwrap_or(0.0);
+
+        Some(Self { device,
+                    mtc,
+                    mmc,
+                    mmc_device,
+                    frame_rate,
+                    offset })
+    }
+}
+
+/// Hours, minutes, seconds and frames of a timecode
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Timecode {
+    hours:   u8,
+    minutes: u8,
+    seconds: u8,
+    frames:  u8,
+}
+
+impl Timecode {
+    fn from_frame(frame: u64, rate: MtcFrameRate) -> Self {
+        let mut frame = frame;
+
+        // drop frame timecode skips the labels of frames 0 and 1 every minute, except every tenth minute
+        if rate == MtcFrameRate::Fps2997DropFrame {
+            let (ten_minutes, remainder) = (frame / 17982, frame % 17982);
+            frame += 18 * ten_minutes;
+            if remainder >= 2 {
+                frame += 2 * ((remainder - 2) / 1798);
+            }
+        }
+
+        let nominal = rate.nominal();
+
+        Self { hours:   { (frame / (nominal * 3600) % 24) as u8 },
+               minutes: { (frame / (nominal * 60) % 60) as u8 },
+               seconds: { (frame / nominal % 60) as u8 },
+               frames:  { (frame % nominal) as u8 }, }
+    }
+
+    fn hour_byte(&self, rate: MtcFrameRate) -> u8 {
+        rate.code() << 5 | self.hours
+    }
+
+    /// Nibble `piece` of an MTC quarter frame message
+    fn quarter_frame_data(&self, piece: u8, rate: MtcFrameRate) -> u8 {
+        let nibble = match piece {
+            0 => self.frames & 0x0F,
+            1 => self.frames >> 4,
+            2 => self.seconds & 0x0F,
+            3 => self.seconds >> 4,
+            4 => self.minutes & 0x0F,
+            5 => self.minutes >> 4,
+            6 => self.hours & 0x0F,
+            _ => rate.code() << 1 | self.hours >> 4,
+        };
+
+        piece << 4 | nibble
+    }
+}
+
+/// Sends MIDI Time Code and MIDI Machine Control for the transport of a project, so that tape machines and hardware
+/// recorders in the studio chase the task
+#[derive(Debug)]
+pub struct SyncOutput {
+    config:       Option<SyncOutputConfig>,
+    next_quarter: Option<u64>,
+}
+
+impl SyncOutput {
+    pub fn new() -> Self {
+        Self { config:       { *SYNC_OUTPUT_CONFIG },
+               next_quarter: { None }, }
+    }
+
+    /// Locate the machines to `position` and start them, in record when the engine records
+    pub fn start(&mut self, position: f64, recording: bool) {
+        let config = match self.config {
+            Some(config) => config,
+            None => return,
+        };
+
+        let timecode = self.timecode_at(&config, position);
+
+        if config.mmc {
+            let hour = timecode.hour_byte(config.frame_rate);
+            self.send(&config,
+                      &[0xF0,
+                        0x7F,
+                        config.mmc_device,
+                        0x06,
+                        0x44,
+                        0x06,
+                        0x01,
+                        hour,
+                        timecode.minutes,
+                        timecode.seconds,
+                        timecode.frames,
+                        0x00,
+                        0xF7]);
+
+            self.send_mmc(&config, if recording { 0x06 } else { 0x02 });
+        }
+
+        if config.mtc {
+            self.send_full_frame(&config, timecode);
+        }
+
+        self.next_quarter = Some(self.quarter_at(&config, position));
+    }
+
+    /// Send the MTC quarter frames that are due at the current transport `position`
+    pub fn run(&mut self, position: f64) {
+        let config = match self.config {
+            Some(config) if config.mtc => config,
+            _ => return,
+        };
+
+        let next_quarter = match self.next_quarter {
+            Some(next_quarter) => next_quarter,
+            None => return,
+        };
+
+        let current = self.quarter_at(&config, position);
+
+        // a seek or a loop jumped the transport, relocate the machines with a full frame
+        if current < next_quarter || current - next_quarter > MAX_QUARTER_FRAMES_PER_TICK {
+            let timecode = self.timecode_at(&config, position);
+            self.send_full_frame(&config, timecode);
+            self.next_quarter = Some(current);
+            return;
+        }
+
+        for quarter in next_quarter..=current {
+            let piece = (quarter % 8) as u8;
+            // all eight pieces describe the frame the sequence started on
+            let timecode = Timecode::from_frame((quarter - piece as u64) / 4, config.frame_rate);
+
+            self.send(&config, &[0xF1, timecode.quarter_frame_data(piece, config.frame_rate)]);
+        }
+
+        self.next_quarter = Some(current + 1);
+    }
+
+    pub fn stop(&mut self) {
+        let config = match self.config {
+            Some(config) => config,
+            None => return,
+        };
+
+        if self.next_quarter.take().is_some() && config.mmc {
+            self.send_mmc(&config, 0x01);
+        }
+    }
+
+    fn timecode_at(&self, config: &SyncOutputConfig, position: f64) -> Timecode {
+        let frame = ((position + config.offset).max(0.0) * config.frame_rate.frames_per_second()).floor() as u64;
+        Timecode::from_frame(frame, config.frame_rate)
+    }
+
+    fn quarter_at(&self, config: &SyncOutputConfig, position: f64) -> u64 {
+        ((position + config.offset).max(0.0) * config.frame_rate.frames_per_second() * 4.0).floor() as u64
+    }
+
+    fn send_mmc(&self, config: &SyncOutputConfig, command: u8) {
+        self.send(config, &[0xF0, 0x7F, config.mmc_device, 0x06, command, 0xF7]);
+    }
+
+    fn send_full_frame(&self, config: &SyncOutputConfig, timecode: Timecode) {
+        self.send(config,
+                  &[0xF0,
+                    0x7F,
+                    0x7F,
+                    0x01,
+                    0x01,
+                    timecode.hour_byte(config.frame_rate),
+                    timecode.minutes,
+                    timecode.seconds,
+                    timecode.frames,
+                    0xF7]);
+    }
+
+    fn send(&self, config: &SyncOutputConfig, message: &[u8]) {
+        unsafe {
+            Reaper::get().low()
+                         .SendMIDIMessageToHardware(config.device, message.as_ptr() as *const _, message.len() as i32);
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use crate::audio_engine::sync_output::{MtcFrameRate, Timecode};
+
+fn timecode(hours: u8, minutes: u8, seconds: u8, frames: u8) -> Timecode {
+    Timecode { hours,
+               minutes,
+               seconds,
+               frames }
+}
+
+#[test]
+fn test_drop_frame_skips_labels_at_minutes() {
+    let at = |frame| Timecode::from_frame(frame, MtcFrameRate::Fps2997DropFrame);
+
+    assert_eq!(at(1799), timecode(0, 0, 59, 29));
+    assert_eq!(at(1800), timecode(0, 1, 0, 2), "00:01:00;00 and ;01 are dropped");
+    assert_eq!(at(3598), timecode(0, 2, 0, 2));
+    assert_eq!(at(17981), timecode(0, 9, 59, 29));
+    assert_eq!(at(17982),
+               timecode(0, 10, 0, 0),
+               "no labels are dropped at every tenth minute");
+    assert_eq!(at(17982 + 1800), timecode(0, 11, 0, 2));
+    assert_eq!(at(6 * 17982), timecode(1, 0, 0, 0));
+}
+
+#[test]
+fn test_non_drop_frame_counts_whole_seconds() {
+    assert_eq!(Timecode::from_frame(1800, MtcFrameRate::Fps30), timecode(0, 1, 0, 0));
+    assert_eq!(Timecode::from_frame(25 * 3661 + 7, MtcFrameRate::Fps25),
+               timecode(1, 1, 1, 7));
+    assert_eq!(Timecode::from_frame(24 * 3600 * 24, MtcFrameRate::Fps24),
+               timecode(0, 0, 0, 0),
+               "wraps at a day");
+}
+
+#[test]
+fn test_rate_code_in_hour_byte() {
+    let tc = timecode(1, 2, 3, 4);
+
+    assert_eq!(tc.hour_byte(MtcFrameRate::Fps24), 0x01);
+    assert_eq!(tc.hour_byte(MtcFrameRate::Fps25), 0x21);
+    assert_eq!(tc.hour_byte(MtcFrameRate::Fps2997DropFrame), 0x41);
+    assert_eq!(tc.hour_byte(MtcFrameRate::Fps30), 0x61);
+    assert_eq!(timecode(23, 0, 0, 0).hour_byte(MtcFrameRate::Fps30), 0x77);
+}
+
+#[test]
+fn test_quarter_frames_pack_nibbles_and_rate_code() {
+    let tc = timecode(0x17, 0x3B, 0x2A, 0x1D);
+    let pieces = (0..8).map(|piece| tc.quarter_frame_data(piece, MtcFrameRate::Fps2997DropFrame))
+                       .collect::<Vec<_>>();
+
+    assert_eq!(pieces, vec![0x0D, 0x11, 0x2A, 0x32, 0x4B, 0x53, 0x67, 0x75]);
+
+    // piece 7 carries the rate code above the high bit of the hours
+    assert_eq!(timecode(1, 0, 0, 0).quarter_frame_data(7, MtcFrameRate::Fps24), 0x70);
+    assert_eq!(timecode(1, 0, 0, 0).quarter_frame_data(7, MtcFrameRate::Fps25), 0x72);
+    assert_eq!(timecode(1, 0, 0, 0).quarter_frame_data(7, MtcFrameRate::Fps2997DropFrame),
+               0x74);
+    assert_eq!(timecode(1, 0, 0, 0).quarter_frame_data(7, MtcFrameRate::Fps30), 0x76);
+}
@@ -0,0 +1,190 @@
+use std::f64::consts::PI;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use askama::Template;
+use reaper_medium::{MediaTrack, ProjectContext, ReaProject, Reaper};
+use tempdir::TempDir;
+use tracing::*;
+
+use crate::audio_engine::project::{close_project_tab, open_project_tab};
+use crate::events::{TestTone, TestToneInput, TestToneResult};
+
+pub(crate) const TONE_SAMPLE_RATE: u32 = 48_000;
+
+/// Inputs are measured a little longer than the tone plays, to catch the latency of converters and outboard
+const MEASURE_TAIL: Duration = Duration::from_millis(250);
+
+/// Peaks below this are reported as silence
+const SILENCE_DB: f64 = -120.0;
+
+#[derive(Template)]
+#[template(path = "audio_engine/test_tone.txt")]
+struct TestToneProjectTemplate<'a> {
+    test_id:   &'a str,
+    tone:      &'a TestTone,
+    tone_path: String,
+}
+
+impl<'a> TestToneProjectTemplate<'a> {
+    fn reaper_output_channel(&self) -> usize {
+        self.tone.output_channel | 1024
+    }
+
+    fn length(&self) -> f64 {
+        self.tone.duration_ms as f64 / 1000.0
+    }
+}
+
+/// A test tone playing in its own project tab, with a monitored track per measured hardware input
+///
+/// The master of the project is muted and no track sends to it, so the tone only reaches the selected output.
+#[derive(Debug)]
+pub struct TestToneRun {
+    test_id:   String,
+    tone:      TestTone,
+    project:   ReaProject,
+    inputs:    Vec<MediaTrack>,
+    peaks:     Vec<f64>,
+    ends_at:   Instant,
+    _temp_dir: TempDir,
+}
+
+impl TestToneRun {
+    pub fn start(test_id: String, tone: TestTone) -> anyhow::Result<Self> {
+        let reaper = Reaper::get();
+
+        let temp_dir = TempDir::new("audiocloud-test-tone")?;
+        let tone_path = temp_dir.path().join("tone.wav");
+        let project_path = temp_dir.path().join("test-tone.rpp");
+
+        write_sine_wav(&tone_path, &tone)?;
+
+        fs::write(&project_path,
+                  TestToneProjectTemplate { test_id:   &test_id,
+                                            tone:      &tone,
+                                            tone_path: tone_path.to_string_lossy().to_string(), }.render()?)?;
+
+        let project = open_project_tab(&project_path)?;
+        let context = ProjectContext::Proj(project);
+
+        // the first track plays the tone, the rest follow in the order of the input channels
+        let inputs = (0..tone.input_channels.len()).map(|index| {
+                                                       reaper.get_track(context, (index + 1) as u32)
+                                                             .ok_or_else(|| anyhow!("Input track {index} not found"))
+                                                   })
+                                                   .collect::<anyhow::Result<Vec<_>>>();
+
+        let inputs = match inputs {
+            Ok(inputs) => inputs,
+            Err(error) => {
+                close_project_tab(project);
+                return Err(error);
+            }
+        };
+
+        reaper.on_play_button_ex(context);
+
+        let ends_at = Instant::now() + Duration::from_millis(tone.duration_ms) + MEASURE_TAIL;
+
+        debug!(%test_id, ?tone, "Started test tone");
+
+        Ok(Self { peaks: vec![0.0; inputs.len()],
+                  test_id,
+                  tone,
+                  project,
+                  inputs,
+                  ends_at,
+                  _temp_dir: temp_dir })
+    }
+
+    pub fn test_id(&self) -> &str {
+        &self.test_id
+    }
+
+    /// Measure the inputs, returning the result once the tone ended
+    pub fn run(&mut self) -> Option<TestToneResult> {
+        let reaper = Reaper::get();
+
+        for (track, peak) in self.inputs.iter().zip(self.peaks.iter_mut()) {
+            let current = unsafe { reaper.track_get_peak_info(*track, 0) }.get();
+            if current > *peak {
+                *peak = current;
+            }
+        }
+
+        if Instant::now() < self.ends_at {
+            return None;
+        }
+
+        let inputs = self.tone
+                         .input_channels
+                         .iter()
+                         .zip(self.peaks.iter())
+                         .map(|(channel, peak)| {
+                             let peak_db = 20.0 * peak.log10();
+                             let peak_db = if peak_db > SILENCE_DB { Some(peak_db) } else { None };
+
+                             TestToneInput { channel: { *channel },
+                                             peak_db: { peak_db }, }
+                         })
+                         .collect();
+
+        Some(TestToneResult { output_channel: { self.tone.output_channel },
+                              inputs:         { inputs }, })
+    }
+}
+
+impl Drop for TestToneRun {
+    fn drop(&mut self) {
+        Reaper::get().on_stop_button_ex(ProjectContext::Proj(self.project));
+        close_project_tab(self.project);
+    }
+}
+
+/// Mono 16 bit PCM sine, faded in and out over 10 ms so the tone does not click through the monitors
+fn write_sine_wav(path: &Path, tone: &TestTone) -> anyhow::Result<()> {
+    let num_samples = (TONE_SAMPLE_RATE as u64 * tone.duration_ms / 1000) as usize;
+    let fade_samples = (TONE_SAMPLE_RATE / 100) as usize;
+    let amplitude = 10f64.powf(tone.level_db / 20.0);
+
+    write_mono_wav(path, num_samples, |i| {
+        let fade = (i.min(num_samples.saturating_sub(1) - i) as f64 / fade_samples as f64).min(1.0);
+
+        (2.0 * PI * tone.frequency * i as f64 / TONE_SAMPLE_RATE as f64).sin() * amplitude * fade
+    })
+}
+
+/// Mono 16 bit PCM at `TONE_SAMPLE_RATE`, `sample` is called for each sample in order
+pub(crate) fn write_mono_wav(path: &Path,
+                             num_samples: usize,
+                             mut sample: impl FnMut(usize) -> f64)
+                             -> anyhow::Result<()> {
+    let data_len = (num_samples * 2) as u32;
+
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&TONE_SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(TONE_SAMPLE_RATE * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+
+    for i in 0..num_samples {
+        let value = sample(i).clamp(-1.0, 1.0);
+
+        wav.extend_from_slice(&((value * i16::MAX as f64) as i16).to_le_bytes());
+    }
+
+    fs::write(path, wav)?;
+
+    Ok(())
+}
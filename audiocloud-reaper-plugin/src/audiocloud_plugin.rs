@@ -19,6 +19,7 @@ use audiocloud_api::common::media::PlayId;
 use audiocloud_api::newtypes::AppTaskId;
 
 use crate::audio_engine::{PluginRegistry, ReaperEngine, ReaperEngineCommand, StreamingPluginCommand};
+use crate::events::EngineExtEvent;
 use crate::streaming::EncoderChain;
 
 pub struct AudioCloudPlugin {
@@ -33,6 +34,8 @@ struct AudioCloudPluginActivation {
     tx_engine: flume::Sender<ReaperEngineCommand>,
     chain:     Option<EncoderChain>,
     context:   ProjectContext,
+    /// Gain of the monitor controller of the task, kept for the chains of later plays
+    gain:      f64,
 }
 
 static SESSION_WRAPPER: OnceCell<SessionWrapper> = OnceCell::new();
@@ -93,7 +96,8 @@ impl Plugin for AudioCloudPlugin {
                                                                             rx_plugin,
                                                                             tx_engine,
                                                                             chain: None,
-                                                                            context: ProjectContext::CurrentProject }
+                                                                            context: ProjectContext::CurrentProject,
+                                                                            gain: 1.0 }
                                            });
         }
     }
@@ -142,9 +146,23 @@ impl AudioCloudPluginActivation {
             self.dispatch_cmd(cmd, native_channels, native_sample_rate)?;
         }
 
-        if let Some(chain) = self.chain.as_mut() {
+        if let Some(chain) = self.chain.as_mut().filter(|chain| !chain.paused) {
             chain.process(buf, Reaper::get().get_play_position_2_ex(self.context).get())?;
+
+            // loudness goes out at the pace of the compressed audio, not with every block REAPER processes
+            let encoded = !chain.compressed.is_empty();
             drain(chain.play.play_id, &mut chain.compressed)?;
+
+            if encoded {
+                let loudness = chain.take_loudness();
+                self.tx_engine
+                    .send(ReaperEngineCommand::Loudness(self.id.clone(), chain.play.play_id, loudness))?;
+            }
+
+            if let Some(spectrum) = chain.take_spectrum() {
+                self.tx_engine
+                    .send(ReaperEngineCommand::Spectrum(self.id.clone(), chain.play.play_id, spectrum))?;
+            }
         }
 
         Ok(())
@@ -158,7 +176,13 @@ impl AudioCloudPluginActivation {
         let drain = self.make_drain();
 
         match cmd {
-            StreamingPluginCommand::Play { context, play } => {
+            StreamingPluginCommand::Play { context,
+                                           play,
+                                           loudness_target,
+                                           latency_profile,
+                                           stream_codec,
+                                           spectrum,
+                                           watermark, } => {
                 if let Some(chain) = self.chain.take() {
                     let play_id = chain.play.play_id;
                     let mut compressed = chain.finish()?;
@@ -166,11 +190,43 @@ impl AudioCloudPluginActivation {
                 }
 
                 let play_id = play.play_id.clone();
-                self.chain = Some(EncoderChain::new(play, native_channels, native_sample_rate)?);
+                self.chain = Some(EncoderChain::new(play,
+                                                    native_channels,
+                                                    native_sample_rate,
+                                                    loudness_target,
+                                                    latency_profile,
+                                                    stream_codec,
+                                                    spectrum,
+                                                    watermark,
+                                                    self.gain)?);
                 self.context = context;
                 let _ = self.tx_engine
                             .send(ReaperEngineCommand::PlayReady(self.id.clone(), play_id));
             }
+            StreamingPluginCommand::SetBitrate { play_id, bitrate } => {
+                if let Some(chain) = self.chain.as_mut().filter(|chain| chain.play.play_id == play_id) {
+                    // a stream that can not change its bitrate keeps streaming as it was
+                    if let Err(error) = chain.set_bitrate(bitrate) {
+                        warn!(%error, %play_id, bitrate, "Failed to change stream bitrate");
+                    }
+                }
+            }
+            StreamingPluginCommand::Seek { play_id } => {
+                if let Some(chain) = self.chain.as_mut().filter(|chain| chain.play.play_id == play_id) {
+                    chain.seek();
+                }
+            }
+            StreamingPluginCommand::Pause { play_id, paused } => {
+                if let Some(chain) = self.chain.as_mut().filter(|chain| chain.play.play_id == play_id) {
+                    chain.paused = paused;
+                }
+            }
+            StreamingPluginCommand::SetMonitorGain { gain } => {
+                self.gain = gain;
+                if let Some(chain) = self.chain.as_mut() {
+                    chain.set_gain(gain);
+                }
+            }
             StreamingPluginCommand::Flush { play_id } => {
                 let is_same_play_id = self.chain
                                           .as_ref()
@@ -235,6 +291,7 @@ fn init_audio_engine(host: &HostCallback) -> ReaperSession {
 
     let (tx_cmd, rx_cmd) = flume::unbounded();
     let (tx_evt, rx_evt) = flume::unbounded::<EngineEvent>();
+    let (tx_ext_evt, rx_ext_evt) = flume::unbounded::<EngineExtEvent>();
 
     let nats_url = env::var("NATS_URL").expect("NATS_URL env var must be set");
     let subscribe_topic = env::var("NATS_CMD_TOPIC").expect("NATS_CMD_TOPIC env var must be set");
@@ -254,6 +311,11 @@ fn init_audio_engine(host: &HostCallback) -> ReaperSession {
     let subscription = connection.subscribe(&subscribe_topic)
                                  .expect("NATS subscription success");
 
+    let ext_subscribe_topic = format!("{subscribe_topic}.ext");
+    debug!(topic = %ext_subscribe_topic, "Subscribing to extension commands");
+    let ext_subscription = connection.subscribe(&ext_subscribe_topic)
+                                     .expect("NATS subscription success");
+
     thread::spawn({
         let tx_cmd = tx_cmd.clone();
         move || {
@@ -262,16 +324,36 @@ fn init_audio_engine(host: &HostCallback) -> ReaperSession {
                 if let Ok(cmd) = MsgPack.deserialize(&msg.data[..]) {
                     let (tx, rx) = flume::unbounded::<anyhow::Result<()>>();
                     if let Ok(_) = tx_cmd.send(ReaperEngineCommand::Request((cmd, tx))) {
-                        thread::spawn(move || {
-                            let result = match rx.recv_timeout(Duration::from_millis(500)) {
-                                Err(_) => Err(format!("Request timed out")),
-                                Ok(Err(err)) => Err(err.to_string()),
-                                Ok(Ok(result)) => Ok(result),
-                            };
-
-                            let result = MsgPack.serialize(&result).expect("Response serialization success");
-                            msg.respond(result).expect("NATS response send");
-                        });
+                        thread::spawn(move || respond_to_request(msg, rx));
+                    }
+                }
+            }
+        }
+    });
+
+    thread::spawn({
+        let tx_cmd = tx_cmd.clone();
+        move || {
+            while let Some(msg) = ext_subscription.next() {
+                if let Ok(cmd) = MsgPack.deserialize(&msg.data[..]) {
+                    let (tx, rx) = flume::unbounded::<anyhow::Result<()>>();
+                    if let Ok(_) = tx_cmd.send(ReaperEngineCommand::Ext((cmd, tx))) {
+                        thread::spawn(move || respond_to_request(msg, rx));
+                    }
+                }
+            }
+        }
+    });
+
+    let ext_publish_topic = format!("{subscribe_topic}.ext.events");
+
+    thread::spawn({
+        let connection = connection.clone();
+        move || {
+            while let Ok(evt) = rx_ext_evt.recv() {
+                if let Ok(encoded) = MsgPack.serialize(&evt) {
+                    if let Err(err) = connection.publish(&ext_publish_topic, encoded) {
+                        warn!(%err, "failed to publish extension event");
                     }
                 }
             }
@@ -291,7 +373,11 @@ fn init_audio_engine(host: &HostCallback) -> ReaperSession {
     debug!("Init plugin registry");
     PluginRegistry::init(tx_cmd.clone());
 
-    session.plugin_register_add_csurf_inst(Box::new(ReaperEngine::new(shared_media_root, tx_cmd, rx_cmd, tx_evt)))
+    session.plugin_register_add_csurf_inst(Box::new(ReaperEngine::new(shared_media_root,
+                                                                      tx_cmd,
+                                                                      rx_cmd,
+                                                                      tx_evt,
+                                                                      tx_ext_evt)))
            .expect("REAPER audio engine control surface register success");
 
     info!("init complete");
@@ -299,6 +385,17 @@ fn init_audio_engine(host: &HostCallback) -> ReaperSession {
     session
 }
 
+fn respond_to_request(msg: nats::Message, rx: flume::Receiver<anyhow::Result<()>>) {
+    let result = match rx.recv_timeout(Duration::from_millis(500)) {
+        Err(_) => Err(format!("Request timed out")),
+        Ok(Err(err)) => Err(err.to_string()),
+        Ok(Ok(result)) => Ok(result),
+    };
+
+    let result = MsgPack.serialize(&result).expect("Response serialization success");
+    msg.respond(result).expect("NATS response send");
+}
+
 struct SessionWrapper(ReaperSession);
 
 impl DerefMut for SessionWrapper {
@@ -1,14 +1,22 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
+use anyhow::anyhow;
 use flume::Sender;
+use serde::{Deserialize, Serialize};
 
 use audiocloud_api::audio_engine::command::EngineCommand;
 use audiocloud_api::audio_engine::event::EngineEvent;
 use audiocloud_api::audio_engine::CompressedAudio;
-use audiocloud_api::common::task::NodePadId;
-use audiocloud_api::PadMetering;
+use audiocloud_api::common::task::{NodePadId, TimeSegment};
+use audiocloud_api::newtypes::{
+    AppTaskId, DynamicInstanceNodeId, MixerNodeId, NodeConnectionId, TrackMediaId, TrackNodeId,
+};
+use audiocloud_api::{PadMetering, PlayId, RenderId};
 
+use crate::loudness::LoudnessReading;
+use crate::spectrum::SpectrumReport;
 use crate::streaming::StreamingConfig;
+use crate::watermark::PlayWatermark;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum ControlSurfaceEvent {
@@ -23,3 +31,648 @@ pub enum AudioStreamingEvent {
 }
 
 pub type EngineCommandWithResultSender = (EngineCommand, Sender<anyhow::Result<()>>);
+
+/// Commands outside the `audiocloud_api` engine protocol, received MsgPack encoded on the `.ext` sibling of the
+/// command topic. Mirrors `EngineExtCommand` of the domain server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum EngineExtCommand {
+    SetTrackInputs {
+        task_id: AppTaskId,
+        inputs:  HashMap<TrackNodeId, TrackHardwareInput>,
+    },
+    SetRecording {
+        task_id:     AppTaskId,
+        armed:       bool,
+        #[serde(default)]
+        loop_record: bool,
+        #[serde(default)]
+        punch:       Option<PunchRegion>,
+    },
+    SetClick {
+        task_id: AppTaskId,
+        click:   Click,
+    },
+    SetLeadIn {
+        task_id: AppTaskId,
+        lead_in: LeadIn,
+    },
+    SetTempoMap {
+        task_id:   AppTaskId,
+        tempo_map: TempoMap,
+    },
+    SetLatencyProfile {
+        task_id: AppTaskId,
+        profile: LatencyProfile,
+    },
+    SetLoudnessTarget {
+        task_id:     AppTaskId,
+        target_lufs: Option<f64>,
+    },
+    SetStreamCodec {
+        task_id: AppTaskId,
+        codec:   StreamCodec,
+    },
+    SetStreamQuality {
+        task_id: AppTaskId,
+        play_id: PlayId,
+        bitrate: u32,
+    },
+    SetPlaylist {
+        task_id:  AppTaskId,
+        playlist: Playlist,
+    },
+    SetEnvelopes {
+        task_id:   AppTaskId,
+        envelopes: Envelopes,
+    },
+    SetMediaFades {
+        task_id: AppTaskId,
+        fades:   MediaFades,
+    },
+    SetMediaRates {
+        task_id: AppTaskId,
+        rates:   MediaRates,
+    },
+    SetMonitor {
+        task_id: AppTaskId,
+        gain:    f64,
+        source:  Option<MixerNodeId>,
+    },
+    SetSpectrum {
+        task_id:  AppTaskId,
+        spectrum: Option<SpectrumSettings>,
+    },
+    /// Watermark of the stream of the next play, sent right before it. None streams the play without one
+    SetWatermark {
+        task_id:   AppTaskId,
+        play_id:   PlayId,
+        watermark: Option<PlayWatermark>,
+    },
+    PausePlay {
+        task_id: AppTaskId,
+        play_id: PlayId,
+    },
+    ResumePlay {
+        task_id: AppTaskId,
+        play_id: PlayId,
+    },
+    StartTestTone {
+        test_id: String,
+        tone:    TestTone,
+    },
+    /// Formats to convert the file of a render to once it finished, replacing those of earlier renders
+    SetRenderFormats {
+        task_id:   AppTaskId,
+        render_id: RenderId,
+        formats:   Vec<RenderFormat>,
+    },
+    /// Write a diagnostic bundle of the session, after the engine reported `error` for it
+    CaptureDiagnostics {
+        task_id: AppTaskId,
+        error:   String,
+    },
+}
+
+impl EngineExtCommand {
+    /// The session the command is for, test tones are for the engine as a whole
+    pub fn task_id(&self) -> Option<&AppTaskId> {
+        match self {
+            Self::SetTrackInputs { task_id, .. }
+            | Self::SetRecording { task_id, .. }
+            | Self::SetClick { task_id, .. }
+            | Self::SetLeadIn { task_id, .. }
+            | Self::SetTempoMap { task_id, .. }
+            | Self::SetLatencyProfile { task_id, .. }
+            | Self::SetLoudnessTarget { task_id, .. }
+            | Self::SetStreamCodec { task_id, .. }
+            | Self::SetStreamQuality { task_id, .. }
+            | Self::SetPlaylist { task_id, .. }
+            | Self::SetEnvelopes { task_id, .. }
+            | Self::SetMediaFades { task_id, .. }
+            | Self::SetMediaRates { task_id, .. }
+            | Self::SetMonitor { task_id, .. }
+            | Self::SetSpectrum { task_id, .. }
+            | Self::SetWatermark { task_id, .. }
+            | Self::PausePlay { task_id, .. }
+            | Self::ResumePlay { task_id, .. }
+            | Self::SetRenderFormats { task_id, .. }
+            | Self::CaptureDiagnostics { task_id, .. } => Some(task_id),
+            Self::StartTestTone { .. } => None,
+        }
+    }
+
+    /// Variant name and ids of the command, without its payload, for recording in diagnostic bundles
+    pub fn summary(&self) -> String {
+        match self {
+            Self::SetTrackInputs { task_id, .. } => format!("SetTrackInputs({task_id})"),
+            Self::SetRecording { task_id, .. } => format!("SetRecording({task_id})"),
+            Self::SetClick { task_id, .. } => format!("SetClick({task_id})"),
+            Self::SetLeadIn { task_id, .. } => format!("SetLeadIn({task_id})"),
+            Self::SetTempoMap { task_id, .. } => format!("SetTempoMap({task_id})"),
+            Self::SetLatencyProfile { task_id, .. } => format!("SetLatencyProfile({task_id})"),
+            Self::SetLoudnessTarget { task_id, .. } => format!("SetLoudnessTarget({task_id})"),
+            Self::SetStreamCodec { task_id, .. } => format!("SetStreamCodec({task_id})"),
+            Self::SetStreamQuality { task_id, play_id, .. } => format!("SetStreamQuality({task_id}, {play_id})"),
+            Self::SetPlaylist { task_id, .. } => format!("SetPlaylist({task_id})"),
+            Self::SetEnvelopes { task_id, .. } => format!("SetEnvelopes({task_id})"),
+            Self::SetMediaFades { task_id, .. } => format!("SetMediaFades({task_id})"),
+            Self::SetMediaRates { task_id, .. } => format!("SetMediaRates({task_id})"),
+            Self::SetMonitor { task_id, .. } => format!("SetMonitor({task_id})"),
+            Self::SetSpectrum { task_id, .. } => format!("SetSpectrum({task_id})"),
+            Self::SetWatermark { task_id, play_id, .. } => format!("SetWatermark({task_id}, {play_id})"),
+            Self::PausePlay { task_id, play_id } => format!("PausePlay({task_id}, {play_id})"),
+            Self::ResumePlay { task_id, play_id } => format!("ResumePlay({task_id}, {play_id})"),
+            Self::StartTestTone { test_id, .. } => format!("StartTestTone({test_id})"),
+            Self::SetRenderFormats { task_id, render_id, .. } => format!("SetRenderFormats({task_id}, {render_id})"),
+            Self::CaptureDiagnostics { task_id, .. } => format!("CaptureDiagnostics({task_id})"),
+        }
+    }
+}
+
+/// Events outside the `audiocloud_api` engine protocol, published MsgPack encoded on the `.ext.events` sibling of the
+/// command topic. Mirrors `EngineExtEvent` of the domain server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum EngineExtEvent {
+    TakeRecorded {
+        task_id:       AppTaskId,
+        track_id:      TrackNodeId,
+        segment:       TimeSegment,
+        path:          String,
+        pass:          Option<usize>,
+        media_segment: Option<TimeSegment>,
+    },
+    ClockStatus {
+        status: ClockStatus,
+    },
+    /// Load of the engine and its sessions, sent every few seconds
+    Resources {
+        resources: EngineResources,
+    },
+    TestToneMeasured {
+        test_id: String,
+        result:  TestToneResult,
+    },
+    /// EBU R128 loudness of the pads the engine meters, sent with the audio of a play
+    Loudness {
+        task_id:  AppTaskId,
+        play_id:  PlayId,
+        loudness: HashMap<NodePadId, LoudnessReading>,
+    },
+    /// Spectrum of the pads the engine analyzes, sent at the rate of the spectrum settings of the session
+    Spectrum {
+        task_id:  AppTaskId,
+        play_id:  PlayId,
+        spectrum: HashMap<NodePadId, SpectrumReport>,
+    },
+    /// A render was cancelled after `rendered_length` seconds, what it rendered so far is kept at `partial_path`,
+    /// relative to the shared media root
+    RenderingCancelled {
+        task_id:         AppTaskId,
+        render_id:       RenderId,
+        partial_path:    Option<String>,
+        rendered_length: f64,
+    },
+    /// The file of a finished render was converted to the formats set for it, each output is a path relative to the
+    /// shared media root or why the conversion failed
+    RenderOutputs {
+        task_id:   AppTaskId,
+        render_id: RenderId,
+        outputs:   Vec<RenderOutput>,
+    },
+    /// A diagnostic bundle was written, `path` is relative to the shared media root
+    DiagnosticsCaptured {
+        task_id: AppTaskId,
+        error:   String,
+        path:    String,
+    },
+    /// Lengths of the media items of the session on the timeline, by track media, once their rates or the spec changed
+    MediaLengths {
+        task_id: AppTaskId,
+        lengths: HashMap<TrackMediaId, f64>,
+    },
+    /// The engine started and reloaded the sessions the previous run had open, with the hashes of their specs
+    Started {
+        sessions: HashMap<AppTaskId, String>,
+    },
+}
+
+/// Clock source and lock status of the audio interface REAPER runs on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClockStatus {
+    pub source:               Option<String>,
+    pub device:               Option<String>,
+    pub sample_rate:          Option<f64>,
+    pub expected_sample_rate: Option<f64>,
+    pub running:              bool,
+    pub underruns:            u64,
+    pub locked:               bool,
+}
+
+/// CPU usage of the REAPER process in percent of all cores, audio device xruns since the engine started, block size of
+/// the audio device and tracks in the project of each session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EngineResources {
+    pub cpu_usage:  Option<f64>,
+    pub xruns:      u64,
+    pub block_size: Option<u32>,
+    pub tracks:     HashMap<AppTaskId, usize>,
+}
+
+/// A sine tone on hardware output `output_channel`, measured on `input_channels`. Channels are zero based
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TestTone {
+    pub output_channel: usize,
+    pub input_channels: Vec<usize>,
+    pub frequency:      f64,
+    pub level_db:       f64,
+    pub duration_ms:    u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TestToneResult {
+    pub output_channel: usize,
+    pub inputs:         Vec<TestToneInput>,
+}
+
+/// Highest peak of a hardware input in dBFS, `None` when it stayed silent
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TestToneInput {
+    pub channel: usize,
+    pub peak_db: Option<f64>,
+}
+
+/// A hardware input a track records and monitors, `channel` is the first of as many channels as the track has
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrackHardwareInput {
+    pub channel: usize,
+}
+
+/// Region of the timeline recordings punch in and out of, on `tracks` or on all tracks with inputs if empty
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PunchRegion {
+    pub segment: TimeSegment,
+    #[serde(default)]
+    pub tracks:  HashSet<TrackNodeId>,
+}
+
+/// Lead-in of plays, `pre_roll` seconds of the project preceded by `count_in_bars` bars of metronome clicks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct LeadIn {
+    #[serde(default)]
+    pub pre_roll:      f64,
+    #[serde(default)]
+    pub count_in_bars: u32,
+}
+
+/// Click track of the next play, counting in `count_in_bars` bars before the pre-roll and sent to a mixer only
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Click {
+    pub mixer_id:      MixerNodeId,
+    #[serde(default)]
+    pub count_in_bars: u32,
+    #[serde(default)]
+    pub through_play:  bool,
+    pub level_db:      f64,
+    /// Fixed tempo of the click, the tempo map of the project if none
+    #[serde(default)]
+    pub tempo:         Option<ClickTempo>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ClickTempo {
+    pub bpm:         f64,
+    pub numerator:   u32,
+    pub denominator: u32,
+}
+
+impl ClickTempo {
+    /// Seconds per beat, `bpm` counting quarter notes
+    pub fn beat_length(&self) -> f64 {
+        60.0 / self.bpm * 4.0 / self.denominator as f64
+    }
+}
+
+/// How a session trades latency for stability of its stream
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LatencyProfile {
+    #[default]
+    Stable,
+    LowLatency,
+}
+
+impl LatencyProfile {
+    /// Samples per FLAC frame, `None` keeps the default of the encoder
+    pub fn flac_block_size(&self) -> Option<u32> {
+        match self {
+            LatencyProfile::Stable => None,
+            LatencyProfile::LowLatency => Some(512),
+        }
+    }
+}
+
+/// Codec the streamed audio of a session is compressed with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "codec")]
+pub enum StreamCodec {
+    #[default]
+    Flac,
+    /// Opus at 48 kHz, one Opus packet of `frame_size` samples per compressed audio buffer
+    Opus { bitrate: u32, frame_size: usize },
+}
+
+/// Sample rate Opus encodes at, the stream is resampled to it
+pub const OPUS_SAMPLE_RATE: usize = 48_000;
+
+/// Samples per Opus frame at 48 kHz, 2.5 to 60 ms
+pub const OPUS_FRAME_SIZES: [usize; 6] = [120, 240, 480, 960, 1920, 2880];
+
+impl StreamCodec {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        match self {
+            StreamCodec::Flac => Ok(()),
+            StreamCodec::Opus { bitrate, frame_size } => {
+                if !(6_000..=510_000).contains(bitrate) {
+                    return Err(anyhow!("Opus bitrate must be between 6000 and 510000 bits per second, not {bitrate}"));
+                }
+
+                if !OPUS_FRAME_SIZES.contains(frame_size) {
+                    return Err(anyhow!("Opus frame size must be one of {OPUS_FRAME_SIZES:?} samples, not \
+                                        {frame_size}"));
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// File format a render is converted to, bit depths are of integer samples except for 32 bit WAV which is float
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "format")]
+pub enum RenderFormat {
+    Wav { bit_depth: u32 },
+    Flac { bit_depth: u32 },
+    Mp3 { bitrate_kbps: u32 },
+}
+
+impl RenderFormat {
+    /// Name of the format, unique among the formats of a render
+    pub fn key(&self) -> String {
+        match self {
+            RenderFormat::Wav { bit_depth } => format!("wav-{bit_depth}"),
+            RenderFormat::Flac { bit_depth } => format!("flac-{bit_depth}"),
+            RenderFormat::Mp3 { bitrate_kbps } => format!("mp3-{bitrate_kbps}"),
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RenderFormat::Wav { .. } => "wav",
+            RenderFormat::Flac { .. } => "flac",
+            RenderFormat::Mp3 { .. } => "mp3",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RenderOutput {
+    pub format: RenderFormat,
+    pub result: Result<String, String>,
+}
+
+/// Bands the streamed audio of a session is analyzed into and spectrum reports per second
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SpectrumSettings {
+    pub bands:   usize,
+    pub rate_hz: f64,
+}
+
+impl SpectrumSettings {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(1..=256).contains(&self.bands) {
+            return Err(anyhow!("Spectrum must have between 1 and 256 bands, not {}", self.bands));
+        }
+
+        if !(self.rate_hz > 0.0 && self.rate_hz <= 60.0) {
+            return Err(anyhow!("Spectrum rate must be above 0 and at most 60 reports per second, not {}",
+                               self.rate_hz));
+        }
+
+        Ok(())
+    }
+}
+
+/// Tempo changes and time signatures of a project, before the first change the project tempo applies
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TempoMap {
+    #[serde(default)]
+    pub changes: Vec<TempoChange>,
+}
+
+/// Segments played back to back, in order, by the following plays
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Playlist {
+    #[serde(default)]
+    pub segments: Vec<TimeSegment>,
+}
+
+/// Parameter changes over time, written as envelopes of the project
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Envelopes {
+    #[serde(default)]
+    pub envelopes: Vec<Envelope>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Envelope {
+    pub target: EnvelopeTarget,
+    pub points: Vec<EnvelopePoint>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum EnvelopeTarget {
+    ConnectionVolume {
+        connection_id: NodeConnectionId,
+    },
+    ConnectionPan {
+        connection_id: NodeConnectionId,
+    },
+    DynamicInstanceParameter {
+        dynamic_id: DynamicInstanceNodeId,
+        parameter:  String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EnvelopePoint {
+    pub time:  f64,
+    pub value: f64,
+    #[serde(default)]
+    pub shape: EnvelopeShape,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EnvelopeShape {
+    #[default]
+    Linear,
+    Square,
+    Smooth,
+}
+
+impl EnvelopeShape {
+    /// Shape of an envelope point as REAPER numbers them
+    pub fn reaper_shape(&self) -> i32 {
+        match self {
+            EnvelopeShape::Linear => 0,
+            EnvelopeShape::Square => 1,
+            EnvelopeShape::Smooth => 2,
+        }
+    }
+}
+
+impl EnvelopeTarget {
+    pub fn connection_id(&self) -> Option<&NodeConnectionId> {
+        match self {
+            EnvelopeTarget::ConnectionVolume { connection_id } | EnvelopeTarget::ConnectionPan { connection_id } => {
+                Some(connection_id)
+            }
+            EnvelopeTarget::DynamicInstanceParameter { .. } => None,
+        }
+    }
+}
+
+impl Envelopes {
+    /// Envelopes of the receive of a connection, the volume envelope before the pan envelope as REAPER writes them
+    pub fn connection_envelopes(&self, connection_id: &NodeConnectionId) -> Vec<&Envelope> {
+        let mut envelopes = self.envelopes
+                                .iter()
+                                .filter(|envelope| envelope.target.connection_id() == Some(connection_id))
+                                .collect::<Vec<_>>();
+
+        envelopes.sort_by_key(|envelope| matches!(envelope.target, EnvelopeTarget::ConnectionPan { .. }));
+        envelopes
+    }
+
+    /// Envelopes of the parameters of a dynamic instance, by parameter name
+    pub fn dynamic_instance_envelopes(&self, dynamic_id: &DynamicInstanceNodeId) -> HashMap<String, Envelope> {
+        self.envelopes
+            .iter()
+            .filter_map(|envelope| match &envelope.target {
+                EnvelopeTarget::DynamicInstanceParameter { dynamic_id: id,
+                                                           parameter, }
+                    if id == dynamic_id =>
+                {
+                    Some((parameter.clone(), envelope.clone()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Fades of the media items, by track media
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MediaFades {
+    #[serde(default)]
+    pub media: HashMap<TrackMediaId, TrackMediaFades>,
+}
+
+/// Fades of a media item in seconds, `crossfade` starts the item earlier to fade in over the item ending where it
+/// starts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrackMediaFades {
+    #[serde(default)]
+    pub fade_in:   f64,
+    #[serde(default)]
+    pub fade_out:  f64,
+    #[serde(default)]
+    pub crossfade: f64,
+    #[serde(default)]
+    pub shape:     FadeShape,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FadeShape {
+    #[default]
+    Linear,
+    FastStart,
+    FastEnd,
+    SCurve,
+}
+
+impl FadeShape {
+    /// Shape of an item fade as REAPER numbers them
+    pub fn reaper_shape(&self) -> i32 {
+        match self {
+            FadeShape::Linear => 0,
+            FadeShape::FastStart => 1,
+            FadeShape::FastEnd => 2,
+            FadeShape::SCurve => 5,
+        }
+    }
+}
+
+/// Playback rates of the media items, by track media
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MediaRates {
+    #[serde(default)]
+    pub media: HashMap<TrackMediaId, TrackMediaRate>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TrackMediaRate {
+    pub rate:    f64,
+    #[serde(default)]
+    pub stretch: StretchMode,
+}
+
+impl Default for TrackMediaRate {
+    fn default() -> Self {
+        Self { rate:    1.0,
+               stretch: StretchMode::default(), }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StretchMode {
+    Varispeed,
+    #[default]
+    Balanced,
+    Tonal,
+    Transient,
+}
+
+impl StretchMode {
+    /// Whether REAPER keeps the pitch of the take when it plays at another rate
+    pub fn preserves_pitch(&self) -> bool {
+        !matches!(self, StretchMode::Varispeed)
+    }
+
+    /// Pitch shifter of the take as REAPER numbers them, the shifter in the upper 16 bits and its mode in the lower.
+    /// -1 is the default of the project
+    pub fn reaper_pitch_mode(&self) -> i32 {
+        match self {
+            StretchMode::Varispeed | StretchMode::Balanced => -1,
+            // élastique Pro
+            StretchMode::Tonal => 9 << 16,
+            // Rubber Band Library, percussive
+            StretchMode::Transient => (13 << 16) | 1,
+        }
+    }
+}
+
+/// Tempo in quarter notes per minute and time signature from `time` seconds on
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TempoChange {
+    pub time:        f64,
+    pub bpm:         f64,
+    pub numerator:   u32,
+    pub denominator: u32,
+}
+
+pub type EngineExtCommandWithResultSender = (EngineExtCommand, Sender<anyhow::Result<()>>);
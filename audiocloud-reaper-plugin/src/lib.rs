@@ -3,7 +3,10 @@ extern crate core;
 pub mod audio_engine;
 pub mod audiocloud_plugin;
 pub mod events;
+pub mod loudness;
+pub mod spectrum;
 pub mod streaming;
+pub mod watermark;
 
 reaper_low::reaper_vst_plugin!();
 vst::plugin_main!(audiocloud_plugin::AudioCloudPlugin);
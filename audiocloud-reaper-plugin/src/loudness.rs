@@ -0,0 +1,280 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+/// Short-term loudness averaging time constant, in seconds
+const MEASURE_TIME_CONSTANT: f64 = 3.0;
+
+/// Gain changes are smoothed with this time constant, in seconds, to avoid pumping
+const GAIN_TIME_CONSTANT: f64 = 0.5;
+
+/// Below this loudness the signal is considered silent and the gain is held
+const GATE_LUFS: f64 = -70.0;
+
+const MAX_BOOST_DB: f64 = 12.0;
+const MAX_CUT_DB: f64 = -24.0;
+
+#[derive(Copy, Clone, Debug, Default)]
+struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    z1: f64,
+    z2: f64,
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b0 * x + self.z1;
+        self.z1 = self.b1 * x - self.a1 * y + self.z2;
+        self.z2 = self.b2 * x - self.a2 * y;
+        y
+    }
+}
+
+/// ITU-R BS.1770 K-weighting filter pair for an arbitrary sample rate
+#[derive(Copy, Clone, Debug)]
+struct KWeighting {
+    shelf:     Biquad,
+    high_pass: Biquad,
+}
+
+impl KWeighting {
+    fn new(sample_rate: f64) -> Self {
+        let f0 = 1681.974450955533;
+        let g = 3.999843853973347;
+        let q = 0.7071752369554196;
+
+        let k = (PI * f0 / sample_rate).tan();
+        let vh = 10f64.powf(g / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+
+        let shelf = Biquad { b0: (vh + vb * k / q + k * k) / a0,
+                             b1: 2.0 * (k * k - vh) / a0,
+                             b2: (vh - vb * k / q + k * k) / a0,
+                             a1: 2.0 * (k * k - 1.0) / a0,
+                             a2: (1.0 - k / q + k * k) / a0,
+                             ..Default::default() };
+
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+        let k = (PI * f0 / sample_rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+
+        let high_pass = Biquad { b0: 1.0,
+                                 b1: -2.0,
+                                 b2: 1.0,
+                                 a1: 2.0 * (k * k - 1.0) / a0,
+                                 a2: (1.0 - k / q + k * k) / a0,
+                                 ..Default::default() };
+
+        Self { shelf, high_pass }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        self.high_pass.process(self.shelf.process(x))
+    }
+}
+
+/// Continuously measures short-term loudness and applies a slowly varying gain towards a target
+///
+/// Used to level-match the monitoring stream so that A/B comparisons are not biased by loudness differences. The
+/// rendered output is never affected.
+pub struct LoudnessNormalizer {
+    target_lufs:  f64,
+    filters:      Vec<KWeighting>,
+    mean_square:  f64,
+    measure_coef: f64,
+    gain_coef:    f64,
+    gain:         f64,
+}
+
+impl LoudnessNormalizer {
+    pub fn new(target_lufs: f64, channels: usize, sample_rate: usize) -> Self {
+        let sample_rate = sample_rate as f64;
+
+        Self { target_lufs:  { target_lufs },
+               filters:      { vec![KWeighting::new(sample_rate); channels] },
+               mean_square:  { 0.0 },
+               measure_coef: { (-1.0 / (MEASURE_TIME_CONSTANT * sample_rate)).exp() },
+               gain_coef:    { (-1.0 / (GAIN_TIME_CONSTANT * sample_rate)).exp() },
+               gain:         { 1.0 }, }
+    }
+
+    pub fn loudness(&self) -> f64 {
+        -0.691 + 10.0 * self.mean_square.max(f64::MIN_POSITIVE).log10()
+    }
+
+    pub fn process(&mut self, channels: &mut [Vec<f64>]) {
+        let len = channels.iter().map(Vec::len).min().unwrap_or_default();
+
+        for i in 0..len {
+            let power = channels.iter()
+                                .zip(self.filters.iter_mut())
+                                .map(|(channel, filter)| filter.process(channel[i]).powi(2))
+                                .sum::<f64>();
+
+            self.mean_square = self.measure_coef * self.mean_square + (1.0 - self.measure_coef) * power;
+
+            let loudness = self.loudness();
+            if loudness > GATE_LUFS {
+                let desired_db = (self.target_lufs - loudness).clamp(MAX_CUT_DB, MAX_BOOST_DB);
+                let desired = 10f64.powf(desired_db / 20.0);
+                self.gain = self.gain_coef * self.gain + (1.0 - self.gain_coef) * desired;
+            }
+
+            for channel in channels.iter_mut() {
+                channel[i] *= self.gain;
+            }
+        }
+    }
+}
+
+/// Momentary and short-term loudness are computed over blocks of this many seconds
+const BLOCK_SECONDS: f64 = 0.1;
+
+/// Blocks in the 400 ms momentary window
+const MOMENTARY_BLOCKS: usize = 4;
+
+/// Blocks in the 3 s short-term window
+const SHORT_TERM_BLOCKS: usize = 30;
+
+/// True peak is measured on the signal oversampled by this factor, as ITU-R BS.1770 recommends
+const TRUE_PEAK_OVERSAMPLING: usize = 4;
+
+/// Input samples each phase of the oversampling filter spans
+const TRUE_PEAK_TAPS: usize = 12;
+
+/// Interpolates a channel at four times its sample rate to find the peaks between samples
+#[derive(Clone, Debug)]
+struct TruePeak {
+    phases:  Vec<Vec<f64>>,
+    history: VecDeque<f64>,
+}
+
+impl TruePeak {
+    fn new() -> Self {
+        let len = TRUE_PEAK_OVERSAMPLING * TRUE_PEAK_TAPS;
+        let center = (len / 2) as f64;
+
+        // Hann windowed sinc, cutting off at the Nyquist frequency of the original rate
+        let filter = (0..len).map(|n| {
+                                 let t = (n as f64 - center) / TRUE_PEAK_OVERSAMPLING as f64;
+                                 let sinc = if t == 0.0 { 1.0 } else { (PI * t).sin() / (PI * t) };
+                                 let window = 0.5 - 0.5 * (2.0 * PI * n as f64 / len as f64).cos();
+                                 sinc * window
+                             })
+                             .collect::<Vec<_>>();
+
+        let phases = (0..TRUE_PEAK_OVERSAMPLING).map(|phase| {
+                                                    filter.iter()
+                                                          .skip(phase)
+                                                          .step_by(TRUE_PEAK_OVERSAMPLING)
+                                                          .copied()
+                                                          .collect()
+                                                })
+                                                .collect();
+
+        Self { phases:  { phases },
+               history: { VecDeque::from(vec![0.0; TRUE_PEAK_TAPS]) }, }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        self.history.pop_back();
+        self.history.push_front(x);
+
+        self.phases
+            .iter()
+            .map(|taps| {
+                taps.iter()
+                    .zip(&self.history)
+                    .map(|(tap, x)| tap * x)
+                    .sum::<f64>()
+                    .abs()
+            })
+            .fold(x.abs(), f64::max)
+    }
+}
+
+/// Loudness of a signal at one moment, `None` where it is silent
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LoudnessReading {
+    pub momentary_lufs:  Option<f64>,
+    pub short_term_lufs: Option<f64>,
+    /// Highest true peak since the previous reading, in dBTP
+    pub true_peak_db:    Option<f64>,
+}
+
+/// Measures momentary and short-term loudness and true peak of a signal, as EBU R128 meters show them
+///
+/// Unlike [`LoudnessNormalizer`] the windows are rectangular, so readings match those of broadcast meters.
+pub struct LoudnessMeter {
+    filters:      Vec<KWeighting>,
+    peaks:        Vec<TruePeak>,
+    block_len:    usize,
+    block_pos:    usize,
+    block_energy: f64,
+    blocks:       VecDeque<f64>,
+    true_peak:    f64,
+}
+
+impl LoudnessMeter {
+    pub fn new(channels: usize, sample_rate: usize) -> Self {
+        let block_len = ((sample_rate as f64 * BLOCK_SECONDS) as usize).max(1);
+
+        Self { filters:      { vec![KWeighting::new(sample_rate as f64); channels] },
+               peaks:        { vec![TruePeak::new(); channels] },
+               block_len:    { block_len },
+               block_pos:    { 0 },
+               block_energy: { 0.0 },
+               blocks:       { VecDeque::with_capacity(SHORT_TERM_BLOCKS) },
+               true_peak:    { 0.0 }, }
+    }
+
+    pub fn process(&mut self, channels: &[Vec<f64>]) {
+        let len = channels.iter().map(Vec::len).min().unwrap_or_default();
+
+        for i in 0..len {
+            for ((channel, filter), peak) in channels.iter().zip(self.filters.iter_mut()).zip(self.peaks.iter_mut()) {
+                self.block_energy += filter.process(channel[i]).powi(2);
+                self.true_peak = self.true_peak.max(peak.process(channel[i]));
+            }
+
+            self.block_pos += 1;
+            if self.block_pos == self.block_len {
+                if self.blocks.len() == SHORT_TERM_BLOCKS {
+                    self.blocks.pop_front();
+                }
+                self.blocks.push_back(self.block_energy / self.block_len as f64);
+
+                self.block_pos = 0;
+                self.block_energy = 0.0;
+            }
+        }
+    }
+
+    /// Loudness over the most recent windows, resetting the true peak for the next reading
+    pub fn take_reading(&mut self) -> LoudnessReading {
+        let true_peak = std::mem::take(&mut self.true_peak);
+
+        LoudnessReading { momentary_lufs:  { self.window_lufs(MOMENTARY_BLOCKS) },
+                          short_term_lufs: { self.window_lufs(SHORT_TERM_BLOCKS) },
+                          true_peak_db:    { Some(20.0 * true_peak.log10()).filter(|_| true_peak > 0.0) }, }
+    }
+
+    fn window_lufs(&self, blocks: usize) -> Option<f64> {
+        if self.blocks.is_empty() {
+            return None;
+        }
+
+        let window = self.blocks.iter().rev().take(blocks);
+        let mean_square = window.clone().sum::<f64>() / window.count() as f64;
+        let lufs = -0.691 + 10.0 * mean_square.max(f64::MIN_POSITIVE).log10();
+
+        Some(lufs).filter(|lufs| *lufs > GATE_LUFS)
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+use crate::events::SpectrumSettings;
+
+/// Samples each spectrum is computed over, a power of two so the FFT can halve it down to single samples
+const FFT_LEN: usize = 4096;
+
+/// Lower edge of the lowest band, in Hz
+const LOWEST_HZ: f64 = 20.0;
+
+/// Level reported for bands without any energy, in dBFS
+const FLOOR_DB: f64 = -120.0;
+
+/// Spectrum of a signal, the level of each band in dBFS from the lowest band up
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SpectrumReport {
+    pub min_hz: f64,
+    pub max_hz: f64,
+    pub bands:  Vec<f64>,
+}
+
+/// Computes the spectrum of the downmixed signal at a fixed rate, over bands spaced evenly on a logarithmic scale
+///
+/// A full scale sine reads close to 0 dBFS in the band it falls in.
+pub struct SpectrumAnalyzer {
+    window:       Vec<f64>,
+    bins:         Vec<(usize, usize)>,
+    max_hz:       f64,
+    history:      VecDeque<f64>,
+    interval:     usize,
+    since_report: usize,
+    report:       Option<SpectrumReport>,
+}
+
+impl SpectrumAnalyzer {
+    pub fn new(settings: SpectrumSettings, sample_rate: usize) -> Self {
+        let nyquist = sample_rate as f64 / 2.0;
+        let bin_hz = sample_rate as f64 / FFT_LEN as f64;
+        let bands = settings.bands.max(1);
+
+        // every band covers at least one bin, low bands narrower than a bin share its level
+        let bins = (0..bands).map(|band| {
+                                 let low = LOWEST_HZ * (nyquist / LOWEST_HZ).powf(band as f64 / bands as f64);
+                                 let high = LOWEST_HZ * (nyquist / LOWEST_HZ).powf((band + 1) as f64 / bands as f64);
+                                 let first = ((low / bin_hz).floor() as usize).clamp(1, FFT_LEN / 2 - 1);
+                                 let last = ((high / bin_hz).ceil() as usize).clamp(first + 1, FFT_LEN / 2);
+                                 (first, last)
+                             })
+                             .collect();
+
+        let window = (0..FFT_LEN).map(|n| 0.5 - 0.5 * (2.0 * PI * n as f64 / FFT_LEN as f64).cos())
+                                 .collect();
+
+        Self { window:       { window },
+               bins:         { bins },
+               max_hz:       { nyquist },
+               history:      { VecDeque::from(vec![0.0; FFT_LEN]) },
+               interval:     { ((sample_rate as f64 / settings.rate_hz) as usize).max(1) },
+               since_report: { 0 },
+               report:       { None }, }
+    }
+
+    pub fn process(&mut self, channels: &[Vec<f64>]) {
+        let len = channels.iter().map(Vec::len).min().unwrap_or_default();
+
+        for i in 0..len {
+            let mono = channels.iter().map(|channel| channel[i]).sum::<f64>() / channels.len() as f64;
+
+            self.history.pop_front();
+            self.history.push_back(mono);
+
+            self.since_report += 1;
+            if self.since_report >= self.interval {
+                self.since_report = 0;
+                self.report = Some(self.analyze());
+            }
+        }
+    }
+
+    /// The most recent spectrum, if one was computed since the previous call
+    pub fn take_report(&mut self) -> Option<SpectrumReport> {
+        self.report.take()
+    }
+
+    fn analyze(&self) -> SpectrumReport {
+        let mut re = self.history
+                         .iter()
+                         .zip(&self.window)
+                         .map(|(x, w)| x * w)
+                         .collect::<Vec<_>>();
+        let mut im = vec![0.0; FFT_LEN];
+
+        fft(&mut re, &mut im);
+
+        // a full scale sine peaks at a quarter of the window length once windowed
+        let full_scale = (FFT_LEN as f64 / 4.0).powi(2);
+
+        let bands = self.bins
+                        .iter()
+                        .map(|&(first, last)| {
+                            let power = (first..last).map(|bin| re[bin].powi(2) + im[bin].powi(2)).sum::<f64>();
+                            if power > 0.0 {
+                                (10.0 * (power / full_scale).log10()).max(FLOOR_DB)
+                            } else {
+                                FLOOR_DB
+                            }
+                        })
+                        .collect();
+
+        SpectrumReport { min_hz: { LOWEST_HZ },
+                         max_hz: { self.max_hz },
+                         bands:  { bands }, }
+    }
+}
+
+/// In place radix-2 FFT, the length has to be a power of two
+fn fft(re: &mut [f64], im: &mut [f64]) {
+    let len = re.len();
+
+    let mut j = 0;
+    for i in 1..len {
+        let mut bit = len >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut size = 2;
+    while size <= len {
+        let angle = -2.0 * PI / size as f64;
+
+        for start in (0..len).step_by(size) {
+            for k in 0..size / 2 {
+                let (sin, cos) = (angle * k as f64).sin_cos();
+                let (a, b) = (start + k, start + k + size / 2);
+
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+
+        size <<= 1;
+    }
+}
@@ -7,8 +7,9 @@ use bytes::Bytes;
 use libflac_sys::{
     FLAC__StreamEncoder, FLAC__StreamEncoderWriteStatus, FLAC__byte, FLAC__stream_encoder_delete,
     FLAC__stream_encoder_finish, FLAC__stream_encoder_init_stream, FLAC__stream_encoder_new,
-    FLAC__stream_encoder_process, FLAC__stream_encoder_set_bits_per_sample, FLAC__stream_encoder_set_channels,
-    FLAC__stream_encoder_set_sample_rate, FLAC__stream_encoder_set_streamable_subset,
+    FLAC__stream_encoder_process, FLAC__stream_encoder_set_bits_per_sample, FLAC__stream_encoder_set_blocksize,
+    FLAC__stream_encoder_set_channels, FLAC__stream_encoder_set_sample_rate,
+    FLAC__stream_encoder_set_streamable_subset,
 };
 use r8brain_rs::PrecisionProfile;
 use tracing::*;
@@ -17,12 +18,18 @@ use vst::buffer::AudioBuffer;
 use audiocloud_api::audio_engine::CompressedAudio;
 use audiocloud_api::common::media::{PlayId, RequestPlay};
 
+use crate::events::{LatencyProfile, SpectrumSettings, StreamCodec, OPUS_SAMPLE_RATE};
+use crate::loudness::{LoudnessMeter, LoudnessNormalizer, LoudnessReading};
+use crate::spectrum::{SpectrumAnalyzer, SpectrumReport};
+use crate::watermark::{PlayWatermark, Watermark};
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct StreamingConfig {
     pub play_id:     PlayId,
     pub sample_rate: usize,
     pub channels:    usize,
     pub bit_depth:   usize,
+    pub codec:       StreamCodec,
 }
 
 pub struct AudioBuf {
@@ -35,7 +42,6 @@ struct Resampler {
     resamplers: Vec<(r8brain_rs::Resampler, Vec<f64>)>,
     timeline:   f64,
     stream:     u64,
-    offset:     f64,
     from:       f64,
     to:         f64,
 }
@@ -58,22 +64,27 @@ impl Resampler {
                              .collect();
 
         let timeline = 0.0;
-        let offset = 0.0;
         let stream = 0;
 
         Self { resamplers,
                timeline,
                stream,
-               offset,
                from,
                to }
     }
 
+    /// Drop the samples the resamplers still hold, the stream keeps counting from where it was
+    pub fn reset(&mut self) {
+        let stream = self.stream;
+        *self = Self::new(self.resamplers.len(), self.from, self.to);
+        self.stream = stream;
+    }
+
     pub fn resample(&mut self, input: AudioBuf, out: &mut VecDeque<AudioBuf>) -> anyhow::Result<()> {
         let mut channels = vec![];
 
+        // the timeline of the input is kept as is, it jumps back when a loop wraps while the stream keeps counting
         self.timeline = input.timeline;
-        self.offset -= input.channels[0].len() as f64 * self.to / self.from;
 
         for (ch, (resampler, temp)) in input.channels.into_iter().zip(self.resamplers.iter_mut()) {
             let size = resampler.process(&ch[..], &mut temp[..]);
@@ -83,10 +94,9 @@ impl Resampler {
         let len = channels.first().map(|v| v.len()).unwrap_or_default();
 
         out.push_back(AudioBuf { stream: self.stream,
-                                 timeline: self.timeline + self.offset,
+                                 timeline: self.timeline,
                                  channels });
 
-        self.offset += len as f64;
         self.stream += len as u64;
 
         Ok(())
@@ -104,7 +114,7 @@ impl Resampler {
 
         out.push_back(AudioBuf { channels,
                                  stream: self.stream,
-                                 timeline: self.timeline + self.offset });
+                                 timeline: self.timeline });
 
         Ok(())
     }
@@ -118,8 +128,9 @@ pub struct FlacEncoder {
     stream_pos:      u64,
     queued_len:      usize,
     play_id:         PlayId,
+    /// Timeline position of the first sample not yet emitted, the frames keep it even if a loop wraps inside them
+    queued_timeline: Option<f64>,
     timeline_pos:    f64,
-    timeline_offset: f64,
     sample_rate:     f64,
 }
 
@@ -131,8 +142,13 @@ impl Drop for FlacEncoder {
 
 impl FlacEncoder {
     #[instrument(skip_all)]
-    pub fn new(play_id: PlayId, sample_rate: usize, channels: usize, bits_per_sample: usize) -> anyhow::Result<Self> {
-        debug!(sample_rate, channels, bits_per_sample, "enter");
+    pub fn new(play_id: PlayId,
+               sample_rate: usize,
+               channels: usize,
+               bits_per_sample: usize,
+               block_size: Option<u32>)
+               -> anyhow::Result<Self> {
+        debug!(sample_rate, channels, bits_per_sample, ?block_size, "enter");
 
         if bits_per_sample > 16 {
             return Err(anyhow!("The reference encoder only supports 16-bit encoding"));
@@ -145,6 +161,10 @@ impl FlacEncoder {
                        1);
             assert_eq!(FLAC__stream_encoder_set_streamable_subset(encoder, 1), 1);
             assert_eq!(FLAC__stream_encoder_set_sample_rate(encoder, sample_rate as u32), 1);
+            if let Some(block_size) = block_size {
+                // smaller frames leave the encoder sooner, at the cost of compression
+                assert_eq!(FLAC__stream_encoder_set_blocksize(encoder, block_size), 1);
+            }
 
             let mut internals = Box::new(SharedInternals { buffer: vec![] });
 
@@ -164,8 +184,8 @@ impl FlacEncoder {
                       bits_per_sample,
                       play_id,
                       queued_len: 0,
+                      queued_timeline: None,
                       timeline_pos: 0.0,
-                      timeline_offset: 0.0,
                       stream_pos: 0,
                       sample_rate: sample_rate as f64 })
         }
@@ -181,8 +201,9 @@ impl FlacEncoder {
             }
         };
 
-        self.timeline_pos = data.timeline;
-        self.timeline_offset -= data.channels[0].len() as f64 / self.sample_rate;
+        let block_len = data.channels.first().map(Vec::len).unwrap_or_default();
+        self.timeline_pos = data.timeline + block_len as f64 / self.sample_rate;
+        self.queued_timeline.get_or_insert(data.timeline);
 
         let mut pointers = vec![];
         for (input, output) in data.channels.iter().zip(self.tmp_buffer.iter_mut()) {
@@ -203,14 +224,15 @@ impl FlacEncoder {
             self.queued_len += len;
 
             if !self.internals.buffer.is_empty() {
+                let timeline_pos = self.queued_timeline.take().unwrap_or(data.timeline);
+
                 output.push_back(CompressedAudio { play_id:      { self.play_id },
-                                                   timeline_pos: { self.timeline_pos + self.timeline_offset },
+                                                   timeline_pos: { timeline_pos },
                                                    stream_pos:   { self.stream_pos },
                                                    buffer:       { Bytes::from(self.internals.buffer.clone()) },
-                                                   num_samples:  { 0 },
+                                                   num_samples:  { self.queued_len as _ },
                                                    last:         { false }, });
                 self.stream_pos += self.queued_len as u64;
-                self.timeline_offset += (self.queued_len as f64) / self.sample_rate as f64;
 
                 self.queued_len = 0;
                 self.internals.buffer.clear();
@@ -227,12 +249,16 @@ impl FlacEncoder {
             }
         }
 
+        let timeline_pos = self.queued_timeline.take().unwrap_or(self.timeline_pos);
+
         output.push_back(CompressedAudio { play_id:      { self.play_id },
-                                           timeline_pos: { self.timeline_pos + self.timeline_offset },
+                                           timeline_pos: { timeline_pos },
                                            stream_pos:   { self.stream_pos },
                                            buffer:       { Bytes::from(self.internals.buffer.clone()) },
-                                           num_samples:  { 0 },
+                                           num_samples:  { self.queued_len as _ },
                                            last:         { true }, });
+        self.stream_pos += self.queued_len as u64;
+        self.queued_len = 0;
 
         self.internals.buffer.clear();
 
@@ -258,27 +284,226 @@ struct SharedInternals {
     buffer: Vec<u8>,
 }
 
+/// Largest Opus packet, as recommended by the libopus documentation
+const MAX_OPUS_PACKET: usize = 4000;
+
+pub struct OpusEncoder {
+    encoder:          opus::Encoder,
+    channels:         usize,
+    frame_size:       usize,
+    /// Interleaved samples waiting for a whole frame
+    pending:          Vec<f32>,
+    /// Timeline position of the first pending sample
+    pending_timeline: Option<f64>,
+    packet:           Vec<u8>,
+    play_id:          PlayId,
+    stream_pos:       u64,
+    timeline_pos:     f64,
+}
+
+impl OpusEncoder {
+    #[instrument(skip_all)]
+    pub fn new(play_id: PlayId, channels: usize, bitrate: u32, frame_size: usize) -> anyhow::Result<Self> {
+        debug!(channels, bitrate, frame_size, "enter");
+
+        let opus_channels = match channels {
+            1 => opus::Channels::Mono,
+            2 => opus::Channels::Stereo,
+            i => return Err(anyhow!("Opus streams are mono or stereo, not {i} channels")),
+        };
+
+        let mut encoder = opus::Encoder::new(OPUS_SAMPLE_RATE as u32, opus_channels, opus::Application::Audio)?;
+        encoder.set_bitrate(opus::Bitrate::Bits(bitrate as i32))?;
+
+        Ok(Self { encoder,
+                  channels,
+                  frame_size,
+                  pending: vec![],
+                  pending_timeline: None,
+                  packet: vec![0; MAX_OPUS_PACKET],
+                  play_id,
+                  stream_pos: 0,
+                  timeline_pos: 0.0 })
+    }
+
+    /// Takes effect from the next frame on, decoders follow without being told
+    pub fn set_bitrate(&mut self, bitrate: u32) -> anyhow::Result<()> {
+        self.encoder.set_bitrate(opus::Bitrate::Bits(bitrate as i32))?;
+
+        Ok(())
+    }
+
+    pub fn process(&mut self, data: AudioBuf, output: &mut VecDeque<CompressedAudio>) -> anyhow::Result<()> {
+        let len = data.channels.iter().map(Vec::len).min().unwrap_or_default();
+        self.timeline_pos = data.timeline + len as f64 / OPUS_SAMPLE_RATE as f64;
+        self.pending_timeline.get_or_insert(data.timeline);
+
+        for i in 0..len {
+            for channel in data.channels.iter().take(self.channels) {
+                self.pending.push(channel[i] as f32);
+            }
+        }
+
+        // each compressed audio buffer is one Opus packet, so web clients can hand it to a decoder as is
+        while self.pending.len() >= self.frame_size * self.channels {
+            self.encode_frame(false, output)?;
+        }
+
+        Ok(())
+    }
+
+    /// Drop the samples waiting for a whole frame, the next frame starts with whatever is processed next
+    pub fn discard_pending(&mut self) {
+        self.pending.clear();
+        self.pending_timeline = None;
+    }
+
+    pub fn finish(&mut self, output: &mut VecDeque<CompressedAudio>) -> anyhow::Result<()> {
+        // the last frame is padded with silence, it still counts only the samples it was given
+        let num_samples = self.pending.len() / self.channels;
+        self.pending.resize(self.frame_size * self.channels, 0.0);
+
+        self.encode_frame(true, output)?;
+
+        if let Some(last) = output.back_mut() {
+            last.num_samples = num_samples as _;
+        }
+
+        Ok(())
+    }
+
+    fn encode_frame(&mut self, last: bool, output: &mut VecDeque<CompressedAudio>) -> anyhow::Result<()> {
+        let frame_len = self.frame_size * self.channels;
+        let size = self.encoder
+                       .encode_float(&self.pending[..frame_len], &mut self.packet[..])?;
+        let timeline_pos = self.pending_timeline.unwrap_or(self.timeline_pos);
+
+        output.push_back(CompressedAudio { play_id:      { self.play_id },
+                                           timeline_pos: { timeline_pos },
+                                           stream_pos:   { self.stream_pos },
+                                           buffer:       { Bytes::copy_from_slice(&self.packet[..size]) },
+                                           num_samples:  { self.frame_size as _ },
+                                           last:         { last }, });
+
+        self.stream_pos += self.frame_size as u64;
+        self.pending.drain(..frame_len);
+        self.pending_timeline = if self.pending.is_empty() {
+            None
+        } else {
+            Some(timeline_pos + self.frame_size as f64 / OPUS_SAMPLE_RATE as f64)
+        };
+
+        Ok(())
+    }
+}
+
+/// Encoder of the codec the play streams with
+enum StreamEncoder {
+    Flac(FlacEncoder),
+    Opus(OpusEncoder),
+}
+
+impl StreamEncoder {
+    fn process(&mut self, data: AudioBuf, output: &mut VecDeque<CompressedAudio>) -> anyhow::Result<()> {
+        match self {
+            StreamEncoder::Flac(encoder) => encoder.process(data, output),
+            StreamEncoder::Opus(encoder) => encoder.process(data, output),
+        }
+    }
+
+    fn finish(&mut self, output: &mut VecDeque<CompressedAudio>) -> anyhow::Result<()> {
+        match self {
+            StreamEncoder::Flac(encoder) => encoder.finish(output),
+            StreamEncoder::Opus(encoder) => encoder.finish(output),
+        }
+    }
+
+    fn seek(&mut self) {
+        match self {
+            // samples handed to libFLAC can not be taken back, they go out at the head of the next frame
+            StreamEncoder::Flac(_) => {}
+            StreamEncoder::Opus(encoder) => encoder.discard_pending(),
+        }
+    }
+
+    fn set_bitrate(&mut self, bitrate: u32) -> anyhow::Result<()> {
+        match self {
+            StreamEncoder::Flac(_) => Err(anyhow!("FLAC streams are lossless, their bitrate can not change")),
+            StreamEncoder::Opus(encoder) => encoder.set_bitrate(bitrate),
+        }
+    }
+}
+
 pub struct EncoderChain {
     resampler:      Option<Resampler>,
-    encoder:        FlacEncoder,
+    meter:          LoudnessMeter,
+    spectrum:       Option<SpectrumAnalyzer>,
+    loudness:       Option<LoudnessNormalizer>,
+    watermark:      Option<Watermark>,
+    gain:           f64,
+    target_gain:    f64,
+    encoder:        StreamEncoder,
     queue:          VecDeque<AudioBuf>,
     stream:         u64,
+    timeline:       Option<f64>,
+    passes:         u64,
     pub play:       RequestPlay,
     pub compressed: VecDeque<CompressedAudio>,
+    /// While paused the transport stands still, the chain keeps its state but encodes nothing
+    pub paused:     bool,
 }
 
 unsafe impl Send for EncoderChain {}
 
 impl EncoderChain {
-    pub fn new(play: RequestPlay, native_channels: usize, native_sample_rate: usize) -> anyhow::Result<Self> {
+    pub fn new(play: RequestPlay,
+               native_channels: usize,
+               native_sample_rate: usize,
+               loudness_target: Option<f64>,
+               latency_profile: LatencyProfile,
+               codec: StreamCodec,
+               spectrum: Option<SpectrumSettings>,
+               watermark: Option<PlayWatermark>,
+               monitor_gain: f64)
+               -> anyhow::Result<Self> {
         let play_sample_rate: usize = play.sample_rate.into();
-        let resampler = if native_sample_rate == play_sample_rate {
-            None
-        } else {
-            Some(Resampler::new(native_channels, play_sample_rate as f64, native_sample_rate as f64))
+
+        let (resampler, encoder) = match codec {
+            StreamCodec::Flac => {
+                let resampler = if native_sample_rate == play_sample_rate {
+                    None
+                } else {
+                    Some(Resampler::new(native_channels, play_sample_rate as f64, native_sample_rate as f64))
+                };
+
+                let encoder = FlacEncoder::new(play.play_id,
+                                               native_sample_rate,
+                                               native_channels,
+                                               play.bit_depth.into(),
+                                               latency_profile.flac_block_size())?;
+
+                (resampler, StreamEncoder::Flac(encoder))
+            }
+            StreamCodec::Opus { bitrate, frame_size } => {
+                // Opus only encodes at a handful of rates, the stream always goes out at 48 kHz
+                let resampler = if native_sample_rate == OPUS_SAMPLE_RATE {
+                    None
+                } else {
+                    Some(Resampler::new(native_channels, native_sample_rate as f64, OPUS_SAMPLE_RATE as f64))
+                };
+
+                let encoder = OpusEncoder::new(play.play_id, native_channels, bitrate, frame_size)?;
+
+                (resampler, StreamEncoder::Opus(encoder))
+            }
         };
 
-        let encoder = FlacEncoder::new(play.play_id, native_sample_rate, native_channels, play.bit_depth.into())?;
+        let loudness = loudness_target.map(|target_lufs| {
+                                          LoudnessNormalizer::new(target_lufs, native_channels, native_sample_rate)
+                                      });
+        let meter = LoudnessMeter::new(native_channels, native_sample_rate);
+        let spectrum = spectrum.map(|settings| SpectrumAnalyzer::new(settings, native_sample_rate));
+        let watermark = watermark.map(|settings| Watermark::new(settings, native_sample_rate));
 
         let queue = VecDeque::new();
         let compressed = VecDeque::new();
@@ -286,22 +511,90 @@ impl EncoderChain {
 
         Ok(Self { play,
                   resampler,
+                  meter,
+                  spectrum,
+                  loudness,
+                  watermark,
+                  gain: monitor_gain,
+                  target_gain: monitor_gain,
                   encoder,
                   queue,
                   compressed,
-                  stream })
+                  stream,
+                  timeline: None,
+                  passes: 0,
+                  paused: false })
+    }
+
+    pub fn set_bitrate(&mut self, bitrate: u32) -> anyhow::Result<()> {
+        self.encoder.set_bitrate(bitrate)
+    }
+
+    /// Gain of the monitor controller, ramped to over the next block so the change does not click
+    pub fn set_gain(&mut self, gain: f64) {
+        self.target_gain = gain;
+    }
+
+    /// The transport moved, drop what is buffered from the old position so the next packet starts at the new one. The
+    /// play, its stream positions and its encoder state are kept, clients keep decoding as if nothing happened
+    pub fn seek(&mut self) {
+        if let Some(resampler) = self.resampler.as_mut() {
+            resampler.reset();
+        }
+
+        self.queue.clear();
+        self.encoder.seek();
+
+        // a seek back is not a loop wrapping
+        self.timeline = None;
+    }
+
+    /// Loudness of the streamed mixer output, as it is before the monitoring stream is level matched
+    pub fn take_loudness(&mut self) -> LoudnessReading {
+        self.meter.take_reading()
+    }
+
+    /// Spectrum of the streamed mixer output, if spectrum reports are on and one is due
+    pub fn take_spectrum(&mut self) -> Option<SpectrumReport> {
+        self.spectrum.as_mut().and_then(SpectrumAnalyzer::take_report)
     }
 
     pub fn process(&mut self, buf: &mut AudioBuffer<f64>, timeline: f64) -> anyhow::Result<()> {
         let (inputs, _) = buf.split();
 
-        let buf = AudioBuf { timeline,
-                             stream: self.stream,
-                             channels: (0..inputs.len()).map(|i| Vec::from_iter(inputs.get(i).into_iter().copied()))
-                                                        .collect() };
+        let mut buf =
+            AudioBuf { timeline,
+                       stream: self.stream,
+                       channels: (0..inputs.len()).map(|i| Vec::from_iter(inputs.get(i).into_iter().copied()))
+                                                  .collect() };
 
         self.stream += buf.channels[0].len() as u64;
 
+        // a loop wrapping moves the timeline back, the stream keeps counting and the encoder keeps going so the
+        // boundary is encoded like any other sample
+        if self.play.looping && matches!(self.timeline, Some(previous) if timeline < previous) {
+            self.passes += 1;
+            trace!(play_id = %self.play.play_id, passes = self.passes, timeline, "wrapped loop");
+        }
+        self.timeline = Some(timeline);
+
+        self.meter.process(&buf.channels);
+
+        if let Some(spectrum) = self.spectrum.as_mut() {
+            spectrum.process(&buf.channels);
+        }
+
+        if let Some(loudness) = self.loudness.as_mut() {
+            loudness.process(&mut buf.channels);
+        }
+
+        if let Some(watermark) = self.watermark.as_mut() {
+            watermark.apply(&mut buf.channels);
+        }
+
+        apply_gain(&mut buf.channels, self.gain, self.target_gain);
+        self.gain = self.target_gain;
+
         if let Some(resampler) = self.resampler.as_mut() {
             resampler.resample(buf, &mut self.queue)?;
         } else {
@@ -329,3 +622,17 @@ impl EncoderChain {
         Ok(self.compressed)
     }
 }
+
+/// Scale the samples by a gain ramping from `from` to `to` over the block
+fn apply_gain(channels: &mut [Vec<f64>], from: f64, to: f64) {
+    if from == 1.0 && to == 1.0 {
+        return;
+    }
+
+    for channel in channels.iter_mut() {
+        let len = channel.len().max(1) as f64;
+        for (i, sample) in channel.iter_mut().enumerate() {
+            *sample *= from + (to - from) * (i + 1) as f64 / len;
+        }
+    }
+}
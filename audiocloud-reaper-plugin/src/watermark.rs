@@ -0,0 +1,165 @@
+use std::env;
+use std::f64::consts::TAU;
+
+use anyhow::anyhow;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tracing::*;
+
+#[cfg(test)]
+mod tests;
+
+/// Key of the chip sequence of inaudible watermarks, shared by every stream of the engine and needed to detect them
+static WATERMARK_KEY: Lazy<u64> = Lazy::new(|| {
+    env::var("WATERMARK_KEY").ok()
+                             .map(|key| fnv1a(key.as_bytes()))
+                             .unwrap_or(0x5eed_0a0d_10c1_0bd5)
+});
+
+/// Samples per payload bit of the inaudible watermark, about 21ms at 192kHz
+const SAMPLES_PER_BIT: usize = 4096;
+
+/// Payload bits, the full payload repeats every `PAYLOAD_BITS * SAMPLES_PER_BIT` samples
+const PAYLOAD_BITS: usize = 64;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatermarkMode {
+    Off,
+    /// Short tone bursts at a fixed interval, encoding nothing but clearly marking the stream as a preview
+    Audible,
+    /// Low level spread spectrum noise carrying the 64 bit payload, recoverable by correlation
+    Inaudible,
+}
+
+/// Watermark of the stream of one play, as the domain sets it from the watermark of the task
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlayWatermark {
+    pub mode:     WatermarkMode,
+    pub level_db: f64,
+    /// Identifies the client the play streams to
+    pub payload:  u64,
+}
+
+impl PlayWatermark {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(-96.0..=0.0).contains(&self.level_db) {
+            return Err(anyhow!("Watermark level must be between -96 and 0 dBFS, not {}", self.level_db));
+        }
+
+        Ok(())
+    }
+}
+
+/// Injects a watermark identifying the client of a play into the monitoring stream before encoding
+pub struct Watermark {
+    mode:        WatermarkMode,
+    gain:        f64,
+    payload:     u64,
+    key:         u64,
+    sample_rate: f64,
+    position:    u64,
+}
+
+impl Watermark {
+    pub fn new(settings: PlayWatermark, sample_rate: usize) -> Self {
+        Self::with_key(settings, *WATERMARK_KEY, sample_rate)
+    }
+
+    pub fn with_key(settings: PlayWatermark, key: u64, sample_rate: usize) -> Self {
+        debug!(payload = format!("{:016x}", settings.payload), mode = ?settings.mode, "Watermarking stream");
+
+        Self { mode:        { settings.mode },
+               gain:        { 10f64.powf(settings.level_db / 20.0) },
+               payload:     { settings.payload },
+               key:         { key },
+               sample_rate: { sample_rate as f64 },
+               position:    { 0 }, }
+    }
+
+    pub fn apply(&mut self, channels: &mut [Vec<f64>]) {
+        let len = channels.iter().map(Vec::len).min().unwrap_or_default();
+
+        for i in 0..len {
+            let position = self.position + i as u64;
+            let sample = match self.mode {
+                WatermarkMode::Off => 0.0,
+                WatermarkMode::Audible => self.tone_sample(position),
+                WatermarkMode::Inaudible => self.spread_sample(position),
+            };
+
+            for channel in channels.iter_mut() {
+                channel[i] += sample;
+            }
+        }
+
+        self.position += len as u64;
+    }
+
+    /// 1kHz burst of 250ms every 10 seconds
+    fn tone_sample(&self, position: u64) -> f64 {
+        let period = (self.sample_rate * 10.0) as u64;
+        let burst = (self.sample_rate * 0.25) as u64;
+        let offset = position % period.max(1);
+
+        if offset < burst {
+            self.gain * (TAU * 1000.0 * offset as f64 / self.sample_rate).sin()
+        } else {
+            0.0
+        }
+    }
+
+    /// Pseudo random chip sequence keyed by the engine key, sign flipped by the current payload bit
+    fn spread_sample(&self, position: u64) -> f64 {
+        let bit = (self.payload >> payload_bit(position)) & 1 == 1;
+
+        if bit {
+            self.gain * chip(self.key, position)
+        } else {
+            -self.gain * chip(self.key, position)
+        }
+    }
+}
+
+/// Recover the payload of an inaudible watermark from one channel of a stream, from the first sample of the play on
+///
+/// Each bit is the sign of the correlation of the samples carrying it with the chip sequence of `key`, summed over
+/// every repetition of the payload in `samples`. Longer recordings detect reliably under louder program material.
+pub fn detect(key: u64, samples: &[f64]) -> u64 {
+    let mut correlations = [0.0; PAYLOAD_BITS];
+
+    for (position, sample) in samples.iter().enumerate() {
+        let position = position as u64;
+        correlations[payload_bit(position) as usize] += sample * chip(key, position);
+    }
+
+    correlations.iter()
+                .enumerate()
+                .filter(|(_, correlation)| **correlation > 0.0)
+                .fold(0, |payload, (bit, _)| payload | (1 << bit))
+}
+
+fn payload_bit(position: u64) -> u64 {
+    (position / SAMPLES_PER_BIT as u64) % PAYLOAD_BITS as u64
+}
+
+fn chip(key: u64, position: u64) -> f64 {
+    if splitmix64(key ^ position) & 1 == 1 {
+        1.0
+    } else {
+        -1.0
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+                    (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+                })
+}
+
+fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,73 @@
+use std::f64::consts::TAU;
+
+use crate::watermark::{detect, PlayWatermark, Watermark, WatermarkMode, PAYLOAD_BITS, SAMPLES_PER_BIT};
+
+const KEY: u64 = 0x0123_4567_89ab_cdef;
+const PAYLOAD: u64 = 0xdead_beef_0bad_f00d;
+
+fn inaudible(level_db: f64) -> PlayWatermark {
+    PlayWatermark { mode:     { WatermarkMode::Inaudible },
+                    level_db: { level_db },
+                    payload:  { PAYLOAD }, }
+}
+
+/// Stereo program of `len` samples with the watermark applied in blocks, as the encoder chain does
+fn watermarked(settings: PlayWatermark, key: u64, program: impl Fn(usize) -> f64, len: usize) -> Vec<Vec<f64>> {
+    let mut watermark = Watermark::with_key(settings, key, 48_000);
+    let mut channels = vec![vec![], vec![]];
+
+    for start in (0..len).step_by(512) {
+        let mut block = vec![(start..(start + 512).min(len)).map(&program).collect::<Vec<_>>(); 2];
+        watermark.apply(&mut block);
+
+        for (channel, block) in channels.iter_mut().zip(block) {
+            channel.extend(block);
+        }
+    }
+
+    channels
+}
+
+#[test]
+fn test_inaudible_watermark_is_detected_in_silence() {
+    let channels = watermarked(inaudible(-66.0), KEY, |_| 0.0, PAYLOAD_BITS * SAMPLES_PER_BIT);
+
+    assert!(channels[0].iter().all(|sample| sample.abs() < 0.001));
+    assert_eq!(detect(KEY, &channels[0]), PAYLOAD);
+    assert_eq!(detect(KEY, &channels[1]), PAYLOAD);
+}
+
+#[test]
+fn test_inaudible_watermark_is_detected_under_program() {
+    let program = |i: usize| 0.25 * (TAU * 440.0 * i as f64 / 48_000.0).sin();
+    let channels = watermarked(inaudible(-40.0), KEY, program, 2 * PAYLOAD_BITS * SAMPLES_PER_BIT);
+
+    assert_eq!(detect(KEY, &channels[0]), PAYLOAD);
+}
+
+#[test]
+fn test_inaudible_watermark_needs_the_key() {
+    let channels = watermarked(inaudible(-66.0), KEY, |_| 0.0, PAYLOAD_BITS * SAMPLES_PER_BIT);
+
+    assert_ne!(detect(!KEY, &channels[0]), PAYLOAD);
+}
+
+#[test]
+fn test_audible_watermark_bursts_carry_no_payload() {
+    let audible = PlayWatermark { mode: { WatermarkMode::Audible },
+                                  ..inaudible(-30.0) };
+    let channels = watermarked(audible, KEY, |_| 0.0, 48_000);
+
+    let peak = |samples: &[f64]| samples.iter().fold(0f64, |peak, sample| peak.max(sample.abs()));
+
+    // 250ms burst at -30 dBFS, then silence until the next one 10 seconds in
+    assert!((peak(&channels[0][..12_000]) - 10f64.powf(-30.0 / 20.0)).abs() < 0.001);
+    assert_eq!(peak(&channels[0][12_000..]), 0.0);
+}
+
+#[test]
+fn test_watermark_level_is_validated() {
+    assert!(inaudible(-66.0).validate().is_ok());
+    assert!(inaudible(3.0).validate().is_err());
+    assert!(inaudible(-100.0).validate().is_err());
+}
@@ -0,0 +1,186 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use rosc::{OscMessage, OscType};
+
+use crate::protocol::{SurfaceInput, SurfaceOutput};
+
+/// Prefix of the addresses of the OSC bridge of the domain
+const OSC_PREFIX: &str = "/audiocloud";
+
+/// Group gains below this are sent as the lowest gain a track group accepts
+const MIN_GAIN_DB: f64 = -144.0;
+
+/// What the fader of a strip controls
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Strip {
+    /// Level of a connection of the task, by connection ID
+    Connection(String),
+    /// Gain of a track group, written as `group:<name>`
+    Group(String),
+}
+
+impl FromStr for Strip {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let strip = match s.strip_prefix("group:") {
+            Some(group) => Self::Group(group.to_owned()),
+            None => Self::Connection(s.to_owned()),
+        };
+
+        match &strip {
+            Self::Connection(id) | Self::Group(id) if id.is_empty() || id.contains('/') => {
+                Err(format!("Invalid strip {s}"))
+            }
+            _ => Ok(strip),
+        }
+    }
+}
+
+/// Translates between a control surface and the OSC bridge of the domain
+///
+/// Faders have a square law curve, with unity gain at about 70% of the travel and +6dB at the top.
+#[derive(Debug)]
+pub struct SurfaceBridge {
+    strips:  Vec<Strip>,
+    /// Strips under the hand of the user, their motor faders are left alone
+    touched: HashSet<usize>,
+    playing: bool,
+    paused:  bool,
+}
+
+impl SurfaceBridge {
+    pub fn new(strips: Vec<Strip>) -> Self {
+        Self { strips:  { strips },
+               touched: { HashSet::new() },
+               playing: { false },
+               paused:  { false }, }
+    }
+
+    /// OSC to send to the domain for an input on the surface
+    pub fn on_surface(&mut self, input: SurfaceInput) -> Option<OscMessage> {
+        match input {
+            SurfaceInput::Fader { strip, position } => {
+                let gain = fader_to_gain(position);
+
+                match self.strips.get(strip)? {
+                    Strip::Connection(connection_id) => Some(message(format!("/connection/{connection_id}/volume"),
+                                                                     vec![OscType::Float(gain as f32)])),
+                    Strip::Group(group) => {
+                        let gain_db = gain_to_db(gain);
+                        Some(message(format!("/group/{group}/gain"), vec![OscType::Float(gain_db as f32)]))
+                    }
+                }
+            }
+            SurfaceInput::FaderTouch { strip, touched } => {
+                if touched {
+                    self.touched.insert(strip);
+                } else {
+                    self.touched.remove(&strip);
+                }
+                None
+            }
+            SurfaceInput::Play => Some(message("/transport/resume".to_owned(), vec![])),
+            SurfaceInput::Stop => Some(message("/transport/pause".to_owned(), vec![])),
+        }
+    }
+
+    /// What to show on the surface for feedback from the domain
+    pub fn on_domain(&mut self, message: &OscMessage) -> Vec<SurfaceOutput> {
+        let addr = match message.addr.strip_prefix(OSC_PREFIX) {
+            Some(addr) => addr,
+            None => return vec![],
+        };
+
+        let parts = addr.trim_start_matches('/').split('/').collect::<Vec<_>>();
+        let arg = message.args.first();
+
+        match (parts.as_slice(), arg) {
+            (["connection", connection_id, "volume"], Some(arg)) => {
+                let strip = Strip::Connection(connection_id.to_string());
+                self.fader(&strip, number(arg).map(gain_to_fader))
+            }
+            (["group", group, "gain"], Some(arg)) => {
+                let strip = Strip::Group(group.to_string());
+                self.fader(&strip, number(arg).map(db_to_gain).map(gain_to_fader))
+            }
+            (["transport", "playing"], Some(arg)) => {
+                self.playing = number(arg).unwrap_or_default() != 0.0;
+                self.transport_leds()
+            }
+            (["transport", "paused"], Some(arg)) => {
+                self.paused = number(arg).unwrap_or_default() != 0.0;
+                self.transport_leds()
+            }
+            _ => vec![],
+        }
+    }
+
+    fn fader(&self, strip: &Strip, position: Option<f64>) -> Vec<SurfaceOutput> {
+        let position = match position {
+            Some(position) => position,
+            None => return vec![],
+        };
+
+        self.strips
+            .iter()
+            .enumerate()
+            .filter(|(index, candidate)| *candidate == strip && !self.touched.contains(index))
+            .map(|(strip, _)| SurfaceOutput::Fader { strip, position })
+            .collect()
+    }
+
+    fn transport_leds(&self) -> Vec<SurfaceOutput> {
+        vec![SurfaceOutput::PlayLed(self.playing && !self.paused),
+             SurfaceOutput::StopLed(self.playing && self.paused),]
+    }
+}
+
+/// Select the task to control, `app_id/task_id`
+pub fn select_task(task: &str) -> Option<OscMessage> {
+    let (app_id, task_id) = task.split_once('/')?;
+    Some(message("/task".to_owned(),
+                 vec![OscType::String(app_id.to_owned()), OscType::String(task_id.to_owned())]))
+}
+
+/// Ask the domain for its complete state, which also keeps us on its list of surfaces
+pub fn refresh() -> OscMessage {
+    message("/refresh".to_owned(), vec![])
+}
+
+fn message(addr: String, args: Vec<OscType>) -> OscMessage {
+    OscMessage { addr: format!("{OSC_PREFIX}{addr}"),
+                 args }
+}
+
+fn number(arg: &OscType) -> Option<f64> {
+    match arg {
+        OscType::Float(value) => Some(*value as f64),
+        OscType::Double(value) => Some(*value),
+        OscType::Int(value) => Some(*value as f64),
+        OscType::Long(value) => Some(*value as f64),
+        _ => None,
+    }
+}
+
+pub fn fader_to_gain(position: f64) -> f64 {
+    let position = position.clamp(0.0, 1.0);
+    2.0 * position * position
+}
+
+pub fn gain_to_fader(gain: f64) -> f64 {
+    (gain.max(0.0) / 2.0).sqrt().min(1.0)
+}
+
+pub fn gain_to_db(gain: f64) -> f64 {
+    if gain > 0.0 {
+        (20.0 * gain.log10()).max(MIN_GAIN_DB)
+    } else {
+        MIN_GAIN_DB
+    }
+}
+
+pub fn db_to_gain(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
@@ -0,0 +1,214 @@
+use std::net::{SocketAddr, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use clap::Parser;
+use midir::{MidiInput, MidiOutput, MidiOutputConnection};
+use rosc::{decoder, encoder, OscMessage, OscPacket};
+use tracing::*;
+
+use crate::bridge::{Strip, SurfaceBridge};
+use crate::protocol::{SurfaceDecoder, SurfaceOutput, SurfaceProtocol};
+
+mod bridge;
+mod protocol;
+
+/// How often HUI surfaces are pinged, they go offline after two seconds without a ping
+const HUI_PING_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Parser, Clone, Debug)]
+pub struct Opts {
+    /// Part of the name of the MIDI input port of the surface
+    #[clap(long, env)]
+    pub surface_midi_input: String,
+
+    /// Part of the name of the MIDI output port of the surface
+    #[clap(long, env)]
+    pub surface_midi_output: String,
+
+    /// Protocol the surface speaks
+    #[clap(long, env, arg_enum, default_value = "mcu")]
+    pub surface_protocol: SurfaceProtocol,
+
+    /// What the faders control, from left to right: a connection ID or `group:<name>` for a track group
+    #[clap(long, env, value_delimiter = ',')]
+    pub surface_strips: Vec<Strip>,
+
+    /// Address the OSC bridge of the domain listens on (`OSC_BIND` of the domain server)
+    #[clap(long, env, default_value = "127.0.0.1:9000")]
+    pub domain_osc_addr: SocketAddr,
+
+    /// Task to control as `app_id/task_id`, otherwise the domain picks the first task to become active
+    #[clap(long, env)]
+    pub surface_task: Option<String>,
+
+    /// Seconds between requests for the complete state, which also keep the domain sending feedback, keep it below
+    /// `OSC_SURFACE_TIMEOUT_SECONDS` of the domain
+    #[clap(long, env, default_value = "20")]
+    pub surface_refresh_seconds: u64,
+}
+
+enum Event {
+    Midi(Vec<u8>),
+    Osc(OscMessage),
+}
+
+fn main() -> anyhow::Result<()> {
+    // standard DAW control surfaces speak MIDI, the domain speaks OSC. this bridge runs next to the surface in the
+    // studio and translates between the two, so the surface moves with the levels and transport of the task
+
+    let _ = dotenv::dotenv();
+
+    tracing_subscriber::fmt().with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+                             .init();
+
+    let opts = Opts::parse();
+
+    let (tx_evt, rx_evt) = flume::unbounded();
+
+    let midi_input = MidiInput::new("audiocloud-surface-bridge")?;
+    let input_port = midi_input.ports()
+                               .into_iter()
+                               .find(|port| {
+                                   midi_input.port_name(port)
+                                             .map(|name| name.contains(&opts.surface_midi_input))
+                                             .unwrap_or(false)
+                               })
+                               .ok_or_else(|| anyhow!("MIDI input {} not found", opts.surface_midi_input))?;
+
+    let _input = midi_input.connect(&input_port,
+                                    "surface",
+                                    {
+                                        let tx_evt = tx_evt.clone();
+                                        move |_, message, _| {
+                                            let _ = tx_evt.send(Event::Midi(message.to_vec()));
+                                        }
+                                    },
+                                    ())
+                           .map_err(|error| anyhow!("Failed to connect to MIDI input: {error}"))?;
+
+    let midi_output = MidiOutput::new("audiocloud-surface-bridge")?;
+    let output_port = midi_output.ports()
+                                 .into_iter()
+                                 .find(|port| {
+                                     midi_output.port_name(port)
+                                                .map(|name| name.contains(&opts.surface_midi_output))
+                                                .unwrap_or(false)
+                                 })
+                                 .ok_or_else(|| anyhow!("MIDI output {} not found", opts.surface_midi_output))?;
+
+    let mut output = midi_output.connect(&output_port, "surface")
+                                .map_err(|error| anyhow!("Failed to connect to MIDI output: {error}"))?;
+
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect(opts.domain_osc_addr)?;
+
+    thread::spawn({
+        let socket = socket.try_clone()?;
+        move || {
+            let mut buf = vec![0u8; decoder::MTU];
+            loop {
+                let len = match socket.recv(&mut buf) {
+                    Ok(len) => len,
+                    Err(error) => {
+                        // the domain not listening yet shows up as a refused connection, keep waiting for it
+                        debug!(%error, "OSC receive failed");
+                        thread::sleep(Duration::from_secs(1));
+                        continue;
+                    }
+                };
+
+                match decoder::decode_udp(&buf[..len]) {
+                    Ok((_, packet)) => {
+                        let mut messages = vec![];
+                        flatten_packet(packet, &mut messages);
+                        for message in messages {
+                            if tx_evt.send(Event::Osc(message)).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                    Err(error) => debug!(?error, "Ignoring invalid OSC packet"),
+                }
+            }
+        }
+    });
+
+    let protocol = opts.surface_protocol;
+    let mut decoder = SurfaceDecoder::new(protocol);
+    let mut bridge = SurfaceBridge::new(opts.surface_strips.clone());
+
+    if let Some(task) = &opts.surface_task {
+        let select = bridge::select_task(task).ok_or_else(|| anyhow!("Task {task} is not app_id/task_id"))?;
+        send_osc(&socket, select);
+    }
+
+    info!(?protocol,
+          domain = %opts.domain_osc_addr,
+          strips = opts.surface_strips.len(),
+          "init complete");
+
+    let refresh_interval = Duration::from_secs(opts.surface_refresh_seconds);
+    let mut last_refresh: Option<Instant> = None;
+    let mut last_ping = Instant::now();
+
+    loop {
+        if last_refresh.map(|at| at.elapsed() >= refresh_interval).unwrap_or(true) {
+            send_osc(&socket, bridge::refresh());
+            last_refresh = Some(Instant::now());
+        }
+
+        if protocol == SurfaceProtocol::Hui && last_ping.elapsed() >= HUI_PING_INTERVAL {
+            send_midi(&mut output, protocol, SurfaceOutput::Ping);
+            last_ping = Instant::now();
+        }
+
+        match rx_evt.recv_timeout(Duration::from_millis(100)) {
+            Ok(Event::Midi(message)) => {
+                if let Some(message) = decoder.decode(&message).and_then(|input| bridge.on_surface(input)) {
+                    send_osc(&socket, message);
+                }
+            }
+            Ok(Event::Osc(message)) => {
+                for output_msg in bridge.on_domain(&message) {
+                    send_midi(&mut output, protocol, output_msg);
+                }
+            }
+            Err(flume::RecvTimeoutError::Timeout) => {}
+            Err(flume::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    Ok(())
+}
+
+fn send_osc(socket: &UdpSocket, message: OscMessage) {
+    match encoder::encode(&OscPacket::Message(message)) {
+        Ok(packet) => {
+            if let Err(error) = socket.send(&packet) {
+                debug!(%error, "Failed to send OSC");
+            }
+        }
+        Err(error) => warn!(?error, "Failed to encode OSC"),
+    }
+}
+
+fn send_midi(output: &mut MidiOutputConnection, protocol: SurfaceProtocol, surface_output: SurfaceOutput) {
+    for message in protocol::encode(protocol, surface_output) {
+        if let Err(error) = output.send(&message) {
+            warn!(%error, "Failed to send MIDI to surface");
+        }
+    }
+}
+
+fn flatten_packet(packet: OscPacket, messages: &mut Vec<OscMessage>) {
+    match packet {
+        OscPacket::Message(message) => messages.push(message),
+        OscPacket::Bundle(bundle) => {
+            for packet in bundle.content {
+                flatten_packet(packet, messages);
+            }
+        }
+    }
+}
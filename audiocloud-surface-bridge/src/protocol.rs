@@ -0,0 +1,189 @@
+use clap::ArgEnum;
+
+#[cfg(test)]
+mod tests;
+
+/// Mackie Control note numbers of the buttons and LEDs the bridge uses
+const MCU_STOP: u8 = 0x5D;
+const MCU_PLAY: u8 = 0x5E;
+const MCU_FADER_TOUCH: u8 = 0x68;
+
+/// HUI zone of the transport buttons and the ports of its stop and play buttons
+const HUI_TRANSPORT_ZONE: u8 = 0x0E;
+const HUI_STOP_PORT: u8 = 0x03;
+const HUI_PLAY_PORT: u8 = 0x04;
+/// Port of the fader touch sensor in the zone of a strip
+const HUI_FADER_TOUCH_PORT: u8 = 0x00;
+
+/// Fader positions are 14 bit on both protocols
+const FADER_MAX: f64 = 16383.0;
+
+/// Strips a surface has faders for, the MCU master fader is the ninth
+const MAX_STRIPS: usize = 9;
+const HUI_STRIPS: usize = 8;
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SurfaceProtocol {
+    /// Mackie Control Universal, also spoken by the X-Touch, Icon and most other MIDI surfaces
+    Mcu,
+    /// Mackie HUI, for older surfaces and surfaces set to Pro Tools mode
+    Hui,
+}
+
+/// Something the user did on the surface
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SurfaceInput {
+    /// Fader of a strip moved, position between 0 and 1
+    Fader {
+        strip:    usize,
+        position: f64,
+    },
+    FaderTouch {
+        strip:   usize,
+        touched: bool,
+    },
+    Play,
+    Stop,
+}
+
+/// Something to show on the surface
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SurfaceOutput {
+    /// Move the motor fader of a strip, position between 0 and 1
+    Fader {
+        strip:    usize,
+        position: f64,
+    },
+    PlayLed(bool),
+    StopLed(bool),
+    /// HUI surfaces go offline unless the host pings them every second
+    Ping,
+}
+
+/// Turns MIDI from a surface into inputs, keeping the state HUI spreads over several messages
+#[derive(Debug)]
+pub struct SurfaceDecoder {
+    protocol:  SurfaceProtocol,
+    hui_zone:  Option<u8>,
+    hui_fader: [u8; HUI_STRIPS],
+}
+
+impl SurfaceDecoder {
+    pub fn new(protocol: SurfaceProtocol) -> Self {
+        Self { protocol:  { protocol },
+               hui_zone:  { None },
+               hui_fader: { [0; HUI_STRIPS] }, }
+    }
+
+    pub fn decode(&mut self, message: &[u8]) -> Option<SurfaceInput> {
+        match self.protocol {
+            SurfaceProtocol::Mcu => decode_mcu(message),
+            SurfaceProtocol::Hui => self.decode_hui(message),
+        }
+    }
+
+    fn decode_hui(&mut self, message: &[u8]) -> Option<SurfaceInput> {
+        match *message {
+            // fader MSB comes first, the position is complete with the LSB
+            [0xB0, cc, msb] if (cc as usize) < HUI_STRIPS => {
+                self.hui_fader[cc as usize] = msb;
+                None
+            }
+            [0xB0, cc, lsb] if (0x20..0x20 + HUI_STRIPS as u8).contains(&cc) => {
+                let strip = (cc - 0x20) as usize;
+                Some(SurfaceInput::Fader { strip,
+                                           position: fader_position(self.hui_fader[strip], lsb) })
+            }
+            [0xB0, 0x0F, zone] => {
+                self.hui_zone = Some(zone);
+                None
+            }
+            [0xB0, 0x2F, port] => {
+                let zone = self.hui_zone?;
+                let pressed = port & 0x40 != 0;
+
+                match (zone, port & 0x0F) {
+                    (HUI_TRANSPORT_ZONE, HUI_PLAY_PORT) if pressed => Some(SurfaceInput::Play),
+                    (HUI_TRANSPORT_ZONE, HUI_STOP_PORT) if pressed => Some(SurfaceInput::Stop),
+                    (zone, HUI_FADER_TOUCH_PORT) if (zone as usize) < HUI_STRIPS => {
+                        Some(SurfaceInput::FaderTouch { strip:   zone as usize,
+                                                        touched: pressed, })
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+fn decode_mcu(message: &[u8]) -> Option<SurfaceInput> {
+    match *message {
+        [status, lsb, msb] if status & 0xF0 == 0xE0 && ((status & 0x0F) as usize) < MAX_STRIPS => {
+            Some(SurfaceInput::Fader { strip:    (status & 0x0F) as usize,
+                                       position: fader_position(msb, lsb), })
+        }
+        [0x90, note, velocity] if (MCU_FADER_TOUCH..MCU_FADER_TOUCH + MAX_STRIPS as u8).contains(&note) => {
+            Some(SurfaceInput::FaderTouch { strip:   (note - MCU_FADER_TOUCH) as usize,
+                                            touched: velocity > 0, })
+        }
+        [0x90, MCU_PLAY, velocity] if velocity > 0 => Some(SurfaceInput::Play),
+        [0x90, MCU_STOP, velocity] if velocity > 0 => Some(SurfaceInput::Stop),
+        _ => None,
+    }
+}
+
+/// MIDI messages that show an output on the surface
+pub fn encode(protocol: SurfaceProtocol, output: SurfaceOutput) -> Vec<Vec<u8>> {
+    match protocol {
+        SurfaceProtocol::Mcu => encode_mcu(output),
+        SurfaceProtocol::Hui => encode_hui(output),
+    }
+}
+
+fn encode_mcu(output: SurfaceOutput) -> Vec<Vec<u8>> {
+    match output {
+        SurfaceOutput::Fader { strip, position } if strip < MAX_STRIPS => {
+            let (msb, lsb) = fader_value(position);
+            vec![vec![0xE0 | strip as u8, lsb, msb]]
+        }
+        SurfaceOutput::PlayLed(on) => vec![vec![0x90, MCU_PLAY, led(on)]],
+        SurfaceOutput::StopLed(on) => vec![vec![0x90, MCU_STOP, led(on)]],
+        _ => vec![],
+    }
+}
+
+fn encode_hui(output: SurfaceOutput) -> Vec<Vec<u8>> {
+    match output {
+        SurfaceOutput::Fader { strip, position } if strip < HUI_STRIPS => {
+            let (msb, lsb) = fader_value(position);
+            vec![vec![0xB0, strip as u8, msb], vec![0xB0, 0x20 + strip as u8, lsb]]
+        }
+        SurfaceOutput::PlayLed(on) => hui_led(HUI_TRANSPORT_ZONE, HUI_PLAY_PORT, on),
+        SurfaceOutput::StopLed(on) => hui_led(HUI_TRANSPORT_ZONE, HUI_STOP_PORT, on),
+        SurfaceOutput::Ping => vec![vec![0x90, 0x00, 0x00]],
+        _ => vec![],
+    }
+}
+
+fn hui_led(zone: u8, port: u8, on: bool) -> Vec<Vec<u8>> {
+    let port = if on { port | 0x40 } else { port };
+    vec![vec![0xB0, 0x0C, zone], vec![0xB0, 0x2C, port]]
+}
+
+fn led(on: bool) -> u8 {
+    if on {
+        0x7F
+    } else {
+        0x00
+    }
+}
+
+fn fader_position(msb: u8, lsb: u8) -> f64 {
+    (((msb as u16 & 0x7F) << 7) | (lsb as u16 & 0x7F)) as f64 / FADER_MAX
+}
+
+fn fader_value(position: f64) -> (u8, u8) {
+    let value = (position.clamp(0.0, 1.0) * FADER_MAX).round() as u16;
+    ((value >> 7) as u8, (value & 0x7F) as u8)
+}
@@ -0,0 +1,50 @@
+use crate::protocol::{encode, SurfaceDecoder, SurfaceInput, SurfaceOutput, SurfaceProtocol};
+
+#[test]
+fn test_mcu_faders_and_transport() {
+    let mut decoder = SurfaceDecoder::new(SurfaceProtocol::Mcu);
+
+    assert_eq!(decoder.decode(&[0xE2, 0x7F, 0x7F]),
+               Some(SurfaceInput::Fader { strip:    2,
+                                          position: 1.0, }));
+    assert_eq!(decoder.decode(&[0x90, 0x69, 0x7F]),
+               Some(SurfaceInput::FaderTouch { strip:   1,
+                                               touched: true, }));
+    assert_eq!(decoder.decode(&[0x90, 0x5E, 0x7F]), Some(SurfaceInput::Play));
+    // button release
+    assert_eq!(decoder.decode(&[0x90, 0x5E, 0x00]), None);
+
+    assert_eq!(encode(SurfaceProtocol::Mcu,
+                      SurfaceOutput::Fader { strip:    0,
+                                             position: 0.0, }),
+               vec![vec![0xE0, 0x00, 0x00]]);
+    assert_eq!(encode(SurfaceProtocol::Mcu, SurfaceOutput::StopLed(true)),
+               vec![vec![0x90, 0x5D, 0x7F]]);
+}
+
+#[test]
+fn test_hui_spreads_messages_over_zones() {
+    let mut decoder = SurfaceDecoder::new(SurfaceProtocol::Hui);
+
+    // fader position is complete with the LSB
+    assert_eq!(decoder.decode(&[0xB0, 0x03, 0x40]), None);
+    assert_eq!(decoder.decode(&[0xB0, 0x23, 0x00]),
+               Some(SurfaceInput::Fader { strip:    3,
+                                          position: 8192.0 / 16383.0, }));
+
+    // play button in the transport zone
+    assert_eq!(decoder.decode(&[0xB0, 0x0F, 0x0E]), None);
+    assert_eq!(decoder.decode(&[0xB0, 0x2F, 0x44]), Some(SurfaceInput::Play));
+    assert_eq!(decoder.decode(&[0xB0, 0x2F, 0x04]), None);
+
+    // fader touch in the zone of the strip
+    assert_eq!(decoder.decode(&[0xB0, 0x0F, 0x05]), None);
+    assert_eq!(decoder.decode(&[0xB0, 0x2F, 0x40]),
+               Some(SurfaceInput::FaderTouch { strip:   5,
+                                               touched: true, }));
+
+    assert_eq!(encode(SurfaceProtocol::Hui, SurfaceOutput::PlayLed(true)),
+               vec![vec![0xB0, 0x0C, 0x0E], vec![0xB0, 0x2C, 0x44]]);
+    assert_eq!(encode(SurfaceProtocol::Hui, SurfaceOutput::Ping),
+               vec![vec![0x90, 0x00, 0x00]]);
+}